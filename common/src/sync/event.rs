@@ -0,0 +1,13 @@
+/// Something a driver can sleep on until its interrupt handler reports
+/// progress.
+///
+/// Implemented by the kernel's wait queues. Drivers that are given one
+/// wait for completions without polling; without one they poll.
+pub trait WaitEvent: Sync {
+    /// Sleep until `condition` returns true. It is checked before each
+    /// sleep and again after every [`notify`](Self::notify).
+    fn wait_until(&self, condition: &mut dyn FnMut() -> bool);
+
+    /// Wake every waiter. Callable from interrupt handlers.
+    fn notify(&self);
+}
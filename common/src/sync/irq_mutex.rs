@@ -1,4 +1,5 @@
 use super::irq::IrqControl;
+use super::lockdep::{self, LockClass};
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
 use spin::{Mutex, MutexGuard};
@@ -16,6 +17,7 @@ use spin::{Mutex, MutexGuard};
 /// Not reentrant. Not fair.
 pub struct IrqMutex<T: ?Sized, I: IrqControl> {
     _irq: PhantomData<I>,
+    class: LockClass,
     inner: Mutex<T>,
 }
 
@@ -24,9 +26,11 @@ unsafe impl<T: Send + ?Sized, I: IrqControl> Sync for IrqMutex<T, I> {}
 
 impl<T, I: IrqControl> IrqMutex<T, I> {
     /// Create a new IRQ-safe mutex.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
     pub const fn new(data: T) -> Self {
         Self {
             inner: Mutex::new(data),
+            class: LockClass::here(),
             _irq: PhantomData,
         }
     }
@@ -34,27 +38,91 @@ impl<T, I: IrqControl> IrqMutex<T, I> {
 
 impl<T: ?Sized, I: IrqControl> IrqMutex<T, I> {
     /// Lock the mutex, disabling interrupts.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
     pub fn lock(&self) -> IrqMutexGuard<'_, T, I> {
+        IrqMutexGuard::lock_class(&self.inner, self.class)
+    }
+
+    /// Try to lock without blocking.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T, I>> {
+        IrqMutexGuard::try_lock_class(&self.inner, self.class)
+    }
+
+    /// Whether the mutex is locked. Only a hint: it may change at once.
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    /// Unlock the mutex, whoever holds it.
+    ///
+    /// # Safety
+    /// The holder's guard must never be used or dropped again.
+    pub unsafe fn force_unlock(&self) {
+        lockdep::release(lock_id(&self.inner));
+        unsafe { self.inner.force_unlock() }
+    }
+}
+
+/// Guard returned by `IrqMutex::lock`.
+///
+/// Restores interrupt state on drop.
+pub struct IrqMutexGuard<'a, T: ?Sized, I: IrqControl> {
+    guard: MutexGuard<'a, T>,
+    irq_state: I::State,
+    #[cfg(feature = "lock-debug")]
+    lock: usize,
+    _irq: PhantomData<I>,
+}
+
+impl<'a, T: ?Sized, I: IrqControl> IrqMutexGuard<'a, T, I> {
+    /// Lock a plain `spin::Mutex` the way `IrqMutex::lock` does.
+    ///
+    /// For a lock owned by code that cannot disable interrupts itself,
+    /// such as a driver's. It is only IRQ-safe if every holder an
+    /// interrupt can preempt takes it this way.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn lock(mutex: &'a Mutex<T>) -> Self {
+        Self::lock_class(mutex, LockClass::unknown())
+    }
+
+    /// Try to lock a plain `spin::Mutex` without blocking, disabling
+    /// interrupts if it is taken.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn try_lock(mutex: &'a Mutex<T>) -> Option<Self> {
+        Self::try_lock_class(mutex, LockClass::unknown())
+    }
+
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    fn lock_class(mutex: &'a Mutex<T>, class: LockClass) -> Self {
         let irq_state = I::save_and_disable();
-        let guard = self.inner.lock();
+        lockdep::acquire(lock_id(mutex), class, false);
+        let guard = mutex.lock();
 
         IrqMutexGuard {
             guard,
             irq_state,
+            #[cfg(feature = "lock-debug")]
+            lock: lock_id(mutex),
             _irq: PhantomData,
         }
     }
 
-    /// Try to lock without blocking.
-    pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T, I>> {
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    fn try_lock_class(mutex: &'a Mutex<T>, class: LockClass) -> Option<Self> {
         let irq_state = I::save_and_disable();
 
-        match self.inner.try_lock() {
-            Some(guard) => Some(IrqMutexGuard {
-                guard,
-                irq_state,
-                _irq: PhantomData,
-            }),
+        match mutex.try_lock() {
+            Some(guard) => {
+                lockdep::acquire(lock_id(mutex), class, true);
+                Some(IrqMutexGuard {
+                    guard,
+                    irq_state,
+                    #[cfg(feature = "lock-debug")]
+                    lock: lock_id(mutex),
+                    _irq: PhantomData,
+                })
+            }
             None => {
                 I::restore(irq_state);
                 None
@@ -63,15 +131,6 @@ impl<T: ?Sized, I: IrqControl> IrqMutex<T, I> {
     }
 }
 
-/// Guard returned by `IrqMutex::lock`.
-///
-/// Restores interrupt state on drop.
-pub struct IrqMutexGuard<'a, T: ?Sized, I: IrqControl> {
-    guard: MutexGuard<'a, T>,
-    irq_state: I::State,
-    _irq: PhantomData<I>,
-}
-
 impl<'a, T: ?Sized, I: IrqControl> Deref for IrqMutexGuard<'a, T, I> {
     type Target = T;
 
@@ -88,6 +147,8 @@ impl<'a, T: ?Sized, I: IrqControl> DerefMut for IrqMutexGuard<'a, T, I> {
 
 impl<'a, T: ?Sized, I: IrqControl> Drop for IrqMutexGuard<'a, T, I> {
     fn drop(&mut self) {
+        #[cfg(feature = "lock-debug")]
+        lockdep::release(self.lock);
         // Explicitly drop the lock first
         unsafe {
             core::ptr::drop_in_place(&mut self.guard);
@@ -95,3 +156,8 @@ impl<'a, T: ?Sized, I: IrqControl> Drop for IrqMutexGuard<'a, T, I> {
         I::restore(self.irq_state);
     }
 }
+
+/// What identifies `mutex` to the lock checker.
+fn lock_id<T: ?Sized>(mutex: &Mutex<T>) -> usize {
+    (mutex as *const Mutex<T>).addr()
+}
@@ -0,0 +1,87 @@
+#[cfg(feature = "lock-debug")]
+use super::Once;
+#[cfg(feature = "lock-debug")]
+use core::panic::Location;
+
+/// What a lock is, for ordering purposes: the place it was created, so
+/// that every lock made by one `new` call is ordered the same way.
+///
+/// Empty without the `lock-debug` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockClass {
+    #[cfg(feature = "lock-debug")]
+    site: Option<&'static Location<'static>>,
+}
+
+impl LockClass {
+    /// The class of locks created where the caller was called from.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub const fn here() -> Self {
+        Self {
+            #[cfg(feature = "lock-debug")]
+            site: Some(Location::caller()),
+        }
+    }
+
+    /// A class of its own, for a lock whose creation site is not known,
+    /// such as a plain `spin::Mutex` locked through `IrqMutexGuard`.
+    pub const fn unknown() -> Self {
+        Self {
+            #[cfg(feature = "lock-debug")]
+            site: None,
+        }
+    }
+
+    /// Where locks of the class are created, if known.
+    #[cfg(feature = "lock-debug")]
+    pub const fn site(&self) -> Option<&'static Location<'static>> {
+        self.site
+    }
+}
+
+/// Checker the locks here report to, registered by the kernel.
+#[cfg(feature = "lock-debug")]
+pub struct Checker {
+    /// `lock`, of `class`, is being taken at `site`. Blocking locks call
+    /// it before they wait, so a lock that would deadlock is reported
+    /// instead of hanging; `try_lock` only once it has the lock.
+    pub acquire:
+        fn(lock: usize, class: LockClass, site: &'static Location<'static>, try_lock: bool),
+    /// `lock` was released.
+    pub release: fn(lock: usize),
+}
+
+#[cfg(feature = "lock-debug")]
+static CHECKER: Once<Checker> = Once::new();
+
+/// Register the checker, or hand it back if one is registered. Locks
+/// taken before go unchecked.
+#[cfg(feature = "lock-debug")]
+pub fn set_checker(checker: Checker) -> Result<(), Checker> {
+    CHECKER.set(checker)
+}
+
+/// Report that the lock at address `lock` is being taken, blocking unless `try_lock`. The
+/// site recorded is the innermost caller not marked `#[track_caller]`,
+/// so a lock method that is marked reports its own caller.
+#[cfg_attr(feature = "lock-debug", track_caller)]
+#[inline(always)]
+pub fn acquire(lock: usize, class: LockClass, try_lock: bool) {
+    #[cfg(feature = "lock-debug")]
+    if let Some(checker) = CHECKER.get() {
+        (checker.acquire)(lock, class, Location::caller(), try_lock);
+    }
+    #[cfg(not(feature = "lock-debug"))]
+    let _ = (lock, class, try_lock);
+}
+
+/// Report that `lock` was released.
+#[inline(always)]
+pub fn release(lock: usize) {
+    #[cfg(feature = "lock-debug")]
+    if let Some(checker) = CHECKER.get() {
+        (checker.release)(lock);
+    }
+    #[cfg(not(feature = "lock-debug"))]
+    let _ = lock;
+}
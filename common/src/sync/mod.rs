@@ -1,2 +1,13 @@
+pub mod event;
 pub mod irq;
 pub mod irq_mutex;
+pub mod lockdep;
+pub mod once;
+pub mod rwlock;
+pub mod seqlock;
+pub mod spsc;
+
+pub use once::{Lazy, Once};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use seqlock::{SeqLock, SeqLockWriteGuard};
+pub use spsc::SpscRing;
@@ -0,0 +1,209 @@
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// No value yet, and nobody making one.
+const INCOMPLETE: usize = 0;
+/// A caller is making the value.
+const RUNNING: usize = 1;
+/// The value is there for good.
+const COMPLETE: usize = 2;
+
+/// A value set once, then shared for good.
+///
+/// - `get` never waits: it has the value or it does not
+/// - `call_once` makes the value if nobody has, and otherwise returns
+///   the one there; callers that arrive while it is being made spin
+///   until it is
+/// - `set` stores a value made elsewhere
+///
+/// Interrupts are left as they are, so an interrupt handler that calls
+/// `call_once` while the code it interrupted is making the value spins
+/// forever. Handlers should only `get`.
+pub struct Once<T> {
+    state: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for Once<T> {}
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+
+impl<T> Once<T> {
+    /// Create an empty `Once`.
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(INCOMPLETE),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// The value, if it has been set.
+    pub fn get(&self) -> Option<&T> {
+        if self.is_completed() {
+            // SAFETY: COMPLETE is only stored once the value is written,
+            // and it is never written again
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Whether the value has been set.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// The value, made with `f` if it has not been set yet.
+    pub fn call_once(&self, f: impl FnOnce() -> T) -> &T {
+        match self.try_call_once(|| Ok::<T, core::convert::Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// The value, made with `f` if it has not been set yet. If `f`
+    /// fails, the `Once` stays unset and the error is returned; a later
+    /// call may try again.
+    pub fn try_call_once<E>(&self, f: impl FnOnce() -> Result<T, E>) -> Result<&T, E> {
+        loop {
+            match self.state.compare_exchange_weak(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    let result = f();
+                    return match result {
+                        Ok(value) => Ok(self.complete(value)),
+                        Err(e) => {
+                            self.state.store(INCOMPLETE, Ordering::Release);
+                            Err(e)
+                        }
+                    };
+                }
+                Err(COMPLETE) => return Ok(self.get().unwrap()),
+                // RUNNING, or a spurious failure
+                Err(_) => core::hint::spin_loop(),
+            }
+        }
+    }
+
+    /// Set the value, or hand `value` back if it was already set or is
+    /// being made.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        match self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                self.complete(value);
+                Ok(())
+            }
+            Err(_) => Err(value),
+        }
+    }
+
+    /// The value; no synchronization needed, as the `Once` is borrowed
+    /// mutably.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if *self.state.get_mut() == COMPLETE {
+            // SAFETY: the value is written, as for `get`
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Write `value` and publish it. The caller moved the state to
+    /// RUNNING, so it alone writes.
+    fn complete(&self, value: T) -> &T {
+        // SAFETY: in RUNNING, only the caller touches the value
+        let value = unsafe { (*self.value.get()).write(value) };
+        self.state.store(COMPLETE, Ordering::Release);
+        value
+    }
+}
+
+impl<T> Default for Once<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Once<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == COMPLETE {
+            // SAFETY: the value is written, and dropped only here
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Once<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("Once").field(value).finish(),
+            None => f.write_str("Once(<unset>)"),
+        }
+    }
+}
+
+/// A value made the first time it is used.
+///
+/// Dereferencing makes it with the function given to `new` if nobody
+/// has yet; see [`Once`] for what happens meanwhile.
+pub struct Lazy<T, F = fn() -> T> {
+    once: Once<T>,
+    init: Cell<Option<F>>,
+}
+
+// `init` is only taken by the caller that moved the `Once` to RUNNING
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F> Lazy<T, F> {
+    /// Create a `Lazy` that makes its value with `init`.
+    pub const fn new(init: F) -> Self {
+        Self {
+            once: Once::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    /// The value, if it has been made.
+    pub fn get(this: &Self) -> Option<&T> {
+        this.once.get()
+    }
+}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    /// Make the value if nobody has, and return it.
+    pub fn force(this: &Self) -> &T {
+        this.once.call_once(|| {
+            let init = this
+                .init
+                .take()
+                .expect("Lazy's value is already being made");
+            init()
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.once.get() {
+            Some(value) => f.debug_tuple("Lazy").field(value).finish(),
+            None => f.write_str("Lazy(<unmade>)"),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use super::lockdep::{self, LockClass};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// State bit: a writer holds the lock.
+const WRITER: usize = 1 << 0;
+/// State bit: a writer is waiting, so no new reader may enter.
+const WRITER_WAITING: usize = 1 << 1;
+/// One reader, counted in the bits above the flags.
+const READER: usize = 1 << 2;
+
+/// Writer-preferring reader-writer spinlock.
+///
+/// - Any number of readers, or one writer
+/// - A waiting writer holds back readers that arrive after it, so a
+///   steady stream of readers cannot starve writers
+/// - Waiting writers are not queued among themselves: which one goes
+///   first is not defined
+///
+/// Interrupts are left as they are, so it must not be taken in an
+/// interrupt handler that can interrupt a holder. Not reentrant: a
+/// reader that takes it again can deadlock behind a waiting writer.
+pub struct RwLock<T: ?Sized> {
+    state: AtomicUsize,
+    class: LockClass,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// Create a new unlocked lock.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            class: LockClass::here(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Take the data out; no locking needed, as the lock is owned.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Lock for reading, spinning while a writer holds or waits for it.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        lockdep::acquire(self.id(), self.class, false);
+        loop {
+            if self.enter_read() {
+                return RwLockReadGuard { lock: self };
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Lock for reading if no writer holds or waits for it.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        if !self.enter_read() {
+            return None;
+        }
+        lockdep::acquire(self.id(), self.class, true);
+        Some(RwLockReadGuard { lock: self })
+    }
+
+    /// Lock for writing, spinning until the readers in it have left.
+    /// Readers that arrive meanwhile wait.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        lockdep::acquire(self.id(), self.class, false);
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & !WRITER_WAITING == 0 {
+                // Free: take it, clearing the waiting flag. Another
+                // waiting writer sets it again.
+                if self
+                    .state
+                    .compare_exchange_weak(state, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return RwLockWriteGuard { lock: self };
+                }
+            } else if state & WRITER_WAITING == 0 {
+                self.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Lock for writing if nobody holds it.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let state = self.state.load(Ordering::Relaxed);
+        if state & !WRITER_WAITING != 0 {
+            return None;
+        }
+        self.state
+            .compare_exchange(state, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        lockdep::acquire(self.id(), self.class, true);
+        Some(RwLockWriteGuard { lock: self })
+    }
+
+    /// Number of readers holding the lock. Only a hint: it may change at
+    /// once.
+    pub fn reader_count(&self) -> usize {
+        self.state.load(Ordering::Relaxed) / READER
+    }
+
+    /// Whether a writer holds the lock. Only a hint: it may change at
+    /// once.
+    pub fn is_write_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & WRITER != 0
+    }
+
+    /// The data; no locking needed, as the lock is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Add a reader if no writer holds or waits for the lock.
+    fn enter_read(&self) -> bool {
+        let state = self.state.load(Ordering::Relaxed);
+        if state & (WRITER | WRITER_WAITING) != 0 {
+            return false;
+        }
+        self.state
+            .compare_exchange(state, state + READER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// What identifies the lock to the lock checker.
+    fn id(&self) -> usize {
+        (self as *const Self).addr()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => f.debug_struct("RwLock").field("data", &&*guard).finish(),
+            None => f.write_str("RwLock { <locked> }"),
+        }
+    }
+}
+
+/// Guard returned by `RwLock::read`.
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: readers only share the data with other readers
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        lockdep::release(self.lock.id());
+        self.lock.state.fetch_sub(READER, Ordering::Release);
+    }
+}
+
+/// Guard returned by `RwLock::write`.
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the writer has the data to itself
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the writer has the data to itself
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        lockdep::release(self.lock.id());
+        // Keeps the flag of a writer that is waiting
+        self.lock.state.fetch_and(!WRITER, Ordering::Release);
+    }
+}
@@ -0,0 +1,142 @@
+use super::lockdep::{self, LockClass};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering, fence};
+
+/// Sequence lock: readers never block writers, and writers never wait
+/// for readers.
+///
+/// - Readers take a copy of the data, and take it again if a writer was
+///   at work meanwhile, so they never see a value half written, such as
+///   a 64-bit value torn in two on a 32-bit CPU
+/// - Writers exclude each other, spinning
+///
+/// Suits small `Copy` data that is read far more often than written.
+/// A reader spins while a write is in progress, so a writer must not be
+/// interrupted by a reader on its own CPU: if an interrupt handler
+/// reads, writers mask interrupts around the write.
+pub struct SeqLock<T> {
+    /// Even while nobody writes; bumped when a write starts and ends
+    seq: AtomicUsize,
+    class: LockClass,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for SeqLock<T> {}
+unsafe impl<T: Send> Sync for SeqLock<T> {}
+
+impl<T> SeqLock<T> {
+    /// Create a new lock.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            class: LockClass::here(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Lock for writing, spinning while another writer holds it. Readers
+    /// retry until the guard is dropped.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn write(&self) -> SeqLockWriteGuard<'_, T> {
+        lockdep::acquire(self.id(), self.class, false);
+        loop {
+            let seq = self.seq.load(Ordering::Relaxed);
+            if seq & 1 == 0
+                && self
+                    .seq
+                    .compare_exchange_weak(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        // The odd sequence is seen before any of the writes
+        fence(Ordering::Release);
+        SeqLockWriteGuard { lock: self }
+    }
+
+    /// Take the data out; no locking needed, as the lock is owned.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// The data; no locking needed, as the lock is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// What identifies the lock to the lock checker.
+    fn id(&self) -> usize {
+        (self as *const Self).addr()
+    }
+}
+
+impl<T: Copy> SeqLock<T> {
+    /// A copy of the data, as no writer left it half written.
+    pub fn read(&self) -> T {
+        loop {
+            let start = self.seq.load(Ordering::Acquire);
+            if start & 1 == 0 {
+                // SAFETY: a writer may be at work, in which case the
+                // sequence moves and the copy is thrown away; `T` is
+                // `Copy`, so a torn copy has nothing to drop
+                let value = unsafe { core::ptr::read_volatile(self.data.get()) };
+                // The copy is taken before the sequence is checked again
+                fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == start {
+                    return value;
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl<T: Default> Default for SeqLock<T> {
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for SeqLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SeqLock")
+            .field("data", &self.read())
+            .finish()
+    }
+}
+
+/// Guard returned by `SeqLock::write`.
+pub struct SeqLockWriteGuard<'a, T> {
+    lock: &'a SeqLock<T>,
+}
+
+impl<T> Deref for SeqLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the writer has the data to itself; readers only copy it
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SeqLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: as for `deref`, and readers throw away what they copy
+        // while the sequence is odd
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SeqLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        lockdep::release(self.lock.id());
+        // Release: the writes are seen with the even sequence
+        self.lock.seq.fetch_add(1, Ordering::Release);
+    }
+}
@@ -0,0 +1,113 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Fixed-capacity single-producer, single-consumer ring buffer.
+///
+/// - Holds up to `N` items, in place: no allocation
+/// - Lock-free: `push` and `pop` never wait, so an interrupt handler can
+///   hand items to a thread, or the other way round, with no lock shared
+/// - Full is full: `push` fails rather than overwrite the oldest item
+///
+/// One producer and one consumer at a time. A side with more than one
+/// user, such as several tasks reading, must serialize them itself.
+pub struct SpscRing<T, const N: usize> {
+    /// Items pushed since creation; only the producer moves it
+    tail: AtomicUsize,
+    /// Items popped since creation; only the consumer moves it
+    head: AtomicUsize,
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+}
+
+unsafe impl<T: Send, const N: usize> Send for SpscRing<T, N> {}
+unsafe impl<T: Send, const N: usize> Sync for SpscRing<T, N> {}
+
+impl<T, const N: usize> SpscRing<T, N> {
+    /// Create an empty ring.
+    pub const fn new() -> Self {
+        assert!(N > 0, "SpscRing needs room for an item");
+        Self {
+            tail: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+        }
+    }
+
+    /// Add `value` at the back, or hand it back if the ring is full.
+    /// Producer side.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        // Acquire: the consumer is done with the slot it gave up
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == N {
+            return Err(value);
+        }
+        // SAFETY: the slot is outside head..tail, so the consumer does not
+        // touch it, and only the producer writes
+        unsafe { (*self.slots[tail % N].get()).write(value) };
+        // Release: the consumer sees the item with the new tail
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Take the item at the front, if there is one. Consumer side.
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        // Acquire: the producer's write of the slot is visible
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        // SAFETY: the slot is inside head..tail, so the producer wrote it
+        // and leaves it alone, and only the consumer reads
+        let value = unsafe { (*self.slots[head % N].get()).assume_init_read() };
+        // Release: the producer may reuse the slot once it sees this
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    /// Number of items waiting. Only a hint: either side may change it
+    /// at once.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(N)
+    }
+
+    /// Whether no item is waiting. Only a hint, as for `len`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `push` would fail. Only a hint, as for `len`.
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    /// Most items the ring holds.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Default for SpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for SpscRing<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for SpscRing<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpscRing")
+            .field("len", &self.len())
+            .field("capacity", &N)
+            .finish()
+    }
+}
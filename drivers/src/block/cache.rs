@@ -0,0 +1,313 @@
+//! Write-back LRU block cache.
+//!
+//! [`CachedBlockDevice`] keeps recently used blocks of an underlying
+//! device in memory. Reads are served from the cache when possible and
+//! writes only mark the cached block dirty; dirty blocks reach the device
+//! when they are evicted, on [`BlockDevice::flush`], or once more of them
+//! are dirty than the high-water mark allows. Writing back, runs of
+//! adjacent dirty blocks go out as one multi-block write.
+//!
+//! Nothing here writes back on a timer; the kernel flushes every block
+//! device periodically from a task of its own.
+
+use crate::hal::block_device::{
+    BlockCache, BlockDevice, BlockDeviceError, BlockDeviceInfo, CacheStats,
+};
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// Default number of cached blocks (128 KiB with 512-byte blocks).
+pub const DEFAULT_CACHE_BLOCKS: usize = 256;
+
+/// Default high-water mark of dirty blocks (a quarter of the default
+/// cache).
+pub const DEFAULT_DIRTY_LIMIT: usize = DEFAULT_CACHE_BLOCKS / 4;
+
+// ============================================================================
+// Error Type
+// ============================================================================
+
+/// Block cache errors
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockCacheError {
+    /// The underlying device failed
+    Device(BlockDeviceError),
+    /// Buffer shorter than the device block size
+    InvalidBuffer,
+}
+
+impl From<BlockCacheError> for BlockDeviceError {
+    fn from(err: BlockCacheError) -> Self {
+        match err {
+            BlockCacheError::Device(e) => e,
+            BlockCacheError::InvalidBuffer => BlockDeviceError::InvalidBuffer,
+        }
+    }
+}
+
+// ============================================================================
+// Cache State
+// ============================================================================
+
+struct CacheEntry {
+    data: Vec<u8>,
+    dirty: bool,
+    last_used: u64,
+}
+
+struct CacheState {
+    blocks: BTreeMap<u64, CacheEntry>,
+    /// Number of dirty entries in `blocks`
+    dirty: usize,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+// ============================================================================
+// Cached Device
+// ============================================================================
+
+/// Block device wrapper with an LRU write-back cache.
+pub struct CachedBlockDevice<B: BlockDevice> {
+    inner: B,
+    block_size: usize,
+    capacity: usize,
+    dirty_limit: usize,
+    state: Mutex<CacheState>,
+}
+
+impl<B: BlockDevice> CachedBlockDevice<B> {
+    /// Wrap `inner` with a cache of [`DEFAULT_CACHE_BLOCKS`] blocks.
+    pub fn new(inner: B) -> Self {
+        Self::with_capacity(inner, DEFAULT_CACHE_BLOCKS)
+    }
+
+    /// Wrap `inner` with a cache of `capacity` blocks (at least one).
+    ///
+    /// The dirty high-water mark is a quarter of `capacity`.
+    pub fn with_capacity(inner: B, capacity: usize) -> Self {
+        let block_size = inner.info().block_size;
+        let capacity = capacity.max(1);
+        Self {
+            inner,
+            block_size,
+            capacity,
+            dirty_limit: (capacity / 4).max(1),
+            state: Mutex::new(CacheState {
+                blocks: BTreeMap::new(),
+                dirty: 0,
+                tick: 0,
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    /// Write every dirty block back once more than `limit` are dirty
+    /// (at least one, at most the capacity).
+    pub fn with_dirty_limit(mut self, limit: usize) -> Self {
+        self.dirty_limit = limit.clamp(1, self.capacity);
+        self
+    }
+
+    /// The wrapped device.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// The dirty high-water mark.
+    pub fn dirty_limit(&self) -> usize {
+        self.dirty_limit
+    }
+
+    fn device_error(err: B::Error) -> BlockCacheError {
+        BlockCacheError::Device(err.into())
+    }
+
+    /// Make room for one more block, writing back the least recently used
+    /// one if it is dirty.
+    fn evict_one(&self, state: &mut CacheState) -> Result<(), BlockCacheError> {
+        if state.blocks.len() < self.capacity {
+            return Ok(());
+        }
+
+        let Some((&block, entry)) = state.blocks.iter().min_by_key(|(_, e)| e.last_used) else {
+            return Ok(());
+        };
+        if entry.dirty {
+            self.inner
+                .write_block(block, &entry.data)
+                .map_err(Self::device_error)?;
+            state.dirty -= 1;
+        }
+        state.blocks.remove(&block);
+        Ok(())
+    }
+
+    /// Write every dirty block back, in ascending block order, each run
+    /// of adjacent ones in a single write.
+    fn write_back(&self, state: &mut CacheState) -> Result<(), BlockCacheError> {
+        let dirty: Vec<u64> = state
+            .blocks
+            .iter()
+            .filter(|(_, e)| e.dirty)
+            .map(|(&block, _)| block)
+            .collect();
+
+        for run in dirty.chunk_by(|a, b| b == &(a + 1)) {
+            let buffers: Vec<&[u8]> = run.iter().map(|b| &state.blocks[b].data[..]).collect();
+            self.inner
+                .write_blocks(run[0], &buffers)
+                .map_err(Self::device_error)?;
+            // Cleaned only once written, so a failed run stays dirty
+            for block in run {
+                if let Some(entry) = state.blocks.get_mut(block) {
+                    entry.dirty = false;
+                }
+            }
+            state.dirty -= run.len();
+        }
+        Ok(())
+    }
+}
+
+impl<B: BlockDevice> BlockDevice for CachedBlockDevice<B> {
+    type Error = BlockCacheError;
+
+    fn info(&self) -> BlockDeviceInfo {
+        self.inner.info()
+    }
+
+    fn read_blocks(&self, start_block: u64, buffers: &mut [&mut [u8]]) -> Result<(), Self::Error> {
+        if buffers.iter().any(|b| b.len() < self.block_size) {
+            return Err(BlockCacheError::InvalidBuffer);
+        }
+
+        let mut state = self.state.lock();
+        for (i, buf) in buffers.iter_mut().enumerate() {
+            let block = start_block + i as u64;
+            let tick = state.touch();
+
+            if let Some(entry) = state.blocks.get_mut(&block) {
+                entry.last_used = tick;
+                buf[..self.block_size].copy_from_slice(&entry.data);
+                state.hits += 1;
+                continue;
+            }
+
+            state.misses += 1;
+            let mut data = vec![0u8; self.block_size];
+            self.inner
+                .read_block(block, &mut data)
+                .map_err(Self::device_error)?;
+            buf[..self.block_size].copy_from_slice(&data);
+
+            self.evict_one(&mut state)?;
+            state.blocks.insert(
+                block,
+                CacheEntry {
+                    data,
+                    dirty: false,
+                    last_used: tick,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn write_blocks(&self, start_block: u64, buffers: &[&[u8]]) -> Result<(), Self::Error> {
+        if buffers.iter().any(|b| b.len() < self.block_size) {
+            return Err(BlockCacheError::InvalidBuffer);
+        }
+        if self.inner.info().read_only {
+            return Err(BlockCacheError::Device(BlockDeviceError::WriteProtected));
+        }
+
+        let mut state = self.state.lock();
+        for (i, buf) in buffers.iter().enumerate() {
+            let block = start_block + i as u64;
+            let tick = state.touch();
+
+            if let Some(entry) = state.blocks.get_mut(&block) {
+                entry.data.copy_from_slice(&buf[..self.block_size]);
+                entry.last_used = tick;
+                if !entry.dirty {
+                    entry.dirty = true;
+                    state.dirty += 1;
+                }
+                continue;
+            }
+
+            // Whole-block write: no need to read the old contents
+            self.evict_one(&mut state)?;
+            state.blocks.insert(
+                block,
+                CacheEntry {
+                    data: buf[..self.block_size].to_vec(),
+                    dirty: true,
+                    last_used: tick,
+                },
+            );
+            state.dirty += 1;
+        }
+
+        // Past the high-water mark: write back now rather than leave it
+        // all to eviction, one block at a time
+        if state.dirty > self.dirty_limit {
+            self.write_back(&mut state)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        let mut state = self.state.lock();
+        self.write_back(&mut state)?;
+        self.inner.flush().map_err(Self::device_error)
+    }
+
+    fn is_ready(&self) -> bool {
+        self.inner.is_ready()
+    }
+}
+
+impl<B: BlockDevice> BlockCache for CachedBlockDevice<B> {
+    /// Drop cached copies of a block range, e.g. after the device was
+    /// written behind the cache's back. Dirty data in the range is lost.
+    fn invalidate(&mut self, start_block: u64, count: u64) {
+        let end = start_block.saturating_add(count);
+        let mut state = self.state.lock();
+        state
+            .blocks
+            .retain(|&block, _| block < start_block || block >= end);
+        state.dirty = state.blocks.values().filter(|e| e.dirty).count();
+    }
+
+    fn cache_stats(&self) -> CacheStats {
+        let state = self.state.lock();
+        CacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            dirty_blocks: state.dirty,
+            cache_size: state.blocks.len(),
+        }
+    }
+}
+
+impl<B: BlockDevice> Drop for CachedBlockDevice<B> {
+    fn drop(&mut self) {
+        if BlockDevice::flush(self).is_err() {
+            log::warn!("block cache: dirty blocks lost on drop");
+        }
+    }
+}
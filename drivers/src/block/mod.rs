@@ -0,0 +1,17 @@
+//! Generic block device layers.
+//!
+//! Wrappers that sit between a storage driver and its users and
+//! themselves implement [`BlockDevice`](crate::hal::block_device::BlockDevice),
+//! so they can be stacked and registered like any other block device.
+//!
+//! - [`cache`]: LRU sector cache with write-back
+//! - [`partition`]: MBR/GPT parsing and per-partition devices
+//! - [`queue`]: asynchronous request queue that merges adjacent requests
+
+pub mod cache;
+pub mod partition;
+pub mod queue;
+
+pub use cache::CachedBlockDevice;
+pub use partition::PartitionDevice;
+pub use queue::BlockRequestQueue;
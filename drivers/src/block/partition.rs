@@ -0,0 +1,360 @@
+//! MBR and GPT partition tables.
+//!
+//! [`scan`] reads the partition table of a whole-disk device and returns
+//! its partitions; [`PartitionDevice`] exposes one of them as a block
+//! device whose block 0 is the first block of the partition.
+//!
+//! Partitions are numbered like Linux does: MBR primaries 1–4 by slot,
+//! logical partitions inside an extended partition from 5, and GPT
+//! partitions by their slot in the entry array.
+
+use crate::hal::block_device::{
+    BlockDevice, BlockDeviceError, BlockDeviceInfo, DynBlockDevice, Partition,
+};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// MBR partition type of a GPT protective entry.
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+/// MBR partition types of extended (container) partitions.
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+
+/// Upper bound on logical partitions, guards against looping EBR chains.
+const MAX_LOGICAL: u32 = 64;
+
+/// Upper bound on GPT entries considered.
+const MAX_GPT_ENTRIES: u32 = 128;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+// ============================================================================
+// Error Type
+// ============================================================================
+
+/// Partition table errors
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PartitionError {
+    /// Reading the table failed
+    Device(BlockDeviceError),
+    /// Protective MBR present but the GPT header is unusable
+    InvalidGpt,
+}
+
+impl From<BlockDeviceError> for PartitionError {
+    fn from(err: BlockDeviceError) -> Self {
+        PartitionError::Device(err)
+    }
+}
+
+// ============================================================================
+// Table Parsing
+// ============================================================================
+
+/// Partition type identifier, as stored in the table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PartitionType {
+    /// MBR system id byte
+    Mbr(u8),
+    /// GPT partition type GUID, in on-disk byte order
+    Gpt([u8; 16]),
+}
+
+/// One partition found by [`scan`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PartitionEntry {
+    /// Partition number (1-based)
+    pub number: u32,
+    /// First block on the disk
+    pub start: u64,
+    /// Length in blocks
+    pub count: u64,
+    /// Type from the table
+    pub kind: PartitionType,
+}
+
+/// Read the partition table of `disk`.
+///
+/// A disk without a valid MBR has no partitions and yields an empty list:
+/// one without the signature, with a slot whose boot indicator is neither
+/// 0x00 nor 0x80 or that starts outside the disk, or whose block 0 is a
+/// FAT boot sector (a "superfloppy" formatted without a table, which
+/// carries the same signature). Entries that run past the end of the disk
+/// are skipped.
+pub fn scan(disk: &dyn DynBlockDevice) -> Result<Vec<PartitionEntry>, PartitionError> {
+    let info = disk.info();
+    let mut block = vec![0u8; info.block_size];
+    disk.read_block(0, &mut block)?;
+
+    if block[510] != 0x55 || block[511] != 0xAA {
+        return Ok(Vec::new());
+    }
+    if is_fat_boot_sector(&block) || !is_mbr(&block, &info) {
+        log::debug!("no partition table, using the whole disk");
+        return Ok(Vec::new());
+    }
+
+    let primaries: Vec<(u8, u64, u64)> = (0..4).map(|i| mbr_entry(&block, i)).collect();
+    if primaries
+        .iter()
+        .any(|&(kind, _, _)| kind == MBR_TYPE_GPT_PROTECTIVE)
+    {
+        return scan_gpt(disk, &info);
+    }
+
+    let mut parts = Vec::new();
+    let mut next_logical = 5;
+    for (slot, &(kind, start, count)) in primaries.iter().enumerate() {
+        if kind == 0 || count == 0 {
+            continue;
+        }
+        if MBR_TYPES_EXTENDED.contains(&kind) {
+            scan_extended(disk, &info, start, &mut next_logical, &mut parts)?;
+            continue;
+        }
+        push_checked(
+            &mut parts,
+            &info,
+            PartitionEntry {
+                number: slot as u32 + 1,
+                start,
+                count,
+                kind: PartitionType::Mbr(kind),
+            },
+        );
+    }
+
+    Ok(parts)
+}
+
+/// `(type, start, count)` of MBR/EBR slot `i`.
+fn mbr_entry(block: &[u8], i: usize) -> (u8, u64, u64) {
+    let e = &block[446 + i * 16..446 + (i + 1) * 16];
+    (
+        e[4],
+        u32::from_le_bytes([e[8], e[9], e[10], e[11]]) as u64,
+        u32::from_le_bytes([e[12], e[13], e[14], e[15]]) as u64,
+    )
+}
+
+/// Whether the four slots of a signed block 0 look like an MBR's: each
+/// has a boot indicator of 0x00 or 0x80 and, if used, starts inside the
+/// disk after block 0.
+fn is_mbr(block: &[u8], info: &BlockDeviceInfo) -> bool {
+    (0..4).all(|i| {
+        let boot = block[446 + i * 16];
+        let (kind, start, count) = mbr_entry(block, i);
+        (boot == 0x00 || boot == 0x80)
+            && (kind == 0 || count == 0 || (start > 0 && start < info.block_count))
+    })
+}
+
+/// Whether a signed block 0 is a FAT volume boot sector: a jump
+/// instruction, a BIOS parameter block with a plausible sector size and
+/// a FAT type string where FAT12/16 or FAT32 keep it.
+fn is_fat_boot_sector(block: &[u8]) -> bool {
+    let jump = block[0] == 0xE9 || (block[0] == 0xEB && block[2] == 0x90);
+    let bytes_per_sector = u16::from_le_bytes([block[11], block[12]]);
+    jump && (512..=4096).contains(&bytes_per_sector)
+        && bytes_per_sector.is_power_of_two()
+        && (block[54..57] == *b"FAT" || block[82..87] == *b"FAT32")
+}
+
+/// Walk the chain of extended boot records inside an extended partition.
+fn scan_extended(
+    disk: &dyn DynBlockDevice,
+    info: &BlockDeviceInfo,
+    ext_start: u64,
+    next_logical: &mut u32,
+    parts: &mut Vec<PartitionEntry>,
+) -> Result<(), PartitionError> {
+    let mut block = vec![0u8; info.block_size];
+    let mut ebr = ext_start;
+
+    for _ in 0..MAX_LOGICAL {
+        if ebr >= info.block_count {
+            break;
+        }
+        disk.read_block(ebr, &mut block)?;
+        if block[510] != 0x55 || block[511] != 0xAA {
+            break;
+        }
+
+        // Slot 0: the logical partition, relative to this EBR
+        let (kind, start, count) = mbr_entry(&block, 0);
+        if kind != 0 && count != 0 {
+            push_checked(
+                parts,
+                info,
+                PartitionEntry {
+                    number: *next_logical,
+                    start: ebr + start,
+                    count,
+                    kind: PartitionType::Mbr(kind),
+                },
+            );
+            *next_logical += 1;
+        }
+
+        // Slot 1: the next EBR, relative to the extended partition
+        let (kind, start, _) = mbr_entry(&block, 1);
+        if kind == 0 || start == 0 {
+            break;
+        }
+        ebr = ext_start + start;
+    }
+
+    Ok(())
+}
+
+fn scan_gpt(
+    disk: &dyn DynBlockDevice,
+    info: &BlockDeviceInfo,
+) -> Result<Vec<PartitionEntry>, PartitionError> {
+    let bs = info.block_size;
+    let mut header = vec![0u8; bs];
+    disk.read_block(1, &mut header)?;
+
+    if &header[0..8] != GPT_SIGNATURE {
+        return Err(PartitionError::InvalidGpt);
+    }
+
+    let u32_at =
+        |b: &[u8], off: usize| u32::from_le_bytes([b[off], b[off + 1], b[off + 2], b[off + 3]]);
+    let u64_at = |b: &[u8], off: usize| {
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(&b[off..off + 8]);
+        u64::from_le_bytes(raw)
+    };
+
+    let entries_lba = u64_at(&header, 72);
+    let num_entries = u32_at(&header, 80).min(MAX_GPT_ENTRIES);
+    let entry_size = u32_at(&header, 84) as usize;
+    if entry_size < 128 || entry_size > bs || !bs.is_multiple_of(entry_size) {
+        return Err(PartitionError::InvalidGpt);
+    }
+
+    let per_block = bs / entry_size;
+    let mut parts = Vec::new();
+    let mut block = vec![0u8; bs];
+
+    for i in 0..num_entries as usize {
+        if i % per_block == 0 {
+            disk.read_block(entries_lba + (i / per_block) as u64, &mut block)?;
+        }
+        let e = &block[(i % per_block) * entry_size..][..entry_size];
+
+        let mut type_guid = [0u8; 16];
+        type_guid.copy_from_slice(&e[0..16]);
+        if type_guid == [0u8; 16] {
+            continue;
+        }
+
+        let first = u64_at(e, 32);
+        let last = u64_at(e, 40);
+        if last < first {
+            continue;
+        }
+        push_checked(
+            &mut parts,
+            info,
+            PartitionEntry {
+                number: i as u32 + 1,
+                start: first,
+                count: last - first + 1,
+                kind: PartitionType::Gpt(type_guid),
+            },
+        );
+    }
+
+    Ok(parts)
+}
+
+fn push_checked(parts: &mut Vec<PartitionEntry>, info: &BlockDeviceInfo, entry: PartitionEntry) {
+    match entry.start.checked_add(entry.count) {
+        Some(end) if entry.start > 0 && end <= info.block_count => parts.push(entry),
+        _ => log::warn!(
+            "partition {} ({}+{}) lies outside the disk, ignored",
+            entry.number,
+            entry.start,
+            entry.count
+        ),
+    }
+}
+
+// ============================================================================
+// Partition Device
+// ============================================================================
+
+/// A partition of a whole-disk device, addressed from its own block 0.
+pub struct PartitionDevice {
+    disk: Arc<dyn DynBlockDevice>,
+    entry: PartitionEntry,
+}
+
+impl PartitionDevice {
+    pub fn new(disk: Arc<dyn DynBlockDevice>, entry: PartitionEntry) -> Self {
+        Self { disk, entry }
+    }
+
+    /// The table entry this device was built from.
+    pub fn entry(&self) -> &PartitionEntry {
+        &self.entry
+    }
+
+    /// Translate a partition-relative range to disk blocks.
+    fn translate(&self, start_block: u64, count: usize) -> Result<u64, BlockDeviceError> {
+        match start_block.checked_add(count as u64) {
+            Some(end) if end <= self.entry.count => Ok(self.entry.start + start_block),
+            _ => Err(BlockDeviceError::InvalidAddress),
+        }
+    }
+}
+
+impl BlockDevice for PartitionDevice {
+    type Error = BlockDeviceError;
+
+    fn info(&self) -> BlockDeviceInfo {
+        let disk = self.disk.info();
+        BlockDeviceInfo {
+            block_count: self.entry.count,
+            capacity: self.entry.count * disk.block_size as u64,
+            ..disk
+        }
+    }
+
+    fn read_blocks(&self, start_block: u64, buffers: &mut [&mut [u8]]) -> Result<(), Self::Error> {
+        let lba = self.translate(start_block, buffers.len())?;
+        self.disk.read_blocks(lba, buffers)
+    }
+
+    fn write_blocks(&self, start_block: u64, buffers: &[&[u8]]) -> Result<(), Self::Error> {
+        let lba = self.translate(start_block, buffers.len())?;
+        self.disk.write_blocks(lba, buffers)
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        self.disk.flush()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.disk.is_ready()
+    }
+}
+
+impl Partition for PartitionDevice {
+    fn device(&self) -> &dyn DynBlockDevice {
+        &*self.disk
+    }
+
+    /// First block on the underlying disk.
+    fn offset(&self) -> u64 {
+        self.entry.start
+    }
+
+    /// Length in blocks.
+    fn size(&self) -> u64 {
+        self.entry.count
+    }
+}
@@ -0,0 +1,372 @@
+//! Block I/O request queue.
+//!
+//! [`BlockRequestQueue`] sits between a block device's users and its
+//! driver. A request is [submitted](BlockRequestQueue::submit) with a
+//! buffer it owns and returns at once; the transfer is carried out later
+//! and its [`Completion`] is called with the buffer and the result. Any
+//! number of tasks can have requests queued at the same time.
+//!
+//! A request for the blocks just before or after one already queued in
+//! the same direction is merged into it, so runs of single-block requests
+//! reach the driver as one multi-block transfer of up to
+//! [`MAX_MERGE_BLOCKS`]. Merging never moves a request past one it
+//! overlaps, so a read still sees every write submitted before it.
+//!
+//! Requests are carried out by a dispatcher, one transfer at a time per
+//! queue. Until one is installed with [`use_dispatcher`], the task that
+//! submits a request carries it out before `submit` returns, as drivers
+//! poll until they are given a [`WaitEvent`]. Once one is, `submit` only
+//! notifies the event, and the kernel task sleeping on it runs
+//! [`dispatch_all`].
+//!
+//! The queue is itself a [`BlockDevice`], whose calls submit a request
+//! and sleep until it completes.
+
+use crate::hal::block_device::{BlockDevice, BlockDeviceError, BlockDeviceInfo};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use common::sync::Once;
+use common::sync::event::WaitEvent;
+use spin::Mutex;
+
+/// Most blocks merged into one transfer (64 KiB with 512-byte blocks).
+pub const MAX_MERGE_BLOCKS: usize = 128;
+
+/// Direction of a request
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockOp {
+    Read,
+    Write,
+}
+
+/// Called once a request is carried out, with its buffer (holding what
+/// was read, for a read) and the result. It runs on the dispatcher, with
+/// the queue busy, so it must not wait on the queue.
+pub type Completion = Box<dyn FnOnce(Vec<u8>, Result<(), BlockDeviceError>) + Send>;
+
+/// Where the dispatcher sleeps; `None` until there is one
+static DISPATCHER: Once<&'static dyn WaitEvent> = Once::new();
+
+/// Every queue, for [`dispatch_all`]
+static QUEUES: Mutex<Vec<Weak<dyn Dispatch>>> = Mutex::new(Vec::new());
+
+/// Carry requests out from now on with a dispatcher sleeping on `event`
+/// instead of on the task that submits them. The dispatcher must call
+/// [`dispatch_all`] whenever [`has_pending`] is true; `event` is notified
+/// when that may have changed and whenever a request completes.
+pub fn use_dispatcher(event: &'static dyn WaitEvent) {
+    let _ = DISPATCHER.set(event);
+}
+
+/// Whether any queue has requests waiting to be carried out.
+pub fn has_pending() -> bool {
+    QUEUES
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .any(|queue| queue.has_pending())
+}
+
+/// Carry out what every queue has waiting, and forget dropped queues.
+pub fn dispatch_all() {
+    let queues: Vec<Arc<dyn Dispatch>> = {
+        let mut queues = QUEUES.lock();
+        queues.retain(|queue| queue.strong_count() > 0);
+        queues.iter().filter_map(Weak::upgrade).collect()
+    };
+    for queue in queues {
+        queue.dispatch();
+    }
+}
+
+/// Wake whoever sleeps on the dispatcher
+fn notify() {
+    if let Some(event) = DISPATCHER.get() {
+        event.notify();
+    }
+}
+
+/// What [`dispatch_all`] needs of a queue, whatever its device
+trait Dispatch: Send + Sync {
+    fn has_pending(&self) -> bool;
+    fn dispatch(&self);
+}
+
+// ============================================================================
+// Requests
+// ============================================================================
+
+/// One submitted request's buffer and completion
+struct Part {
+    buf: Vec<u8>,
+    done: Completion,
+}
+
+/// A transfer of adjacent blocks, made of one or more submitted requests
+/// in block order
+struct Request {
+    op: BlockOp,
+    start: u64,
+    blocks: usize,
+    parts: Vec<Part>,
+}
+
+impl Request {
+    fn end(&self) -> u64 {
+        self.start + self.blocks as u64
+    }
+
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end()
+    }
+}
+
+// ============================================================================
+// Queue
+// ============================================================================
+
+struct Shared<B: BlockDevice> {
+    inner: B,
+    block_size: usize,
+    /// Requests in the order they are carried out
+    pending: Mutex<VecDeque<Request>>,
+    /// Held while requests are carried out, so one at a time reaches the
+    /// driver
+    busy: Mutex<()>,
+}
+
+impl<B: BlockDevice> Shared<B> {
+    /// Queue a part, merged into a queued request if it can be.
+    fn enqueue(&self, op: BlockOp, start: u64, part: Part) {
+        let blocks = part.buf.len() / self.block_size;
+        let end = start + blocks as u64;
+        let mut pending = self.pending.lock();
+
+        // Merging into a request moves the part ahead of every one queued
+        // after it, which must not touch the same blocks
+        let mut merge = None;
+        for (i, request) in pending.iter().enumerate().rev() {
+            if request.op == op
+                && request.blocks + blocks <= MAX_MERGE_BLOCKS
+                && (request.end() == start || end == request.start)
+            {
+                merge = Some(i);
+                break;
+            }
+            if request.overlaps(start, end) {
+                break;
+            }
+        }
+
+        match merge.and_then(|i| pending.get_mut(i)) {
+            Some(request) if request.end() == start => {
+                request.blocks += blocks;
+                request.parts.push(part);
+            }
+            Some(request) => {
+                request.start = start;
+                request.blocks += blocks;
+                request.parts.insert(0, part);
+            }
+            None => pending.push_back(Request {
+                op,
+                start,
+                blocks,
+                parts: vec![part],
+            }),
+        }
+    }
+
+    /// Carry out one request and call its completions.
+    fn carry_out(&self, mut request: Request) {
+        let result = match request.op {
+            BlockOp::Read => {
+                let mut buffers: Vec<&mut [u8]> = request
+                    .parts
+                    .iter_mut()
+                    .flat_map(|part| part.buf.chunks_mut(self.block_size))
+                    .collect();
+                self.inner.read_blocks(request.start, &mut buffers)
+            }
+            BlockOp::Write => {
+                let buffers: Vec<&[u8]> = request
+                    .parts
+                    .iter()
+                    .flat_map(|part| part.buf.chunks(self.block_size))
+                    .collect();
+                self.inner.write_blocks(request.start, &buffers)
+            }
+        }
+        .map_err(Into::into);
+
+        for part in request.parts {
+            (part.done)(part.buf, result);
+        }
+    }
+}
+
+impl<B: BlockDevice> Dispatch for Shared<B> {
+    fn has_pending(&self) -> bool {
+        !self.pending.lock().is_empty()
+    }
+
+    fn dispatch(&self) {
+        loop {
+            // Whoever is busy already carries out what is queued now
+            let Some(busy) = self.busy.try_lock() else {
+                return;
+            };
+            while let Some(request) = self.pending.lock().pop_front() {
+                self.carry_out(request);
+                notify();
+            }
+            drop(busy);
+            // For flushes waiting on the queue to go idle
+            notify();
+
+            // Queued between the last check and the busy lock's release
+            if !self.has_pending() {
+                return;
+            }
+        }
+    }
+}
+
+/// Request queue in front of a block device.
+///
+/// Cloning gives another handle to the same queue.
+pub struct BlockRequestQueue<B: BlockDevice + 'static> {
+    shared: Arc<Shared<B>>,
+}
+
+impl<B: BlockDevice + 'static> Clone for BlockRequestQueue<B> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<B: BlockDevice + 'static> BlockRequestQueue<B> {
+    /// Put a queue in front of `inner`.
+    pub fn new(inner: B) -> Self {
+        let block_size = inner.info().block_size;
+        let shared = Arc::new(Shared {
+            inner,
+            block_size,
+            pending: Mutex::new(VecDeque::new()),
+            busy: Mutex::new(()),
+        });
+        let dispatch: Arc<dyn Dispatch> = shared.clone();
+        QUEUES.lock().push(Arc::downgrade(&dispatch));
+        Self { shared }
+    }
+
+    /// The device behind the queue.
+    pub fn inner(&self) -> &B {
+        &self.shared.inner
+    }
+
+    /// Queue a transfer of `buf` to or from the blocks from
+    /// `start_block` on. `buf` must hold a whole number of blocks, one at
+    /// least; otherwise `done` is called at once with an error.
+    pub fn submit(&self, op: BlockOp, start_block: u64, buf: Vec<u8>, done: Completion) {
+        let shared = &self.shared;
+        if buf.is_empty() || !buf.len().is_multiple_of(shared.block_size) {
+            return done(buf, Err(BlockDeviceError::InvalidBuffer));
+        }
+        if op == BlockOp::Write && shared.inner.info().read_only {
+            return done(buf, Err(BlockDeviceError::WriteProtected));
+        }
+
+        shared.enqueue(op, start_block, Part { buf, done });
+        match DISPATCHER.get() {
+            Some(event) => event.notify(),
+            None => shared.dispatch(),
+        }
+    }
+
+    /// Submit a request and sleep until it completes.
+    fn transfer(
+        &self,
+        op: BlockOp,
+        start_block: u64,
+        buf: Vec<u8>,
+    ) -> (Vec<u8>, Result<(), BlockDeviceError>) {
+        let slot = Arc::new(Mutex::new(None));
+        let filled = slot.clone();
+        self.submit(
+            op,
+            start_block,
+            buf,
+            Box::new(move |buf, result| *filled.lock() = Some((buf, result))),
+        );
+        self.wait_until(&mut || slot.lock().is_some());
+        let taken = slot.lock().take();
+        taken.expect("request completed")
+    }
+
+    /// Sleep on the dispatcher until `condition` holds, or without one
+    /// carry requests out until it does.
+    fn wait_until(&self, condition: &mut dyn FnMut() -> bool) {
+        match DISPATCHER.get() {
+            Some(event) => event.wait_until(condition),
+            None => {
+                while !condition() {
+                    self.shared.dispatch();
+                    core::hint::spin_loop();
+                }
+            }
+        }
+    }
+}
+
+impl<B: BlockDevice + 'static> BlockDevice for BlockRequestQueue<B> {
+    type Error = BlockDeviceError;
+
+    fn info(&self) -> BlockDeviceInfo {
+        self.shared.inner.info()
+    }
+
+    fn read_blocks(&self, start_block: u64, buffers: &mut [&mut [u8]]) -> Result<(), Self::Error> {
+        let block_size = self.shared.block_size;
+        if buffers.iter().any(|b| b.len() < block_size) {
+            return Err(BlockDeviceError::InvalidBuffer);
+        }
+
+        let buf = vec![0u8; buffers.len() * block_size];
+        let (buf, result) = self.transfer(BlockOp::Read, start_block, buf);
+        result?;
+        for (dst, src) in buffers.iter_mut().zip(buf.chunks(block_size)) {
+            dst[..block_size].copy_from_slice(src);
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, start_block: u64, buffers: &[&[u8]]) -> Result<(), Self::Error> {
+        let block_size = self.shared.block_size;
+        if buffers.iter().any(|b| b.len() < block_size) {
+            return Err(BlockDeviceError::InvalidBuffer);
+        }
+
+        let mut buf = Vec::with_capacity(buffers.len() * block_size);
+        for b in buffers {
+            buf.extend_from_slice(&b[..block_size]);
+        }
+        self.transfer(BlockOp::Write, start_block, buf).1
+    }
+
+    /// Wait for every queued request, then flush the device.
+    fn flush(&self) -> Result<(), Self::Error> {
+        let shared = &self.shared;
+        self.wait_until(&mut || !shared.has_pending() && !shared.busy.is_locked());
+        shared.inner.flush().map_err(Into::into)
+    }
+
+    fn is_ready(&self) -> bool {
+        self.shared.inner.is_ready()
+    }
+}
@@ -1,54 +1,120 @@
 //! Device Manager
 //!
 //! Central registry for all hardware devices. Devices are registered by the
-//! platform during initialization and can be accessed by name or type.
+//! platform during initialization and can be accessed by name or class.
+//!
+//! Every device gets a stable, class-derived name (`uart0`, `mmcblk0`,
+//! `fb0`, …) assigned in registration order. The name the platform used
+//! for it (e.g. `serial0` from the boot tables) is kept as an alias, so
+//! lookups by either name succeed. Partitions found on a block device are
+//! registered after it as `mmcblk0p1`, `mmcblk0p2`, …. Block devices the
+//! kernel builds in software are numbered under their own prefix instead
+//! (`loop0`, `ram0`, …).
 //!
 //! # Usage
 //!
 //! ```rust
-//! use drivers::device_manager::{devices, Device};
+//! use drivers::device_manager::{DeviceClass, DeviceManager};
 //!
 //! // Platform registers devices during init
-//! devices().lock().register("serial0", Device::new_serial(uart));
+//! let mut dm = DeviceManager::new();
+//! dm.register_serial("serial0", uart)?; // becomes "uart0", alias "serial0"
 //!
 //! // Kernel accesses devices by name
-//! if let Some(serial) = devices().lock().serial("serial0") {
-//!     let mut port = serial.lock();
-//!     port.write_byte(b'H');
+//! if let Some(serial) = dm.serial("uart0") {
+//!     serial.lock().write_byte(b'H');
+//! }
+//!
+//! // Or enumerates a whole class
+//! for (name, _dev) in dm.by_class(DeviceClass::Block) {
+//!     log::info!("block device {}", name);
 //! }
 //!
-//! // Or get the serial console (default serial)
-//! if let Some(console) = devices().lock().serial_console() {
-//!     console.lock().write_str("Hello, world!\n");
+//! // Subsystems follow hotplug through an event channel
+//! let events = dm.subscribe();
+//! while let Some(ev) = events.try_recv() {
+//!     log::info!("{:?}", ev);
 //! }
 //! ```
 
+use crate::block::partition::{self, PartitionDevice};
+use crate::driver;
+use crate::hal::audio::DynAudioOutput;
 use crate::hal::block_device::{BlockDevice, DynBlockDevice};
 use crate::hal::fb::FrameBuffer;
+use crate::hal::gpio::DynGpioController;
 use crate::hal::interrupt::{DynInterruptController, InterruptController};
+use crate::hal::net::DynNetworkDevice;
+use crate::hal::rtc::DynRtc;
 use crate::hal::serial::DynSerialPort;
 use crate::hal::timer::DynTimer;
-use alloc::collections::BTreeMap;
+use crate::platform::DeviceInfo;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
 use alloc::string::String;
-use alloc::sync::Arc;
-use core::cell::OnceCell;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use common::sync::Once;
+use common::sync::event::WaitEvent;
 use spin::Mutex;
 
-struct OnceCellChannel {
-    inner: OnceCell<usize>,
+static SYS_TIMER_CHANNEL: Once<usize> = Once::new();
+
+/// Device classes, used for stable naming and enumeration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DeviceClass {
+    Serial,
+    Block,
+    FrameBuffer,
+    Gpio,
+    Rtc,
+    Audio,
+    Network,
+    Timer,
+    InterruptController,
 }
 
-unsafe impl Sync for OnceCellChannel {}
+impl DeviceClass {
+    /// Name prefix for devices of this class (`uart` → `uart0`, `uart1`, …).
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            DeviceClass::Serial => "uart",
+            DeviceClass::Block => "mmcblk",
+            DeviceClass::FrameBuffer => "fb",
+            DeviceClass::Gpio => "gpio",
+            DeviceClass::Rtc => "rtc",
+            DeviceClass::Audio => "audio",
+            DeviceClass::Network => "eth",
+            DeviceClass::Timer => "timer",
+            DeviceClass::InterruptController => "intc",
+        }
+    }
 
-static SYS_TIMER_CHANNEL: OnceCellChannel = OnceCellChannel {
-    inner: OnceCell::new(),
-};
+    /// Human-readable class name.
+    pub const fn name(self) -> &'static str {
+        match self {
+            DeviceClass::Serial => "Serial",
+            DeviceClass::Block => "Block",
+            DeviceClass::FrameBuffer => "FrameBuffer",
+            DeviceClass::Gpio => "Gpio",
+            DeviceClass::Rtc => "Rtc",
+            DeviceClass::Audio => "Audio",
+            DeviceClass::Network => "Network",
+            DeviceClass::Timer => "Timer",
+            DeviceClass::InterruptController => "InterruptController",
+        }
+    }
+}
 
 /// Device types that can be managed
 pub enum Device {
     Serial(Arc<Mutex<dyn DynSerialPort>>),
     Block(Arc<dyn DynBlockDevice>),
     FrameBuffer(Arc<Mutex<dyn FrameBuffer>>),
+    Gpio(Arc<Mutex<dyn DynGpioController>>),
+    Rtc(Arc<Mutex<dyn DynRtc>>),
+    Audio(Arc<Mutex<dyn DynAudioOutput>>),
+    Network(Arc<Mutex<dyn DynNetworkDevice>>),
     Timer(Arc<Mutex<dyn DynTimer>>),
     InterruptController(Arc<Mutex<dyn DynInterruptController>>),
 }
@@ -69,6 +135,26 @@ impl Device {
         Device::FrameBuffer(Arc::new(Mutex::new(fb)))
     }
 
+    /// Create a GPIO device from any GpioController implementation
+    pub fn new_gpio<T: DynGpioController + 'static>(gpio: T) -> Self {
+        Device::Gpio(Arc::new(Mutex::new(gpio)))
+    }
+
+    /// Create an RTC device from any Rtc implementation
+    pub fn new_rtc<T: DynRtc + 'static>(rtc: T) -> Self {
+        Device::Rtc(Arc::new(Mutex::new(rtc)))
+    }
+
+    /// Create an audio device from any AudioOutput implementation
+    pub fn new_audio<T: DynAudioOutput + 'static>(audio: T) -> Self {
+        Device::Audio(Arc::new(Mutex::new(audio)))
+    }
+
+    /// Create a network device from any NetworkDevice implementation
+    pub fn new_network<T: DynNetworkDevice + 'static>(net: T) -> Self {
+        Device::Network(Arc::new(Mutex::new(net)))
+    }
+
     /// Create a timer device from any Timer implementation
     pub fn new_timer<T: DynTimer + 'static>(timer: T) -> Self {
         Device::Timer(Arc::new(Mutex::new(timer)))
@@ -78,28 +164,215 @@ impl Device {
     pub fn new_interrupt_controller<T: DynInterruptController + 'static>(intc: T) -> Self {
         Device::InterruptController(Arc::new(Mutex::new(intc)))
     }
+
+    /// The class this device belongs to
+    pub fn class(&self) -> DeviceClass {
+        match self {
+            Device::Serial(_) => DeviceClass::Serial,
+            Device::Block(_) => DeviceClass::Block,
+            Device::FrameBuffer(_) => DeviceClass::FrameBuffer,
+            Device::Gpio(_) => DeviceClass::Gpio,
+            Device::Rtc(_) => DeviceClass::Rtc,
+            Device::Audio(_) => DeviceClass::Audio,
+            Device::Network(_) => DeviceClass::Network,
+            Device::Timer(_) => DeviceClass::Timer,
+            Device::InterruptController(_) => DeviceClass::InterruptController,
+        }
+    }
+}
+
+// ============================================================================
+// Device Events
+// ============================================================================
+
+/// Maximum number of undelivered events kept per subscriber. When a
+/// subscriber falls behind, the oldest events are dropped.
+const MAX_PENDING_EVENTS: usize = 64;
+
+/// Device add/remove notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Added { name: String, class: DeviceClass },
+    Removed { name: String, class: DeviceClass },
+}
+
+impl DeviceEvent {
+    /// Stable name of the device the event refers to
+    pub fn name(&self) -> &str {
+        match self {
+            DeviceEvent::Added { name, .. } | DeviceEvent::Removed { name, .. } => name,
+        }
+    }
+
+    /// Class of the device the event refers to
+    pub fn class(&self) -> DeviceClass {
+        match self {
+            DeviceEvent::Added { class, .. } | DeviceEvent::Removed { class, .. } => *class,
+        }
+    }
+}
+
+type EventQueue = Mutex<VecDeque<DeviceEvent>>;
+
+/// Receiving end of a device event subscription.
+///
+/// Events are queued rather than delivered by callback, so subscribers can
+/// take the device manager lock while handling them. Dropping the receiver
+/// unsubscribes.
+pub struct DeviceEventReceiver {
+    queue: Arc<EventQueue>,
+}
+
+impl DeviceEventReceiver {
+    /// Take the oldest pending event, if any
+    pub fn try_recv(&self) -> Option<DeviceEvent> {
+        self.queue.lock().pop_front()
+    }
+
+    /// Take all pending events
+    pub fn drain(&self) -> impl Iterator<Item = DeviceEvent> {
+        core::mem::take(&mut *self.queue.lock()).into_iter()
+    }
+
+    /// Are there undelivered events?
+    pub fn has_pending(&self) -> bool {
+        !self.queue.lock().is_empty()
+    }
+}
+
+// ============================================================================
+// Interrupt Requests
+// ============================================================================
+
+/// An interrupt a driver asks for at probe. Handlers are the kernel's to
+/// register, so the request is kept until the kernel takes it with
+/// [`DeviceManager::take_irq_requests`] and routes the line.
+pub struct IrqRequest {
+    /// Name of the device, for diagnostics
+    pub device: String,
+    pub irq: u32,
+    /// Run on every interrupt on the line. Returns whether the device
+    /// raised it, as the line may be shared.
+    pub handler: fn() -> bool,
+    /// Called once the line is unmasked, with an event for the driver to
+    /// sleep on and `handler` to notify
+    pub enabled: fn(&'static dyn WaitEvent),
 }
 
 /// Device Manager - Central registry for all hardware devices
 pub struct DeviceManager {
+    /// Devices keyed by their stable name
     devices: BTreeMap<String, Device>,
+    /// Platform-provided names → stable name
+    aliases: BTreeMap<String, String>,
+    /// Event queues of live subscribers
+    subscribers: Vec<Weak<EventQueue>>,
+    /// Interrupts asked for at probe, not yet routed
+    irq_requests: Vec<IrqRequest>,
 }
 
 impl DeviceManager {
     pub const fn new() -> Self {
         Self {
             devices: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            subscribers: Vec::new(),
+            irq_requests: Vec::new(),
         }
     }
 
-    /// Register a device with a name
+    /// Register a device under an explicit name
     pub fn register(&mut self, name: String, device: Device) {
-        self.devices.insert(name, device);
+        let class = device.class();
+        if let Some(old) = self.devices.insert(name.clone(), device) {
+            self.notify(DeviceEvent::Removed {
+                name: name.clone(),
+                class: old.class(),
+            });
+        }
+        self.notify(DeviceEvent::Added { name, class });
+    }
+
+    /// Remove a device by stable name or alias.
+    ///
+    /// Aliases pointing at it are dropped and subscribers receive
+    /// [`DeviceEvent::Removed`]. Outstanding `Arc` handles stay valid; the
+    /// driver decides how to fail I/O on a vanished device.
+    pub fn unregister(&mut self, name: &str) -> Option<Device> {
+        let name = String::from(self.resolve(name)?);
+        let device = self.devices.remove(&name)?;
+        self.aliases.retain(|_, target| *target != name);
+        self.notify(DeviceEvent::Removed {
+            name,
+            class: device.class(),
+        });
+        Some(device)
+    }
+
+    /// Subscribe to device add/remove events.
+    ///
+    /// Only events after the call are delivered; use [`DeviceManager::devices`]
+    /// to pick up what is already registered.
+    pub fn subscribe(&mut self) -> DeviceEventReceiver {
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        self.subscribers.push(Arc::downgrade(&queue));
+        DeviceEventReceiver { queue }
+    }
+
+    /// Queue an event for every live subscriber, pruning dropped ones
+    fn notify(&mut self, event: DeviceEvent) {
+        self.subscribers.retain(|weak| match weak.upgrade() {
+            Some(queue) => {
+                let mut queue = queue.lock();
+                if queue.len() >= MAX_PENDING_EVENTS {
+                    queue.pop_front();
+                }
+                queue.push_back(event.clone());
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Register a device under the next free stable name for its class.
+    ///
+    /// `alias`, if given and different from the stable name, is recorded so
+    /// the device can also be looked up by it. Returns the stable name.
+    pub fn register_device(&mut self, device: Device, alias: Option<String>) -> String {
+        let name = self.next_free_name(device.class().prefix());
+
+        if let Some(alias) = alias.filter(|a| *a != name) {
+            self.aliases.insert(alias, name.clone());
+        }
+        let class = device.class();
+        self.devices.insert(name.clone(), device);
+        self.notify(DeviceEvent::Added {
+            name: name.clone(),
+            class,
+        });
+        name
+    }
+
+    /// First `<prefix>N` not taken by a device or alias
+    fn next_free_name(&self, prefix: &str) -> String {
+        (0..)
+            .map(|i| format!("{}{}", prefix, i))
+            .find(|n| !self.devices.contains_key(n) && !self.aliases.contains_key(n))
+            .unwrap()
+    }
+
+    /// Resolve a stable name or alias to the stable name
+    pub fn resolve<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        if self.devices.contains_key(name) {
+            Some(name)
+        } else {
+            self.aliases.get(name).map(String::as_str)
+        }
     }
 
-    /// Get a device by name
+    /// Get a device by stable name or alias
     pub fn get(&self, name: &str) -> Option<&Device> {
-        self.devices.get(name)
+        self.devices.get(self.resolve(name)?)
     }
 
     /// List all device names
@@ -107,6 +380,21 @@ impl DeviceManager {
         self.devices.keys()
     }
 
+    /// Iterate over all devices with their stable names
+    pub fn devices(&self) -> impl Iterator<Item = (&str, &Device)> {
+        self.devices.iter().map(|(name, dev)| (name.as_str(), dev))
+    }
+
+    /// Iterate over all devices of one class
+    pub fn by_class(&self, class: DeviceClass) -> impl Iterator<Item = (&str, &Device)> {
+        self.devices().filter(move |(_, dev)| dev.class() == class)
+    }
+
+    /// Iterate over `(alias, stable name)` pairs
+    pub fn aliases(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.aliases.iter().map(|(a, n)| (a.as_str(), n.as_str()))
+    }
+
     // ========================================================================
     // Type-Specific Accessors
     // ========================================================================
@@ -135,6 +423,38 @@ impl DeviceManager {
         }
     }
 
+    /// Get a GPIO controller by name
+    pub fn gpio(&self, name: &str) -> Option<Arc<Mutex<dyn DynGpioController>>> {
+        match self.get(name)? {
+            Device::Gpio(gpio) => Some(Arc::clone(gpio)),
+            _ => None,
+        }
+    }
+
+    /// Get a real-time clock by name
+    pub fn rtc(&self, name: &str) -> Option<Arc<Mutex<dyn DynRtc>>> {
+        match self.get(name)? {
+            Device::Rtc(rtc) => Some(Arc::clone(rtc)),
+            _ => None,
+        }
+    }
+
+    /// Get an audio output by name
+    pub fn audio(&self, name: &str) -> Option<Arc<Mutex<dyn DynAudioOutput>>> {
+        match self.get(name)? {
+            Device::Audio(audio) => Some(Arc::clone(audio)),
+            _ => None,
+        }
+    }
+
+    /// Get a network device by name
+    pub fn network(&self, name: &str) -> Option<Arc<Mutex<dyn DynNetworkDevice>>> {
+        match self.get(name)? {
+            Device::Network(net) => Some(Arc::clone(net)),
+            _ => None,
+        }
+    }
+
     /// Get a timer by name
     pub fn timer(&self, name: &str) -> Option<Arc<Mutex<dyn DynTimer>>> {
         match self.get(name)? {
@@ -195,9 +515,22 @@ impl DeviceManager {
             })
     }
 
+    /// Get the wall clock (default RTC)
+    ///
+    /// Tries in order: "rtc", first RTC device
+    pub fn wall_clock(&self) -> Option<Arc<Mutex<dyn DynRtc>>> {
+        self.rtc("rtc").or_else(|| {
+            self.by_class(DeviceClass::Rtc)
+                .find_map(|(_, dev)| match dev {
+                    Device::Rtc(rtc) => Some(rtc.clone()),
+                    _ => None,
+                })
+        })
+    }
+
     /// Get the system timer channel if set
     pub fn sys_timer_channel() -> Option<usize> {
-        SYS_TIMER_CHANNEL.inner.get().copied()
+        SYS_TIMER_CHANNEL.get().copied()
     }
 
     /// Get the interrupt controller (default)
@@ -217,6 +550,16 @@ impl DeviceManager {
             })
     }
 
+    /// Ask for an interrupt to be routed to a driver (helper for probe)
+    pub fn request_irq(&mut self, request: IrqRequest) {
+        self.irq_requests.push(request);
+    }
+
+    /// Take the interrupts drivers have asked for, for the kernel to route
+    pub fn take_irq_requests(&mut self) -> Vec<IrqRequest> {
+        core::mem::take(&mut self.irq_requests)
+    }
+
     // ========================================================================
     // Registration Helpers for Platform
     // ========================================================================
@@ -227,27 +570,112 @@ impl DeviceManager {
         name: impl Into<String>,
         serial: T,
     ) -> Result<(), &'static str> {
-        self.register(name.into(), Device::new_serial(serial));
+        self.register_device(Device::new_serial(serial), Some(name.into()));
         Ok(())
     }
 
     /// Register a block device (helper for platform)
+    ///
+    /// Partitions found on it are registered too, as `<name>p1`, `<name>p2`, …
     pub fn register_block<T: DynBlockDevice + 'static>(
         &mut self,
         name: impl Into<String>,
         block: T,
     ) -> Result<(), &'static str> {
-        self.register(name.into(), Device::new_block(block));
+        let name = self.register_device(Device::new_block(block), Some(name.into()));
+        self.register_partitions(&name);
         Ok(())
     }
 
+    /// Register a software block device under the next free `<prefix>N`
+    /// name (`loop0`, `ram0`, …) instead of the class's `mmcblkN`.
+    ///
+    /// Partitions found on it are registered too, as for
+    /// [`register_block`](Self::register_block). Returns the name.
+    pub fn register_block_numbered<T: DynBlockDevice + 'static>(
+        &mut self,
+        prefix: &str,
+        block: T,
+    ) -> String {
+        let name = self.next_free_name(prefix);
+        self.register(name.clone(), Device::new_block(block));
+        self.register_partitions(&name);
+        name
+    }
+
+    /// Scan a registered disk for partitions and register a block device
+    /// for each. Returns the number found.
+    pub fn register_partitions(&mut self, disk_name: &str) -> usize {
+        let Some(disk) = self.block(disk_name) else {
+            return 0;
+        };
+        let disk_name = String::from(self.resolve(disk_name).unwrap_or(disk_name));
+
+        let parts = match partition::scan(&*disk) {
+            Ok(parts) => parts,
+            Err(e) => {
+                log::warn!("{}: unreadable partition table: {:?}", disk_name, e);
+                return 0;
+            }
+        };
+
+        for entry in &parts {
+            let dev = PartitionDevice::new(Arc::clone(&disk), *entry);
+            self.register(
+                format!("{}p{}", disk_name, entry.number),
+                Device::new_block(dev),
+            );
+        }
+        parts.len()
+    }
+
     /// Register a framebuffer (helper for platform)
     pub fn register_framebuffer<T: FrameBuffer + 'static>(
         &mut self,
         name: impl Into<String>,
         fb: T,
     ) -> Result<(), &'static str> {
-        self.register(name.into(), Device::new_framebuffer(fb));
+        self.register_device(Device::new_framebuffer(fb), Some(name.into()));
+        Ok(())
+    }
+
+    /// Register a GPIO controller (helper for platform)
+    pub fn register_gpio<T: DynGpioController + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        gpio: T,
+    ) -> Result<(), &'static str> {
+        self.register_device(Device::new_gpio(gpio), Some(name.into()));
+        Ok(())
+    }
+
+    /// Register a real-time clock (helper for platform)
+    pub fn register_rtc<T: DynRtc + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        rtc: T,
+    ) -> Result<(), &'static str> {
+        self.register_device(Device::new_rtc(rtc), Some(name.into()));
+        Ok(())
+    }
+
+    /// Register an audio output (helper for platform)
+    pub fn register_audio<T: DynAudioOutput + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        audio: T,
+    ) -> Result<(), &'static str> {
+        self.register_device(Device::new_audio(audio), Some(name.into()));
+        Ok(())
+    }
+
+    /// Register a network device (helper for platform)
+    pub fn register_network<T: DynNetworkDevice + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        net: T,
+    ) -> Result<(), &'static str> {
+        self.register_device(Device::new_network(net), Some(name.into()));
         Ok(())
     }
 
@@ -260,11 +688,10 @@ impl DeviceManager {
     ) -> Result<(), &'static str> {
         if let Some(channel) = sys_channel {
             SYS_TIMER_CHANNEL
-                .inner
                 .set(channel)
                 .map_err(|_| "System timer channel already set")?;
         }
-        self.register(name.into(), Device::new_timer(timer));
+        self.register_device(Device::new_timer(timer), Some(name.into()));
         Ok(())
     }
 
@@ -274,7 +701,41 @@ impl DeviceManager {
         name: impl Into<String>,
         intc: T,
     ) -> Result<(), &'static str> {
-        self.register(name.into(), Device::new_interrupt_controller(intc));
+        self.register_device(Device::new_interrupt_controller(intc), Some(name.into()));
+        Ok(())
+    }
+
+    // ========================================================================
+    // Driver Binding
+    // ========================================================================
+
+    /// Bind every discovered device to its driver.
+    ///
+    /// Walks `devices` (static platform tables or DTB) and calls the probe
+    /// function of the first matching entry in the driver table. Devices
+    /// without a driver are logged and skipped.
+    ///
+    /// # Safety
+    /// MMIO for every device must be mapped; probe functions touch hardware.
+    pub unsafe fn probe_devices<'a>(
+        &mut self,
+        devices: impl Iterator<Item = &'a DeviceInfo>,
+    ) -> Result<(), String> {
+        for device in devices {
+            match driver::find_driver(device.compatible) {
+                Some(drv) => unsafe { (drv.probe)(device, self)? },
+                // Most DTB nodes have no driver here; not worth a warning
+                None => {
+                    log::debug!(
+                        "Unknown device '{}' (compatible: '{}') at {:#x} (size: {:#x})",
+                        device.name,
+                        device.compatible,
+                        device.base_addr,
+                        device.size
+                    );
+                }
+            }
+        }
         Ok(())
     }
 
@@ -282,26 +743,22 @@ impl DeviceManager {
     // Device Counting / Introspection
     // ========================================================================
 
+    /// Count devices of a specific class
+    pub fn count_class(&self, class: DeviceClass) -> usize {
+        self.by_class(class).count()
+    }
+
     /// Count devices of a specific type
     pub fn count_serial(&self) -> usize {
-        self.devices
-            .values()
-            .filter(|d| matches!(d, Device::Serial(_)))
-            .count()
+        self.count_class(DeviceClass::Serial)
     }
 
     pub fn count_block(&self) -> usize {
-        self.devices
-            .values()
-            .filter(|d| matches!(d, Device::Block(_)))
-            .count()
+        self.count_class(DeviceClass::Block)
     }
 
     pub fn count_timer(&self) -> usize {
-        self.devices
-            .values()
-            .filter(|d| matches!(d, Device::Timer(_)))
-            .count()
+        self.count_class(DeviceClass::Timer)
     }
 
     /// Check if any devices are registered
@@ -0,0 +1,84 @@
+//! Driver probe/bind framework.
+//!
+//! Each driver module declares a [`Driver`] descriptor with
+//! [`register_driver!`]. The descriptors are collected by the linker into
+//! the `drivers_table` section, so adding a driver never requires editing
+//! a central list.
+//!
+//! At boot the device manager walks every [`DeviceInfo`] discovered by
+//! the platform layer (static probe tables, Multiboot2, DTB) and calls the
+//! probe function of the first driver whose compatible list matches.
+//!
+//! # Example
+//!
+//! ```rust
+//! register_driver!(PL011_DRIVER, Driver {
+//!     name: "pl011",
+//!     compatible: &["arm,pl011", "arm,primecell"],
+//!     probe,
+//! });
+//!
+//! unsafe fn probe(dev: &DeviceInfo, dm: &mut DeviceManager) -> Result<(), String> {
+//!     dm.register_serial(dev.name, unsafe { PL011::new(dev.base_addr) })?;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::device_manager::DeviceManager;
+use crate::platform::DeviceInfo;
+use alloc::string::String;
+
+/// Probe function: construct the driver for `device` and register it.
+///
+/// # Safety
+/// Called with MMIO for `device` mapped; may touch hardware.
+pub type ProbeFn =
+    unsafe fn(device: &DeviceInfo, device_mgr: &mut DeviceManager) -> Result<(), String>;
+
+/// Static driver descriptor, placed in the `drivers_table` link section.
+pub struct Driver {
+    /// Driver name, for diagnostics
+    pub name: &'static str,
+    /// Compatible strings (DTB style) or platform ids this driver binds to
+    pub compatible: &'static [&'static str],
+    /// Bind function
+    pub probe: ProbeFn,
+}
+
+impl Driver {
+    /// Does this driver bind to `compatible`?
+    pub fn matches(&self, compatible: &str) -> bool {
+        self.compatible.contains(&compatible)
+    }
+}
+
+/// Declare a driver descriptor in the linker-section driver table.
+#[macro_export]
+macro_rules! register_driver {
+    ($ident:ident, $driver:expr) => {
+        #[used]
+        #[unsafe(link_section = "drivers_table")]
+        static $ident: $crate::driver::Driver = $driver;
+    };
+}
+
+unsafe extern "C" {
+    // Provided by the linker for any section whose name is a C identifier.
+    static __start_drivers_table: u8;
+    static __stop_drivers_table: u8;
+}
+
+/// All registered drivers.
+pub fn drivers() -> &'static [Driver] {
+    unsafe {
+        let start = &raw const __start_drivers_table as *const Driver;
+        let stop = &raw const __stop_drivers_table as *const Driver;
+        let len = (stop as usize - start as usize) / core::mem::size_of::<Driver>();
+        core::slice::from_raw_parts(start, len)
+    }
+}
+
+/// Find the driver bound to a compatible string.
+pub fn find_driver(compatible: &str) -> Option<&'static Driver> {
+    drivers().iter().find(|d| d.matches(compatible))
+}
@@ -0,0 +1,148 @@
+//! Audio Output Hardware Abstraction Layer.
+//!
+//! This module defines platform-independent traits for PCM audio output.
+//! Samples are signed 16-bit, interleaved by channel (L, R, L, R, ...).
+
+/// Audio output configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AudioConfig {
+    /// Frames per second.
+    pub sample_rate: u32,
+    /// Interleaved channels per frame (1 = mono, 2 = stereo).
+    pub channels: u8,
+}
+
+impl AudioConfig {
+    /// Stereo at `sample_rate`.
+    pub const fn stereo(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            channels: 2,
+        }
+    }
+
+    /// Mono at `sample_rate`.
+    pub const fn mono(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            channels: 1,
+        }
+    }
+}
+
+impl Default for AudioConfig {
+    /// Default configuration: 44.1 kHz stereo.
+    fn default() -> Self {
+        Self::stereo(44_100)
+    }
+}
+
+/// Audio errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AudioError {
+    /// Sample rate cannot be generated by the hardware.
+    UnsupportedRate,
+    /// Channel count not supported.
+    UnsupportedChannels,
+    /// Sample buffer length is not a whole number of frames.
+    PartialFrame,
+    /// Hardware fault (e.g. DMA error).
+    Hardware,
+    /// Other platform-specific error.
+    Other,
+}
+
+// ============================================================================
+// Audio Output Trait
+// ============================================================================
+
+/// PCM audio output trait.
+pub trait AudioOutput: Send + Sync {
+    type Error: core::fmt::Debug + Into<AudioError>;
+
+    /// Set sample rate and channel count. Stops any playback in progress.
+    fn configure(&mut self, config: AudioConfig) -> Result<(), Self::Error>;
+
+    /// Current configuration.
+    fn config(&self) -> AudioConfig;
+
+    /// Queue interleaved samples for playback, blocking until the device
+    /// has accepted them. Returns the number of samples consumed.
+    fn write_samples(&mut self, samples: &[i16]) -> Result<usize, Self::Error>;
+
+    /// Block until all queued samples have played.
+    fn drain(&mut self) -> Result<(), Self::Error>;
+}
+
+// ============================================================================
+// Object-safe wrapper
+// ============================================================================
+
+pub trait DynAudioOutput: Send + Sync {
+    fn configure(&mut self, config: AudioConfig) -> Result<(), AudioError>;
+    fn config(&self) -> AudioConfig;
+    fn write_samples(&mut self, samples: &[i16]) -> Result<usize, AudioError>;
+    fn drain(&mut self) -> Result<(), AudioError>;
+}
+
+impl<T: AudioOutput> DynAudioOutput for T {
+    fn configure(&mut self, config: AudioConfig) -> Result<(), AudioError> {
+        AudioOutput::configure(self, config).map_err(Into::into)
+    }
+    fn config(&self) -> AudioConfig {
+        AudioOutput::config(self)
+    }
+    fn write_samples(&mut self, samples: &[i16]) -> Result<usize, AudioError> {
+        AudioOutput::write_samples(self, samples).map_err(Into::into)
+    }
+    fn drain(&mut self) -> Result<(), AudioError> {
+        AudioOutput::drain(self).map_err(Into::into)
+    }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Play a square-wave tone on every channel.
+///
+/// `amplitude` is the peak sample value; keep it well below `i16::MAX`
+/// for a beep that is not painfully loud.
+pub fn beep(
+    out: &mut dyn DynAudioOutput,
+    freq_hz: u32,
+    duration_ms: u32,
+    amplitude: i16,
+) -> Result<(), AudioError> {
+    let config = out.config();
+    let channels = config.channels.max(1) as usize;
+    if freq_hz == 0 || freq_hz * 2 > config.sample_rate {
+        return Err(AudioError::UnsupportedRate);
+    }
+
+    let total_frames = (config.sample_rate as u64 * duration_ms as u64 / 1000) as usize;
+    let half_period = (config.sample_rate / (freq_hz * 2)) as usize;
+
+    const CHUNK_FRAMES: usize = 256;
+    let mut chunk = [0i16; CHUNK_FRAMES * 2];
+    let chunk_frames = chunk.len() / channels;
+
+    let mut frame = 0;
+    while frame < total_frames {
+        let frames = chunk_frames.min(total_frames - frame);
+        for i in 0..frames {
+            let high = ((frame + i) / half_period).is_multiple_of(2);
+            let value = if high { amplitude } else { -amplitude };
+            chunk[i * channels..(i + 1) * channels].fill(value);
+        }
+
+        let mut written = 0;
+        let len = frames * channels;
+        while written < len {
+            written += out.write_samples(&chunk[written..len])?;
+        }
+        frame += frames;
+    }
+
+    out.drain()
+}
@@ -96,7 +96,7 @@ pub trait BlockDevice: Send + Sync {
     }
 
     /// Flush pending writes. Default: no-op (assumes immediate persistence).
-    fn flush(&mut self) -> Result<(), Self::Error> {
+    fn flush(&self) -> Result<(), Self::Error> {
         Ok(())
     }
 
@@ -132,7 +132,7 @@ pub trait DynBlockDevice: Send + Sync {
     fn write_blocks(&self, start_block: u64, buffers: &[&[u8]]) -> Result<(), BlockDeviceError>;
     fn read_block(&self, block: u64, buffer: &mut [u8]) -> Result<(), BlockDeviceError>;
     fn write_block(&self, block: u64, buffer: &[u8]) -> Result<(), BlockDeviceError>;
-    fn flush(&mut self) -> Result<(), BlockDeviceError>;
+    fn flush(&self) -> Result<(), BlockDeviceError>;
     fn is_ready(&self) -> bool;
 }
 
@@ -158,7 +158,7 @@ impl<T: BlockDevice> DynBlockDevice for T {
     fn write_block(&self, block: u64, buffer: &[u8]) -> Result<(), BlockDeviceError> {
         BlockDevice::write_block(self, block, buffer).map_err(Into::into)
     }
-    fn flush(&mut self) -> Result<(), BlockDeviceError> {
+    fn flush(&self) -> Result<(), BlockDeviceError> {
         BlockDevice::flush(self).map_err(Into::into)
     }
     fn is_ready(&self) -> bool {
@@ -58,6 +58,19 @@ pub enum LevelDetect {
     Low,
 }
 
+/// Canonical GPIO errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GpioError {
+    /// Pin number does not exist on this controller.
+    InvalidPin,
+    /// Requested pin function/mode is not available.
+    InvalidFunction,
+    /// Operation not supported by this controller.
+    Unsupported,
+    /// Other platform-specific error.
+    Other,
+}
+
 /// GPIO controller trait.
 ///
 /// This trait represents a GPIO controller capable of configuring
@@ -206,3 +219,54 @@ pub trait StatefulOutputPin: OutputPin {
         Ok(self.read()? == PinLevel::Low)
     }
 }
+
+// ============================================================================
+// Object-safe wrapper
+// ============================================================================
+
+/// Object-safe GPIO controller with numeric pin identifiers.
+///
+/// Implemented automatically for every [`GpioController`] whose pin type
+/// can be built from a `u32` and whose error converts into [`GpioError`].
+pub trait DynGpioController: Send + Sync {
+    fn set_pull(&mut self, pin: u32, pull: PullMode) -> Result<(), GpioError>;
+    fn set_high(&mut self, pin: u32) -> Result<(), GpioError>;
+    fn set_low(&mut self, pin: u32) -> Result<(), GpioError>;
+    fn read(&self, pin: u32) -> Result<PinLevel, GpioError>;
+    fn set_level(&mut self, pin: u32, level: PinLevel) -> Result<(), GpioError>;
+    fn toggle(&mut self, pin: u32) -> Result<(), GpioError>;
+}
+
+impl<T> DynGpioController for T
+where
+    T: GpioController + Send + Sync,
+    T::Pin: TryFrom<u32>,
+    T::Error: Into<GpioError>,
+{
+    fn set_pull(&mut self, pin: u32, pull: PullMode) -> Result<(), GpioError> {
+        GpioController::set_pull(self, to_pin::<T>(pin)?, pull).map_err(Into::into)
+    }
+    fn set_high(&mut self, pin: u32) -> Result<(), GpioError> {
+        GpioController::set_high(self, to_pin::<T>(pin)?).map_err(Into::into)
+    }
+    fn set_low(&mut self, pin: u32) -> Result<(), GpioError> {
+        GpioController::set_low(self, to_pin::<T>(pin)?).map_err(Into::into)
+    }
+    fn read(&self, pin: u32) -> Result<PinLevel, GpioError> {
+        GpioController::read(self, to_pin::<T>(pin)?).map_err(Into::into)
+    }
+    fn set_level(&mut self, pin: u32, level: PinLevel) -> Result<(), GpioError> {
+        GpioController::set_level(self, to_pin::<T>(pin)?, level).map_err(Into::into)
+    }
+    fn toggle(&mut self, pin: u32) -> Result<(), GpioError> {
+        GpioController::toggle(self, to_pin::<T>(pin)?).map_err(Into::into)
+    }
+}
+
+fn to_pin<T>(pin: u32) -> Result<T::Pin, GpioError>
+where
+    T: GpioController,
+    T::Pin: TryFrom<u32>,
+{
+    T::Pin::try_from(pin).map_err(|_| GpioError::InvalidPin)
+}
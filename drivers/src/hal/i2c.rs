@@ -0,0 +1,74 @@
+//! I2C (Inter-Integrated Circuit) Hardware Abstraction Layer.
+//!
+//! This module defines platform-independent traits for I2C bus masters
+//! using 7-bit addressing.
+
+/// I2C errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum I2cError {
+    /// Slave did not acknowledge its address or a data byte.
+    Nack,
+    /// Slave held SCL low for too long (clock stretch timeout).
+    ClockStretchTimeout,
+    /// Transfer did not complete in time.
+    Timeout,
+    /// Address or length out of range.
+    InvalidArgument,
+    /// Other platform-specific error.
+    Other,
+}
+
+// ============================================================================
+// I2C Bus Trait
+// ============================================================================
+
+/// I2C bus master trait.
+pub trait I2cBus: Send + Sync {
+    type Error: core::fmt::Debug + Into<I2cError>;
+
+    /// Set the SCL frequency in Hz.
+    fn set_clock(&mut self, hz: u32) -> Result<(), Self::Error>;
+
+    /// Write `bytes` to the slave at `addr`.
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Read `buf.len()` bytes from the slave at `addr`.
+    fn read(&mut self, addr: u8, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write `bytes` then read into `buf` (typically a register address
+    /// followed by its contents).
+    ///
+    /// The default implementation issues a STOP between the two phases,
+    /// which is fine for register-pointer devices; controllers that can
+    /// generate a repeated START should override it.
+    fn write_read(&mut self, addr: u8, bytes: &[u8], buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.write(addr, bytes)?;
+        self.read(addr, buf)
+    }
+}
+
+// ============================================================================
+// Object-safe wrapper
+// ============================================================================
+
+pub trait DynI2cBus: Send + Sync {
+    fn set_clock(&mut self, hz: u32) -> Result<(), I2cError>;
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), I2cError>;
+    fn read(&mut self, addr: u8, buf: &mut [u8]) -> Result<(), I2cError>;
+    fn write_read(&mut self, addr: u8, bytes: &[u8], buf: &mut [u8]) -> Result<(), I2cError>;
+}
+
+impl<T: I2cBus> DynI2cBus for T {
+    fn set_clock(&mut self, hz: u32) -> Result<(), I2cError> {
+        I2cBus::set_clock(self, hz).map_err(Into::into)
+    }
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), I2cError> {
+        I2cBus::write(self, addr, bytes).map_err(Into::into)
+    }
+    fn read(&mut self, addr: u8, buf: &mut [u8]) -> Result<(), I2cError> {
+        I2cBus::read(self, addr, buf).map_err(Into::into)
+    }
+    fn write_read(&mut self, addr: u8, bytes: &[u8], buf: &mut [u8]) -> Result<(), I2cError> {
+        I2cBus::write_read(self, addr, bytes, buf).map_err(Into::into)
+    }
+}
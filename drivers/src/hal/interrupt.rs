@@ -1,8 +1,14 @@
 //! Interrupt Controller Hardware Abstraction Layer.
 
 pub type IrqNumber = u32;
+/// Interrupt priority; lower values are more urgent (GIC convention).
 pub type Priority = u8;
 
+/// Priority given to sources nobody has configured.
+pub const PRIORITY_DEFAULT: Priority = 0xA0;
+/// Mask value that lets every priority through.
+pub const PRIORITY_MASK_NONE: Priority = 0xFF;
+
 pub const IRQ_SYSTEM_TIMER_0: u32 = 0;
 pub const IRQ_SYSTEM_TIMER_1: u32 = 1;
 pub const IRQ_SYSTEM_TIMER_2: u32 = 2;
@@ -38,13 +44,43 @@ pub trait InterruptController: Send + Sync {
         let _ = irq;
         Ok(())
     }
+
+    /// Set the priority of `irq`. Default: no-op for controllers without
+    /// priority support (e.g. BCM2835), which deliver in fixed order.
+    fn set_priority(&mut self, irq: IrqNumber, priority: Priority) -> Result<(), Self::Error> {
+        let _ = (irq, priority);
+        Ok(())
+    }
+
+    /// Only signal interrupts more urgent than `mask` to this CPU.
+    /// Default: no-op.
+    fn priority_mask(&mut self, mask: Priority) -> Result<(), Self::Error> {
+        let _ = mask;
+        Ok(())
+    }
+
+    /// Access FIQ routing through the type-erased interface.
+    ///
+    /// Controllers implementing [`FiqInterruptController`] override this
+    /// to return `Some(self)`.
+    fn as_fiq(&mut self) -> Option<&mut dyn DynFiqInterruptController> {
+        None
+    }
 }
 
 // Extension traits
 
-pub trait PriorityInterruptController: InterruptController {
-    fn set_priority(&mut self, irq: IrqNumber, priority: Priority) -> Result<(), Self::Error>;
-    fn get_priority(&self, irq: IrqNumber) -> Result<Priority, Self::Error>;
+/// Controllers that can steer a source to the fast interrupt (FIQ) line.
+pub trait FiqInterruptController: InterruptController {
+    /// Route `irq` to FIQ instead of IRQ. Controllers with a single FIQ
+    /// source replace any previous selection.
+    fn route_to_fiq(&mut self, irq: IrqNumber) -> Result<(), Self::Error>;
+
+    /// Stop routing any source to FIQ.
+    fn clear_fiq(&mut self) -> Result<(), Self::Error>;
+
+    /// Source currently routed to FIQ, if any.
+    fn fiq_source(&self) -> Option<IrqNumber>;
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -70,6 +106,17 @@ pub trait DynInterruptController: Send + Sync {
         let _ = irq;
         Ok(())
     }
+    fn set_priority(&mut self, irq: IrqNumber, priority: Priority) -> Result<(), InterruptError> {
+        let _ = (irq, priority);
+        Ok(())
+    }
+    fn priority_mask(&mut self, mask: Priority) -> Result<(), InterruptError> {
+        let _ = mask;
+        Ok(())
+    }
+    fn as_fiq(&mut self) -> Option<&mut dyn DynFiqInterruptController> {
+        None
+    }
 }
 
 impl<T: InterruptController> DynInterruptController for T {
@@ -88,21 +135,14 @@ impl<T: InterruptController> DynInterruptController for T {
     fn clear(&mut self, irq: IrqNumber) -> Result<(), InterruptError> {
         InterruptController::clear(self, irq).map_err(Into::into)
     }
-}
-
-// DynPriorityInterruptController
-
-pub trait DynPriorityInterruptController: DynInterruptController {
-    fn set_priority(&mut self, irq: IrqNumber, priority: Priority) -> Result<(), InterruptError>;
-    fn get_priority(&self, irq: IrqNumber) -> Result<Priority, InterruptError>;
-}
-
-impl<T: PriorityInterruptController> DynPriorityInterruptController for T {
     fn set_priority(&mut self, irq: IrqNumber, priority: Priority) -> Result<(), InterruptError> {
-        PriorityInterruptController::set_priority(self, irq, priority).map_err(Into::into)
+        InterruptController::set_priority(self, irq, priority).map_err(Into::into)
     }
-    fn get_priority(&self, irq: IrqNumber) -> Result<Priority, InterruptError> {
-        PriorityInterruptController::get_priority(self, irq).map_err(Into::into)
+    fn priority_mask(&mut self, mask: Priority) -> Result<(), InterruptError> {
+        InterruptController::priority_mask(self, mask).map_err(Into::into)
+    }
+    fn as_fiq(&mut self) -> Option<&mut dyn DynFiqInterruptController> {
+        InterruptController::as_fiq(self)
     }
 }
 
@@ -125,3 +165,23 @@ impl<T: ConfigurableInterruptController> DynConfigurableInterruptController for
         ConfigurableInterruptController::configure_trigger(self, irq, mode).map_err(Into::into)
     }
 }
+
+// DynFiqInterruptController
+
+pub trait DynFiqInterruptController: DynInterruptController {
+    fn route_to_fiq(&mut self, irq: IrqNumber) -> Result<(), InterruptError>;
+    fn clear_fiq(&mut self) -> Result<(), InterruptError>;
+    fn fiq_source(&self) -> Option<IrqNumber>;
+}
+
+impl<T: FiqInterruptController> DynFiqInterruptController for T {
+    fn route_to_fiq(&mut self, irq: IrqNumber) -> Result<(), InterruptError> {
+        FiqInterruptController::route_to_fiq(self, irq).map_err(Into::into)
+    }
+    fn clear_fiq(&mut self) -> Result<(), InterruptError> {
+        FiqInterruptController::clear_fiq(self).map_err(Into::into)
+    }
+    fn fiq_source(&self) -> Option<IrqNumber> {
+        FiqInterruptController::fiq_source(self)
+    }
+}
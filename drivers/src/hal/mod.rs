@@ -19,11 +19,21 @@
 //! - [`timer`]: Hardware timers and delays
 //! - [`interrupt`]: Interrupt controller management
 //! - [`block_device`]: Block storage device access
+//! - [`spi`]: SPI bus master access
+//! - [`i2c`]: I2C bus master access
+//! - [`rtc`]: Battery-backed real-time clocks
+//! - [`audio`]: PCM audio output
+//! - [`net`]: Ethernet controllers
 
+pub mod audio;
 pub mod block_device;
 pub mod console;
 pub mod fb;
 pub mod gpio;
+pub mod i2c;
 pub mod interrupt;
+pub mod net;
+pub mod rtc;
 pub mod serial;
+pub mod spi;
 pub mod timer;
@@ -0,0 +1,114 @@
+//! Network Interface Hardware Abstraction Layer.
+//!
+//! This module defines platform-independent traits for Ethernet
+//! controllers. Frames are whole Ethernet II frames, from the destination
+//! address to the end of the payload, without the frame check sequence,
+//! which the hardware adds and strips.
+
+use core::fmt;
+
+/// Longest frame without the FCS: 14 bytes of header and a 1500-byte
+/// payload.
+pub const ETH_FRAME_MAX: usize = 1514;
+
+/// Ethernet hardware address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl MacAddress {
+    /// Every station on the segment.
+    pub const BROADCAST: Self = Self([0xFF; 6]);
+
+    /// All zeros, as carried by an ARP request.
+    pub const ZERO: Self = Self([0; 6]);
+
+    /// Is this a group address (broadcast included)?
+    pub const fn is_multicast(&self) -> bool {
+        self.0[0] & 1 != 0
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+/// Network device errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetworkDeviceError {
+    /// No frame received, or no room to queue one for transmission.
+    WouldBlock,
+    /// The link is down.
+    LinkDown,
+    /// Frame longer than the device can send, or than the buffer given.
+    FrameTooLong,
+    /// Hardware fault (e.g. DMA or bus error).
+    Hardware,
+    /// Other platform-specific error.
+    Other,
+}
+
+// ============================================================================
+// Network Device Trait
+// ============================================================================
+
+/// Ethernet controller trait.
+///
+/// Neither call waits for the network: `transmit` queues the frame and
+/// `receive` fails with `WouldBlock` when nothing has arrived.
+pub trait NetworkDevice: Send + Sync {
+    type Error: core::fmt::Debug + Into<NetworkDeviceError>;
+
+    /// The interface's hardware address.
+    fn mac_address(&self) -> MacAddress;
+
+    /// Largest payload a frame carries.
+    fn mtu(&self) -> usize {
+        1500
+    }
+
+    /// Is the link up?
+    fn link_up(&self) -> bool;
+
+    /// Queue `frame` for transmission.
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), Self::Error>;
+
+    /// Take the oldest received frame into `buf`, returning its length.
+    fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+// ============================================================================
+// Object-safe wrapper
+// ============================================================================
+
+pub trait DynNetworkDevice: Send + Sync {
+    fn mac_address(&self) -> MacAddress;
+    fn mtu(&self) -> usize;
+    fn link_up(&self) -> bool;
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), NetworkDeviceError>;
+    fn receive(&mut self, buf: &mut [u8]) -> Result<usize, NetworkDeviceError>;
+}
+
+impl<T: NetworkDevice> DynNetworkDevice for T {
+    fn mac_address(&self) -> MacAddress {
+        NetworkDevice::mac_address(self)
+    }
+    fn mtu(&self) -> usize {
+        NetworkDevice::mtu(self)
+    }
+    fn link_up(&self) -> bool {
+        NetworkDevice::link_up(self)
+    }
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), NetworkDeviceError> {
+        NetworkDevice::transmit(self, frame).map_err(Into::into)
+    }
+    fn receive(&mut self, buf: &mut [u8]) -> Result<usize, NetworkDeviceError> {
+        NetworkDevice::receive(self, buf).map_err(Into::into)
+    }
+}
@@ -0,0 +1,202 @@
+//! Real-Time Clock (RTC) Hardware Abstraction Layer.
+//!
+//! This module defines platform-independent traits for battery-backed
+//! wall-clock devices. All times are UTC; the kernel does not track
+//! time zones.
+
+/// Calendar date and time (UTC).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    /// Full year, e.g. 2024.
+    pub year: u16,
+    /// Month, 1–12.
+    pub month: u8,
+    /// Day of month, 1–31.
+    pub day: u8,
+    /// Hour, 0–23.
+    pub hour: u8,
+    /// Minute, 0–59.
+    pub minute: u8,
+    /// Second, 0–59.
+    pub second: u8,
+}
+
+impl DateTime {
+    /// 1970-01-01 00:00:00.
+    pub const UNIX_EPOCH: DateTime = DateTime {
+        year: 1970,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        second: 0,
+    };
+
+    /// Check that every field is in range (including days per month).
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    /// Day of week, 0 = Sunday … 6 = Saturday.
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday.
+        ((days_from_civil(self.year, self.month, self.day) + 4).rem_euclid(7)) as u8
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn to_unix(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) * 86_400
+            + self.hour as i64 * 3_600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+
+    /// Build from seconds since the Unix epoch.
+    pub fn from_unix(secs: i64) -> Self {
+        let days = secs.div_euclid(86_400);
+        let rem = secs.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year,
+            month,
+            day,
+            hour: (rem / 3_600) as u8,
+            minute: (rem % 3_600 / 60) as u8,
+            second: (rem % 60) as u8,
+        }
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Howard Hinnant's days_from_civil / civil_from_days.
+
+fn days_from_civil(year: u16, month: u8, day: u8) -> i64 {
+    let y = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (u16, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as u16;
+    (year, month, day)
+}
+
+/// RTC errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RtcError {
+    /// Date/time out of range or not representable by the device.
+    InvalidTime,
+    /// The oscillator stopped (battery loss); the time is not trustworthy
+    /// until it is set again.
+    ClockNotSet,
+    /// Bus or device communication failure.
+    Bus,
+    /// Operation not supported by this device.
+    Unsupported,
+    /// Other platform-specific error.
+    Other,
+}
+
+// ============================================================================
+// RTC Traits
+// ============================================================================
+
+/// Real-time clock trait.
+pub trait Rtc: Send + Sync {
+    type Error: core::fmt::Debug + Into<RtcError>;
+
+    /// Read the current date and time.
+    fn read_time(&mut self) -> Result<DateTime, Self::Error>;
+
+    /// Set the date and time.
+    fn set_time(&mut self, time: &DateTime) -> Result<(), Self::Error>;
+
+    /// Access alarm support through the type-erased interface.
+    ///
+    /// Drivers implementing [`RtcAlarm`] override this to return `Some(self)`.
+    fn as_alarm(&mut self) -> Option<&mut dyn DynRtcAlarm> {
+        None
+    }
+}
+
+/// Extension trait for RTCs with an alarm output.
+pub trait RtcAlarm: Rtc {
+    /// Arm the alarm for `time`. Devices with coarser resolution round
+    /// down (e.g. to the minute).
+    fn set_alarm(&mut self, time: &DateTime) -> Result<(), Self::Error>;
+
+    /// Disarm the alarm and clear any pending flag.
+    fn clear_alarm(&mut self) -> Result<(), Self::Error>;
+
+    /// Has the alarm fired since it was last cleared?
+    fn alarm_pending(&mut self) -> Result<bool, Self::Error>;
+}
+
+// ============================================================================
+// Object-safe wrappers
+// ============================================================================
+
+pub trait DynRtc: Send + Sync {
+    fn read_time(&mut self) -> Result<DateTime, RtcError>;
+    fn set_time(&mut self, time: &DateTime) -> Result<(), RtcError>;
+    fn as_alarm(&mut self) -> Option<&mut dyn DynRtcAlarm>;
+}
+
+impl<T: Rtc> DynRtc for T {
+    fn read_time(&mut self) -> Result<DateTime, RtcError> {
+        Rtc::read_time(self).map_err(Into::into)
+    }
+    fn set_time(&mut self, time: &DateTime) -> Result<(), RtcError> {
+        Rtc::set_time(self, time).map_err(Into::into)
+    }
+    fn as_alarm(&mut self) -> Option<&mut dyn DynRtcAlarm> {
+        Rtc::as_alarm(self)
+    }
+}
+
+pub trait DynRtcAlarm: DynRtc {
+    fn set_alarm(&mut self, time: &DateTime) -> Result<(), RtcError>;
+    fn clear_alarm(&mut self) -> Result<(), RtcError>;
+    fn alarm_pending(&mut self) -> Result<bool, RtcError>;
+}
+
+impl<T: RtcAlarm> DynRtcAlarm for T {
+    fn set_alarm(&mut self, time: &DateTime) -> Result<(), RtcError> {
+        RtcAlarm::set_alarm(self, time).map_err(Into::into)
+    }
+    fn clear_alarm(&mut self) -> Result<(), RtcError> {
+        RtcAlarm::clear_alarm(self).map_err(Into::into)
+    }
+    fn alarm_pending(&mut self) -> Result<bool, RtcError> {
+        RtcAlarm::alarm_pending(self).map_err(Into::into)
+    }
+}
@@ -86,6 +86,18 @@ pub enum SerialError {
     Other,
 }
 
+impl SerialError {
+    /// Is this a receive line error (framing, parity, overrun, break)?
+    ///
+    /// Line errors affect a single character; the port stays usable.
+    pub fn is_line_error(&self) -> bool {
+        matches!(
+            self,
+            SerialError::Framing | SerialError::Parity | SerialError::Overrun | SerialError::Break
+        )
+    }
+}
+
 // ============================================================================
 // Serial Port Trait
 // ============================================================================
@@ -97,6 +109,17 @@ pub trait SerialPort: Send + Sync {
     type Error: core::fmt::Debug + Into<SerialError>;
 
     fn configure(&mut self, config: SerialConfig) -> Result<(), Self::Error>;
+
+    /// Change baud rate and frame format on an open port.
+    ///
+    /// Pending transmit data is sent with the old settings first. The
+    /// default implementation flushes and calls `configure`, which may
+    /// discard unread receive data; drivers override it to keep it.
+    fn reconfigure(&mut self, config: SerialConfig) -> Result<(), Self::Error> {
+        self.flush()?;
+        self.configure(config)
+    }
+
     fn write_byte(&mut self, byte: u8) -> Result<(), Self::Error>;
     fn read_byte(&mut self) -> Result<u8, Self::Error>;
     fn flush(&mut self) -> Result<(), Self::Error>;
@@ -136,6 +159,13 @@ pub trait NonBlockingSerial: SerialPort {
         }
         Ok(buf.len())
     }
+
+    /// Raise the port's interrupt while received data waits, or stop.
+    /// Reading the data clears it. Returns whether the port can.
+    fn set_rx_interrupt(&mut self, enabled: bool) -> bool {
+        let _ = enabled;
+        false
+    }
 }
 
 // ============================================================================
@@ -145,6 +175,7 @@ pub trait NonBlockingSerial: SerialPort {
 /// Type-erased serial port trait using `SerialError`.
 pub trait DynSerialPort: Send + Sync {
     fn configure(&mut self, config: SerialConfig) -> Result<(), SerialError>;
+    fn reconfigure(&mut self, config: SerialConfig) -> Result<(), SerialError>;
     fn write_byte(&mut self, byte: u8) -> Result<(), SerialError>;
     fn write(&mut self, bytes: &[u8]) -> Result<usize, SerialError>;
     fn read_byte(&mut self) -> Result<u8, SerialError>;
@@ -163,6 +194,7 @@ pub trait DynNonBlockingSerial: DynSerialPort {
     fn try_read_byte(&mut self) -> Result<u8, SerialError>;
     fn try_write(&mut self, bytes: &[u8]) -> Result<usize, SerialError>;
     fn try_read(&mut self, buf: &mut [u8]) -> Result<usize, SerialError>;
+    fn set_rx_interrupt(&mut self, enabled: bool) -> bool;
 }
 
 /// Blanket impl for types that implement SerialPort.
@@ -173,6 +205,9 @@ where
     fn configure(&mut self, config: SerialConfig) -> Result<(), SerialError> {
         SerialPort::configure(self, config).map_err(Into::into)
     }
+    fn reconfigure(&mut self, config: SerialConfig) -> Result<(), SerialError> {
+        SerialPort::reconfigure(self, config).map_err(Into::into)
+    }
     fn write_byte(&mut self, byte: u8) -> Result<(), SerialError> {
         SerialPort::write_byte(self, byte).map_err(Into::into)
     }
@@ -210,6 +245,9 @@ where
     fn try_read(&mut self, buf: &mut [u8]) -> Result<usize, SerialError> {
         NonBlockingSerial::try_read(self, buf).map_err(Into::into)
     }
+    fn set_rx_interrupt(&mut self, enabled: bool) -> bool {
+        NonBlockingSerial::set_rx_interrupt(self, enabled)
+    }
 }
 
 impl fmt::Write for dyn DynSerialPort {
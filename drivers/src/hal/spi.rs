@@ -0,0 +1,173 @@
+//! Serial Peripheral Interface (SPI) Hardware Abstraction Layer.
+//!
+//! This module defines platform-independent traits for SPI bus masters.
+//! Transfers are full-duplex: every byte clocked out clocks one byte in.
+
+/// SPI clock polarity / phase combination.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpiMode {
+    /// CPOL = 0, CPHA = 0
+    Mode0,
+    /// CPOL = 0, CPHA = 1
+    Mode1,
+    /// CPOL = 1, CPHA = 0
+    Mode2,
+    /// CPOL = 1, CPHA = 1
+    Mode3,
+}
+
+impl SpiMode {
+    /// Clock polarity (idle level of SCLK).
+    pub const fn cpol(self) -> bool {
+        matches!(self, SpiMode::Mode2 | SpiMode::Mode3)
+    }
+
+    /// Clock phase (sample on the second edge when set).
+    pub const fn cpha(self) -> bool {
+        matches!(self, SpiMode::Mode1 | SpiMode::Mode3)
+    }
+}
+
+/// SPI bus configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SpiConfig {
+    /// Requested SCLK frequency in Hz. Drivers round down to the nearest
+    /// achievable rate.
+    pub clock_hz: u32,
+    /// Clock polarity / phase.
+    pub mode: SpiMode,
+    /// Chip-select line used for subsequent transfers.
+    pub chip_select: u8,
+}
+
+impl SpiConfig {
+    /// Create a mode 0 configuration on chip-select 0.
+    pub const fn new(clock_hz: u32) -> Self {
+        Self {
+            clock_hz,
+            mode: SpiMode::Mode0,
+            chip_select: 0,
+        }
+    }
+}
+
+impl Default for SpiConfig {
+    /// Default configuration: 1 MHz, mode 0, CS0.
+    fn default() -> Self {
+        Self::new(1_000_000)
+    }
+}
+
+/// SPI errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpiError {
+    /// Transfer did not complete in time.
+    Timeout,
+    /// Receive FIFO overflowed.
+    Overrun,
+    /// Invalid configuration parameter (clock, mode or chip-select).
+    InvalidConfig,
+    /// Other platform-specific error.
+    Other,
+}
+
+// ============================================================================
+// SPI Bus Trait
+// ============================================================================
+
+/// SPI bus master trait.
+///
+/// Chip-select is driven explicitly so that multi-transfer transactions
+/// (e.g. an SD command followed by its data block) can hold the device
+/// selected across calls.
+pub trait SpiBus: Send + Sync {
+    type Error: core::fmt::Debug + Into<SpiError>;
+
+    fn configure(&mut self, config: SpiConfig) -> Result<(), Self::Error>;
+
+    /// Assert the configured chip-select line.
+    fn select(&mut self);
+
+    /// De-assert the configured chip-select line.
+    fn deselect(&mut self);
+
+    /// Full-duplex transfer in place: each byte of `buf` is sent and
+    /// replaced by the byte received at the same time.
+    fn transfer(&mut self, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Send bytes, discarding whatever is received.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        for &b in bytes {
+            self.transfer_byte(b)?;
+        }
+        Ok(())
+    }
+
+    /// Receive bytes while clocking out `fill`.
+    fn read(&mut self, buf: &mut [u8], fill: u8) -> Result<(), Self::Error> {
+        buf.fill(fill);
+        self.transfer(buf)
+    }
+
+    /// Exchange a single byte.
+    fn transfer_byte(&mut self, byte: u8) -> Result<u8, Self::Error> {
+        let mut buf = [byte];
+        self.transfer(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Clock out `count` bytes of `0xFF` with the configured chip-select
+    /// de-asserted, as SD cards need before and after a transaction.
+    ///
+    /// The default de-asserts it and writes; controllers that stop the
+    /// clock along with chip-select override this.
+    fn clock_idle(&mut self, count: usize) -> Result<(), Self::Error> {
+        self.deselect();
+        for _ in 0..count {
+            self.transfer_byte(0xFF)?;
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Object-safe wrapper
+// ============================================================================
+
+pub trait DynSpiBus: Send + Sync {
+    fn configure(&mut self, config: SpiConfig) -> Result<(), SpiError>;
+    fn select(&mut self);
+    fn deselect(&mut self);
+    fn transfer(&mut self, buf: &mut [u8]) -> Result<(), SpiError>;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), SpiError>;
+    fn read(&mut self, buf: &mut [u8], fill: u8) -> Result<(), SpiError>;
+    fn transfer_byte(&mut self, byte: u8) -> Result<u8, SpiError>;
+    fn clock_idle(&mut self, count: usize) -> Result<(), SpiError>;
+}
+
+impl<T: SpiBus> DynSpiBus for T {
+    fn configure(&mut self, config: SpiConfig) -> Result<(), SpiError> {
+        SpiBus::configure(self, config).map_err(Into::into)
+    }
+    fn select(&mut self) {
+        SpiBus::select(self)
+    }
+    fn deselect(&mut self) {
+        SpiBus::deselect(self)
+    }
+    fn transfer(&mut self, buf: &mut [u8]) -> Result<(), SpiError> {
+        SpiBus::transfer(self, buf).map_err(Into::into)
+    }
+    fn write(&mut self, bytes: &[u8]) -> Result<(), SpiError> {
+        SpiBus::write(self, bytes).map_err(Into::into)
+    }
+    fn read(&mut self, buf: &mut [u8], fill: u8) -> Result<(), SpiError> {
+        SpiBus::read(self, buf, fill).map_err(Into::into)
+    }
+    fn transfer_byte(&mut self, byte: u8) -> Result<u8, SpiError> {
+        SpiBus::transfer_byte(self, byte).map_err(Into::into)
+    }
+    fn clock_idle(&mut self, count: usize) -> Result<(), SpiError> {
+        SpiBus::clock_idle(self, count).map_err(Into::into)
+    }
+}
@@ -36,6 +36,13 @@ pub trait Timer: Send + Sync {
     fn stop(&mut self, handle: Self::Handle) -> Result<(), Self::Error>;
     fn clear_interrupt(&mut self, handle: Self::Handle) -> Result<(), Self::Error>;
     fn is_pending(&self, handle: Self::Handle) -> Result<bool, Self::Error>;
+
+    /// Access the free-running counter through the type-erased interface.
+    ///
+    /// Drivers implementing [`CountingTimer`] override this to return `Some(self)`.
+    fn as_counting(&self) -> Option<&dyn DynCountingTimer> {
+        None
+    }
 }
 
 // Extension traits
@@ -73,6 +80,7 @@ pub trait DynTimer: Send + Sync {
     fn stop(&mut self, handle: usize) -> Result<(), TimerError>;
     fn clear_interrupt(&mut self, handle: usize) -> Result<(), TimerError>;
     fn is_pending(&self, handle: usize) -> Result<bool, TimerError>;
+    fn as_counting(&self) -> Option<&dyn DynCountingTimer>;
 }
 
 impl<T> DynTimer for T
@@ -92,6 +100,9 @@ where
     fn is_pending(&self, handle: usize) -> Result<bool, TimerError> {
         Timer::is_pending(self, handle.into()).map_err(Into::into)
     }
+    fn as_counting(&self) -> Option<&dyn DynCountingTimer> {
+        Timer::as_counting(self)
+    }
 }
 
 // DynCountingTimer
@@ -5,6 +5,8 @@
 //! # Module Organization
 //!
 //! - [`hal`]: Platform-independent trait definitions
+//! - [`block`]: Generic block device layers (caching, partitions)
+//! - [`driver`]: Driver descriptors and probe/bind table
 //! - [`platform`]: Platform-specific drivers (SoC level)
 //! - [`peripheral`]: Reusable peripheral drivers
 //!
@@ -33,7 +35,9 @@
 #![allow(dead_code, unused_imports)]
 
 extern crate alloc;
+pub mod block;
 pub mod device_manager;
+pub mod driver;
 pub mod hal;
 pub mod peripheral;
 pub mod platform;
@@ -0,0 +1,253 @@
+//! ARM GIC-400 (GICv2) Interrupt Controller Driver
+//!
+//! Interrupt controller of the BCM2711 (Raspberry Pi 4). Only the
+//! boot CPU is handled: every shared peripheral interrupt is targeted at
+//! CPU 0.
+//!
+//! `base` is the GIC-400 peripheral base; the distributor and CPU
+//! interface sit at fixed offsets from it.
+//!
+//! # Interrupt numbering
+//!
+//! - 0-15: software generated (SGI)
+//! - 16-31: private peripheral (PPI)
+//! - 32+: shared peripheral (SPI)
+
+use crate::hal::interrupt::{
+    InterruptController, InterruptError, IrqNumber, PRIORITY_DEFAULT, PRIORITY_MASK_NONE, Priority,
+};
+use core::ptr::{read_volatile, write_volatile};
+
+/// GIC-400 base on BCM2711 (low peripheral mode).
+pub const GIC400_BASE: usize = 0xFF84_0000;
+
+const GICD_OFFSET: usize = 0x1000;
+const GICC_OFFSET: usize = 0x2000;
+
+// Distributor registers
+const GICD_CTLR: usize = 0x000;
+const GICD_TYPER: usize = 0x004;
+const GICD_ISENABLER: usize = 0x100;
+const GICD_ICENABLER: usize = 0x180;
+const GICD_ISPENDR: usize = 0x200;
+const GICD_ICPENDR: usize = 0x280;
+const GICD_IPRIORITYR: usize = 0x400;
+const GICD_ITARGETSR: usize = 0x800;
+const GICD_ICFGR: usize = 0xC00;
+
+// CPU interface registers
+const GICC_CTLR: usize = 0x000;
+const GICC_PMR: usize = 0x004;
+const GICC_BPR: usize = 0x008;
+const GICC_IAR: usize = 0x00C;
+const GICC_EOIR: usize = 0x010;
+
+/// IAR interrupt id field; 1023 means spurious.
+const IAR_ID_MASK: u32 = 0x3FF;
+const SPURIOUS_IRQ: u32 = 1023;
+
+/// First shared peripheral interrupt.
+const SPI_BASE: u32 = 32;
+
+// ============================================================================
+// Error Type
+// ============================================================================
+
+/// GIC-400 errors
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Gic400Error {
+    /// IRQ number beyond the implemented lines
+    InvalidIrq,
+}
+
+impl From<Gic400Error> for InterruptError {
+    fn from(err: Gic400Error) -> Self {
+        match err {
+            Gic400Error::InvalidIrq => InterruptError::InvalidIrq,
+        }
+    }
+}
+
+// ============================================================================
+// Driver
+// ============================================================================
+
+/// GIC-400 distributor + CPU interface.
+#[derive(Debug)]
+pub struct Gic400 {
+    gicd: usize,
+    gicc: usize,
+    num_irqs: u32,
+}
+
+impl Gic400 {
+    /// Create and initialise the controller.
+    ///
+    /// All interrupts start disabled, level-sensitive, at
+    /// [`PRIORITY_DEFAULT`] and targeted at CPU 0.
+    ///
+    /// # Safety
+    ///
+    /// GIC registers must be mapped at `base`; only one instance may exist.
+    pub unsafe fn new(base: usize) -> Self {
+        let mut gic = Self {
+            gicd: base + GICD_OFFSET,
+            gicc: base + GICC_OFFSET,
+            num_irqs: 0,
+        };
+        gic.init();
+        gic
+    }
+
+    fn init(&mut self) {
+        self.write_gicd(GICD_CTLR, 0);
+
+        let lines = 32 * ((self.read_gicd(GICD_TYPER) & 0x1F) + 1);
+        self.num_irqs = lines.min(SPURIOUS_IRQ);
+
+        for word in 0..self.num_irqs / 32 {
+            let offset = word as usize * 4;
+            self.write_gicd(GICD_ICENABLER + offset, 0xFFFF_FFFF);
+            self.write_gicd(GICD_ICPENDR + offset, 0xFFFF_FFFF);
+        }
+
+        let default = u32::from_ne_bytes([PRIORITY_DEFAULT; 4]);
+        for word in 0..self.num_irqs / 4 {
+            let offset = word as usize * 4;
+            self.write_gicd(GICD_IPRIORITYR + offset, default);
+            // ITARGETSR for SGIs/PPIs is read-only
+            if word * 4 >= SPI_BASE {
+                self.write_gicd(GICD_ITARGETSR + offset, 0x0101_0101);
+            }
+        }
+
+        // Level-sensitive for all SPIs
+        for word in SPI_BASE / 16..self.num_irqs / 16 {
+            self.write_gicd(GICD_ICFGR + word as usize * 4, 0);
+        }
+
+        self.write_gicd(GICD_CTLR, 1);
+
+        self.write_gicc(GICC_PMR, PRIORITY_MASK_NONE as u32);
+        self.write_gicc(GICC_BPR, 0);
+        self.write_gicc(GICC_CTLR, 1);
+    }
+
+    fn validate_irq(&self, irq: IrqNumber) -> Result<(), Gic400Error> {
+        if irq >= self.num_irqs {
+            Err(Gic400Error::InvalidIrq)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// (register offset, bit) for one-bit-per-IRQ banks.
+    #[inline]
+    fn bit(irq: IrqNumber) -> (usize, u32) {
+        ((irq / 32) as usize * 4, 1 << (irq % 32))
+    }
+
+    #[inline]
+    fn read_gicd(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.gicd + offset) as *const u32) }
+    }
+
+    #[inline]
+    fn write_gicd(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.gicd + offset) as *mut u32, value) }
+    }
+
+    #[inline]
+    fn read_gicc(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.gicc + offset) as *const u32) }
+    }
+
+    #[inline]
+    fn write_gicc(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.gicc + offset) as *mut u32, value) }
+    }
+}
+
+impl InterruptController for Gic400 {
+    type Error = Gic400Error;
+
+    fn enable(&mut self, irq: IrqNumber) -> Result<(), Self::Error> {
+        self.validate_irq(irq)?;
+        let (offset, bit) = Self::bit(irq);
+        self.write_gicd(GICD_ISENABLER + offset, bit);
+        Ok(())
+    }
+
+    fn disable(&mut self, irq: IrqNumber) -> Result<(), Self::Error> {
+        self.validate_irq(irq)?;
+        let (offset, bit) = Self::bit(irq);
+        self.write_gicd(GICD_ICENABLER + offset, bit);
+        Ok(())
+    }
+
+    fn is_pending(&self, irq: IrqNumber) -> Result<bool, Self::Error> {
+        self.validate_irq(irq)?;
+        let (offset, bit) = Self::bit(irq);
+        Ok(self.read_gicd(GICD_ISPENDR + offset) & bit != 0)
+    }
+
+    /// Acknowledge the highest-priority pending interrupt.
+    ///
+    /// The interrupt becomes active until [`clear`](Self::clear) signals
+    /// end-of-interrupt.
+    fn next_pending(&self) -> Option<IrqNumber> {
+        let irq = self.read_gicc(GICC_IAR) & IAR_ID_MASK;
+        if irq == SPURIOUS_IRQ { None } else { Some(irq) }
+    }
+
+    /// Signal end-of-interrupt.
+    fn clear(&mut self, irq: IrqNumber) -> Result<(), Self::Error> {
+        self.validate_irq(irq)?;
+        self.write_gicc(GICC_EOIR, irq);
+        Ok(())
+    }
+
+    fn set_priority(&mut self, irq: IrqNumber, priority: Priority) -> Result<(), Self::Error> {
+        self.validate_irq(irq)?;
+        // IPRIORITYR is byte-accessible
+        unsafe {
+            write_volatile(
+                (self.gicd + GICD_IPRIORITYR + irq as usize) as *mut u8,
+                priority,
+            );
+        }
+        Ok(())
+    }
+
+    fn priority_mask(&mut self, mask: Priority) -> Result<(), Self::Error> {
+        self.write_gicc(GICC_PMR, mask as u32);
+        Ok(())
+    }
+}
+
+// SAFETY: The GIC wraps memory-mapped hardware that can be safely
+// accessed from any thread when protected by synchronization.
+unsafe impl Send for Gic400 {}
+unsafe impl Sync for Gic400 {}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    GIC400_DRIVER,
+    crate::driver::Driver {
+        name: "gic-400",
+        compatible: &["arm,gic-400", "arm,cortex-a15-gic"],
+        probe,
+    }
+);
+
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    let gic = unsafe { Gic400::new(device.base_addr) };
+    device_mgr.register_interrupt_controller(device.name, gic)?;
+    Ok(())
+}
@@ -1 +1,2 @@
+pub mod gic400;
 pub mod pl011;
@@ -5,8 +5,8 @@
 //!
 //! # Features
 //!
-//! - Configurable baud rate
-//! - 8N1 configuration (8 data bits, no parity, 1 stop bit)
+//! - Configurable baud rate and frame format (5-8 data bits, parity, 1-2 stop bits)
+//! - Reconfiguration while open without losing received data
 //! - FIFO support
 //! - Blocking and non-blocking I/O
 //!
@@ -29,10 +29,15 @@ use crate::hal::serial::{
 };
 use core::ptr::{read_volatile, write_volatile};
 
-/// PL011 clock frequency
-const PL011_CLOCK_HZ: u32 = 48_000_000;
+/// Default reference clock, used when the real UART clock is unknown
+pub const PL011_DEFAULT_CLOCK_HZ: u32 = 48_000_000;
+
+/// Receive FIFO depth; bounds the bytes saved across a reconfigure
+const RX_FIFO_DEPTH: usize = 32;
 
 // Register offsets
+const DR_OFFSET: usize = 0x00;
+const RSRECR_OFFSET: usize = 0x04;
 const FR_OFFSET: usize = 0x18;
 const IBRD_OFFSET: usize = 0x24;
 const FBRD_OFFSET: usize = 0x28;
@@ -41,6 +46,13 @@ const CR_OFFSET: usize = 0x30;
 const IMSC_OFFSET: usize = 0x38;
 const ICR_OFFSET: usize = 0x44;
 
+// Data Register (DR) receive error bits
+const DR_FE: u32 = 1 << 8;
+const DR_PE: u32 = 1 << 9;
+const DR_BE: u32 = 1 << 10;
+const DR_OE: u32 = 1 << 11;
+const DR_ERROR_MASK: u32 = DR_FE | DR_PE | DR_BE | DR_OE;
+
 // Flag Register (FR) bits
 const FR_BUSY: u32 = 1 << 3;
 const FR_RXFE: u32 = 1 << 4;
@@ -51,9 +63,19 @@ const CR_UARTEN: u32 = 1 << 0;
 const CR_TXE: u32 = 1 << 8;
 const CR_RXE: u32 = 1 << 9;
 
+// Interrupt Mask Set/Clear Register (IMSC) bits
+const IMSC_RXIM: u32 = 1 << 4;
+const IMSC_RTIM: u32 = 1 << 6;
+
 // Line Control Register (LCRH) bits
-const LCRH_WLEN_8: u32 = 0b11 << 5;
+const LCRH_PEN: u32 = 1 << 1;
+const LCRH_EPS: u32 = 1 << 2;
+const LCRH_STP2: u32 = 1 << 3;
 const LCRH_FEN: u32 = 1 << 4;
+const LCRH_WLEN_5: u32 = 0b00 << 5;
+const LCRH_WLEN_6: u32 = 0b01 << 5;
+const LCRH_WLEN_7: u32 = 0b10 << 5;
+const LCRH_WLEN_8: u32 = 0b11 << 5;
 
 // ============================================================================
 // PL011-specific Error Type
@@ -102,10 +124,17 @@ impl From<PL011Error> for SerialError {
 /// PL011 UART driver.
 pub struct PL011 {
     base: usize,
+    /// UARTCLK frequency used for baud divisors
+    clock_hz: u32,
+    /// Bytes drained from the RX FIFO during a reconfigure, returned
+    /// before new FIFO data
+    rx_saved: [u8; RX_FIFO_DEPTH],
+    rx_saved_pos: usize,
+    rx_saved_len: usize,
 }
 
 impl PL011 {
-    /// Create a new PL011 UART instance.
+    /// Create a new PL011 UART instance clocked at [`PL011_DEFAULT_CLOCK_HZ`].
     ///
     /// # Safety
     ///
@@ -113,7 +142,27 @@ impl PL011 {
     /// - Only one instance should exist per UART hardware
     /// - Memory must be properly mapped as device memory
     pub const unsafe fn new(base: usize) -> Self {
-        Self { base }
+        unsafe { Self::with_clock(base, PL011_DEFAULT_CLOCK_HZ) }
+    }
+
+    /// Create a new PL011 UART instance with a known UARTCLK frequency.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as [`PL011::new`].
+    pub const unsafe fn with_clock(base: usize, clock_hz: u32) -> Self {
+        Self {
+            base,
+            clock_hz,
+            rx_saved: [0; RX_FIFO_DEPTH],
+            rx_saved_pos: 0,
+            rx_saved_len: 0,
+        }
+    }
+
+    /// UARTCLK frequency used for baud rate calculation.
+    pub fn clock_hz(&self) -> u32 {
+        self.clock_hz
     }
 
     #[inline]
@@ -134,13 +183,13 @@ impl PL011 {
     }
 
     /// Calculate baud rate divisors.
-    fn calculate_divisors(baud_rate: u32) -> Result<(u32, u32), PL011Error> {
+    fn calculate_divisors(&self, baud_rate: u32) -> Result<(u32, u32), PL011Error> {
         if baud_rate == 0 {
             return Err(PL011Error::InvalidConfig);
         }
 
         // BAUDDIV = (FUARTCLK / (16 × Baud rate))
-        let divisor = ((PL011_CLOCK_HZ as u64) << 6) / (16 * baud_rate as u64);
+        let divisor = ((self.clock_hz as u64) << 6) / (16 * baud_rate as u64);
 
         let integer = (divisor >> 6) as u32;
         let fractional = (divisor & 0x3F) as u32;
@@ -151,28 +200,32 @@ impl PL011 {
 
         Ok((integer, fractional))
     }
-}
-
-// ============================================================================
-// HAL Implementation
-// ============================================================================
-
-impl SerialPort for PL011 {
-    type Error = PL011Error;
-
-    fn configure(&mut self, config: SerialConfig) -> Result<(), Self::Error> {
-        // Validate configuration
-        if !matches!(config.data_bits, DataBits::Eight) {
-            return Err(PL011Error::InvalidConfig);
-        }
 
-        if !matches!(config.parity, Parity::None) {
-            return Err(PL011Error::InvalidConfig);
+    /// Line control value for a frame format (FIFOs enabled).
+    fn line_control(config: &SerialConfig) -> u32 {
+        let mut lcrh = LCRH_FEN;
+        lcrh |= match config.data_bits {
+            DataBits::Five => LCRH_WLEN_5,
+            DataBits::Six => LCRH_WLEN_6,
+            DataBits::Seven => LCRH_WLEN_7,
+            DataBits::Eight => LCRH_WLEN_8,
+        };
+        lcrh |= match config.parity {
+            Parity::None => 0,
+            Parity::Odd => LCRH_PEN,
+            Parity::Even => LCRH_PEN | LCRH_EPS,
+        };
+        if matches!(config.stop_bits, StopBits::Two) {
+            lcrh |= LCRH_STP2;
         }
+        lcrh
+    }
 
-        if !matches!(config.stop_bits, StopBits::One) {
-            return Err(PL011Error::InvalidConfig);
-        }
+    /// Reprogram baud rate and frame format. Leaves the interrupt mask
+    /// alone; the UART is disabled while the registers change.
+    fn program(&mut self, config: &SerialConfig) -> Result<(), PL011Error> {
+        // Validate before touching the hardware
+        let (ibrd, fbrd) = self.calculate_divisors(config.baud_rate)?;
 
         // Disable UART
         let mut cr = self.read_reg(CR_OFFSET);
@@ -187,13 +240,67 @@ impl SerialPort for PL011 {
         lcrh &= !LCRH_FEN;
         self.write_reg(LCRH_OFFSET, lcrh);
 
-        // Calculate and set baud rate divisors
-        let (ibrd, fbrd) = Self::calculate_divisors(config.baud_rate)?;
+        // Set baud rate divisors; they are latched by the LCRH write
         self.write_reg(IBRD_OFFSET, ibrd);
         self.write_reg(FBRD_OFFSET, fbrd);
+        self.write_reg(LCRH_OFFSET, Self::line_control(config));
 
-        // Configure line control: 8N1 with FIFOs enabled
-        self.write_reg(LCRH_OFFSET, LCRH_WLEN_8 | LCRH_FEN);
+        // Enable UART, transmitter, and receiver
+        self.write_reg(CR_OFFSET, CR_UARTEN | CR_TXE | CR_RXE);
+
+        Ok(())
+    }
+
+    /// Pop one character from the RX FIFO, checking its error bits.
+    ///
+    /// On error the character is dropped and the receive status is
+    /// cleared. A break also sets FE, so it is reported first; overrun
+    /// is reported last because the character carrying it is intact but
+    /// data before it was lost.
+    fn read_data(&mut self) -> Result<u8, PL011Error> {
+        let dr = self.read_reg(DR_OFFSET);
+        if dr & DR_ERROR_MASK == 0 {
+            return Ok((dr & 0xFF) as u8);
+        }
+
+        // Any write clears RSRECR
+        self.write_reg(RSRECR_OFFSET, 0);
+
+        Err(if dr & DR_BE != 0 {
+            PL011Error::Break
+        } else if dr & DR_FE != 0 {
+            PL011Error::Framing
+        } else if dr & DR_PE != 0 {
+            PL011Error::Parity
+        } else {
+            PL011Error::Overrun
+        })
+    }
+
+    /// Pop a byte saved by [`reconfigure`](SerialPort::reconfigure).
+    fn take_saved(&mut self) -> Option<u8> {
+        if self.rx_saved_pos < self.rx_saved_len {
+            let byte = self.rx_saved[self.rx_saved_pos];
+            self.rx_saved_pos += 1;
+            Some(byte)
+        } else {
+            None
+        }
+    }
+}
+
+// ============================================================================
+// HAL Implementation
+// ============================================================================
+
+impl SerialPort for PL011 {
+    type Error = PL011Error;
+
+    fn configure(&mut self, config: SerialConfig) -> Result<(), Self::Error> {
+        self.rx_saved_pos = 0;
+        self.rx_saved_len = 0;
+
+        self.program(&config)?;
 
         // Clear all pending interrupts
         self.write_reg(ICR_OFFSET, 0x07FF);
@@ -201,29 +308,58 @@ impl SerialPort for PL011 {
         // Disable all interrupts
         self.write_reg(IMSC_OFFSET, 0);
 
-        // Enable UART, transmitter, and receiver
-        self.write_reg(CR_OFFSET, CR_UARTEN | CR_TXE | CR_RXE);
-
         Ok(())
     }
 
+    /// Drain TX, save whatever is in the RX FIFO and reprogram the line.
+    /// Saved bytes are returned by subsequent reads; the interrupt mask
+    /// is preserved.
+    fn reconfigure(&mut self, config: SerialConfig) -> Result<(), Self::Error> {
+        // Reject bad settings while the port is still running
+        self.calculate_divisors(config.baud_rate)?;
+
+        self.wait_idle();
+
+        // Compact unread saved bytes, then append the FIFO contents
+        self.rx_saved
+            .copy_within(self.rx_saved_pos..self.rx_saved_len, 0);
+        self.rx_saved_len -= self.rx_saved_pos;
+        self.rx_saved_pos = 0;
+        while self.read_reg(FR_OFFSET) & FR_RXFE == 0 {
+            // Characters received with errors are dropped here
+            let Ok(byte) = self.read_data() else {
+                continue;
+            };
+            if self.rx_saved_len < RX_FIFO_DEPTH {
+                self.rx_saved[self.rx_saved_len] = byte;
+                self.rx_saved_len += 1;
+            }
+        }
+
+        self.program(&config)
+    }
+
     fn write_byte(&mut self, byte: u8) -> Result<(), Self::Error> {
         // Wait for TX FIFO to have space
         while self.read_reg(FR_OFFSET) & FR_TXFF != 0 {
             core::hint::spin_loop();
         }
 
-        self.write_reg(0x00, byte as u32);
+        self.write_reg(DR_OFFSET, byte as u32);
         Ok(())
     }
 
     fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        if let Some(byte) = self.take_saved() {
+            return Ok(byte);
+        }
+
         // Wait for data to be available
         while self.read_reg(FR_OFFSET) & FR_RXFE != 0 {
             core::hint::spin_loop();
         }
 
-        Ok((self.read_reg(0x00) & 0xFF) as u8)
+        self.read_data()
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
@@ -242,16 +378,29 @@ impl NonBlockingSerial for PL011 {
             return Err(PL011Error::WouldBlock);
         }
 
-        self.write_reg(0x00, byte as u32);
+        self.write_reg(DR_OFFSET, byte as u32);
         Ok(())
     }
 
     fn try_read_byte(&mut self) -> Result<u8, Self::Error> {
+        if let Some(byte) = self.take_saved() {
+            return Ok(byte);
+        }
+
         if self.read_reg(FR_OFFSET) & FR_RXFE != 0 {
             return Err(PL011Error::WouldBlock);
         }
 
-        Ok((self.read_reg(0x00) & 0xFF) as u8)
+        self.read_data()
+    }
+
+    /// Interrupts at the FIFO's trigger level, and when fewer bytes than
+    /// that have waited for 32 bit periods.
+    fn set_rx_interrupt(&mut self, enabled: bool) -> bool {
+        let rx = IMSC_RXIM | IMSC_RTIM;
+        let mask = self.read_reg(IMSC_OFFSET);
+        self.write_reg(IMSC_OFFSET, if enabled { mask | rx } else { mask & !rx });
+        true
     }
 }
 
@@ -261,3 +410,37 @@ unsafe impl Send for PL011 {}
 unsafe impl Sync for PL011 {}
 
 pub use PL011 as Pl011;
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    PL011_DRIVER,
+    crate::driver::Driver {
+        name: "pl011",
+        compatible: &["arm,pl011", "arm,primecell"],
+        probe,
+    }
+);
+
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    // The device tree's clock if it names a fixed one. On the Pi the UART
+    // clock comes from a clock controller and is set by the firmware
+    // (config.txt init_uart_clock), so ask the firmware if there is a
+    // mailbox to ask through. Otherwise fall back to the common default.
+    let clock_hz = device
+        .clock_hz
+        .or_else(|| unsafe {
+            crate::peripheral::bcm2835::mailbox::get_clock_rate(
+                crate::peripheral::bcm2835::mailbox::ClockId::Uart,
+            )
+        })
+        .unwrap_or(PL011_DEFAULT_CLOCK_HZ);
+    let uart = unsafe { PL011::with_clock(device.base_addr, clock_hz) };
+    device_mgr.register_serial(device.name, uart)?;
+    Ok(())
+}
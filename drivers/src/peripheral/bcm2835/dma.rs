@@ -0,0 +1,262 @@
+//! BCM2835 DMA Controller
+//!
+//! Minimal driver for the "full" DMA channels (0-14). A transfer is
+//! described by a chain of [`ControlBlock`]s in memory; the engine walks
+//! the chain until a block with `nextconbk == 0` completes.
+//!
+//! The DMA engine sees the VideoCore bus address space, not ARM physical
+//! addresses: use [`bus_addr_ram`] and [`bus_addr_peripheral`] when
+//! filling control blocks. Buffers the CPU wrote through the D-cache must
+//! be cleaned with [`clean_dcache_range`] before the engine reads them.
+
+use core::ptr::{read_volatile, write_volatile};
+
+/// DMA controller base address (channel 0).
+pub const DMA_BASE: usize = 0x2000_7000;
+
+/// Per-channel register stride.
+const CHANNEL_STRIDE: usize = 0x100;
+
+/// Global enable register (one bit per channel).
+const REG_ENABLE: usize = 0xFF0;
+
+/// Number of full DMA channels.
+pub const NUM_CHANNELS: u8 = 15;
+
+/// ARM physical base of the peripheral window.
+const ARM_PERIPHERAL_BASE: usize = 0x2000_0000;
+/// Bus address of the peripheral window.
+const BUS_PERIPHERAL_BASE: u32 = 0x7E00_0000;
+/// Bus alias for SDRAM that bypasses the VideoCore L2 cache.
+const BUS_RAM_UNCACHED: u32 = 0xC000_0000;
+
+// Channel register offsets
+const REG_CS: usize = 0x00;
+const REG_CONBLK_AD: usize = 0x04;
+const REG_DEBUG: usize = 0x20;
+
+// Control and status bits
+const CS_ACTIVE: u32 = 1 << 0;
+const CS_END: u32 = 1 << 1;
+const CS_INT: u32 = 1 << 2;
+const CS_ERROR: u32 = 1 << 8;
+const CS_WAIT_FOR_OUTSTANDING_WRITES: u32 = 1 << 28;
+const CS_ABORT: u32 = 1 << 30;
+const CS_RESET: u32 = 1 << 31;
+
+/// Default AXI priority used for transfers
+const CS_PRIORITY: u32 = 8 << 16;
+const CS_PANIC_PRIORITY: u32 = 8 << 20;
+
+/// Debug register error bits (write 1 to clear)
+const DEBUG_ERRORS: u32 = 0b111;
+
+/// Transfer information (`ti`) bits.
+pub mod ti {
+    /// Raise an interrupt when this block completes.
+    pub const INTEN: u32 = 1 << 0;
+    /// Wait for write responses before the next transfer.
+    pub const WAIT_RESP: u32 = 1 << 3;
+    /// Increment the destination address.
+    pub const DEST_INC: u32 = 1 << 4;
+    /// Gate writes on the peripheral's DREQ.
+    pub const DEST_DREQ: u32 = 1 << 6;
+    /// Increment the source address.
+    pub const SRC_INC: u32 = 1 << 8;
+    /// Gate reads on the peripheral's DREQ.
+    pub const SRC_DREQ: u32 = 1 << 10;
+
+    /// Peripheral whose DREQ paces the transfer.
+    pub const fn permap(dreq: u32) -> u32 {
+        (dreq & 0x1F) << 16
+    }
+}
+
+/// Peripheral DREQ numbers.
+pub mod dreq {
+    pub const PWM: u32 = 5;
+    pub const SPI_TX: u32 = 6;
+    pub const SPI_RX: u32 = 7;
+    pub const EMMC: u32 = 11;
+}
+
+/// DMA control block; must be 32-byte aligned.
+#[repr(C, align(32))]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ControlBlock {
+    /// Transfer information (see [`ti`])
+    pub ti: u32,
+    /// Source bus address
+    pub source_ad: u32,
+    /// Destination bus address
+    pub dest_ad: u32,
+    /// Transfer length in bytes
+    pub txfr_len: u32,
+    /// 2D stride (unused in linear mode)
+    pub stride: u32,
+    /// Bus address of the next control block, 0 to stop
+    pub nextconbk: u32,
+    _reserved: [u32; 2],
+}
+
+impl ControlBlock {
+    /// Single linear transfer with no successor.
+    pub const fn new(ti: u32, source_ad: u32, dest_ad: u32, txfr_len: u32) -> Self {
+        Self {
+            ti,
+            source_ad,
+            dest_ad,
+            txfr_len,
+            stride: 0,
+            nextconbk: 0,
+            _reserved: [0; 2],
+        }
+    }
+}
+
+/// Bus address of SDRAM at ARM physical address `addr` (identity mapped).
+pub fn bus_addr_ram(addr: usize) -> u32 {
+    addr as u32 | BUS_RAM_UNCACHED
+}
+
+/// Bus address of a peripheral register at ARM physical address `addr`.
+pub fn bus_addr_peripheral(addr: usize) -> u32 {
+    (addr - ARM_PERIPHERAL_BASE) as u32 + BUS_PERIPHERAL_BASE
+}
+
+/// Write back D-cache lines covering `[addr, addr + len)` so the DMA
+/// engine sees the CPU's writes.
+pub fn clean_dcache_range(addr: usize, len: usize) {
+    #[cfg(target_arch = "arm")]
+    {
+        const LINE: usize = 32;
+        let mut line = addr & !(LINE - 1);
+        while line < addr + len {
+            unsafe {
+                // Clean data cache line by MVA
+                core::arch::asm!("mcr p15, 0, {}, c7, c10, 1", in(reg) line, options(nostack));
+            }
+            line += LINE;
+        }
+        unsafe {
+            // DSB
+            core::arch::asm!("mcr p15, 0, {}, c7, c10, 4", in(reg) 0, options(nostack));
+        }
+    }
+    #[cfg(not(target_arch = "arm"))]
+    let _ = (addr, len);
+}
+
+// ============================================================================
+// Error Type
+// ============================================================================
+
+/// DMA errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DmaError {
+    /// Channel number out of range
+    InvalidChannel,
+    /// Engine reported a read/FIFO/AXI error
+    Transfer,
+}
+
+// ============================================================================
+// Driver
+// ============================================================================
+
+/// One DMA channel.
+#[derive(Debug)]
+pub struct DmaChannel {
+    base: usize,
+    channel: u8,
+}
+
+impl DmaChannel {
+    /// Claim and reset a DMA channel.
+    ///
+    /// # Safety
+    /// - DMA registers must be mapped
+    /// - The channel must not be in use by the firmware or another driver
+    pub unsafe fn new(channel: u8) -> Result<Self, DmaError> {
+        if channel >= NUM_CHANNELS {
+            return Err(DmaError::InvalidChannel);
+        }
+
+        let mut dma = Self {
+            base: DMA_BASE + channel as usize * CHANNEL_STRIDE,
+            channel,
+        };
+
+        unsafe {
+            let enable = (DMA_BASE + REG_ENABLE) as *mut u32;
+            write_volatile(enable, read_volatile(enable) | (1 << channel));
+        }
+        dma.reset();
+        Ok(dma)
+    }
+
+    /// Channel number.
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    #[inline]
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    #[inline]
+    fn write_reg(&mut self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// Abort any transfer and return the channel to idle.
+    pub fn reset(&mut self) {
+        self.write_reg(REG_CS, CS_RESET);
+        while self.read_reg(REG_CS) & CS_RESET != 0 {
+            core::hint::spin_loop();
+        }
+        self.write_reg(REG_DEBUG, DEBUG_ERRORS);
+    }
+
+    /// Start executing the chain beginning at `cb`.
+    ///
+    /// # Safety
+    /// `cb` and every block and buffer it references must stay valid
+    /// until the transfer completes or the channel is reset.
+    pub unsafe fn start(&mut self, cb: &ControlBlock) {
+        self.write_reg(REG_CS, CS_END | CS_INT);
+        self.write_reg(REG_CONBLK_AD, bus_addr_ram(cb as *const _ as usize));
+        self.write_reg(
+            REG_CS,
+            CS_ACTIVE | CS_PRIORITY | CS_PANIC_PRIORITY | CS_WAIT_FOR_OUTSTANDING_WRITES,
+        );
+    }
+
+    /// Is a transfer in progress?
+    pub fn is_active(&self) -> bool {
+        self.read_reg(REG_CS) & CS_ACTIVE != 0
+    }
+
+    /// Wait for the current chain to finish.
+    pub fn wait(&mut self) -> Result<(), DmaError> {
+        loop {
+            let cs = self.read_reg(REG_CS);
+            if cs & CS_ERROR != 0 {
+                self.write_reg(REG_CS, CS_ABORT);
+                self.reset();
+                return Err(DmaError::Transfer);
+            }
+            if cs & CS_ACTIVE == 0 {
+                self.write_reg(REG_CS, CS_END | CS_INT);
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+// SAFETY: the channel only touches its own register block; callers
+// serialise access through the owning driver.
+unsafe impl Send for DmaChannel {}
+unsafe impl Sync for DmaChannel {}
@@ -2,8 +2,19 @@
 //!
 //! This module provides a driver for the BCM2835 EMMC peripheral,
 //! which interfaces with SD/SDHC/SDXC cards.
+//!
+//! A run of more than one block is read or written with a single
+//! multi-block command (CMD18/CMD25), ended by an automatic CMD12.
+//!
+//! Command and data completions are polled for until the kernel routes
+//! the EMMC interrupt, which the probe asks for; from then on the caller
+//! sleeps until the interrupt reports them.
 
 use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use common::sync::Once;
+use common::sync::event::WaitEvent;
 
 use crate::hal::block_device::{
     BlockDevice, BlockDeviceError, BlockDeviceInfo, CardType, Cid, Csd, CsdParseError, CsdVersion,
@@ -69,6 +80,21 @@ const INT_DATA_CRC: u32 = 1 << 21;
 const INT_DATA_END_BIT: u32 = 1 << 22;
 const INT_ACMD_ERR: u32 = 1 << 24;
 
+/// Error bits, taken along with whatever completion was waited for
+const INT_ERROR_MASK: u32 = INT_ERROR
+    | INT_TIMEOUT
+    | INT_CRC
+    | INT_END_BIT
+    | INT_INDEX
+    | INT_DATA_TIMEOUT
+    | INT_DATA_CRC
+    | INT_DATA_END_BIT
+    | INT_ACMD_ERR;
+
+/// Interrupts raised to the CPU once [`use_interrupts`] is called
+const INT_WAIT_MASK: u32 =
+    INT_CMD_DONE | INT_DATA_DONE | INT_WRITE_READY | INT_READ_READY | INT_ERROR_MASK;
+
 /// Command register bits
 const CMD_RESPONSE_NONE: u32 = 0 << 16;
 const CMD_RESPONSE_136: u32 = 1 << 16;
@@ -127,6 +153,9 @@ const ACMD51: u32 = 51;
 /// Block size (fixed to 512 bytes)
 const BLOCK_SIZE: usize = 512;
 
+/// Most blocks in one multi-block transfer (the BLKSIZECNT count field)
+const MAX_BLOCKS_PER_CMD: usize = 0xFFFF;
+
 // ============================================================================
 // Error Type
 // ============================================================================
@@ -179,6 +208,55 @@ impl From<CsdParseError> for EmmcError {
     }
 }
 
+// ============================================================================
+// Interrupts
+// ============================================================================
+
+// There is one controller, at `EMMC_BASE`, so its interrupt state is too
+
+/// Interrupt bits acknowledged and not yet taken by a wait
+static PENDING: AtomicU32 = AtomicU32::new(0);
+
+/// Where waits sleep once the interrupt is routed; unset to poll
+static EVENT: Once<&'static dyn WaitEvent> = Once::new();
+
+/// Acknowledge the raised interrupts waits look for and note them in
+/// [`PENDING`]. Returns the bits taken.
+fn collect_interrupts() -> u32 {
+    let reg = (EMMC_BASE + REG_INTERRUPT) as *mut u32;
+    // SAFETY: only called once the controller is probed, so its
+    // registers are mapped; acknowledging is a write-one-to-clear
+    let interrupt = unsafe { read_volatile(reg) } & INT_WAIT_MASK;
+    if interrupt != 0 {
+        unsafe { write_volatile(reg, interrupt) };
+        PENDING.fetch_or(interrupt, Ordering::AcqRel);
+    }
+    interrupt
+}
+
+/// The EMMC interrupt handler: acknowledge the controller's interrupts
+/// and wake the waiter. Returns whether the controller raised any, as
+/// the line may be shared.
+fn handle_interrupt() -> bool {
+    if collect_interrupts() == 0 {
+        return false;
+    }
+    if let Some(event) = EVENT.get() {
+        event.notify();
+    }
+    true
+}
+
+/// Sleep on `event` for completions from now on, instead of polling.
+/// Called once the interrupt is routed to [`handle_interrupt`].
+fn use_interrupts(event: &'static dyn WaitEvent) {
+    if EVENT.set(event).is_ok() {
+        let reg = (EMMC_BASE + REG_IRPT_EN) as *mut u32;
+        // SAFETY: as in `collect_interrupts`
+        unsafe { write_volatile(reg, INT_WAIT_MASK) };
+    }
+}
+
 // ============================================================================
 // BCM2835 EMMC Driver
 // ============================================================================
@@ -224,38 +302,54 @@ impl Emmc {
         unsafe { write_volatile((self.base + offset) as *mut u32, value) }
     }
 
-    /// Wait for command to complete
-    fn wait_cmd_done(&self) -> Result<(), EmmcError> {
-        let timeout = 100_000;
-        for _ in 0..timeout {
-            let interrupt = self.read_reg(REG_INTERRUPT);
+    /// Clear every interrupt, raised or already acknowledged
+    fn clear_interrupts(&self) {
+        self.write_reg(REG_INTERRUPT, 0xFFFF_FFFF);
+        PENDING.store(0, Ordering::Release);
+    }
+
+    /// Wait until one of the interrupts in `mask`, or an error, is raised
+    /// and acknowledge it. Returns the bits taken, error bits included.
+    ///
+    /// Sleeping waits have no timeout of their own; the controller
+    /// raises command and data timeouts as errors.
+    fn wait_interrupt(&self, mask: u32) -> Result<u32, EmmcError> {
+        let wanted = mask | INT_ERROR_MASK;
+        // Also polled while sleeping, for what was raised before the
+        // interrupt was routed
+        let mut raised = || {
+            collect_interrupts();
+            PENDING.load(Ordering::Acquire) & wanted != 0
+        };
 
-            if interrupt & INT_ERROR != 0 {
-                // Check specific error bits
-                if interrupt & INT_TIMEOUT != 0 {
-                    self.write_reg(REG_INTERRUPT, INT_TIMEOUT);
+        if let Some(event) = EVENT.get() {
+            event.wait_until(&mut raised);
+        } else {
+            let timeout = 100_000;
+            let mut spins = 0;
+            while !raised() {
+                spins += 1;
+                if spins > timeout {
                     return Err(EmmcError::Timeout);
                 }
-                if interrupt & INT_CRC != 0 {
-                    self.write_reg(REG_INTERRUPT, INT_CRC);
-                    return Err(EmmcError::CrcError);
-                }
-                if interrupt & INT_INDEX != 0 {
-                    self.write_reg(REG_INTERRUPT, INT_INDEX);
-                }
-                self.write_reg(REG_INTERRUPT, INT_ERROR);
-                return Err(EmmcError::CommandError);
+                self.delay_us(10);
             }
-
-            if interrupt & INT_CMD_DONE != 0 {
-                // Clear interrupt
-                self.write_reg(REG_INTERRUPT, INT_CMD_DONE);
-                return Ok(());
-            }
-            self.delay_us(10);
         }
+        Ok(PENDING.fetch_and(!wanted, Ordering::AcqRel) & wanted)
+    }
 
-        Err(EmmcError::Timeout)
+    /// Wait for command to complete
+    fn wait_cmd_done(&self) -> Result<(), EmmcError> {
+        let interrupt = self.wait_interrupt(INT_CMD_DONE)?;
+        if interrupt & INT_ERROR == 0 {
+            Ok(())
+        } else if interrupt & INT_TIMEOUT != 0 {
+            Err(EmmcError::Timeout)
+        } else if interrupt & INT_CRC != 0 {
+            Err(EmmcError::CrcError)
+        } else {
+            Err(EmmcError::CommandError)
+        }
     }
 
     /// Send a command with custom flags
@@ -271,7 +365,7 @@ impl Emmc {
         }
 
         // Clear interrupts
-        self.write_reg(REG_INTERRUPT, 0xFFFF_FFFF);
+        self.clear_interrupts();
 
         // Set argument
         self.write_reg(REG_ARG2, (arg >> 32) as u32); // high
@@ -496,7 +590,7 @@ impl Emmc {
         self.write_reg(REG_BLKSIZECNT, (1 << 16) | BLOCK_SIZE as u32);
 
         // Clear interrupts
-        self.write_reg(REG_INTERRUPT, 0xFFFF_FFFF);
+        self.clear_interrupts();
 
         // Calculate address
         let address = match self.csd.version {
@@ -545,7 +639,7 @@ impl Emmc {
         self.write_reg(REG_BLKSIZECNT, (1 << 16) | BLOCK_SIZE as u32);
 
         // Clear interrupts
-        self.write_reg(REG_INTERRUPT, 0xFFFF_FFFF);
+        self.clear_interrupts();
 
         // Calculate address
         let address = match self.csd.version {
@@ -576,6 +670,87 @@ impl Emmc {
         Ok(())
     }
 
+    /// Read consecutive blocks with one CMD18, stopped by an auto CMD12
+    fn read_multiple_internal(&self, lba: u32, buffers: &mut [&mut [u8]]) -> Result<(), EmmcError> {
+        self.wait_dat_idle();
+        self.write_reg(
+            REG_BLKSIZECNT,
+            ((buffers.len() as u32) << 16) | BLOCK_SIZE as u32,
+        );
+        self.clear_interrupts();
+
+        let flags = CMD_RESPONSE_48
+            | CMD_CRCCHK_EN
+            | CMD_IXCHK_EN
+            | CMD_ISDATA
+            | TM_DAT_DIR_READ
+            | TM_MULTI_BLOCK
+            | TM_BLKCNT_EN
+            | TM_AUTO_CMD_EN_CMD12;
+        self.send_cmd(CMD18, self.block_address(lba), flags)?;
+
+        // The controller raises read-ready once per block
+        for buf in buffers.iter_mut() {
+            self.wait_data_ready()?;
+            for chunk in buf[..BLOCK_SIZE].chunks_mut(4) {
+                let word = self.read_reg(REG_DATA);
+                chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+            }
+        }
+
+        self.wait_data_done()
+    }
+
+    /// Write consecutive blocks with one CMD25, stopped by an auto CMD12
+    fn write_multiple_internal(&self, lba: u32, buffers: &[&[u8]]) -> Result<(), EmmcError> {
+        self.wait_dat_idle();
+        self.write_reg(
+            REG_BLKSIZECNT,
+            ((buffers.len() as u32) << 16) | BLOCK_SIZE as u32,
+        );
+        self.clear_interrupts();
+
+        let flags = CMD_RESPONSE_48
+            | CMD_CRCCHK_EN
+            | CMD_IXCHK_EN
+            | CMD_ISDATA
+            | TM_MULTI_BLOCK
+            | TM_BLKCNT_EN
+            | TM_AUTO_CMD_EN_CMD12;
+        self.send_cmd(CMD25, self.block_address(lba), flags)?;
+
+        // The controller raises write-ready once per block
+        for buf in buffers {
+            self.wait_write_ready()?;
+            for chunk in buf[..BLOCK_SIZE].chunks(4) {
+                let mut word = [0u8; 4];
+                word[..chunk.len()].copy_from_slice(chunk);
+                self.write_reg(REG_DATA, u32::from_le_bytes(word));
+            }
+        }
+
+        self.wait_data_done()
+    }
+
+    /// Wait for the DAT line to be free for a new transfer
+    fn wait_dat_idle(&self) {
+        for _ in 0..100_000 {
+            if self.read_reg(REG_STATUS) & STATUS_DAT_INHIBIT == 0 {
+                return;
+            }
+            self.delay_us(10);
+        }
+    }
+
+    /// Command argument addressing block `lba`: a byte address on
+    /// standard-capacity cards, a block number on the others
+    fn block_address(&self, lba: u32) -> u64 {
+        match self.csd.version {
+            CsdVersion::V1_0 => (lba as u64) * (BLOCK_SIZE as u64),
+            CsdVersion::V2_0 | CsdVersion::V3_0 => lba as u64,
+        }
+    }
+
     // ============================================================================
     // Helper methods
     // ============================================================================
@@ -670,77 +845,32 @@ impl Emmc {
     }
 
     fn wait_data_ready(&self) -> Result<(), EmmcError> {
-        let timeout = 100_000;
-        for _ in 0..timeout {
-            let interrupt = self.read_reg(REG_INTERRUPT);
-
-            if interrupt & INT_ERROR != 0 {
-                if interrupt & INT_DATA_TIMEOUT != 0 {
-                    self.write_reg(REG_INTERRUPT, INT_DATA_TIMEOUT);
-                    return Err(EmmcError::Timeout);
-                }
-                if interrupt & INT_DATA_CRC != 0 {
-                    self.write_reg(REG_INTERRUPT, INT_DATA_CRC);
-                    return Err(EmmcError::CrcError);
-                }
-                self.write_reg(REG_INTERRUPT, INT_ERROR);
-                return Err(EmmcError::ReadError);
-            }
-
-            if interrupt & INT_READ_READY != 0 {
-                // Clear interrupt
-                self.write_reg(REG_INTERRUPT, INT_READ_READY);
-                return Ok(());
-            }
-
-            self.delay_us(10);
+        let interrupt = self.wait_interrupt(INT_READ_READY)?;
+        if interrupt & INT_ERROR == 0 {
+            Ok(())
+        } else if interrupt & INT_DATA_TIMEOUT != 0 {
+            Err(EmmcError::Timeout)
+        } else if interrupt & INT_DATA_CRC != 0 {
+            Err(EmmcError::CrcError)
+        } else {
+            Err(EmmcError::ReadError)
         }
-
-        Err(EmmcError::Timeout)
     }
 
     fn wait_write_ready(&self) -> Result<(), EmmcError> {
-        let timeout = 100_000;
-        for _ in 0..timeout {
-            let interrupt = self.read_reg(REG_INTERRUPT);
-
-            if interrupt & INT_ERROR != 0 {
-                self.write_reg(REG_INTERRUPT, INT_ERROR);
-                return Err(EmmcError::WriteError);
-            }
-
-            if interrupt & INT_WRITE_READY != 0 {
-                // Clear interrupt
-                self.write_reg(REG_INTERRUPT, INT_WRITE_READY);
-                return Ok(());
-            }
-
-            self.delay_us(10);
+        let interrupt = self.wait_interrupt(INT_WRITE_READY)?;
+        if interrupt & INT_ERROR != 0 {
+            return Err(EmmcError::WriteError);
         }
-
-        Err(EmmcError::Timeout)
+        Ok(())
     }
 
     fn wait_data_done(&self) -> Result<(), EmmcError> {
-        let timeout = 100_000;
-        for _ in 0..timeout {
-            let interrupt = self.read_reg(REG_INTERRUPT);
-
-            if interrupt & INT_ERROR != 0 {
-                self.write_reg(REG_INTERRUPT, INT_ERROR);
-                return Err(EmmcError::WriteError);
-            }
-
-            if interrupt & INT_DATA_DONE != 0 {
-                // Clear interrupt
-                self.write_reg(REG_INTERRUPT, INT_DATA_DONE);
-                return Ok(());
-            }
-
-            self.delay_us(10);
+        let interrupt = self.wait_interrupt(INT_DATA_DONE)?;
+        if interrupt & INT_ERROR != 0 {
+            return Err(EmmcError::WriteError);
         }
-
-        Err(EmmcError::Timeout)
+        Ok(())
     }
 }
 
@@ -774,9 +904,13 @@ impl BlockDevice for Emmc {
             return Err(EmmcError::NoCard);
         }
 
-        // Read each block
-        for (i, buf_slice) in buffers.iter_mut().enumerate() {
-            self.read_block_internal((start_block + i as u64) as u32, buf_slice)?;
+        if let [buf] = buffers {
+            return self.read_block_internal(start_block as u32, buf);
+        }
+        let mut lba = start_block;
+        for chunk in buffers.chunks_mut(MAX_BLOCKS_PER_CMD) {
+            self.read_multiple_internal(lba as u32, chunk)?;
+            lba += chunk.len() as u64;
         }
 
         Ok(())
@@ -801,15 +935,19 @@ impl BlockDevice for Emmc {
             return Err(EmmcError::NoCard);
         }
 
-        // Write each block
-        for (i, buf_slice) in buffers.iter().enumerate() {
-            self.write_block_internal((start_block + i as u64) as u32, buf_slice)?;
+        if let [buf] = buffers {
+            return self.write_block_internal(start_block as u32, buf);
+        }
+        let mut lba = start_block;
+        for chunk in buffers.chunks(MAX_BLOCKS_PER_CMD) {
+            self.write_multiple_internal(lba as u32, chunk)?;
+            lba += chunk.len() as u64;
         }
 
         Ok(())
     }
 
-    fn flush(&mut self) -> Result<(), Self::Error> {
+    fn flush(&self) -> Result<(), Self::Error> {
         // For SD cards, writes are typically immediate
         Ok(())
     }
@@ -834,3 +972,39 @@ impl IdentifiableBlockDevice for Emmc {
 // accessed from any thread when protected by synchronization.
 unsafe impl Send for Emmc {}
 unsafe impl Sync for Emmc {}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    EMMC_DRIVER,
+    crate::driver::Driver {
+        name: "bcm2835-emmc",
+        compatible: &["brcm,bcm2835-sdhost", "brcm,bcm2711-emmc2"],
+        probe,
+    }
+);
+
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    let block_dev = unsafe { Emmc::new(device.base_addr) }
+        .map_err(|e| alloc::format!("Emmc init failed: {:?}", e))?;
+    // Polled until the kernel routes the interrupt
+    if let Some(irq) = device.irq {
+        device_mgr.request_irq(crate::device_manager::IrqRequest {
+            device: device.name.into(),
+            irq,
+            handler: handle_interrupt,
+            enabled: use_interrupts,
+        });
+    }
+    // Adjacent requests from different tasks become one transfer
+    let block_dev = crate::block::BlockRequestQueue::new(block_dev);
+    // FAT metadata is rewritten constantly; keep hot sectors in memory
+    let block_dev = crate::block::CachedBlockDevice::new(block_dev);
+    device_mgr.register_block(device.name, block_dev)?;
+    Ok(())
+}
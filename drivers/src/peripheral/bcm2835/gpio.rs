@@ -354,3 +354,33 @@ pub enum GpioError {
     InvalidPin,
     InvalidFunction,
 }
+
+impl From<GpioError> for crate::hal::gpio::GpioError {
+    fn from(err: GpioError) -> Self {
+        match err {
+            GpioError::InvalidPin => crate::hal::gpio::GpioError::InvalidPin,
+            GpioError::InvalidFunction => crate::hal::gpio::GpioError::InvalidFunction,
+        }
+    }
+}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    BCM2835_GPIO_DRIVER,
+    crate::driver::Driver {
+        name: "bcm2835-gpio",
+        compatible: &["brcm,bcm2835-gpio"],
+        probe,
+    }
+);
+
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    device_mgr.register_gpio(device.name, unsafe { Bcm2835Gpio::new() })?;
+    Ok(())
+}
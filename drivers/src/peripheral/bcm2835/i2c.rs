@@ -0,0 +1,217 @@
+//! BCM2835 BSC (I2C) Master Driver
+//!
+//! Polled driver for the Broadcom Serial Controller. BSC1 is the bus on
+//! the header pins (GPIO 2/3, ALT0) used by RTC and sensor HATs; pin
+//! muxing is expected to have been done by the firmware (`dtparam=i2c=on`).
+
+use crate::hal::i2c::{I2cBus, I2cError};
+use core::ptr::{read_volatile, write_volatile};
+
+/// BSC1 base address.
+pub const BSC1_BASE: usize = 0x2080_4000;
+
+/// Core clock feeding the BSC divider.
+const CORE_CLOCK_HZ: u32 = 250_000_000;
+
+/// Register offsets
+const REG_C: usize = 0x00;
+const REG_S: usize = 0x04;
+const REG_DLEN: usize = 0x08;
+const REG_A: usize = 0x0C;
+const REG_FIFO: usize = 0x10;
+const REG_DIV: usize = 0x14;
+
+/// Control register bits
+const C_I2CEN: u32 = 1 << 15;
+const C_ST: u32 = 1 << 7;
+const C_CLEAR: u32 = 0b11 << 4;
+const C_READ: u32 = 1 << 0;
+
+/// Status register bits
+const S_CLKT: u32 = 1 << 9;
+const S_ERR: u32 = 1 << 8;
+const S_RXD: u32 = 1 << 5;
+const S_TXD: u32 = 1 << 4;
+const S_DONE: u32 = 1 << 1;
+
+/// Maximum bytes per transfer (DLEN is 16 bits).
+const MAX_TRANSFER: usize = 0xFFFF;
+
+/// Polling iterations before a transfer is declared stuck.
+const TIMEOUT: u32 = 1_000_000;
+
+// ============================================================================
+// Error Type
+// ============================================================================
+
+/// BCM2835 I2C errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bcm2835I2cError {
+    /// Slave address NACKed
+    Nack,
+    /// Clock stretch timeout
+    ClockStretch,
+    /// Transfer did not finish in time
+    Timeout,
+    /// Address > 0x7F, empty buffer or transfer too long
+    InvalidArgument,
+}
+
+impl From<Bcm2835I2cError> for I2cError {
+    fn from(err: Bcm2835I2cError) -> Self {
+        match err {
+            Bcm2835I2cError::Nack => I2cError::Nack,
+            Bcm2835I2cError::ClockStretch => I2cError::ClockStretchTimeout,
+            Bcm2835I2cError::Timeout => I2cError::Timeout,
+            Bcm2835I2cError::InvalidArgument => I2cError::InvalidArgument,
+        }
+    }
+}
+
+// ============================================================================
+// Driver
+// ============================================================================
+
+pub struct Bcm2835I2c {
+    base: usize,
+}
+
+impl Bcm2835I2c {
+    /// Create a new I2C driver running at 100 kHz.
+    ///
+    /// # Safety
+    /// - `base` must point to a BSC register block
+    /// - Only one instance should exist per controller
+    pub unsafe fn new(base: usize) -> Self {
+        let mut i2c = Self { base };
+        let _ = i2c.set_clock(100_000);
+        i2c
+    }
+
+    #[inline]
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    #[inline]
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    fn begin(&self, addr: u8, len: usize, read: bool) -> Result<(), Bcm2835I2cError> {
+        if addr > 0x7F || len == 0 || len > MAX_TRANSFER {
+            return Err(Bcm2835I2cError::InvalidArgument);
+        }
+        // Clear FIFO and the write-1-to-clear status bits
+        self.write_reg(REG_C, C_I2CEN | C_CLEAR);
+        self.write_reg(REG_S, S_CLKT | S_ERR | S_DONE);
+        self.write_reg(REG_A, addr as u32);
+        self.write_reg(REG_DLEN, len as u32);
+        let mut c = C_I2CEN | C_ST;
+        if read {
+            c |= C_READ;
+        }
+        self.write_reg(REG_C, c);
+        Ok(())
+    }
+
+    /// Check error bits, wait for DONE and acknowledge it.
+    fn finish(&self) -> Result<(), Bcm2835I2cError> {
+        for _ in 0..TIMEOUT {
+            let s = self.read_reg(REG_S);
+            if let Some(err) = Self::status_error(s) {
+                self.write_reg(REG_S, S_CLKT | S_ERR | S_DONE);
+                return Err(err);
+            }
+            if s & S_DONE != 0 {
+                self.write_reg(REG_S, S_DONE);
+                return Ok(());
+            }
+        }
+        Err(Bcm2835I2cError::Timeout)
+    }
+
+    fn status_error(s: u32) -> Option<Bcm2835I2cError> {
+        if s & S_ERR != 0 {
+            Some(Bcm2835I2cError::Nack)
+        } else if s & S_CLKT != 0 {
+            Some(Bcm2835I2cError::ClockStretch)
+        } else {
+            None
+        }
+    }
+}
+
+impl I2cBus for Bcm2835I2c {
+    type Error = Bcm2835I2cError;
+
+    fn set_clock(&mut self, hz: u32) -> Result<(), Self::Error> {
+        if hz == 0 {
+            return Err(Bcm2835I2cError::InvalidArgument);
+        }
+        let div = CORE_CLOCK_HZ.div_ceil(hz);
+        if div > 0xFFFE {
+            return Err(Bcm2835I2cError::InvalidArgument);
+        }
+        // Divider is rounded down to an even value by hardware
+        self.write_reg(REG_DIV, div + (div & 1));
+        Ok(())
+    }
+
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.begin(addr, bytes.len(), false)?;
+
+        let mut sent = 0;
+        let mut spins = 0;
+        while sent < bytes.len() {
+            let s = self.read_reg(REG_S);
+            if let Some(err) = Self::status_error(s) {
+                self.write_reg(REG_S, S_CLKT | S_ERR | S_DONE);
+                return Err(err);
+            }
+            if s & S_TXD != 0 {
+                self.write_reg(REG_FIFO, bytes[sent] as u32);
+                sent += 1;
+                spins = 0;
+            } else {
+                spins += 1;
+                if spins > TIMEOUT {
+                    return Err(Bcm2835I2cError::Timeout);
+                }
+            }
+        }
+
+        self.finish()
+    }
+
+    fn read(&mut self, addr: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.begin(addr, buf.len(), true)?;
+
+        let mut received = 0;
+        let mut spins = 0;
+        while received < buf.len() {
+            let s = self.read_reg(REG_S);
+            if let Some(err) = Self::status_error(s) {
+                self.write_reg(REG_S, S_CLKT | S_ERR | S_DONE);
+                return Err(err);
+            }
+            if s & S_RXD != 0 {
+                buf[received] = self.read_reg(REG_FIFO) as u8;
+                received += 1;
+                spins = 0;
+            } else {
+                spins += 1;
+                if spins > TIMEOUT {
+                    return Err(Bcm2835I2cError::Timeout);
+                }
+            }
+        }
+
+        self.finish()
+    }
+}
+
+// SAFETY: the driver only touches its own MMIO block; callers serialise
+// access through the owning device lock.
+unsafe impl Send for Bcm2835I2c {}
+unsafe impl Sync for Bcm2835I2c {}
@@ -1,7 +1,8 @@
 //! BCM2835 Interrupt Controller Driver
 
 use crate::hal::interrupt::{
-    DynInterruptController, InterruptController, InterruptError, IrqNumber,
+    DynFiqInterruptController, DynInterruptController, FiqInterruptController, InterruptController,
+    InterruptError, IrqNumber, Priority,
 };
 use core::ptr::{read_volatile, write_volatile};
 
@@ -113,6 +114,35 @@ pub fn disable_irq(irq: u32) {
     }
 }
 
+/// FIQ control register: source select (bits 0-6) and enable (bit 7).
+const FIQ_SOURCE_MASK: u32 = 0x7F;
+const FIQ_ENABLE: u32 = 1 << 7;
+
+/// Route an interrupt line to FIQ (`None` disables FIQ).
+///
+/// Only one source can be routed at a time. The source must not also be
+/// enabled as a normal IRQ, or it will be delivered on both lines.
+pub fn route_fiq(irq: Option<u32>) {
+    unsafe {
+        let r = regs();
+        let value = match irq {
+            Some(irq) => (irq & FIQ_SOURCE_MASK) | FIQ_ENABLE,
+            None => 0,
+        };
+        write_volatile(&mut (*r).fiq_ctrl, value);
+    }
+}
+
+/// Interrupt line currently routed to FIQ, if any.
+pub fn fiq_source() -> Option<u32> {
+    let value = unsafe { read_volatile(&(*regs()).fiq_ctrl) };
+    if value & FIQ_ENABLE != 0 {
+        Some(value & FIQ_SOURCE_MASK)
+    } else {
+        None
+    }
+}
+
 // ============================================================================
 // Error Type
 // ============================================================================
@@ -194,9 +224,66 @@ impl InterruptController for Bcm2835InterruptController {
     fn next_pending(&self) -> Option<IrqNumber> {
         pending_irq()
     }
+
+    /// No hardware priorities: pending sources are reported in fixed order.
+    fn set_priority(&mut self, irq: IrqNumber, _priority: Priority) -> Result<(), Self::Error> {
+        Self::validate_irq(irq)
+    }
+
+    /// No hardware priority mask; accepted and ignored.
+    fn priority_mask(&mut self, _mask: Priority) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn as_fiq(&mut self) -> Option<&mut dyn DynFiqInterruptController> {
+        Some(self)
+    }
+}
+
+impl FiqInterruptController for Bcm2835InterruptController {
+    fn route_to_fiq(&mut self, irq: IrqNumber) -> Result<(), Self::Error> {
+        // FIQ source numbering matches ours: 0-63 GPU, 64-71 ARM basic
+        if irq >= 72 {
+            return Err(Bcm2835IntcError::InvalidIrq);
+        }
+        disable_irq(irq);
+        route_fiq(Some(irq));
+        Ok(())
+    }
+
+    fn clear_fiq(&mut self) -> Result<(), Self::Error> {
+        route_fiq(None);
+        Ok(())
+    }
+
+    fn fiq_source(&self) -> Option<IrqNumber> {
+        fiq_source()
+    }
 }
 
 // SAFETY: BCM2835 interrupt controller wraps memory-mapped hardware that can be safely
 // accessed from any thread when protected by synchronization.
 unsafe impl Send for Bcm2835InterruptController {}
 unsafe impl Sync for Bcm2835InterruptController {}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    BCM2835_INTC_DRIVER,
+    crate::driver::Driver {
+        name: "bcm2835-armctrl-ic",
+        compatible: &["brcm,bcm2835-armctrl-ic", "brcm,bcm2836-armctrl-ic"],
+        probe,
+    }
+);
+
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    let intc = unsafe { Bcm2835InterruptController::new(device.base_addr) };
+    device_mgr.register_interrupt_controller(device.name, intc)?;
+    Ok(())
+}
@@ -38,6 +38,10 @@ use core::ptr::{read_volatile, write_volatile};
 /// Mailbox base address.
 pub const MAILBOX_BASE: usize = 0x2000_B880;
 
+/// Compatible string of the mailbox in the platform tables. Matched
+/// exactly: other BCM283x blocks share the `brcm,bcm2835` prefix.
+pub const MAILBOX_COMPATIBLE: &str = "brcm,bcm2835-mbox";
+
 // Register offsets
 const REG_READ: usize = 0x00;
 const REG_STATUS: usize = 0x18;
@@ -107,6 +111,8 @@ pub mod tags {
     pub const GET_VC_MEMORY: u32 = 0x0001_0006;
     /// Get clocks.
     pub const GET_CLOCKS: u32 = 0x0001_0007;
+    /// Get clock rate.
+    pub const GET_CLOCK_RATE: u32 = 0x0003_0002;
     /// Get command line.
     pub const GET_COMMAND_LINE: u32 = 0x0005_0001;
     /// Get DMA channels.
@@ -139,6 +145,53 @@ pub mod tags {
     pub const GET_PITCH: u32 = 0x0004_0008;
 }
 
+/// Firmware clock identifiers (property interface).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ClockId {
+    Emmc = 1,
+    Uart = 2,
+    Arm = 3,
+    Core = 4,
+    V3d = 5,
+    H264 = 6,
+    Isp = 7,
+    Sdram = 8,
+    Pixel = 9,
+    Pwm = 10,
+    Emmc2 = 12,
+}
+
+/// Devices the firmware switches the power of (property interface).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum PowerDevice {
+    SdCard = 0,
+    Uart0 = 1,
+    Uart1 = 2,
+    UsbHcd = 3,
+    I2c0 = 4,
+    I2c1 = 5,
+    I2c2 = 6,
+    Spi = 7,
+    Ccp2Tx = 8,
+}
+
+impl PowerDevice {
+    /// Every device, in firmware order.
+    pub const ALL: [PowerDevice; 9] = [
+        PowerDevice::SdCard,
+        PowerDevice::Uart0,
+        PowerDevice::Uart1,
+        PowerDevice::UsbHcd,
+        PowerDevice::I2c0,
+        PowerDevice::I2c1,
+        PowerDevice::I2c2,
+        PowerDevice::Spi,
+        PowerDevice::Ccp2Tx,
+    ];
+}
+
 /// BCM2835 Mailbox interface.
 #[derive(Debug)]
 pub struct Mailbox {
@@ -155,6 +208,16 @@ impl Mailbox {
         Self { base: MAILBOX_BASE }
     }
 
+    /// The mailbox the platform tables list, at the base they give, or
+    /// `None` on a machine without one.
+    pub fn find() -> Option<Self> {
+        crate::platform::Platform::devices()
+            .find(|device| device.compatible == MAILBOX_COMPATIBLE)
+            .map(|device| Self {
+                base: device.base_addr,
+            })
+    }
+
     /// Create a mailbox with custom base address (for testing).
     ///
     /// # Safety
@@ -475,3 +538,164 @@ pub unsafe fn get_board_serial() -> Option<u64> {
         None
     }
 }
+
+/// Longest command line [`get_command_line`] returns.
+pub const COMMAND_LINE_MAX: usize = 1024;
+
+/// Query the kernel command line the firmware built from `cmdline.txt`.
+///
+/// Copies it into `buf`, truncated to fit, and returns its length.
+///
+/// # Safety
+///
+/// - Mailbox must be accessible
+/// - Identity mapping required
+pub unsafe fn get_command_line(buf: &mut [u8]) -> Option<usize> {
+    #[repr(C, align(16))]
+    struct CommandLineRequest {
+        size: u32,
+        code: u32,
+        tag: u32,
+        val_buf_size: u32,
+        val_len: u32,
+        line: [u8; COMMAND_LINE_MAX],
+        end: u32,
+    }
+
+    static mut REQ: CommandLineRequest = CommandLineRequest {
+        size: core::mem::size_of::<CommandLineRequest>() as u32,
+        code: 0,
+        tag: tags::GET_COMMAND_LINE,
+        val_buf_size: COMMAND_LINE_MAX as u32,
+        val_len: 0,
+        line: [0; COMMAND_LINE_MAX],
+        end: 0,
+    };
+
+    let mut mailbox = unsafe { Mailbox::new() };
+    let req_phys = &raw mut REQ as usize;
+
+    if !unsafe { mailbox.call(Channel::Property, req_phys) } {
+        return None;
+    }
+    // The response length has bit 31 set, and may exceed the buffer
+    let len = unsafe { read_volatile(core::ptr::addr_of!(REQ.val_len)) } & 0x7FFF_FFFF;
+    let len = (len as usize).min(COMMAND_LINE_MAX).min(buf.len());
+    for (i, byte) in buf[..len].iter_mut().enumerate() {
+        *byte = unsafe { read_volatile(core::ptr::addr_of!(REQ.line[i])) };
+    }
+    // The firmware counts a terminating NUL
+    Some(buf[..len].iter().position(|&b| b == 0).unwrap_or(len))
+}
+
+/// Query the current rate of a firmware-managed clock.
+///
+/// Returns the rate in Hz, or `None` if the platform has no mailbox (see
+/// [`Mailbox::find`]) or the call failed.
+///
+/// # Safety
+///
+/// - Mailbox must be accessible
+/// - Identity mapping required
+pub unsafe fn get_clock_rate(clock: ClockId) -> Option<u32> {
+    #[repr(C, align(16))]
+    struct ClockRateRequest {
+        size: u32,
+        code: u32,
+        tag: u32,
+        val_buf_size: u32,
+        val_len: u32,
+        clock_id: u32,
+        rate: u32,
+        end: u32,
+    }
+
+    static mut REQ: ClockRateRequest = ClockRateRequest {
+        size: core::mem::size_of::<ClockRateRequest>() as u32,
+        code: 0,
+        tag: tags::GET_CLOCK_RATE,
+        val_buf_size: 8,
+        val_len: 4,
+        clock_id: 0,
+        rate: 0,
+        end: 0,
+    };
+
+    let mut mailbox = Mailbox::find()?;
+    let req_phys = &raw mut REQ as usize;
+
+    // The buffer is rewritten by the firmware; reset it for every call
+    unsafe {
+        write_volatile(core::ptr::addr_of_mut!(REQ.code), 0);
+        write_volatile(core::ptr::addr_of_mut!(REQ.val_len), 4);
+        write_volatile(core::ptr::addr_of_mut!(REQ.clock_id), clock as u32);
+        write_volatile(core::ptr::addr_of_mut!(REQ.rate), 0);
+    }
+
+    if unsafe { mailbox.call(Channel::Property, req_phys) } {
+        let rate = unsafe { read_volatile(core::ptr::addr_of!(REQ.rate)) };
+        (rate != 0).then_some(rate)
+    } else {
+        None
+    }
+}
+
+/// Switch a firmware-managed device on or off, waiting until it has.
+///
+/// Returns whether the device is on afterwards, or `None` if the call
+/// failed or the device does not exist.
+///
+/// # Safety
+///
+/// - Mailbox must be accessible
+/// - Identity mapping required
+/// - Nothing may be using the device when it is switched off
+pub unsafe fn set_power_state(device: PowerDevice, on: bool) -> Option<bool> {
+    /// State bit: the device is on
+    const STATE_ON: u32 = 1 << 0;
+    /// Request bit: wait for the device to settle
+    const STATE_WAIT: u32 = 1 << 1;
+    /// Response bit: there is no such device
+    const STATE_NO_DEVICE: u32 = 1 << 1;
+
+    #[repr(C, align(16))]
+    struct PowerStateRequest {
+        size: u32,
+        code: u32,
+        tag: u32,
+        val_buf_size: u32,
+        val_len: u32,
+        device_id: u32,
+        state: u32,
+        end: u32,
+    }
+
+    static mut REQ: PowerStateRequest = PowerStateRequest {
+        size: core::mem::size_of::<PowerStateRequest>() as u32,
+        code: 0,
+        tag: tags::SET_POWER_STATE,
+        val_buf_size: 8,
+        val_len: 8,
+        device_id: 0,
+        state: 0,
+        end: 0,
+    };
+
+    let mut mailbox = unsafe { Mailbox::new() };
+    let req_phys = &raw mut REQ as usize;
+
+    // The buffer is rewritten by the firmware; reset it for every call
+    let state = if on { STATE_ON } else { 0 } | STATE_WAIT;
+    unsafe {
+        write_volatile(core::ptr::addr_of_mut!(REQ.code), 0);
+        write_volatile(core::ptr::addr_of_mut!(REQ.val_len), 8);
+        write_volatile(core::ptr::addr_of_mut!(REQ.device_id), device as u32);
+        write_volatile(core::ptr::addr_of_mut!(REQ.state), state);
+    }
+
+    if !unsafe { mailbox.call(Channel::Property, req_phys) } {
+        return None;
+    }
+    let state = unsafe { read_volatile(core::ptr::addr_of!(REQ.state)) };
+    (state & STATE_NO_DEVICE == 0).then_some(state & STATE_ON != 0)
+}
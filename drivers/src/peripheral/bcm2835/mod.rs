@@ -1,5 +1,11 @@
+pub mod dma;
 pub mod emmc;
 pub mod framebuffer;
+pub mod gpio;
+pub mod i2c;
 pub mod intc;
 pub mod mailbox;
+pub mod pm;
+pub mod pwm_audio;
+pub mod spi;
 pub mod timer;
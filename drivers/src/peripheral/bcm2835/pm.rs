@@ -0,0 +1,114 @@
+//! BCM2835 Power Management Watchdog
+//!
+//! The power management block has a watchdog timer that resets the whole
+//! SoC when it runs out, after which the firmware boots again as from
+//! power-on. Rebooting is arming it with a timeout of a few ticks.
+//!
+//! Every write to these registers must carry [`PASSWORD`] in its top
+//! byte, or the block ignores it.
+
+use core::ptr::{read_volatile, write_volatile};
+
+/// Power management base address.
+pub const PM_BASE: usize = 0x2010_0000;
+
+/// Offset of the power management block from the peripheral base, which
+/// moves on later SoCs.
+pub const PM_OFFSET: usize = 0x10_0000;
+
+/// Watchdog ticks in a second: a tick is about 15 µs.
+pub const WDOG_TICKS_PER_SEC: u32 = 1 << 16;
+
+/// Reset control register offset.
+const RSTC: usize = 0x1C;
+/// Watchdog timer register offset.
+const WDOG: usize = 0x24;
+
+/// Key every register write must carry.
+const PASSWORD: u32 = 0x5A00_0000;
+/// Where the password goes.
+const PASSWORD_MASK: u32 = 0xFF00_0000;
+
+/// RSTC: what happens when the watchdog runs out.
+const RSTC_WRCFG_MASK: u32 = 0x30;
+/// RSTC: reset the whole SoC.
+const RSTC_WRCFG_FULL_RESET: u32 = 0x20;
+/// RSTC: stop the watchdog.
+const RSTC_RESET: u32 = 0x102;
+
+/// Widest timeout the WDOG register holds, in ticks.
+const WDOG_TIME_MASK: u32 = 0x000F_FFFF;
+
+/// Ticks [`Watchdog::reset`] waits before the reset.
+const RESET_TICKS: u32 = 10;
+
+/// The watchdog of the power management block.
+pub struct Watchdog {
+    base: usize,
+}
+
+impl Watchdog {
+    /// Create the watchdog at the default base address.
+    ///
+    /// # Safety
+    ///
+    /// Power management registers must be properly mapped.
+    pub const unsafe fn new() -> Self {
+        Self { base: PM_BASE }
+    }
+
+    /// Create the watchdog with a custom base address.
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to valid power management registers.
+    pub const unsafe fn with_base(base: usize) -> Self {
+        Self { base }
+    }
+
+    #[inline]
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    #[inline]
+    fn write(&mut self, offset: usize, value: u32) {
+        unsafe {
+            write_volatile(
+                (self.base + offset) as *mut u32,
+                PASSWORD | (value & !PASSWORD_MASK),
+            )
+        }
+    }
+
+    /// Reset the SoC once `ticks` have passed, unless stopped or started
+    /// again before then. Longer timeouts are cut to the widest there is.
+    pub fn start(&mut self, ticks: u32) {
+        let rstc = self.read(RSTC) & !RSTC_WRCFG_MASK;
+        self.write(WDOG, ticks.min(WDOG_TIME_MASK));
+        self.write(RSTC, rstc | RSTC_WRCFG_FULL_RESET);
+    }
+
+    /// Stop the watchdog.
+    pub fn stop(&mut self) {
+        self.write(RSTC, RSTC_RESET);
+    }
+
+    /// Whether the watchdog is counting down to a reset.
+    pub fn is_running(&self) -> bool {
+        self.read(RSTC) & RSTC_WRCFG_MASK == RSTC_WRCFG_FULL_RESET
+    }
+
+    /// Ticks left before the reset.
+    pub fn remaining(&self) -> u32 {
+        self.read(WDOG) & WDOG_TIME_MASK
+    }
+
+    /// Reset the SoC now.
+    pub fn reset(&mut self) -> ! {
+        self.start(RESET_TICKS);
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+}
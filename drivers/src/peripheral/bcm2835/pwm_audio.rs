@@ -0,0 +1,308 @@
+//! BCM2835 PWM Audio Output
+//!
+//! Drives the headphone jack on boards that have one: PWM channel 1 on
+//! GPIO40 (right) and channel 2 on GPIO45 (left), both ALT0, filtered by
+//! the on-board RC network. Samples are converted to PWM duty values and
+//! streamed into the PWM FIFO by DMA from two alternating buffers, so one
+//! buffer can be filled while the other plays.
+//!
+//! The PWM clock is PLLD (500 MHz) divided by two; the range register
+//! sets the sample period, which gives roughly 12 bits of resolution at
+//! 44.1 kHz.
+
+use crate::hal::audio::{AudioConfig, AudioError, AudioOutput};
+use crate::peripheral::bcm2835::dma::{self, ControlBlock, DmaChannel, DmaError};
+use crate::peripheral::bcm2835::gpio::{self, Function};
+use alloc::boxed::Box;
+use core::ptr::{read_volatile, write_volatile};
+
+/// PWM controller base address.
+pub const PWM_BASE: usize = 0x2020_C000;
+
+/// Clock manager base address.
+const CM_BASE: usize = 0x2010_1000;
+const CM_PWMCTL: usize = 0xA0;
+const CM_PWMDIV: usize = 0xA4;
+const CM_PASSWORD: u32 = 0x5A << 24;
+const CM_CTL_SRC_PLLD: u32 = 6;
+const CM_CTL_ENAB: u32 = 1 << 4;
+const CM_CTL_BUSY: u32 = 1 << 7;
+
+/// PLLD runs at 500 MHz; divide by 2.
+const PWM_CLOCK_HZ: u32 = 250_000_000;
+const PWM_CLOCK_DIVI: u32 = 2;
+
+// PWM register offsets
+const REG_CTL: usize = 0x00;
+const REG_STA: usize = 0x04;
+const REG_DMAC: usize = 0x08;
+const REG_RNG1: usize = 0x10;
+const REG_FIF1: usize = 0x18;
+const REG_RNG2: usize = 0x20;
+
+// Control register bits
+const CTL_PWEN1: u32 = 1 << 0;
+const CTL_USEF1: u32 = 1 << 5;
+const CTL_CLRF1: u32 = 1 << 6;
+const CTL_PWEN2: u32 = 1 << 8;
+const CTL_USEF2: u32 = 1 << 13;
+
+// Status register bits
+const STA_EMPT1: u32 = 1 << 1;
+const STA_ERRORS: u32 = 0x1FC;
+
+// DMA configuration: enable, panic and DREQ thresholds
+const DMAC_ENAB: u32 = 1 << 31;
+const DMAC_PANIC: u32 = 7 << 8;
+const DMAC_DREQ: u32 = 7;
+
+/// Headphone jack pins (ALT0 = PWM0 / PWM1).
+const GPIO_AUDIO_RIGHT: u8 = 40;
+const GPIO_AUDIO_LEFT: u8 = 45;
+
+/// DMA channel used for audio (free on stock firmware).
+pub const AUDIO_DMA_CHANNEL: u8 = 5;
+
+/// Supported sample rates.
+const MIN_SAMPLE_RATE: u32 = 8_000;
+const MAX_SAMPLE_RATE: u32 = 192_000;
+
+/// Frames per DMA buffer.
+const BUFFER_FRAMES: usize = 2048;
+/// The FIFO alternates channel 1 / channel 2, so two words per frame.
+const BUFFER_WORDS: usize = BUFFER_FRAMES * 2;
+
+// ============================================================================
+// Error Type
+// ============================================================================
+
+/// PWM audio errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PwmAudioError {
+    /// Sample rate outside 8-192 kHz
+    UnsupportedRate,
+    /// Only mono and stereo are supported
+    UnsupportedChannels,
+    /// Sample count not a multiple of the channel count
+    PartialFrame,
+    /// DMA channel unavailable or transfer failed
+    Dma(DmaError),
+}
+
+impl From<PwmAudioError> for AudioError {
+    fn from(err: PwmAudioError) -> Self {
+        match err {
+            PwmAudioError::UnsupportedRate => AudioError::UnsupportedRate,
+            PwmAudioError::UnsupportedChannels => AudioError::UnsupportedChannels,
+            PwmAudioError::PartialFrame => AudioError::PartialFrame,
+            PwmAudioError::Dma(_) => AudioError::Hardware,
+        }
+    }
+}
+
+impl From<DmaError> for PwmAudioError {
+    fn from(err: DmaError) -> Self {
+        PwmAudioError::Dma(err)
+    }
+}
+
+// ============================================================================
+// Driver
+// ============================================================================
+
+/// Control blocks and sample buffers handed to the DMA engine.
+#[repr(C, align(32))]
+struct DmaBuffers {
+    cbs: [ControlBlock; 2],
+    data: [[u32; BUFFER_WORDS]; 2],
+}
+
+pub struct PwmAudio {
+    base: usize,
+    dma: DmaChannel,
+    buffers: Box<DmaBuffers>,
+    /// Buffer to fill next
+    next: usize,
+    config: AudioConfig,
+    /// PWM range (clock ticks per sample)
+    range: u32,
+}
+
+impl PwmAudio {
+    /// Route the audio pins, start the PWM clock and configure 44.1 kHz
+    /// stereo.
+    ///
+    /// # Safety
+    /// - `base` must point to the PWM register block
+    /// - GPIO, clock manager and DMA registers must be mapped
+    /// - [`AUDIO_DMA_CHANNEL`] must not be used by anything else
+    pub unsafe fn new(base: usize) -> Result<Self, PwmAudioError> {
+        let dma = unsafe { DmaChannel::new(AUDIO_DMA_CHANNEL)? };
+
+        // Zeroed control blocks and silence; too large for the stack
+        let buffers = unsafe { Box::<DmaBuffers>::new_zeroed().assume_init() };
+
+        let mut audio = Self {
+            base,
+            dma,
+            buffers,
+            next: 0,
+            config: AudioConfig::default(),
+            range: 0,
+        };
+
+        let _ = gpio::set_function(GPIO_AUDIO_RIGHT, Function::Alt0);
+        let _ = gpio::set_function(GPIO_AUDIO_LEFT, Function::Alt0);
+        audio.start_clock();
+        audio.configure(AudioConfig::default())?;
+        Ok(audio)
+    }
+
+    #[inline]
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    #[inline]
+    fn write_reg(&mut self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    fn start_clock(&mut self) {
+        let ctl = (CM_BASE + CM_PWMCTL) as *mut u32;
+        let div = (CM_BASE + CM_PWMDIV) as *mut u32;
+        unsafe {
+            // Stop the clock and wait for it to settle before changing it
+            write_volatile(ctl, CM_PASSWORD | CM_CTL_SRC_PLLD);
+            while read_volatile(ctl) & CM_CTL_BUSY != 0 {
+                core::hint::spin_loop();
+            }
+            write_volatile(div, CM_PASSWORD | (PWM_CLOCK_DIVI << 12));
+            write_volatile(ctl, CM_PASSWORD | CM_CTL_SRC_PLLD | CM_CTL_ENAB);
+        }
+    }
+
+    /// Duty value for a signed sample.
+    #[inline]
+    fn duty(&self, sample: i16) -> u32 {
+        ((sample as i32 + 0x8000) as u32 * self.range) >> 16
+    }
+}
+
+impl AudioOutput for PwmAudio {
+    type Error = PwmAudioError;
+
+    fn configure(&mut self, config: AudioConfig) -> Result<(), Self::Error> {
+        if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&config.sample_rate) {
+            return Err(PwmAudioError::UnsupportedRate);
+        }
+        if !matches!(config.channels, 1 | 2) {
+            return Err(PwmAudioError::UnsupportedChannels);
+        }
+
+        self.dma.reset();
+        self.write_reg(REG_CTL, 0);
+
+        self.range = PWM_CLOCK_HZ / config.sample_rate;
+        self.config = config;
+        self.next = 0;
+
+        self.write_reg(REG_RNG1, self.range);
+        self.write_reg(REG_RNG2, self.range);
+        self.write_reg(REG_STA, STA_ERRORS);
+        self.write_reg(REG_CTL, CTL_CLRF1);
+        self.write_reg(REG_DMAC, DMAC_ENAB | DMAC_PANIC | DMAC_DREQ);
+        self.write_reg(REG_CTL, CTL_PWEN1 | CTL_USEF1 | CTL_PWEN2 | CTL_USEF2);
+        Ok(())
+    }
+
+    fn config(&self) -> AudioConfig {
+        self.config
+    }
+
+    fn write_samples(&mut self, samples: &[i16]) -> Result<usize, Self::Error> {
+        let channels = self.config.channels as usize;
+        if !samples.len().is_multiple_of(channels) {
+            return Err(PwmAudioError::PartialFrame);
+        }
+
+        let frames = (samples.len() / channels).min(BUFFER_FRAMES);
+        if frames == 0 {
+            return Ok(0);
+        }
+
+        // Fill the idle buffer while the other one plays
+        let idx = self.next;
+        for i in 0..frames {
+            let (left, right) = if channels == 2 {
+                (samples[i * 2], samples[i * 2 + 1])
+            } else {
+                (samples[i], samples[i])
+            };
+            let (l, r) = (self.duty(left), self.duty(right));
+            // FIFO words alternate channel 1 (GPIO40, right) / 2 (GPIO45, left)
+            self.buffers.data[idx][i * 2] = r;
+            self.buffers.data[idx][i * 2 + 1] = l;
+        }
+
+        let data_addr = self.buffers.data[idx].as_ptr() as usize;
+        let len = frames * 2 * core::mem::size_of::<u32>();
+        self.buffers.cbs[idx] = ControlBlock::new(
+            dma::ti::permap(dma::dreq::PWM)
+                | dma::ti::DEST_DREQ
+                | dma::ti::SRC_INC
+                | dma::ti::WAIT_RESP,
+            dma::bus_addr_ram(data_addr),
+            dma::bus_addr_peripheral(PWM_BASE + REG_FIF1),
+            len as u32,
+        );
+        dma::clean_dcache_range(data_addr, len);
+        dma::clean_dcache_range(
+            &self.buffers.cbs[idx] as *const _ as usize,
+            core::mem::size_of::<ControlBlock>(),
+        );
+
+        // The PWM FIFO covers the gap between buffers
+        self.dma.wait()?;
+        unsafe { self.dma.start(&self.buffers.cbs[idx]) };
+        self.next ^= 1;
+
+        Ok(frames * channels)
+    }
+
+    fn drain(&mut self) -> Result<(), Self::Error> {
+        self.dma.wait()?;
+        while self.read_reg(REG_STA) & STA_EMPT1 == 0 {
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+}
+
+// SAFETY: PWM audio wraps memory-mapped hardware and DMA buffers it owns;
+// callers serialise access through the owning device lock.
+unsafe impl Send for PwmAudio {}
+unsafe impl Sync for PwmAudio {}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    BCM2835_PWM_AUDIO_DRIVER,
+    crate::driver::Driver {
+        name: "bcm2835-pwm-audio",
+        compatible: &["brcm,bcm2835-pwm"],
+        probe,
+    }
+);
+
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    let audio = unsafe { PwmAudio::new(device.base_addr) }
+        .map_err(|e| alloc::format!("PWM audio init failed: {:?}", e))?;
+    device_mgr.register_audio(device.name, audio)?;
+    Ok(())
+}
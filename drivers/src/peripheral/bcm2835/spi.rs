@@ -0,0 +1,202 @@
+//! BCM2835 SPI0 Master Driver
+//!
+//! Polled driver for the primary SPI controller. Pin muxing
+//! (GPIO 7–11, ALT0) is expected to have been done by the firmware
+//! (`dtparam=spi=on`) or by the caller before the bus is used.
+
+use crate::hal::spi::{SpiBus, SpiConfig, SpiError};
+use core::ptr::{read_volatile, write_volatile};
+
+/// SPI0 base address.
+pub const SPI0_BASE: usize = 0x2020_4000;
+
+/// Core clock feeding the SPI divider.
+const CORE_CLOCK_HZ: u32 = 250_000_000;
+
+/// Register offsets
+const REG_CS: usize = 0x00;
+const REG_FIFO: usize = 0x04;
+const REG_CLK: usize = 0x08;
+
+/// CS register bits
+const CS_CS_MASK: u32 = 0b11;
+/// CS2, which is not routed on the Pi
+const CS_UNUSED: u32 = 0b10;
+const CS_CPHA: u32 = 1 << 2;
+const CS_CPOL: u32 = 1 << 3;
+const CS_CLEAR_TX: u32 = 1 << 4;
+const CS_CLEAR_RX: u32 = 1 << 5;
+const CS_TA: u32 = 1 << 7;
+const CS_DONE: u32 = 1 << 16;
+const CS_RXD: u32 = 1 << 17;
+const CS_TXD: u32 = 1 << 18;
+
+/// Polling iterations before a transfer is declared stuck.
+const TIMEOUT: u32 = 1_000_000;
+
+// ============================================================================
+// Error Type
+// ============================================================================
+
+/// BCM2835 SPI errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bcm2835SpiError {
+    /// Requested clock is outside the divider range.
+    InvalidClock,
+    /// Chip-select must be 0 or 1 (CS2 is not routed on the Pi).
+    InvalidChipSelect,
+    /// FIFO did not drain/fill in time.
+    Timeout,
+}
+
+impl From<Bcm2835SpiError> for SpiError {
+    fn from(err: Bcm2835SpiError) -> Self {
+        match err {
+            Bcm2835SpiError::InvalidClock | Bcm2835SpiError::InvalidChipSelect => {
+                SpiError::InvalidConfig
+            }
+            Bcm2835SpiError::Timeout => SpiError::Timeout,
+        }
+    }
+}
+
+// ============================================================================
+// Driver
+// ============================================================================
+
+pub struct Bcm2835Spi {
+    base: usize,
+    config: SpiConfig,
+}
+
+impl Bcm2835Spi {
+    /// Create a new SPI driver, configured with [`SpiConfig::default`].
+    ///
+    /// # Safety
+    /// - `base` must point to the SPI0 register block
+    /// - Only one instance should exist per controller
+    pub unsafe fn new(base: usize) -> Self {
+        let mut spi = Self {
+            base,
+            config: SpiConfig::default(),
+        };
+        let _ = spi.configure(SpiConfig::default());
+        spi
+    }
+
+    #[inline]
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    #[inline]
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// Compute the even clock divider for the requested rate.
+    fn divider_for(clock_hz: u32) -> Result<u32, Bcm2835SpiError> {
+        if clock_hz == 0 || clock_hz > CORE_CLOCK_HZ / 2 {
+            return Err(Bcm2835SpiError::InvalidClock);
+        }
+        let mut div = CORE_CLOCK_HZ.div_ceil(clock_hz);
+        div += div & 1;
+        if div > 65534 {
+            return Err(Bcm2835SpiError::InvalidClock);
+        }
+        Ok(div)
+    }
+}
+
+impl SpiBus for Bcm2835Spi {
+    type Error = Bcm2835SpiError;
+
+    fn configure(&mut self, config: SpiConfig) -> Result<(), Self::Error> {
+        if config.chip_select > 1 {
+            return Err(Bcm2835SpiError::InvalidChipSelect);
+        }
+        let div = Self::divider_for(config.clock_hz)?;
+
+        let mut cs = (config.chip_select as u32) & CS_CS_MASK;
+        if config.mode.cpha() {
+            cs |= CS_CPHA;
+        }
+        if config.mode.cpol() {
+            cs |= CS_CPOL;
+        }
+
+        self.write_reg(REG_CS, cs | CS_CLEAR_TX | CS_CLEAR_RX);
+        self.write_reg(REG_CLK, div);
+        self.config = config;
+        Ok(())
+    }
+
+    fn select(&mut self) {
+        let cs = self.read_reg(REG_CS);
+        self.write_reg(REG_CS, cs | CS_CLEAR_TX | CS_CLEAR_RX | CS_TA);
+    }
+
+    fn deselect(&mut self) {
+        let cs = self.read_reg(REG_CS);
+        self.write_reg(REG_CS, cs & !CS_TA);
+    }
+
+    fn transfer(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let len = buf.len();
+        let mut tx = 0;
+        let mut rx = 0;
+        let mut spins = 0;
+
+        while rx < len {
+            let cs = self.read_reg(REG_CS);
+            let mut progressed = false;
+
+            // Never get more than a FIFO's worth ahead of the receiver.
+            if tx < len && tx - rx < 16 && cs & CS_TXD != 0 {
+                self.write_reg(REG_FIFO, buf[tx] as u32);
+                tx += 1;
+                progressed = true;
+            }
+            if cs & CS_RXD != 0 {
+                buf[rx] = self.read_reg(REG_FIFO) as u8;
+                rx += 1;
+                progressed = true;
+            }
+
+            if progressed {
+                spins = 0;
+            } else {
+                spins += 1;
+                if spins > TIMEOUT {
+                    return Err(Bcm2835SpiError::Timeout);
+                }
+            }
+        }
+
+        for _ in 0..TIMEOUT {
+            if self.read_reg(REG_CS) & CS_DONE != 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(Bcm2835SpiError::Timeout)
+    }
+
+    /// The controller only clocks with TA set, which asserts the
+    /// chip-select line, so this switches to the unrouted CS2 meanwhile.
+    fn clock_idle(&mut self, count: usize) -> Result<(), Self::Error> {
+        let cs = self.read_reg(REG_CS) & !CS_TA;
+        self.write_reg(
+            REG_CS,
+            (cs & !CS_CS_MASK) | CS_UNUSED | CS_CLEAR_TX | CS_CLEAR_RX | CS_TA,
+        );
+        let result = (0..count).try_for_each(|_| self.transfer_byte(0xFF).map(drop));
+        self.write_reg(REG_CS, cs);
+        result
+    }
+}
+
+// SAFETY: the driver only touches its own MMIO block; callers serialise
+// access through the owning block device / device manager lock.
+unsafe impl Send for Bcm2835Spi {}
+unsafe impl Sync for Bcm2835Spi {}
@@ -199,6 +199,10 @@ impl Timer for Bcm2835Timer {
     fn is_pending(&self, handle: Self::Handle) -> Result<bool, Self::Error> {
         Ok(is_pending(handle))
     }
+
+    fn as_counting(&self) -> Option<&dyn DynCountingTimer> {
+        Some(self)
+    }
 }
 
 impl CountingTimer for Bcm2835Timer {
@@ -211,3 +215,27 @@ impl CountingTimer for Bcm2835Timer {
 // accessed from any thread when protected by synchronization.
 unsafe impl Send for Bcm2835Timer {}
 unsafe impl Sync for Bcm2835Timer {}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    BCM2835_TIMER_DRIVER,
+    crate::driver::Driver {
+        name: "bcm2835-system-timer",
+        compatible: &["brcm,bcm2835-system-timer"],
+        probe,
+    }
+);
+
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    let timer = unsafe { Bcm2835Timer::new(device.base_addr) }
+        .map_err(|e| alloc::format!("Timer init failed: {:?}", e))?;
+    // Channel 1 is the first channel not claimed by the GPU firmware.
+    device_mgr.register_timer(device.name, timer, Some(1))?;
+    Ok(())
+}
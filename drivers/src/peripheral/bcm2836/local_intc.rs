@@ -0,0 +1,84 @@
+//! BCM2836 ARM Local Peripherals
+//!
+//! The quad-core BCM2836 and BCM2837 add a block of per-core registers in
+//! front of the BCM2835 peripherals. Each core has four mailboxes: writing
+//! bits to a mailbox sets them and, if enabled, interrupts the core, which
+//! clears them by writing them back. They serve as inter-processor
+//! interrupts, and the firmware parks secondary cores waiting for a start
+//! address in their mailbox 3.
+
+use core::ptr::{read_volatile, write_volatile};
+
+/// Local peripherals base address.
+pub const LOCAL_BASE: usize = 0x4000_0000;
+
+/// Cores in the cluster.
+pub const CORES: usize = 4;
+
+/// Mailbox the firmware's secondary core spin loop reads.
+pub const BOOT_MAILBOX: usize = 3;
+
+// Register offsets, each followed by one register per core
+const REG_MAILBOX_IRQ_CTRL: usize = 0x50;
+const REG_IRQ_SOURCE: usize = 0x60;
+const REG_MAILBOX_SET: usize = 0x80;
+const REG_MAILBOX_CLEAR: usize = 0xC0;
+
+/// IRQ source bit of mailbox 0; the others follow
+const IRQ_SOURCE_MAILBOX0: u32 = 1 << 4;
+
+/// IRQ source bit of the GPU interrupt, routed to one core
+pub const IRQ_SOURCE_GPU: u32 = 1 << 8;
+
+#[inline(always)]
+fn per_core(reg: usize, core: usize) -> *mut u32 {
+    (LOCAL_BASE + reg + core * 4) as *mut u32
+}
+
+#[inline(always)]
+fn mailbox(reg: usize, core: usize, mailbox: usize) -> *mut u32 {
+    (LOCAL_BASE + reg + core * 0x10 + mailbox * 4) as *mut u32
+}
+
+/// Set `bits` in mailbox `mbox` of `core`, interrupting it if the mailbox
+/// interrupt is enabled.
+pub fn send(core: usize, mbox: usize, bits: u32) {
+    unsafe { write_volatile(mailbox(REG_MAILBOX_SET, core, mbox), bits) };
+}
+
+/// Read and clear mailbox `mbox` of `core`.
+pub fn take(core: usize, mbox: usize) -> u32 {
+    let reg = mailbox(REG_MAILBOX_CLEAR, core, mbox);
+    unsafe {
+        let bits = read_volatile(reg);
+        write_volatile(reg, bits);
+        bits
+    }
+}
+
+/// Let mailbox `mbox` of `core` raise an IRQ on that core.
+pub fn enable_mailbox_irq(core: usize, mbox: usize) {
+    let reg = per_core(REG_MAILBOX_IRQ_CTRL, core);
+    unsafe { write_volatile(reg, read_volatile(reg) | 1 << mbox) };
+}
+
+/// Pending IRQ sources of `core`.
+pub fn irq_source(core: usize) -> u32 {
+    unsafe { read_volatile(per_core(REG_IRQ_SOURCE, core)) }
+}
+
+/// Whether mailbox `mbox` has a pending IRQ in the sources `source`.
+pub fn mailbox_pending(source: u32, mbox: usize) -> bool {
+    source & IRQ_SOURCE_MAILBOX0 << mbox != 0
+}
+
+/// Release the parked secondary `core` to start at the physical address
+/// `entry`, with the MMU off.
+pub fn release_core(core: usize, entry: usize) {
+    send(core, BOOT_MAILBOX, entry as u32);
+    // The parked core waits for an event between polls
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        core::arch::asm!("sev", options(nomem, nostack, preserves_flags))
+    };
+}
@@ -0,0 +1,264 @@
+//! Text console on a framebuffer
+//!
+//! Draws text in an 8x8 font, scaled up on large screens so that a line
+//! of 80 columns fills a good part of the width, and scrolls when the
+//! last row is full. The console keeps only the cursor: the framebuffer
+//! is passed to each call, so its owner decides how it is locked.
+//!
+//! Understands `\n`, `\r`, `\t` and backspace; other bytes outside
+//! printable ASCII, such as each byte of a UTF-8 sequence, show as `?`.
+
+use crate::hal::fb::{FrameBuffer, color};
+
+/// Glyph size in font pixels
+const GLYPH_SIZE: usize = 8;
+
+/// Columns between tab stops
+const TAB_WIDTH: usize = 8;
+
+/// Cursor and colours of a text console
+#[derive(Debug, Clone)]
+pub struct FbConsole {
+    col: usize,
+    row: usize,
+    cols: usize,
+    rows: usize,
+    /// Screen pixels per font pixel
+    scale: usize,
+    fg: u32,
+    bg: u32,
+}
+
+impl FbConsole {
+    /// A console covering the whole of `fb`, light grey on black, with
+    /// the cursor at the top left. The screen is left as it is.
+    pub fn new(fb: &dyn FrameBuffer) -> Self {
+        let scale = (fb.width() / 640).clamp(1, 4);
+        let cell = GLYPH_SIZE * scale;
+        Self {
+            col: 0,
+            row: 0,
+            cols: fb.width() / cell,
+            rows: fb.height() / cell,
+            scale,
+            fg: color::LIGHT_GRAY,
+            bg: color::BLACK,
+        }
+    }
+
+    /// Size in characters: columns and rows
+    pub fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    /// Set the text and background colours of the text written next.
+    pub fn set_colors(&mut self, fg: u32, bg: u32) {
+        self.fg = fg;
+        self.bg = bg;
+    }
+
+    /// Fill the screen with the background colour and home the cursor.
+    pub fn clear(&mut self, fb: &mut dyn FrameBuffer) {
+        fb.clear(self.bg);
+        self.col = 0;
+        self.row = 0;
+    }
+
+    /// Move the cursor, clamped to the screen.
+    pub fn set_cursor(&mut self, col: usize, row: usize) {
+        self.col = col.min(self.cols.saturating_sub(1));
+        self.row = row.min(self.rows.saturating_sub(1));
+    }
+
+    pub fn write_str(&mut self, fb: &mut dyn FrameBuffer, s: &str) {
+        for byte in s.bytes() {
+            self.write_byte(fb, byte);
+        }
+    }
+
+    pub fn write_byte(&mut self, fb: &mut dyn FrameBuffer, byte: u8) {
+        if self.cols == 0 || self.rows == 0 {
+            return;
+        }
+        match byte {
+            b'\n' => self.new_line(fb),
+            b'\r' => self.col = 0,
+            b'\t' => {
+                let stop = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.col < stop.min(self.cols) {
+                    self.put(fb, b' ');
+                }
+            }
+            0x08 => {
+                if self.col > 0 {
+                    self.col -= 1;
+                    self.draw_glyph(fb, b' ');
+                }
+            }
+            _ => self.put(fb, byte),
+        }
+    }
+
+    /// Draw `byte` at the cursor and advance, wrapping at the right edge
+    fn put(&mut self, fb: &mut dyn FrameBuffer, byte: u8) {
+        if self.col >= self.cols {
+            self.new_line(fb);
+        }
+        self.draw_glyph(fb, byte);
+        self.col += 1;
+    }
+
+    fn new_line(&mut self, fb: &mut dyn FrameBuffer) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll(fb);
+        }
+    }
+
+    /// Move every row up by one and blank the last
+    fn scroll(&mut self, fb: &mut dyn FrameBuffer) {
+        let cell = GLYPH_SIZE * self.scale;
+        let line = fb.pitch() * cell;
+        let base = fb.buffer_ptr();
+        // SAFETY: both ranges lie within the first `rows` text rows, which
+        // fit in the buffer; `copy` allows them to overlap
+        unsafe { core::ptr::copy(base.add(line), base, line * (self.rows - 1)) };
+        fb.draw_rect(
+            0,
+            ((self.rows - 1) * cell) as u32,
+            (self.cols * cell) as u32,
+            cell as u32,
+            self.bg,
+        );
+    }
+
+    /// Draw `byte` in the cell under the cursor
+    fn draw_glyph(&mut self, fb: &mut dyn FrameBuffer, byte: u8) {
+        let glyph = match byte {
+            0x20..=0x7E => &FONT[(byte - 0x20) as usize],
+            _ => &FONT[(b'?' - 0x20) as usize],
+        };
+        let scale = self.scale as u32;
+        let x = (self.col * GLYPH_SIZE * self.scale) as u32;
+        let y = (self.row * GLYPH_SIZE * self.scale) as u32;
+        let cell = GLYPH_SIZE as u32 * scale;
+        fb.draw_rect(x, y, cell, cell, self.bg);
+        for (dy, bits) in glyph.iter().enumerate() {
+            for dx in 0..GLYPH_SIZE {
+                if bits & (1 << dx) != 0 {
+                    fb.draw_rect(
+                        x + dx as u32 * scale,
+                        y + dy as u32 * scale,
+                        scale,
+                        scale,
+                        self.fg,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Printable ASCII from space to `~`, one byte per row, top first, with
+/// the least significant bit the leftmost pixel. Public domain, from
+/// font8x8_basic.
+#[rustfmt::skip]
+const FONT: [[u8; GLYPH_SIZE]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // '#'
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // '%'
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '''
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // '('
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // '0'
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // '1'
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // '2'
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // '3'
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // '4'
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // '5'
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // '6'
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // '7'
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // '8'
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ';'
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // '='
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // '>'
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // '?'
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // '@'
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // 'A'
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // 'B'
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // 'C'
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // 'D'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // 'E'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // 'F'
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // 'L'
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // 'O'
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // 'P'
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // 'Q'
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // 'S'
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // 'Y'
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // 'Z'
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // '['
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // '\'
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ']'
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // '_'
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // 'b'
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // 'd'
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // 'e'
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // 'f'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'g'
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // 'k'
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // 'o'
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // 'p'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // 'r'
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // 's'
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'y'
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // 'z'
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // '}'
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
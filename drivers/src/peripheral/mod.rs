@@ -1,3 +1,8 @@
 pub mod arm;
 pub mod bcm2835;
+#[cfg(feature = "bcm2836")]
+pub mod bcm2836;
+pub mod fb_console;
+pub mod rtc;
+pub mod spi_sd;
 pub mod x86;
@@ -0,0 +1,172 @@
+//! Maxim DS3231 temperature-compensated RTC.
+//!
+//! Time is kept in 24-hour mode. The century bit in the month register
+//! extends the range to 2000–2199. Alarm 1 is used for [`RtcAlarm`] and
+//! matches on date, hour, minute and second.
+
+use super::{I2cRtcError, RTC_I2C_ADDR, bcd_to_bin, bin_to_bcd, check_range};
+use crate::hal::i2c::I2cBus;
+use crate::hal::rtc::{DateTime, DynRtcAlarm, Rtc, RtcAlarm};
+
+/// Register map
+const REG_SECONDS: u8 = 0x00;
+const REG_ALARM1: u8 = 0x07;
+const REG_CONTROL: u8 = 0x0E;
+const REG_STATUS: u8 = 0x0F;
+
+/// Hour register: 12-hour mode select / PM flag
+const HOUR_12H: u8 = 1 << 6;
+const HOUR_PM: u8 = 1 << 5;
+
+/// Month register: century flag
+const MONTH_CENTURY: u8 = 1 << 7;
+
+/// Control register bits
+const CONTROL_A1IE: u8 = 1 << 0;
+const CONTROL_INTCN: u8 = 1 << 2;
+
+/// Status register bits
+const STATUS_A1F: u8 = 1 << 0;
+const STATUS_OSF: u8 = 1 << 7;
+
+pub struct Ds3231<B: I2cBus> {
+    bus: B,
+}
+
+impl<B: I2cBus> Ds3231<B> {
+    pub fn new(bus: B) -> Self {
+        Self { bus }
+    }
+
+    /// Release the underlying bus.
+    pub fn into_inner(self) -> B {
+        self.bus
+    }
+
+    fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), I2cRtcError> {
+        self.bus
+            .write_read(RTC_I2C_ADDR, &[reg], buf)
+            .map_err(|e| I2cRtcError::Bus(e.into()))
+    }
+
+    fn write_regs(&mut self, bytes: &[u8]) -> Result<(), I2cRtcError> {
+        self.bus
+            .write(RTC_I2C_ADDR, bytes)
+            .map_err(|e| I2cRtcError::Bus(e.into()))
+    }
+
+    fn read_reg(&mut self, reg: u8) -> Result<u8, I2cRtcError> {
+        let mut v = [0u8];
+        self.read_regs(reg, &mut v)?;
+        Ok(v[0])
+    }
+
+    fn update_reg(&mut self, reg: u8, clear: u8, set: u8) -> Result<(), I2cRtcError> {
+        let v = self.read_reg(reg)?;
+        self.write_regs(&[reg, (v & !clear) | set])
+    }
+
+    fn decode_hour(raw: u8) -> u8 {
+        if raw & HOUR_12H != 0 {
+            let h = bcd_to_bin(raw & 0x1F) % 12;
+            if raw & HOUR_PM != 0 { h + 12 } else { h }
+        } else {
+            bcd_to_bin(raw & 0x3F)
+        }
+    }
+}
+
+impl<B: I2cBus> Rtc for Ds3231<B> {
+    type Error = I2cRtcError;
+
+    fn read_time(&mut self) -> Result<DateTime, Self::Error> {
+        if self.read_reg(REG_STATUS)? & STATUS_OSF != 0 {
+            return Err(I2cRtcError::ClockNotSet);
+        }
+
+        let mut r = [0u8; 7];
+        self.read_regs(REG_SECONDS, &mut r)?;
+
+        let century = if r[5] & MONTH_CENTURY != 0 { 100 } else { 0 };
+        Ok(DateTime {
+            year: 2000 + century + bcd_to_bin(r[6]) as u16,
+            month: bcd_to_bin(r[5] & 0x1F),
+            day: bcd_to_bin(r[4] & 0x3F),
+            hour: Self::decode_hour(r[2]),
+            minute: bcd_to_bin(r[1] & 0x7F),
+            second: bcd_to_bin(r[0] & 0x7F),
+        })
+    }
+
+    fn set_time(&mut self, time: &DateTime) -> Result<(), Self::Error> {
+        check_range(time, 2000, 2199)?;
+
+        let century = if time.year >= 2100 { MONTH_CENTURY } else { 0 };
+        self.write_regs(&[
+            REG_SECONDS,
+            bin_to_bcd(time.second),
+            bin_to_bcd(time.minute),
+            bin_to_bcd(time.hour),
+            time.weekday() + 1,
+            bin_to_bcd(time.day),
+            bin_to_bcd(time.month) | century,
+            bin_to_bcd((time.year % 100) as u8),
+        ])?;
+
+        // Time is valid again
+        self.update_reg(REG_STATUS, STATUS_OSF, 0)
+    }
+
+    fn as_alarm(&mut self) -> Option<&mut dyn DynRtcAlarm> {
+        Some(self)
+    }
+}
+
+impl<B: I2cBus> RtcAlarm for Ds3231<B> {
+    fn set_alarm(&mut self, time: &DateTime) -> Result<(), Self::Error> {
+        check_range(time, 2000, 2199)?;
+
+        // A1M1..A1M4 = 0, DY/DT = 0: match date, hours, minutes, seconds
+        self.write_regs(&[
+            REG_ALARM1,
+            bin_to_bcd(time.second),
+            bin_to_bcd(time.minute),
+            bin_to_bcd(time.hour),
+            bin_to_bcd(time.day),
+        ])?;
+        self.update_reg(REG_STATUS, STATUS_A1F, 0)?;
+        self.update_reg(REG_CONTROL, 0, CONTROL_INTCN | CONTROL_A1IE)
+    }
+
+    fn clear_alarm(&mut self) -> Result<(), Self::Error> {
+        self.update_reg(REG_CONTROL, CONTROL_A1IE, 0)?;
+        self.update_reg(REG_STATUS, STATUS_A1F, 0)
+    }
+
+    fn alarm_pending(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read_reg(REG_STATUS)? & STATUS_A1F != 0)
+    }
+}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    DS3231_DRIVER,
+    crate::driver::Driver {
+        name: "ds3231",
+        compatible: &["maxim,ds3231"],
+        probe,
+    }
+);
+
+/// `base_addr` is the I2C controller the chip hangs off.
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    let i2c = unsafe { crate::peripheral::bcm2835::i2c::Bcm2835I2c::new(device.base_addr) };
+    device_mgr.register_rtc(device.name, Ds3231::new(i2c))?;
+    Ok(())
+}
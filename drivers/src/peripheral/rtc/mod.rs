@@ -0,0 +1,53 @@
+//! I2C real-time clock chips.
+//!
+//! Drivers for the battery-backed RTCs found on common Raspberry Pi HATs.
+//! Both chips sit at I2C address `0x68` and store time as BCD.
+
+pub mod ds3231;
+pub mod pcf8523;
+
+use crate::hal::i2c::I2cError;
+use crate::hal::rtc::{DateTime, RtcError};
+
+/// 7-bit I2C address shared by the DS3231 and PCF8523.
+pub const RTC_I2C_ADDR: u8 = 0x68;
+
+/// Errors shared by the I2C RTC drivers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum I2cRtcError {
+    /// I2C transfer failed
+    Bus(I2cError),
+    /// Date/time outside the chip's range
+    InvalidTime,
+    /// Oscillator-stop flag set; time must be set again
+    ClockNotSet,
+}
+
+impl From<I2cRtcError> for RtcError {
+    fn from(err: I2cRtcError) -> Self {
+        match err {
+            I2cRtcError::Bus(_) => RtcError::Bus,
+            I2cRtcError::InvalidTime => RtcError::InvalidTime,
+            I2cRtcError::ClockNotSet => RtcError::ClockNotSet,
+        }
+    }
+}
+
+#[inline]
+fn bcd_to_bin(v: u8) -> u8 {
+    (v >> 4) * 10 + (v & 0x0F)
+}
+
+#[inline]
+fn bin_to_bcd(v: u8) -> u8 {
+    ((v / 10) << 4) | (v % 10)
+}
+
+/// Reject times the chip cannot hold (years outside `min_year..=max_year`).
+fn check_range(time: &DateTime, min_year: u16, max_year: u16) -> Result<(), I2cRtcError> {
+    if time.is_valid() && (min_year..=max_year).contains(&time.year) {
+        Ok(())
+    } else {
+        Err(I2cRtcError::InvalidTime)
+    }
+}
@@ -0,0 +1,180 @@
+//! NXP PCF8523 RTC (Adafruit PiRTC and similar HATs).
+//!
+//! Time is kept in 24-hour mode for years 2000–2099. The alarm has
+//! minute resolution and matches on day, hour and minute.
+
+use super::{I2cRtcError, RTC_I2C_ADDR, bcd_to_bin, bin_to_bcd, check_range};
+use crate::hal::i2c::I2cBus;
+use crate::hal::rtc::{DateTime, DynRtcAlarm, Rtc, RtcAlarm};
+
+/// Register map
+const REG_CONTROL_1: u8 = 0x00;
+const REG_CONTROL_2: u8 = 0x01;
+const REG_CONTROL_3: u8 = 0x02;
+const REG_SECONDS: u8 = 0x03;
+const REG_MINUTE_ALARM: u8 = 0x0A;
+
+/// Control_1: alarm interrupt enable, 12-hour mode
+const CONTROL_1_AIE: u8 = 1 << 1;
+const CONTROL_1_12_24: u8 = 1 << 3;
+
+/// Control_2: alarm flag (cleared by writing 0)
+const CONTROL_2_AF: u8 = 1 << 3;
+
+/// Control_3: power management field; 0b000 = battery switch-over in
+/// standard mode with low-battery detection
+const CONTROL_3_PM_MASK: u8 = 0b111 << 5;
+
+/// Seconds register: oscillator stopped
+const SECONDS_OS: u8 = 1 << 7;
+
+/// Alarm registers: alarm disabled for this field
+const ALARM_DISABLE: u8 = 1 << 7;
+
+pub struct Pcf8523<B: I2cBus> {
+    bus: B,
+}
+
+impl<B: I2cBus> Pcf8523<B> {
+    pub fn new(bus: B) -> Self {
+        Self { bus }
+    }
+
+    /// Enable battery switch-over and 24-hour mode.
+    ///
+    /// The chip powers up with switch-over disabled, so without this the
+    /// time is lost whenever main power goes away.
+    pub fn init(&mut self) -> Result<(), I2cRtcError> {
+        self.update_reg(REG_CONTROL_3, CONTROL_3_PM_MASK, 0)?;
+        self.update_reg(REG_CONTROL_1, CONTROL_1_12_24, 0)
+    }
+
+    /// Release the underlying bus.
+    pub fn into_inner(self) -> B {
+        self.bus
+    }
+
+    fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), I2cRtcError> {
+        self.bus
+            .write_read(RTC_I2C_ADDR, &[reg], buf)
+            .map_err(|e| I2cRtcError::Bus(e.into()))
+    }
+
+    fn write_regs(&mut self, bytes: &[u8]) -> Result<(), I2cRtcError> {
+        self.bus
+            .write(RTC_I2C_ADDR, bytes)
+            .map_err(|e| I2cRtcError::Bus(e.into()))
+    }
+
+    fn read_reg(&mut self, reg: u8) -> Result<u8, I2cRtcError> {
+        let mut v = [0u8];
+        self.read_regs(reg, &mut v)?;
+        Ok(v[0])
+    }
+
+    fn update_reg(&mut self, reg: u8, clear: u8, set: u8) -> Result<(), I2cRtcError> {
+        let v = self.read_reg(reg)?;
+        self.write_regs(&[reg, (v & !clear) | set])
+    }
+}
+
+impl<B: I2cBus> Rtc for Pcf8523<B> {
+    type Error = I2cRtcError;
+
+    fn read_time(&mut self) -> Result<DateTime, Self::Error> {
+        let mut r = [0u8; 7];
+        self.read_regs(REG_SECONDS, &mut r)?;
+
+        if r[0] & SECONDS_OS != 0 {
+            return Err(I2cRtcError::ClockNotSet);
+        }
+
+        // r[4] is the weekday; derived from the date instead
+        Ok(DateTime {
+            year: 2000 + bcd_to_bin(r[6]) as u16,
+            month: bcd_to_bin(r[5] & 0x1F),
+            day: bcd_to_bin(r[3] & 0x3F),
+            hour: bcd_to_bin(r[2] & 0x3F),
+            minute: bcd_to_bin(r[1] & 0x7F),
+            second: bcd_to_bin(r[0] & 0x7F),
+        })
+    }
+
+    fn set_time(&mut self, time: &DateTime) -> Result<(), Self::Error> {
+        check_range(time, 2000, 2099)?;
+
+        // Writing seconds with OS = 0 also clears the oscillator-stop flag
+        self.write_regs(&[
+            REG_SECONDS,
+            bin_to_bcd(time.second),
+            bin_to_bcd(time.minute),
+            bin_to_bcd(time.hour),
+            bin_to_bcd(time.day),
+            time.weekday(),
+            bin_to_bcd(time.month),
+            bin_to_bcd((time.year % 100) as u8),
+        ])
+    }
+
+    fn as_alarm(&mut self) -> Option<&mut dyn DynRtcAlarm> {
+        Some(self)
+    }
+}
+
+impl<B: I2cBus> RtcAlarm for Pcf8523<B> {
+    fn set_alarm(&mut self, time: &DateTime) -> Result<(), Self::Error> {
+        check_range(time, 2000, 2099)?;
+
+        self.write_regs(&[
+            REG_MINUTE_ALARM,
+            bin_to_bcd(time.minute),
+            bin_to_bcd(time.hour),
+            bin_to_bcd(time.day),
+            ALARM_DISABLE, // weekday
+        ])?;
+        self.update_reg(REG_CONTROL_2, CONTROL_2_AF, 0)?;
+        self.update_reg(REG_CONTROL_1, 0, CONTROL_1_AIE)
+    }
+
+    fn clear_alarm(&mut self) -> Result<(), Self::Error> {
+        self.update_reg(REG_CONTROL_1, CONTROL_1_AIE, 0)?;
+        self.write_regs(&[
+            REG_MINUTE_ALARM,
+            ALARM_DISABLE,
+            ALARM_DISABLE,
+            ALARM_DISABLE,
+            ALARM_DISABLE,
+        ])?;
+        self.update_reg(REG_CONTROL_2, CONTROL_2_AF, 0)
+    }
+
+    fn alarm_pending(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read_reg(REG_CONTROL_2)? & CONTROL_2_AF != 0)
+    }
+}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    PCF8523_DRIVER,
+    crate::driver::Driver {
+        name: "pcf8523",
+        compatible: &["nxp,pcf8523"],
+        probe,
+    }
+);
+
+/// `base_addr` is the I2C controller the chip hangs off.
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    let i2c = unsafe { crate::peripheral::bcm2835::i2c::Bcm2835I2c::new(device.base_addr) };
+    let mut rtc = Pcf8523::new(i2c);
+    rtc.init()
+        .map_err(|e| alloc::format!("PCF8523 init failed: {:?}", e))?;
+    device_mgr.register_rtc(device.name, rtc)?;
+    Ok(())
+}
@@ -0,0 +1,436 @@
+//! SD card over SPI
+//!
+//! Implements the SD "SPI mode" protocol on top of any [`SpiBus`], for
+//! setups where the native EMMC/SDHOST controller is unavailable (e.g. an
+//! SD breakout wired to the SPI header). Slower than the 4-bit bus, but
+//! needs nothing beyond a working SPI master.
+//!
+//! Only single-block commands (CMD17/CMD24) are used; multi-block
+//! requests are split into one command per block.
+
+use spin::Mutex;
+
+use crate::hal::block_device::{
+    BlockDevice, BlockDeviceError, BlockDeviceInfo, CardType, Cid, Csd, CsdParseError,
+    IdentifiableBlockDevice,
+};
+use crate::hal::spi::{SpiBus, SpiConfig, SpiError};
+
+/// Block size (fixed to 512 bytes)
+const BLOCK_SIZE: usize = 512;
+
+/// Clock used during card identification (spec maximum is 400 kHz).
+const INIT_CLOCK_HZ: u32 = 400_000;
+
+/// Clock used once the card is initialised (default-speed limit).
+const TRANSFER_CLOCK_HZ: u32 = 25_000_000;
+
+/// SD Commands
+const CMD0: u8 = 0; // GO_IDLE_STATE
+const CMD8: u8 = 8; // SEND_IF_COND
+const CMD9: u8 = 9; // SEND_CSD
+const CMD10: u8 = 10; // SEND_CID
+const CMD16: u8 = 16; // SET_BLOCKLEN
+const CMD17: u8 = 17; // READ_SINGLE_BLOCK
+const CMD24: u8 = 24; // WRITE_BLOCK
+const CMD55: u8 = 55; // APP_CMD
+const CMD58: u8 = 58; // READ_OCR
+const ACMD41: u8 = 41; // SD_SEND_OP_COND
+
+/// R1 response bits
+const R1_IDLE: u8 = 1 << 0;
+const R1_ILLEGAL_COMMAND: u8 = 1 << 2;
+
+/// Data tokens
+const TOKEN_START_BLOCK: u8 = 0xFE;
+const DATA_RESPONSE_MASK: u8 = 0x1F;
+const DATA_RESPONSE_ACCEPTED: u8 = 0x05;
+
+/// OCR: Card Capacity Status (block addressing)
+const OCR_CCS: u32 = 1 << 30;
+
+/// Polling limits, in bytes clocked.
+const RESPONSE_RETRIES: usize = 16;
+const TOKEN_RETRIES: usize = 100_000;
+const BUSY_RETRIES: usize = 500_000;
+const INIT_RETRIES: usize = 10_000;
+
+// ============================================================================
+// Error Type
+// ============================================================================
+
+/// SPI SD card errors
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpiSdError {
+    /// Card did not answer CMD0
+    NoCard,
+    /// Unsupported or unrecognized card
+    UnsupportedCard,
+    /// Card initialization failed
+    InitFailed,
+    /// Card returned an error in its R1 response
+    CommandError,
+    /// Operation timed out
+    Timeout,
+    /// Buffer size is too small
+    BufferTooSmall,
+    /// Block address outside the card
+    InvalidAddress,
+    /// Read operation failed
+    ReadError,
+    /// Write rejected by the card
+    WriteError,
+    /// Underlying SPI bus error
+    Bus(SpiError),
+}
+
+impl From<SpiSdError> for BlockDeviceError {
+    fn from(err: SpiSdError) -> Self {
+        match err {
+            SpiSdError::NoCard => BlockDeviceError::DeviceRemoved,
+            SpiSdError::UnsupportedCard => BlockDeviceError::UnsupportedDevice,
+            SpiSdError::InitFailed => BlockDeviceError::NotReady,
+            SpiSdError::Timeout => BlockDeviceError::Timeout,
+            SpiSdError::BufferTooSmall => BlockDeviceError::InvalidBuffer,
+            SpiSdError::InvalidAddress => BlockDeviceError::InvalidAddress,
+            SpiSdError::ReadError => BlockDeviceError::ReadError,
+            SpiSdError::WriteError => BlockDeviceError::WriteError,
+            SpiSdError::CommandError | SpiSdError::Bus(_) => BlockDeviceError::IoError,
+        }
+    }
+}
+
+impl From<CsdParseError> for SpiSdError {
+    fn from(_err: CsdParseError) -> Self {
+        SpiSdError::UnsupportedCard
+    }
+}
+
+// ============================================================================
+// Driver
+// ============================================================================
+
+/// SD card attached to an SPI bus.
+pub struct SpiSd<S: SpiBus> {
+    bus: Mutex<S>,
+    cid: Cid,
+    csd: Csd,
+    card_type: CardType,
+    /// SDHC/SDXC cards are addressed in blocks, SDSC in bytes.
+    block_addressing: bool,
+    initialized: bool,
+}
+
+impl<S: SpiBus> SpiSd<S> {
+    /// Wrap an SPI bus. Call [`SpiSd::init`] before doing any I/O.
+    pub fn new(bus: S) -> Self {
+        Self {
+            bus: Mutex::new(bus),
+            cid: Cid::default(),
+            csd: Csd::default(),
+            card_type: CardType::Unknown,
+            block_addressing: false,
+            initialized: false,
+        }
+    }
+
+    /// Detected card type.
+    pub fn card_type(&self) -> CardType {
+        self.card_type
+    }
+
+    /// Run the SPI-mode initialisation sequence and read CID/CSD.
+    pub fn init(&mut self) -> Result<(), SpiSdError> {
+        let bus = self.bus.get_mut();
+        bus.configure(SpiConfig::new(INIT_CLOCK_HZ))
+            .map_err(|e| SpiSdError::Bus(e.into()))?;
+
+        // ≥74 clocks with CS high to enter native → SPI mode.
+        bus.clock_idle(10).map_err(|e| SpiSdError::Bus(e.into()))?;
+
+        bus.select();
+        let result = Self::identify(bus);
+        Self::release(bus);
+        let (card_type, block_addressing, cid, csd) = result?;
+
+        bus.configure(SpiConfig::new(TRANSFER_CLOCK_HZ))
+            .map_err(|e| SpiSdError::Bus(e.into()))?;
+
+        self.card_type = card_type;
+        self.block_addressing = block_addressing;
+        self.cid = cid;
+        self.csd = csd;
+        self.initialized = true;
+        Ok(())
+    }
+
+    fn identify(bus: &mut S) -> Result<(CardType, bool, Cid, Csd), SpiSdError> {
+        // CMD0: GO_IDLE_STATE (fixed CRC; SPI mode ignores CRC afterwards)
+        if Self::command(bus, CMD0, 0)? != R1_IDLE {
+            return Err(SpiSdError::NoCard);
+        }
+
+        // CMD8: voltage check, 2.7-3.6V + pattern 0xAA
+        let r1 = Self::command(bus, CMD8, 0x1AA)?;
+        let card_type = if r1 & R1_ILLEGAL_COMMAND != 0 {
+            CardType::SDv1
+        } else {
+            let r7 = Self::read_u32(bus)?;
+            if r7 & 0xFFF != 0x1AA {
+                return Err(SpiSdError::UnsupportedCard);
+            }
+            CardType::SDv2
+        };
+
+        // ACMD41 until the card leaves the idle state
+        let hcs = if card_type == CardType::SDv2 {
+            OCR_CCS
+        } else {
+            0
+        };
+        let mut ready = false;
+        for _ in 0..INIT_RETRIES {
+            Self::command(bus, CMD55, 0)?;
+            if Self::command(bus, ACMD41, hcs)? == 0 {
+                ready = true;
+                break;
+            }
+        }
+        if !ready {
+            return Err(SpiSdError::InitFailed);
+        }
+
+        let block_addressing = if card_type == CardType::SDv2 {
+            if Self::command(bus, CMD58, 0)? != 0 {
+                return Err(SpiSdError::CommandError);
+            }
+            Self::read_u32(bus)? & OCR_CCS != 0
+        } else {
+            false
+        };
+
+        if !block_addressing && Self::command(bus, CMD16, BLOCK_SIZE as u32)? != 0 {
+            return Err(SpiSdError::CommandError);
+        }
+
+        let mut raw = [0u8; 16];
+        if Self::command(bus, CMD10, 0)? != 0 {
+            return Err(SpiSdError::CommandError);
+        }
+        Self::read_data(bus, &mut raw)?;
+        let cid = Cid::parse(&raw);
+
+        if Self::command(bus, CMD9, 0)? != 0 {
+            return Err(SpiSdError::CommandError);
+        }
+        Self::read_data(bus, &mut raw)?;
+        let csd = Csd::parse(&raw)?;
+
+        Ok((card_type, block_addressing, cid, csd))
+    }
+
+    /// Send a command frame and return its R1 response.
+    fn command(bus: &mut S, cmd: u8, arg: u32) -> Result<u8, SpiSdError> {
+        let crc = match cmd {
+            CMD0 => 0x95,
+            CMD8 => 0x87,
+            _ => 0x01,
+        };
+        let a = arg.to_be_bytes();
+        Self::wait_not_busy(bus)?;
+        bus.write(&[0x40 | cmd, a[0], a[1], a[2], a[3], crc])
+            .map_err(|e| SpiSdError::Bus(e.into()))?;
+
+        for _ in 0..RESPONSE_RETRIES {
+            let r1 = Self::xfer(bus, 0xFF)?;
+            if r1 & 0x80 == 0 {
+                return Ok(r1);
+            }
+        }
+        Err(SpiSdError::Timeout)
+    }
+
+    /// Read the 4 trailing bytes of an R3/R7 response.
+    fn read_u32(bus: &mut S) -> Result<u32, SpiSdError> {
+        let mut b = [0u8; 4];
+        bus.read(&mut b, 0xFF)
+            .map_err(|e| SpiSdError::Bus(e.into()))?;
+        Ok(u32::from_be_bytes(b))
+    }
+
+    /// Wait for a start-block token, then read `buf` plus the 16-bit CRC.
+    fn read_data(bus: &mut S, buf: &mut [u8]) -> Result<(), SpiSdError> {
+        let mut token = 0xFF;
+        for _ in 0..TOKEN_RETRIES {
+            token = Self::xfer(bus, 0xFF)?;
+            if token != 0xFF {
+                break;
+            }
+        }
+        if token != TOKEN_START_BLOCK {
+            return Err(if token == 0xFF {
+                SpiSdError::Timeout
+            } else {
+                SpiSdError::ReadError
+            });
+        }
+
+        bus.read(buf, 0xFF).map_err(|e| SpiSdError::Bus(e.into()))?;
+        let mut crc = [0u8; 2];
+        bus.read(&mut crc, 0xFF)
+            .map_err(|e| SpiSdError::Bus(e.into()))?;
+        Ok(())
+    }
+
+    /// The card holds DO low while it is programming.
+    fn wait_not_busy(bus: &mut S) -> Result<(), SpiSdError> {
+        for _ in 0..BUSY_RETRIES {
+            if Self::xfer(bus, 0xFF)? == 0xFF {
+                return Ok(());
+            }
+        }
+        Err(SpiSdError::Timeout)
+    }
+
+    fn xfer(bus: &mut S, byte: u8) -> Result<u8, SpiSdError> {
+        bus.transfer_byte(byte)
+            .map_err(|e| SpiSdError::Bus(e.into()))
+    }
+
+    /// Deselect and clock one extra byte so the card releases DO.
+    fn release(bus: &mut S) {
+        let _ = bus.clock_idle(1);
+    }
+
+    fn address(&self, block: u64) -> u32 {
+        if self.block_addressing {
+            block as u32
+        } else {
+            (block * BLOCK_SIZE as u64) as u32
+        }
+    }
+
+    fn read_block_internal(&self, block: u64, buf: &mut [u8]) -> Result<(), SpiSdError> {
+        let mut bus = self.bus.lock();
+        bus.select();
+        let result = match Self::command(&mut bus, CMD17, self.address(block)) {
+            Ok(0) => Self::read_data(&mut bus, &mut buf[..BLOCK_SIZE]),
+            Ok(_) => Err(SpiSdError::ReadError),
+            Err(e) => Err(e),
+        };
+        Self::release(&mut bus);
+        result
+    }
+
+    fn write_block_internal(&self, block: u64, buf: &[u8]) -> Result<(), SpiSdError> {
+        let mut bus = self.bus.lock();
+        bus.select();
+        let result = Self::write_data(&mut bus, self.address(block), &buf[..BLOCK_SIZE]);
+        Self::release(&mut bus);
+        result
+    }
+
+    fn write_data(bus: &mut S, addr: u32, data: &[u8]) -> Result<(), SpiSdError> {
+        if Self::command(bus, CMD24, addr)? != 0 {
+            return Err(SpiSdError::WriteError);
+        }
+        bus.write(&[0xFF, TOKEN_START_BLOCK])
+            .map_err(|e| SpiSdError::Bus(e.into()))?;
+        bus.write(data).map_err(|e| SpiSdError::Bus(e.into()))?;
+        bus.write(&[0xFF, 0xFF])
+            .map_err(|e| SpiSdError::Bus(e.into()))?;
+
+        let response = Self::xfer(bus, 0xFF)?;
+        if response & DATA_RESPONSE_MASK != DATA_RESPONSE_ACCEPTED {
+            return Err(SpiSdError::WriteError);
+        }
+        Self::wait_not_busy(bus)
+    }
+
+    fn check_request(&self, start_block: u64, count: usize) -> Result<(), SpiSdError> {
+        if !self.initialized {
+            return Err(SpiSdError::InitFailed);
+        }
+        if start_block + count as u64 > self.csd.block_count() {
+            return Err(SpiSdError::InvalidAddress);
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// HAL Implementation
+// ============================================================================
+
+impl<S: SpiBus> BlockDevice for SpiSd<S> {
+    type Error = SpiSdError;
+
+    fn info(&self) -> BlockDeviceInfo {
+        BlockDeviceInfo::new(self.csd.block_count()).removable()
+    }
+
+    fn read_blocks(&self, start_block: u64, buffers: &mut [&mut [u8]]) -> Result<(), Self::Error> {
+        if buffers.iter().any(|b| b.len() < BLOCK_SIZE) {
+            return Err(SpiSdError::BufferTooSmall);
+        }
+        self.check_request(start_block, buffers.len())?;
+
+        for (i, buf) in buffers.iter_mut().enumerate() {
+            self.read_block_internal(start_block + i as u64, buf)?;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, start_block: u64, buffers: &[&[u8]]) -> Result<(), Self::Error> {
+        if buffers.iter().any(|b| b.len() < BLOCK_SIZE) {
+            return Err(SpiSdError::BufferTooSmall);
+        }
+        self.check_request(start_block, buffers.len())?;
+
+        for (i, buf) in buffers.iter().enumerate() {
+            self.write_block_internal(start_block + i as u64, buf)?;
+        }
+        Ok(())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.initialized
+    }
+}
+
+impl<S: SpiBus> IdentifiableBlockDevice for SpiSd<S> {
+    fn cid(&self) -> Option<&Cid> {
+        self.initialized.then_some(&self.cid)
+    }
+
+    fn csd(&self) -> Option<&Csd> {
+        self.initialized.then_some(&self.csd)
+    }
+}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    SPI_SD_DRIVER,
+    crate::driver::Driver {
+        name: "mmc-spi",
+        compatible: &["mmc-spi-slot"],
+        probe,
+    }
+);
+
+/// SD card on the SPI header; `base_addr` is the SPI controller.
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    let spi = unsafe { crate::peripheral::bcm2835::spi::Bcm2835Spi::new(device.base_addr) };
+    let mut block_dev = SpiSd::new(spi);
+    block_dev
+        .init()
+        .map_err(|e| alloc::format!("SPI SD init failed: {:?}", e))?;
+    device_mgr.register_block(device.name, block_dev)?;
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use crate::hal::timer::{CountingTimer, PeriodicTimer, Timer, TimerError};
+use crate::hal::timer::{CountingTimer, DynCountingTimer, PeriodicTimer, Timer, TimerError};
 
 const PIT_CHANNEL_0: u16 = 0x40;
 const PIT_CHANNEL_1: u16 = 0x41;
@@ -126,6 +126,10 @@ impl Timer for I8254PIT {
             Ok((status & 0x80) != 0)
         }
     }
+
+    fn as_counting(&self) -> Option<&dyn DynCountingTimer> {
+        Some(self)
+    }
 }
 
 impl PeriodicTimer for I8254PIT {
@@ -159,3 +163,24 @@ impl CountingTimer for I8254PIT {
 
 unsafe impl Send for I8254PIT {}
 unsafe impl Sync for I8254PIT {}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    I8254_PIT_DRIVER,
+    crate::driver::Driver {
+        name: "i8254-pit",
+        compatible: &["i8254-pit", "intel,8254"],
+        probe,
+    }
+);
+
+/// The PIT is programmed directly by the x86 arch layer; nothing to bind.
+unsafe fn probe(
+    _device: &crate::platform::DeviceInfo,
+    _device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    Ok(())
+}
@@ -28,7 +28,7 @@
 // Only RGB (type 1) is wired up; calling `new` with another type
 // returns `Err(FrameBufferError::NotSupported)`.
 
-use spin::Once;
+use common::sync::Once;
 
 use crate::hal::fb::{FrameBuffer, FrameBufferError, FrameBufferInfo, PixelFormat};
 
@@ -532,3 +532,24 @@ pub static MB2_FB_TAG: Once<Mb2FbTag> = Once::new();
 pub fn set_mb2_fb_tag(tag: Mb2FbTag) {
     MB2_FB_TAG.call_once(|| tag);
 }
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    MB2FB_DRIVER,
+    crate::driver::Driver {
+        name: "multiboot2-fb",
+        compatible: &["multiboot2-fb", "simple-framebuffer"],
+        probe,
+    }
+);
+
+/// Ignored for early boot; [`Mb2Fb`] consumes [`MB2_FB_TAG`] directly during its own init.
+unsafe fn probe(
+    _device: &crate::platform::DeviceInfo,
+    _device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    Ok(())
+}
@@ -1,5 +1,7 @@
 pub mod i8254_pit;
 pub mod mb2fb;
 pub mod pic8259;
+#[cfg(target_arch = "x86")]
+pub mod power;
 pub mod uart16550;
 pub mod vga_text;
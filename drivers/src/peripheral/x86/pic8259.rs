@@ -0,0 +1,22 @@
+//
+// Intel 8259 Programmable Interrupt Controller.
+//
+// The PIC pair is remapped and masked by the x86 arch layer during early
+// boot; this module only claims the platform entry so it is not reported
+// as an unknown device.
+
+crate::register_driver!(
+    PIC8259_DRIVER,
+    crate::driver::Driver {
+        name: "i8259-pic",
+        compatible: &["i8259-pic", "intel,8259"],
+        probe,
+    }
+);
+
+unsafe fn probe(
+    _device: &crate::platform::DeviceInfo,
+    _device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    Ok(())
+}
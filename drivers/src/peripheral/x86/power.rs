@@ -0,0 +1,54 @@
+//! PC reset and power-off
+//!
+//! A reset is asked of the 8042 keyboard controller, whose output port
+//! drives the CPU's reset line on every PC since the AT. Powering off
+//! properly takes ACPI, which is not supported; QEMU and Bochs have
+//! fixed ports that do it instead, so real hardware is halted.
+
+use x86::io::{inb, outb, outw};
+
+/// 8042 status register port.
+const I8042_STATUS: u16 = 0x64;
+/// 8042 command port.
+const I8042_COMMAND: u16 = 0x64;
+/// Status: the input buffer is full, so a command would be lost.
+const I8042_STATUS_INPUT_FULL: u8 = 1 << 1;
+/// Command: pulse the CPU reset line.
+const I8042_PULSE_RESET: u8 = 0xFE;
+
+/// Polls of the 8042 status before giving up on it.
+const I8042_SPINS: usize = 100_000;
+
+/// ACPI PM1a control ports of emulators, newest QEMU first, with the
+/// value that switches the machine off.
+const EMULATOR_POWER_OFF: [(u16, u16); 3] = [(0x604, 0x2000), (0xB004, 0x2000), (0x4004, 0x3400)];
+
+/// Reset the machine through the 8042. Returns if it does not respond.
+///
+/// # Safety
+///
+/// Resets the CPU: nothing that has not been saved survives.
+pub unsafe fn reset() {
+    for _ in 0..I8042_SPINS {
+        if unsafe { inb(I8042_STATUS) } & I8042_STATUS_INPUT_FULL == 0 {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    unsafe { outb(I8042_COMMAND, I8042_PULSE_RESET) };
+    for _ in 0..I8042_SPINS {
+        core::hint::spin_loop();
+    }
+}
+
+/// Switch the machine off, if it is an emulator that knows how. Returns
+/// on anything else.
+///
+/// # Safety
+///
+/// Writes to I/O ports that may belong to other devices on real hardware.
+pub unsafe fn power_off() {
+    for (port, value) in EMULATOR_POWER_OFF {
+        unsafe { outw(port, value) };
+    }
+}
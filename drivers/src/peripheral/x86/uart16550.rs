@@ -67,6 +67,8 @@ const LSR: usize = 5;
 // Bits
 const LCR_DLAB: u8 = 1 << 7;
 
+const IER_RDA: u8 = 1 << 0;
+
 const FCR_ENABLE: u8 = 1 << 0;
 const FCR_CLEAR_RX: u8 = 1 << 1;
 const FCR_CLEAR_TX: u8 = 1 << 2;
@@ -116,6 +118,8 @@ impl From<Uart16550Error> for SerialError {
 
 pub struct Uart16550<I: Io> {
     base: usize,
+    /// Receive interrupt wanted; kept across `configure`
+    rx_interrupt: bool,
     _io: PhantomData<I>,
 }
 
@@ -123,10 +127,15 @@ impl<I: Io> Uart16550<I> {
     pub const fn new(base: usize) -> Self {
         Self {
             base,
+            rx_interrupt: false,
             _io: PhantomData,
         }
     }
 
+    fn ier(&self) -> u8 {
+        if self.rx_interrupt { IER_RDA } else { 0 }
+    }
+
     #[inline]
     fn read_reg(&self, offset: usize) -> u8 {
         I::read8(self.base + offset)
@@ -196,6 +205,7 @@ impl<I: Io> SerialPort for Uart16550<I> {
 
         self.write_reg(FCR, FCR_ENABLE | FCR_CLEAR_RX | FCR_CLEAR_TX);
         self.write_reg(MCR, MCR_DTR | MCR_RTS | MCR_OUT2);
+        self.write_reg(IER, self.ier());
 
         Ok(())
     }
@@ -258,6 +268,12 @@ impl<I: Io> NonBlockingSerial for Uart16550<I> {
         Self::check_errors(lsr)?;
         Ok(self.read_reg(RBR))
     }
+
+    fn set_rx_interrupt(&mut self, enabled: bool) -> bool {
+        self.rx_interrupt = enabled;
+        self.write_reg(IER, self.ier());
+        true
+    }
 }
 
 // ============================================================================
@@ -266,3 +282,29 @@ impl<I: Io> NonBlockingSerial for Uart16550<I> {
 
 unsafe impl<I: Io> Send for Uart16550<I> {}
 unsafe impl<I: Io> Sync for Uart16550<I> {}
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    UART16550_DRIVER,
+    crate::driver::Driver {
+        name: "uart16550",
+        compatible: &["16550a-uart", "ns16550a"],
+        probe,
+    }
+);
+
+unsafe fn probe(
+    device: &crate::platform::DeviceInfo,
+    device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    // Port I/O on PCs, memory-mapped everywhere else.
+    #[cfg(target_arch = "x86")]
+    let uart = Uart16550::<Pio>::new(device.base_addr);
+    #[cfg(not(target_arch = "x86"))]
+    let uart = Uart16550::<Mmio>::new(device.base_addr);
+    device_mgr.register_serial(device.name, uart)?;
+    Ok(())
+}
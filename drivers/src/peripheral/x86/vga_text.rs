@@ -257,3 +257,25 @@ impl fmt::Write for VgaText {
         Ok(())
     }
 }
+
+// ============================================================================
+// Driver Registration
+// ============================================================================
+
+crate::register_driver!(
+    VGA_TEXT_DRIVER,
+    crate::driver::Driver {
+        name: "vga-text",
+        compatible: &["vga-text"],
+        probe,
+    }
+);
+
+/// VGA text console is initialized in subsystems::init — no
+/// device manager registration needed here.
+unsafe fn probe(
+    _device: &crate::platform::DeviceInfo,
+    _device_mgr: &mut crate::device_manager::DeviceManager,
+) -> Result<(), alloc::string::String> {
+    Ok(())
+}
@@ -6,8 +6,8 @@
 //! crate that depends on `drivers`) can reach them.
 
 use super::{
-    ARCH, Architecture, CMDLINE, DEVICE_COUNT, DEVICES, DeviceInfo, INITIALIZED, MAX_DEVICES,
-    MAX_MEMORY_REGIONS, MEMORY_REGION_COUNT, MEMORY_REGIONS, MemoryRegion, MemoryType,
+    ARCH, Architecture, CMDLINE, DEVICE_COUNT, DEVICES, DeviceInfo, INITIALIZED, INITRD,
+    MAX_DEVICES, MAX_MEMORY_REGIONS, MEMORY_REGION_COUNT, MEMORY_REGIONS, MemoryRegion, MemoryType,
     PLATFORM_NAME,
 };
 use core::sync::atomic::Ordering;
@@ -43,6 +43,13 @@ impl PlatformBuilder {
         }
     }
 
+    /// Record where the bootloader loaded the initial ramdisk.
+    pub fn set_initrd(base: usize, size: usize) {
+        unsafe {
+            INITRD = Some((base, size));
+        }
+    }
+
     /// Add a discovered device to the platform device table.
     ///
     /// Silently drops entries beyond [`MAX_DEVICES`].
@@ -13,7 +13,7 @@ pub mod builder;
 
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use alloc::{format, string::String};
+use alloc::string::String;
 // Re-export
 pub use builder::PlatformBuilder;
 
@@ -42,6 +42,10 @@ pub struct DeviceInfo {
     pub base_addr: usize,
     pub size: usize,
     pub irq: Option<u32>,
+    /// Frequency of the clock feeding the device, if the boot information
+    /// gives one (DTB `clock-frequency`, directly or on the clock `clocks`
+    /// points at)
+    pub clock_hz: Option<u32>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -77,6 +81,7 @@ pub(crate) static mut DEVICES: [Option<DeviceInfo>; MAX_DEVICES] = [const { None
 pub(crate) static mut DEVICE_COUNT: usize = 0;
 
 pub(crate) static mut CMDLINE: Option<&'static str> = None;
+pub(crate) static mut INITRD: Option<(usize, usize)> = None;
 pub(crate) static mut PLATFORM_NAME: &'static str = "Unknown";
 pub(crate) static mut ARCH: Architecture = Architecture::X86;
 
@@ -104,6 +109,11 @@ impl Platform {
         unsafe { CMDLINE }
     }
 
+    /// Initial ramdisk handed over by the bootloader, as `(base, size)`.
+    pub fn initrd() -> Option<(usize, usize)> {
+        unsafe { INITRD }
+    }
+
     pub fn memory_regions() -> &'static [MemoryRegion] {
         unsafe { &MEMORY_REGIONS[..MEMORY_REGION_COUNT] }
     }
@@ -163,83 +173,78 @@ impl Platform {
 
     /// Initialize and register all platform devices with the device manager.
     ///
+    /// Each discovered device is bound to its driver through the
+    /// [`crate::driver`] table; see [`DeviceManager::probe_devices`].
+    ///
     /// # Safety
     /// Must be called after `PlatformBuilder::begin()` and after memory
     /// management is initialized.
+    ///
+    /// [`DeviceManager::probe_devices`]: crate::device_manager::DeviceManager::probe_devices
     pub unsafe fn init_devices(
         device_mgr: &mut crate::device_manager::DeviceManager,
     ) -> Result<(), String> {
-        use crate::peripheral::x86::mb2fb::{MB2_FB_TAG, Mb2Fb};
-        use crate::peripheral::*;
+        unsafe { device_mgr.probe_devices(Self::devices()) }
+    }
 
-        unsafe {
-            for device in Self::devices() {
-                match device.compatible {
-                    //  UART
-                    "arm,pl011" | "arm,primecell" => {
-                        let uart = arm::pl011::Pl011::new(device.base_addr);
-                        device_mgr.register_serial(device.name, uart)?;
-                    }
-
-                    "16550a-uart" | "ns16550a" => {
-                        #[cfg(target_arch = "x86")]
-                        let uart =
-                            x86::uart16550::Uart16550::<x86::uart16550::Pio>::new(device.base_addr);
-                        #[cfg(not(target_arch = "x86"))]
-                        let uart = x86::uart16550::Uart16550::<x86::uart16550::Mmio>::new(
-                            device.base_addr,
-                        );
-                        device_mgr.register_serial(device.name, uart)?;
-                    }
-
-                    //  Timers
-                    "brcm,bcm2835-system-timer" => {
-                        let timer = bcm2835::timer::Bcm2835Timer::new(device.base_addr)
-                            .map_err(|e| format!("Timer init failed: {:?}", e))?;
-                        device_mgr.register_timer(device.name, timer, Some(1))?;
-                    }
-                    "arm,armv7-timer" | "arm,armv8-timer" => {}
-                    "i8254-pit" | "intel,8254" => {}
-
-                    //  Interrupt controllers
-                    "brcm,bcm2835-armctrl-ic" | "brcm,bcm2836-armctrl-ic" => {
-                        let intc = bcm2835::intc::Bcm2835InterruptController::new(device.base_addr);
-                        device_mgr.register_interrupt_controller(device.name, intc)?;
-                    }
-                    "arm,gic-400" | "arm,cortex-a15-gic" | "arm,gic-v3" => {}
-                    "i8259-pic" | "intel,8259" => {}
-
-                    //  Framebuffer
-                    "multiboot2-fb" | "simple-framebuffer" => {
-                        // Ignore for early boot, Mb2Fb will consume the MB2_FB_TAG directly during its own init.
-                    }
-
-                    //  Block devices
-                    "brcm,bcm2835-sdhost" | "brcm,bcm2711-emmc2" => {
-                        let block_dev = bcm2835::emmc::Emmc::new(device.base_addr)
-                            .map_err(|e| format!("Emmc init failed: {:?}", e))?;
-                        device_mgr.register_block(device.name, block_dev)?;
-                    }
-
-                    //  Consoles
-                    "vga-text" => {
-                        // VGA text console is initialized in subsystems::init — no
-                        // device manager registration needed here.
-                    }
-
-                    _ => {
-                        log::warn!(
-                            "Unknown device '{}' (compatible: '{}') at {:#x} (size: {:#x})",
-                            device.name,
-                            device.compatible,
-                            device.base_addr,
-                            device.size
-                        );
-                    }
+    /// Restart the machine: through the power management watchdog on the
+    /// Raspberry Pi, the keyboard controller on a PC. Halts if neither
+    /// works.
+    ///
+    /// Nothing is flushed first; that is up to the caller, who should
+    /// also have masked interrupts.
+    pub fn reboot() -> ! {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "arm")] {
+                use crate::peripheral::bcm2835::pm::{PM_OFFSET, Watchdog};
+
+                let base = Self::memory_map().peripheral_base + PM_OFFSET;
+                unsafe { Watchdog::with_base(base).reset() }
+            } else if #[cfg(target_arch = "x86")] {
+                unsafe { crate::peripheral::x86::power::reset() };
+                log::error!("Reset failed; halting");
+                Self::halt()
+            } else {
+                Self::halt()
+            }
+        }
+    }
+
+    /// Switch the machine off where software can, otherwise halt it.
+    ///
+    /// A Raspberry Pi cannot switch itself off, so its firmware-managed
+    /// devices are powered down (mailbox `SET_POWER_STATE`) and the CPU
+    /// halts; only pulling the power or a reset starts it again.
+    ///
+    /// Nothing is flushed first, as for [`reboot`](Self::reboot).
+    pub fn power_off() -> ! {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "arm")] {
+                use crate::peripheral::bcm2835::mailbox::{self, PowerDevice};
+
+                for device in PowerDevice::ALL {
+                    unsafe { mailbox::set_power_state(device, false) };
                 }
+            } else if #[cfg(target_arch = "x86")] {
+                unsafe { crate::peripheral::x86::power::power_off() };
             }
         }
+        Self::halt()
+    }
 
-        Ok(())
+    /// Stop the CPU for good, waiting for interrupts that are not taken.
+    /// Interrupts must be masked, or their handlers still run.
+    pub fn halt() -> ! {
+        loop {
+            cfg_if::cfg_if! {
+                if #[cfg(target_arch = "arm")] {
+                    unsafe { core::arch::asm!("wfi", options(nomem, nostack, preserves_flags)) }
+                } else if #[cfg(target_arch = "x86")] {
+                    unsafe { core::arch::asm!("hlt", options(nomem, nostack, preserves_flags)) }
+                } else {
+                    core::hint::spin_loop();
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,13 @@
+//! Stack backtraces
+//!
+//! The kernel is built with frame pointers. Every function's prologue
+//! pushes the caller's frame pointer, r11, above its return address and
+//! points r11 at the pair, so the pairs form a chain up the stack.
+
+/// The running function's frame pointer
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { core::arch::asm!("mov {}, r11", out(reg) fp, options(nomem, nostack)) };
+    fp
+}
@@ -1,3 +1,10 @@
+use crate::arch::arm::exception::TrapFrame;
+use core::mem::offset_of;
+
+pub mod vfp;
+
+pub use vfp::VfpState;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Context {
@@ -52,3 +59,79 @@ impl Context {
         }
     }
 }
+
+/// Kernel-side state of a task that is not running, as saved by
+/// `context_switch` (switch.S). Everything else the task needs is on its
+/// stack.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaskContext {
+    // Callee-saved registers
+    pub r4: u32,
+    pub r5: u32,
+    pub r6: u32,
+    pub r7: u32,
+    pub r8: u32,
+    pub r9: u32,
+    pub r10: u32,
+    pub r11: u32,
+
+    // Stack pointer
+    pub sp: u32,
+
+    // Where the task resumes
+    pub lr: u32,
+
+    /// VFP registers, saved lazily; see [`vfp`]
+    pub vfp: VfpState,
+}
+
+// switch.S saves the VFP registers at this offset
+const _: () = assert!(offset_of!(TaskContext, vfp) == 40);
+
+impl TaskContext {
+    /// Context of a task that has not run yet: switching to it calls
+    /// `entry` on the stack whose top is `sp`
+    pub fn new(entry: extern "C" fn() -> !, sp: usize) -> Self {
+        Self {
+            sp: sp as u32,
+            lr: entry as usize as u32,
+            ..Self::default()
+        }
+    }
+
+    /// Context of a task that resumes by returning from a copy of
+    /// `frame`, with 0 in the result register. The copy goes at the top
+    /// of the stack ending at `stack_top`.
+    ///
+    /// # Safety
+    /// The stack must belong to the task and be otherwise unused.
+    pub unsafe fn for_trap_return(frame: &TrapFrame, stack_top: usize) -> Self {
+        let copy = ((stack_top - size_of::<TrapFrame>()) & !0xF) as *mut TrapFrame;
+        unsafe { copy.write(TrapFrame { r0: 0, ..*frame }) };
+        Self {
+            sp: copy as u32,
+            lr: trap_return as *const () as u32,
+            ..Self::default()
+        }
+    }
+
+    /// Give a forked child the VFP registers of its parent, the task
+    /// `parent_id` with context `parent`
+    pub fn inherit_fpu(&mut self, parent: &TaskContext, parent_id: usize) {
+        self.vfp = vfp::live_state(parent_id).unwrap_or(parent.vfp);
+    }
+}
+
+unsafe extern "C" {
+    /// Save the running task's context into `old` and resume `new`.
+    /// Returns when another task switches back to `old`.
+    pub fn context_switch(old: *mut TaskContext, new: *const TaskContext);
+
+    /// Pop the TrapFrame at the stack pointer and return from the trap
+    fn trap_return();
+
+    /// Start a freshly loaded program at `entry` with its stack pointer
+    /// at `sp`. The running task's kernel stack is abandoned.
+    pub fn enter_user(entry: usize, sp: usize) -> !;
+}
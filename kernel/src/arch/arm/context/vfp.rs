@@ -0,0 +1,126 @@
+//! Lazy VFP context switching
+//!
+//! The VFP registers are only switched for tasks that use them. A context
+//! switch away from a task that has the VFP enabled saves its registers
+//! into its [`TaskContext`](super::TaskContext) and disables the VFP
+//! (switch.S). The next VFP instruction then traps as undefined, and
+//! [`handle_undefined`] enables the VFP and loads the running task's
+//! registers, unless the VFP still holds them. Each CPU keeps track of
+//! whose registers its VFP holds.
+//!
+//! The kernel is built for hard float too. Kernel code using VFP registers
+//! works on the running task's, so interrupt handlers must not, or they
+//! would corrupt the interrupted task's scratch registers.
+
+use crate::arch::arm::exception::TrapFrame;
+use crate::arch::arm::percpu::this_cpu;
+use crate::process::sched;
+use core::mem::MaybeUninit;
+use core::sync::atomic::Ordering;
+
+/// FPEXC enable bit
+const FPEXC_EN: u32 = 1 << 30;
+
+/// PSR Thumb state bit
+const PSR_THUMB: u32 = 1 << 5;
+
+/// [`PerCpu::vfp_owner`](crate::arch::arm::percpu::PerCpu::vfp_owner)
+/// when no task's registers are loaded
+pub(crate) const NO_OWNER: usize = usize::MAX;
+
+/// VFP registers of a task: d0-d15 and FPSCR, as switch.S stores them
+#[repr(C, align(8))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VfpState {
+    pub d: [u64; 16],
+    pub fpscr: u32,
+}
+
+fn fpexc() -> u32 {
+    let fpexc: u32;
+    unsafe { core::arch::asm!("vmrs {}, fpexc", out(reg) fpexc, options(nomem, nostack)) };
+    fpexc
+}
+
+fn set_fpexc(fpexc: u32) {
+    unsafe { core::arch::asm!("vmsr fpexc, {}", in(reg) fpexc, options(nomem, nostack)) };
+}
+
+/// Store the VFP registers into `state`. The VFP must be enabled.
+unsafe fn save(state: *mut VfpState) {
+    unsafe {
+        core::arch::asm!(
+            "vstmia {state}!, {{d0-d15}}",
+            "vmrs {fpscr}, fpscr",
+            "str {fpscr}, [{state}]",
+            state = inout(reg) state => _,
+            fpscr = out(reg) _,
+            options(nostack),
+        )
+    };
+}
+
+/// Load the VFP registers from `state`. The VFP must be enabled.
+unsafe fn load(state: *const VfpState) {
+    unsafe {
+        core::arch::asm!(
+            "vldmia {state}!, {{d0-d15}}",
+            "ldr {fpscr}, [{state}]",
+            "vmsr fpscr, {fpscr}",
+            state = inout(reg) state => _,
+            fpscr = out(reg) _,
+            out("d0") _, out("d1") _, out("d2") _, out("d3") _,
+            out("d4") _, out("d5") _, out("d6") _, out("d7") _,
+            out("d8") _, out("d9") _, out("d10") _, out("d11") _,
+            out("d12") _, out("d13") _, out("d14") _, out("d15") _,
+            options(nostack),
+        )
+    };
+}
+
+/// Whether `insn`, an ARM state instruction, is a VFP one: a coprocessor
+/// load/store, data processing or register transfer on CP10 or CP11
+fn is_vfp(insn: u32) -> bool {
+    matches!((insn >> 24) & 0xF, 0xC..=0xE) && (insn >> 8) & 0xE == 0xA
+}
+
+/// Give the VFP to the running task if `tf` trapped on its first VFP
+/// instruction since it was switched in; the instruction is then retried.
+/// Returns false for any other undefined instruction.
+pub fn handle_undefined(tf: &TrapFrame) -> bool {
+    // The ARM1176 has no Thumb encodings of VFP instructions
+    if tf.spsr & PSR_THUMB != 0 || fpexc() & FPEXC_EN != 0 {
+        return false;
+    }
+    // SAFETY: the instruction was just fetched, so it is mapped
+    let insn = unsafe { (tf.pc as *const u32).read_volatile() };
+    if !is_vfp(insn) {
+        return false;
+    }
+
+    set_fpexc(FPEXC_EN);
+    let task = sched::current().map_or(NO_OWNER, |task| task.0);
+    if this_cpu().vfp_owner.swap(task, Ordering::Relaxed) != task {
+        // SAFETY: the VFP is enabled, and the state lives in the task
+        sched::with_current(|task| unsafe { load(&task.context.vfp) });
+    }
+    true
+}
+
+/// The VFP registers of the task `id`, if the VFP holds them
+pub fn live_state(id: usize) -> Option<VfpState> {
+    if this_cpu().vfp_owner.load(Ordering::Relaxed) != id {
+        return None;
+    }
+    let mut state = MaybeUninit::<VfpState>::uninit();
+    let fpexc = fpexc();
+    set_fpexc(fpexc | FPEXC_EN);
+    // SAFETY: the VFP is enabled until the registers are stored, which
+    // fills in every field
+    let state = unsafe {
+        save(state.as_mut_ptr());
+        state.assume_init()
+    };
+    set_fpexc(fpexc);
+    Some(state)
+}
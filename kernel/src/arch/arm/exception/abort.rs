@@ -0,0 +1,248 @@
+//! Data and Prefetch Abort Handling
+//!
+//! Translation faults, and permission faults on pages, in the active
+//! address space are first offered to [`address_space::handle_fault`],
+//! which maps a page on demand when the address lies in one of its regions
+//! or copies a copy-on-write page that is written; the faulting
+//! instruction is then retried. A kernel fault the address space cannot
+//! resolve inside the user copy routine resumes it at its exit, so the
+//! copy fails with `EFAULT` (see [`uaccess`](crate::arch::arm::uaccess)).
+//!
+//! Anything else is a genuine invalid access, reported with the decoded
+//! fault status, address, PC and mode. A user process gets a signal for
+//! it, `SIGSEGV` or `SIGBUS`, which kills it unless it has a handler; a
+//! kernel fault panics.
+
+use super::trap::{self, TrapFrame};
+use crate::arch::arm::uaccess;
+use crate::mm::address_space::{self, FaultError};
+use crate::process::signal::{self, SIGBUS, SIGSEGV};
+
+/// DFSR write-not-read bit
+const DFSR_WNR: u32 = 1 << 11;
+
+/// Decoded DFSR fault status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    Alignment,
+    /// No valid section (`section`) or page table entry
+    Translation {
+        section: bool,
+    },
+    AccessFlag {
+        section: bool,
+    },
+    Domain {
+        section: bool,
+    },
+    Permission {
+        section: bool,
+    },
+    /// External abort on a translation table walk
+    TableWalk,
+    External {
+        precise: bool,
+    },
+    CacheMaintenance,
+    Debug,
+    Unknown(u32),
+}
+
+impl FaultKind {
+    /// Decode the FS[4:0] field of a DFSR or IFSR value
+    pub fn decode(fsr: u32) -> Self {
+        let status = ((fsr >> 6) & 0x10) | (fsr & 0xF);
+        match status {
+            0b00001 => Self::Alignment,
+            0b00010 => Self::Debug,
+            0b00011 => Self::AccessFlag { section: true },
+            0b00100 => Self::CacheMaintenance,
+            0b00101 => Self::Translation { section: true },
+            0b00110 => Self::AccessFlag { section: false },
+            0b00111 => Self::Translation { section: false },
+            0b01000 => Self::External { precise: true },
+            0b01001 => Self::Domain { section: true },
+            0b01011 => Self::Domain { section: false },
+            0b01100 | 0b01110 => Self::TableWalk,
+            0b01101 => Self::Permission { section: true },
+            0b01111 => Self::Permission { section: false },
+            0b10110 => Self::External { precise: false },
+            other => Self::Unknown(other),
+        }
+    }
+
+    /// Signal a user process gets for the fault
+    fn signal(self) -> u32 {
+        match self {
+            Self::Alignment | Self::External { .. } | Self::TableWalk => SIGBUS,
+            _ => SIGSEGV,
+        }
+    }
+}
+
+impl core::fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let level = |section| if section { "section" } else { "page" };
+        match *self {
+            Self::Alignment => write!(f, "alignment fault"),
+            Self::Translation { section } => write!(f, "{} translation fault", level(section)),
+            Self::AccessFlag { section } => write!(f, "{} access flag fault", level(section)),
+            Self::Domain { section } => write!(f, "{} domain fault", level(section)),
+            Self::Permission { section } => write!(f, "{} permission fault", level(section)),
+            Self::TableWalk => write!(f, "external abort on translation table walk"),
+            Self::External { precise: true } => write!(f, "precise external abort"),
+            Self::External { precise: false } => write!(f, "imprecise external abort"),
+            Self::CacheMaintenance => write!(f, "cache maintenance fault"),
+            Self::Debug => write!(f, "debug event"),
+            Self::Unknown(status) => write!(f, "unknown fault status {:#07b}", status),
+        }
+    }
+}
+
+/// What faulted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    /// An instruction fetch
+    Execute,
+}
+
+impl Access {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Execute => "instruction fetch",
+        }
+    }
+}
+
+/// Read the Data Fault Status Register
+fn read_dfsr() -> u32 {
+    let dfsr: u32;
+    unsafe {
+        core::arch::asm!("mrc p15, 0, {}, c5, c0, 0", out(reg) dfsr, options(nostack));
+    }
+    dfsr
+}
+
+/// Read the Fault Address Register
+fn read_far() -> u32 {
+    let far: u32;
+    unsafe {
+        core::arch::asm!("mrc p15, 0, {}, c6, c0, 0", out(reg) far, options(nostack));
+    }
+    far
+}
+
+/// Read the Instruction Fault Status Register
+fn read_ifsr() -> u32 {
+    let ifsr: u32;
+    unsafe {
+        core::arch::asm!("mrc p15, 0, {}, c5, c0, 1", out(reg) ifsr, options(nostack));
+    }
+    ifsr
+}
+
+/// Read the Instruction Fault Address Register
+fn read_ifar() -> u32 {
+    let ifar: u32;
+    unsafe {
+        core::arch::asm!("mrc p15, 0, {}, c6, c0, 2", out(reg) ifar, options(nostack));
+    }
+    ifar
+}
+
+/// Offer a fault to the active address space. Fails with the address
+/// space's error, if it was one it could handle.
+fn resolve(kind: FaultKind, far: usize, write: bool) -> Result<(), Option<FaultError>> {
+    match kind {
+        FaultKind::Translation { .. } | FaultKind::Permission { section: false } => {
+            address_space::handle_fault(far, write).map_err(Some)
+        }
+        _ => Err(None),
+    }
+}
+
+/// Handle a data abort, returning the frame to return through. Only a
+/// fault from User mode can return a different frame, to run a signal
+/// handler.
+#[unsafe(no_mangle)]
+pub extern "C" fn data_abort_entry_rust(tf: &mut TrapFrame) -> *mut TrapFrame {
+    let dfsr = read_dfsr();
+    let far = read_far() as usize;
+    let kind = FaultKind::decode(dfsr);
+    let write = dfsr & DFSR_WNR != 0;
+
+    let Err(error) = resolve(kind, far, write) else {
+        // Return to the faulting instruction, which now succeeds
+        return tf;
+    };
+    if !tf.is_user_mode() && uaccess::fixup(tf) {
+        return tf;
+    }
+    let access = if write { Access::Write } else { Access::Read };
+    invalid_access(tf, far, dfsr, kind, access, error)
+}
+
+/// Handle a prefetch abort, like [`data_abort_entry_rust`]
+#[unsafe(no_mangle)]
+pub extern "C" fn prefetch_abort_entry_rust(tf: &mut TrapFrame) -> *mut TrapFrame {
+    let ifsr = read_ifsr();
+    let ifar = read_ifar() as usize;
+    let kind = FaultKind::decode(ifsr);
+
+    let Err(error) = resolve(kind, ifar, false) else {
+        return tf;
+    };
+    invalid_access(tf, ifar, ifsr, kind, Access::Execute, error)
+}
+
+/// Report an access that could not be resolved, and signal the process
+/// that made it or, for a kernel fault, panic.
+fn invalid_access(
+    tf: &mut TrapFrame,
+    far: usize,
+    fsr: u32,
+    kind: FaultKind,
+    access: Access,
+    error: Option<FaultError>,
+) -> *mut TrapFrame {
+    log::error!(
+        "{}: invalid {} at {:#010x} from pc {:#010x} ({} mode): {} (FSR {:#x}){}",
+        if access == Access::Execute {
+            "Prefetch abort"
+        } else {
+            "Data abort"
+        },
+        access.as_str(),
+        far,
+        tf.pc,
+        trap::mode_name(tf.spsr),
+        kind,
+        fsr,
+        match error {
+            Some(FaultError::Unmapped) => ", address not mapped",
+            Some(FaultError::AccessDenied) => ", access not permitted",
+            Some(FaultError::OutOfMemory) => ", out of memory",
+            Some(FaultError::NoAddressSpace) => ", no address space",
+            Some(FaultError::Busy) => ", address space busy",
+            None => "",
+        }
+    );
+    log::error!(
+        "  r0={:#010x} r1={:#010x} r2={:#010x} r3={:#010x} spsr={:#010x}",
+        tf.r0,
+        tf.r1,
+        tf.r2,
+        tf.r3,
+        tf.spsr
+    );
+
+    if tf.is_user_mode() && signal::force(kind.signal()).is_ok() {
+        return trap::deliver_signal(tf);
+    }
+    crate::kcore::panic::set_trap_frame(tf);
+    panic!("kernel fault: invalid {} at {:#010x}", access.as_str(), far);
+}
@@ -0,0 +1,115 @@
+//! Fast Interrupt (FIQ) Handling
+//!
+//! A single interrupt source can be steered to FIQ, bypassing the normal
+//! IRQ dispatch path (no controller lock, no handler table lookup, no
+//! nesting). FIQ mode banks r8-r12, so a handler can keep state such as
+//! buffer pointers in those registers across invocations without touching
+//! memory; they are exposed here as a [`FiqFrame`].
+//!
+//! FIQ handlers run with both IRQ and FIQ masked and must not take locks
+//! that IRQ-context or thread code may hold.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use drivers::hal::interrupt::{InterruptError, IrqNumber};
+
+use crate::subsystems::irq_controller;
+
+const MODE_FIQ: u32 = 0x11;
+
+/// Banked FIQ registers, saved on entry and reloaded on exit.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FiqFrame {
+    pub r8: u32,
+    pub r9: u32,
+    pub r10: u32,
+    pub r11: u32,
+    pub r12: u32,
+}
+
+pub type FiqHandler = fn(&mut FiqFrame);
+
+/// Installed handler, stored as a function pointer (0 = none).
+static FIQ_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+#[unsafe(no_mangle)]
+pub extern "C" fn fiq_entry_rust(frame: &mut FiqFrame) {
+    let handler = FIQ_HANDLER.load(Ordering::Acquire);
+    if handler != 0 {
+        let handler: FiqHandler = unsafe { core::mem::transmute(handler) };
+        handler(frame);
+    }
+}
+
+/// Route `irq` to FIQ and install `handler`.
+///
+/// The banked registers are preloaded from `initial` before the source is
+/// unmasked. Any previously routed source is replaced.
+pub fn register(
+    irq: IrqNumber,
+    handler: FiqHandler,
+    initial: FiqFrame,
+) -> Result<(), InterruptError> {
+    let irqctl = irq_controller().ok_or(InterruptError::Other)?;
+
+    disable();
+    FIQ_HANDLER.store(handler as usize, Ordering::Release);
+    unsafe { load_banked(&initial) };
+
+    let result = match irqctl.lock().as_fiq() {
+        Some(fiq) => fiq.route_to_fiq(irq),
+        None => Err(InterruptError::Unsupported),
+    };
+
+    if result.is_ok() {
+        enable();
+    } else {
+        FIQ_HANDLER.store(0, Ordering::Release);
+    }
+    result
+}
+
+/// Stop routing to FIQ and remove the handler.
+pub fn unregister() -> Result<(), InterruptError> {
+    disable();
+    FIQ_HANDLER.store(0, Ordering::Release);
+
+    let irqctl = irq_controller().ok_or(InterruptError::Other)?;
+    match irqctl.lock().as_fiq() {
+        Some(fiq) => fiq.clear_fiq(),
+        None => Ok(()),
+    }
+}
+
+/// Load the banked FIQ registers from `frame`.
+///
+/// # Safety
+/// FIQs must be masked; briefly switches to FIQ mode.
+unsafe fn load_banked(frame: &FiqFrame) {
+    unsafe {
+        // Operands pinned to low registers: r8-r12 are swapped out while
+        // in FIQ mode.
+        core::arch::asm!(
+            "mrs r2, cpsr",
+            "msr cpsr_c, r1",
+            "ldm r0, {{r8-r12}}",
+            "msr cpsr_c, r2",
+            in("r0") frame as *const FiqFrame,
+            in("r1") MODE_FIQ | (1 << 7) | (1 << 6),
+            out("r2") _,
+            options(nostack)
+        );
+    }
+}
+
+/// Mask FIQs on this CPU.
+#[inline(always)]
+pub fn disable() {
+    unsafe { core::arch::asm!("cpsid f", options(nomem, nostack)) }
+}
+
+/// Unmask FIQs on this CPU.
+#[inline(always)]
+pub fn enable() {
+    unsafe { core::arch::asm!("cpsie f", options(nomem, nostack)) }
+}
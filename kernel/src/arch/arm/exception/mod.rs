@@ -1,2 +1,6 @@
+pub mod abort;
+pub mod fiq;
 pub mod trap;
+pub mod undefined;
+pub use fiq::{FiqFrame, FiqHandler};
 pub use trap::TrapFrame;
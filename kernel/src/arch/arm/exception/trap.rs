@@ -1,8 +1,14 @@
+use crate::arch::Irq;
+use crate::arch::arm::percpu::this_cpu;
+use crate::process::signal::{self, SA_RESTORER, SignalFrame};
+use common::sync::irq::IrqControl;
+use core::mem::offset_of;
+use core::sync::atomic::Ordering;
 use drivers::platform::{CurrentPlatform, Platform};
 
+/// Registers saved on exception entry, lowest address first
 #[repr(C)]
 pub struct TrapFrame {
-    pub spsr: u32,
     pub r0: u32,
     pub r1: u32,
     pub r2: u32,
@@ -16,17 +22,249 @@ pub struct TrapFrame {
     pub r10: u32,
     pub r11: u32,
     pub r12: u32,
+    /// LR of the interrupted code (System/User mode bank)
     pub lr: u32,
+    /// Return address
+    pub pc: u32,
+    pub spsr: u32,
 }
 
+/// PSR mode field
+const PSR_MODE_MASK: u32 = 0x1F;
+const PSR_MODE_USR: u32 = 0x10;
+/// PSR Thumb state bit
+const PSR_THUMB: u32 = 1 << 5;
+/// PSR bits user code may set: NZCVQ, GE and Thumb
+const PSR_USER_BITS: u32 = 0xF80F_0000 | PSR_THUMB;
+
+/// Space the IRQ and SVC handlers leave below the frame for setting up a signal
+/// handler: the rest of the signal frame and the handler's own frame.
+/// Must match `SIGNAL_RESERVE` in entry.S.
+const SIGNAL_RESERVE: usize = offset_of!(SignalFrame, context) + size_of::<TrapFrame>();
+const _: () = assert!(SIGNAL_RESERVE == 80);
+
+impl TrapFrame {
+    /// Whether the trap came from User mode
+    pub fn is_user_mode(&self) -> bool {
+        self.spsr & PSR_MODE_MASK == PSR_MODE_USR
+    }
+
+    /// The register a system call's result goes in
+    pub fn result(&self) -> usize {
+        self.r0 as usize
+    }
+
+    /// Store a system call's result
+    pub fn set_result(&mut self, value: usize) {
+        self.r0 = value as u32;
+    }
+
+    /// The system call number, from r7 as in the EABI
+    pub fn syscall_number(&self) -> u32 {
+        self.r7
+    }
+
+    /// The system call arguments, from r0-r5
+    pub fn syscall_args(&self) -> [usize; 6] {
+        [self.r0, self.r1, self.r2, self.r3, self.r4, self.r5].map(|reg| reg as usize)
+    }
+
+    /// Stack pointer of the interrupted code. SVC and IRQ frames are
+    /// pushed on its own stack, so it is just above the frame.
+    pub fn user_sp(&self) -> usize {
+        self as *const Self as usize + size_of::<Self>()
+    }
+
+    /// Make the frame return to User mode with interrupts enabled,
+    /// keeping only the flags user code may set
+    pub fn force_user(&mut self) {
+        self.spsr = self.spsr & PSR_USER_BITS | PSR_MODE_USR;
+    }
+
+    /// Frame pointer of the interrupted code
+    pub fn frame_pointer(&self) -> usize {
+        self.r11 as usize
+    }
+}
+
+/// Short name of the processor mode in `psr`
+pub(super) fn mode_name(psr: u32) -> &'static str {
+    match psr & PSR_MODE_MASK {
+        0x10 => "USR",
+        0x11 => "FIQ",
+        0x12 => "IRQ",
+        0x13 => "SVC",
+        0x17 => "ABT",
+        0x1B => "UND",
+        0x1F => "SYS",
+        _ => "???",
+    }
+}
+
+/// Register dump, as printed when the kernel panics
+impl core::fmt::Display for TrapFrame {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(
+            f,
+            " r0 {:08x}  r1 {:08x}  r2 {:08x}  r3 {:08x}",
+            self.r0, self.r1, self.r2, self.r3
+        )?;
+        writeln!(
+            f,
+            " r4 {:08x}  r5 {:08x}  r6 {:08x}  r7 {:08x}",
+            self.r4, self.r5, self.r6, self.r7
+        )?;
+        writeln!(
+            f,
+            " r8 {:08x}  r9 {:08x} r10 {:08x} r11 {:08x}",
+            self.r8, self.r9, self.r10, self.r11
+        )?;
+        writeln!(
+            f,
+            "r12 {:08x}  lr {:08x}  pc {:08x}",
+            self.r12, self.lr, self.pc
+        )?;
+        write!(
+            f,
+            "cpsr {:08x} ({}{}{})",
+            self.spsr,
+            mode_name(self.spsr),
+            if self.spsr & PSR_THUMB != 0 {
+                ", Thumb"
+            } else {
+                ""
+            },
+            if self.spsr & (1 << 7) != 0 {
+                ", IRQs off"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+unsafe extern "C" {
+    /// Call `func(arg)` with the stack pointer at `stack_top`
+    fn call_on_stack(arg: *mut TrapFrame, func: extern "C" fn(*mut TrapFrame), stack_top: usize);
+}
+
+/// Handle an IRQ, returning the frame to return through: `tf`, or one
+/// that enters a signal handler
+///
+/// The frame stays on the interrupted task's stack, where the task
+/// switch and signal delivery below need it, but the outermost IRQ runs
+/// its handlers on the CPU's IRQ stack. Nested IRQs stay on that stack.
+/// Handlers run with preemption disabled, so one that tries to sleep
+/// panics instead of switching tasks from under the IRQ stack.
 #[unsafe(no_mangle)]
-pub extern "C" fn irq_entry_rust(tf: &mut TrapFrame) {
-    if let Some(irq) = CurrentPlatform::next_pending_irq() {
-        crate::irq::dispatch(irq, tf);
+pub extern "C" fn irq_entry_rust(tf: &mut TrapFrame) -> *mut TrapFrame {
+    let cpu = this_cpu();
+    let outermost = cpu.irq_depth.fetch_add(1, Ordering::Relaxed) == 0;
+    crate::process::sched::preempt_disable();
+    if outermost {
+        // SAFETY: the IRQ stack is the CPU's own, and only the outermost
+        // IRQ starts at its top
+        unsafe { call_on_stack(tf, handle_irq, cpu.irq_stack_top()) };
+    } else {
+        handle_irq(tf);
+    }
+    crate::process::sched::preempt_enable_no_resched();
+
+    // Only the outermost IRQ may switch tasks: an interrupted dispatch
+    // still has its line masked
+    if cpu.irq_depth.fetch_sub(1, Ordering::Relaxed) == 1 {
+        crate::process::sched::preempt();
+    }
+
+    if tf.is_user_mode() {
+        deliver_signal(tf)
+    } else {
+        tf
+    }
+}
+
+/// Dispatch the pending interrupt, then run the softirqs if this is the
+/// outermost IRQ
+extern "C" fn handle_irq(tf: *mut TrapFrame) {
+    // SAFETY: `irq_entry_rust` passes its own frame
+    let tf = unsafe { &mut *tf };
+    let cpu = this_cpu();
+    #[cfg(feature = "bcm2836")]
+    let ipi = crate::arch::arm::smp::handle_ipi();
+    #[cfg(not(feature = "bcm2836"))]
+    let ipi = false;
+    // Peripheral interrupts are routed to the boot CPU
+    let irq = if cpu.id == 0 {
+        CurrentPlatform::next_pending_irq()
+    } else {
+        None
+    };
+    match irq {
+        Some(irq) => crate::irq::dispatch(irq, tf),
+        None if !ipi => crate::irq::handlers::record_spurious(),
+        None => {}
+    }
+
+    // Softirqs wait for the outermost IRQ, like task switches
+    if cpu.irq_depth.load(Ordering::Relaxed) == 1 {
+        crate::irq::softirq::run();
     }
 }
 
+/// Set up the handler of the next signal for the running process, which
+/// `tf` returns to User mode. The frame is on the user stack, and stays
+/// there as the context of the signal frame; the rest of the signal frame
+/// and the handler's frame go in the space reserved below it.
+pub(super) fn deliver_signal(tf: &mut TrapFrame) -> *mut TrapFrame {
+    let frame = tf as *mut TrapFrame;
+    let Some(delivery) = signal::next_signal() else {
+        return frame;
+    };
+    let action = delivery.action;
+    // An odd handler address is Thumb code
+    let thumb = if action.handler & 1 != 0 {
+        PSR_THUMB
+    } else {
+        0
+    };
+    let restorer = if action.flags & SA_RESTORER != 0 {
+        action.restorer
+    } else {
+        0
+    };
+
+    // SAFETY: the handler left `SIGNAL_RESERVE` bytes free below `tf`,
+    // which covers the signal frame header and the handler's frame
+    unsafe {
+        let signal_frame = frame.byte_sub(offset_of!(SignalFrame, context)) as *mut SignalFrame;
+        (&raw mut (*signal_frame).return_address).write(restorer);
+        (&raw mut (*signal_frame).signal).write(delivery.signal as usize);
+        (&raw mut (*signal_frame).blocked).write(delivery.blocked);
+
+        let handler_frame = (signal_frame as *mut TrapFrame).sub(1);
+        handler_frame.write(TrapFrame {
+            r0: delivery.signal,
+            lr: restorer as u32,
+            pc: action.handler as u32 & !1,
+            spsr: PSR_MODE_USR | thumb,
+            ..frame.read()
+        });
+        handler_frame
+    }
+}
+
+/// Handle a system call, returning the frame to return through like
+/// [`irq_entry_rust`]. The call runs with interrupts enabled, so it may
+/// sleep and be preempted.
 #[unsafe(no_mangle)]
-pub extern "C" fn svc_entry_rust(tf: &mut TrapFrame) {
-    crate::syscall::dispatch(tf)
+pub extern "C" fn svc_entry_rust(tf: &mut TrapFrame) -> *mut TrapFrame {
+    Irq::enable();
+    crate::syscall::dispatch(tf);
+    Irq::disable();
+
+    if tf.is_user_mode() {
+        deliver_signal(tf)
+    } else {
+        tf
+    }
 }
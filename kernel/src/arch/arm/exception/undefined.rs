@@ -0,0 +1,43 @@
+//! Undefined Instruction Handling
+//!
+//! The first VFP instruction a task runs after being switched in traps
+//! here while the VFP is disabled, and is retried once the task's VFP
+//! registers are loaded. Any other undefined instruction sends a user
+//! process `SIGILL`, and is fatal in the kernel.
+
+use super::trap::{self, TrapFrame};
+use crate::arch::arm::context::vfp;
+use crate::process::signal::{self, SIGILL};
+
+/// PSR Thumb state bit
+const PSR_THUMB: u32 = 1 << 5;
+
+/// Handle an undefined instruction, returning the frame to return
+/// through like [`data_abort_entry_rust`](super::abort::data_abort_entry_rust)
+#[unsafe(no_mangle)]
+pub extern "C" fn undefined_entry_rust(tf: &mut TrapFrame) -> *mut TrapFrame {
+    if vfp::handle_undefined(tf) {
+        return tf;
+    }
+
+    // The entry fixup assumed a 4-byte ARM instruction
+    let pc = if tf.spsr & PSR_THUMB != 0 {
+        tf.pc + 2
+    } else {
+        tf.pc
+    };
+
+    log::error!(
+        "Undefined instruction at {:#010x} ({} mode, spsr {:#010x})",
+        pc,
+        trap::mode_name(tf.spsr),
+        tf.spsr
+    );
+    if tf.is_user_mode() && signal::force(SIGILL).is_ok() {
+        // A handler that returns resumes at the instruction
+        tf.pc = pc;
+        return trap::deliver_signal(tf);
+    }
+    crate::kcore::panic::set_trap_frame(tf);
+    panic!("kernel fault: undefined instruction at {:#010x}", pc);
+}
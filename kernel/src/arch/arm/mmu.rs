@@ -1,4 +1,6 @@
-use crate::mm::mmu::{MapFlags, MmuOps};
+use crate::mm::address_space::MapError;
+use crate::mm::mmu::{MapFlags, MmuOps, PageTableOps, RootEntry};
+use crate::mm::page_allocator::{PAGE_SIZE, page_allocator};
 use core::ptr::write_volatile;
 use drivers::platform::{CurrentPlatform, Platform};
 
@@ -29,6 +31,10 @@ pub const MEM_DEVICE: u32 = (0b000 << 12) | (0 << 3) | (1 << 2);
 pub const MEM_NORMAL_UNCACHED: u32 = (0b001 << 12) | (0 << 3) | (0 << 2);
 pub const MEM_NORMAL_WRITEBACK: u32 = (0b001 << 12) | (1 << 3) | (1 << 2);
 
+/// Whether cached memory is marked shareable. The multi-core SoCs need it
+/// to keep the cores' caches coherent; the ARM1176 would not cache it.
+const SHARE_CACHED: bool = cfg!(feature = "bcm2836");
+
 unsafe extern "C" {
     static _vectors: u8;
 }
@@ -45,7 +51,12 @@ fn ap_bits(ap: u32) -> u32 {
 #[inline(always)]
 fn section_entry(phys_addr: usize, mem_type: u32, ap: u32, domain: u32, exec: bool) -> u32 {
     let xn = if exec { 0 } else { 1 << 4 };
-    ((phys_addr & SECTION_MASK) as u32) | mem_type | ap_bits(ap) | (domain << 5) | xn | 0b10
+    let s = if SHARE_CACHED && mem_type == MEM_NORMAL_WRITEBACK {
+        1 << 16
+    } else {
+        0
+    };
+    ((phys_addr & SECTION_MASK) as u32) | mem_type | ap_bits(ap) | (domain << 5) | s | xn | 0b10
 }
 
 #[inline(always)]
@@ -53,11 +64,50 @@ pub fn coarse_entry(l2_phys: usize, domain: u32) -> u32 {
     ((l2_phys & 0xFFFFFC00) as u32) | (domain << 5) | 0b01
 }
 
+/// Small page descriptor. `mem_type` uses the section encoding above.
+#[inline(always)]
+fn small_page_entry(phys_addr: usize, mem_type: u32, ap: u32, exec: bool, global: bool) -> u32 {
+    let base = (phys_addr & PAGE_MASK) as u32;
+    // TEX moves from bits [14:12] in a section to [8:6] in a small page
+    let mem = ((mem_type >> 6) & (0b111 << 6)) | (mem_type & 0b1100);
+    let ap_l2 = ((ap & 0x4) << 7) | ((ap & 0x3) << 4);
+    let ng = if global { 0 } else { 1 << 11 };
+    let s = if SHARE_CACHED && mem_type == MEM_NORMAL_WRITEBACK {
+        1 << 10
+    } else {
+        0
+    };
+    let xn = if exec { 0 } else { 1 };
+    base | mem | ap_l2 | ng | s | xn | 0b10
+}
+
+/// Access permissions, memory type and executability for `flags`
+fn flag_attributes(flags: MapFlags) -> (u32, u32, bool) {
+    let user = flags.contains(MapFlags::USER);
+    let ap = match (user, flags.contains(MapFlags::WRITE)) {
+        (true, true) => AP_FULL,
+        (true, false) => AP_PRIV_RW_USER_RO,
+        (false, true) => AP_PRIV_RW,
+        (false, false) => AP_PRIV_RO,
+    };
+
+    let mem_type = if flags.contains(MapFlags::DEVICE) {
+        MEM_DEVICE
+    } else if flags.contains(MapFlags::CACHED) {
+        MEM_NORMAL_WRITEBACK
+    } else {
+        MEM_NORMAL_UNCACHED
+    };
+
+    (ap, mem_type, flags.contains(MapFlags::EXEC))
+}
+
 #[inline(always)]
 pub fn l2_page_entry(phys_addr: usize, ap: u32) -> u32 {
     let base = (phys_addr & PAGE_MASK) as u32;
     let ap_l2 = ((ap & 0x4) << 7) | ((ap & 0x3) << 4);
-    base | ap_l2 | (1 << 3) | (1 << 2) | 0b10
+    let s = if SHARE_CACHED { 1 << 10 } else { 0 };
+    base | ap_l2 | s | (1 << 3) | (1 << 2) | 0b10
 }
 
 // ============================================================================
@@ -138,30 +188,7 @@ impl MmuOps for ArmMmu {
     }
 
     unsafe fn map_region(virt: usize, phys: usize, size: usize, flags: MapFlags) {
-        // Determine AP and memory type from flags
-        let ap = if flags.contains(MapFlags::USER) {
-            if flags.contains(MapFlags::WRITE) {
-                AP_FULL
-            } else {
-                AP_PRIV_RW_USER_RO
-            }
-        } else {
-            if flags.contains(MapFlags::WRITE) {
-                AP_PRIV_RW
-            } else {
-                AP_PRIV_RO
-            }
-        };
-
-        let mem_type = if flags.contains(MapFlags::DEVICE) {
-            MEM_DEVICE
-        } else if flags.contains(MapFlags::CACHED) {
-            MEM_NORMAL_WRITEBACK
-        } else {
-            MEM_NORMAL_UNCACHED
-        };
-
-        let exec = flags.contains(MapFlags::EXEC);
+        let (ap, mem_type, exec) = flag_attributes(flags);
         let domain = if flags.contains(MapFlags::USER) {
             DOMAIN_USER
         } else {
@@ -175,14 +202,18 @@ impl MmuOps for ArmMmu {
         let aligned_size = (size + SECTION_SIZE - 1) & SECTION_MASK;
         let mut offset = 0;
         while offset < aligned_size {
-            write_volatile(
-                l1.add(l1_index(virt + offset)),
-                section_entry(phys + offset, mem_type, ap, domain, exec),
-            );
+            // SAFETY: the index is within the 4096-entry kernel L1 table
+            unsafe {
+                write_entry(
+                    l1.add(l1_index(virt + offset)),
+                    section_entry(phys + offset, mem_type, ap, domain, exec),
+                );
+            }
             offset += SECTION_SIZE;
         }
 
-        Self::invalidate_tlb_all();
+        // SAFETY: the kernel's own mappings are left as they were
+        unsafe { flush_tlb_all() };
     }
 
     unsafe fn unmap_region(virt: usize, size: usize) {
@@ -192,36 +223,383 @@ impl MmuOps for ArmMmu {
         let aligned_size = (size + SECTION_SIZE - 1) & SECTION_MASK;
         let mut offset = 0;
         while offset < aligned_size {
-            write_volatile(l1.add(l1_index(virt + offset)), 0);
-            Self::invalidate_tlb_entry(virt + offset);
+            // SAFETY: the index is within the kernel L1 table, and the
+            // caller no longer uses the region
+            unsafe {
+                write_entry(l1.add(l1_index(virt + offset)), 0);
+                flush_tlb_page(virt + offset);
+            }
             offset += SECTION_SIZE;
         }
     }
 
+    unsafe fn map_pages(
+        virt: usize,
+        phys: usize,
+        size: usize,
+        flags: MapFlags,
+    ) -> Result<(), MapError> {
+        let (ap, mem_type, exec) = flag_attributes(flags);
+        let l1 = kernel_l1();
+
+        let virt_start = virt & PAGE_MASK;
+        let phys_start = phys & PAGE_MASK;
+        let pages = (size + (virt & !PAGE_MASK)).div_ceil(PAGE_SIZE);
+        for i in 0..pages {
+            let va = virt_start + i * PAGE_SIZE;
+            // SAFETY: `l1` is the kernel's L1 table
+            let leaf = match unsafe { Self::root_entry(l1, va) } {
+                RootEntry::Table(leaf) => leaf,
+                RootEntry::Empty => {
+                    // Kernel tables are never freed
+                    let page = page_allocator().alloc().ok_or(MapError::OutOfMemory)?;
+                    let leaf = page.addr();
+                    core::mem::forget(page);
+                    // SAFETY: the page is zeroed and now belongs to the table
+                    unsafe { Self::set_root_entry(l1, va, leaf) };
+                    leaf
+                }
+                RootEntry::Block => return Err(MapError::AlreadyMapped),
+            };
+
+            // SAFETY: the index is within the 256-entry leaf table, and the
+            // page was unmapped
+            unsafe {
+                let entry = (leaf as *mut u32).add((va >> 12) & 0x3FF);
+                write_entry(
+                    entry,
+                    small_page_entry(phys_start + i * PAGE_SIZE, mem_type, ap, exec, true),
+                );
+                flush_tlb_page(va);
+            }
+        }
+        Ok(())
+    }
+
+    unsafe fn unmap_pages(virt: usize, size: usize) {
+        let l1 = kernel_l1();
+
+        let virt_start = virt & PAGE_MASK;
+        let pages = (size + (virt & !PAGE_MASK)).div_ceil(PAGE_SIZE);
+        for i in 0..pages {
+            let va = virt_start + i * PAGE_SIZE;
+            // SAFETY: `l1` is the kernel's L1 table
+            if let RootEntry::Table(leaf) = unsafe { Self::root_entry(l1, va) } {
+                // SAFETY: the index is within the 256-entry leaf table, and
+                // the caller no longer uses the page
+                unsafe {
+                    write_entry((leaf as *mut u32).add((va >> 12) & 0x3FF), 0);
+                    flush_tlb_page(va);
+                }
+            }
+        }
+    }
+
+    fn enabled() -> bool {
+        mmu_enabled()
+    }
+
     #[inline(always)]
     unsafe fn invalidate_tlb_entry(va: usize) {
+        // SAFETY: the caller's contract is the same
+        unsafe { flush_tlb_page(va) };
+    }
+
+    #[inline(always)]
+    unsafe fn invalidate_tlb_all() {
+        // SAFETY: the caller's contract is the same
+        unsafe { flush_tlb_all() };
+    }
+}
+
+/// Kernel L1 table published by init.rs
+pub(crate) fn kernel_l1() -> usize {
+    crate::kcore::init::KERNEL_L1_TABLE_PHYS.load(core::sync::atomic::Ordering::Relaxed)
+}
+
+/// Size of a coarse (L2) table; four of them share one leaf page
+const COARSE_TABLE_SIZE: usize = 1024;
+
+/// TTBR0 value for the table at `base`, with the walk attributes
+/// `enable_mmu` uses
+#[inline(always)]
+fn ttbr0_value(base: usize) -> u32 {
+    base as u32 | (1 << 6) | (1 << 0)
+}
+
+impl PageTableOps for ArmMmu {
+    /// 16 KB L1 table covering the full 4 GB
+    const ROOT_ORDER: usize = 2;
+
+    /// 8-bit ASID in CONTEXTIDR
+    const ASID_COUNT: usize = 256;
+
+    unsafe fn init_root(root: usize) {
+        let kernel = crate::kcore::init::KERNEL_L1_TABLE_PHYS
+            .load(core::sync::atomic::Ordering::Relaxed) as *const u32;
+        if !kernel.is_null() {
+            // SAFETY: both are whole L1 tables, and the new one is the
+            // caller's alone
+            unsafe { core::ptr::copy_nonoverlapping(kernel, root as *mut u32, NUM_L1_ENTRIES) };
+        }
+    }
+
+    unsafe fn root_entry(root: usize, va: usize) -> RootEntry {
+        let l1 = root as *const u32;
+        let first = l1_index(va) & !3;
+        // SAFETY: the four entries are within the root table
+        let entries = [0, 1, 2, 3].map(|i| unsafe { core::ptr::read_volatile(l1.add(first + i)) });
+
+        if entries.iter().any(|&entry| is_section_entry(entry)) {
+            RootEntry::Block
+        } else if is_coarse_entry(entries[0]) {
+            RootEntry::Table(coarse_base(entries[0]))
+        } else {
+            RootEntry::Empty
+        }
+    }
+
+    unsafe fn set_root_entry(root: usize, va: usize, leaf: usize) {
+        // The zeroed leaf may still sit in the D-cache
+        // SAFETY: the leaf page is in the kernel's RAM mapping
+        unsafe { clean_dcache_range(leaf, PAGE_SIZE) };
+
+        let l1 = root as *mut u32;
+        let first = l1_index(va) & !3;
+        for i in 0..4 {
+            // SAFETY: the four entries are within the root table
+            unsafe {
+                write_entry(
+                    l1.add(first + i),
+                    coarse_entry(leaf + i * COARSE_TABLE_SIZE, DOMAIN_KERNEL),
+                );
+            }
+        }
+    }
+
+    unsafe fn clear_root_entry(root: usize, va: usize) {
+        let l1 = root as *mut u32;
+        let first = l1_index(va) & !3;
+        for i in 0..4 {
+            // SAFETY: the four entries are within the root table
+            unsafe { write_entry(l1.add(first + i), 0) };
+        }
+    }
+
+    unsafe fn write_entry(entry: *mut u32, value: u32) {
+        // SAFETY: the caller's contract is the same
+        unsafe { write_entry(entry, value) };
+    }
+
+    fn leaf_entry(phys: usize, flags: MapFlags) -> u32 {
+        let (ap, mem_type, exec) = flag_attributes(flags);
+        small_page_entry(phys, mem_type, ap, exec, false)
+    }
+
+    unsafe fn switch_to(root: usize, asid: Option<u16>) {
+        // Park on the reserved ASID while TTBR0 changes so no walk mixes
+        // the old tables with the new ASID, then install the new one
+        // SAFETY: the caller vouches that `root` maps the kernel as the
+        // current table does, so the code and stack stay mapped
+        unsafe {
+            core::arch::asm!(
+                "mov {t}, #0",
+                "mcr p15, 0, {t}, c13, c0, 1",     // CONTEXTIDR = 0
+                "mcr p15, 0, {t}, c7, c5, 4",      // ISB
+                "mcr p15, 0, {b}, c2, c0, 0",      // TTBR0
+                "mcr p15, 0, {t}, c7, c5, 4",      // ISB
+                "mcr p15, 0, {a}, c13, c0, 1",     // CONTEXTIDR = asid
+                "mcr p15, 0, {t}, c7, c5, 6",      // Flush branch target cache
+                "mcr p15, 0, {t}, c7, c5, 4",      // ISB
+                b = in(reg) ttbr0_value(root),
+                a = in(reg) asid.unwrap_or(0) as u32,
+                t = out(reg) _,
+                options(nostack),
+            );
+        }
+
+        if asid.is_none() {
+            // SAFETY: the kernel half is the same in every root table
+            unsafe { flush_tlb_all() };
+        }
+    }
+
+    unsafe fn invalidate_page(va: usize, asid: Option<u16>) {
+        match asid {
+            // Invalidate unified TLB entry by MVA and ASID
+            // SAFETY: TLB maintenance and barriers only
+            Some(asid) => unsafe {
+                core::arch::asm!(
+                    "mcr p15, 0, {mva}, c8, c7, 1",
+                    "mcr p15, 0, {t}, c7, c10, 4",     // DSB
+                    "mcr p15, 0, {t}, c7, c5, 4",      // ISB
+                    mva = in(reg) (va & PAGE_MASK) as u32 | asid as u32,
+                    t = in(reg) 0,
+                    options(nostack),
+                )
+            },
+            // Untagged entries may sit under any ASID
+            // SAFETY: the kernel half is the same in every root table
+            None => unsafe { flush_tlb_all() },
+        }
+    }
+
+    unsafe fn invalidate_asid(asid: u16) {
+        // Invalidate unified TLB entries by ASID
+        // SAFETY: TLB maintenance and barriers only
+        unsafe {
+            core::arch::asm!(
+                "mcr p15, 0, {asid}, c8, c7, 2",
+                "mcr p15, 0, {t}, c7, c10, 4",     // DSB
+                "mcr p15, 0, {t}, c7, c5, 4",      // ISB
+                asid = in(reg) asid as u32,
+                t = in(reg) 0,
+                options(nostack),
+            );
+        }
+    }
+}
+
+// ============================================================================
+// State and cache maintenance
+// ============================================================================
+
+/// Whether the MMU (SCTLR bit 0) is on.
+pub fn mmu_enabled() -> bool {
+    let sctlr: u32;
+    unsafe {
+        core::arch::asm!("mrc p15, 0, {}, c1, c0, 0", out(reg) sctlr, options(nostack));
+    }
+    sctlr & 1 != 0
+}
+
+/// D-cache line size of the ARM1176 and Cortex-A7/A53
+const DCACHE_LINE: usize = 32;
+
+/// Run the by-MVA cache operation `op` on every line covering
+/// `[addr, addr + len)`, then wait for it to complete.
+macro_rules! dcache_range_op {
+    ($op:literal, $addr:expr, $len:expr) => {{
+        let (addr, len): (usize, usize) = ($addr, $len);
+        let mut line = addr & !(DCACHE_LINE - 1);
+        while line < addr + len {
+            // SAFETY: cache maintenance by MVA of a line the caller says
+            // is mapped
+            unsafe {
+                core::arch::asm!(concat!("mcr p15, 0, {}, c7, ", $op), in(reg) line, options(nostack));
+            }
+            line += DCACHE_LINE;
+        }
+        dsb();
+    }};
+}
+
+/// Write dirty D-cache lines covering `[addr, addr + len)` back to memory,
+/// so that a device or the table walker reads what the CPU wrote.
+///
+/// # Safety
+/// `addr` must be mapped.
+pub unsafe fn clean_dcache_range(addr: usize, len: usize) {
+    // Clean data cache line by MVA
+    dcache_range_op!("c10, 1", addr, len);
+}
+
+/// Discard the D-cache lines covering `[addr, addr + len)` without writing
+/// them back, so that the CPU reads what a device wrote. Dirty data in
+/// lines only partly inside the range is lost as well.
+///
+/// # Safety
+/// `addr` must be mapped, and nothing the CPU wrote to the range may still
+/// be needed.
+pub unsafe fn invalidate_dcache_range(addr: usize, len: usize) {
+    // Invalidate data cache line by MVA
+    dcache_range_op!("c6, 1", addr, len);
+}
+
+/// Clean and invalidate the D-cache lines covering `[addr, addr + len)`.
+///
+/// # Safety
+/// `addr` must be mapped.
+pub unsafe fn clean_invalidate_dcache_range(addr: usize, len: usize) {
+    // Clean and invalidate data cache line by MVA
+    dcache_range_op!("c14, 1", addr, len);
+}
+
+/// Drop every TLB entry, global ones included.
+///
+/// # Safety
+/// The current tables must map the code that runs next.
+pub unsafe fn flush_tlb_all() {
+    // SAFETY: barriers and TLB maintenance only; the caller vouches for
+    // the tables
+    unsafe {
         core::arch::asm!(
-            "mcr p15, 0, {va}, c8, c7, 1",
-            va = in(reg) va,
+            "mcr p15, 0, {t}, c7, c10, 4",     // DSB: table writes done
+            "mcr p15, 0, {t}, c8, c7, 0",      // Invalidate unified TLB
+            "mcr p15, 0, {t}, c7, c5, 6",      // Flush branch target cache
+            "mcr p15, 0, {t}, c7, c10, 4",     // DSB
+            "mcr p15, 0, {t}, c7, c5, 4",      // ISB
+            t = in(reg) 0,
             options(nostack),
         );
     }
+}
 
-    #[inline(always)]
-    unsafe fn invalidate_tlb_all() {
+/// Drop the global TLB entry for the page at `va`, such as a kernel
+/// mapping. Use [`PageTableOps::invalidate_page`] for user pages.
+///
+/// # Safety
+/// The current tables must map the code that runs next.
+pub unsafe fn flush_tlb_page(va: usize) {
+    // SAFETY: barriers and TLB maintenance only; the caller vouches for
+    // the tables
+    unsafe {
         core::arch::asm!(
-            "mov {t}, #0",
-            "mcr p15, 0, {t}, c8, c7, 0",
-            t = out(reg) _,
+            "mcr p15, 0, {t}, c7, c10, 4",     // DSB: table writes done
+            "mcr p15, 0, {mva}, c8, c7, 1",    // Invalidate unified TLB entry by MVA
+            "mcr p15, 0, {t}, c7, c10, 4",     // DSB
+            "mcr p15, 0, {t}, c7, c5, 4",      // ISB
+            mva = in(reg) va & PAGE_MASK,
+            t = in(reg) 0,
             options(nostack),
         );
     }
 }
 
+/// Store a translation table entry and clean it out of the D-cache, so the
+/// walker sees it whether or not it walks through the cache.
+#[inline(always)]
+unsafe fn write_entry(entry: *mut u32, value: u32) {
+    // SAFETY: the caller passes an entry of a table in mapped memory
+    unsafe {
+        write_volatile(entry, value);
+        clean_dcache_range(entry as usize, core::mem::size_of::<u32>());
+    }
+}
+
+/// Data synchronization barrier
+#[inline(always)]
+fn dsb() {
+    unsafe {
+        core::arch::asm!("mcr p15, 0, {}, c7, c10, 4", in(reg) 0, options(nostack));
+    }
+}
+
 // ============================================================================
 // MMU enable (private, ARM-only)
 // ============================================================================
 
+/// Enable the MMU of a secondary core on the kernel's L1 table at
+/// `l1_phys`, which the boot core set up.
+///
+/// # Safety
+/// - The caller's code and stack must be identity-mapped in that table.
+/// - Called once per core, with its MMU off.
+#[cfg(feature = "bcm2836")]
+pub unsafe fn enable_secondary(l1_phys: usize) {
+    unsafe { enable_mmu(l1_phys) };
+}
+
 /// Load TTBR0, configure TTBCR/DACR, then enable MMU + caches.
 ///
 /// # Safety
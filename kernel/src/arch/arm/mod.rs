@@ -1,9 +1,16 @@
 //! ARM Architecture Support
 //! Architecture-specific utilities and helpers.
+pub mod backtrace;
 pub mod context;
 pub mod exception;
 pub mod interrupt;
 pub mod mmu;
+pub mod percpu;
+#[cfg(not(feature = "bcm2836"))]
+pub mod perf;
+#[cfg(feature = "bcm2836")]
+pub mod smp;
+pub mod uaccess;
 
 /// Data Synchronization Barrier (DSB)
 ///
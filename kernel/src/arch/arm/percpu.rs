@@ -0,0 +1,90 @@
+//! Per-CPU data
+//!
+//! Each CPU finds its [`PerCpu`] through TPIDRPRW, the thread ID register
+//! only privileged modes can read, which [`init`] points at it as the CPU
+//! comes up. Boot code clears the register, so until then the boot CPU's
+//! is used, as it always is on a single-core build.
+//!
+//! Each CPU also has a stack of its own for interrupt handlers, so a
+//! handler's frames do not have to fit on whichever task it interrupts.
+
+use super::context::vfp;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// CPUs the kernel can run on
+pub const MAX_CPUS: usize = if cfg!(feature = "bcm2836") { 4 } else { 1 };
+
+/// Size of each CPU's IRQ stack
+pub const IRQ_STACK_SIZE: usize = 8 * 1024;
+
+#[repr(C, align(8))]
+struct IrqStack([u8; IRQ_STACK_SIZE]);
+
+static mut IRQ_STACKS: [IrqStack; MAX_CPUS] = [const { IrqStack([0; IRQ_STACK_SIZE]) }; MAX_CPUS];
+
+pub struct PerCpu {
+    pub id: usize,
+
+    /// IRQs being handled, counting nested ones
+    pub irq_depth: AtomicUsize,
+
+    /// ID of the task whose registers this CPU's VFP holds
+    pub vfp_owner: AtomicUsize,
+}
+
+impl PerCpu {
+    const fn new(id: usize) -> Self {
+        Self {
+            id,
+            irq_depth: AtomicUsize::new(0),
+            vfp_owner: AtomicUsize::new(vfp::NO_OWNER),
+        }
+    }
+
+    /// Initial stack pointer of the CPU's IRQ stack
+    pub fn irq_stack_top(&self) -> usize {
+        // SAFETY: only the address is taken; the stack is only used by
+        // this CPU's outermost IRQ
+        unsafe { &raw mut IRQ_STACKS[self.id] as usize + IRQ_STACK_SIZE }
+    }
+}
+
+static PER_CPU: [PerCpu; MAX_CPUS] = {
+    let mut cpus = [const { PerCpu::new(0) }; MAX_CPUS];
+    let mut id = 1;
+    while id < MAX_CPUS {
+        cpus[id].id = id;
+        id += 1;
+    }
+    cpus
+};
+
+/// Point the running CPU's TPIDRPRW at the data of CPU `id`
+///
+/// # Panics
+/// If `id` is not below [`MAX_CPUS`].
+pub fn init(id: usize) {
+    let cpu = &PER_CPU[id] as *const PerCpu;
+    unsafe { core::arch::asm!("mcr p15, 0, {}, c13, c0, 4", in(reg) cpu, options(nostack)) };
+}
+
+/// Data of the running CPU
+pub fn this_cpu() -> &'static PerCpu {
+    let cpu: *const PerCpu;
+    unsafe {
+        core::arch::asm!("mrc p15, 0, {}, c13, c0, 4", out(reg) cpu, options(nomem, nostack))
+    };
+    // SAFETY: TPIDRPRW is either cleared or set by `init` to an entry of
+    // `PER_CPU`
+    unsafe { cpu.as_ref() }.unwrap_or(&PER_CPU[0])
+}
+
+/// ID of the running CPU
+pub fn cpu_id() -> usize {
+    this_cpu().id
+}
+
+/// Whether the running CPU is handling an IRQ, softirqs included
+pub fn in_interrupt() -> bool {
+    this_cpu().irq_depth.load(Ordering::Relaxed) != 0
+}
@@ -0,0 +1,289 @@
+//! ARM1176 Performance Monitor
+//!
+//! The ARM1176 has a 32-bit cycle counter and two 32-bit event counters,
+//! each counting one [`Event`], all controlled through the Performance
+//! Monitor Control Register (PMNC) in CP15 c15. [`start`] programs and
+//! zeroes them, [`read`] reads them, and [`measure`] counts the events of
+//! one piece of code.
+//!
+//! Sampling attributes counts to code: once [`start_sampling`] has been
+//! called, every scheduler tick credits the kernel PC it interrupted with
+//! the events counted since the tick before. Samples collect in a fixed
+//! table, so taking them never allocates; [`top_samples`] reads out the
+//! PCs with the most. PCs go through `addr2line -e` on the kernel ELF like
+//! a backtrace. The counters overflow silently, and the BCM2835 routes no
+//! PMU interrupt, so the tick is the only sampling clock.
+//!
+//! Only the ARM1176 has this PMU; the Cortex-A7 of the BCM2836 has the
+//! ARMv7 one.
+
+use super::exception::TrapFrame;
+use crate::arch::IrqSpinLock;
+
+/// PMNC: enable all counters
+const PMNC_E: u32 = 1 << 0;
+/// PMNC: reset both event counters
+const PMNC_P: u32 = 1 << 1;
+/// PMNC: reset the cycle counter
+const PMNC_C: u32 = 1 << 2;
+/// PMNC: overflow flags, written 1 to clear
+const PMNC_OVERFLOW: u32 = 0x7 << 8;
+/// PMNC: event counted by counter 0
+const PMNC_EVT0_SHIFT: u32 = 20;
+/// PMNC: event counted by counter 1
+const PMNC_EVT1_SHIFT: u32 = 12;
+
+/// Distinct PCs the sample table holds
+pub const SAMPLE_SLOTS: usize = 1024;
+
+/// Events the event counters can count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Event {
+    ICacheMiss = 0x00,
+    InstructionBufferStall = 0x01,
+    DataDependencyStall = 0x02,
+    InstructionMicroTlbMiss = 0x03,
+    DataMicroTlbMiss = 0x04,
+    BranchExecuted = 0x05,
+    BranchMispredicted = 0x06,
+    InstructionExecuted = 0x07,
+    /// Cacheable data cache accesses
+    DCacheAccess = 0x09,
+    /// Every data cache access, cacheable or not
+    DCacheAccessAll = 0x0A,
+    DCacheMiss = 0x0B,
+    DCacheWriteback = 0x0C,
+    /// Software changed the PC, other than by a branch
+    PcChanged = 0x0D,
+    MainTlbMiss = 0x0F,
+    ExternalDataAccess = 0x10,
+    LoadStoreFullStall = 0x11,
+    WriteBufferDrained = 0x12,
+    /// Counts cycles, as the cycle counter does
+    Cycles = 0xFF,
+}
+
+/// Counter values since [`start`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub cycles: u32,
+    /// The events of counter 0 and 1
+    pub events: [u32; 2],
+}
+
+impl Counts {
+    /// Counts from `earlier` to `self`, allowing for a counter wrapping
+    /// once
+    pub fn since(self, earlier: Self) -> Self {
+        Self {
+            cycles: self.cycles.wrapping_sub(earlier.cycles),
+            events: [
+                self.events[0].wrapping_sub(earlier.events[0]),
+                self.events[1].wrapping_sub(earlier.events[1]),
+            ],
+        }
+    }
+}
+
+fn read_pmnc() -> u32 {
+    let pmnc: u32;
+    unsafe {
+        core::arch::asm!("mrc p15, 0, {}, c15, c12, 0", out(reg) pmnc, options(nomem, nostack));
+    }
+    pmnc
+}
+
+fn write_pmnc(pmnc: u32) {
+    unsafe {
+        core::arch::asm!("mcr p15, 0, {}, c15, c12, 0", in(reg) pmnc, options(nomem, nostack));
+    }
+}
+
+/// Program the event counters with `events`, zero every counter and
+/// start counting. Ends any sampling.
+pub fn start(events: [Event; 2]) {
+    SAMPLES.lock().enabled = false;
+    program(events);
+}
+
+fn program(events: [Event; 2]) {
+    write_pmnc(
+        (events[0] as u32) << PMNC_EVT0_SHIFT
+            | (events[1] as u32) << PMNC_EVT1_SHIFT
+            | PMNC_OVERFLOW
+            | PMNC_C
+            | PMNC_P
+            | PMNC_E,
+    );
+}
+
+/// Stop every counter, keeping its value
+pub fn stop() {
+    write_pmnc(read_pmnc() & !(PMNC_E | PMNC_OVERFLOW));
+}
+
+/// Whether the counters are counting
+pub fn is_running() -> bool {
+    read_pmnc() & PMNC_E != 0
+}
+
+/// Read every counter
+pub fn read() -> Counts {
+    let (cycles, count0, count1): (u32, u32, u32);
+    unsafe {
+        core::arch::asm!(
+            "mrc p15, 0, {}, c15, c12, 1",
+            "mrc p15, 0, {}, c15, c12, 2",
+            "mrc p15, 0, {}, c15, c12, 3",
+            out(reg) cycles,
+            out(reg) count0,
+            out(reg) count1,
+            options(nomem, nostack)
+        );
+    }
+    Counts {
+        cycles,
+        events: [count0, count1],
+    }
+}
+
+/// Run `f` with the counters counting `events`, returning its result and
+/// what was counted. Ends any sampling, like [`start`].
+pub fn measure<R>(events: [Event; 2], f: impl FnOnce() -> R) -> (R, Counts) {
+    start(events);
+    let before = read();
+    let result = f();
+    let counts = read().since(before);
+    (result, counts)
+}
+
+/// One PC in the sample table
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sample {
+    pub pc: usize,
+    /// Events counted in the ticks that interrupted `pc`
+    pub weight: u32,
+}
+
+/// What the sample table holds besides the samples
+#[derive(Debug, Clone, Copy)]
+pub struct Profile {
+    /// The event sampled
+    pub event: Event,
+    /// Whether sampling goes on
+    pub enabled: bool,
+    /// Events counted in user code
+    pub user: u32,
+    /// Events of PCs that found the table full
+    pub dropped: u32,
+}
+
+struct Samples {
+    /// Open-addressed by PC; a zero PC is a free slot
+    slots: [Sample; SAMPLE_SLOTS],
+    event: Event,
+    /// Whether ticks take samples
+    enabled: bool,
+    /// Counts at the last tick
+    last: Counts,
+    /// Events counted in user code
+    user: u32,
+    /// Events of PCs that found the table full
+    dropped: u32,
+}
+
+impl Samples {
+    fn add(&mut self, pc: usize, weight: u32) {
+        let mut slot = (pc >> 2) % SAMPLE_SLOTS;
+        for _ in 0..SAMPLE_SLOTS {
+            let sample = &mut self.slots[slot];
+            if sample.pc == pc || sample.pc == 0 {
+                sample.pc = pc;
+                sample.weight = sample.weight.saturating_add(weight);
+                return;
+            }
+            slot = (slot + 1) % SAMPLE_SLOTS;
+        }
+        self.dropped = self.dropped.saturating_add(weight);
+    }
+}
+
+static SAMPLES: IrqSpinLock<Samples> = IrqSpinLock::new(Samples {
+    slots: [Sample { pc: 0, weight: 0 }; SAMPLE_SLOTS],
+    event: Event::Cycles,
+    enabled: false,
+    last: Counts {
+        cycles: 0,
+        events: [0, 0],
+    },
+    user: 0,
+    dropped: 0,
+});
+
+/// Clear the sample table and start sampling `event`, counted by counter
+/// 0; [`Event::Cycles`] samples where the time goes.
+pub fn start_sampling(event: Event) {
+    let mut samples = SAMPLES.lock();
+    samples.slots.fill(Sample::default());
+    samples.event = event;
+    samples.user = 0;
+    samples.dropped = 0;
+    program([event, Event::Cycles]);
+    samples.last = read();
+    samples.enabled = true;
+}
+
+/// Stop taking samples, keeping the table
+pub fn stop_sampling() {
+    SAMPLES.lock().enabled = false;
+    stop();
+}
+
+/// Whether ticks take samples
+pub fn is_sampling() -> bool {
+    SAMPLES.lock().enabled
+}
+
+/// Credit the PC `tf` interrupted with the events since the last sample.
+/// Called by the scheduler tick.
+pub fn sample(tf: &TrapFrame) {
+    let Some(mut samples) = SAMPLES.try_lock() else {
+        return;
+    };
+    if !samples.enabled {
+        return;
+    }
+    let now = read();
+    let weight = now.since(samples.last).events[0];
+    samples.last = now;
+
+    if tf.is_user_mode() {
+        samples.user = samples.user.saturating_add(weight);
+    } else {
+        samples.add(tf.pc as usize, weight);
+    }
+}
+
+/// Fill `top` with the samples of most weight, heaviest first, returning
+/// how many there were and what else the table holds
+pub fn top_samples(top: &mut [Sample]) -> (usize, Profile) {
+    let samples = SAMPLES.lock();
+    let mut n = 0;
+    for sample in samples.slots.iter().filter(|sample| sample.pc != 0) {
+        // Insertion into the sorted prefix, dropping the lightest
+        let Some(at) = (0..top.len()).find(|&i| i >= n || top[i].weight < sample.weight) else {
+            continue;
+        };
+        n = (n + 1).min(top.len());
+        top[at..n].rotate_right(1);
+        top[at] = *sample;
+    }
+    let profile = Profile {
+        event: samples.event,
+        enabled: samples.enabled,
+        user: samples.user,
+        dropped: samples.dropped,
+    };
+    (n, profile)
+}
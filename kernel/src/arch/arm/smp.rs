@@ -0,0 +1,136 @@
+//! Secondary core bring-up on the BCM2836 and BCM2837
+//!
+//! The firmware parks cores 1 to 3 polling their boot mailbox.
+//! [`start_secondaries`] gives each core its stacks and releases it into
+//! `secondary_entry` (secondary.S), which sets up its modes and calls
+//! [`secondary_main`]. The core then enables the MMU on the kernel's
+//! tables, points TPIDRPRW at its per-CPU data and starts its scheduler.
+//!
+//! Inter-processor interrupts are raised through mailbox [`IPI_MAILBOX`]
+//! of the target core. Peripheral interrupts all stay with the boot CPU.
+
+use super::mmu;
+use super::percpu::{self, MAX_CPUS};
+use crate::process::sched;
+use crate::process::stack::KernelStack;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use drivers::peripheral::bcm2836::local_intc;
+
+/// Mailbox used for inter-processor interrupts
+const IPI_MAILBOX: usize = 0;
+
+/// IPI asking the target to reschedule
+const IPI_RESCHEDULE: u32 = 1 << 0;
+
+/// Milliseconds a released core has to come online
+const START_TIMEOUT_MS: u32 = 100;
+
+/// CPUs online, CPU `n` at bit `n`
+static ONLINE: AtomicUsize = AtomicUsize::new(1);
+
+/// What `secondary_entry` needs to start a core; must match the offsets
+/// in secondary.S
+#[repr(C)]
+struct SecondaryBoot {
+    /// Initial System mode stack pointer
+    sys_sp: usize,
+    /// Top of the block the exception mode stacks share
+    exception_sp: usize,
+    cpu: usize,
+    /// Physical address of the kernel's L1 table
+    l1: usize,
+    main: extern "C" fn(usize, usize) -> !,
+}
+
+const _: () = assert!(core::mem::size_of::<SecondaryBoot>() == 20);
+
+unsafe extern "C" {
+    fn secondary_entry() -> !;
+    static mut secondary_boot: SecondaryBoot;
+}
+
+/// Release the secondary cores one at a time and wait for each to come
+/// online. A core that does not is left parked, along with the rest.
+pub fn start_secondaries() {
+    for cpu in 1..MAX_CPUS {
+        let (Ok(stack), Ok(exception_stack)) = (KernelStack::new(), KernelStack::new()) else {
+            log::warn!("No memory for CPU {} stacks", cpu);
+            break;
+        };
+
+        let boot = SecondaryBoot {
+            sys_sp: stack.initial_sp(),
+            exception_sp: exception_stack.top(),
+            cpu,
+            l1: mmu::kernel_l1(),
+            main: secondary_main,
+        };
+        // SAFETY: the core starts with its caches off, so the record goes
+        // to memory, and no stale lines of its stacks may be written back
+        // over them later. No other core reads the record: the last one
+        // released is online.
+        unsafe {
+            let record = &raw mut secondary_boot;
+            record.write(boot);
+            mmu::clean_dcache_range(record as usize, core::mem::size_of::<SecondaryBoot>());
+            mmu::clean_invalidate_dcache_range(stack.bottom(), stack.size());
+            mmu::clean_invalidate_dcache_range(exception_stack.bottom(), exception_stack.size());
+        }
+        // The stacks serve the core for good
+        core::mem::forget(stack);
+        core::mem::forget(exception_stack);
+
+        let entry = secondary_entry as unsafe extern "C" fn() -> !;
+        local_intc::release_core(cpu, entry as usize);
+        if !wait_online(cpu) {
+            log::warn!("CPU {} did not come online", cpu);
+            break;
+        }
+    }
+
+    log::info!(
+        "{} of {} CPUs online",
+        ONLINE.load(Ordering::Acquire).count_ones(),
+        MAX_CPUS
+    );
+}
+
+fn wait_online(cpu: usize) -> bool {
+    let online = || ONLINE.load(Ordering::Acquire) & 1 << cpu != 0;
+    for _ in 0..START_TIMEOUT_MS {
+        if online() {
+            return true;
+        }
+        sched::sleep_ms(1);
+    }
+    online()
+}
+
+/// First Rust code run by a secondary core, on the stacks
+/// `start_secondaries` gave it, with its MMU still off
+extern "C" fn secondary_main(cpu: usize, l1: usize) -> ! {
+    // SAFETY: the kernel's table identity-maps the kernel and its stacks
+    unsafe { mmu::enable_secondary(l1) };
+    percpu::init(cpu);
+    local_intc::enable_mailbox_irq(cpu, IPI_MAILBOX);
+    ONLINE.fetch_or(1 << cpu, Ordering::Release);
+
+    sched::init_secondary()
+}
+
+/// Interrupt `cpu` so that it reschedules on the way out
+pub fn send_reschedule(cpu: usize) {
+    local_intc::send(cpu, IPI_MAILBOX, IPI_RESCHEDULE);
+}
+
+/// Acknowledge an IPI to the running CPU, if one is pending, returning
+/// whether one was. Called on every IRQ; the rescheduling itself happens
+/// on the way out of it.
+pub fn handle_ipi() -> bool {
+    let cpu = percpu::cpu_id();
+    let pending = local_intc::mailbox_pending(local_intc::irq_source(cpu), IPI_MAILBOX);
+    if pending {
+        local_intc::take(cpu, IPI_MAILBOX);
+    }
+    pending
+}
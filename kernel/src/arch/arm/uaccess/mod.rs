@@ -0,0 +1,48 @@
+//! Fault-tolerant copies to and from user memory
+//!
+//! The copy loops in copy.S are the only kernel code allowed to fault on
+//! a user address. When the abort handler cannot resolve such a fault, it
+//! calls [`fixup`], which resumes the loop at its exit so the copy
+//! reports how much was left instead of bringing the kernel down.
+
+use crate::arch::arm::exception::TrapFrame;
+
+unsafe extern "C" {
+    fn copy_from_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    fn copy_to_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    static copy_user_fault_start: u8;
+    static copy_user_fault_end: u8;
+}
+
+/// Copy `len` bytes from user memory at `src` to `dst`, returning the
+/// number of bytes not copied because an access faulted.
+///
+/// # Safety
+/// `dst` must be valid for the write. `src` may be anything: a bad
+/// address only cuts the copy short.
+pub unsafe fn copy_in(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    unsafe { copy_from_user(dst, src, len) }
+}
+
+/// Copy `len` bytes from `src` to user memory at `dst`, returning the
+/// number of bytes not copied because an access faulted. The stores are
+/// checked as User mode ones, so a copy-on-write page is copied first.
+///
+/// # Safety
+/// `src` must be valid for the read. `dst` may be anything: a bad
+/// address only cuts the copy short.
+pub unsafe fn copy_out(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    unsafe { copy_to_user(dst, src, len) }
+}
+
+/// Resume a kernel-mode fault `tf` at the exit of the copy loop if it hit
+/// inside it, returning whether it did
+pub fn fixup(tf: &mut TrapFrame) -> bool {
+    let start = &raw const copy_user_fault_start as u32;
+    let end = &raw const copy_user_fault_end as u32;
+    if !(start..end).contains(&tf.pc) {
+        return false;
+    }
+    tf.pc = end;
+    true
+}
@@ -35,5 +35,61 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "arm")] {
+        // ARM-specific implementation
+        pub use crate::arch::arm::context::{TaskContext, context_switch, enter_user};
+    }
+    else if #[cfg(target_arch = "x86")] {
+        // x86-specific implementation
+        pub use crate::arch::x86::context::{TaskContext, context_switch, enter_user};
+    }
+    else {
+        compile_error!("Unsupported architecture");
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "arm")] {
+        // ARM-specific implementation
+        pub use crate::arch::arm::percpu::{MAX_CPUS, cpu_id, in_interrupt};
+    }
+    else if #[cfg(target_arch = "x86")] {
+        // x86-specific implementation
+        pub use crate::arch::x86::{MAX_CPUS, cpu_id, in_interrupt};
+    }
+    else {
+        compile_error!("Unsupported architecture");
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "arm")] {
+        // ARM-specific implementation
+        pub use crate::arch::arm::uaccess;
+    }
+    else if #[cfg(target_arch = "x86")] {
+        // x86-specific implementation
+        pub use crate::arch::x86::uaccess;
+    }
+    else {
+        compile_error!("Unsupported architecture");
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "arm")] {
+        // ARM-specific implementation
+        pub use crate::arch::arm::backtrace;
+    }
+    else if #[cfg(target_arch = "x86")] {
+        // x86-specific implementation
+        pub use crate::arch::x86::backtrace;
+    }
+    else {
+        compile_error!("Unsupported architecture");
+    }
+}
+
 // Type alias that works everywhere
 pub type IrqSpinLock<T> = common::sync::irq_mutex::IrqMutex<T, Irq>;
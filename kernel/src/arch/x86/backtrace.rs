@@ -0,0 +1,13 @@
+//! Stack backtraces
+//!
+//! The kernel is built with frame pointers. Every function's prologue
+//! pushes the caller's EBP below its return address and points EBP at
+//! it, so the pairs form a chain up the stack.
+
+/// The running function's frame pointer
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { core::arch::asm!("mov {}, ebp", out(reg) fp, options(nomem, nostack)) };
+    fp
+}
@@ -1 +1,68 @@
+use crate::arch::x86::exception::trap::TrapFrame;
+
 pub struct Context;
+
+/// Kernel-side state of a task that is not running, as saved by
+/// `context_switch` (switch.S). Everything else the task needs is on its
+/// stack.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaskContext {
+    // Callee-saved registers
+    pub ebx: u32,
+    pub esi: u32,
+    pub edi: u32,
+    pub ebp: u32,
+
+    // Stack pointer
+    pub esp: u32,
+
+    // Where the task resumes
+    pub eip: u32,
+}
+
+impl TaskContext {
+    /// Context of a task that has not run yet: switching to it calls
+    /// `entry` on the stack whose top is `sp`
+    pub fn new(entry: extern "C" fn() -> !, sp: usize) -> Self {
+        Self {
+            // Leave the slot a call would have pushed the return address to
+            esp: (sp - 4) as u32,
+            eip: entry as usize as u32,
+            ..Self::default()
+        }
+    }
+
+    /// Context of a task that resumes by returning from a copy of
+    /// `frame`, with 0 in the result register. The copy goes at the top
+    /// of the stack ending at `stack_top`.
+    ///
+    /// # Safety
+    /// The stack must belong to the task and be otherwise unused.
+    pub unsafe fn for_trap_return(frame: &TrapFrame, stack_top: usize) -> Self {
+        let copy = ((stack_top - size_of::<TrapFrame>()) & !0xF) as *mut TrapFrame;
+        unsafe { copy.write(TrapFrame { eax: 0, ..*frame }) };
+        Self {
+            esp: copy as u32,
+            eip: trap_return as *const () as u32,
+            ..Self::default()
+        }
+    }
+
+    /// Give a forked child the FPU registers of its parent. The x87 and
+    /// SSE state is not switched yet, so there is nothing to copy.
+    pub fn inherit_fpu(&mut self, _parent: &TaskContext, _parent_id: usize) {}
+}
+
+unsafe extern "C" {
+    /// Save the running task's context into `old` and resume `new`.
+    /// Returns when another task switches back to `old`.
+    pub fn context_switch(old: *mut TaskContext, new: *const TaskContext);
+
+    /// Pop the TrapFrame at the stack pointer and return from the trap
+    fn trap_return();
+
+    /// Start a freshly loaded program at `entry` with its stack pointer
+    /// at `sp`. The running task's kernel stack is abandoned.
+    pub fn enter_user(entry: usize, sp: usize) -> !;
+}
@@ -17,3 +17,83 @@ pub struct TrapFrame {
     pub cs: u32,
     pub eflags: u32,
 }
+
+/// EFLAGS interrupt enable flag
+const EFLAGS_IF: u32 = 1 << 9;
+/// EFLAGS bits user code may not set: IOPL, NT and VM
+const EFLAGS_PRIVILEGED: u32 = 0x3000 | 1 << 14 | 1 << 17;
+/// Requested privilege level of user selectors
+const RPL_USER: u32 = 3;
+
+impl TrapFrame {
+    /// Whether the trap came from ring 3
+    pub fn is_user_mode(&self) -> bool {
+        self.cs & 3 == RPL_USER
+    }
+
+    /// The register a system call's result goes in
+    pub fn result(&self) -> usize {
+        self.eax as usize
+    }
+
+    /// Store a system call's result
+    pub fn set_result(&mut self, value: usize) {
+        self.eax = value as u32;
+    }
+
+    /// The system call number, from EAX as in the Linux i386 ABI
+    pub fn syscall_number(&self) -> u32 {
+        self.eax
+    }
+
+    /// The system call arguments, from EBX, ECX, EDX, ESI, EDI and EBP
+    pub fn syscall_args(&self) -> [usize; 6] {
+        [self.ebx, self.ecx, self.edx, self.esi, self.edi, self.ebp].map(|reg| reg as usize)
+    }
+
+    /// Stack pointer of the interrupted code
+    pub fn user_sp(&self) -> usize {
+        self.esp as usize
+    }
+
+    /// Make the frame return to ring 3 with interrupts enabled, keeping
+    /// only the flags user code may set
+    pub fn force_user(&mut self) {
+        for selector in [
+            &mut self.cs,
+            &mut self.ds,
+            &mut self.es,
+            &mut self.fs,
+            &mut self.gs,
+        ] {
+            *selector |= RPL_USER;
+        }
+        self.eflags = self.eflags & !EFLAGS_PRIVILEGED | EFLAGS_IF;
+    }
+
+    /// Frame pointer of the interrupted code
+    pub fn frame_pointer(&self) -> usize {
+        self.ebp as usize
+    }
+}
+
+/// Register dump, as printed when the kernel panics
+impl core::fmt::Display for TrapFrame {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(
+            f,
+            "eax {:08x} ebx {:08x} ecx {:08x} edx {:08x}",
+            self.eax, self.ebx, self.ecx, self.edx
+        )?;
+        writeln!(
+            f,
+            "esi {:08x} edi {:08x} ebp {:08x} esp {:08x}",
+            self.esi, self.edi, self.ebp, self.esp
+        )?;
+        write!(
+            f,
+            "eip {:08x} cs {:04x} eflags {:08x} trap {} error {:#x}",
+            self.eip, self.cs, self.eflags, self.trap_number, self.error_code
+        )
+    }
+}
@@ -3,7 +3,7 @@ use core::{
     sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::mm::mmu::{MapFlags, MmuOps};
+use crate::mm::mmu::{MapFlags, MmuOps, PageTableOps, RootEntry};
 
 /// Number of entries in a page directory or page table.
 const PD_ENTRIES: usize = 1024;
@@ -310,6 +310,15 @@ impl MmuOps for X86Mmu {
         }
     }
 
+    /// Whether CR0.PG is set.
+    fn enabled() -> bool {
+        let cr0: u32;
+        unsafe {
+            core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        }
+        cr0 & (1 << 31) != 0
+    }
+
     /// Invalidate a single TLB entry for virtual address `va`.
     #[inline]
     unsafe fn invalidate_tlb_entry(va: usize) {
@@ -330,3 +339,67 @@ impl MmuOps for X86Mmu {
         }
     }
 }
+
+impl PageTableOps for X86Mmu {
+    /// One 4 KB page directory
+    const ROOT_ORDER: usize = 0;
+
+    /// No PCIDs without PAE/long mode; every CR3 load flushes the TLB
+    const ASID_COUNT: usize = 1;
+
+    /// Copy the kernel's PDEs. Kernel page tables are shared, so later
+    /// kernel mappings under an existing PDE show up everywhere.
+    unsafe fn init_root(root: usize) {
+        let kernel = crate::kcore::init::KERNEL_PD_PHYS.load(Ordering::Relaxed) as *const u32;
+        if !kernel.is_null() {
+            unsafe {
+                ptr::copy_nonoverlapping(kernel, root as *mut u32, PD_ENTRIES);
+            }
+        }
+    }
+
+    unsafe fn root_entry(root: usize, va: usize) -> RootEntry {
+        let pde = unsafe { ptr::read_volatile((root as *const u32).add(pd_index(va))) };
+        if pde & X86_PRESENT == 0 {
+            RootEntry::Empty
+        } else if pde & X86_PS != 0 {
+            RootEntry::Block
+        } else {
+            RootEntry::Table((pde & PHYS_ADDR_MASK) as usize)
+        }
+    }
+
+    unsafe fn set_root_entry(root: usize, va: usize, leaf: usize) {
+        // Permissions are enforced by the PTEs, as in `map_region`
+        let pde = (leaf as u32 & PHYS_ADDR_MASK) | X86_WRITABLE | X86_USER | X86_PRESENT;
+        unsafe {
+            ptr::write_volatile((root as *mut u32).add(pd_index(va)), pde);
+        }
+    }
+
+    unsafe fn clear_root_entry(root: usize, va: usize) {
+        unsafe {
+            ptr::write_volatile((root as *mut u32).add(pd_index(va)), 0);
+        }
+    }
+
+    fn leaf_entry(phys: usize, flags: MapFlags) -> u32 {
+        (phys as u32 & PHYS_ADDR_MASK) | map_flags_to_x86(flags)
+    }
+
+    unsafe fn switch_to(root: usize, _asid: Option<u16>) {
+        unsafe {
+            write_cr3(root as u32);
+        }
+    }
+
+    /// Only the current address space can hold TLB entries, since
+    /// switching reloads CR3.
+    unsafe fn invalidate_page(va: usize, _asid: Option<u16>) {
+        unsafe {
+            invlpg(va);
+        }
+    }
+
+    unsafe fn invalidate_asid(_asid: u16) {}
+}
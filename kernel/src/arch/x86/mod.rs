@@ -1,5 +1,21 @@
+pub mod backtrace;
 pub mod context;
 pub mod exception;
 pub mod interrupt;
 pub mod mmu;
 pub mod time;
+pub mod uaccess;
+
+/// CPUs the kernel can run on; there is no SMP support on x86
+pub const MAX_CPUS: usize = 1;
+
+/// ID of the running CPU
+pub fn cpu_id() -> usize {
+    0
+}
+
+/// Whether the running CPU is handling an interrupt; external interrupts
+/// are not taken on x86 yet
+pub fn in_interrupt() -> bool {
+    false
+}
@@ -0,0 +1,25 @@
+//! Copies to and from user memory
+//!
+//! Page faults are not handled on x86 yet, so a copy cannot recover from
+//! a bad address; callers check the range against the address space
+//! first.
+
+/// Copy `len` bytes from user memory at `src` to `dst`, returning the
+/// number of bytes not copied.
+///
+/// # Safety
+/// Both ranges must be valid for the access.
+pub unsafe fn copy_in(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    unsafe { core::ptr::copy_nonoverlapping(src, dst, len) };
+    0
+}
+
+/// Copy `len` bytes from `src` to user memory at `dst`, returning the
+/// number of bytes not copied.
+///
+/// # Safety
+/// Both ranges must be valid for the access.
+pub unsafe fn copy_out(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    unsafe { core::ptr::copy_nonoverlapping(src, dst, len) };
+    0
+}
@@ -0,0 +1,67 @@
+//! Block device flusher
+//!
+//! Disks cache what is written to them (see
+//! [`CachedBlockDevice`](drivers::block::CachedBlockDevice)), so a FAT
+//! sector rewritten many times over reaches the card once. `kflushd`, a
+//! kernel task, bounds how long it stays cached: every [`WRITEBACK_MS`]
+//! milliseconds it flushes every block device in the device manager.
+//! `sync` and shutdown flush them at once, with [`flush_all`].
+
+use crate::process::sched;
+use crate::subsystems::try_device_manager;
+use alloc::string::String;
+use alloc::vec::Vec;
+use drivers::device_manager::DeviceClass;
+use drivers::hal::block_device::BlockDeviceError;
+
+/// How often `kflushd` flushes, in milliseconds
+pub const WRITEBACK_MS: u32 = 5000;
+
+crate::initcall!(late, FLUSHER_INIT, init);
+
+fn init() {
+    if let Err(e) = sched::spawn("kflushd", run) {
+        log::warn!("No periodic write-back: {:?}", e);
+    }
+}
+
+/// Flush every block device, for ever
+fn run() {
+    // A failing disk is reported once, not every period
+    let mut failing = false;
+    loop {
+        sched::sleep_ms(WRITEBACK_MS);
+        let mut failed = false;
+        for_each_flush(|name, e| {
+            if !failing {
+                log::warn!("kflushd: flush of {} failed: {:?}", name, e);
+            }
+            failed = true;
+        });
+        failing = failed;
+    }
+}
+
+/// Flush every block device, logging those that fail
+pub fn flush_all() {
+    for_each_flush(|name, e| log::warn!("flush of {} failed: {:?}", name, e));
+}
+
+/// Flush every block device, calling `failed` for each that fails
+fn for_each_flush(mut failed: impl FnMut(&str, BlockDeviceError)) {
+    // Flushing may wait on the device, so not under the device manager
+    let Some(device_mgr) = try_device_manager() else {
+        return;
+    };
+    let blocks: Vec<(String, _)> = {
+        let dm = device_mgr.lock();
+        dm.by_class(DeviceClass::Block)
+            .filter_map(|(name, _)| Some((name.into(), dm.block(name)?)))
+            .collect()
+    };
+    for (name, block) in blocks {
+        if let Err(e) = block.flush() {
+            failed(&name, e);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Block request dispatcher
+//!
+//! Block I/O goes through request queues (see
+//! [`drivers::block::queue`]), which until now were served by the task
+//! that submitted each request. `kblockd`, a kernel task, takes that
+//! over at boot: tasks queue their requests and sleep, and `kblockd`
+//! carries them out, adjacent ones merged, and wakes them as they
+//! complete.
+
+use crate::process::sched::{self, WaitQueue, timer};
+use drivers::block::queue;
+
+/// Where `kblockd` waits for requests, and submitters for completions
+static REQUESTS: WaitQueue = WaitQueue::new();
+
+crate::initcall!(late, KBLOCKD_INIT, init);
+
+fn init() {
+    // Submitters sleep holding the disk cache's lock; without the tick,
+    // a task spinning on it would never let `kblockd` run
+    if !timer::ticking() {
+        log::warn!("No scheduler tick, block I/O stays on the submitter");
+        return;
+    }
+    if let Err(e) = sched::spawn("kblockd", run) {
+        log::warn!("No block dispatcher, I/O stays on the submitter: {:?}", e);
+        return;
+    }
+    queue::use_dispatcher(&REQUESTS);
+}
+
+/// Carry out queued requests, for ever
+fn run() {
+    loop {
+        REQUESTS.sleep_on(queue::has_pending);
+        queue::dispatch_all();
+    }
+}
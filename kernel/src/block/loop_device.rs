@@ -0,0 +1,162 @@
+//! Loop devices: a file presented as a block device.
+//!
+//! A disk image stored on a mounted filesystem is attached as `loopN`,
+//! after which its partitions are scanned and it can be mounted like a
+//! card. The file stays open, and its mount busy, until the device is
+//! detached and every user of it is gone.
+
+use crate::fs::fd::FdError;
+use crate::fs::file::{File, OpenFlags};
+use crate::fs::vfs::vfs;
+use crate::fs::{FileSystem, FsError};
+use crate::subsystems::device_manager;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use drivers::device_manager::DeviceClass;
+use drivers::hal::block_device::{BlockDevice, BlockDeviceError, BlockDeviceInfo};
+
+/// Sector size of every loop device
+pub const LOOP_BLOCK_SIZE: usize = 512;
+
+/// Name prefix of loop devices in the device manager
+pub const LOOP_PREFIX: &str = "loop";
+
+pub struct LoopDevice {
+    file: Arc<dyn File>,
+    block_count: u64,
+    read_only: bool,
+}
+
+impl LoopDevice {
+    /// Back a device with `file`. The size is fixed at the file's current
+    /// length; a trailing partial sector is not addressable.
+    pub fn new(file: Arc<dyn File>, read_only: bool) -> Result<Self, FdError> {
+        let size = file.stat()?.size;
+        Ok(Self {
+            file,
+            block_count: (size / LOOP_BLOCK_SIZE) as u64,
+            read_only,
+        })
+    }
+
+    /// Byte offset of `start_block`, checking `count` blocks fit
+    fn offset(&self, start_block: u64, count: usize) -> Result<usize, BlockDeviceError> {
+        match start_block.checked_add(count as u64) {
+            Some(end) if end <= self.block_count => Ok(start_block as usize * LOOP_BLOCK_SIZE),
+            _ => Err(BlockDeviceError::InvalidAddress),
+        }
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    type Error = BlockDeviceError;
+
+    fn info(&self) -> BlockDeviceInfo {
+        let info = BlockDeviceInfo::with_block_size(LOOP_BLOCK_SIZE, self.block_count);
+        if self.read_only {
+            info.read_only()
+        } else {
+            info
+        }
+    }
+
+    fn read_blocks(&self, start_block: u64, buffers: &mut [&mut [u8]]) -> Result<(), Self::Error> {
+        let mut offset = self.offset(start_block, buffers.len())?;
+        for buf in buffers.iter_mut() {
+            if buf.len() != LOOP_BLOCK_SIZE {
+                return Err(BlockDeviceError::InvalidBuffer);
+            }
+
+            let mut done = 0;
+            while done < LOOP_BLOCK_SIZE {
+                let n = self
+                    .file
+                    .read(&mut buf[done..], offset + done)
+                    .map_err(|_| BlockDeviceError::ReadError)?;
+                if n == 0 {
+                    // The file shrank under us; read the rest as zeros
+                    buf[done..].fill(0);
+                    break;
+                }
+                done += n;
+            }
+            offset += LOOP_BLOCK_SIZE;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, start_block: u64, buffers: &[&[u8]]) -> Result<(), Self::Error> {
+        if self.read_only {
+            return Err(BlockDeviceError::WriteProtected);
+        }
+
+        let mut offset = self.offset(start_block, buffers.len())?;
+        for buf in buffers {
+            if buf.len() != LOOP_BLOCK_SIZE {
+                return Err(BlockDeviceError::InvalidBuffer);
+            }
+
+            let mut done = 0;
+            while done < LOOP_BLOCK_SIZE {
+                let n = self
+                    .file
+                    .write(&buf[done..], offset + done)
+                    .map_err(|_| BlockDeviceError::WriteError)?;
+                if n == 0 {
+                    return Err(BlockDeviceError::WriteError);
+                }
+                done += n;
+            }
+            offset += LOOP_BLOCK_SIZE;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        self.file.flush().map_err(|_| BlockDeviceError::IoError)
+    }
+}
+
+/// Attach the file at `path` as the next free loop device and return its
+/// name. A writable device needs the file to be writable.
+pub fn attach(path: &str, read_only: bool) -> Result<String, FsError> {
+    let flags = if read_only {
+        OpenFlags::RDONLY
+    } else {
+        OpenFlags::RDWR
+    };
+    let file = vfs().open(path, flags)?;
+    let dev = LoopDevice::new(file, read_only)?;
+
+    let name = device_manager()
+        .lock()
+        .register_block_numbered(LOOP_PREFIX, dev);
+    log::info!("{}: attached {}", name, path);
+    Ok(name)
+}
+
+/// Detach loop device `name` and its partitions. Mounts and open nodes
+/// that still use it keep the file open until they go away.
+pub fn detach(name: &str) -> Result<(), FsError> {
+    let mut dm = device_manager().lock();
+    if !name.starts_with(LOOP_PREFIX) || dm.block(name).is_none() {
+        return Err(FsError::NotFound);
+    }
+
+    let partitions: Vec<String> = dm
+        .by_class(DeviceClass::Block)
+        .map(|(dev, _)| dev)
+        .filter(|dev| {
+            dev.strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('p'))
+                .is_some_and(|n| n.parse::<u32>().is_ok())
+        })
+        .map(String::from)
+        .collect();
+    for partition in partitions {
+        dm.unregister(&partition);
+    }
+    dm.unregister(name);
+    Ok(())
+}
@@ -0,0 +1,14 @@
+//! Block devices implemented by the kernel itself.
+//!
+//! Hardware disks are driven from the `drivers` crate; the devices here
+//! are backed by kernel objects (files, memory) and registered in the same
+//! device manager, so they can be partitioned, mounted and opened under
+//! `/dev` like any disk.
+//!
+//! The [flusher] writes back what every disk has cached, and [kblockd]
+//! carries out the requests queued for them.
+
+pub mod flusher;
+pub mod kblockd;
+pub mod loop_device;
+pub mod ramdisk;
@@ -0,0 +1,134 @@
+//! RAM disks: block devices backed by pages of memory.
+//!
+//! The contents live only as long as the device and start out zeroed.
+//! One disk, `ram0`, is created at boot; its size is
+//! [`DEFAULT_RAMDISK_SIZE`] unless the command line sets
+//! `ramdisk_size=<KiB>` (0 disables it).
+
+use crate::mm::page_allocator::{PAGE_SIZE, page_allocator};
+use crate::mm::page_table::Page;
+use crate::subsystems::device_manager;
+use alloc::string::String;
+use alloc::vec::Vec;
+use drivers::hal::block_device::{BlockDevice, BlockDeviceError, BlockDeviceInfo};
+use spin::Mutex;
+
+/// Sector size of every RAM disk
+pub const RAMDISK_BLOCK_SIZE: usize = 512;
+
+/// Size of the boot-time `ram0` without `ramdisk_size=`
+pub const DEFAULT_RAMDISK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Name prefix of RAM disks in the device manager
+pub const RAMDISK_PREFIX: &str = "ram";
+
+const SECTORS_PER_PAGE: usize = PAGE_SIZE / RAMDISK_BLOCK_SIZE;
+
+pub struct RamDisk {
+    // Pages need not be contiguous; sector `s` is in page
+    // `s / SECTORS_PER_PAGE`
+    pages: Mutex<Vec<Page>>,
+    block_count: u64,
+}
+
+impl RamDisk {
+    /// Allocate a zeroed disk of `size` bytes, rounded up to whole pages.
+    /// Returns `None` if the page allocator runs out.
+    pub fn new(size: usize) -> Option<Self> {
+        let page_count = size.div_ceil(PAGE_SIZE);
+        let mut pages = Vec::with_capacity(page_count);
+        for _ in 0..page_count {
+            // Pages allocated so far are freed on drop
+            pages.push(page_allocator().alloc()?);
+        }
+
+        Some(Self {
+            pages: Mutex::new(pages),
+            block_count: (page_count * SECTORS_PER_PAGE) as u64,
+        })
+    }
+
+    /// Address of `sector` within its page
+    fn sector_addr(pages: &[Page], sector: u64) -> usize {
+        let sector = sector as usize;
+        pages[sector / SECTORS_PER_PAGE].addr() + sector % SECTORS_PER_PAGE * RAMDISK_BLOCK_SIZE
+    }
+
+    fn check(&self, start_block: u64, count: usize) -> Result<(), BlockDeviceError> {
+        match start_block.checked_add(count as u64) {
+            Some(end) if end <= self.block_count => Ok(()),
+            _ => Err(BlockDeviceError::InvalidAddress),
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    type Error = BlockDeviceError;
+
+    fn info(&self) -> BlockDeviceInfo {
+        BlockDeviceInfo::with_block_size(RAMDISK_BLOCK_SIZE, self.block_count)
+    }
+
+    fn read_blocks(&self, start_block: u64, buffers: &mut [&mut [u8]]) -> Result<(), Self::Error> {
+        self.check(start_block, buffers.len())?;
+        let pages = self.pages.lock();
+
+        for (sector, buf) in (start_block..).zip(buffers.iter_mut()) {
+            if buf.len() != RAMDISK_BLOCK_SIZE {
+                return Err(BlockDeviceError::InvalidBuffer);
+            }
+            let src = Self::sector_addr(&pages, sector) as *const u8;
+            // SAFETY: the sector lies inside a page this disk owns
+            unsafe {
+                core::ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), RAMDISK_BLOCK_SIZE);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, start_block: u64, buffers: &[&[u8]]) -> Result<(), Self::Error> {
+        self.check(start_block, buffers.len())?;
+        let pages = self.pages.lock();
+
+        for (sector, buf) in (start_block..).zip(buffers.iter()) {
+            if buf.len() != RAMDISK_BLOCK_SIZE {
+                return Err(BlockDeviceError::InvalidBuffer);
+            }
+            let dst = Self::sector_addr(&pages, sector) as *mut u8;
+            // SAFETY: the sector lies inside a page this disk owns
+            unsafe {
+                core::ptr::copy_nonoverlapping(buf.as_ptr(), dst, RAMDISK_BLOCK_SIZE);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create a RAM disk of `size` bytes and register it as the next free
+/// `ramN`, returning its name.
+pub fn create(size: usize) -> Option<String> {
+    let disk = RamDisk::new(size)?;
+    Some(
+        device_manager()
+            .lock()
+            .register_block_numbered(RAMDISK_PREFIX, disk),
+    )
+}
+
+crate::initcall!(device, RAMDISK_INIT, init);
+
+/// Create the boot-time `ram0`, sized from the command line.
+pub fn init() {
+    let size = crate::boot::cmdline()
+        .get("ramdisk_size")
+        .and_then(|kib| kib.parse::<usize>().ok())
+        .map_or(DEFAULT_RAMDISK_SIZE, |kib| kib * 1024);
+    if size == 0 {
+        return;
+    }
+
+    match create(size) {
+        Some(name) => log::info!("{}: {} KB RAM disk", name, size / 1024),
+        None => log::warn!("Not enough memory for a {} KB RAM disk", size / 1024),
+    }
+}
@@ -0,0 +1,69 @@
+//! ARM ATAG list parsing.
+//!
+//! Older bootloaders (and the Raspberry Pi firmware with
+//! `disable_device_tree=1`) describe the machine with a tag list at the
+//! address passed in `r2`. The list names no devices, so only the command
+//! line and the initramfs location are taken from it; hardware still comes
+//! from probing.
+
+use drivers::platform::PlatformBuilder;
+
+const ATAG_NONE: u32 = 0x0000_0000;
+pub const ATAG_CORE: u32 = 0x5441_0001;
+const ATAG_INITRD2: u32 = 0x5442_0005;
+const ATAG_CMDLINE: u32 = 0x5441_0009;
+
+/// Upper bound on tags walked, in case the list is not terminated.
+const MAX_TAGS: usize = 64;
+
+/// Does `atags_addr` hold a tag list?
+///
+/// # Safety
+/// `atags_addr` must be readable.
+pub unsafe fn is_atag_list(atags_addr: usize) -> bool {
+    unsafe { *((atags_addr + 4) as *const u32) == ATAG_CORE }
+}
+
+/// Walk the tag list and record the command line and initramfs.
+///
+/// # Safety
+/// `atags_addr` must point to a tag list starting with `ATAG_CORE`.
+pub unsafe fn discover(atags_addr: usize) {
+    let mut tag_addr = atags_addr;
+
+    unsafe {
+        for _ in 0..MAX_TAGS {
+            // Header: size in 32-bit words (including the header), tag
+            let size = *(tag_addr as *const u32) as usize;
+            let tag = *((tag_addr + 4) as *const u32);
+
+            if tag == ATAG_NONE || size < 2 {
+                break;
+            }
+
+            match tag {
+                ATAG_INITRD2 => {
+                    let start = *((tag_addr + 8) as *const u32) as usize;
+                    let len = *((tag_addr + 12) as *const u32) as usize;
+                    if len > 0 {
+                        PlatformBuilder::set_initrd(start, len);
+                    }
+                }
+                ATAG_CMDLINE => parse_cmdline(tag_addr + 8, (size - 2) * 4),
+                _ => {}
+            }
+
+            tag_addr += size * 4;
+        }
+    }
+}
+
+unsafe fn parse_cmdline(addr: usize, max_len: usize) {
+    unsafe {
+        let bytes = core::slice::from_raw_parts(addr as *const u8, max_len);
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(max_len);
+        if let Ok(s) = core::str::from_utf8(&bytes[..len]) {
+            PlatformBuilder::set_cmdline(s);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Kernel command line
+//!
+//! The command line comes with the boot information: an ATAG, the device
+//! tree's `/chosen/bootargs` or a Multiboot2 tag. A Raspberry Pi booted
+//! without either still has the firmware's copy of `cmdline.txt`, which
+//! `from_firmware` asks for over the mailbox.
+//!
+//! It is a list of whitespace-separated `key=value` options and bare
+//! flags. Subsystems query [`cmdline`] during init for the options they
+//! understand:
+//!
+//! - `console=<device>[,<baud>]`: where kernel output goes, given once
+//!   for each console: `tty0` or `fb0` for the framebuffer text console,
+//!   any other device, such as `ttyAMA0` or `ttyS0`, for the serial
+//!   console, followed by its baud rate. Without it, output goes to
+//!   both; the kernel log buffer always keeps it
+//! - `loglevel=<level>`: `off`, `error`, `warn`, `info`, `debug` or
+//!   `trace`, or a Linux console level from 0 to 8
+//! - `root=<device>`: the block device to mount, such as `mmcblk0p2`
+//! - `init=<path>`: the first user program
+//! - `ramdisk_size=<KiB>`: the size of `ram0`
+//! - `ip=dhcp` or `ip=<client>:<server>:<gateway>:<netmask>:...`: how
+//!   to configure the network interfaces, as
+//!   [`ipconfig`](crate::net::ipconfig) describes
+//! - `netconsole=[<port>]@[<ip>]/[<dev>],[<port>]@<ip>/[<mac>]`: send
+//!   the kernel log over UDP, as [`netconsole`](crate::net::netconsole)
+//!   describes
+//! - `panic=<secs>`: reboot that long after a panic, or at once if
+//!   negative; 0, the default, leaves the system stopped
+//!
+//! Where an option is given twice, the last one counts, but for
+//! `console=`.
+
+use drivers::platform::Platform;
+use log::LevelFilter;
+
+/// The parsed command line
+#[derive(Debug, Clone, Copy)]
+pub struct Cmdline {
+    line: &'static str,
+}
+
+/// The kernel command line; empty if the boot information had none
+pub fn cmdline() -> Cmdline {
+    Cmdline {
+        line: Platform::cmdline().unwrap_or(""),
+    }
+}
+
+impl Cmdline {
+    /// The whole line, as given
+    pub fn as_str(&self) -> &'static str {
+        self.line
+    }
+
+    /// The value of the last `key=value` option for `key`
+    pub fn get(&self, key: &str) -> Option<&'static str> {
+        self.line
+            .split_whitespace()
+            .rev()
+            .filter_map(|arg| arg.split_once('='))
+            .find_map(|(name, value)| (name == key).then_some(value))
+    }
+
+    /// Whether the bare flag `flag` was given
+    pub fn has(&self, flag: &str) -> bool {
+        self.line.split_whitespace().any(|arg| arg == flag)
+    }
+
+    /// The `console=` options as device and options, in order
+    fn consoles(&self) -> impl Iterator<Item = (&'static str, Option<&'static str>)> {
+        self.line
+            .split_whitespace()
+            .filter_map(|arg| arg.strip_prefix("console="))
+            .map(|value| match value.split_once(',') {
+                Some((device, options)) => (device, Some(options)),
+                None => (value, None),
+            })
+    }
+
+    /// Whether kernel output goes to `console`: a `console=` option names
+    /// it, or there is none
+    pub fn console_enabled(&self, console: Console) -> bool {
+        let mut named = self
+            .consoles()
+            .map(|(device, _)| Console::of(device))
+            .peekable();
+        named.peek().is_none() || named.any(|named| named == console)
+    }
+
+    /// Baud rate of the serial console, from the last
+    /// `console=<device>,<baud>` naming a serial port. Anything after the
+    /// rate, such as `n8`, is ignored.
+    pub fn console_baud(&self) -> Option<u32> {
+        let (_, options) = self
+            .consoles()
+            .filter(|&(device, _)| Console::of(device) == Console::Serial)
+            .last()?;
+        let options = options?;
+        let digits = options
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(options.len());
+        options[..digits].parse().ok().filter(|&baud| baud != 0)
+    }
+
+    /// Most verbose log level, from `loglevel=`
+    pub fn log_level(&self) -> Option<LevelFilter> {
+        let level = self.get("loglevel")?;
+        match level.parse::<u8>() {
+            // Linux prints messages below the level: errors are 0 to 3,
+            // warnings 4, notices 5, info 6 and debug 7
+            Ok(0) => Some(LevelFilter::Off),
+            Ok(1..=4) => Some(LevelFilter::Error),
+            Ok(5 | 6) => Some(LevelFilter::Warn),
+            Ok(7) => Some(LevelFilter::Info),
+            Ok(_) => Some(LevelFilter::Debug),
+            Err(_) => level.parse().ok(),
+        }
+    }
+
+    /// Block device to mount as the root, from `root=`; a `/dev/` prefix
+    /// is dropped
+    pub fn root(&self) -> Option<&'static str> {
+        let root = self.get("root")?;
+        Some(root.strip_prefix("/dev/").unwrap_or(root)).filter(|root| !root.is_empty())
+    }
+
+    /// Path of the first user program, from `init=`
+    pub fn init(&self) -> Option<&'static str> {
+        self.get("init").filter(|path| path.starts_with('/'))
+    }
+
+    /// Seconds from a panic to the reboot, from `panic=`: negative for at
+    /// once, 0 for never
+    pub fn panic_timeout(&self) -> Option<i32> {
+        self.get("panic")?.parse().ok()
+    }
+}
+
+/// Where kernel output can go, besides the kernel log buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Console {
+    /// The serial console
+    Serial,
+    /// The text console on the framebuffer
+    Framebuffer,
+}
+
+impl Console {
+    /// The console a `console=` device name stands for: `tty` or `fb`
+    /// and a number, as Linux names virtual terminals and framebuffers,
+    /// for the framebuffer, and anything else for the serial console
+    fn of(device: &str) -> Self {
+        let number = device
+            .strip_prefix("tty")
+            .or_else(|| device.strip_prefix("fb"));
+        match number {
+            Some(number) if number.bytes().all(|b| b.is_ascii_digit()) => Console::Framebuffer,
+            _ => Console::Serial,
+        }
+    }
+}
+
+/// Take the command line from the firmware, if the boot information had
+/// none.
+///
+/// # Safety
+/// Must be called during [`init`](super::init), with the mailbox
+/// reachable at its physical address.
+#[cfg(all(target_arch = "arm", not(feature = "bcm2836")))]
+pub(super) unsafe fn from_firmware() {
+    use drivers::peripheral::bcm2835::mailbox::{self, COMMAND_LINE_MAX};
+    use common::sync::Once;
+    use drivers::platform::PlatformBuilder;
+
+    /// The line and its length, kept for good as the platform refers to it
+    static LINE: Once<([u8; COMMAND_LINE_MAX], usize)> = Once::new();
+
+    if Platform::cmdline().is_some() {
+        return;
+    }
+    let mut buf = [0; COMMAND_LINE_MAX];
+    // SAFETY: the caller vouches for the mailbox
+    let Some(len) = (unsafe { mailbox::get_command_line(&mut buf) }) else {
+        return;
+    };
+    let (line, len) = LINE.call_once(|| (buf, len));
+    if let Ok(line) = core::str::from_utf8(&line[..*len]) {
+        PlatformBuilder::set_cmdline(line.trim());
+    }
+}
@@ -18,6 +18,25 @@ pub unsafe fn discover(_dtb_addr: usize) -> Result<(), &'static str> {
         let fdt =
             unsafe { Fdt::from_ptr(_dtb_addr as *const u8).map_err(|_| "invalid device tree")? };
 
+        if let Some(bootargs) = fdt.chosen().bootargs() {
+            PlatformBuilder::set_cmdline(bootargs);
+        }
+
+        // The bootloader records a loaded initramfs in /chosen
+        if let Some(chosen) = fdt.find_node("/chosen") {
+            let start = chosen
+                .property("linux,initrd-start")
+                .and_then(|p| p.as_usize());
+            let end = chosen
+                .property("linux,initrd-end")
+                .and_then(|p| p.as_usize());
+            if let (Some(start), Some(end)) = (start, end)
+                && end > start
+            {
+                PlatformBuilder::set_initrd(start, end - start);
+            }
+        }
+
         for region in fdt.memory().regions() {
             PlatformBuilder::add_memory_region(MemoryRegion {
                 base: region.starting_address as usize,
@@ -26,6 +45,21 @@ pub unsafe fn discover(_dtb_addr: usize) -> Result<(), &'static str> {
             });
         }
 
+        // The blob itself and the firmware's /memreserve/ entries must
+        // survive the page allocator
+        PlatformBuilder::add_memory_region(MemoryRegion {
+            base: _dtb_addr,
+            size: fdt.total_size(),
+            mem_type: MemoryType::Reserved,
+        });
+        for reservation in fdt.memory_reservations() {
+            PlatformBuilder::add_memory_region(MemoryRegion {
+                base: reservation.address() as usize,
+                size: reservation.size(),
+                mem_type: MemoryType::Reserved,
+            });
+        }
+
         for node in fdt.all_nodes() {
             let Some(compatible) = node.compatible() else {
                 continue;
@@ -42,10 +76,29 @@ pub unsafe fn discover(_dtb_addr: usize) -> Result<(), &'static str> {
                 base_addr: region.starting_address as usize,
                 size: region.size.unwrap_or(0),
                 irq: node.interrupts().and_then(|mut i| i.next()),
+                clock_hz: clock_frequency(&fdt, &node),
             });
         }
 
         return Ok(());
+
+        /// Frequency of a node's clock: its own `clock-frequency`, or that
+        /// of the fixed clock its first `clocks` entry points at. `None`
+        /// for clocks a controller generates, which only its driver knows.
+        fn clock_frequency(fdt: &fdt::Fdt, node: &fdt::node::FdtNode) -> Option<u32> {
+            let frequency = |node: &fdt::node::FdtNode| {
+                node.property("clock-frequency")
+                    .and_then(|p| p.as_usize())
+                    .map(|hz| hz as u32)
+            };
+            if let Some(hz) = frequency(node) {
+                return Some(hz);
+            }
+
+            let clocks = node.property("clocks")?.value;
+            let phandle = u32::from_be_bytes(clocks.get(..4)?.try_into().ok()?);
+            frequency(&fdt.find_phandle(phandle)?)
+        }
     }
 
     #[cfg(not(feature = "device-tree"))]
@@ -2,8 +2,10 @@
 //!
 //! This module owns everything that touches boot protocols:
 //!   - Multiboot2 tag parsing  (`multiboot2`)
+//!   - ARM ATAG list parsing    (`atags`)
 //!   - Hardware probing         (`probe`)
 //!   - Device tree parsing      (`device_tree`, stub until DTB support lands)
+//!   - The kernel command line  (`cmdline`)
 //!
 //! The public entry point is [`init`], called from `kmain` with the
 //! boot-protocol arguments GRUB/U-Boot left in registers.
@@ -11,10 +13,14 @@
 //! After [`init`] returns, [`drivers::platform::Platform`] is fully
 //! populated and safe to query from anywhere.
 
+pub mod atags;
+pub mod cmdline;
 pub mod device_tree;
 pub mod multiboot2;
 pub mod probe;
 
+pub use cmdline::cmdline;
+
 use drivers::platform::{Architecture, PlatformBuilder};
 
 /// Boot information passed in from the arch-specific entry point.
@@ -22,6 +28,7 @@ use drivers::platform::{Architecture, PlatformBuilder};
 pub enum BootInfo {
     Multiboot2 { magic: u32, info_addr: usize },
     DeviceTree { dtb_addr: usize },
+    Atags { atags_addr: usize },
     Raw,
 }
 
@@ -47,6 +54,11 @@ pub unsafe fn init(boot_info: BootInfo) -> Result<(), &'static str> {
             multiboot2::discover(magic, info_addr).is_ok()
         },
         BootInfo::DeviceTree { dtb_addr } => unsafe { device_tree::discover(dtb_addr).is_ok() },
+        // ATAGs carry no device list, so probing still runs
+        BootInfo::Atags { atags_addr } => {
+            unsafe { atags::discover(atags_addr) };
+            false
+        }
         BootInfo::Raw => false,
     };
 
@@ -64,6 +76,11 @@ pub unsafe fn init(boot_info: BootInfo) -> Result<(), &'static str> {
         }
     }
 
+    #[cfg(all(target_arch = "arm", not(feature = "bcm2836")))]
+    unsafe {
+        cmdline::from_firmware()
+    };
+
     PlatformBuilder::set_platform_name(determine_platform_name());
     Ok(())
 }
@@ -39,6 +39,7 @@ pub unsafe fn discover(magic: u32, info_addr: usize) -> Result<(), &'static str>
 
             match tag_type {
                 1 => parse_cmdline(tag_addr),
+                3 => parse_module(tag_addr),
                 6 => parse_memory_map(tag_addr)?,
                 8 => parse_framebuffer(tag_addr),
                 _ => {}
@@ -69,6 +70,20 @@ unsafe fn parse_cmdline(tag_addr: usize) {
     }
 }
 
+/// The first boot module is taken as the initramfs.
+unsafe fn parse_module(tag_addr: usize) {
+    if drivers::platform::Platform::initrd().is_some() {
+        return;
+    }
+    unsafe {
+        let mod_start = *((tag_addr + 8) as *const u32) as usize;
+        let mod_end = *((tag_addr + 12) as *const u32) as usize;
+        if mod_end > mod_start {
+            PlatformBuilder::set_initrd(mod_start, mod_end - mod_start);
+        }
+    }
+}
+
 unsafe fn parse_memory_map(tag_addr: usize) -> Result<(), &'static str> {
     unsafe {
         let entry_size = *((tag_addr + 8) as *const u32) as usize;
@@ -114,6 +129,15 @@ unsafe fn parse_framebuffer(tag_addr: usize) {
             base_addr: tag.addr as usize,
             size: (tag.pitch * tag.height) as usize,
             irq: None,
+            clock_hz: None,
+        });
+
+        // Firmware may place the framebuffer in RAM; keep it out of the
+        // page allocator
+        PlatformBuilder::add_memory_region(MemoryRegion {
+            base: tag.addr as usize,
+            size: (tag.pitch * tag.height) as usize,
+            mem_type: MemoryType::Framebuffer,
         });
     }
 }
@@ -5,7 +5,8 @@
 //! devices (serial, PIT, PIC, VGA text) that are always present on a
 //! PC regardless of what GRUB reported.
 
-use drivers::platform::{Architecture, DeviceInfo, PlatformBuilder};
+use drivers::peripheral::bcm2835::mailbox;
+use drivers::platform::{Architecture, DeviceInfo, MemoryRegion, MemoryType, PlatformBuilder};
 
 // x86
 
@@ -29,6 +30,7 @@ pub fn register_standard_pc_devices() {
         base_addr: 0x3F8,
         size: 8,
         irq: Some(4),
+        clock_hz: None,
     });
 
     PlatformBuilder::add_device(DeviceInfo {
@@ -37,6 +39,7 @@ pub fn register_standard_pc_devices() {
         base_addr: 0x40,
         size: 4,
         irq: Some(0),
+        clock_hz: None,
     });
 
     PlatformBuilder::add_device(DeviceInfo {
@@ -45,6 +48,7 @@ pub fn register_standard_pc_devices() {
         base_addr: 0x20,
         size: 2,
         irq: None,
+        clock_hz: None,
     });
 
     PlatformBuilder::add_device(DeviceInfo {
@@ -53,6 +57,7 @@ pub fn register_standard_pc_devices() {
         base_addr: 0xB8000,
         size: 0x8000,
         irq: None,
+        clock_hz: None,
     });
 
     // Conservative fallback memory map (Multiboot2 overrides this with
@@ -104,6 +109,7 @@ fn bcm2835() -> Result<(), &'static str> {
         base_addr: 0x2020_1000,
         size: 0x1000,
         irq: Some(57),
+        clock_hz: None,
     });
     PlatformBuilder::add_device(DeviceInfo {
         name: "timer",
@@ -111,6 +117,7 @@ fn bcm2835() -> Result<(), &'static str> {
         base_addr: 0x2000_3000,
         size: 0x1000,
         irq: Some(1),
+        clock_hz: None,
     });
     PlatformBuilder::add_device(DeviceInfo {
         name: "intc",
@@ -118,8 +125,44 @@ fn bcm2835() -> Result<(), &'static str> {
         base_addr: 0x2000_B200,
         size: 0x200,
         irq: None,
+        clock_hz: None,
     });
-    PlatformBuilder::add_ram_region(0x0000_0000, 512 * 1024 * 1024);
+    PlatformBuilder::add_device(DeviceInfo {
+        name: "mailbox",
+        compatible: mailbox::MAILBOX_COMPATIBLE,
+        base_addr: 0x2000_B880,
+        size: 0x40,
+        irq: None,
+        clock_hz: None,
+    });
+    PlatformBuilder::add_device(DeviceInfo {
+        name: "gpio",
+        compatible: "brcm,bcm2835-gpio",
+        base_addr: 0x2020_0000,
+        size: 0xB4,
+        irq: Some(49),
+        clock_hz: None,
+    });
+    PlatformBuilder::add_device(DeviceInfo {
+        name: "audio",
+        compatible: "brcm,bcm2835-pwm",
+        base_addr: 0x2020_C000,
+        size: 0x28,
+        irq: None,
+        clock_hz: None,
+    });
+    // The firmware splits SDRAM between the ARM and the VideoCore
+    // (`gpu_mem=`); keep the allocators out of the GPU's share
+    let (ram_base, ram_size) =
+        unsafe { mailbox::get_arm_memory() }.unwrap_or((0x0000_0000, 512 * 1024 * 1024));
+    PlatformBuilder::add_ram_region(ram_base, ram_size);
+    if let Some((vc_base, vc_size)) = unsafe { mailbox::get_vc_memory() } {
+        PlatformBuilder::add_memory_region(MemoryRegion {
+            base: vc_base,
+            size: vc_size,
+            mem_type: MemoryType::Reserved,
+        });
+    }
     PlatformBuilder::add_mmio_region(0x2000_0000, 0x0100_0000);
     Ok(())
 }
@@ -131,6 +174,7 @@ fn bcm2836() -> Result<(), &'static str> {
         base_addr: 0x3F20_1000,
         size: 0x1000,
         irq: Some(57),
+        clock_hz: None,
     });
     PlatformBuilder::add_device(DeviceInfo {
         name: "timer",
@@ -138,6 +182,7 @@ fn bcm2836() -> Result<(), &'static str> {
         base_addr: 0,
         size: 0,
         irq: Some(30),
+        clock_hz: None,
     });
     PlatformBuilder::add_device(DeviceInfo {
         name: "intc",
@@ -145,9 +190,19 @@ fn bcm2836() -> Result<(), &'static str> {
         base_addr: 0x3F00_B200,
         size: 0x200,
         irq: None,
+        clock_hz: None,
+    });
+    PlatformBuilder::add_device(DeviceInfo {
+        name: "mailbox",
+        compatible: mailbox::MAILBOX_COMPATIBLE,
+        base_addr: 0x3F00_B880,
+        size: 0x40,
+        irq: None,
+        clock_hz: None,
     });
     PlatformBuilder::add_ram_region(0x0000_0000, 1024 * 1024 * 1024);
     PlatformBuilder::add_mmio_region(0x3F00_0000, 0x0100_0000);
+    PlatformBuilder::add_mmio_region(0x4000_0000, 0x1000); // ARM local peripherals
     Ok(())
 }
 
@@ -158,6 +213,7 @@ fn bcm2837() -> Result<(), &'static str> {
         base_addr: 0x3F20_1000,
         size: 0x1000,
         irq: Some(57),
+        clock_hz: None,
     });
     PlatformBuilder::add_device(DeviceInfo {
         name: "timer",
@@ -165,6 +221,7 @@ fn bcm2837() -> Result<(), &'static str> {
         base_addr: 0,
         size: 0,
         irq: Some(30),
+        clock_hz: None,
     });
     PlatformBuilder::add_device(DeviceInfo {
         name: "intc",
@@ -172,8 +229,18 @@ fn bcm2837() -> Result<(), &'static str> {
         base_addr: 0x3F00_B200,
         size: 0x200,
         irq: None,
+        clock_hz: None,
+    });
+    PlatformBuilder::add_device(DeviceInfo {
+        name: "mailbox",
+        compatible: mailbox::MAILBOX_COMPATIBLE,
+        base_addr: 0x3F00_B880,
+        size: 0x40,
+        irq: None,
+        clock_hz: None,
     });
     PlatformBuilder::add_ram_region(0x0000_0000, 1024 * 1024 * 1024);
     PlatformBuilder::add_mmio_region(0x3F00_0000, 0x0100_0000); // same window as BCM2836
+    PlatformBuilder::add_mmio_region(0x4000_0000, 0x1000); // ARM local peripherals
     Ok(())
 }
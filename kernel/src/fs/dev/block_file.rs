@@ -0,0 +1,146 @@
+use super::super::file::{File, FileStat, FileType};
+use crate::fs::fd::FdError;
+use crate::fs::ioctl;
+use crate::fs::{try_collect, try_zeroed};
+use alloc::string::String;
+use alloc::sync::Arc;
+use drivers::hal::block_device::DynBlockDevice;
+
+/// Block device file - raw byte access to a disk or partition.
+///
+/// The device is addressed in sectors of its own block size. Whole sectors
+/// in the middle of a transfer go straight between the caller's buffer and
+/// the device in one multi-block request; a partial sector at either end
+/// goes through a scratch sector, and writes to one are read-modify-write.
+/// Transfers stop at the end of the device.
+pub struct BlockFile {
+    name: String,
+    dev: Arc<dyn DynBlockDevice>,
+}
+
+impl BlockFile {
+    pub fn new(name: &str, dev: Arc<dyn DynBlockDevice>) -> Self {
+        Self {
+            name: name.into(),
+            dev,
+        }
+    }
+
+    /// Bytes that can be transferred at `offset`, at most `len`
+    fn span(&self, offset: usize, len: usize) -> Result<usize, FdError> {
+        let capacity = self.dev.info().capacity;
+        if offset as u64 > capacity {
+            return Err(FdError::InvalidSeek);
+        }
+        Ok(len.min((capacity - offset as u64) as usize))
+    }
+}
+
+/// Split the byte range `offset..offset + len` at sector boundaries:
+/// bytes before the first boundary, whole sectors, bytes after the last
+fn split(offset: usize, len: usize, bs: usize) -> (usize, usize, usize) {
+    let head = match offset % bs {
+        0 => 0,
+        within => (bs - within).min(len),
+    };
+    let body = (len - head) / bs * bs;
+    (head, body, len - head - body)
+}
+
+impl File for BlockFile {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, FdError> {
+        let len = self.span(offset, buf.len())?;
+        let bs = self.dev.info().block_size;
+        let (head, body, tail) = split(offset, len, bs);
+        let mut scratch = try_zeroed(bs).ok_or(FdError::OutOfMemory)?;
+
+        if head > 0 {
+            let within = offset % bs;
+            self.dev
+                .read_block((offset / bs) as u64, &mut scratch)
+                .map_err(|_| FdError::IoError)?;
+            buf[..head].copy_from_slice(&scratch[within..within + head]);
+        }
+        if body > 0 {
+            let mut sectors = try_collect(buf[head..head + body].chunks_exact_mut(bs))
+                .ok_or(FdError::OutOfMemory)?;
+            self.dev
+                .read_blocks(((offset + head) / bs) as u64, &mut sectors)
+                .map_err(|_| FdError::IoError)?;
+        }
+        if tail > 0 {
+            let start = head + body;
+            self.dev
+                .read_block(((offset + start) / bs) as u64, &mut scratch)
+                .map_err(|_| FdError::IoError)?;
+            buf[start..len].copy_from_slice(&scratch[..tail]);
+        }
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8], offset: usize) -> Result<usize, FdError> {
+        let info = self.dev.info();
+        if info.read_only {
+            return Err(FdError::PermissionDenied);
+        }
+        let len = self.span(offset, buf.len())?;
+        let bs = info.block_size;
+        let (head, body, tail) = split(offset, len, bs);
+        let mut scratch = try_zeroed(bs).ok_or(FdError::OutOfMemory)?;
+
+        if head > 0 {
+            let within = offset % bs;
+            let lba = (offset / bs) as u64;
+            self.dev
+                .read_block(lba, &mut scratch)
+                .map_err(|_| FdError::IoError)?;
+            scratch[within..within + head].copy_from_slice(&buf[..head]);
+            self.dev
+                .write_block(lba, &scratch)
+                .map_err(|_| FdError::IoError)?;
+        }
+        if body > 0 {
+            let sectors =
+                try_collect(buf[head..head + body].chunks_exact(bs)).ok_or(FdError::OutOfMemory)?;
+            self.dev
+                .write_blocks(((offset + head) / bs) as u64, &sectors)
+                .map_err(|_| FdError::IoError)?;
+        }
+        if tail > 0 {
+            let start = head + body;
+            let lba = ((offset + start) / bs) as u64;
+            self.dev
+                .read_block(lba, &mut scratch)
+                .map_err(|_| FdError::IoError)?;
+            scratch[..tail].copy_from_slice(&buf[start..len]);
+            self.dev
+                .write_block(lba, &scratch)
+                .map_err(|_| FdError::IoError)?;
+        }
+        Ok(len)
+    }
+
+    fn stat(&self) -> Result<FileStat, FdError> {
+        Ok(FileStat {
+            file_type: FileType::BlockDevice,
+            size: self.dev.info().capacity as usize,
+            name: self.name.clone(),
+        })
+    }
+
+    fn flush(&self) -> Result<(), FdError> {
+        self.dev.flush().map_err(|_| FdError::IoError)
+    }
+
+    fn ioctl(&self, cmd: u32, _arg: usize) -> Result<usize, FdError> {
+        let info = self.dev.info();
+        match cmd {
+            ioctl::BLKSSZGET => Ok(info.block_size),
+            ioctl::BLKGETSIZE => {
+                usize::try_from(info.block_count).map_err(|_| FdError::InvalidArgument)
+            }
+            ioctl::BLKFLSBUF => self.flush().map(|_| 0),
+            _ => Err(FdError::NotSupported),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! `/dev/console` - the system console as a terminal
+//!
+//! Reads go through the [line discipline](super::tty), fed from the
+//! serial console's input, so a program reads whole lines that were
+//! echoed and could be edited as they were typed. Writes, and the echo,
+//! go to every console kernel output goes to, so a screen on the
+//! framebuffer shows them too. Terminal ioctls apply to the serial
+//! console.
+
+use super::super::file::{File, FileStat, FileType, PollEvents};
+use super::UartFile;
+use super::tty::LineDiscipline;
+use crate::fs::fd::FdError;
+use crate::subsystems::log_sinks::console_write;
+use alloc::vec::Vec;
+use common::sync::Lazy;
+use spin::Mutex;
+
+/// Where typed input comes from
+static INPUT: Lazy<UartFile> = Lazy::new(|| UartFile::new(0));
+
+/// Input typed but not yet read, shared by every open console
+static DISCIPLINE: Mutex<LineDiscipline> = Mutex::new(LineDiscipline::new());
+
+pub struct ConsoleFile;
+
+impl ConsoleFile {
+    /// Pass what `read` takes from the port through the line discipline,
+    /// and echo it
+    fn feed(read: impl FnOnce(&mut [u8]) -> Result<usize, FdError>) -> Result<(), FdError> {
+        let mut raw = [0u8; 64];
+        let n = read(&mut raw)?;
+        let mut echo = Vec::new();
+        DISCIPLINE.lock().input(&raw[..n], &mut echo);
+        // Written with the discipline unlocked: the consoles take locks of
+        // their own
+        console_write(&echo);
+        Ok(())
+    }
+}
+
+impl File for ConsoleFile {
+    /// Sleep until a line has been typed, then return what fits of it.
+    fn read(&self, buf: &mut [u8], _offset: usize) -> Result<usize, FdError> {
+        loop {
+            if let Some(n) = DISCIPLINE.lock().read(buf) {
+                return Ok(n);
+            }
+            Self::feed(|raw| INPUT.read(raw, 0))?;
+        }
+    }
+
+    fn try_read(&self, buf: &mut [u8], _offset: usize) -> Result<usize, FdError> {
+        loop {
+            if let Some(n) = DISCIPLINE.lock().read(buf) {
+                return Ok(n);
+            }
+            Self::feed(|raw| INPUT.try_read(raw, 0))?;
+        }
+    }
+
+    /// Readable once a whole line has been typed, not at the first key.
+    fn poll(&self) -> PollEvents {
+        while !DISCIPLINE.lock().has_line() {
+            let events = INPUT.poll();
+            if events.contains(PollEvents::ERR) {
+                return PollEvents::ERR;
+            }
+            if !events.contains(PollEvents::IN) {
+                return PollEvents::OUT;
+            }
+            if Self::feed(|raw| INPUT.try_read(raw, 0)).is_err() {
+                return PollEvents::OUT;
+            }
+        }
+        PollEvents::IN | PollEvents::OUT
+    }
+
+    fn write(&self, buf: &[u8], _offset: usize) -> Result<usize, FdError> {
+        console_write(buf);
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> Result<FileStat, FdError> {
+        Ok(FileStat {
+            file_type: FileType::CharDevice,
+            size: 0,
+            name: "console".into(),
+        })
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> Result<usize, FdError> {
+        INPUT.ioctl(cmd, arg)
+    }
+}
@@ -1,7 +1,9 @@
 use super::super::file::{File, FileStat};
 use crate::fs::fd::FdError;
 use crate::fs::file::FileType;
+use crate::fs::ioctl::{self, FbIoctlInfo};
 use crate::subsystems::device_manager;
+use crate::syscall::uaccess;
 use alloc::format;
 use alloc::string::String;
 use drivers::hal::fb::FrameBuffer;
@@ -112,4 +114,28 @@ impl File for FrameBufferFile {
             name: self.device_name(),
         })
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> Result<usize, FdError> {
+        match cmd {
+            ioctl::FBIOGET_INFO => {
+                let fb = device_manager()
+                    .lock()
+                    .framebuffer(self.device_name().as_str())
+                    .ok_or(FdError::Other("No such device".into()))?;
+                let info = {
+                    let fb = fb.lock();
+                    FbIoctlInfo {
+                        width: fb.width() as u32,
+                        height: fb.height() as u32,
+                        pitch: fb.pitch() as u32,
+                        bytes_per_pixel: fb.bytes_per_pixel() as u32,
+                        pixel_format: ioctl::pixel_format_code(fb.pixel_format()),
+                    }
+                };
+                uaccess::copy_to_user(arg, info)?;
+                Ok(0)
+            }
+            _ => Err(FdError::NotSupported),
+        }
+    }
 }
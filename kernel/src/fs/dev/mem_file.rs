@@ -0,0 +1,94 @@
+use super::super::file::{File, FileStat, FileType};
+use crate::fs::fd::FdError;
+use crate::subsystems::uptime_us;
+use spin::Mutex;
+
+/// `/dev/null` - reads end of file, writes are discarded
+pub struct NullFile;
+
+impl File for NullFile {
+    fn read(&self, _buf: &mut [u8], _offset: usize) -> Result<usize, FdError> {
+        Ok(0)
+    }
+
+    fn write(&self, buf: &[u8], _offset: usize) -> Result<usize, FdError> {
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> Result<FileStat, FdError> {
+        Ok(FileStat {
+            file_type: FileType::CharDevice,
+            size: 0,
+            name: "null".into(),
+        })
+    }
+}
+
+/// `/dev/zero` - reads zeros forever, writes are discarded
+pub struct ZeroFile;
+
+impl File for ZeroFile {
+    fn read(&self, buf: &mut [u8], _offset: usize) -> Result<usize, FdError> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8], _offset: usize) -> Result<usize, FdError> {
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> Result<FileStat, FdError> {
+        Ok(FileStat {
+            file_type: FileType::CharDevice,
+            size: 0,
+            name: "zero".into(),
+        })
+    }
+}
+
+/// xorshift64* state shared by every `/dev/random` handle; 0 means unseeded
+static RANDOM_STATE: Mutex<u64> = Mutex::new(0);
+
+/// `/dev/random` - pseudo-random bytes.
+///
+/// There is no hardware RNG driver yet, so this is xorshift64* seeded from
+/// the system timer on first use. Fine for test data and hash seeds, not
+/// for anything that needs to be unpredictable. Writes are discarded.
+pub struct RandomFile;
+
+impl RandomFile {
+    fn next(&self) -> u64 {
+        let mut state = RANDOM_STATE.lock();
+        if *state == 0 {
+            *state = uptime_us().unwrap_or(0) ^ 0x9E37_79B9_7F4A_7C15;
+        }
+        let mut x = *state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        *state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+impl File for RandomFile {
+    fn read(&self, buf: &mut [u8], _offset: usize) -> Result<usize, FdError> {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8], _offset: usize) -> Result<usize, FdError> {
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> Result<FileStat, FdError> {
+        Ok(FileStat {
+            file_type: FileType::CharDevice,
+            size: 0,
+            name: "random".into(),
+        })
+    }
+}
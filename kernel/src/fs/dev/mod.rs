@@ -1,14 +1,50 @@
-use super::file::{File, FileStat};
-use super::{FileSystem, FsError};
-use alloc::collections::BTreeMap;
+//! Device filesystem (`/dev`).
+//!
+//! Nodes are generated from the device-manager registry on every lookup,
+//! so devices registered or removed at runtime show up immediately:
+//! serial ports as `uartN`, framebuffers as `fbN` and disks and their
+//! partitions as `mmcblkN` / `mmcblkNpM`. `console`, `null`, `zero` and
+//! `random` always exist. Files registered explicitly with
+//! [`DevFs::register_device`] take precedence over generated ones.
+
+use super::file::{DirEntryInfo, File, FileStat, FileType, OpenFlags};
+use super::{FileSystem, FsError, FsStat};
+use crate::subsystems::device_manager;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use drivers::device_manager::{Device, DeviceClass};
 use spin::Mutex;
 pub use uart_file::UartFile;
+pub mod block_file;
+pub mod console_file;
 pub mod framebuffer_file;
+pub mod mem_file;
+pub mod tty;
 pub mod uart_file;
+pub use block_file::BlockFile;
+pub use console_file::ConsoleFile;
 pub use framebuffer_file::FrameBufferFile;
+pub use mem_file::{NullFile, RandomFile, ZeroFile};
+
+/// Creates the file behind a built-in node
+type Constructor = fn() -> Arc<dyn File>;
+
+/// Nodes that exist on every system, sorted by name
+const BUILTIN: &[(&str, Constructor)] = &[
+    ("console", || Arc::new(ConsoleFile)),
+    ("null", || Arc::new(NullFile)),
+    ("random", || Arc::new(RandomFile)),
+    ("zero", || Arc::new(ZeroFile)),
+];
+
+/// Device classes that have a file interface
+const FILE_CLASSES: &[DeviceClass] = &[
+    DeviceClass::Serial,
+    DeviceClass::Block,
+    DeviceClass::FrameBuffer,
+];
 
 pub struct DevFs {
     devices: Mutex<BTreeMap<String, Arc<dyn File>>>,
@@ -24,15 +60,72 @@ impl DevFs {
     pub fn register_device(&self, name: &str, device: Arc<dyn File>) {
         self.devices.lock().insert(name.into(), device);
     }
+
+    /// The file for node `name`, if it exists
+    fn node(&self, name: &str) -> Option<Arc<dyn File>> {
+        if let Some(file) = self.devices.lock().get(name) {
+            return Some(Arc::clone(file));
+        }
+        if let Some(&(_, make)) = BUILTIN.iter().find(|(n, _)| *n == name) {
+            return Some(make());
+        }
+        Self::device_node(name)
+    }
+
+    /// Generate the file for a registered device. Only stable names are
+    /// accepted, so `ls` and `open` agree.
+    fn device_node(name: &str) -> Option<Arc<dyn File>> {
+        let class = {
+            let dm = device_manager().lock();
+            if dm.resolve(name)? != name {
+                return None;
+            }
+            match dm.get(name)? {
+                Device::Block(dev) => {
+                    return Some(Arc::new(BlockFile::new(name, Arc::clone(dev))));
+                }
+                dev => dev.class(),
+            }
+        };
+
+        // The per-index files look their device up again by name, so the
+        // device manager must be unlocked here
+        let index = name.strip_prefix(class.prefix())?.parse().ok()?;
+        match class {
+            DeviceClass::Serial => Some(Arc::new(UartFile::new(index))),
+            DeviceClass::FrameBuffer => FrameBufferFile::new(index)
+                .ok()
+                .map(|fb| Arc::new(fb) as Arc<dyn File>),
+            _ => None,
+        }
+    }
+
+    /// Every node name, sorted
+    fn names(&self) -> BTreeSet<String> {
+        let mut names: BTreeSet<String> = self.devices.lock().keys().cloned().collect();
+        names.extend(BUILTIN.iter().map(|(name, _)| String::from(*name)));
+
+        let dm = device_manager().lock();
+        names.extend(
+            dm.devices()
+                .filter(|(_, dev)| FILE_CLASSES.contains(&dev.class()))
+                .map(|(name, _)| String::from(name)),
+        );
+        names
+    }
+
+    fn is_root(path: &str) -> bool {
+        path.trim_start_matches('/').is_empty()
+    }
 }
 
 impl FileSystem for DevFs {
-    fn open(&self, path: &str) -> Result<Arc<dyn File>, FsError> {
-        let path = path.trim_start_matches('/');
-        self.devices
-            .lock()
-            .get(path)
-            .cloned()
+    /// Devices always exist and have no length, so flags are ignored.
+    fn open(&self, path: &str, _flags: OpenFlags) -> Result<Arc<dyn File>, FsError> {
+        if Self::is_root(path) {
+            return Err(FsError::IsADirectory);
+        }
+        self.node(path.trim_start_matches('/'))
             .ok_or(FsError::NotFound)
     }
 
@@ -45,13 +138,38 @@ impl FileSystem for DevFs {
     }
 
     fn ls(&self, path: &str) -> Result<Vec<String>, FsError> {
-        if path == "/" || path.is_empty() {
-            Ok(self.devices.lock().keys().cloned().collect())
+        if Self::is_root(path) {
+            Ok(self.names().into_iter().collect())
         } else {
             Err(FsError::NotADirectory)
         }
     }
 
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntryInfo>, FsError> {
+        if !Self::is_root(path) {
+            return Err(FsError::NotADirectory);
+        }
+
+        Ok(self
+            .names()
+            .into_iter()
+            // A device may vanish between listing and lookup
+            .filter_map(|name| {
+                let device = self.node(&name)?;
+                // Devices without stat are reported as character devices
+                let (file_type, size) = device
+                    .stat()
+                    .map(|s| (s.file_type, s.size))
+                    .unwrap_or((FileType::CharDevice, 0));
+                Some(DirEntryInfo {
+                    name,
+                    file_type,
+                    size,
+                })
+            })
+            .collect())
+    }
+
     fn mkdir(&self, _path: &str) -> Result<(), FsError> {
         Err(FsError::PermissionDenied)
     }
@@ -60,10 +178,63 @@ impl FileSystem for DevFs {
         Err(FsError::PermissionDenied)
     }
 
+    fn rename(&self, _old_path: &str, _new_path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn symlink(&self, _target: &str, _link_path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn readlink(&self, path: &str) -> Result<String, FsError> {
+        if Self::is_root(path) || self.node(path.trim_start_matches('/')).is_some() {
+            Err(FsError::InvalidPath)
+        } else {
+            Err(FsError::NotFound)
+        }
+    }
+
+    fn statvfs(&self, _path: &str) -> Result<FsStat, FsError> {
+        Err(FsError::NotSupported)
+    }
+
+    /// Flush the cache of every block device. All are attempted; the
+    /// first error is returned.
+    fn sync(&self) -> Result<(), FsError> {
+        // Flushing writes to the card, so the device manager is unlocked
+        // first
+        let disks: Vec<_> = device_manager()
+            .lock()
+            .devices()
+            .filter_map(|(name, dev)| match dev {
+                Device::Block(dev) => Some((String::from(name), Arc::clone(dev))),
+                _ => None,
+            })
+            .collect();
+
+        let mut result = Ok(());
+        for (name, disk) in disks {
+            if let Err(e) = disk.flush() {
+                log::warn!("devfs: flush of {} failed: {:?}", name, e);
+                if result.is_ok() {
+                    result = Err(FsError::IoError);
+                }
+            }
+        }
+        result
+    }
+
     fn stat(&self, path: &str) -> Result<FileStat, FsError> {
-        let path = path.trim_start_matches('/');
-        let devices = self.devices.lock();
-        let device = devices.get(path).ok_or(FsError::NotFound)?;
+        if Self::is_root(path) {
+            return Ok(FileStat {
+                size: 0,
+                file_type: FileType::Directory,
+                name: "/".into(),
+            });
+        }
+        let device = self
+            .node(path.trim_start_matches('/'))
+            .ok_or(FsError::NotFound)?;
         device.stat().map_err(|e| FsError::from(e))
     }
 }
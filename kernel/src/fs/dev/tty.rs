@@ -0,0 +1,109 @@
+//! TTY line discipline
+//!
+//! Turns the bytes typed on a terminal into the lines a program reads, as
+//! a Unix terminal does in canonical mode: input is held back and can be
+//! edited until Enter, and what is typed is echoed.
+//!
+//! - Enter (CR, LF or CR LF) ends the line, which is read with its `\n`
+//! - Backspace and DEL erase the last character, Ctrl-U the whole line
+//! - Ctrl-C throws the line away
+//! - Ctrl-D hands the line over without a newline; on an empty line,
+//!   that is a read of 0 bytes, end of file
+//!
+//! Other control characters are dropped.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Longest line; what is typed past it is dropped
+pub const LINE_MAX: usize = 1024;
+
+/// Lines typed but not read, beyond which more are dropped
+const LINES_MAX: usize = 16;
+
+/// Canonical-mode input state of one terminal
+pub struct LineDiscipline {
+    /// The line being typed
+    line: Vec<u8>,
+    /// Lines ended but not yet read, oldest first; an empty one is an end
+    /// of file
+    ready: VecDeque<Vec<u8>>,
+    /// The last byte was a CR, so an LF after it ends no line
+    after_cr: bool,
+}
+
+impl LineDiscipline {
+    pub const fn new() -> Self {
+        Self {
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            after_cr: false,
+        }
+    }
+
+    /// Take in bytes typed, appending to `echo` what to show for them.
+    pub fn input(&mut self, bytes: &[u8], echo: &mut Vec<u8>) {
+        for &byte in bytes {
+            let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+            match byte {
+                b'\n' if after_cr => {}
+                b'\r' | b'\n' => {
+                    self.line.push(b'\n');
+                    self.end_line();
+                    echo.push(b'\n');
+                }
+                // Backspace and DEL
+                0x08 | 0x7f => {
+                    if self.line.pop().is_some() {
+                        echo.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+                // Ctrl-U
+                0x15 => {
+                    for _ in self.line.drain(..) {
+                        echo.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+                // Ctrl-C
+                0x03 => {
+                    self.line.clear();
+                    echo.extend_from_slice(b"^C\n");
+                }
+                // Ctrl-D
+                0x04 => self.end_line(),
+                0x20..=0x7e if self.line.len() < LINE_MAX => {
+                    self.line.push(byte);
+                    echo.push(byte);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether a line, or an end of file, is waiting to be read
+    pub fn has_line(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    /// Copy out what fits of the oldest line, or `None` if no line is
+    /// ready. A line too long for `buf` is finished by the next reads.
+    pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let line = self.ready.front_mut()?;
+        let n = line.len().min(buf.len());
+        buf[..n].copy_from_slice(&line[..n]);
+        line.drain(..n);
+        // A read into an empty buffer leaves an end of file for the next
+        if line.is_empty() && !buf.is_empty() {
+            self.ready.pop_front();
+        }
+        Some(n)
+    }
+
+    /// Hand the line being typed over to readers
+    fn end_line(&mut self) {
+        let line = core::mem::take(&mut self.line);
+        if self.ready.len() < LINES_MAX {
+            self.ready.push_back(line);
+        }
+    }
+}
@@ -1,12 +1,26 @@
-use super::super::file::{File, FileStat, FileType};
+use super::super::file::{File, FileStat, FileType, PollEvents};
 use crate::fs::fd::FdError;
-use crate::subsystems::device_manager;
+use crate::fs::ioctl;
+use crate::kcore::console;
+use crate::process::sched::WaitQueue;
+use crate::subsystems::{IrqDevice, serial, serial_console};
 use alloc::string::String;
-use drivers::hal::serial::DynSerialPort;
+use drivers::hal::serial::{DynSerialPort, SerialConfig, SerialError};
+use spin::Mutex;
+
+/// Readers waiting for input on ports other than the console, which
+/// check every timer tick
+static INPUT: WaitQueue = WaitQueue::new();
 
 /// UART device file - provides file interface to serial ports
 pub struct UartFile {
     index: usize,
+    // Last configuration applied through this file; the HAL cannot read
+    // back a port's settings, so this starts at the boot default
+    config: Mutex<SerialConfig>,
+    // Byte taken from the port by `poll` to find out whether input is
+    // waiting; handed out first by the next read
+    peeked: Mutex<Option<u8>>,
 }
 
 impl UartFile {
@@ -15,38 +29,131 @@ impl UartFile {
     /// # Arguments
     /// - `index`: 0 for console/uart0, 1+ for other UARTs if available
     pub fn new(index: usize) -> Self {
-        Self { index }
+        Self {
+            index,
+            config: Mutex::new(SerialConfig::default()),
+            peeked: Mutex::new(None),
+        }
     }
 
     /// Get the device name for this UART
     fn device_name(&self) -> String {
-        if self.index == 0 {
-            "console".into()
-        } else {
-            alloc::format!("uart{}", self.index)
-        }
+        alloc::format!("uart{}", self.index)
+    }
+
+    /// The port behind this file; index 0 falls back to the console so
+    /// stdio works whatever the platform named its first UART
+    fn port(&self) -> Result<IrqDevice<dyn DynSerialPort>, FdError> {
+        serial(self.device_name().as_str())
+            .or_else(|| (self.index == 0).then(serial_console).flatten())
+            .ok_or(FdError::IoError)
     }
 }
 
 impl File for UartFile {
-    fn read(&self, buf: &mut [u8], _offset: usize) -> Result<usize, FdError> {
-        let device_mgr = device_manager().lock();
-        let serial = device_mgr
-            .serial(&self.device_name().as_str())
-            .ok_or(FdError::IoError)?;
-
-        if let Some(nb) = serial.lock().as_nonblocking() {
-            return nb.try_read(buf).map_err(|_| FdError::IoError);
+    /// Sleep until input arrives, then return what has arrived. Console
+    /// readers are woken as input is queued; other ports are checked
+    /// every timer tick.
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, FdError> {
+        if console::owns(&self.port()?) {
+            return Ok(console::console_read_blocking(buf));
+        }
+        let mut result = Err(FdError::WouldBlock);
+        while result == Err(FdError::WouldBlock) {
+            INPUT.sleep_on_timeout(
+                || {
+                    result = self.try_read(buf, offset);
+                    result != Err(FdError::WouldBlock)
+                },
+                1,
+            );
+        }
+        result
+    }
+
+    /// Ports without non-blocking support cannot tell whether input is
+    /// waiting, so on those this blocks until `buf` is full.
+    fn try_read(&self, buf: &mut [u8], _offset: usize) -> Result<usize, FdError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut n = 0;
+        if let Some(byte) = self.peeked.lock().take() {
+            buf[0] = byte;
+            n = 1;
+        }
+
+        let serial = self.port()?;
+        // Console input is queued, past the SysRq monitor
+        if console::owns(&serial) {
+            n += console::console_read(&mut buf[n..]);
+            return if n == 0 {
+                Err(FdError::WouldBlock)
+            } else {
+                Ok(n)
+            };
+        }
+        let mut port = serial.lock();
+        let Some(nb) = port.as_nonblocking() else {
+            return port
+                .read(&mut buf[n..])
+                .map(|read| n + read)
+                .map_err(|_| FdError::IoError);
+        };
+
+        while n < buf.len() {
+            match nb.try_read_byte() {
+                Ok(byte) => {
+                    buf[n] = byte;
+                    n += 1;
+                }
+                Err(SerialError::WouldBlock) => break,
+                // A garbled character is dropped; the port stays usable
+                Err(e) if e.is_line_error() => {}
+                Err(_) => return Err(FdError::IoError),
+            }
+        }
+
+        if n == 0 {
+            Err(FdError::WouldBlock)
         } else {
-            return serial.lock().read(buf).map_err(|_| FdError::IoError);
+            Ok(n)
+        }
+    }
+
+    fn poll(&self) -> PollEvents {
+        let mut peeked = self.peeked.lock();
+        if peeked.is_some() {
+            return PollEvents::IN | PollEvents::OUT;
+        }
+        let Ok(serial) = self.port() else {
+            return PollEvents::ERR;
+        };
+        if console::owns(&serial) {
+            return if console::has_input() {
+                PollEvents::IN | PollEvents::OUT
+            } else {
+                PollEvents::OUT
+            };
+        }
+
+        let mut port = serial.lock();
+        match port.as_nonblocking() {
+            Some(nb) => match nb.try_read_byte() {
+                Ok(byte) => {
+                    *peeked = Some(byte);
+                    PollEvents::IN | PollEvents::OUT
+                }
+                Err(_) => PollEvents::OUT,
+            },
+            // Unknown; a read will wait for input
+            None => PollEvents::IN | PollEvents::OUT,
         }
     }
 
     fn write(&self, buf: &[u8], _offset: usize) -> Result<usize, FdError> {
-        let device_mgr = device_manager().lock();
-        let serial = device_mgr
-            .serial(&self.device_name().as_str())
-            .ok_or(FdError::IoError)?;
+        let serial = self.port()?;
 
         let mut uart = serial.lock();
         uart.write(buf).map_err(|_| FdError::IoError)?;
@@ -60,4 +167,37 @@ impl File for UartFile {
             name: self.device_name(),
         })
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> Result<usize, FdError> {
+        let mut config = self.config.lock();
+
+        let new_config = match cmd {
+            ioctl::TIOCGBAUD => return Ok(config.baud_rate as usize),
+            ioctl::TIOCGFORMAT => return Ok(ioctl::encode_format(&config)),
+            ioctl::TIOCSBAUD => SerialConfig {
+                baud_rate: u32::try_from(arg)
+                    .ok()
+                    .filter(|&baud| baud != 0)
+                    .ok_or(FdError::InvalidArgument)?,
+                ..*config
+            },
+            ioctl::TIOCSFORMAT => {
+                ioctl::decode_format(&config, arg).ok_or(FdError::InvalidArgument)?
+            }
+            ioctl::TIOCDRAIN => *config,
+            _ => return Err(FdError::NotSupported),
+        };
+
+        let serial = self.port()?;
+        let mut uart = serial.lock();
+
+        // Queued output always leaves at the old settings
+        uart.flush().map_err(|_| FdError::IoError)?;
+        if cmd != ioctl::TIOCDRAIN {
+            uart.reconfigure(new_config)
+                .map_err(|_| FdError::InvalidArgument)?;
+            *config = new_config;
+        }
+        Ok(0)
+    }
 }
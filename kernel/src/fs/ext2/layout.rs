@@ -0,0 +1,261 @@
+//! ext2 on-disk structures.
+//!
+//! Only the fields the read-only driver needs are decoded. All values are
+//! little-endian.
+
+use super::Ext2Error;
+use crate::fs::file::FileType;
+
+/// Byte offset of the superblock from the start of the volume
+pub const SUPERBLOCK_OFFSET: u64 = 1024;
+pub const SUPERBLOCK_SIZE: usize = 1024;
+
+const EXT2_MAGIC: u16 = 0xEF53;
+
+/// Inode number of the root directory
+pub const ROOT_INODE: u32 = 2;
+
+/// Revision 0 has fixed 128-byte inodes and no feature flags
+const GOOD_OLD_REV: u32 = 0;
+const GOOD_OLD_INODE_SIZE: u16 = 128;
+
+/// Directory entries carry a file type byte
+pub const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// Incompatible features a read-only driver can ignore or handles
+const INCOMPAT_SUPPORTED: u32 = INCOMPAT_FILETYPE;
+
+/// Inode uses ext4 extents instead of the block map
+const INODE_FLAG_EXTENTS: u32 = 0x0008_0000;
+
+/// Number of block pointers in an inode
+pub const N_BLOCKS: usize = 15;
+/// Direct pointers before the single, double and triple indirect ones
+pub const N_DIRECT: usize = 12;
+
+fn u16_at(raw: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([raw[offset], raw[offset + 1]])
+}
+
+fn u32_at(raw: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        raw[offset],
+        raw[offset + 1],
+        raw[offset + 2],
+        raw[offset + 3],
+    ])
+}
+
+// ============================================================================
+// Superblock
+// ============================================================================
+
+#[derive(Debug, Clone, Copy)]
+pub struct Superblock {
+    pub inodes_count: u32,
+    pub blocks_count: u32,
+    pub free_blocks_count: u32,
+    pub first_data_block: u32,
+    pub block_size: usize,
+    pub blocks_per_group: u32,
+    pub inodes_per_group: u32,
+    pub inode_size: u16,
+    pub feature_incompat: u32,
+}
+
+impl Superblock {
+    pub fn parse(raw: &[u8]) -> Result<Self, Ext2Error> {
+        if u16_at(raw, 56) != EXT2_MAGIC {
+            return Err(Ext2Error::BadSuperblock);
+        }
+
+        let log_block_size = u32_at(raw, 24);
+        if log_block_size > 6 {
+            return Err(Ext2Error::BadSuperblock);
+        }
+
+        let rev_level = u32_at(raw, 76);
+        let (inode_size, feature_incompat) = if rev_level == GOOD_OLD_REV {
+            (GOOD_OLD_INODE_SIZE, 0)
+        } else {
+            (u16_at(raw, 88), u32_at(raw, 96))
+        };
+
+        // Journal recovery, extents, 64-bit and friends change the layout
+        if feature_incompat & !INCOMPAT_SUPPORTED != 0 {
+            return Err(Ext2Error::Unsupported);
+        }
+
+        let sb = Self {
+            inodes_count: u32_at(raw, 0),
+            blocks_count: u32_at(raw, 4),
+            free_blocks_count: u32_at(raw, 12),
+            first_data_block: u32_at(raw, 20),
+            block_size: 1024 << log_block_size,
+            blocks_per_group: u32_at(raw, 32),
+            inodes_per_group: u32_at(raw, 40),
+            inode_size,
+            feature_incompat,
+        };
+
+        // Groups are counted from the first data block on
+        if sb.first_data_block >= sb.blocks_count
+            || sb.blocks_per_group == 0
+            || sb.inodes_per_group == 0
+            || (sb.inode_size as usize) < GOOD_OLD_INODE_SIZE as usize
+            || sb.inode_size as usize > sb.block_size
+        {
+            return Err(Ext2Error::BadSuperblock);
+        }
+        Ok(sb)
+    }
+
+    pub fn group_count(&self) -> u32 {
+        (self.blocks_count - self.first_data_block).div_ceil(self.blocks_per_group)
+    }
+
+    /// Block holding the first group descriptor
+    pub fn group_table_block(&self) -> u32 {
+        self.first_data_block + 1
+    }
+}
+
+// ============================================================================
+// Block Group Descriptor
+// ============================================================================
+
+pub const GROUP_DESC_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GroupDesc {
+    /// First block of the group's inode table
+    pub inode_table: u32,
+}
+
+impl GroupDesc {
+    pub fn parse(raw: &[u8]) -> Self {
+        Self {
+            inode_table: u32_at(raw, 8),
+        }
+    }
+}
+
+// ============================================================================
+// Inode
+// ============================================================================
+
+const S_IFMT: u16 = 0xF000;
+const S_IFSOCK: u16 = 0xC000;
+const S_IFLNK: u16 = 0xA000;
+const S_IFREG: u16 = 0x8000;
+const S_IFBLK: u16 = 0x6000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFCHR: u16 = 0x2000;
+const S_IFIFO: u16 = 0x1000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Inode {
+    pub mode: u16,
+    pub size: u64,
+    /// 512-byte sectors allocated, including indirect blocks
+    pub sectors: u32,
+    pub flags: u32,
+    pub block: [u32; N_BLOCKS],
+}
+
+impl Inode {
+    pub fn parse(raw: &[u8]) -> Result<Self, Ext2Error> {
+        let mode = u16_at(raw, 0);
+        let flags = u32_at(raw, 32);
+        if flags & INODE_FLAG_EXTENTS != 0 {
+            return Err(Ext2Error::Unsupported);
+        }
+
+        let mut size = u32_at(raw, 4) as u64;
+        // Upper half of the size for regular files (LARGE_FILE)
+        if mode & S_IFMT == S_IFREG {
+            size |= (u32_at(raw, 108) as u64) << 32;
+        }
+
+        let mut block = [0u32; N_BLOCKS];
+        for (i, ptr) in block.iter_mut().enumerate() {
+            *ptr = u32_at(raw, 40 + i * 4);
+        }
+
+        Ok(Self {
+            mode,
+            size,
+            sectors: u32_at(raw, 28),
+            flags,
+            block,
+        })
+    }
+
+    pub fn file_type(&self) -> FileType {
+        match self.mode & S_IFMT {
+            S_IFDIR => FileType::Directory,
+            S_IFLNK => FileType::Symlink,
+            S_IFCHR => FileType::CharDevice,
+            S_IFBLK => FileType::BlockDevice,
+            S_IFIFO => FileType::Pipe,
+            S_IFSOCK => FileType::Socket,
+            _ => FileType::Regular,
+        }
+    }
+
+    /// Short link targets live in the block pointers themselves
+    pub fn is_fast_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK && self.sectors == 0 && self.size <= (N_BLOCKS * 4) as u64
+    }
+
+    /// The block pointer area as bytes, for fast symlinks
+    pub fn inline_data(&self) -> [u8; N_BLOCKS * 4] {
+        let mut data = [0u8; N_BLOCKS * 4];
+        for (chunk, ptr) in data.chunks_exact_mut(4).zip(self.block.iter()) {
+            chunk.copy_from_slice(&ptr.to_le_bytes());
+        }
+        data
+    }
+}
+
+// ============================================================================
+// Directory Entry
+// ============================================================================
+
+/// Fixed part of a directory entry before the name
+pub const DIR_ENTRY_HEADER: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct DirEntry<'a> {
+    pub inode: u32,
+    pub rec_len: usize,
+    pub name: &'a [u8],
+}
+
+impl<'a> DirEntry<'a> {
+    /// Decode the entry at the start of `raw`. Without the FILETYPE
+    /// feature the name length is 16 bits wide.
+    pub fn parse(raw: &'a [u8], filetype_feature: bool) -> Result<Self, Ext2Error> {
+        if raw.len() < DIR_ENTRY_HEADER {
+            return Err(Ext2Error::Corrupt);
+        }
+        let rec_len = u16_at(raw, 4) as usize;
+        let name_len = if filetype_feature {
+            raw[6] as usize
+        } else {
+            u16_at(raw, 6) as usize
+        };
+
+        if rec_len < DIR_ENTRY_HEADER
+            || rec_len > raw.len()
+            || DIR_ENTRY_HEADER + name_len > rec_len
+        {
+            return Err(Ext2Error::Corrupt);
+        }
+
+        Ok(Self {
+            inode: u32_at(raw, 0),
+            rec_len,
+            name: &raw[DIR_ENTRY_HEADER..DIR_ENTRY_HEADER + name_len],
+        })
+    }
+}
@@ -0,0 +1,443 @@
+//! Read-only ext2 filesystem.
+//!
+//! Supports revision 0 and 1 volumes with the classic block map (direct,
+//! single, double and triple indirect blocks), so images made by
+//! `mke2fs -t ext2` mount as-is. ext3 volumes mount too, with the journal
+//! ignored, as long as it does not need recovery. ext4 features that
+//! change the on-disk layout (extents, 64-bit, flex_bg) are refused at
+//! mount time. The device must be the filesystem itself, e.g. a partition
+//! device, not a whole partitioned disk.
+
+pub mod layout;
+
+use self::layout::{
+    DirEntry, GROUP_DESC_SIZE, GroupDesc, INCOMPAT_FILETYPE, Inode, N_DIRECT, ROOT_INODE,
+    SUPERBLOCK_OFFSET, SUPERBLOCK_SIZE, Superblock,
+};
+use crate::fs::fd::FdError;
+use crate::fs::file::{DirEntryInfo, FileStat, FileType, OpenFlags};
+use crate::fs::{File, FileSystem, FsError, FsStat, try_zeroed};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use drivers::hal::block_device::DynBlockDevice;
+
+/// ext2 filesystem implementation
+pub struct Ext2FsInner {
+    dev: Arc<dyn DynBlockDevice>,
+    dev_block_size: usize,
+    sb: Superblock,
+    groups: Vec<GroupDesc>,
+}
+
+impl Ext2FsInner {
+    pub fn mount(dev: Arc<dyn DynBlockDevice>) -> Result<Arc<Self>, Ext2Error> {
+        let dev_block_size = dev.info().block_size;
+        if dev_block_size == 0 {
+            return Err(Ext2Error::ReadError);
+        }
+
+        let mut raw = [0u8; SUPERBLOCK_SIZE];
+        read_volume(&*dev, dev_block_size, SUPERBLOCK_OFFSET, &mut raw)?;
+        let sb = Superblock::parse(&raw)?;
+
+        let mut table = try_zeroed(sb.group_count() as usize * GROUP_DESC_SIZE)
+            .ok_or(Ext2Error::OutOfMemory)?;
+        let table_offset = sb.group_table_block() as u64 * sb.block_size as u64;
+        read_volume(&*dev, dev_block_size, table_offset, &mut table)?;
+        let groups = table
+            .chunks_exact(GROUP_DESC_SIZE)
+            .map(GroupDesc::parse)
+            .collect();
+
+        Ok(Arc::new(Self {
+            dev,
+            dev_block_size,
+            sb,
+            groups,
+        }))
+    }
+
+    // ============================================================================
+    // Block Access
+    // ============================================================================
+
+    fn block_offset(&self, block: u32) -> u64 {
+        block as u64 * self.sb.block_size as u64
+    }
+
+    fn read_bytes(&self, offset: u64, buf: &mut [u8]) -> Result<(), Ext2Error> {
+        read_volume(&*self.dev, self.dev_block_size, offset, buf)
+    }
+
+    /// Entry `index` of the indirect block `block` (0 for holes)
+    fn indirect(&self, block: u32, index: u64) -> Result<u32, Ext2Error> {
+        if block == 0 {
+            return Ok(0);
+        }
+        let mut raw = [0u8; 4];
+        self.read_bytes(self.block_offset(block) + index * 4, &mut raw)?;
+        Ok(u32::from_le_bytes(raw))
+    }
+
+    /// Physical block holding logical block `n` of `inode` (0 for holes)
+    fn map_block(&self, inode: &Inode, n: u64) -> Result<u32, Ext2Error> {
+        let per = (self.sb.block_size / 4) as u64;
+
+        let mut n = n;
+        if n < N_DIRECT as u64 {
+            return Ok(inode.block[n as usize]);
+        }
+        n -= N_DIRECT as u64;
+        if n < per {
+            return self.indirect(inode.block[N_DIRECT], n);
+        }
+        n -= per;
+        if n < per * per {
+            let l1 = self.indirect(inode.block[N_DIRECT + 1], n / per)?;
+            return self.indirect(l1, n % per);
+        }
+        n -= per * per;
+        if n < per * per * per {
+            let l1 = self.indirect(inode.block[N_DIRECT + 2], n / (per * per))?;
+            let l2 = self.indirect(l1, n / per % per)?;
+            return self.indirect(l2, n % per);
+        }
+        Err(Ext2Error::Corrupt)
+    }
+
+    /// Read file data at `offset`; holes read as zeros
+    fn read_data(&self, inode: &Inode, offset: u64, buf: &mut [u8]) -> Result<usize, Ext2Error> {
+        if offset >= inode.size {
+            return Ok(0);
+        }
+        let len = (inode.size - offset).min(buf.len() as u64) as usize;
+        let bs = self.sb.block_size as u64;
+
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let within = (pos % bs) as usize;
+            let n = (bs as usize - within).min(len - done);
+
+            match self.map_block(inode, pos / bs)? {
+                0 => buf[done..done + n].fill(0),
+                block => self.read_bytes(
+                    self.block_offset(block) + within as u64,
+                    &mut buf[done..done + n],
+                )?,
+            }
+            done += n;
+        }
+        Ok(len)
+    }
+
+    // ============================================================================
+    // Inodes and Directories
+    // ============================================================================
+
+    fn read_inode(&self, ino: u32) -> Result<Inode, Ext2Error> {
+        if ino == 0 || ino > self.sb.inodes_count {
+            return Err(Ext2Error::Corrupt);
+        }
+        let group = ((ino - 1) / self.sb.inodes_per_group) as usize;
+        let index = ((ino - 1) % self.sb.inodes_per_group) as u64;
+        let table = self
+            .groups
+            .get(group)
+            .ok_or(Ext2Error::Corrupt)?
+            .inode_table;
+
+        let mut raw = vec![0u8; self.sb.inode_size as usize];
+        self.read_bytes(
+            self.block_offset(table) + index * self.sb.inode_size as u64,
+            &mut raw,
+        )?;
+        Inode::parse(&raw)
+    }
+
+    /// Names and inode numbers in a directory, without `.` and `..`
+    fn dir_entries(&self, dir: &Inode) -> Result<Vec<(String, u32)>, Ext2Error> {
+        if dir.file_type() != FileType::Directory {
+            return Err(Ext2Error::NotADirectory);
+        }
+
+        let filetype = self.sb.feature_incompat & INCOMPAT_FILETYPE != 0;
+        let bs = self.sb.block_size;
+        let mut block = vec![0u8; bs];
+        let mut entries = Vec::new();
+
+        let mut offset = 0;
+        while offset < dir.size {
+            let n = self.read_data(dir, offset, &mut block)?;
+            let mut pos = 0;
+            while pos < n {
+                let entry = DirEntry::parse(&block[pos..n], filetype)?;
+                if entry.inode != 0 && entry.name != b"." && entry.name != b".." {
+                    let name = core::str::from_utf8(entry.name).map_err(|_| Ext2Error::Corrupt)?;
+                    entries.push((String::from(name), entry.inode));
+                }
+                pos += entry.rec_len;
+            }
+            offset += bs as u64;
+        }
+        Ok(entries)
+    }
+
+    /// Walk `path` from the root, returning the inode it names
+    fn lookup(&self, path: &str) -> Result<Inode, Ext2Error> {
+        let mut inode = self.read_inode(ROOT_INODE)?;
+
+        for part in path.split('/').filter(|s| !s.is_empty()) {
+            let (_, ino) = self
+                .dir_entries(&inode)?
+                .into_iter()
+                .find(|(name, _)| name == part)
+                .ok_or(Ext2Error::NotFound)?;
+            inode = self.read_inode(ino)?;
+        }
+        Ok(inode)
+    }
+
+    pub fn open(self: &Arc<Self>, path: &str) -> Result<Ext2File, Ext2Error> {
+        let inode = self.lookup(path)?;
+        match inode.file_type() {
+            FileType::Regular => {}
+            FileType::Directory => return Err(Ext2Error::IsADirectory),
+            _ => return Err(Ext2Error::Unsupported),
+        }
+        Ok(Ext2File {
+            fs: Arc::clone(self),
+            inode,
+            name: String::from(path.rsplit('/').next().unwrap_or(path)),
+        })
+    }
+
+    pub fn stat(&self, path: &str) -> Result<FileStat, Ext2Error> {
+        let inode = self.lookup(path)?;
+        let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+        Ok(FileStat {
+            size: inode.size as usize,
+            file_type: inode.file_type(),
+            name: if name.is_empty() {
+                "/".into()
+            } else {
+                name.into()
+            },
+        })
+    }
+
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntryInfo>, Ext2Error> {
+        let dir = self.lookup(path)?;
+        self.dir_entries(&dir)?
+            .into_iter()
+            .map(|(name, ino)| {
+                let inode = self.read_inode(ino)?;
+                Ok(DirEntryInfo {
+                    name,
+                    file_type: inode.file_type(),
+                    size: inode.size as usize,
+                })
+            })
+            .collect()
+    }
+
+    pub fn ls(&self, path: &str) -> Result<Vec<String>, Ext2Error> {
+        let dir = self.lookup(path)?;
+        Ok(self
+            .dir_entries(&dir)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    pub fn readlink(&self, path: &str) -> Result<String, Ext2Error> {
+        let inode = self.lookup(path)?;
+        if inode.file_type() != FileType::Symlink {
+            return Err(Ext2Error::NotASymlink);
+        }
+
+        let target = if inode.is_fast_symlink() {
+            inode.inline_data()[..inode.size as usize].to_vec()
+        } else {
+            let mut data = try_zeroed(inode.size as usize).ok_or(Ext2Error::OutOfMemory)?;
+            self.read_data(&inode, 0, &mut data)?;
+            data
+        };
+        String::from_utf8(target).map_err(|_| Ext2Error::Corrupt)
+    }
+
+    pub fn statvfs(&self) -> FsStat {
+        FsStat {
+            block_size: self.sb.block_size,
+            total_blocks: self.sb.blocks_count as u64,
+            free_blocks: self.sb.free_blocks_count as u64,
+        }
+    }
+}
+
+/// Read `buf.len()` bytes at byte `offset` of the volume, whatever the
+/// device's own block size.
+fn read_volume(
+    dev: &dyn DynBlockDevice,
+    bs: usize,
+    offset: u64,
+    buf: &mut [u8],
+) -> Result<(), Ext2Error> {
+    let mut scratch = vec![0u8; bs];
+    let mut done = 0;
+
+    while done < buf.len() {
+        let pos = offset + done as u64;
+        let lba = pos / bs as u64;
+        let within = (pos % bs as u64) as usize;
+        let n = (bs - within).min(buf.len() - done);
+
+        if within == 0 && n == bs {
+            dev.read_block(lba, &mut buf[done..done + bs])
+                .map_err(|_| Ext2Error::ReadError)?;
+        } else {
+            dev.read_block(lba, &mut scratch)
+                .map_err(|_| Ext2Error::ReadError)?;
+            buf[done..done + n].copy_from_slice(&scratch[within..within + n]);
+        }
+        done += n;
+    }
+    Ok(())
+}
+
+/// ext2 file handle
+pub struct Ext2File {
+    fs: Arc<Ext2FsInner>,
+    inode: Inode,
+    name: String,
+}
+
+impl File for Ext2File {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, FdError> {
+        self.fs
+            .read_data(&self.inode, offset as u64, buf)
+            .map_err(|_| FdError::IoError)
+    }
+
+    fn write(&self, _buf: &[u8], _offset: usize) -> Result<usize, FdError> {
+        Err(FdError::PermissionDenied)
+    }
+
+    fn stat(&self) -> Result<FileStat, FdError> {
+        Ok(FileStat {
+            size: self.inode.size as usize,
+            file_type: FileType::Regular,
+            name: self.name.clone(),
+        })
+    }
+}
+
+// ============================================================================
+// FileSystem Trait Implementation
+// ============================================================================
+
+pub struct Ext2Fs(Arc<Ext2FsInner>);
+
+impl Ext2Fs {
+    pub fn mount(dev: Arc<dyn DynBlockDevice>) -> Result<Arc<Self>, Ext2Error> {
+        Ok(Arc::new(Self(Ext2FsInner::mount(dev)?)))
+    }
+}
+
+impl FileSystem for Ext2Fs {
+    fn open(&self, path: &str, flags: OpenFlags) -> Result<Arc<dyn File>, FsError> {
+        let modifies = OpenFlags::WRONLY | OpenFlags::RDWR | OpenFlags::CREATE | OpenFlags::TRUNC;
+        if flags.intersects(modifies) {
+            return Err(FsError::PermissionDenied);
+        }
+        Ok(Arc::new(Ext2FsInner::open(&self.0, path)?))
+    }
+
+    fn create(&self, _path: &str) -> Result<Arc<dyn File>, FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn delete(&self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn stat(&self, path: &str) -> Result<FileStat, FsError> {
+        Ok(self.0.stat(path)?)
+    }
+
+    fn ls(&self, path: &str) -> Result<Vec<String>, FsError> {
+        Ok(self.0.ls(path)?)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntryInfo>, FsError> {
+        Ok(self.0.read_dir(path)?)
+    }
+
+    fn mkdir(&self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn rmdir(&self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn rename(&self, _old_path: &str, _new_path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn symlink(&self, _target: &str, _link_path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn readlink(&self, path: &str) -> Result<String, FsError> {
+        Ok(self.0.readlink(path)?)
+    }
+
+    fn statvfs(&self, _path: &str) -> Result<FsStat, FsError> {
+        Ok(self.0.statvfs())
+    }
+
+    /// Read-only, so nothing is ever dirty.
+    fn sync(&self) -> Result<(), FsError> {
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Error Types
+// ============================================================================
+
+#[derive(Debug)]
+pub enum Ext2Error {
+    NotFound,
+    ReadError,
+    /// Missing magic or nonsensical geometry
+    BadSuperblock,
+    /// Feature or inode format this driver cannot read
+    Unsupported,
+    /// Structure points outside the volume or is malformed
+    Corrupt,
+    IsADirectory,
+    NotADirectory,
+    NotASymlink,
+    /// Metadata too large for the kernel heap
+    OutOfMemory,
+}
+
+impl From<Ext2Error> for FsError {
+    fn from(err: Ext2Error) -> Self {
+        match err {
+            Ext2Error::NotFound => FsError::NotFound,
+            Ext2Error::ReadError | Ext2Error::BadSuperblock | Ext2Error::Corrupt => {
+                FsError::IoError
+            }
+            Ext2Error::Unsupported => FsError::NotSupported,
+            Ext2Error::IsADirectory => FsError::IsADirectory,
+            Ext2Error::NotADirectory => FsError::NotADirectory,
+            Ext2Error::NotASymlink => FsError::InvalidPath,
+            Ext2Error::OutOfMemory => FsError::OutOfMemory,
+        }
+    }
+}
@@ -1,14 +1,17 @@
 use crate::fs::fd::FdError;
-use crate::fs::file::FileType;
+use crate::fs::file::{DirEntryInfo, FileType, OpenFlags};
 use crate::fs::{File, file::FileStat};
-use crate::fs::{FileSystem, FsError};
+use crate::fs::{FileSystem, FsError, FsStat};
+use crate::sync::Mutex;
+use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use alloc::vec::Vec;
-use core::sync::atomic::AtomicU32;
+use common::sync::RwLock;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use drivers::hal::block_device::DynBlockDevice;
-use spin::{Mutex, RwLock};
+use drivers::hal::rtc::DateTime;
 
 /// FAT32 filesystem implementation
 #[derive(Clone)]
@@ -19,6 +22,12 @@ pub struct Fat32FsInner {
     metadata_lock: Arc<RwLock<()>>,
     // Protects FAT table access
     fat_lock: Arc<Mutex<()>>,
+    // Write-through cache of FAT sectors; only used with `fat_lock` held
+    fat_cache: Arc<Mutex<FatCache>>,
+    // Free-space bookkeeping; only modified with `fat_lock` held
+    fs_info: Arc<Mutex<FsInfo>>,
+    // Entry locations of open files, patched when rename moves an entry
+    open_entries: Arc<Mutex<Vec<Weak<Mutex<EntryLocation>>>>>,
 }
 
 #[derive(Copy, Clone)]
@@ -30,6 +39,7 @@ pub struct FatInfo {
     pub num_dir_entries: u16,
     pub sectors_per_fat: u64,
     pub root_cluster: u32,
+    pub fs_info_sector: u16,
     pub fat_start_lba: u64,
     pub cluster_heap_start_lba: u64,
     pub partition_start_lba: u64,
@@ -81,54 +91,106 @@ impl FatInfo {
                 boot_sector[46],
                 boot_sector[47],
             ]),
+            fs_info_sector: u16::from_le_bytes([boot_sector[48], boot_sector[49]]),
             fat_start_lba: 0,
             cluster_heap_start_lba: 0,
             partition_start_lba: 0,
             total_clusters,
         })
     }
+
+    /// Highest valid cluster number (data clusters start at 2)
+    fn last_cluster(&self) -> u32 {
+        self.total_clusters + 1
+    }
 }
 
 /// FAT32 file handle
 pub struct Fat32File {
     fs: Arc<Fat32FsInner>,
-    start_cluster: u32,
-    size: Arc<AtomicU32>, // Mutable size for extending
+    start_cluster: AtomicU32, // Allocated on first write for empty files
+    size: Arc<AtomicU32>,     // Mutable size for extending
     name: String,
+    // On-disk directory entry, rewritten with size and timestamps
+    entry: Arc<Mutex<EntryLocation>>,
+    // Written since the directory entry was last updated
+    dirty: AtomicBool,
+    // Opened with APPEND: writes ignore the offset and go to the end
+    append: bool,
     // Protects concurrent I/O operations on this file
     io_lock: RwLock<()>,
 }
 
 impl Fat32File {
-    pub fn new(
+    fn new(
         fs: Arc<Fat32FsInner>,
         start_cluster: u32,
         size: u32,
         name: String,
+        entry: EntryLocation,
     ) -> Result<Self, Fat32Error> {
         // Validate cluster for non-empty files
         if start_cluster < 2 && size > 0 {
             return Err(Fat32Error::InvalidCluster);
         }
 
+        let entry = Arc::new(Mutex::new(entry));
+        fs.track_open_entry(&entry);
+
         Ok(Self {
             fs,
-            start_cluster,
+            start_cluster: AtomicU32::new(start_cluster),
             size: Arc::new(AtomicU32::new(size)),
             name,
+            entry,
+            dirty: AtomicBool::new(false),
+            append: false,
             io_lock: RwLock::new(()),
         })
     }
 
     /// Get current file size
     fn get_size(&self) -> u32 {
-        self.size.load(core::sync::atomic::Ordering::Acquire)
+        self.size.load(Ordering::Acquire)
     }
 
     /// Set file size (internal use only)
     fn set_size(&self, new_size: u32) {
-        self.size
-            .store(new_size, core::sync::atomic::Ordering::Release);
+        self.size.store(new_size, Ordering::Release);
+    }
+
+    fn get_start_cluster(&self) -> u32 {
+        self.start_cluster.load(Ordering::Acquire)
+    }
+
+    /// Write size, first cluster and modification time back to the
+    /// directory entry
+    fn sync_entry(&self) -> Result<(), Fat32Error> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let result = self.fs.update_dir_entry(
+            &self.entry,
+            self.get_start_cluster(),
+            self.get_size(),
+            FatTimestamp::now(),
+        );
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+}
+
+impl Drop for Fat32File {
+    fn drop(&mut self) {
+        if self.sync_entry().is_err() {
+            log::warn!("fat32: failed to update directory entry for {}", self.name);
+        }
+        if self.fs.dev.flush().is_err() {
+            log::warn!("fat32: failed to flush device after closing {}", self.name);
+        }
     }
 }
 
@@ -152,7 +214,7 @@ impl File for Fat32File {
 
         let cluster_chain = self
             .fs
-            .get_chain(self.start_cluster)
+            .get_chain(self.get_start_cluster())
             .map_err(|_| FdError::IoError)?;
 
         let bytes_per_cluster = (self.fs.fat_info.bytes_per_sector as usize)
@@ -204,19 +266,28 @@ impl File for Fat32File {
         }
 
         let current_size = self.get_size() as usize;
+        let offset = if self.append { current_size } else { offset };
         let new_size = offset + bytes_to_write;
 
+        // Empty files created elsewhere may have no cluster yet
+        if self.get_start_cluster() < 2 {
+            let cluster = self.fs.alloc_cluster().map_err(|_| FdError::IoError)?;
+            self.start_cluster.store(cluster, Ordering::Release);
+        }
+
         // Extend file if needed
-        if new_size > current_size {
+        let grown = new_size > current_size;
+        if grown {
             self.fs
-                .extend_file(self.start_cluster, new_size)
+                .extend_file(self.get_start_cluster(), new_size)
                 .map_err(|_| FdError::IoError)?;
             self.set_size(new_size as u32);
         }
+        self.dirty.store(true, Ordering::Release);
 
         let cluster_chain = self
             .fs
-            .get_chain(self.start_cluster)
+            .get_chain(self.get_start_cluster())
             .map_err(|_| FdError::IoError)?;
 
         let bytes_per_cluster = (self.fs.fat_info.bytes_per_sector as usize)
@@ -267,9 +338,62 @@ impl File for Fat32File {
             file_offset += bytes_to_copy;
         }
 
+        // Persist a new size right away so a crash never leaves the entry
+        // pointing at less data than was written
+        if grown {
+            self.sync_entry().map_err(|_| FdError::IoError)?;
+        }
+
         Ok(bytes_written)
     }
 
+    fn flush(&self) -> Result<(), FdError> {
+        let _guard = self.io_lock.write();
+        self.sync_entry().map_err(|_| FdError::IoError)?;
+        // Push the entry and data out of any block cache
+        self.fs.dev.flush().map_err(|_| FdError::IoError)
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), FdError> {
+        let len_u32 = u32::try_from(len).map_err(|_| FdError::InvalidSeek)?;
+
+        let guard = self.io_lock.write();
+        let current_size = self.get_size() as usize;
+
+        if len > current_size {
+            // Growing is a zero-filled write of the gap
+            drop(guard);
+            let zeros = vec![0u8; self.fs.fat_info.bytes_per_sector as usize];
+            let mut offset = current_size;
+            while offset < len {
+                let n = zeros.len().min(len - offset);
+                offset += self.write(&zeros[..n], offset)?;
+            }
+            return Ok(());
+        }
+
+        let bytes_per_cluster = (self.fs.fat_info.bytes_per_sector as usize)
+            * (self.fs.fat_info.sectors_per_cluster as usize);
+        let keep = len.div_ceil(bytes_per_cluster);
+
+        let start = self.get_start_cluster();
+        if start >= 2 {
+            if keep == 0 {
+                // Empty files own no clusters
+                self.fs.free_chain(start).map_err(|_| FdError::IoError)?;
+                self.start_cluster.store(0, Ordering::Release);
+            } else {
+                self.fs
+                    .shrink_chain(start, keep)
+                    .map_err(|_| FdError::IoError)?;
+            }
+        }
+
+        self.set_size(len_u32);
+        self.dirty.store(true, Ordering::Release);
+        self.sync_entry().map_err(|_| FdError::IoError)
+    }
+
     fn stat(&self) -> Result<FileStat, FdError> {
         Ok(FileStat {
             size: self.get_size() as usize,
@@ -285,7 +409,13 @@ impl Fat32FsInner {
         dev.read_block(0, &mut mbr)
             .map_err(|_| Fat32Error::ReadError)?;
 
-        let partition_start_lba = u32::from_le_bytes([mbr[454], mbr[455], mbr[456], mbr[457]]);
+        // A partition device starts with the boot sector itself; a whole
+        // disk starts with an MBR whose first entry we mount
+        let partition_start_lba = if is_fat32_boot_sector(&mbr) {
+            0
+        } else {
+            u32::from_le_bytes([mbr[454], mbr[455], mbr[456], mbr[457]])
+        };
 
         let mut boot = [0u8; 512];
         dev.read_block(partition_start_lba as u64, &mut boot)
@@ -297,11 +427,16 @@ impl Fat32FsInner {
         let total_fat_sectors = (fat.num_fats as u64) * fat.sectors_per_fat;
         fat.cluster_heap_start_lba = fat.fat_start_lba + total_fat_sectors;
 
+        let fs_info = FsInfo::load(&*dev, &fat)?;
+
         let fs = Self {
             dev,
             fat_info: fat,
             metadata_lock: Arc::new(RwLock::new(())),
             fat_lock: Arc::new(Mutex::new(())),
+            fat_cache: Arc::new(Mutex::new(FatCache::new())),
+            fs_info: Arc::new(Mutex::new(fs_info)),
+            open_entries: Arc::new(Mutex::new(Vec::new())),
         };
 
         Ok(Arc::new(fs))
@@ -333,12 +468,148 @@ impl Fat32FsInner {
             return Err(Fat32Error::IsADirectory);
         }
 
-        Ok(Fat32File::new(
+        Fat32File::new(
             Arc::clone(self),
             entry.first_cluster,
             entry.size,
             entry.name,
-        )?)
+            entry.location,
+        )
+    }
+
+    pub fn create(self: &Arc<Self>, path: &str) -> Result<Fat32File, Fat32Error> {
+        self.create_with_attr(path, Fat32Attribute::Archive as u8)
+    }
+
+    fn create_with_attr(self: &Arc<Self>, path: &str, attr: u8) -> Result<Fat32File, Fat32Error> {
+        // Exclusive lock: we modify directory structure
+        let _guard = self.metadata_lock.write();
+
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        if parts.is_empty() {
+            return Err(Fat32Error::InvalidPath);
+        }
+
+        // Navigate to parent directory
+        let parent_parts = &parts[..parts.len() - 1];
+        let parent_cluster = if parent_parts.is_empty() {
+            self.fat_info.root_cluster
+        } else {
+            let parent_path = parent_parts.join("/");
+            self.navigate_to_dir(&parent_path)?
+        };
+
+        let file_name = parts[parts.len() - 1];
+        match self.find_entry(parent_cluster, file_name) {
+            Ok(_) => return Err(Fat32Error::AlreadyExists),
+            Err(Fat32Error::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        let short_name = self.generate_short_name(parent_cluster, file_name)?;
+        let slot = self.alloc_dir_slot(parent_cluster)?;
+
+        // Empty files own no clusters; the first write allocates one
+        let raw = build_dir_entry(&short_name, attr, 0, 0, FatTimestamp::now());
+        self.write_dir_entry(slot, &raw)?;
+
+        Fat32File::new(Arc::clone(self), 0, 0, parse_83(&short_name), slot)
+    }
+
+    /// Create a symbolic link at `path` pointing to `target`.
+    ///
+    /// FAT has no links, so the link is a system file holding
+    /// [`SYMLINK_MAGIC`] followed by the target.
+    pub fn symlink(self: &Arc<Self>, target: &str, path: &str) -> Result<(), Fat32Error> {
+        if target.is_empty() || target.len() > SYMLINK_MAX {
+            return Err(Fat32Error::InvalidPath);
+        }
+
+        let attr = Fat32Attribute::Archive as u8 | Fat32Attribute::System as u8;
+        let file = self.create_with_attr(path, attr)?;
+
+        let mut data = Vec::with_capacity(SYMLINK_MAGIC.len() + target.len());
+        data.extend_from_slice(SYMLINK_MAGIC);
+        data.extend_from_slice(target.as_bytes());
+        match file.write(&data, 0) {
+            Ok(n) if n == data.len() => Ok(()),
+            _ => Err(Fat32Error::WriteError),
+        }
+    }
+
+    /// Target of the symbolic link at `path`
+    pub fn readlink(&self, path: &str) -> Result<String, Fat32Error> {
+        // Shared lock for reading
+        let _guard = self.metadata_lock.read();
+
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        if parts.is_empty() {
+            return Err(Fat32Error::NotASymlink);
+        }
+
+        let parent_cluster = self.navigate_to_dir(&parts[..parts.len() - 1].join("/"))?;
+        let entry = self.find_entry(parent_cluster, parts[parts.len() - 1])?;
+        self.link_target(&entry)?.ok_or(Fat32Error::NotASymlink)
+    }
+
+    pub fn rename(&self, old_path: &str, new_path: &str) -> Result<(), Fat32Error> {
+        // Exclusive lock: we modify directory structure
+        let _guard = self.metadata_lock.write();
+
+        let old_parts: Vec<&str> = old_path.split('/').filter(|s| !s.is_empty()).collect();
+        let new_parts: Vec<&str> = new_path.split('/').filter(|s| !s.is_empty()).collect();
+        if old_parts.is_empty() || new_parts.is_empty() {
+            return Err(Fat32Error::InvalidPath);
+        }
+
+        let old_parent = self.navigate_to_dir(&old_parts[..old_parts.len() - 1].join("/"))?;
+        let entry = self.find_entry(old_parent, old_parts[old_parts.len() - 1])?;
+
+        // Resolve the destination directory, refusing to move a directory
+        // into itself or one of its descendants
+        let mut new_parent = self.fat_info.root_cluster;
+        for part in &new_parts[..new_parts.len() - 1] {
+            let dir = self.find_entry(new_parent, part)?;
+            if !dir.is_dir {
+                return Err(Fat32Error::NotADirectory);
+            }
+            if entry.is_dir && dir.first_cluster == entry.first_cluster {
+                return Err(Fat32Error::InvalidPath);
+            }
+            new_parent = dir.first_cluster;
+        }
+
+        let new_name = new_parts[new_parts.len() - 1];
+        match self.find_entry(new_parent, new_name) {
+            // Renaming onto itself (e.g. a case change) is a no-op
+            Ok(existing) if existing.location == entry.location => return Ok(()),
+            Ok(_) => return Err(Fat32Error::AlreadyExists),
+            Err(Fat32Error::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut raw = self.read_dir_entry(entry.location)?;
+        raw[0..11].copy_from_slice(&self.generate_short_name(new_parent, new_name)?);
+
+        if new_parent == old_parent {
+            return self.write_dir_entry(entry.location, &raw);
+        }
+
+        // Cross-directory move: add the new entry before dropping the old
+        // one, so a failure part-way leaves the file reachable
+        let slot = self.alloc_dir_slot(new_parent)?;
+        self.write_dir_entry(slot, &raw)?;
+
+        let mut deleted = raw;
+        deleted[0] = 0xE5;
+        self.write_dir_entry(entry.location, &deleted)?;
+
+        if entry.is_dir {
+            self.set_dotdot(entry.first_cluster, new_parent)?;
+        }
+
+        self.relocate_open_entry(entry.location, slot);
+        Ok(())
     }
 
     pub fn ls(&self, path: &str) -> Result<Vec<String>, Fat32Error> {
@@ -350,6 +621,25 @@ impl Fat32FsInner {
         Ok(entries.into_iter().map(|e| e.name).collect())
     }
 
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntryInfo>, Fat32Error> {
+        // Shared lock for reading
+        let _guard = self.metadata_lock.read();
+
+        let cluster = self.navigate_to_dir(path)?;
+        let entries = self.list_entries(cluster)?;
+        entries
+            .into_iter()
+            .map(|e| {
+                let (file_type, size) = self.entry_type(&e)?;
+                Ok(DirEntryInfo {
+                    name: e.name,
+                    file_type,
+                    size,
+                })
+            })
+            .collect()
+    }
+
     pub fn stat(&self, path: &str) -> Result<FileStat, Fat32Error> {
         // Shared lock for reading
         let _guard = self.metadata_lock.read();
@@ -377,18 +667,66 @@ impl Fat32FsInner {
         // Find the entry
         let name = parts[parts.len() - 1];
         let entry = self.find_entry(parent_cluster, name)?;
+        let (file_type, size) = self.entry_type(&entry)?;
 
         Ok(FileStat {
-            size: entry.size as usize,
-            file_type: if entry.is_dir {
-                FileType::Directory
-            } else {
-                FileType::Regular
-            },
+            size,
+            file_type,
             name: entry.name,
         })
     }
 
+    // ============================================================================
+    // Symbolic Link Emulation
+    // ============================================================================
+
+    /// File type and reported size of an entry (links report their
+    /// target length)
+    fn entry_type(&self, entry: &DirEntry) -> Result<(FileType, usize), Fat32Error> {
+        if entry.is_dir {
+            return Ok((FileType::Directory, 0));
+        }
+        Ok(match self.link_target(entry)? {
+            Some(target) => (FileType::Symlink, target.len()),
+            None => (FileType::Regular, entry.size as usize),
+        })
+    }
+
+    /// Target of a link file, or `None` if the entry is not one. Only
+    /// system files of a plausible size are read.
+    fn link_target(&self, entry: &DirEntry) -> Result<Option<String>, Fat32Error> {
+        let size = entry.size as usize;
+        if entry.is_dir
+            || entry.attr & Fat32Attribute::System as u8 == 0
+            || size <= SYMLINK_MAGIC.len()
+            || size > SYMLINK_MAGIC.len() + SYMLINK_MAX
+        {
+            return Ok(None);
+        }
+
+        let sector_size = self.fat_info.bytes_per_sector as usize;
+        let mut data = Vec::with_capacity(size);
+        let mut sector = vec![0u8; sector_size];
+        'chain: for cluster in self.get_chain(entry.first_cluster)? {
+            let base = self.cluster_to_lba(cluster);
+            for s in 0..self.fat_info.sectors_per_cluster as u64 {
+                self.dev
+                    .read_block(base + s, &mut sector)
+                    .map_err(|_| Fat32Error::ReadError)?;
+                let n = sector_size.min(size - data.len());
+                data.extend_from_slice(&sector[..n]);
+                if data.len() == size {
+                    break 'chain;
+                }
+            }
+        }
+
+        match data.strip_prefix(SYMLINK_MAGIC) {
+            Some(target) => Ok(core::str::from_utf8(target).ok().map(String::from)),
+            None => Ok(None),
+        }
+    }
+
     // ============================================================================
     // Cluster Management
     // ============================================================================
@@ -396,20 +734,138 @@ impl Fat32FsInner {
     /// Allocate a free cluster
     fn alloc_cluster(&self) -> Result<u32, Fat32Error> {
         let _guard = self.fat_lock.lock();
+        let mut info = self.fs_info.lock();
 
-        // Search for a free cluster (entry == 0)
-        for cluster in 2..self.fat_info.total_clusters {
+        // Search for a free cluster (entry == 0), starting at the hint and
+        // wrapping around
+        let last = self.fat_info.last_cluster();
+        let start = info.next_free;
+        for cluster in (start..=last).chain(2..start) {
             let entry = self.read_fat_entry_unlocked(cluster)?;
             if entry == 0 {
                 // Mark as end of chain
                 self.write_fat_entry_unlocked(cluster, 0x0FFFFFFF)?;
+
+                info.next_free = if cluster < last { cluster + 1 } else { 2 };
+                if let Some(free) = info.free_count.as_mut() {
+                    *free = free.saturating_sub(1);
+                }
+                info.dirty = true;
                 return Ok(cluster);
             }
         }
 
+        info.free_count = Some(0);
+        info.dirty = true;
         Err(Fat32Error::DiskFull)
     }
 
+    /// Return every cluster of a chain to the free pool
+    fn free_chain(&self, start: u32) -> Result<(), Fat32Error> {
+        let chain = self.get_chain(start)?;
+        let _guard = self.fat_lock.lock();
+        let mut info = self.fs_info.lock();
+
+        for &cluster in &chain {
+            self.write_fat_entry_unlocked(cluster, 0)?;
+        }
+
+        if let Some(free) = info.free_count.as_mut() {
+            *free = (*free + chain.len() as u32).min(self.fat_info.total_clusters);
+        }
+        info.next_free = info.next_free.min(start);
+        info.dirty = true;
+        Ok(())
+    }
+
+    /// Free and total space, counting free clusters once if FSInfo did
+    /// not provide a trustworthy count
+    pub fn statvfs(&self) -> Result<FsStat, Fat32Error> {
+        let _guard = self.fat_lock.lock();
+        let mut info = self.fs_info.lock();
+
+        let free = match info.free_count {
+            Some(free) => free,
+            None => {
+                let free = self.count_free_clusters()?;
+                info.free_count = Some(free);
+                info.dirty = true;
+                free
+            }
+        };
+
+        Ok(FsStat {
+            block_size: self.fat_info.bytes_per_sector as usize
+                * self.fat_info.sectors_per_cluster as usize,
+            total_blocks: self.fat_info.total_clusters as u64,
+            free_blocks: free as u64,
+        })
+    }
+
+    /// Count free clusters by scanning the first FAT (caller holds `fat_lock`)
+    fn count_free_clusters(&self) -> Result<u32, Fat32Error> {
+        let mut sector = vec![0u8; self.fat_info.bytes_per_sector as usize];
+        let entries_per_sector = sector.len() as u32 / 4;
+        let last = self.fat_info.last_cluster();
+        let mut free = 0;
+
+        for s in 0..self.fat_info.sectors_per_fat {
+            let first = s as u32 * entries_per_sector;
+            if first > last {
+                break;
+            }
+            self.dev
+                .read_block(self.fat_info.fat_start_lba + s, &mut sector)
+                .map_err(|_| Fat32Error::ReadError)?;
+
+            for (i, raw) in sector.chunks_exact(4).enumerate() {
+                let cluster = first + i as u32;
+                let entry = u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) & 0x0FFF_FFFF;
+                if (2..=last).contains(&cluster) && entry == 0 {
+                    free += 1;
+                }
+            }
+        }
+
+        Ok(free)
+    }
+
+    /// Push filesystem metadata and the device cache to the medium.
+    ///
+    /// FAT sectors are written through as they change, so only the
+    /// FSInfo sector and whatever the device buffers remain.
+    pub fn sync(&self) -> Result<(), Fat32Error> {
+        self.fs_info.lock().flush(&*self.dev)?;
+        self.dev.flush().map_err(|_| Fat32Error::WriteError)
+    }
+
+    /// Cut a chain after its first `keep` clusters (at least one) and free
+    /// the rest
+    fn shrink_chain(&self, start: u32, keep: usize) -> Result<(), Fat32Error> {
+        let chain = self.get_chain(start)?;
+        if keep == 0 || keep >= chain.len() {
+            return Ok(());
+        }
+
+        {
+            let _guard = self.fat_lock.lock();
+            self.write_fat_entry_unlocked(chain[keep - 1], 0x0FFFFFFF)?;
+        }
+        self.free_chain(chain[keep])
+    }
+
+    /// Fill a cluster with zeros (new directory clusters must read as empty)
+    fn zero_cluster(&self, cluster: u32) -> Result<(), Fat32Error> {
+        let zeros = vec![0u8; self.fat_info.bytes_per_sector as usize];
+        let base = self.cluster_to_lba(cluster);
+        for s in 0..self.fat_info.sectors_per_cluster as u64 {
+            self.dev
+                .write_block(base + s, &zeros)
+                .map_err(|_| Fat32Error::WriteError)?;
+        }
+        Ok(())
+    }
+
     /// Link a cluster to the end of a chain
     fn link_cluster(&self, last_cluster: u32, new_cluster: u32) -> Result<(), Fat32Error> {
         let _guard = self.fat_lock.lock();
@@ -454,33 +910,11 @@ impl Fat32FsInner {
 
     /// Read FAT entry for a given cluster (without lock - internal use)
     fn read_fat_entry_unlocked(&self, cluster: u32) -> Result<u32, Fat32Error> {
-        let bytes_per_sector = self.fat_info.bytes_per_sector as u64;
-
-        // FAT32 entry = 4 bytes per cluster
-        let offset = cluster as u64 * 4;
-        let sector = self.fat_info.fat_start_lba + (offset / bytes_per_sector);
-        let idx = (offset % bytes_per_sector) as usize;
-
-        let mut buf = vec![0u8; self.fat_info.bytes_per_sector as usize];
-        self.dev
-            .read_block(sector, &mut buf)
-            .map_err(|_| Fat32Error::ReadError)?;
-
-        let entry = if idx + 4 <= buf.len() {
-            u32::from_le_bytes([buf[idx], buf[idx + 1], buf[idx + 2], buf[idx + 3]])
-        } else {
-            // Entry crosses sector boundary → read next sector
-            let mut next = vec![0u8; self.fat_info.bytes_per_sector as usize];
-            self.dev
-                .read_block(sector + 1, &mut next)
-                .map_err(|_| Fat32Error::ReadError)?;
+        let (sector, idx) = self.fat_entry_position(cluster);
 
-            let mut tmp = [0u8; 4];
-            let first = buf.len() - idx;
-            tmp[..first].copy_from_slice(&buf[idx..]);
-            tmp[first..].copy_from_slice(&next[..4 - first]);
-            u32::from_le_bytes(tmp)
-        };
+        let mut cache = self.fat_cache.lock();
+        let buf = self.cached_fat_sector(&mut cache, sector)?;
+        let entry = u32::from_le_bytes([buf[idx], buf[idx + 1], buf[idx + 2], buf[idx + 3]]);
 
         Ok(entry & 0x0FFF_FFFF)
     }
@@ -493,57 +927,61 @@ impl Fat32FsInner {
 
     /// Write FAT entry for a given cluster (without lock - internal use)
     fn write_fat_entry_unlocked(&self, cluster: u32, value: u32) -> Result<(), Fat32Error> {
-        let bytes_per_sector = self.fat_info.bytes_per_sector as u64;
-
-        // Mask to preserve reserved bits
-        let value = value & 0x0FFF_FFFF;
+        let (sector, idx) = self.fat_entry_position(cluster);
 
-        // FAT32 entry = 4 bytes per cluster
-        let offset = cluster as u64 * 4;
-        let sector = self.fat_info.fat_start_lba + (offset / bytes_per_sector);
-        let idx = (offset % bytes_per_sector) as usize;
+        let mut cache = self.fat_cache.lock();
+        let buf = self.cached_fat_sector(&mut cache, sector)?;
 
-        let mut buf = vec![0u8; self.fat_info.bytes_per_sector as usize];
-        self.dev
-            .read_block(sector, &mut buf)
-            .map_err(|_| Fat32Error::ReadError)?;
+        // Preserve the reserved top 4 bits
+        let old = u32::from_le_bytes([buf[idx], buf[idx + 1], buf[idx + 2], buf[idx + 3]]);
+        let value = (old & 0xF000_0000) | (value & 0x0FFF_FFFF);
+        buf[idx..idx + 4].copy_from_slice(&value.to_le_bytes());
 
-        if idx + 4 <= buf.len() {
-            // Entry fits in one sector
-            let bytes = value.to_le_bytes();
-            buf[idx..idx + 4].copy_from_slice(&bytes);
-            self.dev
-                .write_block(sector, &buf)
-                .map_err(|_| Fat32Error::WriteError)?;
-        } else {
-            // Entry crosses sector boundary
-            let mut next = vec![0u8; self.fat_info.bytes_per_sector as usize];
-            self.dev
-                .read_block(sector + 1, &mut next)
-                .map_err(|_| Fat32Error::ReadError)?;
+        // Write-through to all FAT copies
+        for fat_idx in 0..self.fat_info.num_fats {
+            let fat_sector = sector + (fat_idx as u64 * self.fat_info.sectors_per_fat);
+            if self.dev.write_block(fat_sector, buf).is_err() {
+                // The cached copy may now be ahead of the disk
+                cache.remove(sector);
+                return Err(Fat32Error::WriteError);
+            }
+        }
 
-            let bytes = value.to_le_bytes();
-            let first = buf.len() - idx;
-            buf[idx..].copy_from_slice(&bytes[..first]);
-            next[..4 - first].copy_from_slice(&bytes[first..]);
+        Ok(())
+    }
 
-            self.dev
-                .write_block(sector, &buf)
-                .map_err(|_| Fat32Error::WriteError)?;
-            self.dev
-                .write_block(sector + 1, &next)
-                .map_err(|_| Fat32Error::WriteError)?;
-        }
+    /// Sector of the first FAT holding `cluster`'s entry, and the byte
+    /// offset within it. Entries are 4-byte aligned and never straddle
+    /// sectors.
+    fn fat_entry_position(&self, cluster: u32) -> (u64, usize) {
+        let bytes_per_sector = self.fat_info.bytes_per_sector as u64;
+        let offset = cluster as u64 * 4;
+        (
+            self.fat_info.fat_start_lba + offset / bytes_per_sector,
+            (offset % bytes_per_sector) as usize,
+        )
+    }
 
-        // Write to all FAT copies
-        for fat_idx in 1..self.fat_info.num_fats {
-            let fat_sector = sector + (fat_idx as u64 * self.fat_info.sectors_per_fat);
+    /// Look up a FAT sector, reading it from disk on a miss
+    fn cached_fat_sector<'a>(
+        &self,
+        cache: &'a mut FatCache,
+        lba: u64,
+    ) -> Result<&'a mut [u8], Fat32Error> {
+        if !cache.contains(lba) {
+            let mut buf = vec![0u8; self.fat_info.bytes_per_sector as usize];
             self.dev
-                .write_block(fat_sector, &buf)
-                .map_err(|_| Fat32Error::WriteError)?;
+                .read_block(lba, &mut buf)
+                .map_err(|_| Fat32Error::ReadError)?;
+            cache.insert(lba, buf);
         }
+        Ok(cache.get(lba).unwrap())
+    }
 
-        Ok(())
+    /// Drop all cached FAT sectors
+    pub fn invalidate_fat_cache(&self) {
+        let _guard = self.fat_lock.lock();
+        self.fat_cache.lock().clear();
     }
 
     /// Get the full cluster chain starting from a given cluster
@@ -626,7 +1064,11 @@ impl Fat32FsInner {
                         // End of directory
                         return Ok(entries);
                     }
-                    if let Some(e) = parse_dir_entry(raw) {
+                    let loc = EntryLocation {
+                        lba: base + s as u64,
+                        index: i,
+                    };
+                    if let Some(e) = parse_dir_entry(raw, loc) {
                         entries.push(e);
                     }
                 }
@@ -635,6 +1077,184 @@ impl Fat32FsInner {
         Ok(entries)
     }
 
+    // ============================================================================
+    // Directory Entry Allocation
+    // ============================================================================
+
+    /// Walk the raw 32-byte entries of a directory and return the location
+    /// of the first one for which `pred` returns true.
+    fn scan_dir(
+        &self,
+        dir_cluster: u32,
+        mut pred: impl FnMut(&[u8]) -> bool,
+    ) -> Result<Option<EntryLocation>, Fat32Error> {
+        let mut sector = vec![0u8; self.fat_info.bytes_per_sector as usize];
+        let chain = self.get_chain(dir_cluster)?;
+
+        for cluster in chain {
+            let base = self.cluster_to_lba(cluster);
+            for s in 0..self.fat_info.sectors_per_cluster as u64 {
+                self.dev
+                    .read_block(base + s, &mut sector)
+                    .map_err(|_| Fat32Error::ReadError)?;
+
+                for i in 0..sector.len() / 32 {
+                    if pred(&sector[i * 32..i * 32 + 32]) {
+                        return Ok(Some(EntryLocation {
+                            lba: base + s,
+                            index: i,
+                        }));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Find a free (never used or deleted) slot in a directory, growing the
+    /// directory by one zeroed cluster if it is full.
+    fn alloc_dir_slot(&self, dir_cluster: u32) -> Result<EntryLocation, Fat32Error> {
+        if let Some(slot) = self.scan_dir(dir_cluster, |raw| raw[0] == 0x00 || raw[0] == 0xE5)? {
+            return Ok(slot);
+        }
+
+        let chain = self.get_chain(dir_cluster)?;
+        let new_cluster = self.alloc_cluster()?;
+        if let Err(e) = self.zero_cluster(new_cluster) {
+            let _ = self.free_chain(new_cluster);
+            return Err(e);
+        }
+        self.link_cluster(*chain.last().unwrap(), new_cluster)?;
+
+        Ok(EntryLocation {
+            lba: self.cluster_to_lba(new_cluster),
+            index: 0,
+        })
+    }
+
+    /// Read the 32-byte directory entry at `loc`
+    fn read_dir_entry(&self, loc: EntryLocation) -> Result<[u8; 32], Fat32Error> {
+        let mut sector = vec![0u8; self.fat_info.bytes_per_sector as usize];
+        self.dev
+            .read_block(loc.lba, &mut sector)
+            .map_err(|_| Fat32Error::ReadError)?;
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(&sector[loc.index * 32..loc.index * 32 + 32]);
+        Ok(raw)
+    }
+
+    /// Overwrite the 32-byte directory entry at `loc`
+    fn write_dir_entry(&self, loc: EntryLocation, raw: &[u8; 32]) -> Result<(), Fat32Error> {
+        let mut sector = vec![0u8; self.fat_info.bytes_per_sector as usize];
+        self.dev
+            .read_block(loc.lba, &mut sector)
+            .map_err(|_| Fat32Error::ReadError)?;
+        sector[loc.index * 32..loc.index * 32 + 32].copy_from_slice(raw);
+        self.dev
+            .write_block(loc.lba, &sector)
+            .map_err(|_| Fat32Error::WriteError)
+    }
+
+    /// Rewrite the mutable fields of a file's directory entry
+    fn update_dir_entry(
+        &self,
+        entry: &Mutex<EntryLocation>,
+        first_cluster: u32,
+        size: u32,
+        modified: FatTimestamp,
+    ) -> Result<(), Fat32Error> {
+        // Entries share sectors; serialise against other entry writers.
+        // The location is read under the lock so a concurrent rename
+        // cannot leave us writing to the old slot.
+        let _guard = self.metadata_lock.write();
+        let loc = *entry.lock();
+
+        let mut sector = vec![0u8; self.fat_info.bytes_per_sector as usize];
+        self.dev
+            .read_block(loc.lba, &mut sector)
+            .map_err(|_| Fat32Error::ReadError)?;
+
+        let raw = &mut sector[loc.index * 32..loc.index * 32 + 32];
+        raw[18..20].copy_from_slice(&modified.date.to_le_bytes()); // last access date
+        raw[20..22].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
+        raw[22..24].copy_from_slice(&modified.time.to_le_bytes());
+        raw[24..26].copy_from_slice(&modified.date.to_le_bytes());
+        raw[26..28].copy_from_slice(&(first_cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+
+        self.dev
+            .write_block(loc.lba, &sector)
+            .map_err(|_| Fat32Error::WriteError)
+    }
+
+    /// Point the `..` entry of a directory at its new parent
+    fn set_dotdot(&self, dir_cluster: u32, parent_cluster: u32) -> Result<(), Fat32Error> {
+        let Some(loc) = self.scan_dir(dir_cluster, |raw| raw[..11] == *b"..         ")? else {
+            return Ok(());
+        };
+
+        // `..` of a directory directly under the root holds cluster 0
+        let parent = if parent_cluster == self.fat_info.root_cluster {
+            0
+        } else {
+            parent_cluster
+        };
+
+        let mut raw = self.read_dir_entry(loc)?;
+        raw[20..22].copy_from_slice(&((parent >> 16) as u16).to_le_bytes());
+        raw[26..28].copy_from_slice(&(parent as u16).to_le_bytes());
+        self.write_dir_entry(loc, &raw)
+    }
+
+    // ============================================================================
+    // Open File Tracking
+    // ============================================================================
+
+    fn track_open_entry(&self, entry: &Arc<Mutex<EntryLocation>>) {
+        let mut open = self.open_entries.lock();
+        open.retain(|e| e.strong_count() > 0);
+        open.push(Arc::downgrade(entry));
+    }
+
+    /// Repoint open files at a directory entry that has moved
+    fn relocate_open_entry(&self, from: EntryLocation, to: EntryLocation) {
+        for entry in self.open_entries.lock().iter().filter_map(Weak::upgrade) {
+            let mut loc = entry.lock();
+            if *loc == from {
+                *loc = to;
+            }
+        }
+    }
+
+    /// Is an 8.3 name already used in a directory?
+    fn short_name_exists(&self, dir_cluster: u32, name: &[u8; 11]) -> Result<bool, Fat32Error> {
+        let found = self.scan_dir(dir_cluster, |raw| {
+            raw[0] != 0x00
+                && raw[0] != 0xE5
+                && raw[11] != Fat32Attribute::LongFilename as u8
+                && raw[..11] == name[..]
+        })?;
+        Ok(found.is_some())
+    }
+
+    /// Pick the on-disk 8.3 name for `name`, stored upper-cased.
+    ///
+    /// Long file name entries are neither written nor read, so a name
+    /// that does not fit 8.3 could not be looked up again afterwards and
+    /// is rejected instead of given a `~N` alias.
+    fn generate_short_name(&self, dir_cluster: u32, name: &str) -> Result<[u8; 11], Fat32Error> {
+        let (base, ext, lossy) = short_name_parts(name)?;
+        if lossy {
+            return Err(Fat32Error::InvalidName);
+        }
+
+        let short = pack_83(&base, &ext);
+        if self.short_name_exists(dir_cluster, &short)? {
+            return Err(Fat32Error::AlreadyExists);
+        }
+        Ok(short)
+    }
+
     fn find_entry(&self, start_cluster: u32, name: &str) -> Result<DirEntry, Fat32Error> {
         let mut sector = vec![0u8; self.fat_info.bytes_per_sector as usize];
         let chain = self.get_chain(start_cluster)?;
@@ -653,10 +1273,14 @@ impl Fat32FsInner {
                         // End of directory
                         return Err(Fat32Error::NotFound);
                     }
-                    if let Some(e) = parse_dir_entry(raw) {
-                        if e.name.eq_ignore_ascii_case(name) {
-                            return Ok(e);
-                        }
+                    let loc = EntryLocation {
+                        lba: base + s as u64,
+                        index: i,
+                    };
+                    if let Some(e) = parse_dir_entry(raw, loc)
+                        && e.name.eq_ignore_ascii_case(name)
+                    {
+                        return Ok(e);
                     }
                 }
             }
@@ -669,7 +1293,7 @@ impl Fat32FsInner {
 // Directory Entry Parsing
 // ============================================================================
 
-fn parse_dir_entry(raw: &[u8]) -> Option<DirEntry> {
+fn parse_dir_entry(raw: &[u8], location: EntryLocation) -> Option<DirEntry> {
     if raw[0] == 0xE5 {
         return None;
     }
@@ -698,9 +1322,97 @@ fn parse_dir_entry(raw: &[u8]) -> Option<DirEntry> {
         first_cluster,
         size,
         is_dir: attr & 0x10 != 0,
+        attr,
+        location,
     })
 }
 
+/// Does `sector` look like a FAT32 boot sector (as opposed to an MBR)?
+fn is_fat32_boot_sector(sector: &[u8]) -> bool {
+    matches!(sector[0], 0xEB | 0xE9)
+        && &sector[82..90] == b"FAT32   "
+        && sector[510..512] == [0x55, 0xAA]
+}
+
+/// Upper-cased 8.3 base and extension for `name`, and whether anything
+/// was lost (too long, or characters dropped/replaced).
+fn short_name_parts(name: &str) -> Result<(Vec<u8>, Vec<u8>, bool), Fat32Error> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(Fat32Error::InvalidPath);
+    }
+
+    // Extension is whatever follows the last dot (a leading dot does not count)
+    let (base, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i + 1..]),
+        _ => (name, ""),
+    };
+
+    let mut lossy = false;
+    let mut clean = |part: &str| -> Vec<u8> {
+        let mut out = Vec::new();
+        for c in part.chars() {
+            match c {
+                ' ' | '.' => lossy = true,
+                c if c.is_ascii_alphanumeric() || "!#$%&'()-@^_`{}~".contains(c) => {
+                    out.push(c.to_ascii_uppercase() as u8)
+                }
+                _ => {
+                    lossy = true;
+                    out.push(b'_');
+                }
+            }
+        }
+        out
+    };
+
+    let mut base = clean(base);
+    let mut ext = clean(ext);
+
+    if base.is_empty() {
+        return Err(Fat32Error::InvalidPath);
+    }
+    if base.len() > 8 {
+        base.truncate(8);
+        lossy = true;
+    }
+    if ext.len() > 3 {
+        ext.truncate(3);
+        lossy = true;
+    }
+
+    Ok((base, ext, lossy))
+}
+
+/// Pack base and extension into the space-padded 11-byte on-disk form
+fn pack_83(base: &[u8], ext: &[u8]) -> [u8; 11] {
+    let mut out = [b' '; 11];
+    out[..base.len()].copy_from_slice(base);
+    out[8..8 + ext.len()].copy_from_slice(ext);
+    out
+}
+
+/// Build a 32-byte short directory entry; all timestamps set to `now`
+fn build_dir_entry(
+    name: &[u8; 11],
+    attr: u8,
+    first_cluster: u32,
+    size: u32,
+    now: FatTimestamp,
+) -> [u8; 32] {
+    let mut raw = [0u8; 32];
+    raw[0..11].copy_from_slice(name);
+    raw[11] = attr;
+    raw[14..16].copy_from_slice(&now.time.to_le_bytes()); // creation time
+    raw[16..18].copy_from_slice(&now.date.to_le_bytes()); // creation date
+    raw[18..20].copy_from_slice(&now.date.to_le_bytes()); // last access date
+    raw[20..22].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
+    raw[22..24].copy_from_slice(&now.time.to_le_bytes()); // write time
+    raw[24..26].copy_from_slice(&now.date.to_le_bytes()); // write date
+    raw[26..28].copy_from_slice(&(first_cluster as u16).to_le_bytes());
+    raw[28..32].copy_from_slice(&size.to_le_bytes());
+    raw
+}
+
 fn parse_83(raw: &[u8]) -> String {
     let base = core::str::from_utf8(&raw[0..8]).unwrap_or("").trim_end();
     let ext = core::str::from_utf8(&raw[8..11]).unwrap_or("").trim_end();
@@ -719,14 +1431,30 @@ fn parse_83(raw: &[u8]) -> String {
 pub struct Fat32Fs(Arc<Fat32FsInner>);
 
 impl FileSystem for Fat32Fs {
-    fn open(&self, path: &str) -> Result<Arc<dyn File>, FsError> {
-        let file = Fat32FsInner::open(&self.0, path)?;
+    fn open(&self, path: &str, flags: OpenFlags) -> Result<Arc<dyn File>, FsError> {
+        let mut file = match Fat32FsInner::open(&self.0, path) {
+            Err(Fat32Error::NotFound) if flags.contains(OpenFlags::CREATE) => {
+                // Someone may have created it since the lookup
+                match Fat32FsInner::create(&self.0, path) {
+                    Err(Fat32Error::AlreadyExists) => Fat32FsInner::open(&self.0, path)?,
+                    result => result?,
+                }
+            }
+            result => result?,
+        };
+
+        if flags.contains(OpenFlags::TRUNC) && flags.intersects(OpenFlags::WRONLY | OpenFlags::RDWR)
+        {
+            file.truncate(0)?;
+        }
+        file.append = flags.contains(OpenFlags::APPEND);
+
         Ok(Arc::new(file))
     }
 
-    fn create(&self, _p: &str) -> Result<Arc<dyn File>, FsError> {
-        let _guard = self.0.metadata_lock.write();
-        todo!()
+    fn create(&self, p: &str) -> Result<Arc<dyn File>, FsError> {
+        let file = Fat32FsInner::create(&self.0, p)?;
+        Ok(Arc::new(file))
     }
 
     fn delete(&self, _p: &str) -> Result<(), FsError> {
@@ -738,6 +1466,10 @@ impl FileSystem for Fat32Fs {
         Ok(Fat32FsInner::ls(&*self.0, p)?)
     }
 
+    fn read_dir(&self, p: &str) -> Result<Vec<DirEntryInfo>, FsError> {
+        Ok(Fat32FsInner::read_dir(&self.0, p)?)
+    }
+
     fn mkdir(&self, _p: &str) -> Result<(), FsError> {
         let _guard = self.0.metadata_lock.write();
         todo!()
@@ -748,9 +1480,42 @@ impl FileSystem for Fat32Fs {
         todo!()
     }
 
+    fn rename(&self, old_path: &str, new_path: &str) -> Result<(), FsError> {
+        Ok(Fat32FsInner::rename(&self.0, old_path, new_path)?)
+    }
+
+    fn symlink(&self, target: &str, link_path: &str) -> Result<(), FsError> {
+        Ok(Fat32FsInner::symlink(&self.0, target, link_path)?)
+    }
+
+    fn readlink(&self, path: &str) -> Result<String, FsError> {
+        Ok(Fat32FsInner::readlink(&self.0, path)?)
+    }
+
+    fn statvfs(&self, _path: &str) -> Result<FsStat, FsError> {
+        Ok(Fat32FsInner::statvfs(&self.0)?)
+    }
+
     fn stat(&self, p: &str) -> Result<FileStat, FsError> {
         Ok(Fat32FsInner::stat(&*self.0, p)?)
     }
+
+    fn sync(&self) -> Result<(), FsError> {
+        Ok(Fat32FsInner::sync(&self.0)?)
+    }
+}
+
+impl Drop for Fat32Fs {
+    fn drop(&mut self) {
+        // Unmounted: files still open keep working, but re-read the FAT
+        self.0.invalidate_fat_cache();
+        if self.0.fs_info.lock().flush(&*self.0.dev).is_err() {
+            log::warn!("fat32: failed to update FSInfo sector on unmount");
+        }
+        if self.0.dev.flush().is_err() {
+            log::warn!("fat32: failed to flush device on unmount");
+        }
+    }
 }
 
 impl Fat32Fs {
@@ -774,6 +1539,10 @@ pub enum Fat32Error {
     IsADirectory,
     NotADirectory,
     DiskFull,
+    AlreadyExists,
+    NotASymlink,
+    /// Name that does not fit 8.3
+    InvalidName,
 }
 
 impl From<Fat32Error> for crate::fs::FsError {
@@ -784,9 +1553,12 @@ impl From<Fat32Error> for crate::fs::FsError {
                 crate::fs::FsError::IoError
             }
             Fat32Error::InvalidPath | Fat32Error::InvalidCluster => crate::fs::FsError::NotFound,
+            Fat32Error::InvalidName => crate::fs::FsError::InvalidPath,
             Fat32Error::IsADirectory => crate::fs::FsError::IsADirectory,
             Fat32Error::NotADirectory => crate::fs::FsError::NotADirectory,
             Fat32Error::DiskFull => crate::fs::FsError::IoError,
+            Fat32Error::AlreadyExists => crate::fs::FsError::AlreadyExists,
+            Fat32Error::NotASymlink => crate::fs::FsError::InvalidPath,
         }
     }
 }
@@ -806,9 +1578,209 @@ enum Fat32Attribute {
     LongFilename = 0x0F,
 }
 
+/// Leading bytes of a file emulating a symbolic link
+const SYMLINK_MAGIC: &[u8] = b"XSym\n";
+
+/// Longest link target stored
+const SYMLINK_MAX: usize = 1024;
+
+/// Number of FAT sectors kept in memory (each covers 128 clusters)
+const FAT_CACHE_SECTORS: usize = 64;
+
+/// Least-recently-used cache of FAT sectors
+struct FatCache {
+    sectors: BTreeMap<u64, CachedSector>,
+    tick: u64,
+}
+
+struct CachedSector {
+    data: Vec<u8>,
+    last_used: u64,
+}
+
+impl FatCache {
+    const fn new() -> Self {
+        Self {
+            sectors: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn contains(&self, lba: u64) -> bool {
+        self.sectors.contains_key(&lba)
+    }
+
+    fn get(&mut self, lba: u64) -> Option<&mut [u8]> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.sectors.get_mut(&lba).map(|s| {
+            s.last_used = tick;
+            s.data.as_mut_slice()
+        })
+    }
+
+    fn insert(&mut self, lba: u64, data: Vec<u8>) {
+        if self.sectors.len() >= FAT_CACHE_SECTORS {
+            let oldest = self
+                .sectors
+                .iter()
+                .min_by_key(|(_, s)| s.last_used)
+                .map(|(&lba, _)| lba);
+            if let Some(oldest) = oldest {
+                self.sectors.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.sectors.insert(
+            lba,
+            CachedSector {
+                data,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, lba: u64) {
+        self.sectors.remove(&lba);
+    }
+
+    fn clear(&mut self) {
+        self.sectors.clear();
+    }
+}
+
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIG: u32 = 0x6141_7272;
+const FSINFO_TRAIL_SIG: u32 = 0xAA55_0000;
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+/// Cached contents of the FSInfo sector
+struct FsInfo {
+    /// Sector holding FSInfo, `None` if the volume has no valid one
+    lba: Option<u64>,
+    /// Free cluster count, `None` until known
+    free_count: Option<u32>,
+    /// Where to start looking for a free cluster
+    next_free: u32,
+    /// Changed since the sector was last written; written on sync and
+    /// unmount rather than on every allocation
+    dirty: bool,
+    bytes_per_sector: usize,
+}
+
+impl FsInfo {
+    fn load(dev: &dyn DynBlockDevice, fat: &FatInfo) -> Result<Self, Fat32Error> {
+        let mut info = Self {
+            lba: None,
+            free_count: None,
+            next_free: 2,
+            dirty: false,
+            bytes_per_sector: fat.bytes_per_sector as usize,
+        };
+
+        if fat.fs_info_sector == 0 || fat.fs_info_sector == 0xFFFF {
+            return Ok(info);
+        }
+
+        let lba = fat.partition_start_lba + fat.fs_info_sector as u64;
+        let mut sector = vec![0u8; info.bytes_per_sector];
+        dev.read_block(lba, &mut sector)
+            .map_err(|_| Fat32Error::ReadError)?;
+
+        let word = |off: usize| {
+            u32::from_le_bytes([
+                sector[off],
+                sector[off + 1],
+                sector[off + 2],
+                sector[off + 3],
+            ])
+        };
+        if word(0) != FSINFO_LEAD_SIG
+            || word(484) != FSINFO_STRUCT_SIG
+            || word(508) != FSINFO_TRAIL_SIG
+        {
+            return Ok(info);
+        }
+
+        // Both fields are hints; discard values that cannot be right
+        let free = word(488);
+        if free != FSINFO_UNKNOWN && free <= fat.total_clusters {
+            info.free_count = Some(free);
+        }
+        let next = word(492);
+        if (2..=fat.last_cluster()).contains(&next) {
+            info.next_free = next;
+        }
+        info.lba = Some(lba);
+
+        Ok(info)
+    }
+
+    /// Write the free count and hint back to the FSInfo sector if they
+    /// changed.
+    fn flush(&mut self, dev: &dyn DynBlockDevice) -> Result<(), Fat32Error> {
+        let Some(lba) = self.lba.filter(|_| self.dirty) else {
+            return Ok(());
+        };
+
+        let mut sector = vec![0u8; self.bytes_per_sector];
+        dev.read_block(lba, &mut sector)
+            .map_err(|_| Fat32Error::ReadError)?;
+        sector[488..492].copy_from_slice(&self.free_count.unwrap_or(FSINFO_UNKNOWN).to_le_bytes());
+        sector[492..496].copy_from_slice(&self.next_free.to_le_bytes());
+        dev.write_block(lba, &sector)
+            .map_err(|_| Fat32Error::WriteError)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Packed FAT date and time (2-second resolution, local time = UTC)
+#[derive(Debug, Copy, Clone)]
+struct FatTimestamp {
+    date: u16,
+    time: u16,
+}
+
+impl FatTimestamp {
+    /// 1980-01-01 00:00:00, the earliest representable time
+    const EPOCH: FatTimestamp = FatTimestamp {
+        date: (1 << 5) | 1,
+        time: 0,
+    };
+
+    fn from_datetime(dt: &DateTime) -> Self {
+        if !(1980..=2107).contains(&dt.year) {
+            return Self::EPOCH;
+        }
+        Self {
+            date: ((dt.year - 1980) << 9) | ((dt.month as u16) << 5) | dt.day as u16,
+            time: ((dt.hour as u16) << 11) | ((dt.minute as u16) << 5) | (dt.second as u16 / 2),
+        }
+    }
+
+    /// Current time from the wall clock, or the FAT epoch without one
+    fn now() -> Self {
+        crate::time::now()
+            .map(|dt| Self::from_datetime(&dt))
+            .unwrap_or(Self::EPOCH)
+    }
+}
+
+/// Location of a 32-byte directory entry on disk
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct EntryLocation {
+    /// Sector holding the entry
+    lba: u64,
+    /// Entry index within the sector
+    index: usize,
+}
+
 struct DirEntry {
     name: String,
     first_cluster: u32,
     size: u32,
     is_dir: bool,
+    attr: u8,
+    location: EntryLocation,
 }
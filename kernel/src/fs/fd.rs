@@ -1,6 +1,12 @@
-use super::dev::UartFile;
-use super::file::{File, SeekWhence};
-use crate::fs::FsError;
+use super::dev::ConsoleFile;
+use super::file::{File, OpenFlags, SeekWhence};
+use crate::fs::{FileSystem, FsError};
+use crate::mm::address_space::MapError;
+use crate::process::elf::ExecError;
+use crate::process::exit::WaitError;
+use crate::process::fork::ForkError;
+use crate::process::futex::FutexError;
+use crate::process::signal::SignalError;
 use alloc::string::String;
 use alloc::{sync::Arc, vec::Vec};
 use bitflags::bitflags;
@@ -16,6 +22,8 @@ bitflags! {
     pub struct FdFlags : u32 {
         /// Close this fd on `exec`.
         const CLOEXEC = 1 << 0;
+        /// Fail with `WouldBlock` instead of waiting (`O_NONBLOCK`).
+        const NONBLOCK = 1 << 1;
     }
 }
 
@@ -41,6 +49,22 @@ impl AccessMode {
     pub const APPEND_MODE: Self = Self::WRITE.union(Self::APPEND);
 }
 
+impl From<OpenFlags> for AccessMode {
+    fn from(flags: OpenFlags) -> Self {
+        let mut mode = if flags.contains(OpenFlags::RDWR) {
+            Self::RDWR
+        } else if flags.contains(OpenFlags::WRONLY) {
+            Self::WRONLY
+        } else {
+            Self::RDONLY
+        };
+        if flags.contains(OpenFlags::APPEND) {
+            mode |= Self::APPEND;
+        }
+        mode
+    }
+}
+
 // ---------------------------------------------------------------------------
 // File descriptor number
 // ---------------------------------------------------------------------------
@@ -64,6 +88,7 @@ impl Fd {
 // ---------------------------------------------------------------------------
 
 /// A single entry in a process's file descriptor table.
+#[derive(Clone)]
 pub struct FileDescriptor {
     file: Arc<dyn File>,
     flags: FdFlags,
@@ -85,7 +110,11 @@ impl FileDescriptor {
         if !self.access.contains(AccessMode::READ) {
             return Err(FdError::PermissionDenied);
         }
-        let n = self.file.read(buf, self.offset)?;
+        let n = if self.flags.contains(FdFlags::NONBLOCK) {
+            self.file.try_read(buf, self.offset)?
+        } else {
+            self.file.read(buf, self.offset)?
+        };
         self.offset += n;
         Ok(n)
     }
@@ -97,7 +126,11 @@ impl FileDescriptor {
         if self.access.contains(AccessMode::APPEND) {
             self.offset = self.file.stat()?.size;
         }
-        let n = self.file.write(buf, self.offset)?;
+        let n = if self.flags.contains(FdFlags::NONBLOCK) {
+            self.file.try_write(buf, self.offset)?
+        } else {
+            self.file.write(buf, self.offset)?
+        };
         self.offset += n;
         Ok(n)
     }
@@ -116,6 +149,9 @@ impl FileDescriptor {
     pub fn offset(&self) -> usize {
         self.offset
     }
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset;
+    }
     pub fn file(&self) -> &Arc<dyn File> {
         &self.file
     }
@@ -129,6 +165,14 @@ impl FileDescriptor {
     pub fn set_cloexec(&mut self, enabled: bool) {
         self.flags.set(FdFlags::CLOEXEC, enabled);
     }
+
+    pub fn set_append(&mut self, enabled: bool) {
+        self.access.set(AccessMode::APPEND, enabled);
+    }
+
+    pub fn set_nonblocking(&mut self, enabled: bool) {
+        self.flags.set(FdFlags::NONBLOCK, enabled);
+    }
 }
 
 impl fmt::Debug for FileDescriptor {
@@ -146,17 +190,40 @@ impl fmt::Debug for FileDescriptor {
 // FileDescriptorTable
 // ---------------------------------------------------------------------------
 
+/// Open descriptors a process may hold unless its limit is changed
+pub const DEFAULT_FD_LIMIT: usize = 64;
+
+// fcntl commands
+/// Duplicate to the lowest free descriptor `>= arg`
+pub const F_DUPFD: u32 = 0;
+/// Get the descriptor flags (`FD_CLOEXEC`)
+pub const F_GETFD: u32 = 1;
+/// Set the descriptor flags (`FD_CLOEXEC`)
+pub const F_SETFD: u32 = 2;
+/// Get the access mode and status flags as [`OpenFlags`] bits
+pub const F_GETFL: u32 = 3;
+/// Set the `APPEND` and `NONBLOCK` status flags; other bits are ignored
+pub const F_SETFL: u32 = 4;
+
+/// `F_GETFD` / `F_SETFD` bit for close-on-exec
+pub const FD_CLOEXEC: usize = 1;
+
 /// Per-process file descriptor table.
 pub struct FileDescriptorTable {
     fds: Vec<Option<FileDescriptor>>,
+    /// Descriptors are allocated below this number
+    limit: usize,
 }
 
 impl FileDescriptorTable {
-    /// Creates a new table with stdin/stdout/stderr wired to platform UART 0.
+    /// Creates a new table with stdin/stdout/stderr open on the console.
     pub fn new() -> Self {
-        let mut table = Self { fds: Vec::new() };
+        let mut table = Self {
+            fds: Vec::new(),
+            limit: DEFAULT_FD_LIMIT,
+        };
 
-        let stdio_file = Arc::new(UartFile::new(0));
+        let stdio_file = Arc::new(ConsoleFile);
 
         table.fds.push(Some(FileDescriptor::new(
             stdio_file.clone(),
@@ -177,22 +244,62 @@ impl FileDescriptorTable {
         table
     }
 
+    /// Copy of the table for a forked child: the same files, flags and
+    /// offsets. Offsets are not shared, so reads and seeks in one process
+    /// do not move the other's.
+    pub fn fork(&self) -> Self {
+        Self {
+            fds: self.fds.clone(),
+            limit: self.limit,
+        }
+    }
+
+    /// Maximum number of descriptors; every open one is below it
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Change the descriptor limit. Descriptors already open at or above
+    /// the new limit stay open, but no new ones are handed out there.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
     pub fn alloc(
         &mut self,
         file: Arc<dyn File>,
         flags: FdFlags,
         access: AccessMode,
     ) -> Result<Fd, FdError> {
-        for (i, slot) in self.fds.iter_mut().enumerate() {
-            if slot.is_none() {
-                *slot = Some(FileDescriptor::new(file, flags, access));
-                return Ok(Fd(i));
-            }
+        self.alloc_from(Fd(0), file, flags, access)
+    }
+
+    /// Allocate the lowest free descriptor not below `min`
+    fn alloc_from(
+        &mut self,
+        min: Fd,
+        file: Arc<dyn File>,
+        flags: FdFlags,
+        access: AccessMode,
+    ) -> Result<Fd, FdError> {
+        let free = (min.0..self.limit).find(|&i| self.fds.get(i).is_none_or(|slot| slot.is_none()));
+        let Some(i) = free else {
+            return Err(FdError::TooManyFiles);
+        };
+
+        if i >= self.fds.len() {
+            self.fds.resize_with(i + 1, || None);
         }
-        let fd = Fd(self.fds.len());
-        self.fds
-            .push(Some(FileDescriptor::new(file, flags, access)));
-        Ok(fd)
+        self.fds[i] = Some(FileDescriptor::new(file, flags, access));
+        Ok(Fd(i))
+    }
+
+    /// Allocate a descriptor for `file`, opened with `flags`, whose access
+    /// mode follows them, so reads and writes outside it are refused.
+    pub fn install(&mut self, file: Arc<dyn File>, flags: OpenFlags) -> Result<Fd, FdError> {
+        let mut fd_flags = FdFlags::empty();
+        fd_flags.set(FdFlags::NONBLOCK, flags.contains(OpenFlags::NONBLOCK));
+        self.alloc(file, fd_flags, AccessMode::from(flags))
     }
 
     pub fn get(&self, fd: Fd) -> Result<&FileDescriptor, FdError> {
@@ -209,12 +316,13 @@ impl FileDescriptorTable {
             .ok_or(FdError::BadFd)
     }
 
-    pub fn close(&mut self, fd: Fd) -> Result<(), FdError> {
-        if fd.0 >= self.fds.len() || self.fds[fd.0].is_none() {
-            return Err(FdError::BadFd);
-        }
-        self.fds[fd.0] = None;
-        Ok(())
+    /// Close `fd`, handing the descriptor back as
+    /// [`close_all`](Self::close_all) does.
+    pub fn close(&mut self, fd: Fd) -> Result<FileDescriptor, FdError> {
+        self.fds
+            .get_mut(fd.0)
+            .and_then(Option::take)
+            .ok_or(FdError::BadFd)
     }
 
     pub fn dup(&mut self, oldfd: Fd) -> Result<Fd, FdError> {
@@ -222,35 +330,100 @@ impl FileDescriptorTable {
         self.alloc(Arc::clone(entry.file()), entry.flags(), entry.access())
     }
 
-    pub fn dup2(&mut self, oldfd: Fd, newfd: Fd) -> Result<Fd, FdError> {
+    /// Duplicate `oldfd` onto `newfd`, handing back what `newfd` held as
+    /// [`close_all`](Self::close_all) does.
+    pub fn dup2(&mut self, oldfd: Fd, newfd: Fd) -> Result<(Fd, Option<FileDescriptor>), FdError> {
         if oldfd == newfd {
-            return Ok(newfd);
+            return Ok((newfd, None));
         }
         let entry = self.get(oldfd)?;
         let file = Arc::clone(entry.file());
         let flags = entry.flags();
         let access = entry.access();
 
-        if newfd.0 < self.fds.len() && self.fds[newfd.0].is_some() {
-            self.close(newfd)?;
+        if newfd.0 >= self.limit {
+            return Err(FdError::BadFd);
         }
         while self.fds.len() <= newfd.0 {
             self.fds.push(None);
         }
-        self.fds[newfd.0] = Some(FileDescriptor::new(file, flags, access));
-        Ok(newfd)
+        let closed = self.fds[newfd.0].replace(FileDescriptor::new(file, flags, access));
+        Ok((newfd, closed))
+    }
+
+    /// Close every descriptor. The descriptors are handed back so that
+    /// the files are released once the caller drops its locks.
+    pub fn close_all(&mut self) -> Vec<FileDescriptor> {
+        self.fds.drain(..).flatten().collect()
+    }
+
+    /// Close every descriptor marked close-on-exec, handing them back as
+    /// [`close_all`](Self::close_all) does.
+    pub fn close_on_exec(&mut self) -> Vec<FileDescriptor> {
+        self.fds
+            .iter_mut()
+            .filter(|slot| {
+                slot.as_ref()
+                    .is_some_and(|fd| fd.flags().contains(FdFlags::CLOEXEC))
+            })
+            .filter_map(Option::take)
+            .collect()
     }
 
-    pub fn close_on_exec(&mut self) {
-        for slot in self.fds.iter_mut() {
-            if let Some(fd) = slot {
-                if fd.flags().contains(FdFlags::CLOEXEC) {
-                    *slot = None;
-                }
+    /// File control: `F_*` command on `fd`, returning the result value.
+    pub fn fcntl(&mut self, fd: Fd, cmd: u32, arg: usize) -> Result<usize, FdError> {
+        match cmd {
+            F_DUPFD => {
+                let entry = self.get(fd)?;
+                let (file, access) = (Arc::clone(entry.file()), entry.access());
+                // The copy does not inherit close-on-exec
+                let flags = entry.flags() - FdFlags::CLOEXEC;
+                Ok(self.alloc_from(Fd(arg), file, flags, access)?.0)
+            }
+            F_GETFD => {
+                let cloexec = self.get(fd)?.flags().contains(FdFlags::CLOEXEC);
+                Ok(if cloexec { FD_CLOEXEC } else { 0 })
+            }
+            F_SETFD => {
+                self.get_mut(fd)?.set_cloexec(arg & FD_CLOEXEC != 0);
+                Ok(0)
             }
+            F_GETFL => {
+                let entry = self.get(fd)?;
+                let access = entry.access();
+                let mut flags = if access.contains(AccessMode::RDWR) {
+                    OpenFlags::RDWR
+                } else if access.contains(AccessMode::WRITE) {
+                    OpenFlags::WRONLY
+                } else {
+                    OpenFlags::RDONLY
+                };
+                flags.set(OpenFlags::APPEND, access.contains(AccessMode::APPEND));
+                flags.set(
+                    OpenFlags::NONBLOCK,
+                    entry.flags().contains(FdFlags::NONBLOCK),
+                );
+                Ok(flags.bits() as usize)
+            }
+            F_SETFL => {
+                let flags = OpenFlags::from_bits_truncate(arg as u32);
+                let entry = self.get_mut(fd)?;
+                entry.set_append(flags.contains(OpenFlags::APPEND));
+                entry.set_nonblocking(flags.contains(OpenFlags::NONBLOCK));
+                Ok(0)
+            }
+            _ => Err(FdError::InvalidArgument),
         }
     }
 
+    /// Open descriptors in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (Fd, &FileDescriptor)> {
+        self.fds
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| slot.as_ref().map(|entry| (Fd(i), entry)))
+    }
+
     pub fn count(&self) -> usize {
         self.fds.iter().filter(|fd| fd.is_some()).count()
     }
@@ -261,6 +434,7 @@ impl fmt::Debug for FileDescriptorTable {
         f.debug_struct("FileDescriptorTable")
             .field("open_fds", &self.count())
             .field("capacity", &self.fds.len())
+            .field("limit", &self.limit)
             .finish()
     }
 }
@@ -277,6 +451,21 @@ pub enum FdError {
     InvalidSeek,
     NotSupported,
     PermissionDenied,
+    InvalidArgument,
+    /// Write to a pipe with no reader left
+    BrokenPipe,
+    /// Non-blocking operation could not proceed without waiting
+    WouldBlock,
+    /// Not enough memory, or no room in the address space
+    OutOfMemory,
+    /// No child process to wait for
+    NoChild,
+    /// No process with the given PID
+    NoSuchProcess,
+    /// A user pointer to memory the caller may not access
+    BadAddress,
+    /// Failure of a filesystem operation on a path
+    Fs(FsError),
     Other(String),
 }
 
@@ -287,11 +476,84 @@ impl From<FdError> for FsError {
             FdError::IoError => FsError::IoError,
             FdError::NotSupported => FsError::NotSupported,
             FdError::PermissionDenied => FsError::PermissionDenied,
+            FdError::OutOfMemory => FsError::OutOfMemory,
+            FdError::Fs(err) => err,
             _ => FsError::Unknown,
         }
     }
 }
 
+impl From<FsError> for FdError {
+    fn from(err: FsError) -> Self {
+        FdError::Fs(err)
+    }
+}
+
+impl From<MapError> for FdError {
+    fn from(err: MapError) -> Self {
+        match err {
+            MapError::Unaligned | MapError::OutOfRange => FdError::InvalidArgument,
+            MapError::OutOfMemory | MapError::AlreadyMapped => FdError::OutOfMemory,
+        }
+    }
+}
+
+impl From<ForkError> for FdError {
+    fn from(err: ForkError) -> Self {
+        match err {
+            ForkError::NoProcess => FdError::NotSupported,
+            ForkError::OutOfMemory => FdError::OutOfMemory,
+            ForkError::TooManyProcesses => FdError::WouldBlock,
+        }
+    }
+}
+
+impl From<WaitError> for FdError {
+    fn from(err: WaitError) -> Self {
+        match err {
+            WaitError::NoProcess => FdError::NotSupported,
+            WaitError::NoChild => FdError::NoChild,
+        }
+    }
+}
+
+impl From<SignalError> for FdError {
+    fn from(err: SignalError) -> Self {
+        match err {
+            SignalError::NoProcess => FdError::NotSupported,
+            SignalError::NoSuchProcess => FdError::NoSuchProcess,
+            SignalError::InvalidSignal | SignalError::InvalidArgument => FdError::InvalidArgument,
+        }
+    }
+}
+
+impl From<FutexError> for FdError {
+    fn from(err: FutexError) -> Self {
+        match err {
+            FutexError::NoProcess => FdError::NotSupported,
+            FutexError::Unaligned => FdError::InvalidArgument,
+            FutexError::BadAddress => FdError::BadAddress,
+            FutexError::ValueChanged => FdError::WouldBlock,
+            FutexError::OutOfMemory => FdError::OutOfMemory,
+        }
+    }
+}
+
+impl From<ExecError> for FdError {
+    fn from(err: ExecError) -> Self {
+        match err {
+            ExecError::NotExecutable | ExecError::Malformed => FdError::NotSupported,
+            ExecError::ArgumentsTooLong => FdError::InvalidArgument,
+            ExecError::NoProcess => FdError::NotSupported,
+            ExecError::TooManyProcesses => FdError::WouldBlock,
+            ExecError::Fs(FsError::PermissionDenied) => FdError::PermissionDenied,
+            ExecError::Fs(FsError::OutOfMemory) => FdError::OutOfMemory,
+            ExecError::Fs(_) => FdError::IoError,
+            ExecError::Map(err) => err.into(),
+        }
+    }
+}
+
 impl fmt::Display for FdError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -301,6 +563,14 @@ impl fmt::Display for FdError {
             FdError::InvalidSeek => write!(f, "invalid seek"),
             FdError::NotSupported => write!(f, "operation not supported"),
             FdError::PermissionDenied => write!(f, "permission denied"),
+            FdError::InvalidArgument => write!(f, "invalid argument"),
+            FdError::BrokenPipe => write!(f, "broken pipe"),
+            FdError::WouldBlock => write!(f, "operation would block"),
+            FdError::OutOfMemory => write!(f, "out of memory"),
+            FdError::NoChild => write!(f, "no child processes"),
+            FdError::NoSuchProcess => write!(f, "no such process"),
+            FdError::BadAddress => write!(f, "bad address"),
+            FdError::Fs(err) => write!(f, "filesystem error: {:?}", err),
             FdError::Other(code) => write!(f, "unknown error: {}", code),
         }
     }
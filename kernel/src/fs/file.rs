@@ -8,10 +8,45 @@ pub trait File: Send + Sync {
     /// Write to the file
     fn write(&self, buf: &[u8], offset: usize) -> Result<usize, FdError>;
 
+    /// Read without waiting: like [`read`](File::read), but fails with
+    /// [`FdError::WouldBlock`] when no data is available yet. Files whose
+    /// reads never wait keep the default.
+    fn try_read(&self, buf: &mut [u8], offset: usize) -> Result<usize, FdError> {
+        self.read(buf, offset)
+    }
+
+    /// Write without waiting: transfers what fits and fails with
+    /// [`FdError::WouldBlock`] only when nothing does.
+    fn try_write(&self, buf: &[u8], offset: usize) -> Result<usize, FdError> {
+        self.write(buf, offset)
+    }
+
+    /// Which operations would complete without waiting right now
+    fn poll(&self) -> PollEvents {
+        PollEvents::IN | PollEvents::OUT
+    }
+
     /// Get file statistics
     fn stat(&self) -> Result<FileStat, FdError> {
         Err(FdError::NotSupported)
     }
+
+    /// Write buffered data and metadata back to the device
+    fn flush(&self) -> Result<(), FdError> {
+        Ok(())
+    }
+
+    /// Set the file size to `len`, discarding data past it or zero-filling
+    /// up to it
+    fn truncate(&self, _len: usize) -> Result<(), FdError> {
+        Err(FdError::NotSupported)
+    }
+
+    /// Device-specific control; commands are listed in
+    /// [`ioctl`](super::ioctl)
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> Result<usize, FdError> {
+        Err(FdError::NotSupported)
+    }
 }
 
 /// Type of file in the filesystem
@@ -97,6 +132,25 @@ bitflags::bitflags! {
         const CREATE = 1 << 6;
         const TRUNC = 1 << 9;
         const APPEND = 1 << 10;
+        const NONBLOCK = 1 << 11;
+    }
+}
+
+bitflags::bitflags! {
+    /// Readiness events for [`File::poll`] (values match `POLL*` from
+    /// POSIX `poll(2)`)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PollEvents: u16 {
+        /// Data can be read
+        const IN = 0x0001;
+        /// Data can be written
+        const OUT = 0x0004;
+        /// Error condition, e.g. a pipe with no reader left
+        const ERR = 0x0008;
+        /// The other end hung up
+        const HUP = 0x0010;
+        /// The descriptor is not open
+        const NVAL = 0x0020;
     }
 }
 
@@ -121,3 +175,14 @@ pub struct FileStat {
     /// File name
     pub name: alloc::string::String,
 }
+
+/// Directory entry returned by [`FileSystem::read_dir`](super::FileSystem::read_dir)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntryInfo {
+    /// Entry name (no path)
+    pub name: alloc::string::String,
+    /// Type of file
+    pub file_type: FileType,
+    /// File size in bytes (0 for directories)
+    pub size: usize,
+}
@@ -0,0 +1,142 @@
+//! cpio "newc" archive parser.
+//!
+//! The format used by Linux initramfs images (`cpio -H newc`): each member
+//! is a 110-byte ASCII header of hex fields, the NUL-terminated name and
+//! the file data, with header+name and data each padded to 4 bytes. The
+//! archive ends with a member named `TRAILER!!!`.
+
+/// Header magic without and with checksums (the checksum is not verified)
+const MAGIC_NEWC: &[u8] = b"070701";
+const MAGIC_CRC: &[u8] = b"070702";
+
+const HEADER_LEN: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+/// File type bits of `mode`
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFBLK: u32 = 0o060000;
+
+/// cpio parse errors
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CpioError {
+    /// Archive ends inside a member or before the trailer
+    Truncated,
+    /// Member header does not start with a newc magic
+    BadMagic,
+    /// Header field is not hexadecimal
+    BadField,
+    /// Name is not NUL-terminated UTF-8
+    BadName,
+}
+
+/// One archive member
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<'a> {
+    /// Path as stored, usually relative (`bin/init` or `./bin/init`)
+    pub name: &'a str,
+    /// Type and permission bits
+    pub mode: u32,
+    /// File contents, or the target of a symbolic link
+    pub data: &'a [u8],
+}
+
+impl Entry<'_> {
+    pub fn file_type(&self) -> u32 {
+        self.mode & S_IFMT
+    }
+}
+
+/// Iterator over the members of an archive, stopping at the trailer.
+///
+/// Yields an error once and then ends if the archive is malformed.
+pub struct Reader<'a> {
+    archive: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(archive: &'a [u8]) -> Self {
+        Self {
+            archive,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    fn next_entry(&mut self) -> Result<Option<Entry<'a>>, CpioError> {
+        let header = self
+            .archive
+            .get(self.offset..self.offset + HEADER_LEN)
+            .ok_or(CpioError::Truncated)?;
+
+        let magic = &header[..6];
+        if magic != MAGIC_NEWC && magic != MAGIC_CRC {
+            return Err(CpioError::BadMagic);
+        }
+
+        // Fields after the magic: ino, mode, uid, gid, nlink, mtime,
+        // filesize, devmajor, devminor, rdevmajor, rdevminor, namesize, check
+        let field = |index: usize| parse_hex(&header[6 + index * 8..14 + index * 8]);
+        let mode = field(1)?;
+        let file_size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        let name_start = self.offset + HEADER_LEN;
+        let name = self
+            .archive
+            .get(name_start..name_start + name_size)
+            .ok_or(CpioError::Truncated)?;
+        let name = match name.split_last() {
+            Some((0, name)) => core::str::from_utf8(name).map_err(|_| CpioError::BadName)?,
+            _ => return Err(CpioError::BadName),
+        };
+
+        let data_start = align4(name_start + name_size);
+        let data = self
+            .archive
+            .get(data_start..data_start + file_size)
+            .ok_or(CpioError::Truncated)?;
+
+        self.offset = align4(data_start + file_size);
+
+        if name == TRAILER {
+            return Ok(None);
+        }
+        Ok(Some(Entry { name, mode, data }))
+    }
+}
+
+impl<'a> Iterator for Reader<'a> {
+    type Item = Result<Entry<'a>, CpioError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_entry() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn parse_hex(field: &[u8]) -> Result<u32, CpioError> {
+    let text = core::str::from_utf8(field).map_err(|_| CpioError::BadField)?;
+    u32::from_str_radix(text, 16).map_err(|_| CpioError::BadField)
+}
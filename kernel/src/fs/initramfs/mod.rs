@@ -0,0 +1,300 @@
+//! Initial RAM filesystem.
+//!
+//! A read-only view of a cpio "newc" archive, mounted as the root before
+//! any block device is up so an init program can run from it. The archive
+//! is either handed over by the bootloader (ATAG_INITRD2, DTB `/chosen`,
+//! Multiboot2 module) or linked into the kernel image's `.initramfs`
+//! section. A built-in archive stays in memory for the life of the kernel
+//! and file data is served straight from it; a bootloader's is unpacked,
+//! so the memory it was loaded into can be given back.
+
+pub mod cpio;
+
+use self::cpio::{CpioError, Reader, S_IFBLK, S_IFCHR, S_IFDIR, S_IFLNK, S_IFREG};
+use super::fd::FdError;
+use super::file::{DirEntryInfo, File, FileStat, FileType, OpenFlags};
+use super::{FileSystem, FsError, FsStat};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+use drivers::platform::Platform;
+
+/// Where the archive to mount as root is
+pub enum Archive {
+    /// Loaded by the bootloader into memory the page allocator keeps
+    /// reserved until it is unpacked
+    Loaded(&'static [u8]),
+    /// Linked into the kernel image
+    BuiltIn(&'static [u8]),
+}
+
+/// The archive to mount as root: the bootloader's if it passed one,
+/// otherwise the one built into the kernel.
+pub fn locate() -> Option<Archive> {
+    if let Some((base, size)) = Platform::initrd() {
+        // SAFETY: the bootloader placed the archive in RAM and the
+        // allocators are kept away from it until it is unpacked
+        let data = unsafe { core::slice::from_raw_parts(base as *const u8, size) };
+        return Some(Archive::Loaded(data));
+    }
+
+    unsafe extern "C" {
+        static __initramfs_start: u8;
+        static __initramfs_end: u8;
+    }
+    let start = &raw const __initramfs_start;
+    let end = &raw const __initramfs_end;
+    let len = end as usize - start as usize;
+    // SAFETY: the linker script brackets the section with these symbols
+    (len > 0).then(|| Archive::BuiltIn(unsafe { core::slice::from_raw_parts(start, len) }))
+}
+
+/// Contents of a member: in an archive that stays in memory, or copied
+/// out of one that does not
+#[derive(Clone)]
+enum Data {
+    Static(&'static [u8]),
+    Owned(Arc<[u8]>),
+}
+
+impl Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Data::Static(data) => data,
+            Data::Owned(data) => data,
+        }
+    }
+}
+
+/// An archive member
+#[derive(Clone)]
+struct Node {
+    file_type: FileType,
+    data: Data,
+}
+
+pub struct InitramFs {
+    // Keyed by path without leading or trailing `/`; the root is implicit
+    nodes: BTreeMap<String, Node>,
+}
+
+impl InitramFs {
+    /// Index every member of `archive`, which file data is then served
+    /// from.
+    ///
+    /// Parent directories missing from the archive are added, so
+    /// `cpio` lists generated without directory entries still browse.
+    pub fn new(archive: &'static [u8]) -> Result<Self, CpioError> {
+        Self::index(archive, Data::Static)
+    }
+
+    /// Like [`Self::new`], but copy file data out so `archive` can be
+    /// freed afterwards.
+    pub fn unpack(archive: &[u8]) -> Result<Self, CpioError> {
+        Self::index(archive, |data| Data::Owned(Arc::from(data)))
+    }
+
+    fn index<'a>(archive: &'a [u8], keep: impl Fn(&'a [u8]) -> Data) -> Result<Self, CpioError> {
+        let mut nodes = BTreeMap::new();
+
+        for entry in Reader::new(archive) {
+            let entry = entry?;
+            let path = normalize(entry.name);
+            if path.is_empty() {
+                continue;
+            }
+
+            let file_type = match entry.file_type() {
+                S_IFDIR => FileType::Directory,
+                S_IFREG => FileType::Regular,
+                S_IFLNK => FileType::Symlink,
+                S_IFCHR => FileType::CharDevice,
+                S_IFBLK => FileType::BlockDevice,
+                // FIFOs and sockets have no meaning without a writer
+                _ => continue,
+            };
+
+            let mut parent = path;
+            while let Some((dir, _)) = parent.rsplit_once('/') {
+                nodes.entry(String::from(dir)).or_insert(Node {
+                    file_type: FileType::Directory,
+                    data: Data::Static(&[]),
+                });
+                parent = dir;
+            }
+
+            nodes.insert(
+                String::from(path),
+                Node {
+                    file_type,
+                    data: keep(entry.data),
+                },
+            );
+        }
+
+        Ok(Self { nodes })
+    }
+
+    fn node(&self, path: &str) -> Result<Node, FsError> {
+        let path = normalize(path);
+        if path.is_empty() {
+            return Ok(Node {
+                file_type: FileType::Directory,
+                data: Data::Static(&[]),
+            });
+        }
+        self.nodes.get(path).cloned().ok_or(FsError::NotFound)
+    }
+
+    /// Direct children of the directory at `path`
+    fn children(&self, path: &str) -> Result<impl Iterator<Item = (&str, &Node)>, FsError> {
+        if !self.node(path)?.file_type.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+        let dir = normalize(path);
+        Ok(self.nodes.iter().filter_map(move |(key, node)| {
+            let name = if dir.is_empty() {
+                key.as_str()
+            } else {
+                key.strip_prefix(dir)?.strip_prefix('/')?
+            };
+            (!name.contains('/')).then_some((name, node))
+        }))
+    }
+}
+
+/// Strip `./`, leading and trailing `/` from an archive or VFS path
+fn normalize(path: &str) -> &str {
+    let path = path.trim_matches('/');
+    let path = path.strip_prefix("./").unwrap_or(path);
+    if path == "." { "" } else { path }
+}
+
+impl FileSystem for InitramFs {
+    fn open(&self, path: &str, flags: OpenFlags) -> Result<Arc<dyn File>, FsError> {
+        let node = self.node(path)?;
+        match node.file_type {
+            FileType::Regular => {}
+            FileType::Directory => return Err(FsError::IsADirectory),
+            _ => return Err(FsError::NotSupported),
+        }
+        let modifies = OpenFlags::WRONLY | OpenFlags::RDWR | OpenFlags::CREATE | OpenFlags::TRUNC;
+        if flags.intersects(modifies) {
+            return Err(FsError::PermissionDenied);
+        }
+
+        let name = normalize(path);
+        Ok(Arc::new(InitramFile {
+            name: String::from(name.rsplit('/').next().unwrap_or(name)),
+            data: node.data,
+        }))
+    }
+
+    fn create(&self, _path: &str) -> Result<Arc<dyn File>, FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn delete(&self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn stat(&self, path: &str) -> Result<FileStat, FsError> {
+        let node = self.node(path)?;
+        let name = normalize(path);
+        Ok(FileStat {
+            size: node.data.len(),
+            file_type: node.file_type,
+            name: String::from(name.rsplit('/').next().unwrap_or(name)),
+        })
+    }
+
+    fn ls(&self, path: &str) -> Result<Vec<String>, FsError> {
+        Ok(self
+            .children(path)?
+            .map(|(name, _)| String::from(name))
+            .collect())
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntryInfo>, FsError> {
+        Ok(self
+            .children(path)?
+            .map(|(name, node)| DirEntryInfo {
+                name: String::from(name),
+                file_type: node.file_type,
+                size: node.data.len(),
+            })
+            .collect())
+    }
+
+    fn mkdir(&self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn rmdir(&self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn rename(&self, _old_path: &str, _new_path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn symlink(&self, _target: &str, _link_path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn readlink(&self, path: &str) -> Result<String, FsError> {
+        let node = self.node(path)?;
+        if node.file_type != FileType::Symlink {
+            return Err(FsError::InvalidPath);
+        }
+        core::str::from_utf8(&node.data)
+            .map(String::from)
+            .map_err(|_| FsError::InvalidPath)
+    }
+
+    /// Everything is in use and nothing can be allocated.
+    fn statvfs(&self, _path: &str) -> Result<FsStat, FsError> {
+        let used: usize = self.nodes.values().map(|node| node.data.len()).sum();
+        Ok(FsStat {
+            block_size: 1,
+            total_blocks: used as u64,
+            free_blocks: 0,
+        })
+    }
+
+    /// Read-only and in memory.
+    fn sync(&self) -> Result<(), FsError> {
+        Ok(())
+    }
+}
+
+/// Regular file in the archive
+struct InitramFile {
+    name: String,
+    data: Data,
+}
+
+impl File for InitramFile {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, FdError> {
+        let available = self.data.get(offset..).unwrap_or(&[]);
+        let n = buf.len().min(available.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        Ok(n)
+    }
+
+    fn write(&self, _buf: &[u8], _offset: usize) -> Result<usize, FdError> {
+        Err(FdError::PermissionDenied)
+    }
+
+    fn stat(&self) -> Result<FileStat, FdError> {
+        Ok(FileStat {
+            size: self.data.len(),
+            file_type: FileType::Regular,
+            name: self.name.clone(),
+        })
+    }
+}
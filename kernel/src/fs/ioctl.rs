@@ -0,0 +1,129 @@
+//! ioctl command numbers and argument layouts.
+//!
+//! Commands are grouped by device class in the high byte, following the
+//! Linux convention (`'T'` for terminals, `0x12` for block devices, `'F'`
+//! for framebuffers). Values are only meaningful to the file type that
+//! defines them; every other file answers
+//! [`FdError::NotSupported`](super::fd::FdError::NotSupported).
+
+use drivers::hal::fb::PixelFormat;
+use drivers::hal::serial::{DataBits, Parity, SerialConfig, StopBits};
+
+// ============================================================================
+// Serial Ports
+// ============================================================================
+
+/// Get the baud rate (returned)
+pub const TIOCGBAUD: u32 = 0x5401;
+/// Set the baud rate (`arg`)
+pub const TIOCSBAUD: u32 = 0x5402;
+/// Get the frame format, encoded as below (returned)
+pub const TIOCGFORMAT: u32 = 0x5403;
+/// Set the frame format, encoded as below (`arg`)
+pub const TIOCSFORMAT: u32 = 0x5404;
+/// Wait until all queued output has been transmitted
+pub const TIOCDRAIN: u32 = 0x5405;
+
+/// Frame format: data bits minus 5
+pub const FORMAT_DATA_MASK: usize = 0b11;
+/// Frame format: parity (0 none, 1 odd, 2 even)
+pub const FORMAT_PARITY_SHIFT: usize = 2;
+pub const FORMAT_PARITY_MASK: usize = 0b11 << FORMAT_PARITY_SHIFT;
+/// Frame format: two stop bits
+pub const FORMAT_STOP2: usize = 1 << 4;
+
+/// Encode the frame format of `config` for [`TIOCGFORMAT`].
+pub fn encode_format(config: &SerialConfig) -> usize {
+    let data = match config.data_bits {
+        DataBits::Five => 0,
+        DataBits::Six => 1,
+        DataBits::Seven => 2,
+        DataBits::Eight => 3,
+    };
+    let parity = match config.parity {
+        Parity::None => 0,
+        Parity::Odd => 1,
+        Parity::Even => 2,
+    };
+    let stop = match config.stop_bits {
+        StopBits::One => 0,
+        StopBits::Two => FORMAT_STOP2,
+    };
+    data | parity << FORMAT_PARITY_SHIFT | stop
+}
+
+/// Apply a [`TIOCSFORMAT`] argument to `config`, keeping its baud rate.
+///
+/// Returns `None` for unknown bits or an invalid parity value.
+pub fn decode_format(config: &SerialConfig, arg: usize) -> Option<SerialConfig> {
+    if arg & !(FORMAT_DATA_MASK | FORMAT_PARITY_MASK | FORMAT_STOP2) != 0 {
+        return None;
+    }
+    let data_bits = match arg & FORMAT_DATA_MASK {
+        0 => DataBits::Five,
+        1 => DataBits::Six,
+        2 => DataBits::Seven,
+        _ => DataBits::Eight,
+    };
+    let parity = match (arg & FORMAT_PARITY_MASK) >> FORMAT_PARITY_SHIFT {
+        0 => Parity::None,
+        1 => Parity::Odd,
+        2 => Parity::Even,
+        _ => return None,
+    };
+    let stop_bits = if arg & FORMAT_STOP2 != 0 {
+        StopBits::Two
+    } else {
+        StopBits::One
+    };
+    Some(SerialConfig {
+        baud_rate: config.baud_rate,
+        data_bits,
+        parity,
+        stop_bits,
+    })
+}
+
+// ============================================================================
+// Block Devices
+// ============================================================================
+
+/// Get the device size in sectors (returned)
+pub const BLKGETSIZE: u32 = 0x1260;
+/// Write back anything the device has buffered
+pub const BLKFLSBUF: u32 = 0x1261;
+/// Get the sector size in bytes (returned)
+pub const BLKSSZGET: u32 = 0x1268;
+
+// ============================================================================
+// Framebuffers
+// ============================================================================
+
+/// Fill the [`FbIoctlInfo`] that `arg` points to
+pub const FBIOGET_INFO: u32 = 0x4600;
+
+/// Framebuffer geometry returned by [`FBIOGET_INFO`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FbIoctlInfo {
+    /// Visible width in pixels
+    pub width: u32,
+    /// Visible height in pixels
+    pub height: u32,
+    /// Bytes per row
+    pub pitch: u32,
+    /// Bytes per pixel
+    pub bytes_per_pixel: u32,
+    /// Channel order (0 RGB, 1 BGR, 2 RGBA, 3 BGRA)
+    pub pixel_format: u32,
+}
+
+/// Numeric channel order used in [`FbIoctlInfo::pixel_format`]
+pub fn pixel_format_code(format: PixelFormat) -> u32 {
+    match format {
+        PixelFormat::Rgb => 0,
+        PixelFormat::Bgr => 1,
+        PixelFormat::Rgba => 2,
+        PixelFormat::Bgra => 3,
+    }
+}
@@ -1,14 +1,20 @@
 use alloc::{string::String, sync::Arc, vec::Vec};
 
-use crate::fs::file::File;
+use crate::fs::file::{File, OpenFlags};
 
 pub mod dev;
+pub mod ext2;
 pub mod fat;
 pub mod fd;
 pub mod file;
+pub mod initramfs;
+pub mod ioctl;
+pub mod pipe;
+pub mod poll;
+pub mod proc;
 pub mod vfs;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FsError {
     NotFound,
     AlreadyExists,
@@ -16,13 +22,58 @@ pub enum FsError {
     IsADirectory,
     PermissionDenied,
     NotSupported,
+    /// Operation spans two mounted filesystems
+    CrossDevice,
+    /// Malformed path, or `..` above the root
+    InvalidPath,
+    /// Mount point still has open files or nested mounts
+    Busy,
+    /// Too many levels of symbolic links
+    LinkLoop,
+    /// The kernel heap cannot hold a buffer the operation needs
+    OutOfMemory,
     IoError,
     Unknown,
 }
 
+/// Zeroed buffer of `len` bytes, or `None` if the heap cannot hold it.
+///
+/// For buffers sized by a user request or by on-disk metadata, where
+/// running out of memory must fail the operation instead of panicking.
+pub fn try_zeroed(len: usize) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).ok()?;
+    buf.resize(len, 0);
+    Some(buf)
+}
+
+/// Collect `iter` like [`try_zeroed`]: `None` if the heap cannot hold
+/// every item.
+pub fn try_collect<T>(iter: impl ExactSizeIterator<Item = T>) -> Option<Vec<T>> {
+    let mut items = Vec::new();
+    items.try_reserve_exact(iter.len()).ok()?;
+    items.extend(iter);
+    Some(items)
+}
+
+/// Filesystem space usage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsStat {
+    /// Allocation unit in bytes
+    pub block_size: usize,
+    /// Total allocation units
+    pub total_blocks: u64,
+    /// Unallocated allocation units
+    pub free_blocks: u64,
+}
+
 pub trait FileSystem: Send + Sync {
-    /// Open a file
-    fn open(&self, path: &str) -> Result<Arc<dyn File>, FsError>;
+    /// Open a file.
+    ///
+    /// `CREATE` creates a missing file, `TRUNC` empties a file opened for
+    /// writing and `APPEND` makes every write go to the end of the file.
+    /// Access mode and `NONBLOCK` are handled by the fd layer, not here.
+    fn open(&self, path: &str, flags: OpenFlags) -> Result<Arc<dyn File>, FsError>;
 
     /// Create a file
     fn create(&self, path: &str) -> Result<Arc<dyn File>, FsError>;
@@ -36,9 +87,30 @@ pub trait FileSystem: Send + Sync {
     /// List directory contents
     fn ls(&self, path: &str) -> Result<Vec<String>, FsError>;
 
+    /// List directory contents with type and size of each entry
+    fn read_dir(&self, path: &str) -> Result<Vec<file::DirEntryInfo>, FsError>;
+
     /// Make a directory
     fn mkdir(&self, path: &str) -> Result<(), FsError>;
 
     /// Remove a directory
     fn rmdir(&self, path: &str) -> Result<(), FsError>;
+
+    /// Rename or move a file or directory within this filesystem
+    fn rename(&self, old_path: &str, new_path: &str) -> Result<(), FsError>;
+
+    /// Create a symbolic link at `link_path` pointing to `target`
+    fn symlink(&self, target: &str, link_path: &str) -> Result<(), FsError>;
+
+    /// Read the target of a symbolic link (`InvalidPath` if `path` is not one)
+    fn readlink(&self, path: &str) -> Result<String, FsError>;
+
+    /// Get space usage of the filesystem containing `path`
+    fn statvfs(&self, path: &str) -> Result<FsStat, FsError>;
+
+    /// Write everything the filesystem has buffered back to its device.
+    ///
+    /// Covers filesystem metadata and the device's own cache; data held
+    /// by open files is written by [`File::flush`].
+    fn sync(&self) -> Result<(), FsError>;
 }
@@ -0,0 +1,329 @@
+//! Pipes.
+//!
+//! A pipe is a fixed-size ring buffer with a read end and a write end.
+//! Readers wait for data and see end of file once every write end is
+//! closed; writers wait for space and fail with
+//! [`FdError::BrokenPipe`] once every read end is closed. An end is
+//! closed when its last [`PipeFile`] handle is dropped, so `dup`ed
+//! descriptors keep it open as expected.
+//!
+//! Waiting readers and writers sleep on a condition variable each, which
+//! the other end notifies as it moves data or closes, waking pollers as
+//! well. Descriptors opened with `NONBLOCK` get [`FdError::WouldBlock`]
+//! instead.
+//!
+//! Anonymous pipes come from [`pipe`]. Named pipes (FIFOs) are a [`Fifo`]
+//! kept by the filesystem that owns the node; every open of the node
+//! attaches a new end to the same buffer.
+
+use super::fd::FdError;
+use super::file::{File, FileStat, FileType, OpenFlags, PollEvents};
+use super::poll;
+use crate::sync::{CondVar, Mutex};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+
+/// Bytes a pipe can hold before writers have to wait
+pub const PIPE_CAPACITY: usize = 4096;
+
+struct Ring {
+    buf: Box<[u8]>,
+    /// Index of the oldest byte
+    head: usize,
+    len: usize,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buf: vec![0u8; capacity].into_boxed_slice(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn free(&self) -> usize {
+        self.buf.len() - self.len
+    }
+
+    /// Move up to `out.len()` bytes out of the ring
+    fn pop(&mut self, out: &mut [u8]) -> usize {
+        let n = out.len().min(self.len);
+        for byte in &mut out[..n] {
+            *byte = self.buf[self.head];
+            self.head = (self.head + 1) % self.buf.len();
+        }
+        self.len -= n;
+        n
+    }
+
+    /// Move as much of `data` as fits into the ring
+    fn push(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(self.free());
+        let cap = self.buf.len();
+        for (i, &byte) in data[..n].iter().enumerate() {
+            self.buf[(self.head + self.len + i) % cap] = byte;
+        }
+        self.len += n;
+        n
+    }
+}
+
+struct PipeState {
+    ring: Ring,
+    readers: usize,
+    writers: usize,
+    /// Set once a write end has been opened. A FIFO reader that arrives
+    /// first waits for a writer instead of seeing end of file.
+    had_writer: bool,
+}
+
+impl PipeState {
+    /// Read what is there into `buf`, or find there is nothing to wait
+    /// for: 0 once no writer is left
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FdError> {
+        if self.ring.len > 0 {
+            Ok(self.ring.pop(buf))
+        } else if self.had_writer && self.writers == 0 {
+            Ok(0)
+        } else {
+            Err(FdError::WouldBlock)
+        }
+    }
+
+    /// Write as much of `buf` as fits
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FdError> {
+        if self.readers == 0 {
+            return Err(FdError::BrokenPipe);
+        }
+        match self.ring.push(buf) {
+            0 if !buf.is_empty() => Err(FdError::WouldBlock),
+            n => Ok(n),
+        }
+    }
+}
+
+/// Buffer shared by both ends of a pipe
+struct Pipe {
+    state: Mutex<PipeState>,
+    /// Notified when data arrives or the last writer leaves
+    readable: CondVar,
+    /// Notified when space frees up or the last reader leaves
+    writable: CondVar,
+}
+
+impl Pipe {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(PipeState {
+                ring: Ring::new(PIPE_CAPACITY),
+                readers: 0,
+                writers: 0,
+                had_writer: false,
+            }),
+            readable: CondVar::new(),
+            writable: CondVar::new(),
+        })
+    }
+}
+
+/// One end of a pipe
+pub struct PipeFile {
+    pipe: Arc<Pipe>,
+    readable: bool,
+    writable: bool,
+}
+
+impl PipeFile {
+    fn attach(pipe: &Arc<Pipe>, readable: bool, writable: bool) -> Self {
+        let mut state = pipe.state.lock();
+        if readable {
+            state.readers += 1;
+        }
+        if writable {
+            state.writers += 1;
+            state.had_writer = true;
+        }
+        drop(state);
+
+        Self {
+            pipe: Arc::clone(pipe),
+            readable,
+            writable,
+        }
+    }
+
+    /// Read from the locked pipe, letting writers know of the space
+    fn read_locked(&self, state: &mut PipeState, buf: &mut [u8]) -> Result<usize, FdError> {
+        let result = state.read(buf);
+        if matches!(result, Ok(n) if n > 0) {
+            self.pipe.writable.notify_all();
+            poll::wake();
+        }
+        result
+    }
+
+    /// Write to the locked pipe, letting readers know of the data
+    fn write_locked(&self, state: &mut PipeState, buf: &[u8]) -> Result<usize, FdError> {
+        let result = state.write(buf);
+        if matches!(result, Ok(n) if n > 0) {
+            self.pipe.readable.notify_all();
+            poll::wake();
+        }
+        result
+    }
+}
+
+impl Drop for PipeFile {
+    fn drop(&mut self) {
+        let mut state = self.pipe.state.lock();
+        if self.readable {
+            state.readers -= 1;
+        }
+        if self.writable {
+            state.writers -= 1;
+        }
+        drop(state);
+        // Whoever waits on the other end may now be done waiting
+        self.pipe.readable.notify_all();
+        self.pipe.writable.notify_all();
+        poll::wake();
+    }
+}
+
+impl File for PipeFile {
+    /// Wait until data is available and read what is there, or return 0
+    /// once the buffer is empty and no writer is left.
+    fn read(&self, buf: &mut [u8], _offset: usize) -> Result<usize, FdError> {
+        if !self.readable {
+            return Err(FdError::PermissionDenied);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut state = self.pipe.state.lock();
+        loop {
+            match self.read_locked(&mut state, buf) {
+                Err(FdError::WouldBlock) => state = self.pipe.readable.wait(state),
+                result => return result,
+            }
+        }
+    }
+
+    /// Write all of `buf`, waiting for space as needed.
+    fn write(&self, buf: &[u8], _offset: usize) -> Result<usize, FdError> {
+        if !self.writable {
+            return Err(FdError::PermissionDenied);
+        }
+
+        let mut state = self.pipe.state.lock();
+        let mut done = 0;
+        while done < buf.len() {
+            match self.write_locked(&mut state, &buf[done..]) {
+                Ok(n) => done += n,
+                Err(FdError::WouldBlock) => state = self.pipe.writable.wait(state),
+                // Report what already went through, like a short write
+                Err(FdError::BrokenPipe) if done > 0 => return Ok(done),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(done)
+    }
+
+    fn try_read(&self, buf: &mut [u8], _offset: usize) -> Result<usize, FdError> {
+        if !self.readable {
+            return Err(FdError::PermissionDenied);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.read_locked(&mut self.pipe.state.lock(), buf)
+    }
+
+    fn try_write(&self, buf: &[u8], _offset: usize) -> Result<usize, FdError> {
+        if !self.writable {
+            return Err(FdError::PermissionDenied);
+        }
+
+        self.write_locked(&mut self.pipe.state.lock(), buf)
+    }
+
+    fn poll(&self) -> PollEvents {
+        let state = self.pipe.state.lock();
+        let hung_up = state.had_writer && state.writers == 0;
+
+        let mut events = PollEvents::empty();
+        if self.readable {
+            if state.ring.len > 0 || hung_up {
+                events |= PollEvents::IN;
+            }
+            if hung_up {
+                events |= PollEvents::HUP;
+            }
+        }
+        if self.writable {
+            if state.readers == 0 {
+                events |= PollEvents::ERR;
+            } else if state.ring.free() > 0 {
+                events |= PollEvents::OUT;
+            }
+        }
+        events
+    }
+
+    /// The size is the number of bytes waiting to be read.
+    fn stat(&self) -> Result<FileStat, FdError> {
+        Ok(FileStat {
+            size: self.pipe.state.lock().ring.len,
+            file_type: FileType::Pipe,
+            name: "pipe".into(),
+        })
+    }
+}
+
+/// Create an anonymous pipe, returning its `(read, write)` ends.
+pub fn pipe() -> (Arc<PipeFile>, Arc<PipeFile>) {
+    let pipe = Pipe::new();
+    let read = Arc::new(PipeFile::attach(&pipe, true, false));
+    let write = Arc::new(PipeFile::attach(&pipe, false, true));
+    (read, write)
+}
+
+/// Named pipe: the buffer behind a FIFO node.
+///
+/// Data written but not yet read survives while any end is open and is
+/// discarded with the `Fifo` itself.
+pub struct Fifo {
+    pipe: Arc<Pipe>,
+}
+
+impl Fifo {
+    pub fn new() -> Self {
+        Self { pipe: Pipe::new() }
+    }
+
+    /// Open an end with the access mode in `flags`; `RDWR` gives one
+    /// handle that is both a reader and a writer.
+    pub fn open(&self, flags: OpenFlags) -> Arc<PipeFile> {
+        let (readable, writable) = if flags.contains(OpenFlags::RDWR) {
+            (true, true)
+        } else if flags.contains(OpenFlags::WRONLY) {
+            (false, true)
+        } else {
+            (true, false)
+        };
+        Arc::new(PipeFile::attach(&self.pipe, readable, writable))
+    }
+
+    /// Bytes waiting to be read
+    pub fn len(&self) -> usize {
+        self.pipe.state.lock().ring.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,37 @@
+//! Waiting for files to become ready
+//!
+//! `poll` sleeps on one queue whatever files it waits for. Whatever
+//! changes what a file's [`File::poll`](super::file::File::poll) reports,
+//! such as data arriving in a pipe or on the console or an end closing,
+//! calls [`wake`], and the pollers check their files again. Files that
+//! cannot say when they become ready, such as serial ports other than the
+//! console, are checked again every timer tick.
+
+use crate::process::sched::WaitQueue;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Where pollers sleep
+static POLLERS: WaitQueue = WaitQueue::new();
+
+/// Bumped on every [`wake`], so a poller can tell it missed none between
+/// checking its files and going to sleep
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+/// Note that a file may have become ready, and wake the pollers.
+/// Callable from interrupt context.
+pub fn wake() {
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+    POLLERS.wake_all();
+}
+
+/// The count of [`wake`]s so far, to read before checking the files and
+/// pass to [`wait`]
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Acquire)
+}
+
+/// Sleep until [`wake`] is called after `seen` was read from
+/// [`generation`], or for a timer tick at most.
+pub fn wait(seen: u32) {
+    POLLERS.sleep_on_timeout(|| generation() != seen, 1);
+}
@@ -0,0 +1,392 @@
+//! Process filesystem (`/proc`).
+//!
+//! A flat, read-only directory of text files generated from kernel state.
+//! Contents are captured when a file is opened, so one handle always reads
+//! a consistent snapshot. `kmsg` is the kernel message log, `tasks`
+//! lists the CPU use of every task, and `net` the network interfaces;
+//! per-process directories are yet to come.
+
+use super::fd::FdError;
+use super::file::{DirEntryInfo, File, FileStat, FileType, OpenFlags};
+use super::{FileSystem, FsError, FsStat};
+use crate::irq::handlers::{self, MAX_IRQS};
+use crate::mm::page_allocator::{self, Zone};
+use crate::mm::{self, buddy_allocator::AllocatorStats, heap_allocator};
+use crate::net;
+use crate::process::sched;
+use crate::subsystems::{device_manager, uptime_us};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Produces the contents of one file
+type Generator = fn() -> Result<String, FsError>;
+
+/// Every file in the filesystem, sorted by name
+const ENTRIES: &[(&str, Generator)] = &[
+    ("devices", devices),
+    ("interrupts", interrupts),
+    ("kmsg", kmsg),
+    ("memcheck", memcheck),
+    ("meminfo", meminfo),
+    ("net", net),
+    ("stat", stat),
+    ("tasks", tasks),
+    ("uptime", uptime),
+];
+
+pub struct ProcFs;
+
+impl ProcFs {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn entry(path: &str) -> Result<Generator, FsError> {
+        let path = path.trim_start_matches('/');
+        ENTRIES
+            .iter()
+            .find(|(name, _)| *name == path)
+            .map(|&(_, generate)| generate)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn is_root(path: &str) -> bool {
+        path.trim_start_matches('/').is_empty()
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn open(&self, path: &str, flags: OpenFlags) -> Result<Arc<dyn File>, FsError> {
+        if Self::is_root(path) {
+            return Err(FsError::IsADirectory);
+        }
+        let generate = Self::entry(path)?;
+        let modifies = OpenFlags::WRONLY | OpenFlags::RDWR | OpenFlags::CREATE | OpenFlags::TRUNC;
+        if flags.intersects(modifies) {
+            return Err(FsError::PermissionDenied);
+        }
+
+        Ok(Arc::new(ProcFile {
+            name: path.trim_start_matches('/').into(),
+            data: generate()?.into_bytes(),
+        }))
+    }
+
+    fn create(&self, _path: &str) -> Result<Arc<dyn File>, FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn delete(&self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    /// Generated files report a size of zero; read them to the end.
+    fn stat(&self, path: &str) -> Result<FileStat, FsError> {
+        if Self::is_root(path) {
+            return Ok(FileStat {
+                size: 0,
+                file_type: FileType::Directory,
+                name: "/".into(),
+            });
+        }
+        Self::entry(path)?;
+        Ok(FileStat {
+            size: 0,
+            file_type: FileType::Regular,
+            name: path.trim_start_matches('/').into(),
+        })
+    }
+
+    fn ls(&self, path: &str) -> Result<Vec<String>, FsError> {
+        if !Self::is_root(path) {
+            Self::entry(path)?;
+            return Err(FsError::NotADirectory);
+        }
+        Ok(ENTRIES
+            .iter()
+            .map(|(name, _)| String::from(*name))
+            .collect())
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntryInfo>, FsError> {
+        if !Self::is_root(path) {
+            Self::entry(path)?;
+            return Err(FsError::NotADirectory);
+        }
+        Ok(ENTRIES
+            .iter()
+            .map(|(name, _)| DirEntryInfo {
+                name: String::from(*name),
+                file_type: FileType::Regular,
+                size: 0,
+            })
+            .collect())
+    }
+
+    fn mkdir(&self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn rmdir(&self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn rename(&self, _old_path: &str, _new_path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn symlink(&self, _target: &str, _link_path: &str) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn readlink(&self, path: &str) -> Result<String, FsError> {
+        if !Self::is_root(path) {
+            Self::entry(path)?;
+        }
+        Err(FsError::InvalidPath)
+    }
+
+    fn statvfs(&self, _path: &str) -> Result<FsStat, FsError> {
+        Err(FsError::NotSupported)
+    }
+
+    /// Nothing is buffered.
+    fn sync(&self) -> Result<(), FsError> {
+        Ok(())
+    }
+}
+
+/// Snapshot of a generated file
+struct ProcFile {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl File for ProcFile {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, FdError> {
+        let available = self.data.get(offset..).unwrap_or(&[]);
+        let n = buf.len().min(available.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        Ok(n)
+    }
+
+    fn write(&self, _buf: &[u8], _offset: usize) -> Result<usize, FdError> {
+        Err(FdError::PermissionDenied)
+    }
+
+    fn stat(&self) -> Result<FileStat, FdError> {
+        Ok(FileStat {
+            size: self.data.len(),
+            file_type: FileType::Regular,
+            name: self.name.clone(),
+        })
+    }
+}
+
+// ============================================================================
+// Generators
+// ============================================================================
+
+/// Registered devices, one `name class` line each
+fn devices() -> Result<String, FsError> {
+    let dm = device_manager().lock();
+    let mut out = String::new();
+    for (name, device) in dm.devices() {
+        let _ = writeln!(out, "{:<16} {}", name, device.class().name());
+    }
+    Ok(out)
+}
+
+/// Statistics of every line that has fired or has a handler: dispatch
+/// count, dispatches no handler claimed, uptime of the last dispatch and
+/// longest handler time in microseconds, and the handlers registered.
+/// The last line counts IRQs taken with nothing pending.
+fn interrupts() -> Result<String, FsError> {
+    let mut out = String::new();
+    for irq in 0..MAX_IRQS as u32 {
+        let stats = handlers::irq_stats(irq);
+        let handlers = handlers::handler_count(irq);
+        if stats.count != 0 || handlers != 0 {
+            let _ = writeln!(
+                out,
+                "{:>4}: {:>10} {:>10} {:>14} {:>8} {}",
+                irq, stats.count, stats.unhandled, stats.last_us, stats.max_us, handlers
+            );
+        }
+    }
+    let _ = writeln!(out, " SPU: {:>10}", handlers::spurious_count());
+    Ok(out)
+}
+
+/// The kernel message log, oldest record first
+fn kmsg() -> Result<String, FsError> {
+    let mut out = String::new();
+    // Reserve first: the ring is locked while it is read, and allocating
+    // may log
+    out.try_reserve(crate::logger::KMSG_SIZE)
+        .map_err(|_| FsError::OutOfMemory)?;
+    crate::logger::for_each_record(|record| out.push_str(record));
+    Ok(out)
+}
+
+/// Result of walking the free lists of the heap and each page zone
+fn memcheck() -> Result<String, FsError> {
+    let pages = page_allocator::page_allocator();
+    let results = [
+        ("Heap", heap_allocator::check_heap()),
+        ("PageDma", pages.check_consistency(Zone::Dma)),
+        ("PageNormal", pages.check_consistency(Zone::Normal)),
+    ];
+
+    let mut out = String::new();
+    for (name, result) in results {
+        let _ = match result {
+            Some(Ok(())) => writeln!(out, "{}: ok", name),
+            Some(Err(corruption)) => writeln!(out, "{}: {:?}", name, corruption),
+            None => continue,
+        };
+    }
+    Ok(out)
+}
+
+/// Heap, page allocator and DMA zone usage in kB, allocation counters,
+/// and the number of free blocks of each order (smallest first)
+fn meminfo() -> Result<String, FsError> {
+    // Take both snapshots before allocating the output
+    let stats = mm::stats();
+
+    let mut out = String::new();
+    let mut section = |prefix: &str, stats: Option<AllocatorStats>| {
+        if let Some(stats) = stats {
+            let sizes = [
+                ("Total: ", stats.total_bytes),
+                ("Used:  ", stats.used_bytes),
+                ("Free:  ", stats.free_bytes),
+                ("Peak:  ", stats.peak_used_bytes),
+                ("Resvd: ", stats.reserved_bytes),
+                ("Block: ", stats.largest_free),
+            ];
+            for (name, bytes) in sizes {
+                let _ = writeln!(out, "{}{} {:>10} kB", prefix, name, bytes / 1024);
+            }
+            let counts = [
+                ("Allocs:", stats.allocations),
+                ("Frees: ", stats.frees),
+                ("Failed:", stats.failed_allocations),
+            ];
+            for (name, count) in counts {
+                let _ = writeln!(out, "{}{} {:>10}", prefix, name, count);
+            }
+            let _ = write!(out, "{}Orders:", prefix);
+            for count in stats.free_blocks {
+                let _ = write!(out, " {}", count);
+            }
+            out.push('\n');
+        }
+    };
+    section("Heap", stats.heap);
+    section("Page", stats.pages);
+    section("Dma", stats.dma);
+    Ok(out)
+}
+
+/// Every network interface: state, hardware and IPv4 address, DHCP lease
+/// and traffic counters, then the DNS servers known
+fn net() -> Result<String, FsError> {
+    let mut out = String::new();
+    for iface in net::iface::interfaces() {
+        let state = if !iface.up {
+            "down"
+        } else if iface.link {
+            "up"
+        } else {
+            "no-carrier"
+        };
+        let _ = writeln!(
+            out,
+            "{}: {} mtu {} ether {}",
+            iface.name, state, iface.mtu, iface.mac
+        );
+        if let Some(config) = iface.config {
+            let _ = write!(out, "    inet {}/{}", config.addr, config.prefix_len);
+            if let Some(gateway) = config.gateway {
+                let _ = write!(out, " gateway {}", gateway);
+            }
+            let _ = match net::dhcp::lease(&iface.name) {
+                Some(lease) => writeln!(
+                    out,
+                    " dhcp {} expires {}s",
+                    lease.server,
+                    lease.remaining_secs()
+                ),
+                None => writeln!(out, " static"),
+            };
+        }
+        let stats = iface.stats;
+        let _ = writeln!(
+            out,
+            "    rx {} packets {} bytes {} dropped tx {} packets {} bytes {} errors",
+            stats.rx_packets,
+            stats.rx_bytes,
+            stats.rx_dropped,
+            stats.tx_packets,
+            stats.tx_bytes,
+            stats.tx_errors
+        );
+    }
+    for server in net::ipconfig::dns_servers() {
+        let _ = writeln!(out, "nameserver {}", server);
+    }
+    Ok(out)
+}
+
+/// CPU time spent busy and idle since the scheduler started, in
+/// microseconds, and the share of it busy
+fn stat() -> Result<String, FsError> {
+    let (busy, idle) = sched::cpu_stats();
+    let total = (busy + idle).max(1);
+    Ok(alloc::format!(
+        "busy_us: {}\nidle_us: {}\nload:    {}%\n",
+        busy,
+        idle,
+        busy * 100 / total
+    ))
+}
+
+/// One line per task: ID, name, state, nice level, CPU and CPU time in ms
+fn tasks() -> Result<String, FsError> {
+    // Take the snapshot before allocating the output
+    let tasks = sched::task_stats();
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:>5} {:<16} {:<8} {:>4} {:>3} {:>10}",
+        "ID", "NAME", "STATE", "NI", "CPU", "CPU_MS"
+    );
+    for task in tasks {
+        let _ = writeln!(
+            out,
+            "{:>5} {:<16} {:<8} {:>4} {:>3} {:>10}",
+            task.id.0,
+            task.name,
+            alloc::format!("{:?}", task.state),
+            task.nice,
+            task.cpu,
+            task.cpu_us / 1000
+        );
+    }
+    Ok(out)
+}
+
+/// Seconds since the system timer started counting
+fn uptime() -> Result<String, FsError> {
+    let us = uptime_us().ok_or(FsError::NotSupported)?;
+    Ok(alloc::format!(
+        "{}.{:02}\n",
+        us / 1_000_000,
+        us % 1_000_000 / 10_000
+    ))
+}
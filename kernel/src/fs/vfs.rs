@@ -1,18 +1,72 @@
-use crate::fs::file::{File, FileStat};
-use crate::fs::{FileSystem, FsError};
+use crate::fs::fd::FdError;
+use crate::fs::file::{DirEntryInfo, File, FileStat, FileType, OpenFlags, PollEvents};
+use crate::fs::{FileSystem, FsError, FsStat};
+use crate::sync::Mutex;
 
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-use spin::Mutex;
+use bitflags::bitflags;
+
+bitflags! {
+    /// Per-mount options, enforced by the VFS whatever the backing filesystem.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct MountFlags: u32 {
+        /// Refuse every modification (`MS_RDONLY`)
+        const READ_ONLY = 1 << 0;
+        /// Files may not be executed (`MS_NOEXEC`); checked by the loader
+        const NOEXEC = 1 << 1;
+        /// Flush after every write (`MS_SYNCHRONOUS`)
+        const SYNC = 1 << 2;
+    }
+}
 
 /// A mount point in the VFS.
 pub struct Mount {
     pub prefix: String,
     pub fs: Arc<dyn FileSystem>,
+    pub flags: MountFlags,
+    usage: Arc<MountUsage>,
+}
+
+/// Shared between a mount and the files opened through it
+#[derive(Default)]
+struct MountUsage {
+    open_files: AtomicUsize,
+    // Force-unmounted: remaining handles fail all I/O
+    detached: AtomicBool,
 }
 
+impl Mount {
+    fn new(prefix: String, fs: Arc<dyn FileSystem>, flags: MountFlags) -> Self {
+        Self {
+            prefix,
+            fs,
+            flags,
+            usage: Arc::new(MountUsage::default()),
+        }
+    }
+
+    /// Number of files opened through this mount that are still alive.
+    pub fn open_files(&self) -> usize {
+        self.usage.open_files.load(Ordering::Acquire)
+    }
+
+    fn check_writable(&self) -> Result<(), FsError> {
+        if self.flags.contains(MountFlags::READ_ONLY) {
+            Err(FsError::PermissionDenied)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Symbolic links followed while resolving one path before giving up with
+/// [`FsError::LinkLoop`]
+const MAX_LINK_HOPS: usize = 40;
+
 static VFS: VirtFS = VirtFS::new();
 
 pub struct VirtFS {
@@ -30,30 +84,46 @@ impl VirtFS {
     pub fn init(&'static self, rootfs: Arc<dyn FileSystem>) {
         let mut mounts = self.mounts.lock();
         mounts.clear();
-        mounts.push(Mount {
-            prefix: "/".into(),
-            fs: rootfs,
-        });
+        mounts.push(Mount::new("/".into(), rootfs, MountFlags::empty()));
     }
 
     /// Mount a filesystem at a path.
-    pub fn mount_fs(&self, prefix: &str, fs: Arc<dyn FileSystem>) -> Result<(), FsError> {
+    pub fn mount_fs(
+        &self,
+        prefix: &str,
+        fs: Arc<dyn FileSystem>,
+        flags: MountFlags,
+    ) -> Result<(), FsError> {
+        let prefix = canonicalize(prefix)?;
         let mut mounts = self.mounts.lock();
 
         if mounts.iter().any(|m| m.prefix == prefix) {
             return Err(FsError::AlreadyExists);
         }
 
-        mounts.push(Mount {
-            prefix: prefix.into(),
-            fs,
-        });
+        mounts.push(Mount::new(prefix, fs, flags));
 
         Ok(())
     }
 
     /// Unmount a filesystem.
+    ///
+    /// Fails with [`FsError::Busy`] while files opened through the mount
+    /// are still alive or another filesystem is mounted below it.
     pub fn umount(&self, prefix: &str) -> Result<(), FsError> {
+        self.unmount(prefix, false)
+    }
+
+    /// Unmount a filesystem even if it is busy.
+    ///
+    /// Files still open on it are detached: every further operation on
+    /// them fails with an I/O error. Nested mounts are left in place.
+    pub fn umount_force(&self, prefix: &str) -> Result<(), FsError> {
+        self.unmount(prefix, true)
+    }
+
+    fn unmount(&self, prefix: &str, force: bool) -> Result<(), FsError> {
+        let prefix = canonicalize(prefix)?;
         let mut mounts = self.mounts.lock();
 
         let idx = mounts
@@ -61,23 +131,148 @@ impl VirtFS {
             .position(|m| m.prefix == prefix)
             .ok_or(FsError::NotFound)?;
 
-        mounts.remove(idx);
+        if !force {
+            let nested = mounts.iter().any(|m| {
+                m.prefix != prefix
+                    && (prefix == "/"
+                        || m.prefix
+                            .strip_prefix(prefix.as_str())
+                            .is_some_and(|rest| rest.starts_with('/')))
+            });
+            if nested || mounts[idx].open_files() > 0 {
+                return Err(FsError::Busy);
+            }
+        }
+
+        let mount = mounts.remove(idx);
+        mount.usage.detached.store(true, Ordering::Release);
         Ok(())
     }
 
-    /// Dispatch a path to the filesystem with the longest matching mount prefix.
+    /// Every mount point and its options, oldest mount first.
+    pub fn mounts(&self) -> Vec<(String, MountFlags)> {
+        self.mounts
+            .lock()
+            .iter()
+            .map(|m| (m.prefix.clone(), m.flags))
+            .collect()
+    }
+
+    /// Options of the mount that `path` resolves to.
+    pub fn mount_flags(&self, path: &str) -> Result<MountFlags, FsError> {
+        self.dispatch(path, |mount, _| Ok(mount.flags))
+    }
+
+    /// Dispatch a path to the filesystem with the longest matching mount
+    /// prefix, following symbolic links in every component.
     fn dispatch<T, F>(&self, path: &str, f: F) -> Result<T, FsError>
     where
         F: Fn(&Mount, &str) -> Result<T, FsError>,
     {
+        let path = self.follow_links(path, true)?;
         let mounts = self.mounts.lock();
+        let (mount, rest) = Self::resolve(&mounts, &path)?;
+        f(mount, rest)
+    }
 
+    /// Like [`Self::dispatch`], but a link in the last component is
+    /// operated on rather than followed.
+    fn dispatch_nofollow<T, F>(&self, path: &str, f: F) -> Result<T, FsError>
+    where
+        F: Fn(&Mount, &str) -> Result<T, FsError>,
+    {
+        let path = self.follow_links(path, false)?;
+        let mounts = self.mounts.lock();
+        let (mount, rest) = Self::resolve(&mounts, &path)?;
+        f(mount, rest)
+    }
+
+    /// Canonicalize `path` and substitute symbolic links component by
+    /// component, the last one only if `follow_last` is set.
+    ///
+    /// `..` is resolved lexically before any link is looked at, so
+    /// `link/..` is the directory holding `link`, not the parent of its
+    /// target. Relative targets are taken from the directory holding the
+    /// link. Links may cross mounts.
+    fn follow_links(&self, path: &str, follow_last: bool) -> Result<String, FsError> {
+        let mut path = canonicalize(path)?;
+        let mut hops = 0;
+
+        'restart: loop {
+            let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+            let mut current = String::with_capacity(path.len());
+
+            for (i, part) in parts.iter().enumerate() {
+                let parent_len = current.len();
+                current.push('/');
+                current.push_str(part);
+
+                if i + 1 == parts.len() && !follow_last {
+                    break;
+                }
+
+                let Some(target) = self.link_at(&current)? else {
+                    continue;
+                };
+
+                hops += 1;
+                if hops > MAX_LINK_HOPS {
+                    return Err(FsError::LinkLoop);
+                }
+
+                let mut next = if target.starts_with('/') {
+                    String::new()
+                } else {
+                    String::from(&current[..parent_len])
+                };
+                next.push('/');
+                next.push_str(&target);
+                for rest in &parts[i + 1..] {
+                    next.push('/');
+                    next.push_str(rest);
+                }
+
+                path = canonicalize(&next)?;
+                continue 'restart;
+            }
+
+            return Ok(path);
+        }
+    }
+
+    /// Target of the link at canonical `path`, if it is one. Lookup errors
+    /// are left for the operation itself to report.
+    fn link_at(&self, path: &str) -> Result<Option<String>, FsError> {
+        let mounts = self.mounts.lock();
+        let (mount, rest) = Self::resolve(&mounts, path)?;
+
+        // A mount point is always the root of its filesystem
+        if rest.is_empty() {
+            return Ok(None);
+        }
+
+        match mount.fs.stat(rest) {
+            Ok(stat) if stat.file_type == FileType::Symlink => mount.fs.readlink(rest).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Find the mount with the longest prefix of the canonical `path` and
+    /// the path relative to it. Prefixes only match whole components, so
+    /// `/dev` does not capture `/devices`.
+    fn resolve<'a>(mounts: &'a [Mount], path: &'a str) -> Result<(&'a Mount, &'a str), FsError> {
         let mut best: Option<(&Mount, &str)> = None;
 
         for mount in mounts.iter() {
-            if let Some(rest) = path.strip_prefix(&mount.prefix) {
-                let rest = rest.strip_prefix('/').unwrap_or(rest);
+            let rest = if mount.prefix == "/" {
+                path.strip_prefix('/')
+            } else {
+                path.strip_prefix(mount.prefix.as_str())
+                    .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                    .map(|rest| rest.strip_prefix('/').unwrap_or(rest))
+            };
 
+            if let Some(rest) = rest {
                 match best {
                     None => best = Some((mount, rest)),
                     Some((prev, _)) if mount.prefix.len() > prev.prefix.len() => {
@@ -88,39 +283,252 @@ impl VirtFS {
             }
         }
 
-        let (mount, rest) = best.ok_or(FsError::NotFound)?;
-        f(mount, rest)
+        best.ok_or(FsError::NotFound)
     }
 }
 
 impl FileSystem for VirtFS {
-    fn open(&self, path: &str) -> Result<Arc<dyn File>, FsError> {
-        self.dispatch(path, |mount, rest| mount.fs.open(rest))
+    fn open(&self, path: &str, flags: OpenFlags) -> Result<Arc<dyn File>, FsError> {
+        self.dispatch(path, |mount, rest| {
+            let modifies =
+                OpenFlags::WRONLY | OpenFlags::RDWR | OpenFlags::CREATE | OpenFlags::TRUNC;
+            if flags.intersects(modifies) {
+                mount.check_writable()?;
+            }
+            let file = mount.fs.open(rest, flags)?;
+            Ok(MountFile::wrap(file, mount))
+        })
     }
 
     fn create(&self, path: &str) -> Result<Arc<dyn File>, FsError> {
-        self.dispatch(path, |mount, rest| mount.fs.create(rest))
+        self.dispatch_nofollow(path, |mount, rest| {
+            mount.check_writable()?;
+            let file = mount.fs.create(rest)?;
+            Ok(MountFile::wrap(file, mount))
+        })
     }
 
     fn delete(&self, path: &str) -> Result<(), FsError> {
-        self.dispatch(path, |mount, rest| mount.fs.delete(rest))
+        self.dispatch_nofollow(path, |mount, rest| {
+            mount.check_writable()?;
+            mount.fs.delete(rest)
+        })
     }
 
     fn ls(&self, path: &str) -> Result<Vec<String>, FsError> {
         self.dispatch(path, |mount, rest| mount.fs.ls(rest))
     }
 
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntryInfo>, FsError> {
+        self.dispatch(path, |mount, rest| mount.fs.read_dir(rest))
+    }
+
     fn mkdir(&self, path: &str) -> Result<(), FsError> {
-        self.dispatch(path, |mount, rest| mount.fs.mkdir(rest))
+        self.dispatch_nofollow(path, |mount, rest| {
+            mount.check_writable()?;
+            mount.fs.mkdir(rest)
+        })
     }
 
     fn rmdir(&self, path: &str) -> Result<(), FsError> {
-        self.dispatch(path, |mount, rest| mount.fs.rmdir(rest))
+        self.dispatch_nofollow(path, |mount, rest| {
+            mount.check_writable()?;
+            mount.fs.rmdir(rest)
+        })
     }
 
     fn stat(&self, path: &str) -> Result<FileStat, FsError> {
         self.dispatch(path, |mount, rest| mount.fs.stat(rest))
     }
+
+    fn statvfs(&self, path: &str) -> Result<FsStat, FsError> {
+        self.dispatch(path, |mount, rest| mount.fs.statvfs(rest))
+    }
+
+    fn rename(&self, old_path: &str, new_path: &str) -> Result<(), FsError> {
+        let old_path = self.follow_links(old_path, false)?;
+        let new_path = self.follow_links(new_path, false)?;
+        let mounts = self.mounts.lock();
+        let (old_mount, old_rest) = Self::resolve(&mounts, &old_path)?;
+        let (new_mount, new_rest) = Self::resolve(&mounts, &new_path)?;
+
+        if !core::ptr::eq(old_mount, new_mount) {
+            return Err(FsError::CrossDevice);
+        }
+        old_mount.check_writable()?;
+        old_mount.fs.rename(old_rest, new_rest)
+    }
+
+    /// `target` is stored verbatim and resolved against the VFS namespace
+    /// when the link is followed.
+    fn symlink(&self, target: &str, link_path: &str) -> Result<(), FsError> {
+        self.dispatch_nofollow(link_path, |mount, rest| {
+            mount.check_writable()?;
+            mount.fs.symlink(target, rest)
+        })
+    }
+
+    fn readlink(&self, path: &str) -> Result<String, FsError> {
+        self.dispatch_nofollow(path, |mount, rest| mount.fs.readlink(rest))
+    }
+
+    /// Sync every mounted filesystem. All are attempted; the first error
+    /// is returned.
+    fn sync(&self) -> Result<(), FsError> {
+        // Syncing does I/O, so the mount table is not held across it
+        let mounts: Vec<(String, Arc<dyn FileSystem>)> = self
+            .mounts
+            .lock()
+            .iter()
+            .map(|mount| (mount.prefix.clone(), Arc::clone(&mount.fs)))
+            .collect();
+        let mut result = Ok(());
+        for (prefix, fs) in mounts {
+            if let Err(e) = fs.sync() {
+                log::warn!("vfs: sync of {} failed: {:?}", prefix, e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// File handed out by a mount.
+///
+/// Counts towards the mount's open files for as long as it lives, applies
+/// the mount's write flags and stops working once the mount is forcibly
+/// removed.
+struct MountFile {
+    inner: Arc<dyn File>,
+    flags: MountFlags,
+    usage: Arc<MountUsage>,
+}
+
+impl MountFile {
+    fn wrap(file: Arc<dyn File>, mount: &Mount) -> Arc<dyn File> {
+        mount.usage.open_files.fetch_add(1, Ordering::AcqRel);
+        Arc::new(Self {
+            inner: file,
+            flags: mount.flags,
+            usage: Arc::clone(&mount.usage),
+        })
+    }
+
+    fn check_attached(&self) -> Result<(), FdError> {
+        if self.usage.detached.load(Ordering::Acquire) {
+            Err(FdError::IoError)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_writable(&self) -> Result<(), FdError> {
+        self.check_attached()?;
+        if self.flags.contains(MountFlags::READ_ONLY) {
+            Err(FdError::PermissionDenied)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for MountFile {
+    fn drop(&mut self) {
+        self.usage.open_files.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl File for MountFile {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, FdError> {
+        self.check_attached()?;
+        self.inner.read(buf, offset)
+    }
+
+    fn write(&self, buf: &[u8], offset: usize) -> Result<usize, FdError> {
+        self.check_writable()?;
+        let n = self.inner.write(buf, offset)?;
+        if self.flags.contains(MountFlags::SYNC) {
+            self.inner.flush()?;
+        }
+        Ok(n)
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: usize) -> Result<usize, FdError> {
+        self.check_attached()?;
+        self.inner.try_read(buf, offset)
+    }
+
+    fn try_write(&self, buf: &[u8], offset: usize) -> Result<usize, FdError> {
+        self.check_writable()?;
+        let n = self.inner.try_write(buf, offset)?;
+        if self.flags.contains(MountFlags::SYNC) {
+            self.inner.flush()?;
+        }
+        Ok(n)
+    }
+
+    fn poll(&self) -> PollEvents {
+        if self.check_attached().is_err() {
+            return PollEvents::ERR;
+        }
+        self.inner.poll()
+    }
+
+    fn stat(&self) -> Result<FileStat, FdError> {
+        self.check_attached()?;
+        self.inner.stat()
+    }
+
+    fn flush(&self) -> Result<(), FdError> {
+        self.check_attached()?;
+        self.inner.flush()
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), FdError> {
+        self.check_writable()?;
+        self.inner.truncate(len)?;
+        if self.flags.contains(MountFlags::SYNC) {
+            self.inner.flush()?;
+        }
+        Ok(())
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> Result<usize, FdError> {
+        self.check_attached()?;
+        self.inner.ioctl(cmd, arg)
+    }
+}
+
+/// Reduce a path to canonical absolute form: a single leading `/`, no
+/// empty, `.` or `..` components and no trailing `/`.
+///
+/// Relative paths are taken from the root. `..` above the root is an
+/// error rather than being clamped, so a path can never climb out of the
+/// namespace.
+pub fn canonicalize(path: &str) -> Result<String, FsError> {
+    let mut parts: Vec<&str> = Vec::new();
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop().ok_or(FsError::InvalidPath)?;
+            }
+            part => parts.push(part),
+        }
+    }
+
+    let mut canonical = String::with_capacity(path.len() + 1);
+    for part in &parts {
+        canonical.push('/');
+        canonical.push_str(part);
+    }
+    if canonical.is_empty() {
+        canonical.push('/');
+    }
+    Ok(canonical)
 }
 
 /// Public VFS entry point
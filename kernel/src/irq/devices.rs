@@ -0,0 +1,52 @@
+//! Device interrupts
+//!
+//! Drivers ask for their interrupt at probe with
+//! [`DeviceManager::request_irq`](drivers::device_manager::DeviceManager::request_irq),
+//! as registering a handler is the kernel's business. Once the scheduler
+//! runs, each request gets its handler on the line, the line is unmasked
+//! and the driver is handed a wait queue to sleep on. Until then, and for
+//! good if routing fails, the driver polls.
+
+use super::handlers::{self, IrqReturn};
+use crate::arch::TrapFrame;
+use crate::process::sched::WaitQueue;
+use crate::subsystems::{device_manager, irq_controller};
+use alloc::boxed::Box;
+use drivers::device_manager::IrqRequest;
+
+crate::initcall!(late, DEVICE_IRQS_INIT, init);
+
+fn init() {
+    let requests = device_manager().lock().take_irq_requests();
+    for request in requests {
+        match route(&request) {
+            Ok(()) => log::info!("{} on IRQ {}", request.device, request.irq),
+            Err(e) => log::warn!("{} polled: {}", request.device, e),
+        }
+    }
+}
+
+/// Put the request's handler on its line, unmask it and tell the driver
+fn route(request: &IrqRequest) -> Result<(), &'static str> {
+    let irqctl = irq_controller().ok_or("no IRQ controller")?;
+    let handler = request.handler;
+
+    // Dropped on failure, which unregisters the handler
+    let handle = handlers::register(request.irq, move |_: &mut TrapFrame| {
+        if handler() {
+            IrqReturn::Handled
+        } else {
+            IrqReturn::NotMine
+        }
+    })
+    .map_err(|_| "invalid IRQ")?;
+    irqctl
+        .lock()
+        .enable(request.irq)
+        .map_err(|_| "failed to enable IRQ")?;
+    handle.forget();
+
+    let event: &'static WaitQueue = Box::leak(Box::new(WaitQueue::new()));
+    (request.enabled)(event);
+    Ok(())
+}
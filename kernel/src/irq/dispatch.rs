@@ -3,6 +3,7 @@
 //! Called from architecture-specific exception handlers.
 
 use crate::arch::{Irq, TrapFrame};
+use crate::irq::handlers::IrqReturn;
 use crate::subsystems::irq_controller;
 use common::sync::irq::{self, IrqControl};
 
@@ -16,32 +17,47 @@ use common::sync::irq::{self, IrqControl};
 ///
 /// # Process
 /// 1. Mask the IRQ to prevent re-entry
-/// 2. Enable interrupts to allow nesting
-/// 3. Call registered handler
-/// 4. Disable interrupts for critical exit
-/// 5. Unmask the IRQ
+/// 2. Signal end-of-interrupt, so the controller lets other IRQs through
+/// 3. Enable interrupts to allow nesting
+/// 4. Call the registered handlers
+/// 5. Disable interrupts for critical exit
+/// 6. Unmask the IRQ
 pub fn dispatch(irq: u32, tf: &mut TrapFrame) {
     let irqctl = irq_controller().expect("no IRQ controller registered");
-    // Mask this specific IRQ line to prevent re-entry
-    let _ = irqctl.lock().disable(irq);
+    {
+        // Mask this specific IRQ line to prevent re-entry, then signal
+        // end-of-interrupt (required on GIC; no-op on BCM2835). Until
+        // then the GIC holds back interrupts of the same or lower
+        // priority, the timer tick among them, for the whole handler.
+        let mut ctl = irqctl.lock();
+        let _ = ctl.disable(irq);
+        let _ = ctl.clear(irq);
+    }
 
     // Enable CPU interrupts to allow nested interrupts
     // (other IRQs can fire while we handle this one)
     crate::arch::Irq::enable();
 
-    // Call the registered handler for this IRQ
-    if let Some(handler) = crate::irq::handlers::get_handler(irq) {
-        handler(tf);
-    } else {
-        // No handler registered - spurious interrupt
+    // Walk the handler chain for this IRQ. The slot is not held while
+    // it runs, so a handler may unregister itself.
+    let start = crate::irq::handlers::now_us();
+    let handled = crate::irq::handlers::handle(irq, tf);
+    let end = crate::irq::handlers::now_us();
+    let claimed = handled == Some(IrqReturn::Handled);
+    crate::irq::handlers::record(irq, start, end, claimed);
+    if !claimed {
+        // No handler registered, or none claimed it
         log::info!("Unhandled IRQ: {}", irq);
     }
 
     // Enter critical section for cleanup
     Irq::disable();
 
-    // Unmask this IRQ line so it can fire again
-    let _ = irqctl.lock().enable(irq);
+    // Unmask this IRQ line so it can fire again, unless its handler was
+    // unregistered meanwhile and left it masked
+    if handled.is_none() || crate::irq::handlers::is_registered(irq) {
+        let _ = irqctl.lock().enable(irq);
+    }
 
     // Return to interrupted code
 }
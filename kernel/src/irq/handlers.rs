@@ -1,46 +1,305 @@
-use drivers::device_manager::DeviceManager;
+//! IRQ handler table
+//!
+//! Several sources can share a line, as the GPIO banks and the AUX
+//! peripherals do on the BCM2835, so each line has a chain of handlers:
+//! anything implementing [`IrqHandler`], which includes closures, so a
+//! driver can hand over the state its handler needs. The dispatcher asks
+//! every handler on the chain, and each says whether the interrupt was
+//! its device's. [`register`] returns an [`IrqHandle`]
+//! that removes the handler when dropped, masking the line if it was the
+//! last.
+//!
+//! A chain is replaced whole rather than edited, so the dispatcher can
+//! take it without allocating and walk it without holding the slot.
 
-use crate::arch::TrapFrame;
-use crate::subsystems::{serial_console, system_timer};
-pub type IrqHandler = fn(&mut TrapFrame);
+use drivers::hal::interrupt::{InterruptError, Priority};
+use drivers::hal::timer::{DynTimer, TimerError};
 
-const MAX_IRQS: usize = 128;
+use crate::arch::{IrqSpinLock, TrapFrame};
+use crate::process::sched;
+use crate::subsystems::{IrqDevice, irq_controller};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use common::sync::Once;
+use core::sync::atomic::{AtomicU32, Ordering};
 
-static mut IRQ_HANDLERS: [Option<IrqHandler>; MAX_IRQS] = [None; MAX_IRQS];
+/// What a handler made of an interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// Its device raised the interrupt and has been serviced
+    Handled,
+    /// Its device did not raise the interrupt
+    NotMine,
+}
+
+/// Something that services an interrupt line
+pub trait IrqHandler: Send + Sync {
+    fn handle(&self, tf: &mut TrapFrame) -> IrqReturn;
+}
+
+impl<F: Fn(&mut TrapFrame) -> IrqReturn + Send + Sync> IrqHandler for F {
+    fn handle(&self, tf: &mut TrapFrame) -> IrqReturn {
+        self(tf)
+    }
+}
+
+/// A handler on a chain, with the id its [`IrqHandle`] knows it by
+struct Chained {
+    id: u32,
+    handler: Box<dyn IrqHandler>,
+}
+
+/// Handlers of a line, in the order they were registered
+type Chain = Arc<Vec<Arc<Chained>>>;
+
+/// A timer and the channel on it
+type TimerChannel = (IrqDevice<dyn DynTimer>, usize);
+
+/// Covers the BCM2835 (80 lines) and the GIC-400 SPIs used on BCM2711.
+pub const MAX_IRQS: usize = 256;
+
+/// Handler chain of each line. Taken with IRQs off, so a nested
+/// interrupt cannot spin on a slot its own CPU holds.
+static IRQ_HANDLERS: [IrqSpinLock<Option<Chain>>; MAX_IRQS] =
+    [const { IrqSpinLock::new(None) }; MAX_IRQS];
+
+/// Id of the next handler registered
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Dispatch statistics of each line
+static IRQ_STATS: [IrqSpinLock<IrqStats>; MAX_IRQS] =
+    [const { IrqSpinLock::new(IrqStats::new()) }; MAX_IRQS];
+
+/// IRQs taken with no interrupt pending
+static SPURIOUS: AtomicU32 = AtomicU32::new(0);
 
-pub fn register(irq: u32, handler: IrqHandler) {
-    unsafe {
-        IRQ_HANDLERS[irq as usize] = Some(handler);
+/// Timer channel behind the scheduler tick. Kept here so [`timer`] does
+/// not need the device manager, whose lock the interrupted code may hold.
+static TICK_TIMER: Once<TimerChannel> = Once::new();
+
+/// Registration of a handler on a line; dropping it unregisters the
+/// handler
+#[must_use = "dropping the handle unregisters the handler"]
+pub struct IrqHandle {
+    irq: u32,
+    id: u32,
+}
+
+impl IrqHandle {
+    /// Line the handler is registered on
+    pub fn irq(&self) -> u32 {
+        self.irq
+    }
+
+    /// Leave the handler registered for good
+    pub fn forget(self) {
+        core::mem::forget(self);
     }
 }
 
-pub(crate) fn get_handler(irq: u32) -> Option<IrqHandler> {
-    unsafe { IRQ_HANDLERS[irq as usize] }
+impl Drop for IrqHandle {
+    /// Remove the handler, masking the line first if no other handler is
+    /// left on it. A dispatch already under way still finishes, holding
+    /// its own reference to the chain.
+    fn drop(&mut self) {
+        let mut slot = IRQ_HANDLERS[self.irq as usize].lock();
+        let Some(chain) = slot.as_ref() else {
+            return;
+        };
+        let rest: Vec<_> = chain
+            .iter()
+            .filter(|chained| chained.id != self.id)
+            .cloned()
+            .collect();
+        if rest.is_empty() {
+            if let Some(irqctl) = irq_controller() {
+                let _ = irqctl.lock().disable(self.irq);
+            }
+            *slot = None;
+        } else {
+            *slot = Some(Arc::new(rest));
+        }
+    }
 }
 
-pub fn timer(_tf: &mut TrapFrame) {
-    let channel = DeviceManager::sys_timer_channel()
-        .expect("timer IRQ fired but no system timer channel registered");
+/// Add `handler` to the end of the chain for `irq`. The line is not
+/// unmasked.
+pub fn register(irq: u32, handler: impl IrqHandler + 'static) -> Result<IrqHandle, InterruptError> {
+    let slot = IRQ_HANDLERS
+        .get(irq as usize)
+        .ok_or(InterruptError::InvalidIrq)?;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let chained = Arc::new(Chained {
+        id,
+        handler: Box::new(handler),
+    });
 
-    let sys_timer = system_timer().expect("timer IRQ fired but no system timer registered");
+    let mut slot = slot.lock();
+    let mut chain = slot.as_deref().cloned().unwrap_or_default();
+    chain.push(chained);
+    *slot = Some(Arc::new(chain));
+    Ok(IrqHandle { irq, id })
+}
+
+/// Register a handler and set the source's priority on the interrupt
+/// controller (lower is more urgent). Controllers without priority
+/// support ignore the priority, and a shared line takes the priority
+/// last set.
+pub fn register_with_priority(
+    irq: u32,
+    handler: impl IrqHandler + 'static,
+    priority: Priority,
+) -> Result<IrqHandle, InterruptError> {
+    let irqctl = irq_controller().ok_or(InterruptError::Other)?;
+    let handle = register(irq, handler)?;
+    irqctl.lock().set_priority(irq, priority)?;
+    Ok(handle)
+}
+
+/// Run every handler on `irq`, as more than one device on a shared line
+/// may be waiting. Returns `None` if the line has no handlers, and
+/// otherwise whether any of them handled the interrupt.
+pub(crate) fn handle(irq: u32, tf: &mut TrapFrame) -> Option<IrqReturn> {
+    let chain = IRQ_HANDLERS.get(irq as usize)?.lock().clone()?;
+    let mut result = IrqReturn::NotMine;
+    for chained in chain.iter() {
+        if chained.handler.handle(tf) == IrqReturn::Handled {
+            result = IrqReturn::Handled;
+        }
+    }
+    Some(result)
+}
+
+/// Number of handlers on `irq`
+pub fn handler_count(irq: u32) -> usize {
+    IRQ_HANDLERS
+        .get(irq as usize)
+        .and_then(|slot| slot.lock().as_ref().map(|chain| chain.len()))
+        .unwrap_or(0)
+}
+
+/// Whether `irq` has a handler
+pub fn is_registered(irq: u32) -> bool {
+    handler_count(irq) != 0
+}
+
+/// Dispatch statistics of a line
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IrqStats {
+    /// Times dispatched since boot (wraps at `u32::MAX`)
+    pub count: u32,
+    /// Dispatches no handler claimed
+    pub unhandled: u32,
+    /// Uptime at the last dispatch, in microseconds
+    pub last_us: u64,
+    /// Longest the handlers have taken over one dispatch, in
+    /// microseconds
+    pub max_us: u64,
+}
+
+impl IrqStats {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            unhandled: 0,
+            last_us: 0,
+            max_us: 0,
+        }
+    }
+}
+
+/// Account a dispatch of `irq` that started at `start` and whose
+/// handlers ran until `end`, uptimes in microseconds if there is a
+/// counting timer
+pub(crate) fn record(irq: u32, start: Option<u64>, end: Option<u64>, handled: bool) {
+    let Some(stats) = IRQ_STATS.get(irq as usize) else {
+        return;
+    };
+    let mut stats = stats.lock();
+    stats.count = stats.count.wrapping_add(1);
+    if !handled {
+        stats.unhandled = stats.unhandled.wrapping_add(1);
+    }
+    if let Some(start) = start {
+        stats.last_us = start;
+        if let Some(end) = end {
+            stats.max_us = stats.max_us.max(end.saturating_sub(start));
+        }
+    }
+}
+
+/// Microseconds since boot on the tick timer, if it can be read without
+/// waiting: the handler a nested IRQ interrupted may hold it
+pub(crate) fn now_us() -> Option<u64> {
+    let (timer, _) = TICK_TIMER.get()?;
+    timer
+        .try_lock()
+        .and_then(|timer| timer.as_counting().map(|counter| counter.now_us()))
+}
+
+/// Count an IRQ taken with nothing pending
+pub(crate) fn record_spurious() {
+    SPURIOUS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Dispatch statistics of `irq`
+pub fn irq_stats(irq: u32) -> IrqStats {
+    IRQ_STATS
+        .get(irq as usize)
+        .map_or_else(IrqStats::default, |stats| *stats.lock())
+}
+
+/// Number of times `irq` has been dispatched (wraps at `u32::MAX`)
+pub fn irq_count(irq: u32) -> u32 {
+    irq_stats(irq).count
+}
+
+/// Number of IRQs taken with no interrupt pending (wraps at `u32::MAX`)
+pub fn spurious_count() -> u32 {
+    SPURIOUS.load(Ordering::Relaxed)
+}
+
+/// Start the scheduler tick on `channel` of `timer`; its interrupt must
+/// be routed to [`timer`].
+pub fn start_tick(timer: IrqDevice<dyn DynTimer>, channel: usize) -> Result<(), TimerError> {
+    let (timer, channel) = TICK_TIMER.call_once(|| (timer, channel));
+    timer.lock().start(*channel, sched::TICK_US)
+}
+
+pub fn timer(tf: &mut TrapFrame) -> IrqReturn {
+    let Some((sys_timer, channel)) = TICK_TIMER.get() else {
+        return IrqReturn::NotMine;
+    };
+    let channel = *channel;
 
     let mut timer = sys_timer.lock();
+    if !timer.is_pending(channel).unwrap_or(true) {
+        return IrqReturn::NotMine;
+    }
     timer.stop(channel).expect("failed to stop system timer");
     timer
         .clear_interrupt(channel)
         .expect("failed to clear timer interrupt");
 
-    drop(timer); // release before console write to minimize lock hold time
+    timer
+        .start(channel, sched::TICK_US)
+        .expect("failed to restart system timer");
+    drop(timer);
 
-    let _ = serial_console()
-        .expect("no console registered")
-        .lock()
-        .write(b"Timer interrupt\n");
+    #[cfg(all(target_arch = "arm", not(feature = "bcm2836")))]
+    crate::arch::arm::perf::sample(tf);
+    #[cfg(not(all(target_arch = "arm", not(feature = "bcm2836"))))]
+    let _ = tf;
 
-    sys_timer
-        .lock()
-        .start(channel, 1_000_000)
-        .expect("failed to restart system timer");
+    crate::kcore::console::tick();
+    sched::tick();
+    IrqReturn::Handled
 }
 
-pub fn uart(_tf: &mut TrapFrame) {}
+/// The console's receive interrupt: input is queued here, and passed on
+/// to readers by a softirq
+pub fn uart(_tf: &mut TrapFrame) -> IrqReturn {
+    crate::kcore::console::receive();
+    IrqReturn::Handled
+}
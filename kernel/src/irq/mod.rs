@@ -1,3 +1,5 @@
+pub mod devices;
 pub mod dispatch;
 pub mod handlers;
+pub mod softirq;
 pub use dispatch::dispatch;
@@ -0,0 +1,77 @@
+//! Softirqs
+//!
+//! A handler that has more to do than it should with the line masked
+//! raises a softirq instead, and the work runs on the way out of the
+//! outermost interrupt with interrupts enabled again. Softirqs still run
+//! in interrupt context: they must not sleep, and as the interrupted code
+//! may hold the heap lock, they must not allocate either. Work that needs
+//! either belongs on the [workqueue](crate::kcore::workqueue).
+//!
+//! A softirq raised while softirqs are running runs in the same pass, up
+//! to [`MAX_RESTARTS`] times over; anything still pending after that
+//! waits for the next interrupt.
+
+use crate::arch::Irq;
+use common::sync::irq::IrqControl;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Passes over the pending softirqs per interrupt exit
+pub const MAX_RESTARTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Softirq {
+    /// Input arrived on the console UART
+    ConsoleRx = 0,
+}
+
+impl Softirq {
+    const ALL: [Softirq; 1] = [Softirq::ConsoleRx];
+
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    fn action(self) -> fn() {
+        match self {
+            Softirq::ConsoleRx => crate::kcore::console::input_ready,
+        }
+    }
+}
+
+/// Raised softirqs, one bit each
+static PENDING: AtomicU32 = AtomicU32::new(0);
+
+/// Whether some CPU is running softirqs
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Mark `softirq` to run when the current interrupt returns
+pub fn raise(softirq: Softirq) {
+    PENDING.fetch_or(softirq.bit(), Ordering::AcqRel);
+}
+
+/// Run the pending softirqs. Called by the architecture's interrupt entry
+/// on the way out of the outermost interrupt, with interrupts disabled,
+/// and returns with them disabled. Does nothing if another CPU, or an
+/// interrupt this one took, is already running them.
+pub fn run() {
+    if PENDING.load(Ordering::Acquire) == 0 || RUNNING.swap(true, Ordering::Acquire) {
+        return;
+    }
+
+    Irq::enable();
+    for _ in 0..MAX_RESTARTS {
+        let pending = PENDING.swap(0, Ordering::AcqRel);
+        if pending == 0 {
+            break;
+        }
+        for softirq in Softirq::ALL {
+            if pending & softirq.bit() != 0 {
+                softirq.action()();
+            }
+        }
+    }
+    Irq::disable();
+
+    RUNNING.store(false, Ordering::Release);
+}
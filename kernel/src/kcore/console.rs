@@ -0,0 +1,224 @@
+//! Console input
+//!
+//! What is typed on the serial console is taken from the port in
+//! interrupt context and queued for readers: by the port's receive
+//! interrupt where the port and the platform have one, and by the
+//! scheduler tick otherwise. Each byte goes through the
+//! [SysRq monitor](super::sysrq) first.
+//!
+//! [`console_read_blocking`] sleeps until input is queued. Before the
+//! scheduler runs, and once the kernel has panicked, nothing would wake
+//! it, so it polls the port instead.
+
+use crate::fs::poll;
+use crate::irq::handlers;
+use crate::irq::softirq::{self, Softirq};
+use crate::kcore::panic;
+use crate::kcore::sysrq;
+use crate::process::sched::{self, WaitQueue};
+use crate::subsystems::{IrqDevice, irq_controller, serial, serial_console};
+use common::sync::{Once, SpscRing};
+use core::sync::atomic::{AtomicBool, Ordering};
+use drivers::hal::serial::{DynNonBlockingSerial, DynSerialPort};
+use drivers::platform::Platform;
+use spin::Mutex;
+
+/// Bytes of console input queued for readers
+const INPUT_SIZE: usize = 256;
+
+/// The console port, once its input is queued
+static CONSOLE: Once<IrqDevice<dyn DynSerialPort>> = Once::new();
+
+/// Set once the port's receive interrupt queues the input
+static IRQ_DRIVEN: AtomicBool = AtomicBool::new(false);
+
+/// Console input waiting for readers, oldest first: filled by
+/// [`receive`], which the console lock keeps to one at a time, and
+/// emptied by [`console_read`] under [`READER`]
+static INPUT: SpscRing<u8, INPUT_SIZE> = SpscRing::new();
+
+/// Keeps readers of [`INPUT`] to one at a time. Only tasks take it.
+static READER: Mutex<()> = Mutex::new(());
+
+/// Tasks waiting for console input
+static READERS: WaitQueue = WaitQueue::new();
+
+crate::initcall!(late, CONSOLE_INIT, init);
+
+/// Start queueing console input, on the port's receive interrupt if it
+/// can raise one. A console that cannot be read without blocking is left
+/// to its readers.
+fn init() {
+    let Some(console) = serial_console() else {
+        return;
+    };
+    if console.lock().as_nonblocking().is_none() {
+        log::warn!("Console input unbuffered and SysRq unavailable: the console cannot be polled");
+        return;
+    }
+    let console = CONSOLE.call_once(|| console);
+    match enable_irq(console) {
+        Ok(irq) => {
+            IRQ_DRIVEN.store(true, Ordering::Relaxed);
+            log::info!("Console input on IRQ {}", irq);
+        }
+        Err(e) => log::info!("Console input polled every tick: {}", e),
+    }
+    log::info!("SysRq monitor on the console: Ctrl-O, then h for help");
+}
+
+/// Route the console's receive interrupt to [`handlers::uart`] and
+/// unmask it, returning the line
+fn enable_irq(console: &IrqDevice<dyn DynSerialPort>) -> Result<u32, &'static str> {
+    let irq = Platform::devices()
+        .find(|device| serial(device.name).is_some_and(|port| port.ptr_eq(console)))
+        .and_then(|device| device.irq)
+        .ok_or("console has no IRQ")?;
+    let irqctl = irq_controller().ok_or("no IRQ controller")?;
+
+    // Dropped on failure, which unregisters the handler
+    let handle = handlers::register(irq, handlers::uart).map_err(|_| "invalid console IRQ")?;
+    let set_rx_interrupt = |enabled| {
+        console
+            .lock()
+            .as_nonblocking()
+            .is_some_and(|nb| nb.set_rx_interrupt(enabled))
+    };
+    if !set_rx_interrupt(true) {
+        return Err("console has no receive interrupt");
+    }
+    if irqctl.lock().enable(irq).is_err() {
+        set_rx_interrupt(false);
+        return Err("failed to enable console IRQ");
+    }
+    handle.forget();
+    Ok(irq)
+}
+
+/// Whether input on `port` is queued here, so it is read with
+/// [`console_read`] rather than from the port
+pub fn owns(port: &IrqDevice<dyn DynSerialPort>) -> bool {
+    CONSOLE.get().is_some_and(|console| console.ptr_eq(port))
+}
+
+/// Queue what has arrived on the console, after the SysRq monitor has
+/// seen it. Called by the console's interrupt handler; skipped if
+/// someone is using the console.
+pub fn receive() {
+    let Some(mut port) = CONSOLE.get().and_then(|console| console.try_lock()) else {
+        return;
+    };
+    let mut queued = false;
+    loop {
+        let Some(nb) = port.as_nonblocking() else {
+            return;
+        };
+        let byte = match nb.try_read_byte() {
+            Ok(byte) => byte,
+            // A garbled character is dropped; the port stays usable
+            Err(e) if e.is_line_error() => continue,
+            Err(_) => break,
+        };
+        // A full queue drops the byte
+        if sysrq::filter(&mut *port, byte) {
+            queued |= INPUT.push(byte).is_ok();
+        }
+    }
+    drop(port);
+
+    if queued {
+        softirq::raise(Softirq::ConsoleRx);
+    }
+}
+
+/// Queue console input from the scheduler tick, unless the console's
+/// interrupt does.
+pub fn tick() {
+    if !IRQ_DRIVEN.load(Ordering::Relaxed) {
+        receive();
+    }
+}
+
+/// Wake readers and pollers waiting for input. Run by the console RX
+/// softirq.
+pub fn input_ready() {
+    READERS.wake_all();
+    poll::wake();
+}
+
+/// Take queued console input into `buf`, returning how many bytes were
+/// taken
+pub fn console_read(buf: &mut [u8]) -> usize {
+    let _reader = READER.lock();
+    let mut n = 0;
+    while n < buf.len() {
+        let Some(byte) = INPUT.pop() else {
+            break;
+        };
+        buf[n] = byte;
+        n += 1;
+    }
+    n
+}
+
+/// Whether console input is queued
+pub fn has_input() -> bool {
+    !INPUT.is_empty()
+}
+
+/// Sleep until console input arrives, then take what has into `buf`.
+/// Polls the port instead where nothing would wake the reader.
+pub fn console_read_blocking(buf: &mut [u8]) -> usize {
+    if buf.is_empty() {
+        return 0;
+    }
+    if CONSOLE.get().is_none() || sched::current().is_none() || panic::panicking() {
+        return read_polled(buf);
+    }
+    let mut n = 0;
+    READERS.sleep_on(|| {
+        n = console_read(buf);
+        n > 0
+    });
+    n
+}
+
+/// Spin until the console port has input, then take what it has. Input
+/// already queued is left: nothing queues it at the times this is used.
+fn read_polled(buf: &mut [u8]) -> usize {
+    let Some(console) = CONSOLE.get().cloned().or_else(serial_console) else {
+        return 0;
+    };
+    loop {
+        if let Some(mut port) = console.try_lock() {
+            let n = match port.as_nonblocking() {
+                Some(nb) => read_available(nb, buf),
+                None => port.read_byte().map_or(0, |byte| {
+                    buf[0] = byte;
+                    1
+                }),
+            };
+            if n > 0 {
+                return n;
+            }
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Take what the port has received into `buf`, dropping garbled
+/// characters
+fn read_available(nb: &mut dyn DynNonBlockingSerial, buf: &mut [u8]) -> usize {
+    let mut n = 0;
+    while n < buf.len() {
+        match nb.try_read_byte() {
+            Ok(byte) => {
+                buf[n] = byte;
+                n += 1;
+            }
+            Err(e) if e.is_line_error() => {}
+            Err(_) => break,
+        }
+    }
+    n
+}
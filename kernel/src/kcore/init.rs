@@ -1,9 +1,15 @@
 use crate::boot::BootInfo;
+use crate::fs::fat::fat32::Fat32Fs;
+use crate::fs::initramfs::{self, Archive, InitramFs};
+use crate::fs::vfs::{MountFlags, vfs};
+use crate::kcore::initcall::{self, Level};
 use crate::logger;
 use crate::mm::mmu::{MmuOps, PlatformMmu};
 use crate::mm::{heap_allocator, page_allocator::page_allocator};
+use crate::subsystems::device_manager;
 use crate::subsystems::enable_graphical_framebuffer;
-use crate::subsystems::log_sinks::SERIAL_SINK;
+use crate::subsystems::log_sinks;
+use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicUsize, Ordering};
@@ -34,11 +40,24 @@ pub extern "C" fn kernel_init(machine_type: u32, atags_addr: u32) {
         crate::boot::init(determine_boot_info(machine_type, atags_addr))
             .expect("Platform initialization failed");
 
-        logger::init(log::LevelFilter::Info);
+        logger::init(
+            crate::boot::cmdline()
+                .log_level()
+                .unwrap_or(log::LevelFilter::Info),
+        );
 
         let layout = setup_memory_management();
+        initcall::run(Level::Early);
 
         crate::subsystems::init_devices();
+        configure_console();
+        initcall::run(Level::Core);
+
+        initcall::run(Level::Device);
+
+        mount_initramfs();
+
+        mount_sd_card();
 
         // #[cfg(target_arch = "arm")]
         // {
@@ -54,7 +73,7 @@ pub extern "C" fn kernel_init(machine_type: u32, atags_addr: u32) {
 
         log::info!("Kernel Early Initialization Complete\n");
 
-        logger::attach_runtime(vec![&SERIAL_SINK]);
+        logger::attach_runtime(log_sinks::console_sinks());
 
         // enable_graphical_framebuffer().expect("Failed to enable graphical framebuffer");
 
@@ -71,6 +90,12 @@ pub extern "C" fn kernel_init(machine_type: u32, atags_addr: u32) {
         log_system_info();
         log_discovered_hardware();
         log_available_devices();
+
+        crate::process::sched::init();
+        initcall::run(Level::Late);
+
+        #[cfg(all(target_arch = "arm", feature = "bcm2836"))]
+        crate::arch::arm::smp::start_secondaries();
     }
 }
 
@@ -99,6 +124,12 @@ unsafe fn determine_boot_info(machine_type: u32, atags_addr: u32) -> BootInfo {
                     dtb_addr: atags_addr as usize,
                 };
             }
+            // SAFETY: the pointer comes from the boot registers
+            if unsafe { crate::boot::atags::is_atag_list(atags_addr as usize) } {
+                return BootInfo::Atags {
+                    atags_addr: atags_addr as usize,
+                };
+            }
         }
         BootInfo::Raw
     }
@@ -110,6 +141,100 @@ unsafe fn determine_boot_info(machine_type: u32, atags_addr: u32) -> BootInfo {
     }
 }
 
+// ============================================================================
+// Console
+// ============================================================================
+
+/// Set the serial console to the baud rate the command line asks for
+fn configure_console() {
+    let Some(baud) = crate::boot::cmdline().console_baud() else {
+        return;
+    };
+    let Some(serial) = crate::subsystems::serial_console() else {
+        return;
+    };
+    let mut port = serial.lock();
+    let config = drivers::hal::serial::SerialConfig::new_8n1(baud);
+    // Output already queued leaves at the old rate
+    if port.flush().is_err() || port.reconfigure(config).is_err() {
+        drop(port);
+        log::warn!("Could not set the console to {} baud", baud);
+    }
+}
+
+// ============================================================================
+// Root Filesystem
+// ============================================================================
+
+/// Mount the initramfs, if there is one, as the initial root. A
+/// bootloader-loaded archive is unpacked and its memory given back to the
+/// page allocator.
+fn mount_initramfs() {
+    let Some(archive) = initramfs::locate() else {
+        return;
+    };
+    let (fs, len) = match archive {
+        Archive::BuiltIn(data) => (InitramFs::new(data), data.len()),
+        Archive::Loaded(data) => {
+            let fs = InitramFs::unpack(data);
+            let start = data.as_ptr() as usize;
+            // SAFETY: nothing points into the archive once it is unpacked
+            unsafe {
+                page_allocator().release(start, start + data.len());
+            }
+            (fs, data.len())
+        }
+    };
+    match fs {
+        Ok(fs) => {
+            vfs().init(Arc::new(fs));
+            log::info!("Mounted initramfs ({} KB) as root", len / 1024);
+        }
+        Err(e) => log::warn!("Ignoring malformed initramfs: {:?}", e),
+    }
+}
+
+/// Where the SD card's volume goes when the initramfs is the root
+const SD_MOUNT_POINT: &str = "/sd";
+
+/// Mount the FAT32 volume on the SD card, or the device the command
+/// line's `root=` names, if there is one: as the root unless the
+/// initramfs already is, else at [`SD_MOUNT_POINT`].
+fn mount_sd_card() {
+    let root = crate::boot::cmdline().root();
+    let dev = {
+        let dm = device_manager().lock();
+        match root {
+            Some(name) => dm.block(name),
+            None => dm.block("mmcblk0p1").or_else(|| dm.block("mmcblk0")),
+        }
+    };
+    let Some(dev) = dev else {
+        if let Some(name) = root {
+            log::warn!("No root device {}", name);
+        }
+        return;
+    };
+    let fs = match Fat32Fs::mount(dev) {
+        Ok(fs) => fs,
+        Err(e) => {
+            log::warn!("No FAT32 volume on the SD card: {:?}", e);
+            return;
+        }
+    };
+
+    for mount_point in ["/", SD_MOUNT_POINT] {
+        if vfs()
+            .mount_fs(mount_point, fs.clone(), MountFlags::empty())
+            .is_ok()
+        {
+            log::info!("Mounted SD card at {}", mount_point);
+            return;
+        }
+    }
+    log::warn!("Could not mount the SD card");
+}
+
 // ============================================================================
 // Memory Management Setup
 // ============================================================================
@@ -206,14 +331,36 @@ unsafe fn setup_memory_management() -> MemoryLayout {
     };
 
     // -------------------------------------------------------------------------
-    // Heap: 10% of remaining RAM, capped at 16 MB
+    // Heap: 10% of the RAM beside a bootloader-loaded initramfs, capped at
+    // 16 MB, below the initramfs unless they would overlap
     // -------------------------------------------------------------------------
-    let available_ram = usable_ram_end.saturating_sub(post_table_start);
+    let initrd =
+        Platform::initrd().map(|(base, size)| (base & !0xFFF, (base + size + 0xFFF) & !0xFFF));
+    let initrd_size = initrd.map_or(0, |(start, end)| end - start);
+    let available_ram = usable_ram_end
+        .saturating_sub(post_table_start)
+        .saturating_sub(initrd_size);
     let heap_size = core::cmp::min(16 * 1024 * 1024, available_ram / 10);
 
-    let heap_start = post_table_start;
+    let heap_start = match initrd {
+        Some((start, end)) if start < post_table_start + heap_size && end > post_table_start => end,
+        _ => post_table_start,
+    };
     let heap_end = heap_start + heap_size;
-    let page_alloc_start = (heap_end + 0xFFF) & !0xFFF;
+    assert!(
+        heap_end <= usable_ram_end,
+        "No room for the heap beside the initramfs"
+    );
+
+    // -------------------------------------------------------------------------
+    // Page allocator: the rest of usable RAM. The initramfs stays reserved
+    // until it is unpacked.
+    // -------------------------------------------------------------------------
+    let page_alloc_start = if heap_start == post_table_start {
+        (heap_end + 0xFFF) & !0xFFF
+    } else {
+        post_table_start
+    };
     let page_alloc_end = usable_ram_end;
 
     // Final guard: page allocator range must not touch MMIO
@@ -227,7 +374,11 @@ unsafe fn setup_memory_management() -> MemoryLayout {
 
     unsafe {
         heap_allocator::init_heap(heap_start, heap_end);
-        page_allocator().init(page_alloc_start, page_alloc_end);
+    }
+    let mut reserved = boot_reserved_regions();
+    reserved.push((heap_start, heap_end));
+    unsafe {
+        page_allocator().init(page_alloc_start, page_alloc_end, &reserved);
     }
 
     let page_table: Option<(usize, usize)> = {
@@ -259,6 +410,19 @@ unsafe fn setup_memory_management() -> MemoryLayout {
     }
 }
 
+/// Memory the boot information marks as in use, to keep the page
+/// allocator off: firmware data, the device tree, the GPU's share of RAM,
+/// a framebuffer, the initrd. MMIO is already outside the allocator's
+/// range.
+fn boot_reserved_regions() -> Vec<(usize, usize)> {
+    let regions = Platform::memory_regions()
+        .iter()
+        .filter(|region| !matches!(region.mem_type, MemoryType::Available | MemoryType::Mmio))
+        .map(|region| (region.base, region.base.saturating_add(region.size)));
+    let initrd = Platform::initrd().map(|(base, size)| (base, base.saturating_add(size)));
+    regions.chain(initrd).collect()
+}
+
 #[cfg(target_arch = "x86")]
 unsafe fn get_kernel_end_address() -> usize {
     unsafe extern "C" {
@@ -392,13 +556,15 @@ fn log_discovered_hardware() {
 // log_available_devices
 fn log_available_devices() {
     use crate::device_manager;
-    let names: Vec<alloc::string::String> = {
+    let entries: Vec<(alloc::string::String, &'static str)> = {
         let mgr = device_manager().lock();
-        mgr.list().cloned().collect()
+        mgr.devices()
+            .map(|(name, dev)| (name.into(), dev.class().name()))
+            .collect()
     };
 
     log::info!("Registered Devices:");
-    for name in &names {
-        log::info!("  - {}", name);
+    for (name, class) in &entries {
+        log::info!("  - {} ({})", name, class);
     }
 }
@@ -0,0 +1,115 @@
+//! Initcalls
+//!
+//! Subsystems, drivers and filesystems that need setting up at boot
+//! register an init function with [`initcall!`](crate::initcall) next to
+//! their code, rather than being called from `kernel_init`. The linker
+//! collects the registrations into the `.initcalls` section, one input
+//! section per [`Level`] in level order, and `kernel_init` runs each
+//! level at its point in the boot with [`run`].
+//!
+//! Within a level the order is the link order, which is not to be relied
+//! on: what must come first goes in an earlier level. An init function
+//! that fails logs why and returns; the boot goes on without it.
+//!
+//! # Example
+//!
+//! ```rust
+//! initcall!(device, RAMDISK_INIT, init);
+//!
+//! fn init() {
+//!     // ...
+//! }
+//! ```
+
+/// When in the boot an init function runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Once the heap and page allocator are up, before any device is
+    /// probed
+    Early,
+    /// Once devices are probed and the console configured: the
+    /// subsystems the rest build on
+    Core,
+    /// Devices that are not on the platform's list, such as RAM disks,
+    /// before the root filesystem is mounted
+    Device,
+    /// Once the root filesystem is mounted and the scheduler runs
+    Late,
+}
+
+/// A registered init function
+pub struct Initcall {
+    /// Where the function was registered, for diagnostics
+    pub name: &'static str,
+    pub init: fn(),
+}
+
+/// Register `init` to run at `level`: one of `early`, `core`, `device`
+/// or `late`, for the [`Level`] of that name. `ident` names the static
+/// holding the registration.
+#[macro_export]
+macro_rules! initcall {
+    (early, $ident:ident, $init:path) => {
+        $crate::initcall!(@section ".initcall.early", $ident, $init);
+    };
+    (core, $ident:ident, $init:path) => {
+        $crate::initcall!(@section ".initcall.core", $ident, $init);
+    };
+    (device, $ident:ident, $init:path) => {
+        $crate::initcall!(@section ".initcall.device", $ident, $init);
+    };
+    (late, $ident:ident, $init:path) => {
+        $crate::initcall!(@section ".initcall.late", $ident, $init);
+    };
+    (@section $section:literal, $ident:ident, $init:path) => {
+        // Kept from the linker by the script's KEEP, not by the object
+        // file, so a link without the script drops them
+        #[used(compiler)]
+        #[unsafe(link_section = $section)]
+        static $ident: $crate::kcore::initcall::Initcall = $crate::kcore::initcall::Initcall {
+            name: concat!(module_path!(), "::", stringify!($init)),
+            init: $init,
+        };
+    };
+}
+
+unsafe extern "C" {
+    // Provided by the linker script, in level order.
+    static __initcall_early_start: u8;
+    static __initcall_core_start: u8;
+    static __initcall_device_start: u8;
+    static __initcall_late_start: u8;
+    static __initcall_end: u8;
+}
+
+/// The init functions registered for `level`
+pub fn initcalls(level: Level) -> &'static [Initcall] {
+    unsafe {
+        let (start, stop) = match level {
+            Level::Early => (
+                &raw const __initcall_early_start,
+                &raw const __initcall_core_start,
+            ),
+            Level::Core => (
+                &raw const __initcall_core_start,
+                &raw const __initcall_device_start,
+            ),
+            Level::Device => (
+                &raw const __initcall_device_start,
+                &raw const __initcall_late_start,
+            ),
+            Level::Late => (&raw const __initcall_late_start, &raw const __initcall_end),
+        };
+        let start = start as *const Initcall;
+        let len = (stop as usize - start as usize) / core::mem::size_of::<Initcall>();
+        core::slice::from_raw_parts(start, len)
+    }
+}
+
+/// Run the init functions registered for `level`
+pub fn run(level: Level) {
+    for call in initcalls(level) {
+        log::debug!("initcall: {}", call.name);
+        (call.init)();
+    }
+}
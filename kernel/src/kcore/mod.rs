@@ -1,4 +1,13 @@
+pub mod console;
 pub mod init;
+pub mod initcall;
+pub mod panic;
+pub mod power;
+pub mod print;
+#[cfg(feature = "shell")]
+pub mod shell;
+pub mod sysrq;
+pub mod workqueue;
 
 cfg_if::cfg_if!(
     if #[cfg(target_arch = "x86")] {
@@ -0,0 +1,228 @@
+//! Panic reports
+//!
+//! A panic prints what is known about it to the serial console: the
+//! message, the registers of the trap that led to it if an exception
+//! handler recorded one with [`set_trap_frame`], a backtrace, and the
+//! last lines of the kernel log. Backtrace addresses are return
+//! addresses; `addr2line -e` on the kernel ELF turns them into source
+//! lines.
+//!
+//! The code that panicked may hold the locks the console sits behind, so
+//! the report takes them without waiting long, breaking them if need be.
+//! A panic while reporting one stops at once.
+//!
+//! Once reported, the system stays stopped, unless the command line's
+//! `panic=` option asks for a reboot; see [`reboot_after_timeout`].
+
+use crate::arch::{Irq, IrqSpinLock, TrapFrame, backtrace};
+use crate::boot::cmdline;
+use crate::subsystems::{IrqDevice, try_device_manager};
+use common::sync::irq_mutex::IrqMutexGuard;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use drivers::platform::Platform;
+
+/// Most frames a backtrace follows
+const BACKTRACE_DEPTH: usize = 32;
+
+/// Log records repeated at the end of the report
+const LOG_LINES: usize = 16;
+
+/// Tries at a console lock before breaking it
+const LOCK_SPINS: usize = 100_000;
+
+/// Set by the first panic
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+/// Trap the running code is handling, if it is about to panic over it
+static TRAP_FRAME: AtomicPtr<TrapFrame> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Whether the kernel has panicked
+pub fn panicking() -> bool {
+    PANICKED.load(Ordering::Acquire)
+}
+
+/// Record the trap frame an exception handler is about to panic over, for
+/// the report's register dump and backtrace
+pub fn set_trap_frame(tf: &TrapFrame) {
+    TRAP_FRAME.store(tf as *const TrapFrame as *mut TrapFrame, Ordering::Release);
+}
+
+/// Print the report of a panic. Returns at once if another panic is
+/// already being reported.
+pub fn report(info: &PanicInfo) {
+    if PANICKED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    // Breaking locks is out of order by design
+    #[cfg(feature = "lock-debug")]
+    crate::sync::lockdep::stop();
+
+    let mut out = PanicConsole;
+    let _ = writeln!(out, "\n*** KERNEL PANIC: {}", info);
+
+    // SAFETY: the frame was recorded by a handler that has not returned,
+    // as it is the one panicking
+    let frame = unsafe { TRAP_FRAME.load(Ordering::Acquire).as_ref() };
+    let fp = match frame {
+        Some(tf) => {
+            let _ = writeln!(out, "Registers:\n{}", tf);
+            // A user stack is no concern of the kernel's
+            (!tf.is_user_mode()).then(|| tf.frame_pointer())
+        }
+        None => Some(backtrace::frame_pointer()),
+    };
+    if let Some(fp) = fp {
+        let _ = writeln!(out, "Backtrace:");
+        let _ = write_backtrace(&mut out, fp);
+    }
+
+    let _ = writeln!(out, "Last log lines:");
+    crate::logger::try_for_each_recent(LOG_LINES, |record| {
+        let _ = out.write_str(record);
+    });
+    let _ = writeln!(out, "*** end of panic report");
+}
+
+/// Reboot as the `panic=` option asks: after that many seconds, or at
+/// once if it is negative. Returns if it is absent or 0. Without a
+/// counting timer to wait on, the reboot is at once.
+///
+/// Nothing is synced: what the kernel has cached cannot be trusted.
+pub fn reboot_after_timeout() {
+    let secs = match cmdline().panic_timeout() {
+        None | Some(0) => return,
+        Some(secs) => secs,
+    };
+    if secs > 0 {
+        let _ = writeln!(PanicConsole, "Rebooting in {} seconds", secs);
+        wait_us(secs as u64 * 1_000_000);
+    }
+    Platform::reboot()
+}
+
+/// Spin for `us` microseconds on the system timer's counter, if there is
+/// one
+fn wait_us(us: u64) {
+    let Some(start) = now_us() else {
+        return;
+    };
+    while now_us().is_some_and(|now| now.wrapping_sub(start) < us) {
+        core::hint::spin_loop();
+    }
+}
+
+/// The system timer's counter, reached around the locks in the way
+fn now_us() -> Option<u64> {
+    let timer = IrqDevice::new(bust(try_device_manager()?).system_timer()?);
+    bust(&timer).as_counting().map(|counter| counter.now_us())
+}
+
+/// Follow the frame pointer chain from `fp`, printing each return
+/// address
+fn write_backtrace(out: &mut impl Write, fp: usize) -> fmt::Result {
+    let mut fp = fp;
+    for depth in 0..BACKTRACE_DEPTH {
+        if fp == 0 || !fp.is_multiple_of(align_of::<usize>()) {
+            break;
+        }
+        // SAFETY: a nonzero, aligned frame pointer points at the saved
+        // frame pointer and return address of a live frame. A corrupt
+        // stack can fault here, which stops the report.
+        let (next, ret) = unsafe {
+            let record = fp as *const usize;
+            (record.read(), record.add(1).read())
+        };
+        if ret == 0 {
+            break;
+        }
+        writeln!(out, "  #{:<2} {:#010x}", depth, ret)?;
+        // The stack grows down, so each caller's frame is above
+        if next <= fp {
+            break;
+        }
+        fp = next;
+    }
+    Ok(())
+}
+
+/// A lock the report can break
+trait Breakable {
+    type Guard<'a>
+    where
+        Self: 'a;
+
+    fn lock(&self) -> Self::Guard<'_>;
+    fn try_lock(&self) -> Option<Self::Guard<'_>>;
+    unsafe fn force_unlock(&self);
+}
+
+impl<T: ?Sized> Breakable for IrqSpinLock<T> {
+    type Guard<'a>
+        = IrqMutexGuard<'a, T, Irq>
+    where
+        Self: 'a;
+
+    fn lock(&self) -> Self::Guard<'_> {
+        self.lock()
+    }
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        self.try_lock()
+    }
+
+    unsafe fn force_unlock(&self) {
+        unsafe { self.force_unlock() }
+    }
+}
+
+impl<T: ?Sized> Breakable for IrqDevice<T> {
+    type Guard<'a>
+        = IrqMutexGuard<'a, T, Irq>
+    where
+        Self: 'a;
+
+    fn lock(&self) -> Self::Guard<'_> {
+        self.lock()
+    }
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        self.try_lock()
+    }
+
+    unsafe fn force_unlock(&self) {
+        unsafe { self.force_unlock() }
+    }
+}
+
+/// Lock `lock`, breaking it if it stays held: its holder may be the code
+/// that panicked
+fn bust<L: Breakable + ?Sized>(lock: &L) -> L::Guard<'_> {
+    for _ in 0..LOCK_SPINS {
+        if let Some(guard) = lock.try_lock() {
+            return guard;
+        }
+        core::hint::spin_loop();
+    }
+    // SAFETY: the system is going down; the console matters more than
+    // whatever the holder was doing with it
+    unsafe { lock.force_unlock() };
+    lock.lock()
+}
+
+/// The serial console, reached around the locks in the way
+pub(crate) struct PanicConsole;
+
+impl Write for PanicConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let Some(device_mgr) = try_device_manager() else {
+            return Ok(());
+        };
+        let Some(serial) = bust(device_mgr).serial_console().map(IrqDevice::new) else {
+            return Ok(());
+        };
+        let _ = bust(&serial).write(s.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+//! Reboot and power off
+//!
+//! [`reboot`], [`power_off`] and [`halt`] take the system down in order:
+//! every mounted filesystem is synced and every block device flushed, so
+//! nothing written is lost in a cache, then interrupts are masked for
+//! good and the platform does the rest (see [`Platform::reboot`]). A
+//! failed flush is logged but does not stop the shutdown.
+//!
+//! Flushing may sleep and allocate, so these are for task context; the
+//! SysRq monitor goes straight to the platform instead, as Linux's does.
+
+use crate::arch::Irq;
+use crate::block::flusher;
+use crate::fs::FileSystem;
+use crate::fs::vfs::vfs;
+use common::sync::irq::IrqControl;
+use drivers::platform::Platform;
+
+/// Sync every filesystem and flush every block device, then restart the
+/// machine
+pub fn reboot() -> ! {
+    shutdown("Restarting system");
+    Platform::reboot()
+}
+
+/// Sync every filesystem and flush every block device, then switch the
+/// machine off, or halt it where that is not possible
+pub fn power_off() -> ! {
+    shutdown("Power down");
+    Platform::power_off()
+}
+
+/// Sync every filesystem and flush every block device, then stop the CPU
+/// with the power left on
+pub fn halt() -> ! {
+    shutdown("System halted");
+    Platform::halt()
+}
+
+/// Write back everything cached on its way to a disk
+pub fn sync_all() {
+    if let Err(e) = vfs().sync() {
+        log::warn!("sync failed: {:?}", e);
+    }
+    flusher::flush_all();
+}
+
+fn shutdown(what: &str) {
+    sync_all();
+    log::info!("{}", what);
+    Irq::disable();
+}
@@ -0,0 +1,67 @@
+//! Console printing
+//!
+//! [`kprint!`](crate::kprint) and [`kprintln!`](crate::kprintln) format
+//! straight onto the serial console, as `print!` and `println!` do onto
+//! stdout: no allocation, no time stamp, and nothing kept in the kernel
+//! message log. They are for text meant for the console as it is, such
+//! as a command's output; diagnostics go through `log`.
+//!
+//! The port is locked, with interrupts off, for the whole of one call, so
+//! a line printed at once is never split by another; printing with the
+//! port or the device manager locked deadlocks. Before the device
+//! manager has a serial console, the text goes to the boot console; once
+//! the kernel has panicked, it goes the way of the panic report, breaking
+//! the console's lock if the code that panicked holds it.
+
+use crate::kcore::panic::{self, PanicConsole};
+use crate::subsystems::boot_sinks::BootSink;
+use crate::subsystems::{IrqDevice, boot_console, try_device_manager};
+use core::fmt::{self, Write};
+
+/// Print to the serial console.
+#[macro_export]
+macro_rules! kprint {
+    ($($arg:tt)*) => {
+        $crate::kcore::print::_print(format_args!($($arg)*))
+    };
+}
+
+/// Print to the serial console, with a newline.
+#[macro_export]
+macro_rules! kprintln {
+    () => {
+        $crate::kprint!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::kcore::print::_print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    if panic::panicking() {
+        let _ = PanicConsole.write_fmt(args);
+        return;
+    }
+    let console = try_device_manager()
+        .and_then(|device_mgr| device_mgr.lock().serial_console())
+        .map(IrqDevice::new);
+    match console {
+        Some(console) => {
+            let _ = console.lock().write_fmt(args);
+        }
+        None => {
+            let _ = BootConsole.write_fmt(args);
+        }
+    }
+}
+
+/// Writes to the boot console
+struct BootConsole;
+
+impl Write for BootConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        boot_console().write_str(s);
+        Ok(())
+    }
+}
@@ -0,0 +1,656 @@
+//! Kernel shell
+//!
+//! With the `shell` feature, a task reads command lines from the serial
+//! console and runs them, so the system can be used and looked into
+//! before there are programs to run. The commands go through the VFS and
+//! the device manager like any other kernel code:
+//!
+//! - `ls`, `cat`, `hexdump` and `echo ... > file` for files
+//! - `mount` and `umount` for filesystems: a block device's FAT32 or
+//!   ext2 volume, `proc` or `dev`
+//! - `lsdev`, `meminfo` and `irqstat`, read from the proc filesystem
+//! - `ifconfig` to list network interfaces, address them and bring them
+//!   up or down, `ping` to check a host answers, and `tftp get` to fetch
+//!   a file from a TFTP server
+//! - `reboot`, which syncs the disks first
+//!
+//! `help` lists them with their arguments. Words are split on whitespace,
+//! with no quoting; relative paths are taken from the root. Backspace
+//! edits the line and Ctrl-C drops it. Input is read like any reader of
+//! `/dev/uart0` does, so the SysRq monitor keeps working.
+
+use crate::fs::FileSystem;
+use crate::fs::FsError;
+use crate::fs::dev::{DevFs, UartFile};
+use crate::fs::ext2::Ext2Fs;
+use crate::fs::fat::fat32::Fat32Fs;
+use crate::fs::fd::FdError;
+use crate::fs::file::{File, FileType, OpenFlags};
+use crate::fs::proc::ProcFs;
+use crate::fs::vfs::{MountFlags, vfs};
+use crate::net::icmp::Pinger;
+use crate::net::{self, IfConfig, InterfaceInfo, Ipv4Addr, NetError};
+use crate::process::sched;
+use crate::subsystems::device_manager;
+use crate::{kprint, kprintln};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use drivers::hal::block_device::DynBlockDevice;
+
+/// Longest command line, in bytes
+const LINE_MAX: usize = 256;
+
+const PROMPT: &str = "kshell> ";
+
+/// Bytes read from a file at a time
+const CHUNK: usize = 512;
+
+/// Echo requests `ping` sends unless told otherwise
+const PING_COUNT: u16 = 4;
+
+/// Bytes of payload in a `ping` request, as Unix ping sends
+const PING_PAYLOAD: usize = 56;
+
+/// How long `ping` waits for each reply, and between requests, in
+/// milliseconds
+const PING_INTERVAL_MS: u32 = 1000;
+
+type Command = fn(&[&str]) -> Result<(), Error>;
+
+/// Every command: name, arguments and what it runs, sorted by name
+const COMMANDS: &[(&str, &str, Command)] = &[
+    ("cat", "<file>", cat),
+    ("echo", "[text...] [> file | >> file]", echo),
+    ("help", "", help),
+    ("hexdump", "<file> [bytes]", hexdump),
+    (
+        "ifconfig",
+        "[<interface> [<addr>/<prefix> [gateway]] [up | down]]",
+        ifconfig,
+    ),
+    ("irqstat", "", irqstat),
+    ("ls", "[path]", ls),
+    ("lsdev", "", lsdev),
+    ("meminfo", "", meminfo),
+    ("mount", "[[-r] <device | proc | dev> <dir>]", mount),
+    ("ping", "<addr> [count]", ping),
+    ("reboot", "", reboot),
+    ("tftp", "get <server> <file> [path]", tftp),
+    ("umount", "[-f] <dir>", umount),
+];
+
+crate::initcall!(late, SHELL_INIT, init);
+
+/// Start the shell task.
+fn init() {
+    match sched::spawn("kshell", run) {
+        Ok(_) => log::info!("Kernel shell on the console"),
+        Err(e) => log::warn!("No kernel shell: {:?}", e),
+    }
+}
+
+/// Why a command failed
+enum Error {
+    /// The arguments do not fit the command's
+    Usage,
+    Fd(FdError),
+}
+
+impl From<FdError> for Error {
+    fn from(e: FdError) -> Self {
+        Error::Fd(e)
+    }
+}
+
+impl From<NetError> for Error {
+    fn from(e: NetError) -> Self {
+        Error::Fd(FdError::Other(format!("{}", e)))
+    }
+}
+
+impl From<FsError> for Error {
+    fn from(e: FsError) -> Self {
+        Error::Fd(e.into())
+    }
+}
+
+/// Read and run command lines until the console fails
+fn run() {
+    let console = UartFile::new(0);
+    let mut line = String::new();
+    loop {
+        kprint!("{}", PROMPT);
+        if let Err(e) = read_line(&console, &mut line) {
+            log::warn!("kshell: console read failed: {:?}", e);
+            return;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else {
+            continue;
+        };
+        let Some(&(_, usage, command)) = COMMANDS.iter().find(|(n, _, _)| *n == name) else {
+            kprintln!("{}: unknown command; try help", name);
+            continue;
+        };
+        match command(args) {
+            Ok(()) => {}
+            Err(Error::Usage) => kprintln!("usage: {} {}", name, usage),
+            Err(Error::Fd(FdError::Fs(e))) => kprintln!("{}: {:?}", name, e),
+            Err(Error::Fd(FdError::Other(why))) => kprintln!("{}: {}", name, why),
+            Err(Error::Fd(e)) => kprintln!("{}: {:?}", name, e),
+        }
+    }
+}
+
+/// Read a line from the console into `line`, echoing what is typed
+fn read_line(console: &UartFile, line: &mut String) -> Result<(), FdError> {
+    line.clear();
+    let mut buf = [0u8; 16];
+    // A line ended by CR LF is one line, not two
+    let mut after_cr = false;
+    loop {
+        let n = console.read(&mut buf, 0)?;
+        for &byte in &buf[..n] {
+            let was_cr = core::mem::replace(&mut after_cr, byte == b'\r');
+            match byte {
+                b'\n' if was_cr => {}
+                b'\r' | b'\n' => {
+                    kprintln!();
+                    return Ok(());
+                }
+                // Backspace and DEL
+                0x08 | 0x7f => {
+                    if line.pop().is_some() {
+                        kprint!("\x08 \x08");
+                    }
+                }
+                // Ctrl-C
+                0x03 => {
+                    line.clear();
+                    kprint!("^C\n{}", PROMPT);
+                }
+                0x20..=0x7e if line.len() < LINE_MAX => {
+                    line.push(byte as char);
+                    kprint!("{}", byte as char);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn help(args: &[&str]) -> Result<(), Error> {
+    if !args.is_empty() {
+        return Err(Error::Usage);
+    }
+    for (name, usage, _) in COMMANDS {
+        kprintln!("  {} {}", name, usage);
+    }
+    Ok(())
+}
+
+/// List a directory: type, size and name of each entry
+fn ls(args: &[&str]) -> Result<(), Error> {
+    let path = match args {
+        [] => "/",
+        [path] => path,
+        _ => return Err(Error::Usage),
+    };
+    let stat = vfs().stat(path)?;
+    if stat.file_type != FileType::Directory {
+        kprintln!(
+            "{} {:>10} {}",
+            type_char(stat.file_type),
+            stat.size,
+            stat.name
+        );
+        return Ok(());
+    }
+    for entry in vfs().read_dir(path)? {
+        kprintln!(
+            "{} {:>10} {}",
+            type_char(entry.file_type),
+            entry.size,
+            entry.name
+        );
+    }
+    Ok(())
+}
+
+/// The letter `ls -l` shows for a file type
+fn type_char(file_type: FileType) -> char {
+    match file_type {
+        FileType::Regular => '-',
+        FileType::Directory => 'd',
+        FileType::CharDevice => 'c',
+        FileType::BlockDevice => 'b',
+        FileType::Symlink => 'l',
+        FileType::Pipe => 'p',
+        FileType::Socket => 's',
+    }
+}
+
+fn cat(args: &[&str]) -> Result<(), Error> {
+    let [path] = args else {
+        return Err(Error::Usage);
+    };
+    print_file(&*vfs().open(path, OpenFlags::RDONLY)?)
+}
+
+/// Print `file` as text, invalid UTF-8 as U+FFFD
+fn print_file(file: &dyn File) -> Result<(), Error> {
+    let mut buf = [0u8; CHUNK];
+    let mut offset = 0;
+    // Bytes of a character cut in two by the end of the last read
+    let mut pending = 0;
+    loop {
+        let n = file.read(&mut buf[pending..], offset)?;
+        if n == 0 {
+            if pending > 0 {
+                kprint!("\u{FFFD}");
+            }
+            return Ok(());
+        }
+        offset += n;
+        let len = pending + n;
+        let printed = print_text(&buf[..len]);
+        buf.copy_within(printed..len, 0);
+        pending = len - printed;
+    }
+}
+
+/// Print `bytes` as text, invalid UTF-8 as U+FFFD. Returns how many were
+/// printed: a character cut short at the end is left for the next call.
+fn print_text(bytes: &[u8]) -> usize {
+    let mut rest = bytes;
+    loop {
+        match core::str::from_utf8(rest) {
+            Ok(text) => {
+                kprint!("{}", text);
+                return bytes.len();
+            }
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                kprint!("{}", core::str::from_utf8(valid).unwrap_or_default());
+                match e.error_len() {
+                    Some(len) => {
+                        kprint!("\u{FFFD}");
+                        rest = &invalid[len..];
+                    }
+                    None => return bytes.len() - invalid.len(),
+                }
+            }
+        }
+    }
+}
+
+/// Dump a file, or its first bytes, in hex and ASCII, 16 bytes a line
+fn hexdump(args: &[&str]) -> Result<(), Error> {
+    let (path, limit) = match args {
+        [path] => (path, usize::MAX),
+        [path, bytes] => (path, bytes.parse().map_err(|_| Error::Usage)?),
+        _ => return Err(Error::Usage),
+    };
+    let file = vfs().open(path, OpenFlags::RDONLY)?;
+    let mut line = [0u8; 16];
+    let mut offset = 0;
+    while offset < limit {
+        let want = line.len().min(limit - offset);
+        let n = file.read(&mut line[..want], offset)?;
+        if n == 0 {
+            break;
+        }
+        kprint!("{:08x} ", offset);
+        for (i, byte) in line.iter().enumerate() {
+            if i % 8 == 0 {
+                kprint!(" ");
+            }
+            if i < n {
+                kprint!("{:02x} ", byte);
+            } else {
+                kprint!("   ");
+            }
+        }
+        kprint!(" |");
+        for &byte in &line[..n] {
+            let shown = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            kprint!("{}", shown);
+        }
+        kprintln!("|");
+        offset += n;
+    }
+    kprintln!("{:08x}", offset);
+    Ok(())
+}
+
+/// Print the words, or write them to a file with `>`, or add them to its
+/// end with `>>`
+fn echo(args: &[&str]) -> Result<(), Error> {
+    let (words, target) = match args.iter().position(|&arg| arg == ">" || arg == ">>") {
+        Some(index) => match &args[index..] {
+            [redirect, path] => (&args[..index], Some((*redirect, *path))),
+            _ => return Err(Error::Usage),
+        },
+        None => (args, None),
+    };
+    let mut text = words.join(" ");
+    text.push('\n');
+
+    let Some((redirect, path)) = target else {
+        kprint!("{}", text);
+        return Ok(());
+    };
+    let mode = if redirect == ">>" {
+        OpenFlags::APPEND
+    } else {
+        OpenFlags::TRUNC
+    };
+    let file = vfs().open(path, OpenFlags::WRONLY | OpenFlags::CREATE | mode)?;
+    let mut written = 0;
+    while written < text.len() {
+        match file.write(&text.as_bytes()[written..], written)? {
+            0 => return Err(Error::Fd(FdError::IoError)),
+            n => written += n,
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// List the mounts, or mount a filesystem
+fn mount(args: &[&str]) -> Result<(), Error> {
+    let (flags, args) = match args {
+        ["-r", rest @ ..] => (MountFlags::READ_ONLY, rest),
+        _ => (MountFlags::empty(), args),
+    };
+    let (source, dir) = match args {
+        [] if flags.is_empty() => {
+            for (prefix, flags) in vfs().mounts() {
+                let mode = if flags.contains(MountFlags::READ_ONLY) {
+                    "ro"
+                } else {
+                    "rw"
+                };
+                kprintln!("{} {}", prefix, mode);
+            }
+            return Ok(());
+        }
+        [source, dir] => (*source, *dir),
+        _ => return Err(Error::Usage),
+    };
+
+    let fs: Arc<dyn FileSystem> = match source {
+        "proc" => Arc::new(ProcFs::new()),
+        "dev" => Arc::new(DevFs::new()),
+        name => {
+            let dev = device_manager()
+                .lock()
+                .block(name)
+                .ok_or(FsError::NotFound)?;
+            volume(dev)?
+        }
+    };
+    vfs().mount_fs(dir, fs, flags)?;
+    Ok(())
+}
+
+/// The filesystem on `dev`, FAT32 or ext2
+fn volume(dev: Arc<dyn DynBlockDevice>) -> Result<Arc<dyn FileSystem>, Error> {
+    let fat = match Fat32Fs::mount(dev.clone()) {
+        Ok(fs) => return Ok(fs),
+        Err(e) => e,
+    };
+    let ext2 = match Ext2Fs::mount(dev) {
+        Ok(fs) => return Ok(fs),
+        Err(e) => e,
+    };
+    Err(Error::Fd(FdError::Other(format!(
+        "no FAT32 ({:?}) or ext2 ({:?}) volume",
+        fat, ext2
+    ))))
+}
+
+fn umount(args: &[&str]) -> Result<(), Error> {
+    match args {
+        ["-f", dir] => vfs().umount_force(dir)?,
+        [dir] => vfs().umount(dir)?,
+        _ => return Err(Error::Usage),
+    }
+    Ok(())
+}
+
+fn lsdev(args: &[&str]) -> Result<(), Error> {
+    print_proc(args, "devices")
+}
+
+fn meminfo(args: &[&str]) -> Result<(), Error> {
+    print_proc(args, "meminfo")
+}
+
+fn irqstat(args: &[&str]) -> Result<(), Error> {
+    print_proc(args, "interrupts")
+}
+
+/// Print the proc file `name`, whether or not proc is mounted, for a
+/// command that takes no arguments
+fn print_proc(args: &[&str], name: &str) -> Result<(), Error> {
+    if !args.is_empty() {
+        return Err(Error::Usage);
+    }
+    print_file(&*ProcFs::new().open(name, OpenFlags::RDONLY)?)
+}
+
+/// List the network interfaces, or configure one
+fn ifconfig(args: &[&str]) -> Result<(), Error> {
+    let Some((&name, mut args)) = args.split_first() else {
+        for iface in net::iface::interfaces() {
+            print_interface(&iface);
+        }
+        return Ok(());
+    };
+    if args.is_empty() {
+        print_interface(&net::iface::interface(name).ok_or(NetError::NoSuchInterface)?);
+        return Ok(());
+    }
+
+    let up = match args.split_last() {
+        Some((&"up", rest)) => {
+            args = rest;
+            Some(true)
+        }
+        Some((&"down", rest)) => {
+            args = rest;
+            Some(false)
+        }
+        _ => None,
+    };
+    let config = match args {
+        [] => None,
+        [addr] | [addr, _] => {
+            let (addr, prefix_len) = addr.split_once('/').ok_or(Error::Usage)?;
+            let gateway = match args.get(1) {
+                Some(gateway) => Some(Ipv4Addr::parse(gateway).ok_or(Error::Usage)?),
+                None => None,
+            };
+            Some(IfConfig {
+                addr: Ipv4Addr::parse(addr).ok_or(Error::Usage)?,
+                prefix_len: prefix_len
+                    .parse()
+                    .ok()
+                    .filter(|&len| len <= 32)
+                    .ok_or(Error::Usage)?,
+                gateway,
+            })
+        }
+        _ => return Err(Error::Usage),
+    };
+
+    if let Some(config) = config {
+        net::iface::set_config(name, Some(config))?;
+    }
+    if let Some(up) = up {
+        net::iface::set_up(name, up)?;
+    }
+    Ok(())
+}
+
+fn print_interface(iface: &InterfaceInfo) {
+    let state = match (iface.up, iface.link) {
+        (false, _) => "DOWN",
+        (true, false) => "UP NO-CARRIER",
+        (true, true) => "UP",
+    };
+    kprintln!("{}: {} mtu {}", iface.name, state, iface.mtu);
+    kprintln!("    ether {}", iface.mac);
+    if let Some(config) = iface.config {
+        kprint!(
+            "    inet {}/{} broadcast {}",
+            config.addr,
+            config.prefix_len,
+            config.broadcast()
+        );
+        match config.gateway {
+            Some(gateway) => kprintln!(" gateway {}", gateway),
+            None => kprintln!(),
+        }
+    }
+    let stats = &iface.stats;
+    kprintln!(
+        "    RX {} packets {} bytes {} dropped",
+        stats.rx_packets,
+        stats.rx_bytes,
+        stats.rx_dropped
+    );
+    kprintln!(
+        "    TX {} packets {} bytes {} errors",
+        stats.tx_packets,
+        stats.tx_bytes,
+        stats.tx_errors
+    );
+}
+
+/// Send echo requests to a host and report the replies' round trips
+fn ping(args: &[&str]) -> Result<(), Error> {
+    let (addr, count) = match args {
+        [addr] => (*addr, PING_COUNT),
+        [addr, count] => (*addr, count.parse().map_err(|_| Error::Usage)?),
+        _ => return Err(Error::Usage),
+    };
+    let dst = Ipv4Addr::parse(addr).ok_or(Error::Usage)?;
+    let pinger = Pinger::new();
+    kprintln!("PING {}: {} data bytes", dst, PING_PAYLOAD);
+
+    let mut rtts = Vec::new();
+    for seq in 1..=count {
+        let start = sched::ticks();
+        match pinger.send(dst, seq, PING_PAYLOAD) {
+            Ok(()) => loop {
+                let reply = match pinger.recv(PING_INTERVAL_MS) {
+                    Ok(reply) => reply,
+                    Err(_) => {
+                        kprintln!("Request timeout for icmp_seq {}", seq);
+                        break;
+                    }
+                };
+                // A late reply to an earlier request
+                if reply.seq != seq {
+                    continue;
+                }
+                kprintln!(
+                    "{} bytes from {}: icmp_seq={} ttl={} time={}.{:03} ms",
+                    reply.len + net::icmp::HEADER_LEN,
+                    reply.src,
+                    reply.seq,
+                    reply.ttl,
+                    reply.rtt_us / 1000,
+                    reply.rtt_us % 1000
+                );
+                rtts.push(reply.rtt_us);
+                break;
+            },
+            Err(e) => kprintln!("ping: icmp_seq {}: {}", seq, e),
+        }
+        if seq != count {
+            let elapsed_ms = (sched::ticks() - start) * sched::TICK_US as u64 / 1000;
+            sched::sleep_ms(PING_INTERVAL_MS.saturating_sub(elapsed_ms as u32));
+        }
+    }
+
+    let sent = count as usize;
+    kprintln!("--- {} ping statistics ---", dst);
+    kprintln!(
+        "{} packets transmitted, {} received, {}% packet loss",
+        sent,
+        rtts.len(),
+        (sent - rtts.len()) * 100 / sent.max(1)
+    );
+    if let (Some(min), Some(max)) = (rtts.iter().min(), rtts.iter().max()) {
+        let avg = rtts.iter().sum::<u64>() / rtts.len() as u64;
+        let ms = |us: u64| format!("{}.{:03}", us / 1000, us % 1000);
+        kprintln!("rtt min/avg/max = {}/{}/{} ms", ms(*min), ms(avg), ms(*max));
+    }
+    Ok(())
+}
+
+/// Fetch a file from a TFTP server into the VFS, under its own name in
+/// the root unless given a path
+fn tftp(args: &[&str]) -> Result<(), Error> {
+    let (server, remote, path) = match args {
+        ["get", server, remote] => (
+            *server,
+            *remote,
+            remote.rsplit('/').next().unwrap_or(remote),
+        ),
+        ["get", server, remote, path] => (*server, *remote, *path),
+        _ => return Err(Error::Usage),
+    };
+    let server = Ipv4Addr::parse(server).ok_or(Error::Usage)?;
+    let file = vfs().open(
+        path,
+        OpenFlags::WRONLY | OpenFlags::CREATE | OpenFlags::TRUNC,
+    )?;
+
+    let start = sched::ticks();
+    let mut offset = 0;
+    let received = net::tftp::get(server, remote, |mut data| {
+        while !data.is_empty() {
+            match file.write(data, offset)? {
+                0 => return Err(FdError::IoError),
+                n => {
+                    offset += n;
+                    data = &data[n..];
+                }
+            }
+        }
+        Ok(())
+    });
+    let received = match received {
+        Ok(received) => received,
+        Err(net::tftp::TftpError::Sink(e)) => return Err(Error::Fd(e)),
+        Err(e) => return Err(Error::Fd(FdError::Other(format!("{}", e)))),
+    };
+    file.flush()?;
+
+    let ms = (sched::ticks() - start) * sched::TICK_US as u64 / 1000;
+    kprintln!(
+        "{}: {} bytes in {}.{:03} s",
+        path,
+        received,
+        ms / 1000,
+        ms % 1000
+    );
+    Ok(())
+}
+
+fn reboot(args: &[&str]) -> Result<(), Error> {
+    if !args.is_empty() {
+        return Err(Error::Usage);
+    }
+    crate::kcore::power::reboot()
+}
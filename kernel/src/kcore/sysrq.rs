@@ -0,0 +1,213 @@
+//! Debug monitor on the serial console (SysRq)
+//!
+//! Typing [`SYSRQ_KEY`] (Ctrl-O) on the console and then a command key
+//! prints a report straight to the console:
+//!
+//! - `t`: the tasks of every CPU
+//! - `m`: memory usage
+//! - `i`: interrupt counts
+//! - `l`: which of the kernel's main locks are held
+//! - `d`: the last lines of the kernel log
+//! - `p`: on the ARM1176, start profiling, or stop and print the hottest
+//!   kernel PCs (see [`perf`](crate::arch::arm::perf))
+//! - `b`: reboot at once, without syncing or flushing anything
+//! - `o`: power off at once, likewise
+//! - anything else: the list of commands
+//!
+//! Ctrl-O twice passes one through to readers.
+//!
+//! The [console input](super::console) is read in interrupt context, so
+//! the monitor answers even when no task gets to run. Nothing it does
+//! allocates or waits on a lock: what is behind a held lock is left out
+//! of the report. The rest of the input is queued for readers of the
+//! console.
+
+use crate::arch::MAX_CPUS;
+use crate::irq::handlers::{self, MAX_IRQS};
+use crate::mm::{self, buddy_allocator::AllocatorStats};
+use crate::process::sched;
+use crate::process::table::process_table;
+use crate::subsystems::try_device_manager;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use drivers::hal::serial::DynSerialPort;
+use drivers::platform::Platform;
+
+/// The key that starts a command: Ctrl-O
+pub const SYSRQ_KEY: u8 = 0x0F;
+
+/// Log lines the `d` command prints
+const LOG_LINES: usize = 32;
+
+/// PCs the `p` command prints
+#[cfg(all(target_arch = "arm", not(feature = "bcm2836")))]
+const PROFILE_LINES: usize = 20;
+
+/// Set by [`SYSRQ_KEY`]: the next byte is a command
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+/// Pass a byte typed on the console through the monitor, running it if
+/// it is a command. Returns whether it is input for readers. Called with
+/// the console locked, as `port`.
+pub fn filter(port: &mut dyn DynSerialPort, byte: u8) -> bool {
+    if ARMED.swap(false, Ordering::Relaxed) {
+        if byte != SYSRQ_KEY {
+            let _ = run(&mut Console(port), byte);
+            return false;
+        }
+    } else if byte == SYSRQ_KEY {
+        ARMED.store(true, Ordering::Relaxed);
+        return false;
+    }
+    true
+}
+
+/// Writes to the console port the monitor holds
+struct Console<'a>(&'a mut dyn DynSerialPort);
+
+impl Write for Console<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write(s.as_bytes()).map_err(|_| fmt::Error)?;
+        Ok(())
+    }
+}
+
+fn run(out: &mut impl Write, command: u8) -> fmt::Result {
+    writeln!(out, "\nSysRq: {}", command as char)?;
+    match command {
+        b't' => tasks(out),
+        b'm' => memory(out),
+        b'i' => interrupts(out),
+        b'l' => locks(out),
+        b'd' => log_lines(out),
+        #[cfg(all(target_arch = "arm", not(feature = "bcm2836")))]
+        b'p' => profile(out),
+        b'b' => {
+            writeln!(out, "Rebooting")?;
+            Platform::reboot()
+        }
+        b'o' => {
+            writeln!(out, "Powering off")?;
+            Platform::power_off()
+        }
+        _ => help(out),
+    }
+}
+
+fn help(out: &mut impl Write) -> fmt::Result {
+    writeln!(
+        out,
+        "SysRq commands: t (tasks), m (memory), i (interrupts), l (locks), d (log), p (profile), \
+         b (reboot), o (power off)"
+    )
+}
+
+/// Every task, CPU by CPU: ID, name and state
+fn tasks(out: &mut impl Write) -> fmt::Result {
+    let mut result = Ok(());
+    for cpu in 0..MAX_CPUS {
+        result = result.and_then(|()| writeln!(out, "CPU {}:", cpu));
+        let listed = sched::try_for_each_task(cpu, |id, name, state| {
+            result = result.and_then(|()| writeln!(out, "{:>5} {:<16} {:?}", id.0, name, state));
+        });
+        if !listed {
+            result = result.and_then(|()| writeln!(out, "  (scheduler locked)"));
+        }
+    }
+    result
+}
+
+/// Usage of each allocator in kB, as in `/proc/meminfo`
+fn memory(out: &mut impl Write) -> fmt::Result {
+    let stats = mm::try_stats();
+    let mut section = |name: &str, stats: Option<AllocatorStats>| match stats {
+        Some(stats) => writeln!(
+            out,
+            "{:<5} total {:>8} kB, used {:>8} kB, free {:>8} kB, largest {:>8} kB",
+            name,
+            stats.total_bytes / 1024,
+            stats.used_bytes / 1024,
+            stats.free_bytes / 1024,
+            stats.largest_free / 1024
+        ),
+        None => writeln!(out, "{:<5} (unavailable or locked)", name),
+    };
+    section("Heap", stats.heap)?;
+    section("Page", stats.pages)?;
+    section("Dma", stats.dma)
+}
+
+/// Lines that have fired, as in `/proc/interrupts`
+fn interrupts(out: &mut impl Write) -> fmt::Result {
+    writeln!(
+        out,
+        "{:>4}: {:>10} {:>10} {:>8}",
+        "IRQ", "COUNT", "UNHANDLED", "MAX_US"
+    )?;
+    for irq in 0..MAX_IRQS as u32 {
+        let stats = handlers::irq_stats(irq);
+        if stats.count != 0 {
+            writeln!(
+                out,
+                "{:>4}: {:>10} {:>10} {:>8}",
+                irq, stats.count, stats.unhandled, stats.max_us
+            )?;
+        }
+    }
+    writeln!(out, " SPU: {:>10}", handlers::spurious_count())
+}
+
+/// The kernel's main locks that are held. Who holds them is not known.
+fn locks(out: &mut impl Write) -> fmt::Result {
+    let held = |locked: bool| if locked { "held" } else { "free" };
+    writeln!(
+        out,
+        "device manager: {}",
+        held(try_device_manager().is_some_and(|manager| manager.is_locked()))
+    )?;
+    writeln!(out, "process table:  {}", held(process_table().is_locked()))?;
+    writeln!(
+        out,
+        "heap:           {}",
+        held(mm::heap_allocator::is_locked())
+    )?;
+    writeln!(
+        out,
+        "page allocator: {}",
+        held(mm::page_allocator::page_allocator().is_locked())
+    )?;
+    for cpu in 0..MAX_CPUS {
+        writeln!(out, "scheduler {:<4} {}", cpu, held(sched::is_locked(cpu)))?;
+    }
+    Ok(())
+}
+
+/// Start sampling cycles, or stop and print the PCs they went to
+#[cfg(all(target_arch = "arm", not(feature = "bcm2836")))]
+fn profile(out: &mut impl Write) -> fmt::Result {
+    use crate::arch::arm::perf::{self, Event, Sample};
+
+    if !perf::is_sampling() {
+        perf::start_sampling(Event::Cycles);
+        return writeln!(out, "Profiling cycles; SysRq p again for the report");
+    }
+    perf::stop_sampling();
+
+    let mut top = [Sample::default(); PROFILE_LINES];
+    let (n, profile) = perf::top_samples(&mut top);
+    writeln!(out, "{:>10} {:>10}  ({:?})", "PC", "COUNT", profile.event)?;
+    for sample in &top[..n] {
+        writeln!(out, "{:#010x} {:>10}", sample.pc, sample.weight)?;
+    }
+    writeln!(out, "{:>10} {:>10}", "user", profile.user)?;
+    writeln!(out, "{:>10} {:>10}", "dropped", profile.dropped)
+}
+
+/// The last lines of the kernel log, unless it is locked
+fn log_lines(out: &mut impl Write) -> fmt::Result {
+    let mut result = Ok(());
+    crate::logger::try_for_each_recent(LOG_LINES, |record| {
+        result = result.and_then(|()| out.write_str(record));
+    });
+    result
+}
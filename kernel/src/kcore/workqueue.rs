@@ -0,0 +1,95 @@
+//! Workqueue
+//!
+//! Work that may sleep or allocate, such as filesystem reads a keypress
+//! starts or processing a finished SD transfer, cannot run in an
+//! interrupt handler or a softirq. It is queued here instead and run by a
+//! kernel task, `kworker`, in the order it was queued.
+//!
+//! Work items are statics, so queueing one never allocates and is safe
+//! from interrupt context. An item is queued at most once at a time; it
+//! can be queued again as soon as it starts running.
+
+use crate::arch::IrqSpinLock;
+use crate::process::sched::{self, WaitQueue};
+use crate::process::stack::StackError;
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Most items queued at once
+pub const MAX_QUEUED: usize = 64;
+
+/// A function to run on the workqueue
+pub struct Work {
+    func: fn(),
+    queued: AtomicBool,
+}
+
+impl Work {
+    pub const fn new(func: fn()) -> Self {
+        Self {
+            func,
+            queued: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether the item is waiting to run
+    pub fn is_queued(&self) -> bool {
+        self.queued.load(Ordering::Acquire)
+    }
+}
+
+/// Queued items, oldest first. Room for [`MAX_QUEUED`] is reserved by
+/// [`init`], so queueing never grows it.
+static QUEUE: IrqSpinLock<VecDeque<&'static Work>> = IrqSpinLock::new(VecDeque::new());
+
+/// Where `kworker` sleeps while the queue is empty
+static MORE: WaitQueue = WaitQueue::new();
+
+crate::initcall!(late, WORKQUEUE_INIT, start);
+
+/// Reserve the queue and start `kworker`
+pub fn init() -> Result<(), StackError> {
+    QUEUE.lock().reserve(MAX_QUEUED);
+    sched::spawn("kworker", worker)?;
+    Ok(())
+}
+
+/// [`init`] at boot, which goes on without the workqueue if it fails
+fn start() {
+    if let Err(e) = init() {
+        log::warn!("No workqueue: {:?}", e);
+    }
+}
+
+/// Queue `work` to run on `kworker`. Returns false, queueing nothing, if
+/// it is already queued or the queue is full.
+pub fn queue(work: &'static Work) -> bool {
+    if work.queued.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+
+    {
+        let mut queue = QUEUE.lock();
+        if queue.len() >= queue.capacity().min(MAX_QUEUED) {
+            work.queued.store(false, Ordering::Release);
+            return false;
+        }
+        queue.push_back(work);
+    }
+    MORE.wake_one();
+    true
+}
+
+fn worker() {
+    loop {
+        MORE.sleep_on(|| !QUEUE.lock().is_empty());
+        loop {
+            let next = QUEUE.lock().pop_front();
+            let Some(work) = next else {
+                break;
+            };
+            work.queued.store(false, Ordering::Release);
+            (work.func)();
+        }
+    }
+}
@@ -2,10 +2,21 @@
 //!
 //! Phase 1: Boot logging via BootSink (UART/VGA)
 //! Phase 2: Runtime logging via dynamic LogSink fanout
-use crate::subsystems::boot_console;
+//!
+//! Every record is stamped with the uptime and kept in a fixed-size ring
+//! buffer, the kernel message log, before it goes to the console. The
+//! console is fed from the ring: a record logged while another is being
+//! written out, say from an interrupt handler, is left in the ring and
+//! written out by whoever holds the console, so logging never waits on
+//! it. `/proc/kmsg` reads the ring. Records are stamped from the system
+//! timer once the runtime phase starts, and with zero before.
+use crate::arch::IrqSpinLock;
 use crate::subsystems::boot_sinks::BootSink;
+use crate::subsystems::{IrqDevice, boot_console, system_timer};
+use common::sync::Once;
 use core::fmt::Write;
 use core::sync::atomic::{AtomicU8, Ordering};
+use drivers::hal::timer::DynTimer;
 use log::{LevelFilter, Log, Metadata, Record};
 use spin::Mutex;
 
@@ -14,6 +25,17 @@ use spin::Mutex;
 /// ----------------------------
 pub trait LogSink: Send + Sync {
     fn write_str(&self, s: &str);
+
+    /// Write bytes that need not be UTF-8, such as a program's output.
+    /// Sinks that can only show text get `?` for what is not.
+    fn write_bytes(&self, bytes: &[u8]) {
+        for chunk in bytes.utf8_chunks() {
+            self.write_str(chunk.valid());
+            if !chunk.invalid().is_empty() {
+                self.write_str("?");
+            }
+        }
+    }
 }
 
 /// ----------------------------
@@ -44,6 +66,113 @@ pub static LOGGER: KernelLogger = KernelLogger {
     max_level: AtomicU8::new(LevelFilter::Info as u8),
 };
 
+/// ----------------------------
+/// Kernel message ring
+/// ----------------------------
+/// Size of the kernel message ring in bytes
+pub const KMSG_SIZE: usize = 16 * 1024;
+
+/// Longest record, newline included; longer ones are cut short
+const RECORD_MAX: usize = 512;
+
+/// Records oldest first, overwritten once full. Positions count every
+/// byte ever logged; byte `pos` is at `pos % KMSG_SIZE`.
+struct Kmsg {
+    buf: [u8; KMSG_SIZE],
+    /// Bytes logged since boot
+    written: u64,
+    /// Bytes written out to the console
+    drained: u64,
+    /// Time stamp of the last record
+    last_us: u64,
+}
+
+impl Kmsg {
+    const fn new() -> Self {
+        Self {
+            buf: [0; KMSG_SIZE],
+            written: 0,
+            drained: 0,
+            last_us: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.buf[(self.written % KMSG_SIZE as u64) as usize] = byte;
+            self.written += 1;
+        }
+    }
+
+    /// Position of the oldest whole record still in the ring
+    fn oldest(&self) -> u64 {
+        let start = self.written.saturating_sub(KMSG_SIZE as u64);
+        if start == 0 {
+            return 0;
+        }
+        // The oldest record may have been partly overwritten
+        (start..self.written)
+            .find(|&pos| self.byte(pos) == b'\n')
+            .map_or(self.written, |pos| pos + 1)
+    }
+
+    fn byte(&self, pos: u64) -> u8 {
+        self.buf[(pos % KMSG_SIZE as u64) as usize]
+    }
+
+    /// Position of the oldest of the last `n` records in the ring
+    fn recent(&self, n: usize) -> u64 {
+        if n == 0 {
+            return self.written;
+        }
+        let oldest = self.oldest();
+        let mut seen = 0;
+        // The newest record ends with the last byte written
+        let mut pos = self.written.saturating_sub(1);
+        while pos > oldest {
+            if self.byte(pos - 1) == b'\n' {
+                seen += 1;
+                if seen == n {
+                    return pos;
+                }
+            }
+            pos -= 1;
+        }
+        oldest
+    }
+
+    /// Copy the record at `pos` into `out`, returning the position after
+    /// it
+    fn record_at(&self, pos: u64, out: &mut FmtBuf<RECORD_MAX>) -> u64 {
+        let mut pos = pos;
+        while pos < self.written && out.pos < RECORD_MAX {
+            let byte = self.byte(pos);
+            out.buf[out.pos] = byte;
+            out.pos += 1;
+            pos += 1;
+            if byte == b'\n' {
+                break;
+            }
+        }
+        pos
+    }
+}
+
+static KMSG: IrqSpinLock<Kmsg> = IrqSpinLock::new(Kmsg::new());
+
+/// Timer records are stamped from. Kept here, as records may be logged
+/// with the device manager locked.
+static CLOCK: Once<IrqDevice<dyn DynTimer>> = Once::new();
+
+/// Microseconds since boot, if the clock can be read without waiting:
+/// the code that holds it may be what is logging
+fn timestamp() -> Option<u64> {
+    CLOCK
+        .get()?
+        .try_lock()
+        .and_then(|timer| timer.as_counting().map(|counter| counter.now_us()))
+}
+
 /// ----------------------------
 /// Initialization (boot phase)
 /// ----------------------------
@@ -58,9 +187,72 @@ pub fn init(level: LevelFilter) {
 /// Transition to runtime phase
 /// ----------------------------
 pub fn attach_runtime(sinks: alloc::vec::Vec<&'static dyn LogSink>) {
+    if let Some(timer) = system_timer() {
+        CLOCK.call_once(|| timer);
+    }
     *LOGGER.mode.lock() = LoggerMode::Runtime { sinks };
 }
 
+/// ----------------------------
+/// Runtime level control
+/// ----------------------------
+/// Log records up to `level` from now on
+pub fn set_level(level: LevelFilter) {
+    LOGGER.max_level.store(level as u8, Ordering::Relaxed);
+    log::set_max_level(level);
+}
+
+/// The most verbose level logged
+pub fn level() -> LevelFilter {
+    level_from_u8(LOGGER.max_level.load(Ordering::Relaxed))
+}
+
+/// ----------------------------
+/// Ring readers
+/// ----------------------------
+/// Call `f` with each record in the ring, oldest first, newline
+/// included. The ring is locked meanwhile, so `f` must not log.
+pub fn for_each_record(f: impl FnMut(&str)) {
+    let kmsg = KMSG.lock();
+    records_from(&kmsg, kmsg.oldest(), f);
+}
+
+/// Call `f` with each of the last `n` records in the ring, oldest first,
+/// unless the ring is locked. For the panic handler, which may have
+/// interrupted the holder.
+pub fn try_for_each_recent(n: usize, f: impl FnMut(&str)) {
+    if let Some(kmsg) = KMSG.try_lock() {
+        records_from(&kmsg, kmsg.recent(n), f);
+    }
+}
+
+/// Call `f` with each record logged from position `pos` on, oldest
+/// first, and the position after it, to pass next time to read on from
+/// there. Position 0 is the oldest record in the ring; records
+/// overwritten since `pos` are skipped. The ring is locked meanwhile, so
+/// `f` must not log.
+pub fn for_each_record_from(pos: u64, mut f: impl FnMut(&str, u64)) {
+    let kmsg = KMSG.lock();
+    let mut pos = pos.max(kmsg.oldest());
+    while pos < kmsg.written {
+        let mut record = FmtBuf::<RECORD_MAX>::new();
+        pos = kmsg.record_at(pos, &mut record);
+        if let Ok(record) = core::str::from_utf8(&record.buf[..record.pos]) {
+            f(record, pos);
+        }
+    }
+}
+
+fn records_from(kmsg: &Kmsg, mut pos: u64, mut f: impl FnMut(&str)) {
+    while pos < kmsg.written {
+        let mut record = FmtBuf::<RECORD_MAX>::new();
+        pos = kmsg.record_at(pos, &mut record);
+        if let Ok(record) = core::str::from_utf8(&record.buf[..record.pos]) {
+            f(record);
+        }
+    }
+}
+
 /// ----------------------------
 /// Log implementation
 /// ----------------------------
@@ -75,30 +267,71 @@ impl Log for KernelLogger {
             return;
         }
 
-        let mut buf = FmtBuf::<512>::new();
+        // A record that cannot read the clock takes the last one's time
+        let now = timestamp().unwrap_or_else(|| KMSG.lock().last_us);
+        let mut buf = FmtBuf::<RECORD_MAX>::new();
         let _ = write!(
             buf,
-            "[{:<5} {}] {}\n",
+            "[{:>5}.{:06}] [{:<5} {}] {}",
+            now / 1_000_000,
+            now % 1_000_000,
             record.level(),
             record.target(),
             record.args()
         );
-        let s = buf.as_str();
+        // Cut short to leave room for the newline
+        buf.truncate(RECORD_MAX - 1);
+        let _ = buf.write_str("\n");
+        {
+            let mut kmsg = KMSG.lock();
+            kmsg.last_us = kmsg.last_us.max(now);
+            kmsg.push(buf.as_str().as_bytes());
+        }
+
+        self.drain();
+    }
 
-        let mode = self.mode.lock();
-        match &*mode {
-            LoggerMode::Boot => {
-                boot_console().write_str(s);
+    fn flush(&self) {
+        self.drain();
+    }
+}
+
+impl KernelLogger {
+    /// Write the records the console has not seen out to it, unless
+    /// someone else is already doing so
+    fn drain(&self) {
+        let Some(mode) = self.mode.try_lock() else {
+            return;
+        };
+        loop {
+            let mut record = FmtBuf::<RECORD_MAX>::new();
+            {
+                let mut kmsg = KMSG.lock();
+                // Records overwritten before they were written out are lost
+                if kmsg.drained < kmsg.written.saturating_sub(KMSG_SIZE as u64) {
+                    kmsg.drained = kmsg.oldest();
+                }
+                if kmsg.drained >= kmsg.written {
+                    return;
+                }
+                let pos = kmsg.drained;
+                kmsg.drained = kmsg.record_at(pos, &mut record);
             }
-            LoggerMode::Runtime { sinks } => {
-                for sink in sinks.iter() {
-                    sink.write_str(s);
+            let Ok(s) = core::str::from_utf8(&record.buf[..record.pos]) else {
+                continue;
+            };
+            match &*mode {
+                LoggerMode::Boot => {
+                    boot_console().write_str(s);
+                }
+                LoggerMode::Runtime { sinks } => {
+                    for sink in sinks.iter() {
+                        sink.write_str(s);
+                    }
                 }
             }
         }
     }
-
-    fn flush(&self) {}
 }
 
 fn level_from_u8(v: u8) -> LevelFilter {
@@ -129,16 +362,25 @@ impl<const N: usize> FmtBuf<N> {
     }
 
     pub fn as_str(&self) -> &str {
-        // SAFETY: we only ever write valid UTF-8 (from &str slices)
+        // SAFETY: we only ever write valid UTF-8 (from &str slices, cut
+        // at character boundaries)
         unsafe { core::str::from_utf8_unchecked(&self.buf[..self.pos]) }
     }
+
+    /// Cut the contents to at most `len` bytes, at a character boundary
+    pub fn truncate(&mut self, len: usize) {
+        let s = self.as_str();
+        if len < s.len() {
+            self.pos = s.floor_char_boundary(len);
+        }
+    }
 }
 
 impl<const N: usize> Write for FmtBuf<N> {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
         let bytes = s.as_bytes();
         let space = N.saturating_sub(self.pos);
-        let n = bytes.len().min(space);
+        let n = s.floor_char_boundary(bytes.len().min(space));
         self.buf[self.pos..self.pos + n].copy_from_slice(&bytes[..n]);
         self.pos += n;
         Ok(())
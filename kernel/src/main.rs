@@ -3,19 +3,24 @@
 #![no_std]
 #![no_main]
 #![feature(alloc_error_handler)]
+#![feature(used_with_arg)]
 #![allow(dead_code, unused_imports)]
 extern crate alloc;
 
 mod arch;
+mod block;
 mod boot;
 mod fs;
 mod irq;
 mod kcore;
 mod logger;
 mod mm;
+mod net;
 mod process;
 mod subsystems;
+mod sync;
 mod syscall;
+mod time;
 
 use crate::arch::Irq;
 use crate::fs::FileSystem;
@@ -39,43 +44,63 @@ pub extern "C" fn kernel_main() -> ! {
     log::info!("Booting {} kernel", Platform::name());
     print_devices();
 
-    // Draw something
-    if let Some(fb_dev) = crate::subsystems::device_manager()
+    // Draw something, once the device manager is unlocked: it masks IRQs.
+    // Not over the kernel's output, if it goes to the framebuffer
+    let fb = crate::subsystems::device_manager()
         .lock()
-        .get("framebuffer")
-    {
-        use drivers::device_manager::Device;
-        if let Device::FrameBuffer(fb) = fb_dev {
-            let mut fb = fb.lock();
+        .framebuffer("framebuffer")
+        .filter(|_| !subsystems::log_sinks::FRAMEBUFFER_SINK.is_attached());
+    if let Some(fb) = fb {
+        let mut fb = fb.lock();
 
-            // Clear to dark blue
-            fb.clear(0x00001A);
+        // Clear to dark blue
+        fb.clear(0x00001A);
 
-            // White rectangle in the center
-            let cx = (fb.width() / 2 - 50) as u32;
-            let cy = (fb.height() / 2 - 50) as u32;
-            fb.draw_rect(cx, cy, 100, 100, 0xFFFFFF);
+        // White rectangle in the center
+        let cx = (fb.width() / 2 - 50) as u32;
+        let cy = (fb.height() / 2 - 50) as u32;
+        fb.draw_rect(cx, cy, 100, 100, 0xFFFFFF);
 
-            let width = fb.width() as u32;
-            let height = fb.height() as u32;
+        let width = fb.width() as u32;
+        let height = fb.height() as u32;
 
-            // Red horizontal line
-            fb.draw_hline(0, width - 1, height / 2, 0xFF0000);
+        // Red horizontal line
+        fb.draw_hline(0, width - 1, height / 2, 0xFF0000);
 
-            // Green vertical line
-            fb.draw_vline(width / 2, 0, height - 1, 0x00FF00);
-        }
+        // Green vertical line
+        fb.draw_vline(width / 2, 0, height - 1, 0x00FF00);
     }
 
+    start_init();
+
     kernel_main_loop();
 }
 
+// ============================================================================
+// Init
+// ============================================================================
+
+/// The first user program, which becomes PID 1, unless the command line
+/// names another with `init=`
+const INIT_PATH: &str = "/bin/init";
+
+/// Start init from the root filesystem
+fn start_init() {
+    let path = boot::cmdline().init().unwrap_or(INIT_PATH);
+    match process::elf::spawn_from_path(path, &[path.into()], &[]) {
+        Ok(pid) => log::info!("Started {} as PID {}", path, pid.0),
+        Err(e) => log::warn!("Could not start {}: {:?}", path, e),
+    }
+}
+
 // ============================================================================
 // Kernel Main Loop
 // ============================================================================
 
+/// The boot thread's work is done: it exits, leaving the CPU to the other
+/// tasks and the idle task
 fn kernel_main_loop() -> ! {
-    loop {}
+    process::sched::exit()
 }
 
 // ============================================================================
@@ -105,9 +130,18 @@ fn panic(info: &PanicInfo) -> ! {
             }
         }
 
-        let _ = write!(VgaPanic { col: 0 }, "PANIC: {}", info);
+        let mut vga = VgaPanic { col: 0 };
+        let _ = write!(vga, "PANIC: {}", info);
+        if let Some(Err(corruption)) = mm::heap_allocator::check_heap() {
+            let _ = write!(vga, " (heap: {:?})", corruption);
+        }
     }
 
+    kcore::panic::report(info);
+
+    // Nothing may preempt the code that panicked
+    Irq::disable();
+    kcore::panic::reboot_after_timeout();
     loop {
         core::hint::spin_loop();
     }
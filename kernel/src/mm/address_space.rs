@@ -0,0 +1,613 @@
+//! Per-process virtual address spaces.
+//!
+//! An [`AddressSpace`] owns a root page table that starts out as a copy of
+//! the kernel's mappings, plus the leaf tables created as user pages are
+//! mapped into it. Mappings are private to the address space: on ARM they
+//! are tagged with an ASID so switching does not flush the TLB, and an
+//! address space that could not get one flushes it instead.
+//!
+//! Slots the kernel maps with sections (ARM) or 4 MB pages (x86), and leaf
+//! tables shared with the kernel, cannot hold user pages; mapping over
+//! them fails with [`MapError::AlreadyMapped`].
+//!
+//! Besides fixed mappings made with [`AddressSpace::map`], an address space
+//! keeps a [`VmaList`] of anonymous areas whose pages are only allocated,
+//! zeroed and mapped when first touched. The architecture's
+//! page-fault handler resolves faults in the active address space through
+//! [`handle_fault`].
+//!
+//! [`AddressSpace::copy_to`] fills area pages from the kernel, as when a
+//! program image is loaded.
+//!
+//! The program break of [`AddressSpace::set_brk`] moves the end of one such
+//! area, the heap, which starts empty after the program image.
+//!
+//! [`AddressSpace::fork`] copies an address space without copying its
+//! area pages: both sides map them read-only and share them by reference
+//! count, and the first write from either side takes a private copy.
+
+use crate::mm::mmu::{LEAF_SPAN, MapFlags, PageTableOps, PlatformMmu, RootEntry};
+use crate::mm::page_allocator::{PAGE_SIZE, page_allocator};
+use crate::mm::page_table::{Page, PageBlock};
+use crate::mm::vma::{Vma, VmaList};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use spin::Mutex;
+
+const ROOT_ORDER: usize = <PlatformMmu as PageTableOps>::ROOT_ORDER;
+const ASID_COUNT: usize = <PlatformMmu as PageTableOps>::ASID_COUNT;
+
+/// Allocated ASIDs, one bit each; ASID 0 is never handed out
+static ASIDS: Mutex<[u64; 4]> = Mutex::new([1, 0, 0, 0]);
+
+/// Address space installed by the last [`AddressSpace::activate`]
+static ACTIVE: AtomicPtr<AddressSpace> = AtomicPtr::new(core::ptr::null_mut());
+
+fn alloc_asid() -> Option<u16> {
+    let mut asids = ASIDS.lock();
+    let asid = (1..ASID_COUNT).find(|&asid| asids[asid / 64] & (1 << (asid % 64)) == 0)?;
+    asids[asid / 64] |= 1 << (asid % 64);
+    Some(asid as u16)
+}
+
+fn free_asid(asid: u16) {
+    let asid = asid as usize;
+    ASIDS.lock()[asid / 64] &= !(1 << (asid % 64));
+}
+
+/// Address space errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// No memory for a page table
+    OutOfMemory,
+    /// Address or size not page aligned, or the range wraps
+    Unaligned,
+    /// Part of the range is already mapped
+    AlreadyMapped,
+    /// Outside the range the operation allows, such as a break below the
+    /// start of the heap or with no heap set up
+    OutOfRange,
+}
+
+/// Why a page fault could not be resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultError {
+    /// No address space is active
+    NoAddressSpace,
+    /// The address is not in any area
+    Unmapped,
+    /// The area does not allow the access, or the page is already
+    /// present and the fault was a genuine protection violation
+    AccessDenied,
+    /// No memory for the page or its leaf table
+    OutOfMemory,
+    /// The address space was being modified when the fault hit, so the
+    /// fault came from the kernel itself
+    Busy,
+}
+
+pub struct AddressSpace {
+    root: PageBlock<ROOT_ORDER>,
+    asid: Option<u16>,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    /// Physical address of the root table
+    root: usize,
+    asid: Option<u16>,
+    /// Leaf tables owned by this address space, by `va / LEAF_SPAN`
+    leaves: BTreeMap<usize, Page>,
+    /// Demand-paged areas
+    vmas: VmaList,
+    /// Pages allocated for areas, by virtual address. A page whose
+    /// count is above one is shared copy-on-write with another address
+    /// space and mapped read-only.
+    frames: BTreeMap<usize, Arc<Page>>,
+    heap: Option<Heap>,
+}
+
+/// Heap area bounds
+#[derive(Debug, Clone, Copy)]
+struct Heap {
+    start: usize,
+    /// Program break; the heap area ends at the page boundary above it
+    brk: usize,
+}
+
+/// Protection of heap pages
+const HEAP_FLAGS: MapFlags = MapFlags::USER
+    .union(MapFlags::READ)
+    .union(MapFlags::WRITE)
+    .union(MapFlags::CACHED);
+
+impl AddressSpace {
+    /// Create an address space containing only the kernel's mappings.
+    pub fn new() -> Result<Self, MapError> {
+        let root = page_allocator()
+            .alloc_block::<ROOT_ORDER>()
+            .ok_or(MapError::OutOfMemory)?;
+        unsafe {
+            PlatformMmu::init_root(root.addr());
+        }
+        let asid = alloc_asid();
+
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                root: root.addr(),
+                asid,
+                leaves: BTreeMap::new(),
+                vmas: VmaList::new(),
+                frames: BTreeMap::new(),
+                heap: None,
+            }),
+            root,
+            asid,
+        })
+    }
+
+    /// Physical address of the root table
+    pub fn root(&self) -> usize {
+        self.root.addr()
+    }
+
+    /// Hardware ASID, if one was available
+    pub fn asid(&self) -> Option<u16> {
+        self.asid
+    }
+
+    /// Map `size` bytes at `va` to physical memory at `pa`.
+    ///
+    /// Nothing is mapped if any page of the range is already in use or
+    /// belongs to an area.
+    pub fn map(&self, va: usize, pa: usize, size: usize, flags: MapFlags) -> Result<(), MapError> {
+        let end = check_range(va, size)?;
+        if !pa.is_multiple_of(PAGE_SIZE) {
+            return Err(MapError::Unaligned);
+        }
+
+        let mut inner = self.inner.lock();
+        if inner.vmas.overlaps(va, end) {
+            return Err(MapError::AlreadyMapped);
+        }
+        inner.check_unmapped(va, end)?;
+
+        for (page, phys) in (va..end).step_by(PAGE_SIZE).zip((pa..).step_by(PAGE_SIZE)) {
+            inner.set(page, PlatformMmu::leaf_entry(phys, flags))?;
+        }
+        Ok(())
+    }
+
+    /// Add a demand-zero area of `size` bytes at `start`. No memory is
+    /// allocated until the pages are touched.
+    pub fn add_vma(&self, start: usize, size: usize, flags: MapFlags) -> Result<(), MapError> {
+        let end = check_range(start, size)?;
+        self.inner.lock().add_vma(Vma { start, end, flags })
+    }
+
+    /// Add a demand-zero area of `size` bytes in the first gap of
+    /// `[low, high)` that can hold it, returning its start.
+    pub fn add_vma_anywhere(
+        &self,
+        size: usize,
+        low: usize,
+        high: usize,
+        flags: MapFlags,
+    ) -> Result<usize, MapError> {
+        check_range(low, size)?;
+        let mut inner = self.inner.lock();
+
+        // The gap may still hold fixed mappings or kernel slots; move past
+        // the clash and retry
+        let mut from = low;
+        loop {
+            let start = inner
+                .vmas
+                .find_free(size, from, high)
+                .ok_or(MapError::OutOfMemory)?;
+            let end = start + size;
+            match inner.add_vma(Vma { start, end, flags }) {
+                Err(MapError::AlreadyMapped) => from = start + PAGE_SIZE,
+                result => return result.map(|()| start),
+            }
+        }
+    }
+
+    /// Area containing `va`
+    pub fn vma(&self, va: usize) -> Option<Vma> {
+        self.inner.lock().vmas.find(va).copied()
+    }
+
+    /// Remove the mappings in `size` bytes at `va`, freeing pages that
+    /// were demand-allocated there and cutting the range out of any
+    /// area. Pages that are not mapped are skipped; leaf tables left
+    /// empty are freed.
+    pub fn unmap(&self, va: usize, size: usize) -> Result<(), MapError> {
+        let end = check_range(va, size)?;
+        self.inner.lock().unmap(va, end);
+        Ok(())
+    }
+
+    /// Start an empty heap at `start`, normally the first page after the
+    /// program image. Replaces any previous heap bounds.
+    pub fn init_heap(&self, start: usize) -> Result<(), MapError> {
+        check_range(start, 0)?;
+        self.inner.lock().heap = Some(Heap { start, brk: start });
+        Ok(())
+    }
+
+    /// Current program break, or `None` before [`Self::init_heap`]
+    pub fn brk(&self) -> Option<usize> {
+        self.inner.lock().heap.map(|heap| heap.brk)
+    }
+
+    /// Move the program break to `brk`, growing or shrinking the heap
+    /// area to the page boundary above it. Pages added to the heap read
+    /// as zero. Returns the new break.
+    pub fn set_brk(&self, brk: usize) -> Result<usize, MapError> {
+        let mut inner = self.inner.lock();
+        let heap = inner.heap.ok_or(MapError::OutOfRange)?;
+        if brk < heap.start {
+            return Err(MapError::OutOfRange);
+        }
+
+        let old_end = page_align_up(heap.brk).ok_or(MapError::OutOfRange)?;
+        let new_end = page_align_up(brk).ok_or(MapError::OutOfRange)?;
+        if new_end > old_end {
+            inner.add_vma(Vma {
+                start: old_end,
+                end: new_end,
+                flags: HEAP_FLAGS,
+            })?;
+        } else if new_end < old_end {
+            inner.unmap(new_end, old_end);
+        }
+
+        inner.heap = Some(Heap { brk, ..heap });
+        Ok(brk)
+    }
+
+    /// Resolve a fault at `va` in an area that allows the access: map a
+    /// zeroed page if none is present yet, or give a write to a shared
+    /// copy-on-write page its own writable copy.
+    pub fn handle_fault(&self, va: usize, write: bool) -> Result<(), FaultError> {
+        let mut inner = self.inner.try_lock().ok_or(FaultError::Busy)?;
+        let vma = *inner.vmas.find(va).ok_or(FaultError::Unmapped)?;
+        if !vma.flags.contains(MapFlags::READ) || write && !vma.flags.contains(MapFlags::WRITE) {
+            return Err(FaultError::AccessDenied);
+        }
+
+        let page = va & !(PAGE_SIZE - 1);
+        if !write && inner.frames.contains_key(&page) {
+            // Present and readable, so this was a protection fault
+            return Err(FaultError::AccessDenied);
+        }
+        inner
+            .private_frame(page, vma.flags)
+            .map_err(|_| FaultError::OutOfMemory)?;
+        Ok(())
+    }
+
+    /// Check that user code may access every byte of the `len` bytes at
+    /// `va`, writing them if `write`: they must all lie in areas that
+    /// allow it.
+    pub fn check_user(&self, va: usize, len: usize, write: bool) -> Result<(), FaultError> {
+        let end = va.checked_add(len).ok_or(FaultError::Unmapped)?;
+        let mut needed = MapFlags::USER | MapFlags::READ;
+        if write {
+            needed |= MapFlags::WRITE;
+        }
+
+        let inner = self.inner.lock();
+        let mut at = va;
+        while at < end {
+            let vma = inner.vmas.find(at).ok_or(FaultError::Unmapped)?;
+            if !vma.flags.contains(needed) {
+                return Err(FaultError::AccessDenied);
+            }
+            at = vma.end;
+        }
+        Ok(())
+    }
+
+    /// Physical address of `va` in an area the user may write, once its
+    /// page has a private frame, as after a write fault. The address then
+    /// holds until the page is unmapped.
+    pub fn private_address(&self, va: usize) -> Result<usize, FaultError> {
+        let mut inner = self.inner.lock();
+        let vma = *inner.vmas.find(va).ok_or(FaultError::Unmapped)?;
+        if !vma.flags.contains(MapFlags::USER | MapFlags::WRITE) {
+            return Err(FaultError::AccessDenied);
+        }
+
+        let page = va & !(PAGE_SIZE - 1);
+        let frame = match inner.frames.get(&page) {
+            Some(frame) if Arc::strong_count(frame) == 1 => frame.addr(),
+            _ => inner
+                .private_frame(page, vma.flags)
+                .map_err(|_| FaultError::OutOfMemory)?,
+        };
+        Ok(frame + (va - page))
+    }
+
+    /// Copy `data` into the areas at `va`, allocating their pages as
+    /// needed.
+    ///
+    /// The copy goes through physical memory, so it works whether or not
+    /// this address space is active and whatever the areas' protection;
+    /// it is how program images are loaded. Fails with `OutOfRange` at
+    /// the first byte outside every area, leaving earlier bytes copied.
+    pub fn copy_to(&self, va: usize, data: &[u8]) -> Result<(), MapError> {
+        let end = va.checked_add(data.len()).ok_or(MapError::OutOfRange)?;
+        let mut inner = self.inner.lock();
+
+        let mut addr = va;
+        while addr < end {
+            let vma = *inner.vmas.find(addr).ok_or(MapError::OutOfRange)?;
+            let page = addr & !(PAGE_SIZE - 1);
+            let frame = inner.private_frame(page, vma.flags)?;
+            let offset = addr - page;
+            let len = (PAGE_SIZE - offset).min(end - addr);
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    data[addr - va..].as_ptr(),
+                    (frame + offset) as *mut u8,
+                    len,
+                );
+            }
+            addr += len;
+        }
+        Ok(())
+    }
+
+    /// Copy this address space for a child process.
+    ///
+    /// Areas are copied and their pages shared copy-on-write: they are
+    /// made read-only here as well as in the copy. Fixed mappings are
+    /// shared as they are.
+    pub fn fork(&self) -> Result<Self, MapError> {
+        let child = Self::new()?;
+        {
+            let parent = self.inner.lock();
+            let mut copy = child.inner.lock();
+            copy.vmas = parent.vmas.clone();
+            copy.heap = parent.heap;
+
+            for (&slot, leaf) in &parent.leaves {
+                for va in (slot * LEAF_SPAN..(slot + 1) * LEAF_SPAN).step_by(PAGE_SIZE) {
+                    let entry = slot_in(leaf, va);
+                    let value = unsafe { entry.read_volatile() };
+                    if value == 0 {
+                        continue;
+                    }
+
+                    let Some(frame) = parent.frames.get(&va) else {
+                        copy.set(va, value)?;
+                        continue;
+                    };
+                    let flags = parent.vmas.find(va).map_or(MapFlags::READ, |vma| vma.flags);
+                    let shared = PlatformMmu::leaf_entry(frame.addr(), flags - MapFlags::WRITE);
+                    unsafe {
+                        PlatformMmu::write_entry(entry, shared);
+                        PlatformMmu::invalidate_page(va, self.asid);
+                    }
+                    copy.set(va, shared)?;
+                    copy.frames.insert(va, Arc::clone(frame));
+                }
+            }
+        }
+        Ok(child)
+    }
+
+    /// Make this the current user address space.
+    ///
+    /// # Safety
+    /// The address space must stay alive, and must not be dropped, while
+    /// it is current.
+    pub unsafe fn activate(&self) {
+        ACTIVE.store(self as *const Self as *mut Self, Ordering::Release);
+        unsafe {
+            PlatformMmu::switch_to(self.root(), self.asid);
+        }
+    }
+}
+
+impl Inner {
+    /// Map the area page at `page` with `flags` to a frame no other
+    /// address space shares: a zeroed one if none is present yet, or a
+    /// copy of one still shared copy-on-write. Returns its physical
+    /// address.
+    fn private_frame(&mut self, page: usize, flags: MapFlags) -> Result<usize, MapError> {
+        let frame = match self.frames.get(&page) {
+            None => Arc::new(page_allocator().alloc().ok_or(MapError::OutOfMemory)?),
+            // The other sharers went away; the page is ours to write
+            Some(frame) if Arc::strong_count(frame) == 1 => Arc::clone(frame),
+            Some(frame) => {
+                let copy = page_allocator().alloc().ok_or(MapError::OutOfMemory)?;
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        frame.addr() as *const u8,
+                        copy.addr() as *mut u8,
+                        PAGE_SIZE,
+                    );
+                }
+                Arc::new(copy)
+            }
+        };
+
+        let addr = frame.addr();
+        self.set(page, PlatformMmu::leaf_entry(addr, flags))?;
+        unsafe {
+            PlatformMmu::invalidate_page(page, self.asid);
+        }
+        // Replacing a shared page drops this side's reference to it
+        self.frames.insert(page, frame);
+        Ok(addr)
+    }
+
+    /// Unmap `[va, end)`; see [`AddressSpace::unmap`]
+    fn unmap(&mut self, va: usize, end: usize) {
+        if va == end {
+            return;
+        }
+
+        for page in (va..end).step_by(PAGE_SIZE) {
+            let Ok(Some(entry)) = self.entry(page) else {
+                continue;
+            };
+            unsafe {
+                if entry.read_volatile() != 0 {
+                    PlatformMmu::write_entry(entry, 0);
+                    PlatformMmu::invalidate_page(page, self.asid);
+                }
+            }
+        }
+
+        // Free the pages only now that nothing maps them
+        let freed: Vec<usize> = self.frames.range(va..end).map(|(&page, _)| page).collect();
+        for page in freed {
+            self.frames.remove(&page);
+        }
+
+        self.vmas.remove(va, end);
+        self.free_empty_leaves(va / LEAF_SPAN, (end - 1) / LEAF_SPAN);
+    }
+
+    fn add_vma(&mut self, vma: Vma) -> Result<(), MapError> {
+        if self.vmas.overlaps(vma.start, vma.end) {
+            return Err(MapError::AlreadyMapped);
+        }
+        self.check_unmapped(vma.start, vma.end)?;
+        self.vmas.insert(vma);
+        Ok(())
+    }
+
+    /// Fail if any page of `[start, end)` is mapped or in a kernel slot
+    fn check_unmapped(&self, start: usize, end: usize) -> Result<(), MapError> {
+        for page in (start..end).step_by(PAGE_SIZE) {
+            if self
+                .entry(page)?
+                .is_some_and(|entry| unsafe { entry.read_volatile() } != 0)
+            {
+                return Err(MapError::AlreadyMapped);
+            }
+        }
+        Ok(())
+    }
+
+    /// Store the leaf entry `value` for the page at `va`
+    fn set(&mut self, va: usize, value: u32) -> Result<(), MapError> {
+        let entry = self.leaf_slot(va)?;
+        unsafe {
+            PlatformMmu::write_entry(entry, value);
+        }
+        Ok(())
+    }
+
+    /// Leaf entry for `va` in a table this address space owns, `None` if
+    /// the slot is still empty, or `AlreadyMapped` if the kernel owns it.
+    fn entry(&self, va: usize) -> Result<Option<*mut u32>, MapError> {
+        if let Some(leaf) = self.leaves.get(&(va / LEAF_SPAN)) {
+            return Ok(Some(slot_in(leaf, va)));
+        }
+        match unsafe { PlatformMmu::root_entry(self.root, va) } {
+            RootEntry::Empty => Ok(None),
+            RootEntry::Table(_) | RootEntry::Block => Err(MapError::AlreadyMapped),
+        }
+    }
+
+    /// Leaf entry for `va`, creating its leaf table if needed
+    fn leaf_slot(&mut self, va: usize) -> Result<*mut u32, MapError> {
+        let slot = va / LEAF_SPAN;
+        if !self.leaves.contains_key(&slot) {
+            // Refuse slots the kernel owns
+            self.entry(va)?;
+            let leaf = page_allocator().alloc().ok_or(MapError::OutOfMemory)?;
+            unsafe {
+                PlatformMmu::set_root_entry(self.root, va, leaf.addr());
+            }
+            self.leaves.insert(slot, leaf);
+        }
+        Ok(slot_in(&self.leaves[&slot], va))
+    }
+
+    /// Free leaf tables in slots `first..=last` that map nothing
+    fn free_empty_leaves(&mut self, first: usize, last: usize) {
+        let empty: Vec<usize> = self
+            .leaves
+            .range(first..=last)
+            .filter(|(_, leaf)| is_empty_leaf(leaf))
+            .map(|(&slot, _)| slot)
+            .collect();
+        for slot in empty {
+            unsafe {
+                PlatformMmu::clear_root_entry(self.root, slot * LEAF_SPAN);
+            }
+            self.leaves.remove(&slot);
+        }
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        let _ = ACTIVE.compare_exchange(
+            self as *mut Self,
+            core::ptr::null_mut(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+
+        // Tables and pages are freed with the fields; only the TLB and
+        // ASID need care
+        if let Some(asid) = self.asid {
+            unsafe {
+                PlatformMmu::invalidate_asid(asid);
+            }
+            free_asid(asid);
+        }
+    }
+}
+
+/// End of the range, checking alignment
+fn check_range(va: usize, size: usize) -> Result<usize, MapError> {
+    if !va.is_multiple_of(PAGE_SIZE) || !size.is_multiple_of(PAGE_SIZE) {
+        return Err(MapError::Unaligned);
+    }
+    va.checked_add(size).ok_or(MapError::Unaligned)
+}
+
+fn page_align_up(addr: usize) -> Option<usize> {
+    addr.checked_next_multiple_of(PAGE_SIZE)
+}
+
+fn slot_in(leaf: &Page, va: usize) -> *mut u32 {
+    (leaf.addr() as *mut u32).wrapping_add((va >> 12) & 0x3FF)
+}
+
+fn is_empty_leaf(leaf: &Page) -> bool {
+    let entries = leaf.addr() as *const u32;
+    (0..PAGE_SIZE / 4).all(|i| unsafe { entries.add(i).read_volatile() } == 0)
+}
+
+/// Check that user code may access the `len` bytes at `va` in the active
+/// address space; see [`AddressSpace::check_user`].
+pub fn check_user(va: usize, len: usize, write: bool) -> Result<(), FaultError> {
+    let active = ACTIVE.load(Ordering::Acquire);
+    // SAFETY: as in `handle_fault`
+    let space = unsafe { active.as_ref() }.ok_or(FaultError::NoAddressSpace)?;
+    space.check_user(va, len, write)
+}
+
+/// Resolve a page fault at `va` in the active address space.
+///
+/// Called from the architecture's abort handler, which reports the
+/// access as fatal if this fails.
+pub fn handle_fault(va: usize, write: bool) -> Result<(), FaultError> {
+    let active = ACTIVE.load(Ordering::Acquire);
+    // SAFETY: `activate` requires the address space to outlive its time
+    // as the active one, and `drop` clears it
+    let space = unsafe { active.as_ref() }.ok_or(FaultError::NoAddressSpace)?;
+    space.handle_fault(va, write)
+}
@@ -6,24 +6,129 @@ use core::{
 /// Maximum supported order for buddy allocator (2^MAX_ORDER * min_block_size max block size)
 pub const MAX_ORDER: usize = 10;
 
+/// [`BlockHeader::magic`] of a live allocation
+const ALLOCATED_MAGIC: u16 = 0xA110;
+
+/// [`FreeBlock::magic`] of a block on a free list
+const FREE_MAGIC: u32 = 0xF4EE_B10C;
+
+/// Bytes after each allocation filled with [`RED_ZONE_BYTE`] in debug builds
+#[cfg(debug_assertions)]
+const RED_ZONE_SIZE: usize = 8;
+#[cfg(not(debug_assertions))]
+const RED_ZONE_SIZE: usize = 0;
+
+#[cfg(debug_assertions)]
+const RED_ZONE_BYTE: u8 = 0xFD;
+
 /// Free block in the buddy allocator's free list
 #[repr(C)]
 struct FreeBlock {
     next: *mut FreeBlock,
+    /// [`FREE_MAGIC`], checked by [`BuddyAllocator::check_consistency`]
+    magic: u32,
 }
 
-/// Header stored before each allocated block in the heap
+/// Header stored right before the pointer returned by
+/// [`BuddyAllocator::alloc`]
 #[repr(C, align(8))]
 struct BlockHeader {
+    /// [`ALLOCATED_MAGIC`] until the block is freed
+    magic: u16,
     /// Order of the allocated block (power-of-two)
     order: u8,
+    /// Bytes from the start of the block to the user pointer
+    offset: u32,
+    /// Requested size, locating the red zone
+    #[cfg(debug_assertions)]
+    size: usize,
+}
+
+/// Damage found by [`BuddyAllocator::check_consistency`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+    /// A free list entry lies outside the managed memory
+    OutOfBounds { order: usize, addr: usize },
+    /// A free list entry is not aligned to its block size
+    Misaligned { order: usize, addr: usize },
+    /// A free block was written to
+    BadMagic { order: usize, addr: usize },
+    /// A free list loops back on itself
+    Cycle { order: usize },
+    /// Free, allocated and reserved bytes do not add up to the managed size
+    Accounting {
+        free: usize,
+        used: usize,
+        reserved: usize,
+        total: usize,
+    },
+}
+
+/// Usage snapshot of a [`BuddyAllocator`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocatorStats {
+    /// Bytes under management
+    pub total_bytes: usize,
+    /// Bytes in allocated blocks, including headers and rounding
+    pub used_bytes: usize,
+    /// Bytes on the free lists
+    pub free_bytes: usize,
+    /// Highest `used_bytes` seen since initialization
+    pub peak_used_bytes: usize,
+    /// Bytes taken out of use by [`BuddyAllocator::reserve`]
+    pub reserved_bytes: usize,
+    /// Largest block that can be handed out in one piece
+    pub largest_free: usize,
+    /// Size of an order 0 block
+    pub min_block_size: usize,
+    /// Length of the free list of each order
+    pub free_blocks: [usize; MAX_ORDER + 1],
+    /// Successful allocations since initialization
+    pub allocations: u64,
+    /// Blocks freed since initialization
+    pub frees: u64,
+    /// Allocations that could not be satisfied
+    pub failed_allocations: u64,
+}
+
+impl AllocatorStats {
+    /// Blocks currently allocated
+    pub fn live_allocations(&self) -> u64 {
+        self.allocations - self.frees
+    }
+
+    /// Usage of two allocators together. The peak is the sum of both peaks,
+    /// which may not have been reached at the same time.
+    pub fn combine(self, other: Self) -> Self {
+        let mut free_blocks = self.free_blocks;
+        for (count, other) in free_blocks.iter_mut().zip(other.free_blocks) {
+            *count += other;
+        }
+
+        Self {
+            total_bytes: self.total_bytes + other.total_bytes,
+            used_bytes: self.used_bytes + other.used_bytes,
+            free_bytes: self.free_bytes + other.free_bytes,
+            peak_used_bytes: self.peak_used_bytes + other.peak_used_bytes,
+            reserved_bytes: self.reserved_bytes + other.reserved_bytes,
+            largest_free: self.largest_free.max(other.largest_free),
+            min_block_size: self.min_block_size.min(other.min_block_size),
+            free_blocks,
+            allocations: self.allocations + other.allocations,
+            frees: self.frees + other.frees,
+            failed_allocations: self.failed_allocations + other.failed_allocations,
+        }
+    }
 }
 
 /// A general-purpose buddy allocator for heap memory.
 ///
 /// The allocator splits memory into blocks of size `2^order * min_block_size`.
 /// Each allocated block stores a `BlockHeader` before the user-visible memory
-/// so that `free` can retrieve the order and merge buddies.
+/// so that `free` can retrieve the order and merge buddies. The header's
+/// magic value catches double frees and pointers that did not come from
+/// `alloc`; debug builds also fill a red zone after each allocation and
+/// check it on free.
 ///
 /// # Safety
 /// All methods are `unsafe` because the allocator assumes exclusive access
@@ -40,6 +145,20 @@ pub struct BuddyAllocator {
 
     /// Minimum allocatable block size
     min_block_size: usize,
+
+    /// Bytes currently handed out
+    used_bytes: usize,
+
+    /// High-water mark of `used_bytes`
+    peak_used_bytes: usize,
+
+    /// Bytes removed from the free lists by `reserve`
+    reserved_bytes: usize,
+
+    /// Counters reported by [`Self::stats`]
+    allocations: u64,
+    frees: u64,
+    failed_allocations: u64,
 }
 
 impl BuddyAllocator {
@@ -52,6 +171,12 @@ impl BuddyAllocator {
             base_addr: 0,
             total_size: 0,
             min_block_size,
+            used_bytes: 0,
+            peak_used_bytes: 0,
+            reserved_bytes: 0,
+            allocations: 0,
+            frees: 0,
+            failed_allocations: 0,
         }
     }
 
@@ -61,6 +186,24 @@ impl BuddyAllocator {
     /// - Caller must ensure this memory range is not used elsewhere.
     /// - Memory should be aligned to `min_block_size`.
     pub unsafe fn init(&mut self, start_addr: usize, end_addr: usize) {
+        unsafe { self.init_reserved(start_addr, end_addr, &[]) }
+    }
+
+    /// Initializes the allocator over a contiguous memory range, keeping
+    /// the `reserved` ranges in it, widened to whole blocks, off the free
+    /// lists from the start. Unlike [`Self::reserve`] this writes nothing
+    /// into them, so they may hold data that is still needed.
+    ///
+    /// # Safety
+    /// - Caller must ensure the range outside `reserved` is not used
+    ///   elsewhere.
+    /// - Memory should be aligned to `min_block_size`.
+    pub unsafe fn init_reserved(
+        &mut self,
+        start_addr: usize,
+        end_addr: usize,
+        reserved: &[(usize, usize)],
+    ) {
         let start = (start_addr + self.min_block_size - 1) & !(self.min_block_size - 1);
         let end = end_addr & !(self.min_block_size - 1);
 
@@ -71,28 +214,104 @@ impl BuddyAllocator {
             self.free_lists[i] = ptr::null_mut();
         }
 
+        let block_mask = self.min_block_size - 1;
+        let (low, high) = (start, end);
+        let reserved = || {
+            reserved.iter().map(move |&(start, end)| {
+                let start = (start & !block_mask).max(low);
+                let end = (end.saturating_add(block_mask) & !block_mask).min(high);
+                (start, end.max(start))
+            })
+        };
+        let mut free = 0;
         let mut current = start;
-        while current + self.min_block_size <= end {
-            let remaining = end - current;
-            let mut order = MAX_ORDER;
-            while order > 0 {
-                let block_size = self.min_block_size << order;
-                if remaining >= block_size && (current & (block_size - 1)) == 0 {
-                    unsafe {
-                        self.add_to_free_list(current, order);
-                    }
-                    current += block_size;
-                    break;
-                }
-                order -= 1;
+        while current < end {
+            // Skip past every reserved range covering `current`
+            if let Some(skip) = reserved()
+                .filter(|&(start, end)| start <= current && current < end)
+                .map(|(_, end)| end)
+                .max()
+            {
+                current = skip;
+                continue;
             }
 
-            if order == 0 && remaining >= self.min_block_size {
+            let next = reserved()
+                .map(|(start, _)| start)
+                .filter(|&start| start > current)
+                .min()
+                .unwrap_or(end);
+            unsafe {
+                self.add_range(current, next);
+            }
+            free += next - current;
+            current = next;
+        }
+        self.reserved_bytes = self.total_size - free;
+    }
+
+    /// Takes `[start, end)`, widened to whole blocks and clipped to the
+    /// managed memory, off the free lists for good, e.g. for firmware
+    /// data or a framebuffer inside RAM.
+    ///
+    /// Returns `false` if part of the range was already allocated; that
+    /// part stays with its owner and is not reserved.
+    pub fn reserve(&mut self, start: usize, end: usize) -> bool {
+        let start = (start & !(self.min_block_size - 1)).max(self.base_addr);
+        let end = end
+            .saturating_add(self.min_block_size - 1)
+            .min(self.base_addr + self.total_size)
+            & !(self.min_block_size - 1);
+        if start >= end {
+            return true;
+        }
+
+        let mut reserved = 0;
+        for order in 0..=MAX_ORDER {
+            let block_size = self.min_block_size << order;
+            // Blocks outside the range go back on the lists at lower orders
+            // as they are split, so search again after each one
+            while let Some(block) =
+                self.find_free(order, |block| block < end && block + block_size > start)
+            {
                 unsafe {
-                    self.add_to_free_list(current, 0);
+                    self.remove_specific_from_free_list(block, order);
+                    self.add_range(block, start.max(block));
+                    self.add_range(end.min(block + block_size), block + block_size);
                 }
-                current += self.min_block_size;
+                reserved += end.min(block + block_size) - start.max(block);
+            }
+        }
+
+        self.reserved_bytes += reserved;
+        reserved == end - start
+    }
+
+    /// Puts the whole blocks of a reserved `[start, end)` back on the free
+    /// lists, e.g. once the data the bootloader left there is copied out.
+    ///
+    /// # Safety
+    /// - The range must have been reserved and no longer be in use.
+    pub unsafe fn release(&mut self, start: usize, end: usize) {
+        let block_mask = self.min_block_size - 1;
+        let mut current = ((start + block_mask) & !block_mask).max(self.base_addr);
+        let end = (end & !block_mask).min(self.base_addr + self.total_size);
+
+        while current < end {
+            // The largest aligned block that fits, merged with its buddies
+            let order = (0..=MAX_ORDER)
+                .rev()
+                .find(|&order| {
+                    let block_size = self.min_block_size << order;
+                    current & (block_size - 1) == 0 && current + block_size <= end
+                })
+                .unwrap_or(0);
+            let block_size = self.min_block_size << order;
+            self.reserved_bytes = self.reserved_bytes.saturating_sub(block_size);
+            unsafe {
+                self.insert_block(current, order);
             }
+            current += block_size;
         }
     }
 
@@ -113,25 +332,30 @@ impl BuddyAllocator {
         let header_size = core::mem::size_of::<BlockHeader>();
         let header_size = (header_size + align - 1) & !(align - 1);
 
-        let total_size = layout.size() + header_size;
-
-        let mut order = 0;
-        let mut block_size = self.min_block_size;
-
-        while block_size < total_size {
-            order += 1;
-            block_size <<= 1;
-        }
+        let order = self.order_for(layout.size() + header_size + RED_ZONE_SIZE);
 
         unsafe {
             let addr = self.alloc_block_order(order)?;
-            let header_ptr = addr as *mut BlockHeader;
-            (*header_ptr).order = order as u8;
-
             let user_ptr = addr + header_size;
 
             debug_assert!(user_ptr % align == 0, "Non-aligned allocation returned");
 
+            let header_ptr = (user_ptr - core::mem::size_of::<BlockHeader>()) as *mut BlockHeader;
+            header_ptr.write(BlockHeader {
+                magic: ALLOCATED_MAGIC,
+                order: order as u8,
+                offset: header_size as u32,
+                #[cfg(debug_assertions)]
+                size: layout.size(),
+            });
+
+            #[cfg(debug_assertions)]
+            ptr::write_bytes(
+                (user_ptr + layout.size()) as *mut u8,
+                RED_ZONE_BYTE,
+                RED_ZONE_SIZE,
+            );
+
             Some(NonNull::new_unchecked(user_ptr as *mut u8))
         }
     }
@@ -141,19 +365,185 @@ impl BuddyAllocator {
     /// # Safety
     /// - `ptr` must have been returned by a prior `alloc` call.
     /// - Must not be double-freed.
+    ///
+    /// # Panics
+    /// Panics if the header before `ptr` is not that of a live allocation,
+    /// or, in debug builds, if the red zone after it was overwritten.
     pub unsafe fn free(&mut self, ptr: *mut u8) {
         if ptr.is_null() {
             return;
         }
 
         let header_addr = (ptr as usize) - core::mem::size_of::<BlockHeader>();
+        assert!(
+            self.contains(header_addr),
+            "free of {:p} outside the heap",
+            ptr
+        );
 
         unsafe {
-            let header = &*(header_addr as *const BlockHeader);
+            let header = &mut *(header_addr as *mut BlockHeader);
+            assert!(
+                header.magic == ALLOCATED_MAGIC,
+                "double free or corrupt header at {:p}",
+                ptr
+            );
+            header.magic = 0;
+
+            #[cfg(debug_assertions)]
+            {
+                let red_zone = core::slice::from_raw_parts(ptr.add(header.size), RED_ZONE_SIZE);
+                assert!(
+                    red_zone.iter().all(|&byte| byte == RED_ZONE_BYTE),
+                    "write past the end of the {} byte allocation at {:p}",
+                    header.size,
+                    ptr
+                );
+            }
+
             let order = header.order as usize;
+            let block = ptr as usize - header.offset as usize;
+            self.free_block(block, order);
+        }
+    }
+
+    /// Resizes the allocation at `ptr` to `new_size` bytes without moving
+    /// it: shrinking returns the upper halves of the block to the free
+    /// lists, growing absorbs the following buddies if they are all free.
+    ///
+    /// Returns `false`, leaving the allocation untouched, if it cannot grow
+    /// in place.
+    ///
+    /// # Safety
+    /// `ptr` must be a live allocation returned by `alloc`.
+    ///
+    /// # Panics
+    /// Panics if the header before `ptr` is not that of a live allocation.
+    pub unsafe fn resize_in_place(&mut self, ptr: *mut u8, new_size: usize) -> bool {
+        let header_addr = (ptr as usize) - core::mem::size_of::<BlockHeader>();
+        let header = unsafe { &mut *(header_addr as *mut BlockHeader) };
+        assert!(
+            header.magic == ALLOCATED_MAGIC,
+            "realloc of a freed or corrupt allocation at {:p}",
+            ptr
+        );
+
+        let block = ptr as usize - header.offset as usize;
+        let old_order = header.order as usize;
+        let new_order = self.order_for(header.offset as usize + new_size.max(1) + RED_ZONE_SIZE);
+
+        if new_order > old_order {
+            if !unsafe { self.grow_block(block, old_order, new_order) } {
+                return false;
+            }
+        } else {
+            for order in (new_order..old_order).rev() {
+                unsafe {
+                    self.add_to_free_list(block + (self.min_block_size << order), order);
+                }
+            }
+        }
+
+        let old_size = self.min_block_size << old_order;
+        let new_block_size = self.min_block_size << new_order;
+        self.used_bytes = self.used_bytes - old_size + new_block_size;
+        self.peak_used_bytes = self.peak_used_bytes.max(self.used_bytes);
+        header.order = new_order as u8;
+
+        #[cfg(debug_assertions)]
+        unsafe {
+            header.size = new_size;
+            ptr::write_bytes(ptr.add(new_size), RED_ZONE_BYTE, RED_ZONE_SIZE);
+        }
+        true
+    }
+
+    /// Walk the free lists and report current usage.
+    pub fn stats(&self) -> AllocatorStats {
+        let mut free_bytes = 0;
+        let mut largest_free = 0;
+        let mut free_blocks = [0; MAX_ORDER + 1];
+
+        for (order, (&head, count)) in self.free_lists.iter().zip(&mut free_blocks).enumerate() {
+            let block_size = self.min_block_size << order;
+            let mut block = head;
+            while !block.is_null() {
+                free_bytes += block_size;
+                largest_free = block_size;
+                *count += 1;
+                // SAFETY: free list entries always point into managed memory
+                block = unsafe { (*block).next };
+            }
+        }
+
+        AllocatorStats {
+            total_bytes: self.total_size,
+            used_bytes: self.used_bytes,
+            free_bytes,
+            peak_used_bytes: self.peak_used_bytes,
+            reserved_bytes: self.reserved_bytes,
+            largest_free,
+            min_block_size: self.min_block_size,
+            free_blocks,
+            allocations: self.allocations,
+            frees: self.frees,
+            failed_allocations: self.failed_allocations,
+        }
+    }
+
+    /// Whether `addr` lies in the memory this allocator manages
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.base_addr && addr - self.base_addr < self.total_size
+    }
+
+    /// Walk the free lists and check that every entry is an aligned block
+    /// inside the managed memory that still carries its free marker, that
+    /// no list loops, and that free and allocated bytes add up.
+    ///
+    /// Entries are bounds-checked before they are followed, so this is
+    /// safe to call on a corrupted allocator.
+    pub fn check_consistency(&self) -> Result<(), Corruption> {
+        let mut free = 0;
+
+        for (order, &head) in self.free_lists.iter().enumerate() {
+            let block_size = self.min_block_size << order;
+            // More entries than fit in the managed memory means a loop
+            let max_blocks = self.total_size / block_size;
+            let mut count = 0;
+
+            let mut block = head;
+            while !block.is_null() {
+                let addr = block as usize;
+                if !self.contains(addr) || addr - self.base_addr + block_size > self.total_size {
+                    return Err(Corruption::OutOfBounds { order, addr });
+                }
+                if !addr.is_multiple_of(block_size) {
+                    return Err(Corruption::Misaligned { order, addr });
+                }
+                // SAFETY: checked to be inside managed memory above
+                let entry = unsafe { &*block };
+                if entry.magic != FREE_MAGIC {
+                    return Err(Corruption::BadMagic { order, addr });
+                }
+
+                count += 1;
+                if count > max_blocks {
+                    return Err(Corruption::Cycle { order });
+                }
+                free += block_size;
+                block = entry.next;
+            }
+        }
 
-            self.free_block(header_addr, order);
+        if free + self.used_bytes + self.reserved_bytes != self.total_size {
+            return Err(Corruption::Accounting {
+                free,
+                used: self.used_bytes,
+                reserved: self.reserved_bytes,
+                total: self.total_size,
+            });
         }
+        Ok(())
     }
 
     /* ---------------- Block-level alloc/free ---------------- */
@@ -190,28 +580,16 @@ impl BuddyAllocator {
     /// - `Some(addr)` containing the base address of the allocated block.
     /// - `None` if no suitable block can be allocated.
     pub(in crate::mm) unsafe fn alloc_block_order(&mut self, order: usize) -> Option<usize> {
-        if order > MAX_ORDER {
-            return None;
-        }
-
-        if !self.free_lists[order].is_null() {
-            return Some(unsafe { self.remove_from_free_list(order) });
-        }
-
-        for higher_order in (order + 1)..=MAX_ORDER {
-            if !self.free_lists[higher_order].is_null() {
-                let block = unsafe { self.remove_from_free_list(higher_order) };
-                for split_order in ((order + 1)..=higher_order).rev() {
-                    let buddy = block + (self.min_block_size << (split_order - 1));
-                    unsafe {
-                        self.add_to_free_list(buddy, split_order - 1);
-                    }
-                }
-                return Some(block);
+        let block = unsafe { self.take_block(order) };
+        match block {
+            Some(_) => {
+                self.allocations += 1;
+                self.used_bytes += self.min_block_size << order;
+                self.peak_used_bytes = self.peak_used_bytes.max(self.used_bytes);
             }
+            None => self.failed_allocations += 1,
         }
-
-        None
+        block
     }
 
     /// Frees a block of memory at `addr` of the specified `order`.
@@ -232,6 +610,18 @@ impl BuddyAllocator {
             return;
         }
 
+        self.frees += 1;
+        self.used_bytes = self.used_bytes.saturating_sub(self.min_block_size << order);
+
+        unsafe {
+            self.insert_block(addr, order);
+        }
+    }
+
+    /* ---------------- Internal helpers ---------------- */
+
+    /// Puts a block on the free lists, merged with its free buddies
+    unsafe fn insert_block(&mut self, addr: usize, order: usize) {
         let mut current_addr = addr;
         let mut current_order = order;
 
@@ -256,13 +646,125 @@ impl BuddyAllocator {
         }
     }
 
-    /* ---------------- Internal helpers ---------------- */
+    /// Smallest order whose blocks hold `size` bytes, possibly above
+    /// `MAX_ORDER`
+    fn order_for(&self, size: usize) -> usize {
+        let mut order = 0;
+        let mut block_size = self.min_block_size;
+
+        while block_size < size {
+            order += 1;
+            block_size <<= 1;
+        }
+        order
+    }
+
+    /// Merges the allocated block at `addr` with the buddies following it
+    /// up to `new_order`, if `addr` is the lower half at every level and
+    /// all those buddies are free. Returns whether it did.
+    unsafe fn grow_block(&mut self, addr: usize, order: usize, new_order: usize) -> bool {
+        if new_order > MAX_ORDER {
+            return false;
+        }
+
+        let mergeable = (order..new_order).all(|order| {
+            let block_size = self.min_block_size << order;
+            let buddy = addr + block_size;
+            addr & block_size == 0 && self.contains(buddy) && self.is_free(buddy, order)
+        });
+        if !mergeable {
+            return false;
+        }
+
+        for order in order..new_order {
+            unsafe {
+                self.remove_specific_from_free_list(addr + (self.min_block_size << order), order);
+            }
+        }
+        true
+    }
+
+    /// Whether the block at `addr` is on the free list of `order`
+    fn is_free(&self, addr: usize, order: usize) -> bool {
+        self.find_free(order, |block| block == addr).is_some()
+    }
+
+    /// First block on the free list of `order` that `pred` accepts
+    fn find_free(&self, order: usize, pred: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut block = self.free_lists[order];
+        while !block.is_null() {
+            if pred(block as usize) {
+                return Some(block as usize);
+            }
+            // SAFETY: free list entries always point into managed memory
+            block = unsafe { (*block).next };
+        }
+        None
+    }
+
+    /// Puts `[start, end)` on the free lists in the largest aligned blocks
+    /// that fit. Both ends must be multiples of `min_block_size`.
+    unsafe fn add_range(&mut self, start: usize, end: usize) {
+        let mut current = start;
+        while current + self.min_block_size <= end {
+            let remaining = end - current;
+            let mut order = MAX_ORDER;
+            while order > 0 {
+                let block_size = self.min_block_size << order;
+                if remaining >= block_size && (current & (block_size - 1)) == 0 {
+                    unsafe {
+                        self.add_to_free_list(current, order);
+                    }
+                    current += block_size;
+                    break;
+                }
+                order -= 1;
+            }
+
+            if order == 0 && remaining >= self.min_block_size {
+                unsafe {
+                    self.add_to_free_list(current, 0);
+                }
+                current += self.min_block_size;
+            }
+        }
+    }
+
+    /// Takes a block of `order` off the free lists, splitting a larger one
+    /// if needed
+    unsafe fn take_block(&mut self, order: usize) -> Option<usize> {
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        if !self.free_lists[order].is_null() {
+            return Some(unsafe { self.remove_from_free_list(order) });
+        }
+
+        for higher_order in (order + 1)..=MAX_ORDER {
+            if !self.free_lists[higher_order].is_null() {
+                let block = unsafe { self.remove_from_free_list(higher_order) };
+                for split_order in ((order + 1)..=higher_order).rev() {
+                    let buddy = block + (self.min_block_size << (split_order - 1));
+                    unsafe {
+                        self.add_to_free_list(buddy, split_order - 1);
+                    }
+                }
+                return Some(block);
+            }
+        }
+
+        None
+    }
 
     /// Adds a block to the free list of the given order
     unsafe fn add_to_free_list(&mut self, addr: usize, order: usize) {
         let block = addr as *mut FreeBlock;
         unsafe {
-            (*block).next = self.free_lists[order];
+            block.write(FreeBlock {
+                next: self.free_lists[order],
+                magic: FREE_MAGIC,
+            });
         }
         self.free_lists[order] = block;
     }
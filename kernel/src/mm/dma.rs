@@ -0,0 +1,141 @@
+//! DMA-coherent buffers.
+//!
+//! Devices such as the EMMC controller and the VideoCore access RAM behind
+//! the CPU's back, so memory shared with them must be physically contiguous
+//! and must not be held in the data cache. A [`DmaBuffer`] is a block from
+//! the page allocator that is mapped Normal-uncached while it lives and
+//! mapped write-back again when it is dropped.
+//!
+//! The ARM MMU maps RAM in 1 MiB sections, so while it is enabled a buffer
+//! takes at least one whole, aligned section. With the MMU off the data
+//! cache is off as well and buffers are only rounded up to pages. x86 DMA
+//! snoops the caches, so nothing is remapped there.
+
+use crate::mm::buddy_allocator::MAX_ORDER;
+use crate::mm::page_allocator::{PAGE_SIZE, page_allocator};
+use core::ptr::NonNull;
+
+/// A physically contiguous, uncached buffer for device DMA.
+pub struct DmaBuffer {
+    addr: NonNull<u8>,
+    /// Bytes requested; the block behind it may be larger
+    len: usize,
+    /// Order of the page block
+    order: usize,
+    /// Whether the block was remapped and has to be restored on drop
+    remapped: bool,
+}
+
+// SAFETY: the buffer owns its block, and access goes through `&self` /
+// `&mut self` like any other owned memory.
+unsafe impl Send for DmaBuffer {}
+
+impl DmaBuffer {
+    /// Page block order needed for a `len` byte buffer, or `None` if it is
+    /// larger than the page allocator can hand out in one piece.
+    pub(in crate::mm) fn order_for(len: usize) -> Option<usize> {
+        let size = len.max(1).next_multiple_of(Self::granule());
+        let order = (size / PAGE_SIZE).next_power_of_two().trailing_zeros() as usize;
+        (order <= MAX_ORDER).then_some(order)
+    }
+
+    /// Take ownership of the page block at `addr`, zero it and make it
+    /// uncached.
+    ///
+    /// # Safety
+    /// `addr` must be a block of `order` just taken from the page allocator.
+    pub(in crate::mm) unsafe fn new(addr: usize, order: usize, len: usize) -> Self {
+        let size = PAGE_SIZE << order;
+        unsafe {
+            core::ptr::write_bytes(addr as *mut u8, 0, size);
+        }
+
+        let remapped = Self::granule() > PAGE_SIZE;
+        #[cfg(target_arch = "arm")]
+        if remapped {
+            use crate::arch::arm::mmu;
+            use crate::mm::mmu::{MapFlags, MmuOps, PlatformMmu};
+
+            // Push the zeros out and drop the lines before the cached
+            // alias goes away, so no dirty line is evicted over device data
+            unsafe {
+                mmu::clean_invalidate_dcache_range(addr, size);
+                PlatformMmu::map_region(addr, addr, size, MapFlags::READ | MapFlags::WRITE);
+            }
+        }
+
+        Self {
+            addr: NonNull::new(addr as *mut u8).unwrap(),
+            len,
+            order,
+            remapped,
+        }
+    }
+
+    /// Smallest region that can be given its own cache attributes
+    fn granule() -> usize {
+        #[cfg(target_arch = "arm")]
+        if crate::arch::arm::mmu::mmu_enabled() {
+            return crate::arch::arm::mmu::SECTION_SIZE;
+        }
+        PAGE_SIZE
+    }
+
+    /// Physical (and, identity mapped, virtual) address of the buffer
+    pub fn addr(&self) -> usize {
+        self.addr.as_ptr() as usize
+    }
+
+    /// Address of the buffer as seen by the device.
+    ///
+    /// On the BCM2835 this is the VideoCore bus alias that bypasses its L2
+    /// cache; elsewhere it is the physical address.
+    pub fn bus_addr(&self) -> u32 {
+        #[cfg(target_arch = "arm")]
+        {
+            drivers::peripheral::bcm2835::dma::bus_addr_ram(self.addr())
+        }
+        #[cfg(not(target_arch = "arm"))]
+        {
+            self.addr() as u32
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: the block is at least `len` bytes and owned by `self`
+        unsafe { core::slice::from_raw_parts(self.addr.as_ptr(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the block is at least `len` bytes and owned by `self`
+        unsafe { core::slice::from_raw_parts_mut(self.addr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        #[cfg(target_arch = "arm")]
+        if self.remapped {
+            use crate::mm::mmu::{MapFlags, MmuOps, PlatformMmu};
+
+            let flags = MapFlags::READ | MapFlags::WRITE | MapFlags::EXEC | MapFlags::CACHED;
+            unsafe {
+                PlatformMmu::map_region(self.addr(), self.addr(), PAGE_SIZE << self.order, flags);
+            }
+        }
+        #[cfg(not(target_arch = "arm"))]
+        let _ = self.remapped;
+
+        unsafe {
+            page_allocator().free_block(self.addr(), self.order);
+        }
+    }
+}
@@ -1,18 +1,28 @@
-use super::buddy_allocator::BuddyAllocator;
+use super::buddy_allocator::{AllocatorStats, BuddyAllocator, Corruption};
+use common::sync::Once;
 use core::alloc::{GlobalAlloc, Layout};
 use spin::Mutex;
 
+/// Runs when the heap cannot satisfy `layout`, with the heap unlocked.
+/// Returns `true` if it released memory, for example by killing a user
+/// process, and the allocation should be tried again.
+pub type OomHandler = fn(Layout) -> bool;
+
+/// Policy installed with [`set_oom_handler`]
+static OOM_HANDLER: Mutex<Option<OomHandler>> = Mutex::new(None);
+
+/// Retries allowed after the OOM handler reports progress
+const OOM_RETRIES: usize = 3;
+
 /// Global heap allocator using buddy allocation
 pub struct HeapAllocator {
-    inner: Mutex<Option<BuddyAllocator>>,
+    inner: Once<Mutex<BuddyAllocator>>,
 }
 
 impl HeapAllocator {
     /// Creates a new uninitialized heap allocator
     const fn new() -> Self {
-        Self {
-            inner: Mutex::new(None),
-        }
+        Self { inner: Once::new() }
     }
 
     /// Initializes the heap with a memory region
@@ -24,9 +34,8 @@ impl HeapAllocator {
     /// # Panics
     /// Panics if already initialized
     unsafe fn init(&self, start: usize, end: usize) {
-        let mut allocator = self.inner.lock();
-
-        if allocator.is_some() {
+        // Checked first: the region may be the live heap
+        if self.inner.is_completed() {
             panic!("HeapAllocator already initialized");
         }
 
@@ -35,31 +44,48 @@ impl HeapAllocator {
         unsafe {
             buddy.init(start, end);
         }
-        *allocator = Some(buddy);
+        if self.inner.set(Mutex::new(buddy)).is_err() {
+            panic!("HeapAllocator already initialized");
+        }
     }
 }
 
 unsafe impl GlobalAlloc for HeapAllocator {
+    /// Returns null when the heap is exhausted and the OOM handler cannot
+    /// help, so fallible APIs such as `try_reserve` see the failure.
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut guard = self.inner.lock();
-        let allocator = guard.as_mut().expect("heap not initialized");
+        for _ in 0..=OOM_RETRIES {
+            let allocator = self.inner.get().expect("heap not initialized");
+            let block = unsafe { allocator.lock().alloc(layout) };
+            if let Some(ptr) = block {
+                return ptr.as_ptr();
+            }
 
-        match unsafe { allocator.alloc(layout) } {
-            Some(ptr) => ptr.as_ptr(),
-            None => alloc_error_handler(layout),
+            let handler = *OOM_HANDLER.lock();
+            if !handler.is_some_and(|handler| handler(layout)) {
+                break;
+            }
         }
+        core::ptr::null_mut()
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-        let mut guard = self.inner.lock();
-        if let Some(allocator) = guard.as_mut() {
+        if let Some(allocator) = self.inner.get() {
             unsafe {
-                allocator.free(ptr);
+                allocator.lock().free(ptr);
             }
         }
     }
 
+    /// Resizes in place when the buddy allocator can, otherwise moves the
+    /// data to a new allocation.
     unsafe fn realloc(&self, ptr: *mut u8, old_layout: Layout, new_size: usize) -> *mut u8 {
+        if let Some(allocator) = self.inner.get()
+            && unsafe { allocator.lock().resize_in_place(ptr, new_size) }
+        {
+            return ptr;
+        }
+
         unsafe {
             let new_layout = Layout::from_size_align_unchecked(new_size, old_layout.align());
 
@@ -84,7 +110,41 @@ unsafe impl GlobalAlloc for HeapAllocator {
 #[global_allocator]
 static HEAP: HeapAllocator = HeapAllocator::new();
 
-/// Handler for allocation failures
+/// Current kernel heap usage, or `None` before the heap is initialized
+pub fn heap_stats() -> Option<AllocatorStats> {
+    HEAP.inner.get().map(|allocator| allocator.lock().stats())
+}
+
+/// Current kernel heap usage, or `None` before the heap is initialized or
+/// while it is locked. Never blocks.
+pub fn try_heap_stats() -> Option<AllocatorStats> {
+    HEAP.inner
+        .get()?
+        .try_lock()
+        .map(|allocator| allocator.stats())
+}
+
+/// Whether the heap is locked, by an allocation in progress
+pub fn is_locked() -> bool {
+    HEAP.inner.get().is_some_and(Mutex::is_locked)
+}
+
+/// Check the kernel heap's free lists, or `None` before the heap is
+/// initialized or while it is locked. Never blocks, so a panic handler can
+/// call it even if the panic happened inside the allocator.
+pub fn check_heap() -> Option<Result<(), Corruption>> {
+    let allocator = HEAP.inner.get()?.try_lock()?;
+    Some(allocator.check_consistency())
+}
+
+/// Install the policy run when the heap is exhausted, replacing any
+/// previous one. Without a policy, failed allocations fail straight away.
+pub fn set_oom_handler(handler: OomHandler) {
+    *OOM_HANDLER.lock() = Some(handler);
+}
+
+/// Handler for failed infallible allocations. Kernel paths that serve user
+/// requests allocate fallibly and never get here.
 #[alloc_error_handler]
 fn alloc_error_handler(layout: Layout) -> ! {
     panic!(
@@ -1,4 +1,10 @@
+use crate::mm::address_space::MapError;
+use crate::mm::page_allocator::PAGE_SIZE;
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+
 bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct MapFlags: u32 {
         const READ   = 1 << 0;
         const WRITE  = 1 << 1;
@@ -22,6 +28,28 @@ pub trait MmuOps {
     /// Unmap a virtual region. Does not free any backing physical memory.
     unsafe fn unmap_region(virt: usize, size: usize);
 
+    /// Map a physically contiguous region in 4 KB pages, creating kernel
+    /// page tables as needed. Fails only if no page table can be
+    /// allocated.
+    unsafe fn map_pages(
+        virt: usize,
+        phys: usize,
+        size: usize,
+        flags: MapFlags,
+    ) -> Result<(), MapError> {
+        unsafe { Self::map_region(virt, phys, size, flags) };
+        Ok(())
+    }
+
+    /// Unmap a region mapped with [`Self::map_pages`]. Page tables are
+    /// kept for later mappings.
+    unsafe fn unmap_pages(virt: usize, size: usize) {
+        unsafe { Self::unmap_region(virt, size) };
+    }
+
+    /// Whether address translation is on.
+    fn enabled() -> bool;
+
     /// Invalidate a single TLB entry by virtual address.
     unsafe fn invalidate_tlb_entry(va: usize);
 
@@ -29,9 +57,127 @@ pub trait MmuOps {
     unsafe fn invalidate_tlb_all();
 }
 
+/// Contents of the root table slot covering an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootEntry {
+    /// Nothing mapped
+    Empty,
+    /// Points to the leaf table at this physical address
+    Table(usize),
+    /// Maps a large block directly (section or 4 MB page)
+    Block,
+}
+
+/// Bytes of address space mapped by one leaf table page
+pub const LEAF_SPAN: usize = 4 * 1024 * 1024;
+
+/// Page table handling for [`AddressSpace`](super::address_space::AddressSpace).
+///
+/// An address space is a root table of `2^ROOT_ORDER` pages plus leaf
+/// tables of one page each, every leaf mapping [`LEAF_SPAN`] bytes in 4 KB
+/// pages. The entry for `va` is at index `(va >> 12) & 0x3FF` of its leaf.
+pub trait PageTableOps {
+    /// Order of the root table allocation
+    const ROOT_ORDER: usize;
+
+    /// Number of hardware address space identifiers, counting ASID 0,
+    /// which is kept for the kernel. 1 means the TLB is not tagged.
+    const ASID_COUNT: usize;
+
+    /// Fill a zeroed root table with the kernel's mappings.
+    unsafe fn init_root(root: usize);
+
+    /// Inspect the root slot covering `va`.
+    unsafe fn root_entry(root: usize, va: usize) -> RootEntry;
+
+    /// Point the root slot covering `va` at the zeroed leaf page `leaf`.
+    unsafe fn set_root_entry(root: usize, va: usize, leaf: usize);
+
+    /// Clear the root slot covering `va`.
+    unsafe fn clear_root_entry(root: usize, va: usize);
+
+    /// Leaf entry mapping a 4 KB page at `phys`, private to one address
+    /// space.
+    fn leaf_entry(phys: usize, flags: MapFlags) -> u32;
+
+    /// Store a root or leaf entry where the table walker will see it.
+    /// The TLB still has to be invalidated for changed mappings.
+    unsafe fn write_entry(entry: *mut u32, value: u32) {
+        unsafe { entry.write_volatile(value) };
+    }
+
+    /// Make `root` the current user address space, tagging its TLB
+    /// entries with `asid`, or flushing the TLB if it has none.
+    unsafe fn switch_to(root: usize, asid: Option<u16>);
+
+    /// Drop the TLB entry for `va` in address space `asid`.
+    unsafe fn invalidate_page(va: usize, asid: Option<u16>);
+
+    /// Drop every TLB entry tagged with `asid`.
+    unsafe fn invalidate_asid(asid: u16);
+}
+
 // Stable re-export so callers never need a cfg themselves
 #[cfg(target_arch = "arm")]
 pub use crate::arch::arm::mmu::ArmMmu as PlatformMmu;
 
 #[cfg(target_arch = "x86")]
 pub use crate::arch::x86::mmu::X86Mmu as PlatformMmu;
+
+// ============================================================================
+// Device memory mappings
+// ============================================================================
+
+/// Start of the virtual window [`ioremap`] hands out
+pub const IOREMAP_BASE: usize = 0xD000_0000;
+
+/// End of the [`ioremap`] window, exclusive
+pub const IOREMAP_END: usize = 0xE000_0000;
+
+/// Live [`ioremap`] mappings, start address to size in bytes
+static IOREMAPS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// Map `len` bytes of device registers at `phys` as Device memory and
+/// return the virtual address of `phys`.
+///
+/// With the MMU off every address is physical, so `phys` itself comes
+/// back. Page tables for the window are shared by reference, but only
+/// address spaces created after the first mapping in a 4 MB stretch of
+/// the window see it, so drivers should map their registers at probe time.
+pub fn ioremap(phys: usize, len: usize) -> Result<usize, MapError> {
+    if !PlatformMmu::enabled() {
+        return Ok(phys);
+    }
+
+    let offset = phys % PAGE_SIZE;
+    let size = (offset + len.max(1))
+        .checked_next_multiple_of(PAGE_SIZE)
+        .ok_or(MapError::OutOfRange)?;
+
+    let mut maps = IOREMAPS.lock();
+    let mut start = IOREMAP_BASE;
+    for (&va, &mapped) in maps.iter() {
+        if va - start >= size {
+            break;
+        }
+        start = va + mapped;
+    }
+    if IOREMAP_END - start < size {
+        return Err(MapError::OutOfMemory);
+    }
+
+    let flags = MapFlags::READ | MapFlags::WRITE | MapFlags::DEVICE;
+    unsafe { PlatformMmu::map_pages(start, phys - offset, size, flags)? };
+    maps.insert(start, size);
+    Ok(start + offset)
+}
+
+/// Remove the mapping [`ioremap`] returned `addr` for. Addresses outside
+/// the window, including the physical ones handed out with the MMU off,
+/// are ignored.
+pub fn iounmap(addr: usize) {
+    let start = addr - addr % PAGE_SIZE;
+    if let Some(size) = IOREMAPS.lock().remove(&start) {
+        unsafe { PlatformMmu::unmap_pages(start, size) };
+    }
+}
@@ -1,5 +1,42 @@
+pub mod address_space;
 pub mod buddy_allocator;
+pub mod dma;
 pub mod heap_allocator;
 pub mod mmu;
 pub mod page_allocator;
 pub mod page_table;
+pub mod vma;
+
+use buddy_allocator::AllocatorStats;
+
+/// Usage of the kernel's memory allocators
+#[derive(Debug, Clone, Copy)]
+pub struct MemStats {
+    /// Kernel heap (`alloc`), `None` before it is initialized
+    pub heap: Option<AllocatorStats>,
+    /// Physical page allocator, all zones together, `None` before it is
+    /// initialized
+    pub pages: Option<AllocatorStats>,
+    /// DMA zone of the page allocator, also counted in `pages`; `None`
+    /// if the zone is empty
+    pub dma: Option<AllocatorStats>,
+}
+
+/// Snapshot the usage of every allocator.
+pub fn stats() -> MemStats {
+    MemStats {
+        heap: heap_allocator::heap_stats(),
+        pages: page_allocator::page_allocator().stats(),
+        dma: page_allocator::page_allocator().zone_stats(page_allocator::Zone::Dma),
+    }
+}
+
+/// Like [`stats`], without waiting for a locked allocator: its usage is
+/// left out. For code that may have interrupted an allocation.
+pub fn try_stats() -> MemStats {
+    MemStats {
+        heap: heap_allocator::try_heap_stats(),
+        pages: page_allocator::page_allocator().try_stats(),
+        dma: page_allocator::page_allocator().try_zone_stats(page_allocator::Zone::Dma),
+    }
+}
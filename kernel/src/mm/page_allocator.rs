@@ -1,32 +1,77 @@
-use crate::mm::buddy_allocator::BuddyAllocator;
+use crate::mm::buddy_allocator::{AllocatorStats, BuddyAllocator, Corruption};
+use crate::mm::dma::DmaBuffer;
 use crate::mm::page_table::Page;
 use crate::mm::page_table::{L1Table, L2Table, PageBlock};
+use common::sync::Once;
 use spin::Mutex;
-use core::cell::OnceCell;
 
 pub const PAGE_SIZE: usize = 4096;
 
+/// End of the memory DMA engines can address: the VideoCore bus aliases of
+/// SDRAM cover the first GiB
+#[cfg(target_arch = "arm")]
+pub const DMA_ZONE_END: usize = 0x4000_0000;
+
+/// End of the memory DMA engines can address: ISA DMA reaches the first
+/// 16 MiB
+#[cfg(not(target_arch = "arm"))]
+pub const DMA_ZONE_END: usize = 0x0100_0000;
+
+/// Number of [`Zone`]s
+pub const ZONE_COUNT: usize = 2;
+
+/// Physical memory zones the page allocator keeps apart, so that memory
+/// devices need is not used up by allocations that could live anywhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    /// Memory below [`DMA_ZONE_END`]
+    Dma,
+    /// Memory only the CPU needs to reach
+    Normal,
+}
+
+impl Zone {
+    /// Zones to try, in order, for an allocation from `self`: normal
+    /// allocations fall back to the DMA zone, DMA allocations cannot fall
+    /// back
+    fn fallbacks(self) -> &'static [Zone] {
+        match self {
+            Zone::Dma => &[Zone::Dma],
+            Zone::Normal => &[Zone::Normal, Zone::Dma],
+        }
+    }
+
+    /// Zone of memory between `start` and `end`, split at [`DMA_ZONE_END`]
+    fn bounds(self, start: usize, end: usize) -> (usize, usize) {
+        match self {
+            Zone::Dma => (start, end.min(DMA_ZONE_END)),
+            Zone::Normal => (start.max(DMA_ZONE_END), end),
+        }
+    }
+}
+
+/// One buddy allocator per zone; `None` for a zone with no memory
+type Zones = [Option<Mutex<BuddyAllocator>>; ZONE_COUNT];
+
 /// Global page allocator using buddy allocation
 static PAGE_ALLOCATOR: PageAllocator = PageAllocator::new();
 
 /// High-level interface for allocating pages, page blocks, and page tables.
 ///
-/// `PageAllocator` wraps a `BuddyAllocator` stored in `PAGE_ALLOCATOR`.
-/// Provides RAII-style wrappers for allocated memory to ensure proper
-/// deallocation when values go out of scope.
+/// `PageAllocator` wraps a `BuddyAllocator` per [`Zone`], stored in
+/// `PAGE_ALLOCATOR`. Provides RAII-style wrappers for allocated memory to
+/// ensure proper deallocation when values go out of scope.
 pub struct PageAllocator {
-    inner: OnceCell<Mutex<BuddyAllocator>>,
+    zones: Once<Zones>,
 }
 
 impl PageAllocator {
     /// Create a new uninitialized page allocator
     const fn new() -> Self {
-        Self {
-            inner: OnceCell::new(),
-        }
+        Self { zones: Once::new() }
     }
 
-    /// Initializes the global buddy allocator.
+    /// Initializes the buddy allocator of each zone.
     ///
     /// # Safety
     /// - Must be called exactly once during early boot.
@@ -35,55 +80,172 @@ impl PageAllocator {
     /// # Arguments
     /// - `start`: The start physical address of memory to manage.
     /// - `end`: The end physical address of memory to manage.
+    /// - `reserved`: Ranges inside it that are in use from boot, e.g. the
+    ///   device tree or an initrd. They are left untouched.
     ///
     /// # Panics
     /// Panics if called more than once.
-    pub unsafe fn init(&self, start: usize, end: usize) {
-        // Initialize buddy allocator
-        let mut buddy = BuddyAllocator::new(PAGE_SIZE);
-        unsafe {
-            buddy.init(start, end);
-        }
+    pub unsafe fn init(&self, start: usize, end: usize, reserved: &[(usize, usize)]) {
+        let zones = [Zone::Dma, Zone::Normal].map(|zone| {
+            let (start, end) = zone.bounds(start, end);
+            if end.saturating_sub(start) < PAGE_SIZE {
+                return None;
+            }
+
+            let mut buddy = BuddyAllocator::new(PAGE_SIZE);
+            unsafe {
+                buddy.init_reserved(start, end, reserved);
+            }
+            log::debug!("Page zone {:?}: {:#010x}-{:#010x}", zone, start, end);
+            Some(Mutex::new(buddy))
+        });
 
-        // Try to set the OnceCell
-        if self.inner.set(Mutex::new(buddy)).is_err() {
+        if self.zones.set(zones).is_err() {
             panic!("PageAllocator already initialized");
         }
     }
 
-    /// Execute a closure with exclusive access to the underlying BuddyAllocator
+    /// Execute a closure with exclusive access to the BuddyAllocator of
+    /// `zone`, or return `None` if the zone has no memory
     ///
     /// # Panics
     /// Panics if the allocator is not yet initialized.
-    fn with_page_allocator<F, R>(&self, f: F) -> R
+    fn with_zone<F, R>(&self, zone: Zone, f: F) -> Option<R>
     where
         F: FnOnce(&mut BuddyAllocator) -> R,
     {
-        let allocator = self.inner.get().expect("PageAllocator not initialized");
-        let mut guard = allocator.lock();
-        f(&mut *guard)
+        let zones = self.zones.get().expect("PageAllocator not initialized");
+        let mut guard = zones[zone as usize].as_ref()?.lock();
+        Some(f(&mut guard))
+    }
+
+    /// Allocate a block of `order` from `zone` or a zone it falls back to
+    fn alloc_order_in(&self, zone: Zone, order: usize) -> Option<usize> {
+        zone.fallbacks().iter().find_map(|&zone| {
+            self.with_zone(zone, |alloc| unsafe { alloc.alloc_block_order(order) })?
+        })
     }
 
     /// Allocates a single page.
     pub fn alloc(&self) -> Option<Page> {
-        self.with_page_allocator(|alloc| unsafe { alloc.alloc_block() }.map(Page::new))
+        self.alloc_page_in(Zone::Normal)
+    }
+
+    /// Allocates a single page from `zone`. Normal allocations fall back to
+    /// the DMA zone when the normal zone is exhausted or empty.
+    pub fn alloc_page_in(&self, zone: Zone) -> Option<Page> {
+        self.alloc_order_in(zone, 0).map(Page::new)
     }
 
     /// Allocates a block of pages of size `2^ORDER`.
     pub fn alloc_block<const ORDER: usize>(&self) -> Option<PageBlock<ORDER>> {
-        self.with_page_allocator(|alloc| {
-            unsafe { alloc.alloc_block_order(ORDER) }.map(PageBlock::new)
-        })
+        self.alloc_order_in(Zone::Normal, ORDER).map(PageBlock::new)
     }
 
     /// Allocates an L1 page table (8 KiB, order = 2).
     pub fn alloc_l1_table(&self) -> Option<L1Table> {
-        self.with_page_allocator(|alloc| unsafe { alloc.alloc_block_order(2) }.map(L1Table::new))
+        self.alloc_order_in(Zone::Normal, 2).map(L1Table::new)
     }
 
     /// Allocates an L2 page table (single page).
     pub fn alloc_l2_table(&self) -> Option<L2Table> {
-        self.with_page_allocator(|alloc| unsafe { alloc.alloc_block() }.map(L2Table::new))
+        self.alloc_order_in(Zone::Normal, 0).map(L2Table::new)
+    }
+
+    /// Allocates a zeroed, physically contiguous, uncached buffer of at
+    /// least `len` bytes for device DMA from the DMA zone. See
+    /// [`crate::mm::dma`].
+    pub fn alloc_dma(&self, len: usize) -> Option<DmaBuffer> {
+        let order = DmaBuffer::order_for(len)?;
+        self.alloc_order_in(Zone::Dma, order)
+            .map(|addr| unsafe { DmaBuffer::new(addr, order, len) })
+    }
+
+    /// Take `[start, end)` out of use, e.g. firmware data or a framebuffer
+    /// inside RAM. Returns `false` if some of it was already allocated.
+    ///
+    /// # Panics
+    /// Panics if the allocator is not yet initialized.
+    pub fn reserve(&self, start: usize, end: usize) -> bool {
+        // Every zone's share is reserved, whatever became of the others'
+        let reserved = [Zone::Dma, Zone::Normal].map(|zone| {
+            let (start, end) = zone.bounds(start, end);
+            start >= end
+                || self
+                    .with_zone(zone, |alloc| alloc.reserve(start, end))
+                    .unwrap_or(true)
+        });
+        reserved.into_iter().all(|reserved| reserved)
+    }
+
+    /// Give the whole pages of a range reserved at [`Self::init`] or by
+    /// [`Self::reserve`] back for allocation.
+    ///
+    /// # Safety
+    /// Nothing may use the range any more.
+    ///
+    /// # Panics
+    /// Panics if the allocator is not yet initialized.
+    pub unsafe fn release(&self, start: usize, end: usize) {
+        for zone in [Zone::Dma, Zone::Normal] {
+            let (start, end) = zone.bounds(start, end);
+            if start < end {
+                self.with_zone(zone, |alloc| unsafe { alloc.release(start, end) });
+            }
+        }
+    }
+
+    /// Current usage of all zones together, or `None` before [`Self::init`]
+    pub fn stats(&self) -> Option<AllocatorStats> {
+        let zones = self.zones.get()?;
+        zones
+            .iter()
+            .flatten()
+            .map(|allocator| allocator.lock().stats())
+            .reduce(AllocatorStats::combine)
+    }
+
+    /// Current usage of `zone`, or `None` before [`Self::init`] or if the
+    /// zone has no memory
+    pub fn zone_stats(&self, zone: Zone) -> Option<AllocatorStats> {
+        let allocator = self.zones.get()?[zone as usize].as_ref()?;
+        Some(allocator.lock().stats())
+    }
+
+    /// Like [`Self::stats`], but `None` if a zone is locked rather than
+    /// waiting for it
+    pub fn try_stats(&self) -> Option<AllocatorStats> {
+        let zones = self.zones.get()?;
+        let mut stats = None;
+        for allocator in zones.iter().flatten() {
+            let zone = allocator.try_lock()?.stats();
+            stats = Some(stats.map_or(zone, |stats| AllocatorStats::combine(stats, zone)));
+        }
+        stats
+    }
+
+    /// Like [`Self::zone_stats`], but `None` if the zone is locked rather
+    /// than waiting for it
+    pub fn try_zone_stats(&self, zone: Zone) -> Option<AllocatorStats> {
+        let allocator = self.zones.get()?[zone as usize].as_ref()?;
+        Some(allocator.try_lock()?.stats())
+    }
+
+    /// Whether any zone is locked, by an allocation in progress
+    pub fn is_locked(&self) -> bool {
+        self.zones.get().is_some_and(|zones| {
+            zones
+                .iter()
+                .flatten()
+                .any(|allocator| allocator.is_locked())
+        })
+    }
+
+    /// Check the free lists of `zone`, or `None` before [`Self::init`] or
+    /// if the zone has no memory
+    pub fn check_consistency(&self, zone: Zone) -> Option<Result<(), Corruption>> {
+        let allocator = self.zones.get()?[zone as usize].as_ref()?;
+        Some(allocator.lock().check_consistency())
     }
 
     /// Free a block of memory
@@ -93,23 +255,21 @@ impl PageAllocator {
     /// - `order` must match the order used during allocation
     /// - Must not be double-freed
     pub unsafe fn free_block(&self, addr: usize, order: usize) {
-        if let Some(allocator) = self.inner.get() {
+        let Some(zones) = self.zones.get() else {
+            return;
+        };
+        for allocator in zones.iter().flatten() {
             let mut guard = allocator.lock();
-            unsafe {
-                guard.free_block(addr, order);
+            if guard.contains(addr) {
+                unsafe {
+                    guard.free_block(addr, order);
+                }
+                return;
             }
         }
     }
 }
 
-// SAFETY: PageAllocator wraps a OnceCell<Mutex<BuddyAllocator>>.
-// - OnceCell provides thread-safe one-time initialization
-// - Mutex ensures exclusive access to the BuddyAllocator
-// - BuddyAllocator itself is Send + Sync (manages its own invariants)
-// Thread safety is guaranteed by the Mutex wrapper.
-unsafe impl Send for PageAllocator {}
-unsafe impl Sync for PageAllocator {}
-
 pub fn page_allocator() -> &'static PageAllocator {
     &PAGE_ALLOCATOR
 }
@@ -67,6 +67,11 @@ impl Page {
     }
 }
 
+// SAFETY: a Page exclusively owns its memory and `&Page` only exposes the
+// address, so it may be moved to and shared with other threads.
+unsafe impl Send for Page {}
+unsafe impl Sync for Page {}
+
 impl Drop for Page {
     /// Frees the page when it goes out of scope.
     fn drop(&mut self) {
@@ -102,6 +107,11 @@ impl<const ORDER: usize> PageBlock<ORDER> {
     }
 }
 
+// SAFETY: as for Page, the block is exclusively owned and only its
+// address is exposed.
+unsafe impl<const ORDER: usize> Send for PageBlock<ORDER> {}
+unsafe impl<const ORDER: usize> Sync for PageBlock<ORDER> {}
+
 impl<const ORDER: usize> Drop for PageBlock<ORDER> {
     fn drop(&mut self) {
         self.flag.mark_freed();
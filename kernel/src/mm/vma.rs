@@ -0,0 +1,154 @@
+//! Virtual memory areas.
+//!
+//! A [`VmaList`] records the parts of an address space that are backed by
+//! demand-paged memory rather than fixed mappings. Only anonymous,
+//! zero-filled areas exist so far; file-backed areas will hang off the
+//! same list.
+//!
+//! Also defines the `mmap` protection and flag bits (Linux values).
+
+use crate::mm::mmu::MapFlags;
+use alloc::vec::Vec;
+
+/// Pages may be read
+pub const PROT_READ: u32 = 0x1;
+/// Pages may be written
+pub const PROT_WRITE: u32 = 0x2;
+/// Pages may be executed
+pub const PROT_EXEC: u32 = 0x4;
+
+/// Share the mapping with other mappers of the same object
+pub const MAP_SHARED: u32 = 0x01;
+/// Private copy-on-write mapping
+pub const MAP_PRIVATE: u32 = 0x02;
+/// Place the mapping at exactly the given address
+pub const MAP_FIXED: u32 = 0x10;
+/// Not backed by a file; zero-filled
+pub const MAP_ANONYMOUS: u32 = 0x20;
+
+/// Lowest address `mmap` picks on its own
+pub const MMAP_BASE: usize = 0x4000_0000;
+/// End of the range `mmap` picks addresses from
+pub const MMAP_END: usize = 0x8000_0000;
+
+/// Top of the stack a program starts on
+pub const USER_STACK_TOP: usize = 0xC000_0000;
+/// Size of the area below [`USER_STACK_TOP`] reserved for the stack
+pub const USER_STACK_SIZE: usize = 128 * 1024;
+
+/// A range of anonymous, demand-zero memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vma {
+    pub start: usize,
+    /// Exclusive
+    pub end: usize,
+    /// Protection of the pages mapped into the area; without `READ` every
+    /// access faults
+    pub flags: MapFlags,
+}
+
+impl Vma {
+    pub fn contains(&self, va: usize) -> bool {
+        (self.start..self.end).contains(&va)
+    }
+
+    /// Page protection for `PROT_*` bits
+    pub fn flags_for_prot(prot: u32) -> MapFlags {
+        let mut flags = MapFlags::USER | MapFlags::CACHED;
+        if prot & (PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+            // Nothing is writable or executable without being readable
+            flags |= MapFlags::READ;
+        }
+        if prot & PROT_WRITE != 0 {
+            flags |= MapFlags::WRITE;
+        }
+        if prot & PROT_EXEC != 0 {
+            flags |= MapFlags::EXEC;
+        }
+        flags
+    }
+}
+
+/// Areas of one address space, sorted by start and never overlapping
+#[derive(Debug, Clone, Default)]
+pub struct VmaList {
+    areas: Vec<Vma>,
+}
+
+impl VmaList {
+    pub fn new() -> Self {
+        Self { areas: Vec::new() }
+    }
+
+    /// Area containing `va`
+    pub fn find(&self, va: usize) -> Option<&Vma> {
+        let index = self.areas.partition_point(|vma| vma.end <= va);
+        self.areas.get(index).filter(|vma| vma.contains(va))
+    }
+
+    /// Whether any area intersects `[start, end)`
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        let index = self.areas.partition_point(|vma| vma.end <= start);
+        self.areas.get(index).is_some_and(|vma| vma.start < end)
+    }
+
+    /// Add `vma`, which must not overlap an existing area. It is merged
+    /// with neighbours it touches that have the same protection.
+    pub fn insert(&mut self, mut vma: Vma) {
+        debug_assert!(!self.overlaps(vma.start, vma.end));
+        let index = self.areas.partition_point(|area| area.start < vma.start);
+
+        if let Some(next) = self.areas.get(index)
+            && next.start == vma.end
+            && next.flags == vma.flags
+        {
+            vma.end = next.end;
+            self.areas.remove(index);
+        }
+        if index > 0 {
+            let prev = &mut self.areas[index - 1];
+            if prev.end == vma.start && prev.flags == vma.flags {
+                prev.end = vma.end;
+                return;
+            }
+        }
+        self.areas.insert(index, vma);
+    }
+
+    /// Cut `[start, end)` out of every area, splitting one that spans it.
+    pub fn remove(&mut self, start: usize, end: usize) {
+        let mut areas = Vec::with_capacity(self.areas.len() + 1);
+        for vma in self.areas.drain(..) {
+            if vma.end <= start || vma.start >= end {
+                areas.push(vma);
+                continue;
+            }
+            if vma.start < start {
+                areas.push(Vma { end: start, ..vma });
+            }
+            if vma.end > end {
+                areas.push(Vma { start: end, ..vma });
+            }
+        }
+        self.areas = areas;
+    }
+
+    /// Lowest free gap of `size` bytes within `[low, high)`
+    pub fn find_free(&self, size: usize, low: usize, high: usize) -> Option<usize> {
+        let mut candidate = low;
+        for vma in &self.areas {
+            if vma.end <= candidate {
+                continue;
+            }
+            if vma.start >= candidate.checked_add(size)? {
+                break;
+            }
+            candidate = vma.end;
+        }
+        (candidate.checked_add(size)? <= high).then_some(candidate)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Vma> {
+        self.areas.iter()
+    }
+}
@@ -0,0 +1,175 @@
+//! ARP
+//!
+//! Each interface keeps a cache of the hardware addresses of its
+//! neighbours, learnt from replies to its requests and from requests for
+//! its own address, and answers requests for its own address. Entries
+//! are forgotten after [`ENTRY_MS`], so a neighbour that changed its
+//! card is asked again.
+
+use super::NetError;
+use super::ethernet::ETHERTYPE_ARP;
+use super::iface::Interface;
+use super::ipv4::Ipv4Addr;
+use crate::process::sched::{self, TICK_US, WaitQueue};
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU32, Ordering};
+use drivers::hal::net::MacAddress;
+
+/// How long an entry lasts, in milliseconds
+pub const ENTRY_MS: u32 = 5 * 60 * 1000;
+
+/// Entries per interface; learning another evicts the oldest
+const MAX_ENTRIES: usize = 64;
+
+/// Length of an Ethernet/IPv4 ARP packet
+const PACKET_LEN: usize = 28;
+
+const HTYPE_ETHERNET: u16 = 1;
+const PTYPE_IPV4: u16 = 0x0800;
+const OP_REQUEST: u16 = 1;
+const OP_REPLY: u16 = 2;
+
+/// Bumped whenever an address is learnt
+static LEARNT: AtomicU32 = AtomicU32::new(0);
+
+/// Tasks waiting for an address to be learnt
+pub static RESOLVED: WaitQueue = WaitQueue::new();
+
+/// Timer ticks an entry lasts
+const fn entry_ticks() -> u64 {
+    ENTRY_MS as u64 * 1000 / TICK_US as u64
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Entry {
+    mac: MacAddress,
+    /// Tick at which the entry is forgotten
+    expires: u64,
+}
+
+/// Neighbours' hardware addresses
+pub struct ArpCache {
+    entries: BTreeMap<Ipv4Addr, Entry>,
+}
+
+impl ArpCache {
+    pub const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// The hardware address of `ip`, if it is known
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<MacAddress> {
+        let entry = self.entries.get(&ip)?;
+        (entry.expires > sched::ticks()).then_some(entry.mac)
+    }
+
+    /// Every live entry
+    pub fn entries(&self) -> impl Iterator<Item = (Ipv4Addr, MacAddress)> + '_ {
+        let now = sched::ticks();
+        self.entries
+            .iter()
+            .filter(move |(_, entry)| entry.expires > now)
+            .map(|(ip, entry)| (*ip, entry.mac))
+    }
+
+    /// Forget entries that have expired
+    pub fn expire(&mut self) {
+        let now = sched::ticks();
+        self.entries.retain(|_, entry| entry.expires > now);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Record that `ip` is at `mac`, and wake tasks waiting on it
+    fn learn(&mut self, ip: Ipv4Addr, mac: MacAddress) {
+        if !self.entries.contains_key(&ip) && self.entries.len() >= MAX_ENTRIES {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(ip, _)| *ip);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        let expires = sched::ticks() + entry_ticks();
+        self.entries.insert(ip, Entry { mac, expires });
+        LEARNT.fetch_add(1, Ordering::Release);
+        RESOLVED.wake_all();
+    }
+}
+
+/// How many addresses have been learnt so far, to tell whether one has
+/// been since
+pub fn learnt() -> u32 {
+    LEARNT.load(Ordering::Acquire)
+}
+
+/// Broadcast a request for the hardware address of `ip` on `iface`
+pub(super) fn request(iface: &mut Interface, ip: Ipv4Addr) -> Result<(), NetError> {
+    let packet = build(OP_REQUEST, iface, MacAddress::ZERO, ip);
+    iface.transmit(MacAddress::BROADCAST, ETHERTYPE_ARP, &packet)
+}
+
+/// Take in an ARP packet that arrived on `iface`, answering it if it asks
+/// for our address
+pub(super) fn receive(iface: &mut Interface, bytes: &[u8]) {
+    if bytes.len() < PACKET_LEN {
+        iface.stats.rx_dropped += 1;
+        return;
+    }
+    let field = |at: usize| u16::from_be_bytes([bytes[at], bytes[at + 1]]);
+    if field(0) != HTYPE_ETHERNET || field(2) != PTYPE_IPV4 || bytes[4] != 6 || bytes[5] != 4 {
+        return;
+    }
+    let op = field(6);
+    let sender_mac = MacAddress(bytes[8..14].try_into().unwrap());
+    let sender_ip = Ipv4Addr(bytes[14..18].try_into().unwrap());
+    let target_ip = Ipv4Addr(bytes[24..28].try_into().unwrap());
+
+    // A probe's sender has no address yet
+    if sender_ip.is_unspecified() {
+        return;
+    }
+    let Some(ours) = iface.config.map(|config| config.addr) else {
+        return;
+    };
+    if target_ip == ours {
+        iface.arp.learn(sender_ip, sender_mac);
+        if op == OP_REQUEST {
+            let reply = build(OP_REPLY, iface, sender_mac, sender_ip);
+            // Lost like any other frame; the neighbour asks again
+            let _ = iface.transmit(sender_mac, ETHERTYPE_ARP, &reply);
+        }
+    } else if iface.arp.lookup(sender_ip).is_some() {
+        // Someone we talk to told someone else where it is now
+        iface.arp.learn(sender_ip, sender_mac);
+    }
+}
+
+/// An ARP packet from `iface` to `target_mac` at `target_ip`
+fn build(
+    op: u16,
+    iface: &Interface,
+    target_mac: MacAddress,
+    target_ip: Ipv4Addr,
+) -> [u8; PACKET_LEN] {
+    let sender_ip = iface
+        .config
+        .map_or(Ipv4Addr::UNSPECIFIED, |config| config.addr);
+    let mut packet = [0u8; PACKET_LEN];
+    packet[0..2].copy_from_slice(&HTYPE_ETHERNET.to_be_bytes());
+    packet[2..4].copy_from_slice(&PTYPE_IPV4.to_be_bytes());
+    packet[4] = 6;
+    packet[5] = 4;
+    packet[6..8].copy_from_slice(&op.to_be_bytes());
+    packet[8..14].copy_from_slice(&iface.mac.0);
+    packet[14..18].copy_from_slice(&sender_ip.0);
+    packet[18..24].copy_from_slice(&target_mac.0);
+    packet[24..28].copy_from_slice(&target_ip.0);
+    packet
+}
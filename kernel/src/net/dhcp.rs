@@ -0,0 +1,452 @@
+//! DHCP client
+//!
+//! Asks a DHCP server for an address, the subnet, a gateway and DNS
+//! servers, and keeps the lease: it is renewed halfway through, or when
+//! the link comes back after going down, in case the interface was moved
+//! to another network meanwhile. A lease that runs out, or that the
+//! server refuses to renew, takes the address away and the client starts
+//! over.
+//!
+//! [`maintain`] does what is due for one interface; the
+//! [boot-time configuration](super::ipconfig) task calls it every second
+//! for each interface it runs DHCP on. Only one interface is dealt with
+//! at a time, as they all share port 68.
+
+use super::iface::{self, IfConfig, InterfaceInfo};
+use super::ipv4::Ipv4Addr;
+use super::{NetError, UdpSocket};
+use crate::process::sched::{self, TICK_US};
+use crate::subsystems::uptime_us;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use drivers::hal::net::MacAddress;
+use spin::Mutex;
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+
+/// `op` of a message from a client, and from a server
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+
+/// Length of the fixed part, up to the options' magic cookie
+const FIXED_LEN: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+// Options
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAMS: u8 = 55;
+const OPT_RENEWAL_TIME: u8 = 58;
+const OPT_END: u8 = 255;
+
+// Message types
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+
+/// Times a message is sent before giving up, waiting twice as long after
+/// each
+const ATTEMPTS: u32 = 4;
+
+/// How long to wait for the first answer, in milliseconds
+const FIRST_WAIT_MS: u32 = 1000;
+
+/// How long to wait after a failure before starting over, in seconds
+const RETRY_SECS: u64 = 10;
+
+/// Lease assumed when the server gives none, in seconds
+const DEFAULT_LEASE_SECS: u32 = 3600;
+
+/// Why no lease was had
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DhcpError {
+    /// No server answered
+    NoServer,
+    /// The server refused the address asked for
+    Refused,
+    Net(NetError),
+}
+
+impl From<NetError> for DhcpError {
+    fn from(e: NetError) -> Self {
+        match e {
+            NetError::TimedOut => DhcpError::NoServer,
+            e => DhcpError::Net(e),
+        }
+    }
+}
+
+impl fmt::Display for DhcpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DhcpError::NoServer => write!(f, "no DHCP server answered"),
+            DhcpError::Refused => write!(f, "refused by the DHCP server"),
+            DhcpError::Net(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// An address leased from a server
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub config: IfConfig,
+    pub dns: Vec<Ipv4Addr>,
+    /// The server that granted it
+    pub server: Ipv4Addr,
+    /// How long it lasts, in seconds
+    pub lease_secs: u32,
+    /// When to renew it, in seconds after it was granted
+    pub renew_secs: u32,
+    /// Timer tick it was granted at
+    pub granted: u64,
+}
+
+impl Lease {
+    /// Timer tick at which to renew it
+    fn renew_at(&self) -> u64 {
+        self.granted + secs_to_ticks(self.renew_secs as u64)
+    }
+
+    /// Timer tick at which it runs out
+    fn expires_at(&self) -> u64 {
+        self.granted + secs_to_ticks(self.lease_secs as u64)
+    }
+
+    /// Seconds left before it runs out
+    pub fn remaining_secs(&self) -> u64 {
+        let left = self.expires_at().saturating_sub(sched::ticks());
+        left * TICK_US as u64 / 1_000_000
+    }
+}
+
+/// What the client knows of one interface
+#[derive(Default)]
+struct Client {
+    lease: Option<Lease>,
+    /// Timer tick before which to leave the interface alone
+    wait_until: u64,
+    /// The link was up last time
+    link: bool,
+}
+
+/// Clients by interface name
+static CLIENTS: Mutex<BTreeMap<String, Client>> = Mutex::new(BTreeMap::new());
+
+const fn secs_to_ticks(secs: u64) -> u64 {
+    secs * 1_000_000 / TICK_US as u64
+}
+
+/// The lease held on `iface`
+pub fn lease(iface: &str) -> Option<Lease> {
+    CLIENTS.lock().get(iface)?.lease.clone()
+}
+
+/// DNS servers of every lease, in interface order
+pub fn dns_servers() -> Vec<Ipv4Addr> {
+    let clients = CLIENTS.lock();
+    let mut servers: Vec<Ipv4Addr> = Vec::new();
+    for lease in clients.values().filter_map(|client| client.lease.as_ref()) {
+        for server in &lease.dns {
+            if !servers.contains(server) {
+                servers.push(*server);
+            }
+        }
+    }
+    servers
+}
+
+/// Get, renew or give up a lease on `iface`, whatever is due
+pub fn maintain(iface: &InterfaceInfo) {
+    let now = sched::ticks();
+    let (lease, due, link_back) = {
+        let mut clients = CLIENTS.lock();
+        let client = clients.entry(iface.name.clone()).or_default();
+        let link_back = iface.link && !client.link;
+        client.link = iface.link;
+        (client.lease.clone(), now >= client.wait_until, link_back)
+    };
+    if !iface.link {
+        return;
+    }
+
+    let result = match lease {
+        Some(lease) if now >= lease.expires_at() => {
+            log::warn!(
+                "{}: DHCP lease on {} ran out",
+                iface.name,
+                lease.config.addr
+            );
+            let _ = iface::set_config(&iface.name, None);
+            store(&iface.name, None, now);
+            return;
+        }
+        Some(lease) if link_back || (due && now >= lease.renew_at()) => renew(iface, &lease),
+        Some(_) => return,
+        None if due => obtain(iface),
+        None => return,
+    };
+
+    match result {
+        Ok(lease) => {
+            let renewed = self::lease(&iface.name).is_some_and(|old| old.config == lease.config);
+            if !renewed {
+                let _ = iface::set_config(&iface.name, Some(lease.config));
+            }
+            log::info!(
+                "{}: DHCP lease on {} from {} for {}s",
+                iface.name,
+                lease.config.addr,
+                lease.server,
+                lease.lease_secs
+            );
+            store(&iface.name, Some(lease), now);
+        }
+        Err(DhcpError::Refused) if self::lease(&iface.name).is_some() => {
+            log::warn!("{}: DHCP lease taken back", iface.name);
+            let _ = iface::set_config(&iface.name, None);
+            store(&iface.name, None, now);
+        }
+        Err(e) => {
+            log::warn!("{}: DHCP failed: {}", iface.name, e);
+            let mut clients = CLIENTS.lock();
+            let client = clients.entry(iface.name.clone()).or_default();
+            client.wait_until = now + secs_to_ticks(RETRY_SECS);
+        }
+    }
+}
+
+/// Record the lease on `iface`, or its loss
+fn store(iface: &str, lease: Option<Lease>, now: u64) {
+    let mut clients = CLIENTS.lock();
+    let client = clients.entry(String::from(iface)).or_default();
+    client.lease = lease;
+    client.wait_until = now;
+}
+
+/// Ask for an address from whichever server offers one first
+fn obtain(iface: &InterfaceInfo) -> Result<Lease, DhcpError> {
+    let socket = UdpSocket::bind(CLIENT_PORT)?;
+    let xid = transaction_id(iface.mac);
+    let discover = Message::new(DISCOVER, xid, iface.mac);
+    let offer = exchange(&socket, iface, &discover, Ipv4Addr::BROADCAST, |reply| {
+        reply.message_type == OFFER
+    })?;
+
+    let mut request = Message::new(REQUEST, xid, iface.mac);
+    request.requested = Some(offer.yiaddr);
+    request.server = offer.server;
+    let ack = exchange(&socket, iface, &request, Ipv4Addr::BROADCAST, |reply| {
+        matches!(reply.message_type, ACK | NAK) && reply.server == offer.server
+    })?;
+    ack.lease()
+}
+
+/// Ask the server that granted `lease` for more time
+fn renew(iface: &InterfaceInfo, lease: &Lease) -> Result<Lease, DhcpError> {
+    let socket = UdpSocket::bind(CLIENT_PORT)?;
+    let mut request = Message::new(REQUEST, transaction_id(iface.mac), iface.mac);
+    // Unicast from the address held, which the server knows it by
+    request.ciaddr = lease.config.addr;
+    let ack = exchange(&socket, iface, &request, lease.server, |reply| {
+        matches!(reply.message_type, ACK | NAK)
+    })?;
+    ack.lease()
+}
+
+/// Send `message` to the server at `dst` until a reply `wanted` comes
+fn exchange(
+    socket: &UdpSocket,
+    iface: &InterfaceInfo,
+    message: &Message,
+    dst: Ipv4Addr,
+    wanted: impl Fn(&Message) -> bool,
+) -> Result<Message, DhcpError> {
+    let bytes = message.build();
+    let mut buf = [0u8; 1024];
+    let mut wait_ms = FIRST_WAIT_MS;
+    for _ in 0..ATTEMPTS {
+        if dst == Ipv4Addr::BROADCAST {
+            socket.send_to_via(&iface.name, &bytes, dst, SERVER_PORT)?;
+        } else {
+            socket.send_to(&bytes, dst, SERVER_PORT)?;
+        }
+        let deadline = sched::ticks() + (wait_ms as u64 * 1000).div_ceil(TICK_US as u64);
+        loop {
+            let left = deadline.saturating_sub(sched::ticks());
+            if left == 0 {
+                break;
+            }
+            let left_ms = (left * TICK_US as u64 / 1000) as u32;
+            let n = match socket.recv_from(&mut buf, Some(left_ms.max(1))) {
+                Ok((n, _, _)) => n,
+                Err(NetError::TimedOut) => break,
+                Err(e) => return Err(e.into()),
+            };
+            let Some(reply) = Message::parse(&buf[..n]) else {
+                continue;
+            };
+            if reply.xid == message.xid && reply.chaddr == message.chaddr && wanted(&reply) {
+                return Ok(reply);
+            }
+        }
+        wait_ms *= 2;
+    }
+    Err(DhcpError::NoServer)
+}
+
+/// A transaction ID unlikely to be another client's
+fn transaction_id(mac: MacAddress) -> u32 {
+    let [a, b, c, d, e, f] = mac.0;
+    let now = uptime_us().unwrap_or(sched::ticks()) as u32;
+    u32::from_be_bytes([c ^ a, d ^ b, e, f]) ^ now.rotate_left(16)
+}
+
+/// The parts of a DHCP message the client uses
+#[derive(Debug, Clone, Default)]
+struct Message {
+    op: u8,
+    xid: u32,
+    ciaddr: Ipv4Addr,
+    yiaddr: Ipv4Addr,
+    chaddr: MacAddress,
+    message_type: u8,
+    requested: Option<Ipv4Addr>,
+    server: Ipv4Addr,
+    subnet_mask: Option<Ipv4Addr>,
+    router: Option<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+    lease_secs: Option<u32>,
+    renew_secs: Option<u32>,
+}
+
+impl Message {
+    fn new(message_type: u8, xid: u32, chaddr: MacAddress) -> Self {
+        Self {
+            op: BOOTREQUEST,
+            xid,
+            chaddr,
+            message_type,
+            ..Self::default()
+        }
+    }
+
+    fn build(&self) -> Vec<u8> {
+        let mut bytes = alloc::vec![0u8; FIXED_LEN];
+        bytes[0] = self.op;
+        bytes[1] = 1; // Ethernet
+        bytes[2] = 6;
+        bytes[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        // Ask for broadcast replies while we have no address
+        if self.ciaddr.is_unspecified() {
+            bytes[10] = 0x80;
+        }
+        bytes[12..16].copy_from_slice(&self.ciaddr.0);
+        bytes[28..34].copy_from_slice(&self.chaddr.0);
+        bytes.extend_from_slice(&MAGIC_COOKIE);
+
+        bytes.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, self.message_type]);
+        if let Some(requested) = self.requested {
+            bytes.extend_from_slice(&[OPT_REQUESTED_IP, 4]);
+            bytes.extend_from_slice(&requested.0);
+        }
+        if !self.server.is_unspecified() {
+            bytes.extend_from_slice(&[OPT_SERVER_ID, 4]);
+            bytes.extend_from_slice(&self.server.0);
+        }
+        bytes.extend_from_slice(&[
+            OPT_PARAMS,
+            5,
+            OPT_SUBNET_MASK,
+            OPT_ROUTER,
+            OPT_DNS,
+            OPT_LEASE_TIME,
+            OPT_RENEWAL_TIME,
+        ]);
+        bytes.push(OPT_END);
+        // Some servers ignore messages shorter than BOOTP's 300 bytes
+        bytes.resize(bytes.len().max(300), OPT_PAD);
+        bytes
+    }
+
+    /// A server's reply, if `bytes` is one
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < FIXED_LEN + MAGIC_COOKIE.len()
+            || bytes[0] != BOOTREPLY
+            || bytes[FIXED_LEN..FIXED_LEN + 4] != MAGIC_COOKIE
+        {
+            return None;
+        }
+        let addr = |at: &[u8]| Some(Ipv4Addr(at.get(..4)?.try_into().ok()?));
+        let secs = |at: &[u8]| Some(u32::from_be_bytes(at.get(..4)?.try_into().ok()?));
+        let mut message = Self {
+            op: bytes[0],
+            xid: u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
+            ciaddr: addr(&bytes[12..])?,
+            yiaddr: addr(&bytes[16..])?,
+            chaddr: MacAddress(bytes[28..34].try_into().unwrap()),
+            ..Self::default()
+        };
+
+        let mut options = &bytes[FIXED_LEN + 4..];
+        while let [code, rest @ ..] = options {
+            match *code {
+                OPT_PAD => {
+                    options = rest;
+                    continue;
+                }
+                OPT_END => break,
+                _ => {}
+            }
+            let [len, rest @ ..] = rest else {
+                break;
+            };
+            let value = rest.get(..*len as usize)?;
+            match *code {
+                OPT_MESSAGE_TYPE => message.message_type = *value.first()?,
+                OPT_SERVER_ID => message.server = addr(value)?,
+                OPT_SUBNET_MASK => message.subnet_mask = addr(value),
+                OPT_ROUTER => message.router = addr(value),
+                OPT_DNS => message.dns = value.chunks_exact(4).filter_map(addr).collect(),
+                OPT_LEASE_TIME => message.lease_secs = secs(value),
+                OPT_RENEWAL_TIME => message.renew_secs = secs(value),
+                _ => {}
+            }
+            options = &rest[*len as usize..];
+        }
+        (message.message_type != 0).then_some(message)
+    }
+
+    /// The lease an ACK grants
+    fn lease(&self) -> Result<Lease, DhcpError> {
+        if self.message_type != ACK {
+            return Err(DhcpError::Refused);
+        }
+        let prefix_len = self
+            .subnet_mask
+            .map_or(24, |mask| mask.to_bits().leading_ones() as u8);
+        let lease_secs = self.lease_secs.unwrap_or(DEFAULT_LEASE_SECS);
+        Ok(Lease {
+            config: IfConfig {
+                addr: self.yiaddr,
+                prefix_len,
+                gateway: self.router,
+            },
+            dns: self.dns.clone(),
+            server: self.server,
+            lease_secs,
+            renew_secs: self.renew_secs.unwrap_or(lease_secs / 2).min(lease_secs),
+            granted: sched::ticks(),
+        })
+    }
+}
@@ -0,0 +1,48 @@
+//! Ethernet II framing
+
+use alloc::vec::Vec;
+use drivers::hal::net::MacAddress;
+
+/// Destination, source and EtherType
+pub const HEADER_LEN: usize = 14;
+
+/// Shortest frame without the FCS; shorter ones are padded to it
+pub const MIN_FRAME: usize = 60;
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+/// A received frame's header, and its payload
+pub struct Frame<'a> {
+    pub dst: MacAddress,
+    pub src: MacAddress,
+    pub ethertype: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    /// Split `frame` into header and payload, if it is long enough
+    pub fn parse(frame: &'a [u8]) -> Option<Self> {
+        if frame.len() < HEADER_LEN {
+            return None;
+        }
+        let mac = |at: usize| MacAddress(frame[at..at + 6].try_into().unwrap());
+        Some(Self {
+            dst: mac(0),
+            src: mac(6),
+            ethertype: u16::from_be_bytes([frame[12], frame[13]]),
+            payload: &frame[HEADER_LEN..],
+        })
+    }
+}
+
+/// A frame carrying `payload`, padded to the minimum length
+pub fn build(dst: MacAddress, src: MacAddress, ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity((HEADER_LEN + payload.len()).max(MIN_FRAME));
+    frame.extend_from_slice(&dst.0);
+    frame.extend_from_slice(&src.0);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame.resize(frame.len().max(MIN_FRAME), 0);
+    frame
+}
@@ -0,0 +1,182 @@
+//! ICMP echo
+//!
+//! Echo requests to our address are answered straight from the interface
+//! they arrived on, to the hardware address they came from; those sent
+//! to a broadcast address are not. A [`Pinger`] sends requests of its
+//! own and takes the replies to them. Every request carries the time it
+//! was sent, by the counting timer, so its reply tells the round trip.
+//! Other ICMP messages are ignored.
+
+use super::NetError;
+use super::ethernet::ETHERTYPE_IPV4;
+use super::iface::Interface;
+use super::ipv4::{self, Ipv4Addr, PROTO_ICMP, Packet};
+use crate::process::sched::{TICK_US, WaitQueue};
+use crate::subsystems::uptime_us;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use drivers::hal::net::MacAddress;
+use spin::Mutex;
+
+/// Type, code, checksum, identifier and sequence number
+pub const HEADER_LEN: usize = 8;
+
+const ECHO_REPLY: u8 = 0;
+const ECHO_REQUEST: u8 = 8;
+
+/// Bytes of the payload that hold the time the request was sent
+const TIMESTAMP_LEN: usize = 8;
+
+/// Replies queued on a pinger, beyond which more are dropped
+const QUEUE_MAX: usize = 16;
+
+/// An answer to one of our echo requests
+#[derive(Debug, Copy, Clone)]
+pub struct EchoReply {
+    pub src: Ipv4Addr,
+    pub seq: u16,
+    pub ttl: u8,
+    /// Bytes of ICMP payload
+    pub len: usize,
+    /// Round trip, in microseconds
+    pub rtt_us: u64,
+}
+
+/// What the stack shares with a pinger
+struct Endpoint {
+    replies: Mutex<VecDeque<EchoReply>>,
+    /// Tasks waiting for a reply
+    arrived: WaitQueue,
+}
+
+/// Pingers by identifier
+static PINGERS: Mutex<BTreeMap<u16, Arc<Endpoint>>> = Mutex::new(BTreeMap::new());
+
+/// Identifier of the next pinger
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Sends echo requests and takes the replies to them. Replies are told
+/// apart from other pingers' by the identifier the requests carry.
+pub struct Pinger {
+    id: u16,
+    endpoint: Arc<Endpoint>,
+}
+
+impl Pinger {
+    pub fn new() -> Self {
+        let endpoint = Arc::new(Endpoint {
+            replies: Mutex::new(VecDeque::new()),
+            arrived: WaitQueue::new(),
+        });
+        let mut pingers = PINGERS.lock();
+        let id = loop {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed) as u16;
+            if id != 0 && !pingers.contains_key(&id) {
+                break id;
+            }
+        };
+        pingers.insert(id, endpoint.clone());
+        Self { id, endpoint }
+    }
+
+    /// Send request `seq` to `dst`, with `payload_len` bytes of payload
+    /// (at least [`TIMESTAMP_LEN`], which hold the time it was sent)
+    pub fn send(&self, dst: Ipv4Addr, seq: u16, payload_len: usize) -> Result<(), NetError> {
+        let mut payload = alloc::vec![0u8; payload_len.max(TIMESTAMP_LEN)];
+        for (i, byte) in payload.iter_mut().enumerate().skip(TIMESTAMP_LEN) {
+            *byte = i as u8;
+        }
+        let sent = uptime_us().unwrap_or(0);
+        payload[..TIMESTAMP_LEN].copy_from_slice(&sent.to_be_bytes());
+        let message = build(ECHO_REQUEST, self.id, seq, &payload);
+        ipv4::send(dst, PROTO_ICMP, |_| message.clone())
+    }
+
+    /// Sleep until a reply arrives, for at most `timeout_ms`
+    /// milliseconds
+    pub fn recv(&self, timeout_ms: u32) -> Result<EchoReply, NetError> {
+        let mut reply = None;
+        let take = || {
+            reply = self.endpoint.replies.lock().pop_front();
+            reply.is_some()
+        };
+        let ticks = (timeout_ms as u64 * 1000).div_ceil(TICK_US as u64);
+        self.endpoint.arrived.sleep_on_timeout(take, ticks);
+        reply.ok_or(NetError::TimedOut)
+    }
+}
+
+impl Default for Pinger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Pinger {
+    fn drop(&mut self) {
+        PINGERS.lock().remove(&self.id);
+    }
+}
+
+/// An ICMP message with its checksum filled in
+fn build(kind: u8, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER_LEN + payload.len());
+    message.extend_from_slice(&[kind, 0, 0, 0]);
+    message.extend_from_slice(&id.to_be_bytes());
+    message.extend_from_slice(&seq.to_be_bytes());
+    message.extend_from_slice(payload);
+    let sum = ipv4::checksum(&[&message]);
+    message[2..4].copy_from_slice(&sum.to_be_bytes());
+    message
+}
+
+/// Take in an ICMP message that arrived on `iface` from `src_mac`
+pub(super) fn receive(iface: &mut Interface, src_mac: MacAddress, packet: &Packet) {
+    let message = packet.payload;
+    if message.len() < HEADER_LEN || ipv4::checksum(&[message]) != 0 {
+        iface.stats.rx_dropped += 1;
+        return;
+    }
+    let id = u16::from_be_bytes([message[4], message[5]]);
+    let seq = u16::from_be_bytes([message[6], message[7]]);
+    let payload = &message[HEADER_LEN..];
+
+    match message[0] {
+        ECHO_REQUEST => {
+            let Some(config) = iface.config else {
+                return;
+            };
+            if packet.dst != config.addr && !iface.is_loopback() {
+                return;
+            }
+            let reply = build(ECHO_REPLY, id, seq, payload);
+            let packet = ipv4::build(packet.dst, packet.src, PROTO_ICMP, &reply);
+            // Lost like any other frame; the sender asks again
+            let _ = iface.transmit(src_mac, ETHERTYPE_IPV4, &packet);
+        }
+        ECHO_REPLY => {
+            let Some(endpoint) = PINGERS.lock().get(&id).cloned() else {
+                return;
+            };
+            let sent = payload
+                .get(..TIMESTAMP_LEN)
+                .map_or(0, |sent| u64::from_be_bytes(sent.try_into().unwrap()));
+            let reply = EchoReply {
+                src: packet.src,
+                seq,
+                ttl: packet.ttl,
+                len: payload.len(),
+                rtt_us: uptime_us().map_or(0, |now| now.saturating_sub(sent)),
+            };
+            let mut replies = endpoint.replies.lock();
+            if replies.len() < QUEUE_MAX {
+                replies.push_back(reply);
+            }
+            drop(replies);
+            endpoint.arrived.wake_all();
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,491 @@
+//! Network interfaces
+//!
+//! An interface is a network device as the stack sees it: whether it has
+//! been brought up, its IPv4 configuration, its ARP cache and its
+//! counters. Interfaces are named after their device, and come and go
+//! with it.
+//!
+//! A down interface neither sends nor takes in frames. One that is up
+//! without an address still takes in whatever is sent to it, and sends
+//! from `0.0.0.0` when told which interface to use, which is how an
+//! address is asked for in the first place.
+//!
+//! Routing is by interface: a destination on an interface's subnet is
+//! sent there directly, and any other through the first gateway
+//! configured. Our own addresses, and `127.0.0.0/8`, go through `lo`.
+
+use super::arp::{self, ArpCache};
+use super::ethernet::{self, ETHERTYPE_ARP, ETHERTYPE_IPV4, Frame};
+use super::ipv4::{self, Ipv4Addr};
+use super::{NetError, loopback};
+use crate::process::sched::TICK_US;
+use crate::subsystems::device_manager;
+use crate::sync::Mutex;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use common::sync::Once;
+use drivers::device_manager::{DeviceClass, DeviceEvent, DeviceEventReceiver};
+use drivers::hal::net::{DynNetworkDevice, ETH_FRAME_MAX, MacAddress, NetworkDeviceError};
+
+/// Frames taken from one interface per poll, so a busy one cannot keep
+/// the others waiting
+const RX_BUDGET: usize = 32;
+
+/// ARP requests sent for a next hop before giving up on it
+const ARP_ATTEMPTS: usize = 4;
+
+/// How long to wait for an ARP reply before asking again, in milliseconds
+const ARP_RETRY_MS: u32 = 250;
+
+/// IPv4 configuration of an interface
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IfConfig {
+    pub addr: Ipv4Addr,
+    pub prefix_len: u8,
+    pub gateway: Option<Ipv4Addr>,
+}
+
+impl IfConfig {
+    pub fn netmask(&self) -> Ipv4Addr {
+        Ipv4Addr::netmask(self.prefix_len)
+    }
+
+    /// The subnet's broadcast address
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from_bits(self.addr.to_bits() | !self.netmask().to_bits())
+    }
+
+    /// Is `ip` on the subnet?
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        let mask = self.netmask().to_bits();
+        ip.to_bits() & mask == self.addr.to_bits() & mask
+    }
+}
+
+/// Traffic counters of an interface
+#[derive(Debug, Copy, Clone, Default)]
+pub struct IfStats {
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    /// Frames received but malformed, or with nowhere to go
+    pub rx_dropped: u64,
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+    /// Frames the device failed to send
+    pub tx_errors: u64,
+}
+
+/// A snapshot of an interface
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    pub name: String,
+    pub mac: MacAddress,
+    pub mtu: usize,
+    /// Brought up
+    pub up: bool,
+    /// The device has a link
+    pub link: bool,
+    pub config: Option<IfConfig>,
+    pub stats: IfStats,
+}
+
+pub(super) struct Interface {
+    pub name: String,
+    device: Arc<spin::Mutex<dyn DynNetworkDevice>>,
+    pub mac: MacAddress,
+    pub mtu: usize,
+    pub up: bool,
+    link: bool,
+    /// Frames go straight back to us, so no ARP
+    loopback: bool,
+    pub config: Option<IfConfig>,
+    pub arp: ArpCache,
+    pub stats: IfStats,
+}
+
+impl Interface {
+    fn new(name: String, device: Arc<spin::Mutex<dyn DynNetworkDevice>>) -> Self {
+        let (mac, mtu, link) = {
+            let device = device.lock();
+            (device.mac_address(), device.mtu(), device.link_up())
+        };
+        Self {
+            loopback: name == loopback::NAME,
+            name,
+            device,
+            mac,
+            mtu,
+            up: false,
+            link,
+            config: None,
+            arp: ArpCache::new(),
+            stats: IfStats::default(),
+        }
+    }
+
+    fn info(&self) -> InterfaceInfo {
+        InterfaceInfo {
+            name: self.name.clone(),
+            mac: self.mac,
+            mtu: self.mtu,
+            up: self.up,
+            link: self.link,
+            config: self.config,
+            stats: self.stats,
+        }
+    }
+
+    /// Send `payload` to `dst` in a frame of `ethertype`
+    pub fn transmit(
+        &mut self,
+        dst: MacAddress,
+        ethertype: u16,
+        payload: &[u8],
+    ) -> Result<(), NetError> {
+        if !self.up {
+            return Err(NetError::InterfaceDown);
+        }
+        if payload.len() > self.mtu {
+            return Err(NetError::TooLong);
+        }
+        let frame = ethernet::build(dst, self.mac, ethertype, payload);
+        match self.device.lock().transmit(&frame) {
+            Ok(()) => {
+                self.stats.tx_packets += 1;
+                self.stats.tx_bytes += frame.len() as u64;
+                Ok(())
+            }
+            Err(e) => {
+                self.stats.tx_errors += 1;
+                Err(NetError::Device(e))
+            }
+        }
+    }
+
+    /// Whether frames sent go straight back to us
+    pub fn is_loopback(&self) -> bool {
+        self.loopback
+    }
+
+    /// Whether a packet to `dst` is for us. Without an address, anything
+    /// is.
+    pub fn accepts(&self, dst: Ipv4Addr) -> bool {
+        match self.config {
+            _ if self.loopback => true,
+            None => true,
+            Some(config) => {
+                dst == config.addr || dst == Ipv4Addr::BROADCAST || dst == config.broadcast()
+            }
+        }
+    }
+
+    /// The hardware address to send to `next_hop` at, if it needs no
+    /// asking
+    fn hardware_addr(&self, next_hop: Ipv4Addr) -> Option<MacAddress> {
+        if self.loopback {
+            Some(MacAddress::ZERO)
+        } else if next_hop == Ipv4Addr::BROADCAST
+            || self
+                .config
+                .is_some_and(|config| config.broadcast() == next_hop)
+        {
+            Some(MacAddress::BROADCAST)
+        } else {
+            self.arp.lookup(next_hop)
+        }
+    }
+
+    /// Take in what the device has received
+    fn poll(&mut self, buf: &mut [u8]) {
+        let link = self.device.lock().link_up();
+        if link != self.link {
+            self.link = link;
+            log::info!("{}: link {}", self.name, if link { "up" } else { "down" });
+        }
+        if !self.up {
+            return;
+        }
+
+        for _ in 0..RX_BUDGET {
+            let received = self.device.lock().receive(buf);
+            let n = match received {
+                Ok(n) => n,
+                Err(NetworkDeviceError::WouldBlock) => break,
+                Err(e) => {
+                    log::debug!("{}: receive failed: {:?}", self.name, e);
+                    self.stats.rx_dropped += 1;
+                    break;
+                }
+            };
+            self.stats.rx_packets += 1;
+            self.stats.rx_bytes += n as u64;
+            self.input(&buf[..n]);
+        }
+        self.arp.expire();
+    }
+
+    /// Hand a received frame up the stack
+    fn input(&mut self, bytes: &[u8]) {
+        let Some(frame) = Frame::parse(bytes) else {
+            self.stats.rx_dropped += 1;
+            return;
+        };
+        if frame.dst != self.mac && !frame.dst.is_multicast() {
+            return;
+        }
+        match frame.ethertype {
+            ETHERTYPE_ARP => arp::receive(self, frame.payload),
+            ETHERTYPE_IPV4 => ipv4::receive(self, frame.src, frame.payload),
+            _ => {}
+        }
+    }
+}
+
+/// Every interface. Held while frames are sent and taken in, so only by
+/// tasks.
+static INTERFACES: Mutex<Vec<Interface>> = Mutex::new(Vec::new());
+
+/// Network devices registered and removed since [`init`]
+static EVENTS: Once<DeviceEventReceiver> = Once::new();
+
+/// Register `lo` and make an interface of every network device, and of
+/// those registered later. `lo` is brought up as `127.0.0.1/8`.
+pub fn init() {
+    loopback::register();
+    let (devices, events) = {
+        let mut dm = device_manager().lock();
+        let devices: Vec<_> = dm
+            .by_class(DeviceClass::Network)
+            .filter_map(|(name, _)| Some((String::from(name), dm.network(name)?)))
+            .collect();
+        (devices, dm.subscribe())
+    };
+    EVENTS.call_once(|| events);
+
+    let mut interfaces = INTERFACES.lock();
+    for (name, device) in devices {
+        add(&mut interfaces, name, device);
+    }
+    drop(interfaces);
+
+    let localhost = IfConfig {
+        addr: Ipv4Addr::LOCALHOST,
+        prefix_len: 8,
+        gateway: None,
+    };
+    let _ = set_config(loopback::NAME, Some(localhost));
+    let _ = set_up(loopback::NAME, true);
+}
+
+fn add(
+    interfaces: &mut Vec<Interface>,
+    name: String,
+    device: Arc<spin::Mutex<dyn DynNetworkDevice>>,
+) {
+    let iface = Interface::new(name, device);
+    log::info!("{}: network interface, MAC {}", iface.name, iface.mac);
+    interfaces.retain(|other| other.name != iface.name);
+    interfaces.push(iface);
+}
+
+/// Pick up network devices registered or removed since the last call
+fn update(interfaces: &mut Vec<Interface>) {
+    let Some(events) = EVENTS.get() else {
+        return;
+    };
+    for event in events.drain() {
+        if event.class() != DeviceClass::Network {
+            continue;
+        }
+        match event {
+            DeviceEvent::Added { name, .. } => {
+                let device = device_manager().lock().network(&name);
+                if let Some(device) = device {
+                    add(interfaces, name, device);
+                }
+            }
+            DeviceEvent::Removed { name, .. } => {
+                interfaces.retain(|iface| iface.name != name);
+                log::info!("{}: network interface removed", name);
+            }
+        }
+    }
+}
+
+/// Take in what every interface has received. Run by `netd`.
+pub fn poll() {
+    let mut buf = vec![0u8; ETH_FRAME_MAX];
+    let mut interfaces = INTERFACES.lock();
+    update(&mut interfaces);
+    for iface in interfaces.iter_mut() {
+        iface.poll(&mut buf);
+    }
+}
+
+/// Every interface, in the order they appeared
+pub fn interfaces() -> Vec<InterfaceInfo> {
+    INTERFACES.lock().iter().map(Interface::info).collect()
+}
+
+/// The interface `name`
+pub fn interface(name: &str) -> Option<InterfaceInfo> {
+    INTERFACES
+        .lock()
+        .iter()
+        .find(|iface| iface.name == name)
+        .map(Interface::info)
+}
+
+/// Run `f` on the interface `name`
+fn with_interface<R>(name: &str, f: impl FnOnce(&mut Interface) -> R) -> Result<R, NetError> {
+    let mut interfaces = INTERFACES.lock();
+    let iface = interfaces
+        .iter_mut()
+        .find(|iface| iface.name == name)
+        .ok_or(NetError::NoSuchInterface)?;
+    Ok(f(iface))
+}
+
+/// Give the interface `name` an address, or take it away with `None`.
+/// What it knew of its neighbours is forgotten.
+pub fn set_config(name: &str, config: Option<IfConfig>) -> Result<(), NetError> {
+    with_interface(name, |iface| {
+        iface.config = config;
+        iface.arp.clear();
+        match config {
+            Some(config) => log::info!(
+                "{}: {}/{}{}",
+                iface.name,
+                config.addr,
+                config.prefix_len,
+                config
+                    .gateway
+                    .map(|gw| alloc::format!(" via {}", gw))
+                    .unwrap_or_default()
+            ),
+            None => log::info!("{}: address removed", iface.name),
+        }
+    })
+}
+
+/// Set the address of the interface `name`, keeping its gateway
+pub fn set_ip(name: &str, addr: Ipv4Addr, prefix_len: u8) -> Result<(), NetError> {
+    let gateway = interface(name)
+        .ok_or(NetError::NoSuchInterface)?
+        .config
+        .and_then(|config| config.gateway);
+    set_config(
+        name,
+        Some(IfConfig {
+            addr,
+            prefix_len: prefix_len.min(32),
+            gateway,
+        }),
+    )
+}
+
+/// Bring the interface `name` up or down
+pub fn set_up(name: &str, up: bool) -> Result<(), NetError> {
+    with_interface(name, |iface| {
+        if iface.up != up {
+            iface.up = up;
+            log::info!("{}: {}", iface.name, if up { "up" } else { "down" });
+        }
+    })
+}
+
+/// The interface and next hop to reach `dst` by
+fn route(interfaces: &[Interface], dst: Ipv4Addr) -> Result<(usize, Ipv4Addr), NetError> {
+    let usable = |iface: &Interface| iface.up && !iface.loopback;
+    let ours = interfaces
+        .iter()
+        .any(|iface| iface.config.is_some_and(|config| config.addr == dst));
+    if dst.is_loopback() || ours {
+        return interfaces
+            .iter()
+            .position(|iface| iface.loopback && iface.up)
+            .map(|index| (index, dst))
+            .ok_or(NetError::NoRoute);
+    }
+    if dst == Ipv4Addr::BROADCAST {
+        return interfaces
+            .iter()
+            .position(|iface| usable(iface) && iface.config.is_some())
+            .map(|index| (index, dst))
+            .ok_or(NetError::NoRoute);
+    }
+    if let Some(index) = interfaces
+        .iter()
+        .position(|iface| usable(iface) && iface.config.is_some_and(|config| config.contains(dst)))
+    {
+        return Ok((index, dst));
+    }
+    interfaces
+        .iter()
+        .enumerate()
+        .filter(|(_, iface)| usable(iface))
+        .find_map(|(index, iface)| Some((index, iface.config?.gateway?)))
+        .ok_or(NetError::NoRoute)
+}
+
+/// The interface `via` and the next hop on it to `dst`
+fn route_via(
+    interfaces: &[Interface],
+    via: &str,
+    dst: Ipv4Addr,
+) -> Result<(usize, Ipv4Addr), NetError> {
+    let index = interfaces
+        .iter()
+        .position(|iface| iface.name == via)
+        .ok_or(NetError::NoSuchInterface)?;
+    let next_hop = match interfaces[index].config {
+        Some(config) if dst != Ipv4Addr::BROADCAST && !config.contains(dst) => {
+            config.gateway.unwrap_or(dst)
+        }
+        _ => dst,
+    };
+    Ok((index, next_hop))
+}
+
+/// Send an IPv4 packet to `dst` out of `via`, or the interface routing
+/// it, resolving the next hop first. `payload` is given the source
+/// address.
+pub(super) fn send_ipv4(
+    via: Option<&str>,
+    dst: Ipv4Addr,
+    protocol: u8,
+    payload: impl Fn(Ipv4Addr) -> Vec<u8>,
+) -> Result<(), NetError> {
+    let retry_ticks = (ARP_RETRY_MS as u64 * 1000).div_ceil(TICK_US as u64);
+    for attempt in 0..=ARP_ATTEMPTS {
+        let learnt = arp::learnt();
+        {
+            let mut interfaces = INTERFACES.lock();
+            let (index, next_hop) = match via {
+                Some(via) => route_via(&interfaces, via, dst)?,
+                None => route(&interfaces, dst)?,
+            };
+            let iface = &mut interfaces[index];
+            if !iface.up {
+                return Err(NetError::InterfaceDown);
+            }
+            let src = iface
+                .config
+                .map_or(Ipv4Addr::UNSPECIFIED, |config| config.addr);
+            if let Some(mac) = iface.hardware_addr(next_hop) {
+                let packet = ipv4::build(src, dst, protocol, &payload(src));
+                return iface.transmit(mac, ETHERTYPE_IPV4, &packet);
+            }
+            if attempt == ARP_ATTEMPTS {
+                break;
+            }
+            arp::request(iface, next_hop)?;
+        }
+        // Woken by any address learnt; a stranger's is checked and asked
+        // for again
+        arp::RESOLVED.sleep_on_timeout(|| arp::learnt() != learnt, retry_ticks);
+    }
+    Err(NetError::HostUnreachable)
+}
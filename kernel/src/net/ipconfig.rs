@@ -0,0 +1,167 @@
+//! Interface configuration at boot
+//!
+//! The `ip=` option of the command line says how to configure the
+//! network, in the form Linux takes:
+//!
+//! - `ip=dhcp` (or `on`, `any`): run the [DHCP client](super::dhcp) on
+//!   every network interface
+//! - `ip=<client>:<server>:<gateway>:<netmask>:<hostname>:<device>:<autoconf>:<dns0>:<dns1>`:
+//!   give `<device>`, or the first interface if it is empty, the address
+//!   `<client>`, or run DHCP on it if `<autoconf>` is `dhcp` or
+//!   `<client>` is empty. Trailing fields may be left out; the server
+//!   and hostname are ignored, and the netmask defaults to `/24`
+//! - `ip=off` (or `none`), the default: leave the interfaces down
+//!
+//! An `ipconfig` task does it, as the interfaces are found: one that
+//! appears later, such as a USB adapter, is configured then. With DHCP,
+//! the task stays to keep the leases.
+
+use super::dhcp;
+use super::iface::{self, IfConfig, InterfaceInfo};
+use super::ipv4::Ipv4Addr;
+use super::loopback;
+use crate::process::sched;
+use alloc::string::String;
+use alloc::vec::Vec;
+use common::sync::Once;
+
+/// How often the task looks at the interfaces, in milliseconds
+const CHECK_MS: u32 = 1000;
+
+/// Netmask of a static address given without one
+const DEFAULT_PREFIX_LEN: u8 = 24;
+
+/// What `ip=` asks for
+#[derive(Debug, Clone)]
+pub enum Mode {
+    Off,
+    /// DHCP on the device, or on every interface
+    Dhcp {
+        device: Option<&'static str>,
+    },
+    /// A fixed address on the device, or on the first interface
+    Static {
+        device: Option<&'static str>,
+        config: IfConfig,
+        dns: Vec<Ipv4Addr>,
+    },
+}
+
+impl Mode {
+    /// Parse the value of `ip=`
+    pub fn parse(value: &'static str) -> Option<Self> {
+        match value {
+            "off" | "none" => return Some(Mode::Off),
+            "dhcp" | "on" | "any" => return Some(Mode::Dhcp { device: None }),
+            _ => {}
+        }
+        let fields: Vec<&'static str> = value.split(':').collect();
+        let field = |index: usize| fields.get(index).copied().filter(|f| !f.is_empty());
+        let addr = |index: usize| field(index).map(Ipv4Addr::parse);
+        let device = field(5);
+
+        if matches!(field(6), Some("dhcp" | "on" | "any")) || field(0).is_none() {
+            return Some(Mode::Dhcp { device });
+        }
+        let prefix_len = match addr(3) {
+            Some(mask) => mask?.to_bits().leading_ones() as u8,
+            None => DEFAULT_PREFIX_LEN,
+        };
+        let gateway = match addr(2) {
+            Some(gateway) => Some(gateway?),
+            None => None,
+        };
+        Some(Mode::Static {
+            device,
+            config: IfConfig {
+                addr: addr(0)??,
+                prefix_len,
+                gateway,
+            },
+            dns: (7..=8).filter_map(addr).collect::<Option<_>>()?,
+        })
+    }
+}
+
+static MODE: Once<Mode> = Once::new();
+
+/// Start configuring the interfaces as `ip=` asks
+pub fn init() {
+    let Some(value) = crate::boot::cmdline().get("ip") else {
+        return;
+    };
+    let Some(mode) = Mode::parse(value) else {
+        log::warn!("Ignoring malformed ip={}", value);
+        return;
+    };
+    if matches!(mode, Mode::Off) {
+        return;
+    }
+    MODE.call_once(|| mode);
+    if let Err(e) = sched::spawn("ipconfig", run) {
+        log::warn!("Network not configured: {:?}", e);
+    }
+}
+
+/// DNS servers given on the command line, then those leased
+pub fn dns_servers() -> Vec<Ipv4Addr> {
+    let mut servers = match MODE.get() {
+        Some(Mode::Static { dns, .. }) => dns.clone(),
+        _ => Vec::new(),
+    };
+    for server in dhcp::dns_servers() {
+        if !servers.contains(&server) {
+            servers.push(server);
+        }
+    }
+    servers
+}
+
+/// Configure interfaces as they appear, and keep their DHCP leases
+fn run() {
+    let Some(mode) = MODE.get() else {
+        return;
+    };
+    // Interfaces taken over so far
+    let mut taken: Vec<String> = Vec::new();
+    loop {
+        let interfaces = iface::interfaces();
+        taken.retain(|name| interfaces.iter().any(|iface| iface.name == *name));
+        for iface in interfaces {
+            if iface.name == loopback::NAME {
+                continue;
+            }
+            match mode {
+                Mode::Off => return,
+                Mode::Dhcp { device } => {
+                    if device.is_some_and(|device| device != iface.name) {
+                        continue;
+                    }
+                    if take(&mut taken, &iface) {
+                        dhcp::maintain(&iface);
+                    }
+                }
+                Mode::Static { device, config, .. } => {
+                    if device.is_some_and(|device| device != iface.name) {
+                        continue;
+                    }
+                    if iface::set_config(&iface.name, Some(*config)).is_ok() {
+                        let _ = iface::set_up(&iface.name, true);
+                        return;
+                    }
+                }
+            }
+        }
+        sched::sleep_ms(CHECK_MS);
+    }
+}
+
+/// Bring `iface` up the first time it is seen, and say whether it is up:
+/// one brought down since is left alone
+fn take(taken: &mut Vec<String>, iface: &InterfaceInfo) -> bool {
+    if taken.contains(&iface.name) {
+        return iface.up;
+    }
+    taken.push(iface.name.clone());
+    iface.up || iface::set_up(&iface.name, true).is_ok()
+}
@@ -0,0 +1,200 @@
+//! IPv4
+//!
+//! Packets are sent without options and never fragmented; received ones
+//! with options have them skipped, and fragments are dropped.
+
+use super::iface::{self, Interface};
+use super::{NetError, icmp, tcp, udp};
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+use drivers::hal::net::MacAddress;
+
+/// Header length without options
+pub const HEADER_LEN: usize = 20;
+
+pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_TCP: u8 = 6;
+pub const PROTO_UDP: u8 = 17;
+
+/// Hops a packet sent here may take
+const DEFAULT_TTL: u8 = 64;
+
+/// Identification of the next packet sent
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// IPv4 address
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED: Self = Self([0; 4]);
+    pub const BROADCAST: Self = Self([255; 4]);
+    pub const LOCALHOST: Self = Self([127, 0, 0, 1]);
+
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Self {
+        Self([a, b, c, d])
+    }
+
+    pub const fn to_bits(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits.to_be_bytes())
+    }
+
+    /// The mask of a `prefix_len`-bit network prefix
+    pub const fn netmask(prefix_len: u8) -> Self {
+        match prefix_len {
+            0 => Self::UNSPECIFIED,
+            len if len >= 32 => Self::BROADCAST,
+            len => Self::from_bits(!0 << (32 - len)),
+        }
+    }
+
+    /// Parse dotted-quad notation, e.g. `10.0.2.15`
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut octets = [0u8; 4];
+        let mut parts = s.split('.');
+        for octet in &mut octets {
+            let part = parts.next()?;
+            if part.is_empty() || part.len() > 3 || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            *octet = part.parse().ok()?;
+        }
+        parts.next().is_none().then_some(Self(octets))
+    }
+
+    pub const fn is_unspecified(self) -> bool {
+        self.to_bits() == 0
+    }
+
+    pub const fn is_loopback(self) -> bool {
+        self.0[0] == 127
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+/// A received packet's header fields, and its payload
+pub struct Packet<'a> {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub protocol: u8,
+    pub ttl: u8,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Packet<'a> {
+    /// Check the header of `bytes` and split off the payload. Fragments
+    /// and damaged packets are refused.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN || bytes[0] >> 4 != 4 {
+            return None;
+        }
+        let header_len = (bytes[0] & 0x0F) as usize * 4;
+        let total_len = u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+        if header_len < HEADER_LEN || total_len < header_len || total_len > bytes.len() {
+            return None;
+        }
+        if checksum(&[&bytes[..header_len]]) != 0 {
+            return None;
+        }
+        // More fragments, or a fragment offset
+        if u16::from_be_bytes([bytes[6], bytes[7]]) & 0x3FFF != 0 {
+            return None;
+        }
+        let addr = |at: usize| Ipv4Addr(bytes[at..at + 4].try_into().unwrap());
+        Some(Self {
+            src: addr(12),
+            dst: addr(16),
+            protocol: bytes[9],
+            ttl: bytes[8],
+            payload: &bytes[header_len..total_len],
+        })
+    }
+}
+
+/// A packet from `src` to `dst` carrying `payload`, with Don't Fragment
+/// set
+pub fn build(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, payload: &[u8]) -> Vec<u8> {
+    let total_len = (HEADER_LEN + payload.len()) as u16;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed) as u16;
+    let mut packet = Vec::with_capacity(HEADER_LEN + payload.len());
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&total_len.to_be_bytes());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&0x4000u16.to_be_bytes());
+    packet.extend_from_slice(&[DEFAULT_TTL, protocol, 0, 0]);
+    packet.extend_from_slice(&src.0);
+    packet.extend_from_slice(&dst.0);
+    let sum = checksum(&[&packet]);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// The Internet checksum of `parts` laid end to end. Over data that
+/// includes its checksum, it is 0 when the data is intact.
+pub fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    let mut high = true;
+    for &byte in parts.iter().flat_map(|part| part.iter()) {
+        sum += if high {
+            (byte as u32) << 8
+        } else {
+            byte as u32
+        };
+        high = !high;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Send `payload` to `dst`, on the interface the route table picks.
+/// `payload` is built once the source address is known, as transport
+/// checksums cover it.
+pub fn send(
+    dst: Ipv4Addr,
+    protocol: u8,
+    payload: impl Fn(Ipv4Addr) -> Vec<u8>,
+) -> Result<(), NetError> {
+    iface::send_ipv4(None, dst, protocol, payload)
+}
+
+/// Like [`send`], but out of the interface `via` whatever the routes say,
+/// from `0.0.0.0` if it has no address yet
+pub fn send_via(
+    via: &str,
+    dst: Ipv4Addr,
+    protocol: u8,
+    payload: impl Fn(Ipv4Addr) -> Vec<u8>,
+) -> Result<(), NetError> {
+    iface::send_ipv4(Some(via), dst, protocol, payload)
+}
+
+/// Take in a packet that arrived on `iface` from `src_mac`
+pub(super) fn receive(iface: &mut Interface, src_mac: MacAddress, bytes: &[u8]) {
+    let Some(packet) = Packet::parse(bytes) else {
+        iface.stats.rx_dropped += 1;
+        return;
+    };
+    if !iface.accepts(packet.dst) {
+        return;
+    }
+    match packet.protocol {
+        PROTO_ICMP => icmp::receive(iface, src_mac, &packet),
+        PROTO_TCP => tcp::receive(iface, src_mac, &packet),
+        PROTO_UDP => udp::receive(iface, &packet),
+        _ => {}
+    }
+}
@@ -0,0 +1,71 @@
+//! Loopback network device
+//!
+//! Every frame sent on `lo` is received on it again, so the stack can
+//! talk to itself without hardware. It is registered in the device
+//! manager like any network device.
+
+use crate::subsystems::device_manager;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use drivers::device_manager::Device;
+use drivers::hal::net::{ETH_FRAME_MAX, MacAddress, NetworkDevice, NetworkDeviceError};
+
+/// Name of the loopback device and interface
+pub const NAME: &str = "lo";
+
+/// Frames sent but not yet received, beyond which sending fails
+const QUEUE_MAX: usize = 64;
+
+pub struct Loopback {
+    frames: VecDeque<Vec<u8>>,
+}
+
+impl Loopback {
+    pub const fn new() -> Self {
+        Self {
+            frames: VecDeque::new(),
+        }
+    }
+}
+
+impl NetworkDevice for Loopback {
+    type Error = NetworkDeviceError;
+
+    fn mac_address(&self) -> MacAddress {
+        MacAddress::ZERO
+    }
+
+    fn link_up(&self) -> bool {
+        true
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), Self::Error> {
+        if frame.len() > ETH_FRAME_MAX {
+            return Err(NetworkDeviceError::FrameTooLong);
+        }
+        if self.frames.len() >= QUEUE_MAX {
+            return Err(NetworkDeviceError::WouldBlock);
+        }
+        self.frames.push_back(frame.to_vec());
+        Ok(())
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let frame = self
+            .frames
+            .pop_front()
+            .ok_or(NetworkDeviceError::WouldBlock)?;
+        let dst = buf
+            .get_mut(..frame.len())
+            .ok_or(NetworkDeviceError::FrameTooLong)?;
+        dst.copy_from_slice(&frame);
+        Ok(frame.len())
+    }
+}
+
+/// Register `lo` in the device manager
+pub fn register() {
+    device_manager()
+        .lock()
+        .register(NAME.into(), Device::new_network(Loopback::new()));
+}
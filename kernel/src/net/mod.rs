@@ -0,0 +1,113 @@
+//! Networking
+//!
+//! A small IPv4 stack of the kernel's own over the network devices in the
+//! device manager: Ethernet II framing, ARP, IPv4 without fragments or
+//! options, ICMP echo, UDP sockets and [TCP](tcp) connections, with a
+//! DHCP and a TFTP client on top.
+//!
+//! Every network device becomes an [interface](iface), down and without
+//! an address until it is configured, and devices registered later are
+//! picked up as they appear. A loopback interface, `lo`, is up from the
+//! start with `127.0.0.1/8`. The others are configured as the `ip=`
+//! option of the command line says, with a fixed address or by
+//! [DHCP](dhcp); see [`ipconfig`].
+//!
+//! Received frames are taken from the devices by `netd`, a kernel task
+//! that polls every interface every [`POLL_MS`] milliseconds and hands
+//! them up the stack; nothing is done in interrupt context. Sending runs
+//! on the sender's task, which sleeps while the next hop's address is
+//! resolved.
+
+pub mod arp;
+pub mod dhcp;
+pub mod ethernet;
+pub mod icmp;
+pub mod iface;
+pub mod ipconfig;
+pub mod ipv4;
+pub mod loopback;
+pub mod netconsole;
+pub mod tcp;
+pub mod tftp;
+pub mod udp;
+
+use crate::process::sched;
+use core::fmt;
+use drivers::hal::net::NetworkDeviceError;
+
+pub use iface::{IfConfig, IfStats, InterfaceInfo};
+pub use ipv4::Ipv4Addr;
+pub use tcp::{TcpListener, TcpStream};
+pub use udp::UdpSocket;
+
+/// How often `netd` polls the interfaces, in milliseconds
+pub const POLL_MS: u32 = 10;
+
+/// Why a network operation failed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetError {
+    /// No interface of that name
+    NoSuchInterface,
+    /// The interface is down, or has no address to send from
+    InterfaceDown,
+    /// No interface reaches the destination
+    NoRoute,
+    /// The next hop did not answer ARP
+    HostUnreachable,
+    /// The port is bound already
+    AddressInUse,
+    /// Nothing arrived in time
+    TimedOut,
+    /// The payload does not fit a frame
+    TooLong,
+    /// Nothing listens on the port
+    ConnectionRefused,
+    /// The peer reset the connection
+    ConnectionReset,
+    /// The connection is not open, or has been closed here
+    NotConnected,
+    /// The device failed to send
+    Device(NetworkDeviceError),
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::NoSuchInterface => write!(f, "no such interface"),
+            NetError::InterfaceDown => write!(f, "interface down"),
+            NetError::NoRoute => write!(f, "no route to host"),
+            NetError::HostUnreachable => write!(f, "host unreachable"),
+            NetError::AddressInUse => write!(f, "address in use"),
+            NetError::TimedOut => write!(f, "timed out"),
+            NetError::TooLong => write!(f, "message too long"),
+            NetError::ConnectionRefused => write!(f, "connection refused"),
+            NetError::ConnectionReset => write!(f, "connection reset"),
+            NetError::NotConnected => write!(f, "not connected"),
+            NetError::Device(e) => write!(f, "device error: {:?}", e),
+        }
+    }
+}
+
+crate::initcall!(late, NET_INIT, init);
+
+/// Set up the interfaces, start `netd`, and configure the interfaces and
+/// the netconsole as the command line says.
+fn init() {
+    iface::init();
+    if let Err(e) = sched::spawn("netd", netd) {
+        log::warn!("No network polling: {:?}", e);
+        return;
+    }
+    ipconfig::init();
+    netconsole::init();
+}
+
+/// Take in what the interfaces have received and run the TCP timers, for
+/// ever
+fn netd() {
+    loop {
+        iface::poll();
+        tcp::poll();
+        sched::sleep_ms(POLL_MS);
+    }
+}
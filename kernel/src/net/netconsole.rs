@@ -0,0 +1,158 @@
+//! Netconsole
+//!
+//! Sends the kernel message log as UDP datagrams, one record each, to a
+//! host given on the command line, so a board without a serial adapter
+//! can still be watched with `nc -u -l 6666` or the like. The option
+//! takes the form Linux's does:
+//!
+//! `netconsole=[<src-port>]@[<src-ip>]/[<dev>],[<tgt-port>]@<tgt-ip>/[<tgt-mac>]`
+//!
+//! The source address and target hardware address are ignored: records
+//! go from the interface's own address, to wherever the routes say,
+//! through `<dev>` if given. The ports default to 6665 and 6666.
+//!
+//! A `netconsole` task reads the log ring and sends what is new every
+//! [`FLUSH_MS`] milliseconds. Until there is an interface up to send on,
+//! records wait in the ring, so the boot log goes out once there is one,
+//! as far as the ring still holds it. Records are never sent from where
+//! they are logged: that may be an interrupt handler, or code holding a
+//! lock sending needs.
+
+use super::ipv4::Ipv4Addr;
+use super::{UdpSocket, iface, loopback};
+use crate::logger;
+use crate::process::sched;
+use alloc::vec::Vec;
+use common::sync::Once;
+
+/// How often new records are sent, in milliseconds
+pub const FLUSH_MS: u32 = 100;
+
+const DEFAULT_LOCAL_PORT: u16 = 6665;
+const DEFAULT_REMOTE_PORT: u16 = 6666;
+
+/// Bytes of records taken from the ring at a time
+const BATCH_BYTES: usize = 4096;
+
+/// Records taken from the ring at a time
+const BATCH_RECORDS: usize = 64;
+
+/// Where records go
+#[derive(Debug, Copy, Clone)]
+pub struct Target {
+    pub local_port: u16,
+    pub device: Option<&'static str>,
+    pub remote: Ipv4Addr,
+    pub remote_port: u16,
+}
+
+impl Target {
+    /// Parse the value of `netconsole=`
+    pub fn parse(value: &'static str) -> Option<Self> {
+        let (local, remote) = value.split_once(',')?;
+        let port = |port: &str, default| match port {
+            "" => Some(default),
+            port => port.parse().ok(),
+        };
+
+        // A leading + asks Linux for extended records; they are all alike
+        // here
+        let local = local.strip_prefix('+').unwrap_or(local);
+        let (local_port, local) = local.split_once('@')?;
+        let device = local.split_once('/').map(|(_, dev)| dev);
+        let (remote_port, remote) = remote.split_once('@')?;
+        let remote = remote.split_once('/').map_or(remote, |(ip, _)| ip);
+        Some(Self {
+            local_port: port(local_port, DEFAULT_LOCAL_PORT)?,
+            device: device.filter(|dev| !dev.is_empty()),
+            remote: Ipv4Addr::parse(remote)?,
+            remote_port: port(remote_port, DEFAULT_REMOTE_PORT)?,
+        })
+    }
+}
+
+static TARGET: Once<Target> = Once::new();
+
+/// Start sending the log where `netconsole=` says, if it is given
+pub fn init() {
+    let Some(value) = crate::boot::cmdline().get("netconsole") else {
+        return;
+    };
+    let Some(target) = Target::parse(value) else {
+        log::warn!("Ignoring malformed netconsole={}", value);
+        return;
+    };
+    TARGET.call_once(|| target);
+    match sched::spawn("netconsole", run) {
+        Ok(_) => log::info!("Netconsole to {}:{}", target.remote, target.remote_port),
+        Err(e) => log::warn!("No netconsole: {:?}", e),
+    }
+}
+
+/// Send new records, for ever
+fn run() {
+    let Some(target) = TARGET.get() else {
+        return;
+    };
+    let socket = match UdpSocket::bind(target.local_port) {
+        Ok(socket) => socket,
+        Err(e) => {
+            log::warn!("Netconsole: port {}: {}", target.local_port, e);
+            return;
+        }
+    };
+
+    let mut bytes = Vec::with_capacity(BATCH_BYTES);
+    // The end of each record in `bytes`, and its position in the ring
+    let mut records: Vec<(usize, u64)> = Vec::with_capacity(BATCH_RECORDS);
+    let mut pos = 0;
+    loop {
+        if ready(target) {
+            // Nothing may allocate with the ring locked, as that may log
+            bytes.clear();
+            records.clear();
+            let mut full = false;
+            logger::for_each_record_from(pos, |record, next| {
+                full = full
+                    || bytes.len() + record.len() > bytes.capacity()
+                    || records.len() == records.capacity();
+                // The rest is read next time, in order
+                if !full {
+                    bytes.extend_from_slice(record.as_bytes());
+                    records.push((bytes.len(), next));
+                }
+            });
+
+            let mut start = 0;
+            for &(end, next) in &records {
+                let sent = match target.device {
+                    Some(dev) => socket.send_to_via(
+                        dev,
+                        &bytes[start..end],
+                        target.remote,
+                        target.remote_port,
+                    ),
+                    None => socket.send_to(&bytes[start..end], target.remote, target.remote_port),
+                };
+                // Left in the ring to try again
+                if sent.is_err() {
+                    break;
+                }
+                start = end;
+                pos = next;
+            }
+        }
+        sched::sleep_ms(FLUSH_MS);
+    }
+}
+
+/// Whether there is an interface to send on: `<dev>`, up with an
+/// address, or any such but `lo` for a remote host
+fn ready(target: &Target) -> bool {
+    iface::interfaces().iter().any(|iface| {
+        iface.up
+            && iface.config.is_some()
+            && target.device.is_none_or(|dev| dev == iface.name)
+            && (iface.name != loopback::NAME || target.remote.is_loopback())
+    })
+}
@@ -0,0 +1,1011 @@
+//! TCP
+//!
+//! A [`TcpStream`] is a connection opened with [`TcpStream::connect`] or
+//! taken from a [`TcpListener`], and carries bytes each way until both
+//! sides have closed it. Segments are taken in order only: one that
+//! arrives ahead of a gap is dropped and the gap acknowledged again, for
+//! the peer to fill. What is sent waits for its acknowledgement, and is
+//! sent again from the first byte unacknowledged when none comes within
+//! the retransmission timeout, which doubles with every attempt. There is
+//! no congestion control beyond that, no urgent data, and no option but
+//! the maximum segment size.
+//!
+//! Segments that arrive are handled by `netd` as they are taken in, and
+//! answered straight from the interface they came on. The timers run on
+//! `netd` too, in [`poll`].
+
+use super::NetError;
+use super::ethernet::ETHERTYPE_IPV4;
+use super::iface::Interface;
+use super::ipv4::{self, Ipv4Addr, PROTO_TCP, Packet};
+use crate::process::sched::{self, TICK_US, WaitQueue};
+use crate::subsystems::uptime_us;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use drivers::hal::net::MacAddress;
+use spin::Mutex;
+
+/// Ports, sequence and acknowledgement numbers, offset, flags, window,
+/// checksum and urgent pointer
+pub const HEADER_LEN: usize = 20;
+
+/// Largest segment we take, what an Ethernet frame holds
+pub const MSS: u16 = 1460;
+
+/// Bytes received but not yet read, beyond which the window is closed
+pub const RECV_BUF_MAX: usize = 16384;
+
+/// Bytes written but not yet acknowledged, beyond which writers wait
+pub const SEND_BUF_MAX: usize = 16384;
+
+/// Connections opened to a listener and not yet accepted, beyond which
+/// more are refused
+pub const BACKLOG_MAX: usize = 8;
+
+/// Connections at once, beyond which more are refused
+const CONNECTIONS_MAX: usize = 256;
+
+/// Segment size a peer that does not say takes
+const DEFAULT_MSS: u16 = 536;
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+
+/// Retransmission timeout before any has run out, and its limit, in
+/// milliseconds
+const RTO_INITIAL_MS: u32 = 1000;
+const RTO_MAX_MS: u32 = 60_000;
+
+/// Times a SYN, or anything else, is sent again before giving up
+const SYN_RETRIES: u32 = 5;
+const DATA_RETRIES: u32 = 8;
+
+/// How long a connection closed here lingers to answer the peer's FIN
+/// again, in milliseconds
+const TIME_WAIT_MS: u32 = 10_000;
+
+/// How long to wait for the peer to close after we have, in milliseconds
+const FIN_WAIT_2_MS: u32 = 60_000;
+
+/// Ports handed out to outgoing connections
+const EPHEMERAL_FIRST: u16 = 49152;
+const EPHEMERAL_COUNT: u32 = 16384;
+
+/// Where the search for a free ephemeral port starts next
+static NEXT_EPHEMERAL: AtomicU32 = AtomicU32::new(0);
+
+/// Mixed into initial sequence numbers, so two taken in the same tick
+/// differ
+static NEXT_ISS: AtomicU32 = AtomicU32::new(0);
+
+/// Open connections, by local port, remote address and remote port
+static CONNECTIONS: Mutex<BTreeMap<Key, Arc<Connection>>> = Mutex::new(BTreeMap::new());
+
+/// Listening ports
+static LISTENERS: Mutex<BTreeMap<u16, Arc<Listen>>> = Mutex::new(BTreeMap::new());
+
+type Key = (u16, Ipv4Addr, u16);
+
+/// Where a connection is in its life, as RFC 793 names it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    Closed,
+}
+
+/// What a connection knows of itself and its peer
+struct Tcb {
+    state: State,
+    iss: u32,
+    /// First byte sent and not acknowledged
+    snd_una: u32,
+    /// Next byte to send; set back to `snd_una` to send everything again
+    snd_nxt: u32,
+    /// Bytes the peer takes past `snd_una`
+    snd_wnd: u32,
+    /// Largest segment the peer takes
+    mss: u16,
+    /// Bytes written from `snd_una` on, sent or not
+    send_buf: VecDeque<u8>,
+    /// The connection has been closed here, so a FIN follows the data
+    fin_queued: bool,
+    /// Next byte expected from the peer
+    rcv_nxt: u32,
+    /// Bytes received in order and not yet read
+    recv_buf: VecDeque<u8>,
+    /// The peer has closed, so nothing follows `recv_buf`
+    fin_received: bool,
+    /// Nobody holds the stream any more, so what arrives is thrown away
+    orphaned: bool,
+    /// Something arrived that wants acknowledging
+    ack_pending: bool,
+    /// Retransmission timeout, in ticks
+    rto: u64,
+    /// When to send again what has not been acknowledged
+    retransmit_at: Option<u64>,
+    /// Times sent again without an acknowledgement
+    retries: u32,
+    /// When a connection lingering in TIME-WAIT or FIN-WAIT-2 closes
+    deadline: Option<u64>,
+    /// Why the connection failed
+    error: Option<NetError>,
+    /// The listener a connection still opening goes to when it opens
+    listener: Option<Weak<Listen>>,
+}
+
+/// A segment to send
+struct Outgoing {
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    window: u16,
+    data: Vec<u8>,
+}
+
+/// What the stack shares with a stream
+struct Connection {
+    local_port: u16,
+    remote: Ipv4Addr,
+    remote_port: u16,
+    tcb: Mutex<Tcb>,
+    /// Tasks waiting for the connection to change
+    changed: WaitQueue,
+}
+
+/// What the stack shares with a listener
+struct Listen {
+    /// Connections opened and not yet accepted
+    ready: Mutex<VecDeque<Arc<Connection>>>,
+    /// Tasks waiting for a connection
+    arrived: WaitQueue,
+}
+
+/// A received segment's header fields, and its data
+struct Segment<'a> {
+    src_port: u16,
+    dst_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    window: u16,
+    mss: Option<u16>,
+    data: &'a [u8],
+}
+
+impl<'a> Segment<'a> {
+    /// Check `bytes`, which came from `src` to `dst`, and split off the
+    /// data. Damaged segments are refused.
+    fn parse(src: Ipv4Addr, dst: Ipv4Addr, bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN {
+            return None;
+        }
+        let header_len = (bytes[12] >> 4) as usize * 4;
+        if header_len < HEADER_LEN || header_len > bytes.len() {
+            return None;
+        }
+        if checksum(src, dst, bytes) != 0 {
+            return None;
+        }
+        let field16 = |at: usize| u16::from_be_bytes([bytes[at], bytes[at + 1]]);
+        let field32 = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+        Some(Self {
+            src_port: field16(0),
+            dst_port: field16(2),
+            seq: field32(4),
+            ack: field32(8),
+            flags: bytes[13],
+            window: field16(14),
+            mss: parse_mss(&bytes[HEADER_LEN..header_len]),
+            data: &bytes[header_len..],
+        })
+    }
+
+    fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Sequence space the segment takes up
+    fn len(&self) -> u32 {
+        self.data.len() as u32 + self.has(SYN) as u32 + self.has(FIN) as u32
+    }
+}
+
+/// The maximum segment size among `options`, if there is one
+fn parse_mss(mut options: &[u8]) -> Option<u16> {
+    while let Some(&kind) = options.first() {
+        match kind {
+            0 => break,
+            1 => options = &options[1..],
+            _ => {
+                let len = *options.get(1)? as usize;
+                if len < 2 || len > options.len() {
+                    return None;
+                }
+                if kind == 2 && len == 4 {
+                    return Some(u16::from_be_bytes([options[2], options[3]]));
+                }
+                options = &options[len..];
+            }
+        }
+    }
+    None
+}
+
+/// Is `a` before `b`, in sequence space?
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+fn ms_to_ticks(ms: u32) -> u64 {
+    (ms as u64 * 1000).div_ceil(TICK_US as u64)
+}
+
+/// A fresh initial sequence number, from a clock that ticks every 4
+/// microseconds as RFC 793 has it
+fn initial_seq() -> u32 {
+    let now_us = uptime_us().unwrap_or_else(|| sched::ticks() * TICK_US as u64);
+    ((now_us / 4) as u32).wrapping_add(NEXT_ISS.fetch_add(64000, Ordering::Relaxed))
+}
+
+/// The checksum of `segment` from `src` to `dst`, over the IPv4 pseudo
+/// header as well
+fn checksum(src: Ipv4Addr, dst: Ipv4Addr, segment: &[u8]) -> u16 {
+    let len = (segment.len() as u16).to_be_bytes();
+    let pseudo = [0, PROTO_TCP, len[0], len[1]];
+    ipv4::checksum(&[&src.0, &dst.0, &pseudo, segment])
+}
+
+/// The bytes of `segment` from `src_port` at `src` to `dst_port` at
+/// `dst`. A SYN carries our maximum segment size.
+fn build(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    src_port: u16,
+    dst_port: u16,
+    segment: &Outgoing,
+) -> Vec<u8> {
+    let mss = MSS.to_be_bytes();
+    let options: &[u8] = if segment.flags & SYN != 0 {
+        &[2, 4, mss[0], mss[1]]
+    } else {
+        &[]
+    };
+    let header_len = HEADER_LEN + options.len();
+    let mut bytes = Vec::with_capacity(header_len + segment.data.len());
+    bytes.extend_from_slice(&src_port.to_be_bytes());
+    bytes.extend_from_slice(&dst_port.to_be_bytes());
+    bytes.extend_from_slice(&segment.seq.to_be_bytes());
+    bytes.extend_from_slice(&segment.ack.to_be_bytes());
+    bytes.extend_from_slice(&[(header_len as u8 / 4) << 4, segment.flags]);
+    bytes.extend_from_slice(&segment.window.to_be_bytes());
+    bytes.extend_from_slice(&[0, 0, 0, 0]);
+    bytes.extend_from_slice(options);
+    bytes.extend_from_slice(&segment.data);
+    let sum = checksum(src, dst, &bytes);
+    bytes[16..18].copy_from_slice(&sum.to_be_bytes());
+    bytes
+}
+
+impl Tcb {
+    fn new(state: State, rcv_nxt: u32) -> Self {
+        let iss = initial_seq();
+        Self {
+            state,
+            iss,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_wnd: 0,
+            mss: DEFAULT_MSS,
+            send_buf: VecDeque::new(),
+            fin_queued: false,
+            rcv_nxt,
+            recv_buf: VecDeque::new(),
+            fin_received: false,
+            orphaned: false,
+            ack_pending: false,
+            rto: ms_to_ticks(RTO_INITIAL_MS),
+            retransmit_at: None,
+            retries: 0,
+            deadline: None,
+            error: None,
+            listener: None,
+        }
+    }
+
+    /// Room left in `recv_buf`, which is what the peer may send
+    fn window(&self) -> u16 {
+        (RECV_BUF_MAX - self.recv_buf.len()) as u16
+    }
+
+    fn segment(&self, seq: u32, flags: u8, data: Vec<u8>) -> Outgoing {
+        Outgoing {
+            seq,
+            ack: self.rcv_nxt,
+            flags,
+            window: self.window(),
+            data,
+        }
+    }
+
+    /// A reset that ends the connection, if the peer knows of it
+    fn reset(&mut self) -> Option<Outgoing> {
+        let known = !matches!(self.state, State::SynSent | State::TimeWait | State::Closed);
+        let reset = known.then(|| self.segment(self.snd_nxt, RST | ACK, Vec::new()));
+        self.close_now(None);
+        reset
+    }
+
+    /// Be done with the connection at once, failed with `error` if given
+    fn close_now(&mut self, error: Option<NetError>) {
+        self.state = State::Closed;
+        self.error = self.error.or(error);
+        self.retransmit_at = None;
+        self.deadline = None;
+    }
+
+    /// Linger in TIME-WAIT, to acknowledge the peer's FIN again should it
+    /// come again
+    fn time_wait(&mut self, now: u64) {
+        self.state = State::TimeWait;
+        self.retransmit_at = None;
+        self.deadline = Some(now + ms_to_ticks(TIME_WAIT_MS));
+    }
+
+    /// The segments that are due: a SYN until it is acknowledged, then
+    /// what of `send_buf` fits the peer's window and a FIN after it once
+    /// the connection is closed here, and an acknowledgement if nothing
+    /// else carries one
+    fn output(&mut self, now: u64) -> Vec<Outgoing> {
+        let mut segments = Vec::new();
+        match self.state {
+            State::SynSent | State::SynReceived => {
+                if self.snd_nxt == self.snd_una {
+                    let flags = match self.state {
+                        State::SynSent => SYN,
+                        _ => SYN | ACK,
+                    };
+                    segments.push(self.segment(self.iss, flags, Vec::new()));
+                    self.snd_nxt = self.iss.wrapping_add(1);
+                }
+            }
+            State::Closed => return segments,
+            _ => {
+                let sent = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+                // A closed window is probed a byte at a time
+                let window = (self.snd_wnd as usize).max(1);
+                let end = self.send_buf.len().min(window);
+                let mut offset = sent.min(self.send_buf.len());
+                while offset < end {
+                    let len = (end - offset).min(self.mss as usize);
+                    let data = self.send_buf.range(offset..offset + len).copied().collect();
+                    let seq = self.snd_una.wrapping_add(offset as u32);
+                    segments.push(self.segment(seq, PSH | ACK, data));
+                    offset += len;
+                }
+                if offset > sent {
+                    self.snd_nxt = self.snd_una.wrapping_add(offset as u32);
+                }
+                let fin_due = self.fin_queued
+                    && sent <= self.send_buf.len()
+                    && offset == self.send_buf.len()
+                    && matches!(
+                        self.state,
+                        State::Established
+                            | State::CloseWait
+                            | State::FinWait1
+                            | State::Closing
+                            | State::LastAck
+                    );
+                if fin_due {
+                    segments.push(self.segment(self.snd_nxt, FIN | ACK, Vec::new()));
+                    self.snd_nxt = self.snd_nxt.wrapping_add(1);
+                    self.state = match self.state {
+                        State::Established => State::FinWait1,
+                        State::CloseWait => State::LastAck,
+                        state => state,
+                    };
+                }
+                if segments.is_empty() && self.ack_pending {
+                    segments.push(self.segment(self.snd_nxt, ACK, Vec::new()));
+                }
+            }
+        }
+        self.ack_pending = false;
+        if self.snd_nxt != self.snd_una && self.retransmit_at.is_none() {
+            self.retransmit_at = Some(now + self.rto);
+        }
+        segments
+    }
+
+    /// Run the timers as of `now`
+    fn tick(&mut self, now: u64) {
+        if self.deadline.is_some_and(|deadline| deadline <= now) {
+            self.close_now(None);
+            return;
+        }
+        if !self.retransmit_at.is_some_and(|at| at <= now) {
+            return;
+        }
+        let retries = match self.state {
+            State::SynSent | State::SynReceived => SYN_RETRIES,
+            _ => DATA_RETRIES,
+        };
+        if self.retries >= retries {
+            self.close_now(Some(NetError::TimedOut));
+            return;
+        }
+        self.retries += 1;
+        self.rto = (self.rto * 2).min(ms_to_ticks(RTO_MAX_MS));
+        self.retransmit_at = None;
+        self.snd_nxt = self.snd_una;
+    }
+
+    /// Take in `segment`, as of `now`. Returns whether it opened a
+    /// connection that was opening to a listener.
+    fn input(&mut self, segment: &Segment, now: u64) -> bool {
+        if self.state == State::SynSent {
+            self.input_syn_sent(segment);
+            return false;
+        }
+
+        // A reset anywhere in the window is taken
+        if segment.has(RST) {
+            let window = self.window().max(1) as u32;
+            if seq_le(self.rcv_nxt, segment.seq)
+                && seq_lt(segment.seq, self.rcv_nxt.wrapping_add(window))
+            {
+                let error = match self.state {
+                    State::SynReceived => NetError::ConnectionRefused,
+                    _ => NetError::ConnectionReset,
+                };
+                self.close_now(Some(error));
+            }
+            return false;
+        }
+        if segment.has(SYN) {
+            // The peer's SYN again: ours, or its acknowledgement, was lost
+            if self.state == State::SynReceived {
+                self.snd_nxt = self.snd_una;
+            } else {
+                self.ack_pending = true;
+            }
+            return false;
+        }
+        if !segment.has(ACK) {
+            return false;
+        }
+
+        let mut opened = false;
+        if self.state == State::SynReceived {
+            if segment.ack != self.snd_nxt {
+                return false;
+            }
+            self.snd_una = segment.ack;
+            self.state = State::Established;
+            self.retransmit_at = None;
+            self.retries = 0;
+            opened = true;
+        }
+        self.input_ack(segment, now);
+        if self.state == State::Closed {
+            return opened;
+        }
+        self.input_data(segment, now);
+        opened
+    }
+
+    /// Take in the answer to our SYN
+    fn input_syn_sent(&mut self, segment: &Segment) {
+        let acceptable =
+            segment.has(ACK) && seq_lt(self.iss, segment.ack) && seq_le(segment.ack, self.snd_nxt);
+        if segment.has(RST) {
+            if acceptable {
+                self.close_now(Some(NetError::ConnectionRefused));
+            }
+            return;
+        }
+        if !acceptable || !segment.has(SYN) {
+            return;
+        }
+        self.rcv_nxt = segment.seq.wrapping_add(1);
+        self.snd_una = segment.ack;
+        self.snd_wnd = segment.window as u32;
+        self.mss = segment.mss.unwrap_or(DEFAULT_MSS).min(MSS);
+        self.state = State::Established;
+        self.retransmit_at = None;
+        self.retries = 0;
+        self.ack_pending = true;
+    }
+
+    /// Take in what `segment` acknowledges, and its window
+    fn input_ack(&mut self, segment: &Segment, now: u64) {
+        if seq_lt(self.snd_nxt, segment.ack) {
+            // Acknowledges what was never sent
+            self.ack_pending = true;
+            return;
+        }
+        if seq_lt(segment.ack, self.snd_una) {
+            return;
+        }
+        self.snd_wnd = segment.window as u32;
+        let acked = segment.ack.wrapping_sub(self.snd_una) as usize;
+        if acked == 0 {
+            // A peer that answers a probe of its closed window is still
+            // there, however long it keeps it closed
+            if self.snd_wnd == 0 {
+                self.retries = 0;
+            }
+            return;
+        }
+        let fin_acked = acked > self.send_buf.len();
+        self.send_buf.drain(..acked.min(self.send_buf.len()));
+        self.snd_una = segment.ack;
+        self.retries = 0;
+        self.rto = ms_to_ticks(RTO_INITIAL_MS);
+        self.retransmit_at = (self.snd_nxt != self.snd_una).then(|| now + self.rto);
+        if fin_acked {
+            match self.state {
+                State::FinWait1 => {
+                    self.state = State::FinWait2;
+                    self.deadline = Some(now + ms_to_ticks(FIN_WAIT_2_MS));
+                }
+                State::Closing => self.time_wait(now),
+                State::LastAck => self.close_now(None),
+                _ => {}
+            }
+        }
+    }
+
+    /// Take in what `segment` carries, if it is next in order, and its FIN
+    fn input_data(&mut self, segment: &Segment, now: u64) {
+        if segment.len() == 0 {
+            return;
+        }
+        self.ack_pending = true;
+        if !seq_le(segment.seq, self.rcv_nxt) {
+            // Beyond a gap: acknowledging again asks for what is missing
+            return;
+        }
+        let skip = self.rcv_nxt.wrapping_sub(segment.seq) as usize;
+        let receiving = matches!(
+            self.state,
+            State::Established | State::FinWait1 | State::FinWait2
+        );
+        if receiving && skip < segment.data.len() {
+            let data = &segment.data[skip..];
+            let len = data.len().min(RECV_BUF_MAX - self.recv_buf.len());
+            if !self.orphaned {
+                self.recv_buf.extend(&data[..len]);
+            }
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(len as u32);
+        }
+
+        let end = segment.seq.wrapping_add(segment.data.len() as u32);
+        if !segment.has(FIN) || end != self.rcv_nxt || self.fin_received {
+            if self.state == State::TimeWait && segment.has(FIN) {
+                self.time_wait(now);
+            }
+            return;
+        }
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+        self.fin_received = true;
+        match self.state {
+            State::Established => self.state = State::CloseWait,
+            State::FinWait1 => self.state = State::Closing,
+            State::FinWait2 => self.time_wait(now),
+            _ => {}
+        }
+    }
+}
+
+impl Connection {
+    fn key(&self) -> Key {
+        (self.local_port, self.remote, self.remote_port)
+    }
+
+    /// Send `segments` on the interface the route table picks
+    fn transmit(&self, segments: Vec<Outgoing>) -> Result<(), NetError> {
+        for segment in &segments {
+            ipv4::send(self.remote, PROTO_TCP, |src| {
+                build(src, self.remote, self.local_port, self.remote_port, segment)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Send what is due. A failure is left to the retransmission timer.
+    fn flush(&self) {
+        let segments = self.tcb.lock().output(sched::ticks());
+        let _ = self.transmit(segments);
+    }
+
+    /// Reset the connection and wake whoever waits on it
+    fn abort(&self) {
+        let reset = self.tcb.lock().reset();
+        if let Some(reset) = reset {
+            let _ = self.transmit(alloc::vec![reset]);
+        }
+        self.changed.wake_all();
+    }
+}
+
+/// Sleep on `queue` until `condition` holds, for at most `timeout_ms`
+/// milliseconds if given
+fn wait(
+    queue: &WaitQueue,
+    condition: impl FnMut() -> bool,
+    timeout_ms: Option<u32>,
+) -> Result<(), NetError> {
+    match timeout_ms {
+        None => queue.sleep_on(condition),
+        Some(ms) => {
+            if !queue.sleep_on_timeout(condition, ms_to_ticks(ms)) {
+                return Err(NetError::TimedOut);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A TCP connection. Dropping it closes the connection, or resets it if
+/// what arrived has not all been read.
+pub struct TcpStream {
+    connection: Arc<Connection>,
+}
+
+impl TcpStream {
+    /// Open a connection to `port` at `dst`, from an ephemeral port, and
+    /// sleep until it is open or refused
+    pub fn connect(dst: Ipv4Addr, port: u16) -> Result<Self, NetError> {
+        let connection = {
+            let mut connections = CONNECTIONS.lock();
+            if connections.len() >= CONNECTIONS_MAX {
+                return Err(NetError::AddressInUse);
+            }
+            let start = NEXT_EPHEMERAL.fetch_add(1, Ordering::Relaxed);
+            let listeners = LISTENERS.lock();
+            let local_port = (0..EPHEMERAL_COUNT)
+                .map(|i| EPHEMERAL_FIRST + ((start + i) % EPHEMERAL_COUNT) as u16)
+                .find(|port| {
+                    !listeners.contains_key(port) && !connections.keys().any(|key| key.0 == *port)
+                })
+                .ok_or(NetError::AddressInUse)?;
+            drop(listeners);
+            let connection = Arc::new(Connection {
+                local_port,
+                remote: dst,
+                remote_port: port,
+                tcb: Mutex::new(Tcb::new(State::SynSent, 0)),
+                changed: WaitQueue::new(),
+            });
+            connections.insert(connection.key(), connection.clone());
+            connection
+        };
+        let stream = Self { connection };
+
+        let syn = stream.connection.tcb.lock().output(sched::ticks());
+        stream.connection.transmit(syn).inspect_err(|_| {
+            stream.connection.tcb.lock().close_now(None);
+        })?;
+        let tcb = &stream.connection.tcb;
+        stream
+            .connection
+            .changed
+            .sleep_on(|| tcb.lock().state != State::SynSent);
+        let error = tcb.lock().error;
+        match error {
+            Some(e) => Err(e),
+            None => Ok(stream),
+        }
+    }
+
+    /// The local port, and the address and port of the peer
+    pub fn local_port(&self) -> u16 {
+        self.connection.local_port
+    }
+
+    pub fn peer(&self) -> (Ipv4Addr, u16) {
+        (self.connection.remote, self.connection.remote_port)
+    }
+
+    /// Sleep until there is room to send, and queue what of `data` fits.
+    /// Returns how much that was.
+    pub fn write(&self, data: &[u8]) -> Result<usize, NetError> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let tcb = &self.connection.tcb;
+        self.connection.changed.sleep_on(|| {
+            let tcb = tcb.lock();
+            tcb.send_buf.len() < SEND_BUF_MAX || tcb.state == State::Closed
+        });
+        let len = {
+            let mut tcb = tcb.lock();
+            if let Some(e) = tcb.error {
+                return Err(e);
+            }
+            if tcb.fin_queued || !matches!(tcb.state, State::Established | State::CloseWait) {
+                return Err(NetError::NotConnected);
+            }
+            let len = data.len().min(SEND_BUF_MAX - tcb.send_buf.len());
+            tcb.send_buf.extend(&data[..len]);
+            len
+        };
+        self.connection.flush();
+        Ok(len)
+    }
+
+    /// Write all of `data`, sleeping for room as often as it takes
+    pub fn write_all(&self, mut data: &[u8]) -> Result<(), NetError> {
+        while !data.is_empty() {
+            let len = self.write(data)?;
+            data = &data[len..];
+        }
+        Ok(())
+    }
+
+    /// Sleep until something arrives, for at most `timeout_ms`
+    /// milliseconds if given, and copy what fits of it into `buf`.
+    /// Returns how much that was, 0 once the peer has closed and
+    /// everything it sent has been read.
+    pub fn read(&self, buf: &mut [u8], timeout_ms: Option<u32>) -> Result<usize, NetError> {
+        let tcb = &self.connection.tcb;
+        let readable = || {
+            let tcb = tcb.lock();
+            !tcb.recv_buf.is_empty() || tcb.fin_received || tcb.state == State::Closed
+        };
+        wait(&self.connection.changed, readable, timeout_ms)?;
+        let (len, reopened) = {
+            let mut tcb = tcb.lock();
+            if tcb.recv_buf.is_empty() {
+                return match tcb.error {
+                    Some(e) => Err(e),
+                    None => Ok(0),
+                };
+            }
+            let before = tcb.window();
+            let len = tcb.recv_buf.len().min(buf.len());
+            for (to, from) in buf.iter_mut().zip(tcb.recv_buf.drain(..len)) {
+                *to = from;
+            }
+            // Tell a peer held up by a window too small for a segment that
+            // there is room again
+            let reopened = before < tcb.mss && tcb.window() >= tcb.mss;
+            tcb.ack_pending |= reopened;
+            (len, reopened)
+        };
+        if reopened {
+            self.connection.flush();
+        }
+        Ok(len)
+    }
+
+    /// Close the connection here, once what has been written is sent.
+    /// What the peer sends can still be read.
+    pub fn close(&self) {
+        {
+            let mut tcb = self.connection.tcb.lock();
+            match tcb.state {
+                State::SynSent => tcb.close_now(None),
+                _ => tcb.fin_queued = true,
+            }
+        }
+        self.connection.flush();
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        let unread = {
+            let mut tcb = self.connection.tcb.lock();
+            tcb.orphaned = true;
+            !tcb.recv_buf.is_empty()
+        };
+        if unread {
+            self.connection.abort();
+        } else {
+            self.close();
+        }
+    }
+}
+
+/// A TCP port listening for connections. Dropping it stops listening and
+/// resets the connections not yet accepted.
+pub struct TcpListener {
+    port: u16,
+    listen: Arc<Listen>,
+}
+
+impl TcpListener {
+    /// Listen on `port`
+    pub fn bind(port: u16) -> Result<Self, NetError> {
+        let mut listeners = LISTENERS.lock();
+        if port == 0 || listeners.contains_key(&port) {
+            return Err(NetError::AddressInUse);
+        }
+        let listen = Arc::new(Listen {
+            ready: Mutex::new(VecDeque::new()),
+            arrived: WaitQueue::new(),
+        });
+        listeners.insert(port, listen.clone());
+        Ok(Self { port, listen })
+    }
+
+    /// The port listened on
+    pub fn local_port(&self) -> u16 {
+        self.port
+    }
+
+    /// Sleep until a connection has been opened, for at most
+    /// `timeout_ms` milliseconds if given, and take it
+    pub fn accept(&self, timeout_ms: Option<u32>) -> Result<TcpStream, NetError> {
+        let mut connection = None;
+        let take = || {
+            connection = self.listen.ready.lock().pop_front();
+            connection.is_some()
+        };
+        wait(&self.listen.arrived, take, timeout_ms)?;
+        let connection = connection.ok_or(NetError::TimedOut)?;
+        Ok(TcpStream { connection })
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        LISTENERS.lock().remove(&self.port);
+        let ready = core::mem::take(&mut *self.listen.ready.lock());
+        for connection in ready {
+            connection.abort();
+        }
+    }
+}
+
+/// Answer `packet`, which arrived on `iface` from `src_mac`, with
+/// `segments`
+fn reply(
+    iface: &mut Interface,
+    src_mac: MacAddress,
+    packet: &Packet,
+    segment: &Segment,
+    segments: &[Outgoing],
+) {
+    for reply in segments {
+        let bytes = build(
+            packet.dst,
+            packet.src,
+            segment.dst_port,
+            segment.src_port,
+            reply,
+        );
+        let packet = ipv4::build(packet.dst, packet.src, PROTO_TCP, &bytes);
+        let _ = iface.transmit(src_mac, ETHERTYPE_IPV4, &packet);
+    }
+}
+
+/// Take in a segment that arrived on `iface` from `src_mac`, for the
+/// connection it belongs to or the listener on its port. Anything else
+/// is reset.
+pub(super) fn receive(iface: &mut Interface, src_mac: MacAddress, packet: &Packet) {
+    let Some(segment) = Segment::parse(packet.src, packet.dst, packet.payload) else {
+        iface.stats.rx_dropped += 1;
+        return;
+    };
+    if packet.dst == Ipv4Addr::BROADCAST || packet.src == Ipv4Addr::BROADCAST {
+        return;
+    }
+    let now = sched::ticks();
+    let key = (segment.dst_port, packet.src, segment.src_port);
+
+    let connection = CONNECTIONS.lock().get(&key).cloned();
+    let Some(connection) = connection.or_else(|| listen(&segment, key)) else {
+        if !segment.has(RST) {
+            reply(iface, src_mac, packet, &segment, &[refusal(&segment)]);
+        }
+        return;
+    };
+
+    let (opened, listener, segments) = {
+        let mut tcb = connection.tcb.lock();
+        let opened = tcb.input(&segment, now);
+        let listener = if opened { tcb.listener.take() } else { None };
+        (opened, listener, tcb.output(now))
+    };
+    reply(iface, src_mac, packet, &segment, &segments);
+    if opened {
+        match listener.and_then(|listener| listener.upgrade()) {
+            Some(listener) => {
+                listener.ready.lock().push_back(connection.clone());
+                listener.arrived.wake_all();
+            }
+            None => {
+                let reset = connection.tcb.lock().reset();
+                reply(iface, src_mac, packet, &segment, reset.as_slice());
+            }
+        }
+    }
+    connection.changed.wake_all();
+}
+
+/// A connection opening to the listener on the port `segment` is for,
+/// if it is a SYN and there is one with room for it
+fn listen(segment: &Segment, key: Key) -> Option<Arc<Connection>> {
+    if segment.flags & (SYN | ACK | RST) != SYN {
+        return None;
+    }
+    let listener = LISTENERS.lock().get(&segment.dst_port).cloned()?;
+    if listener.ready.lock().len() >= BACKLOG_MAX {
+        return None;
+    }
+    let mut connections = CONNECTIONS.lock();
+    if connections.len() >= CONNECTIONS_MAX {
+        return None;
+    }
+    let mut tcb = Tcb::new(State::SynReceived, segment.seq.wrapping_add(1));
+    tcb.snd_wnd = segment.window as u32;
+    tcb.mss = segment.mss.unwrap_or(DEFAULT_MSS).min(MSS);
+    tcb.listener = Some(Arc::downgrade(&listener));
+    let connection = Arc::new(Connection {
+        local_port: key.0,
+        remote: key.1,
+        remote_port: key.2,
+        tcb: Mutex::new(tcb),
+        changed: WaitQueue::new(),
+    });
+    connections.insert(key, connection.clone());
+    Some(connection)
+}
+
+/// The reset that answers `segment`, for a connection there is not
+fn refusal(segment: &Segment) -> Outgoing {
+    let (seq, ack, flags) = if segment.has(ACK) {
+        (segment.ack, 0, RST)
+    } else {
+        (0, segment.seq.wrapping_add(segment.len()), RST | ACK)
+    };
+    Outgoing {
+        seq,
+        ack,
+        flags,
+        window: 0,
+        data: Vec::new(),
+    }
+}
+
+/// Send again what has gone unacknowledged too long, and be done with
+/// connections that have closed. Run by `netd`.
+pub fn poll() {
+    let now = sched::ticks();
+    let connections: Vec<_> = CONNECTIONS.lock().values().cloned().collect();
+    for connection in connections {
+        let (segments, before, state) = {
+            let mut tcb = connection.tcb.lock();
+            let before = tcb.state;
+            tcb.tick(now);
+            (tcb.output(now), before, tcb.state)
+        };
+        if state != before {
+            connection.changed.wake_all();
+        }
+        let _ = connection.transmit(segments);
+        if state == State::Closed {
+            CONNECTIONS.lock().remove(&connection.key());
+        }
+    }
+}
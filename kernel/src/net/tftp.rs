@@ -0,0 +1,173 @@
+//! TFTP client
+//!
+//! Fetches a file from a TFTP server (RFC 1350) in octet mode, 512 bytes
+//! a block, each acknowledged before the server sends the next. Lost
+//! blocks and acknowledgements are recovered by sending the last packet
+//! again after [`TIMEOUT_MS`], up to [`RETRIES`] times. Only reading is
+//! supported.
+
+use super::ipv4::Ipv4Addr;
+use super::{NetError, UdpSocket};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The port servers listen on
+pub const SERVER_PORT: u16 = 69;
+
+/// How long to wait for the next block, in milliseconds
+pub const TIMEOUT_MS: u32 = 2000;
+
+/// Times the last packet is sent again before giving up
+pub const RETRIES: u32 = 5;
+
+/// Bytes of data in a block; a shorter one is the last
+const BLOCK_SIZE: usize = 512;
+
+// Opcodes
+const RRQ: u16 = 1;
+const DATA: u16 = 3;
+const ACK: u16 = 4;
+const ERROR: u16 = 5;
+
+// Error codes
+const ERR_UNDEFINED: u16 = 0;
+const ERR_UNKNOWN_TID: u16 = 5;
+
+/// Why a transfer failed
+#[derive(Debug)]
+pub enum TftpError<E> {
+    Net(NetError),
+    /// The server stopped answering
+    TimedOut,
+    /// The server sent an error
+    Remote {
+        code: u16,
+        message: String,
+    },
+    /// The server sent something it should not have
+    Protocol,
+    /// Storing what arrived failed
+    Sink(E),
+}
+
+impl<E> From<NetError> for TftpError<E> {
+    fn from(e: NetError) -> Self {
+        TftpError::Net(e)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for TftpError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TftpError::Net(e) => write!(f, "{}", e),
+            TftpError::TimedOut => write!(f, "server not answering"),
+            TftpError::Remote { code, message } => {
+                write!(f, "server error {}: {}", code, message)
+            }
+            TftpError::Protocol => write!(f, "unexpected packet from server"),
+            TftpError::Sink(e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+/// Fetch `filename` from the server at `server`, handing each block to
+/// `sink` in order as it arrives. Returns the file's length.
+pub fn get<E>(
+    server: Ipv4Addr,
+    filename: &str,
+    mut sink: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<usize, TftpError<E>> {
+    let socket = UdpSocket::bind(0)?;
+    let mut request = Vec::with_capacity(filename.len() + 10);
+    request.extend_from_slice(&RRQ.to_be_bytes());
+    request.extend_from_slice(filename.as_bytes());
+    request.push(0);
+    request.extend_from_slice(b"octet\0");
+
+    // The server answers from a port of its own, its transfer ID, which
+    // the rest of the transfer goes to
+    let mut server_port = None;
+    let mut last_sent = request;
+    let mut expected: u16 = 1;
+    let mut received = 0;
+    let mut buf = [0u8; 4 + BLOCK_SIZE];
+    let mut retries = 0;
+    socket.send_to(&last_sent, server, SERVER_PORT)?;
+    loop {
+        let (n, src, port) = match socket.recv_from(&mut buf, Some(TIMEOUT_MS)) {
+            Ok(reply) => reply,
+            Err(NetError::TimedOut) if retries < RETRIES => {
+                retries += 1;
+                let dst_port = server_port.unwrap_or(SERVER_PORT);
+                socket.send_to(&last_sent, server, dst_port)?;
+                continue;
+            }
+            Err(NetError::TimedOut) => return Err(TftpError::TimedOut),
+            Err(e) => return Err(e.into()),
+        };
+        if src != server || server_port.is_some_and(|tid| tid != port) {
+            // Lost, or from a transfer long gone
+            let _ = socket.send_to(&error(ERR_UNKNOWN_TID, "unknown transfer ID"), src, port);
+            continue;
+        }
+        let packet = &buf[..n];
+        if packet.len() < 4 {
+            return Err(TftpError::Protocol);
+        }
+        let opcode = u16::from_be_bytes([packet[0], packet[1]]);
+        let number = u16::from_be_bytes([packet[2], packet[3]]);
+        match opcode {
+            DATA => {}
+            ERROR => {
+                let message = packet[4..].split(|&b| b == 0).next().unwrap_or(&[]);
+                return Err(TftpError::Remote {
+                    code: number,
+                    message: String::from_utf8_lossy(message).into_owned(),
+                });
+            }
+            _ => return Err(TftpError::Protocol),
+        }
+        server_port = Some(port);
+
+        // A block sent again because our acknowledgement was lost
+        if number != expected {
+            if number == expected.wrapping_sub(1) {
+                socket.send_to(&last_sent, server, port)?;
+            }
+            continue;
+        }
+        let data = &packet[4..];
+        if let Err(e) = sink(data) {
+            let _ = socket.send_to(&error(ERR_UNDEFINED, "write failed"), server, port);
+            return Err(TftpError::Sink(e));
+        }
+        received += data.len();
+        retries = 0;
+
+        last_sent = ack(number);
+        socket.send_to(&last_sent, server, port)?;
+        if data.len() < BLOCK_SIZE {
+            return Ok(received);
+        }
+        // Block numbers wrap, so files past 32 MiB work with servers
+        // that allow it
+        expected = expected.wrapping_add(1);
+    }
+}
+
+fn ack(block: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(4);
+    packet.extend_from_slice(&ACK.to_be_bytes());
+    packet.extend_from_slice(&block.to_be_bytes());
+    packet
+}
+
+fn error(code: u16, message: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(5 + message.len());
+    packet.extend_from_slice(&ERROR.to_be_bytes());
+    packet.extend_from_slice(&code.to_be_bytes());
+    packet.extend_from_slice(message.as_bytes());
+    packet.push(0);
+    packet
+}
@@ -0,0 +1,205 @@
+//! UDP sockets
+//!
+//! A [`UdpSocket`] is bound to a local port on every interface at once.
+//! Datagrams that arrive for it are queued until read, up to
+//! [`QUEUE_MAX`] of them; the rest, and those for ports nobody has bound,
+//! are dropped.
+
+use super::NetError;
+use super::iface::Interface;
+use super::ipv4::{self, Ipv4Addr, PROTO_UDP, Packet};
+use crate::process::sched::{TICK_US, WaitQueue};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+
+/// Source and destination port, length and checksum
+pub const HEADER_LEN: usize = 8;
+
+/// Datagrams queued on a socket, beyond which more are dropped
+pub const QUEUE_MAX: usize = 32;
+
+/// Ports handed out when binding port 0
+const EPHEMERAL_FIRST: u16 = 49152;
+const EPHEMERAL_COUNT: u32 = 16384;
+
+/// A datagram queued for reading
+struct Datagram {
+    src: Ipv4Addr,
+    port: u16,
+    data: Vec<u8>,
+}
+
+/// What the stack shares with a bound socket
+struct Endpoint {
+    queue: Mutex<VecDeque<Datagram>>,
+    /// Tasks waiting for a datagram
+    arrived: WaitQueue,
+}
+
+/// Bound ports
+static SOCKETS: Mutex<BTreeMap<u16, Arc<Endpoint>>> = Mutex::new(BTreeMap::new());
+
+/// Where the search for a free ephemeral port starts next
+static NEXT_EPHEMERAL: AtomicU32 = AtomicU32::new(0);
+
+/// A bound UDP port. Dropping it unbinds the port.
+pub struct UdpSocket {
+    port: u16,
+    endpoint: Arc<Endpoint>,
+}
+
+impl UdpSocket {
+    /// Bind `port`, or a free ephemeral port if it is 0
+    pub fn bind(port: u16) -> Result<Self, NetError> {
+        let mut sockets = SOCKETS.lock();
+        let port = match port {
+            0 => {
+                let start = NEXT_EPHEMERAL.fetch_add(1, Ordering::Relaxed);
+                (0..EPHEMERAL_COUNT)
+                    .map(|i| EPHEMERAL_FIRST + ((start + i) % EPHEMERAL_COUNT) as u16)
+                    .find(|port| !sockets.contains_key(port))
+                    .ok_or(NetError::AddressInUse)?
+            }
+            port if sockets.contains_key(&port) => return Err(NetError::AddressInUse),
+            port => port,
+        };
+        let endpoint = Arc::new(Endpoint {
+            queue: Mutex::new(VecDeque::new()),
+            arrived: WaitQueue::new(),
+        });
+        sockets.insert(port, endpoint.clone());
+        Ok(Self { port, endpoint })
+    }
+
+    /// The port the socket is bound to
+    pub fn local_port(&self) -> u16 {
+        self.port
+    }
+
+    /// Send `data` to `port` at `dst`
+    pub fn send_to(&self, data: &[u8], dst: Ipv4Addr, port: u16) -> Result<(), NetError> {
+        let datagram = self.datagram(data, port)?;
+        ipv4::send(dst, PROTO_UDP, |src| datagram(src, dst))
+    }
+
+    /// Send `data` to `port` at `dst` out of the interface `via`, whether
+    /// or not it has an address
+    pub fn send_to_via(
+        &self,
+        via: &str,
+        data: &[u8],
+        dst: Ipv4Addr,
+        port: u16,
+    ) -> Result<(), NetError> {
+        let datagram = self.datagram(data, port)?;
+        ipv4::send_via(via, dst, PROTO_UDP, |src| datagram(src, dst))
+    }
+
+    /// Sleep until a datagram arrives, for at most `timeout_ms`
+    /// milliseconds if given, and copy what fits of it into `buf`.
+    /// Returns its length in `buf` and who sent it.
+    pub fn recv_from(
+        &self,
+        buf: &mut [u8],
+        timeout_ms: Option<u32>,
+    ) -> Result<(usize, Ipv4Addr, u16), NetError> {
+        let mut datagram = None;
+        let take = || {
+            datagram = self.endpoint.queue.lock().pop_front();
+            datagram.is_some()
+        };
+        match timeout_ms {
+            None => self.endpoint.arrived.sleep_on(take),
+            Some(ms) => {
+                let ticks = (ms as u64 * 1000).div_ceil(TICK_US as u64);
+                if !self.endpoint.arrived.sleep_on_timeout(take, ticks) {
+                    return Err(NetError::TimedOut);
+                }
+            }
+        }
+        let datagram = datagram.ok_or(NetError::TimedOut)?;
+        let n = datagram.data.len().min(buf.len());
+        buf[..n].copy_from_slice(&datagram.data[..n]);
+        Ok((n, datagram.src, datagram.port))
+    }
+
+    /// A builder of the datagram carrying `data` to `dst_port`, given the
+    /// addresses its checksum covers
+    fn datagram(
+        &self,
+        data: &[u8],
+        dst_port: u16,
+    ) -> Result<impl Fn(Ipv4Addr, Ipv4Addr) -> Vec<u8>, NetError> {
+        let len = u16::try_from(HEADER_LEN + data.len()).map_err(|_| NetError::TooLong)?;
+        let src_port = self.port;
+        Ok(move |src: Ipv4Addr, dst: Ipv4Addr| {
+            let mut datagram = Vec::with_capacity(len as usize);
+            datagram.extend_from_slice(&src_port.to_be_bytes());
+            datagram.extend_from_slice(&dst_port.to_be_bytes());
+            datagram.extend_from_slice(&len.to_be_bytes());
+            datagram.extend_from_slice(&[0, 0]);
+            datagram.extend_from_slice(data);
+            // All zeros means no checksum, so a zero sum is sent as ones
+            let sum = match checksum(src, dst, &datagram) {
+                0 => 0xFFFF,
+                sum => sum,
+            };
+            datagram[6..8].copy_from_slice(&sum.to_be_bytes());
+            datagram
+        })
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        SOCKETS.lock().remove(&self.port);
+    }
+}
+
+/// The checksum of `datagram` from `src` to `dst`, over the IPv4 pseudo
+/// header as well
+fn checksum(src: Ipv4Addr, dst: Ipv4Addr, datagram: &[u8]) -> u16 {
+    let len = (datagram.len() as u16).to_be_bytes();
+    let pseudo = [0, PROTO_UDP, len[0], len[1]];
+    ipv4::checksum(&[&src.0, &dst.0, &pseudo, datagram])
+}
+
+/// Queue a datagram that arrived on `iface` for the socket bound to its
+/// port
+pub(super) fn receive(iface: &mut Interface, packet: &Packet) {
+    let bytes = packet.payload;
+    if bytes.len() < HEADER_LEN {
+        iface.stats.rx_dropped += 1;
+        return;
+    }
+    let field = |at: usize| u16::from_be_bytes([bytes[at], bytes[at + 1]]);
+    let len = field(4) as usize;
+    if len < HEADER_LEN || len > bytes.len() {
+        iface.stats.rx_dropped += 1;
+        return;
+    }
+    let datagram = &bytes[..len];
+    if field(6) != 0 && checksum(packet.src, packet.dst, datagram) != 0 {
+        iface.stats.rx_dropped += 1;
+        return;
+    }
+
+    let Some(endpoint) = SOCKETS.lock().get(&field(2)).cloned() else {
+        return;
+    };
+    let mut queue = endpoint.queue.lock();
+    if queue.len() >= QUEUE_MAX {
+        iface.stats.rx_dropped += 1;
+        return;
+    }
+    queue.push_back(Datagram {
+        src: packet.src,
+        port: field(0),
+        data: datagram[HEADER_LEN..].to_vec(),
+    });
+    drop(queue);
+    endpoint.arrived.wake_all();
+}
@@ -0,0 +1,493 @@
+//! ELF32 program loading and `execve`.
+//!
+//! [`load`] accepts statically linked, 32-bit little-endian executables
+//! for this machine (on ARM, built for the EABI) and builds a fresh
+//! address space from them:
+//!
+//! - each `PT_LOAD` segment becomes an area holding the segment's file
+//!   bytes followed by zeros, with the protection its flags ask for;
+//! - the heap starts on the page above the highest segment;
+//! - the stack area below [`USER_STACK_TOP`] holds `argc`, the `argv` and
+//!   `envp` arrays and the auxiliary vector, as the C runtime expects.
+//!
+//! Images that need an interpreter (`PT_INTERP`) are refused.
+//!
+//! [`execve`] reads a program through the VFS and replaces the running
+//! process's image with it; [`spawn_from_path`] starts it as a new
+//! process instead, which is how init is started.
+
+use super::fork::{self, ForkError};
+use super::pcb::Pid;
+use super::sched::{self, Task, TaskId};
+use super::table::process_table;
+use crate::arch::enter_user;
+use crate::fs::fd::FileDescriptorTable;
+use crate::fs::file::OpenFlags;
+use crate::fs::vfs::{MountFlags, vfs};
+use crate::fs::{FileSystem, FsError, try_zeroed};
+use crate::mm::address_space::{AddressSpace, MapError};
+use crate::mm::mmu::MapFlags;
+use crate::mm::page_allocator::PAGE_SIZE;
+use crate::mm::vma::{PROT_EXEC, PROT_READ, PROT_WRITE, USER_STACK_SIZE, USER_STACK_TOP, Vma};
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u32 = 1;
+const ET_EXEC: u16 = 2;
+
+#[cfg(target_arch = "arm")]
+const MACHINE: u16 = 40; // EM_ARM
+#[cfg(target_arch = "x86")]
+const MACHINE: u16 = 3; // EM_386
+
+/// EABI version in the top byte of `e_flags`
+#[cfg(target_arch = "arm")]
+const EF_ARM_EABIMASK: u32 = 0xFF00_0000;
+#[cfg(target_arch = "arm")]
+const EF_ARM_EABI_VER4: u32 = 0x0400_0000;
+#[cfg(target_arch = "arm")]
+const EF_ARM_EABI_VER5: u32 = 0x0500_0000;
+
+const EHDR_SIZE: usize = 52;
+const PHDR_SIZE: usize = 32;
+
+const PT_LOAD: u32 = 1;
+const PT_INTERP: u32 = 3;
+
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+const PF_R: u32 = 0x4;
+
+/// Auxiliary vector entry types
+const AT_NULL: u32 = 0;
+const AT_PAGESZ: u32 = 6;
+const AT_ENTRY: u32 = 9;
+
+/// Most bytes of arguments, environment and the pointers to them that a
+/// program can start with
+pub const ARG_MAX: usize = USER_STACK_SIZE / 4;
+
+#[derive(Debug)]
+pub enum ExecError {
+    /// Not an ELF executable this kernel runs: wrong class, byte order,
+    /// machine or ABI, not `ET_EXEC`, or dynamically linked
+    NotExecutable,
+    /// Headers or segments that run past the end of the file, overlap,
+    /// or leave the entry point outside every segment
+    Malformed,
+    /// The arguments and environment are larger than [`ARG_MAX`]
+    ArgumentsTooLong,
+    /// The running task is not a process
+    NoProcess,
+    /// Every PID is in use
+    TooManyProcesses,
+    /// The program could not be read
+    Fs(FsError),
+    /// The address space could not be built, including segments that
+    /// clash with the kernel's mappings
+    Map(MapError),
+}
+
+impl From<FsError> for ExecError {
+    fn from(err: FsError) -> Self {
+        ExecError::Fs(err)
+    }
+}
+
+impl From<MapError> for ExecError {
+    fn from(err: MapError) -> Self {
+        ExecError::Map(err)
+    }
+}
+
+impl From<ForkError> for ExecError {
+    fn from(err: ForkError) -> Self {
+        match err {
+            ForkError::NoProcess => ExecError::NoProcess,
+            ForkError::OutOfMemory => ExecError::Map(MapError::OutOfMemory),
+            ForkError::TooManyProcesses => ExecError::TooManyProcesses,
+        }
+    }
+}
+
+/// A loaded program, ready to be entered
+pub struct Image {
+    pub address_space: AddressSpace,
+    /// Entry point
+    pub entry: usize,
+    /// Initial stack pointer, pointing at `argc`
+    pub sp: usize,
+}
+
+/// A `PT_LOAD` program header
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    offset: usize,
+    vaddr: usize,
+    filesz: usize,
+    memsz: usize,
+    flags: u32,
+}
+
+impl Segment {
+    fn end(&self) -> usize {
+        self.vaddr + self.memsz
+    }
+
+    fn map_flags(&self) -> MapFlags {
+        let mut prot = 0;
+        if self.flags & PF_R != 0 {
+            prot |= PROT_READ;
+        }
+        if self.flags & PF_W != 0 {
+            prot |= PROT_WRITE;
+        }
+        if self.flags & PF_X != 0 {
+            prot |= PROT_EXEC;
+        }
+        Vma::flags_for_prot(prot)
+    }
+}
+
+fn u16_at(raw: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([raw[offset], raw[offset + 1]])
+}
+
+fn u32_at(raw: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        raw[offset],
+        raw[offset + 1],
+        raw[offset + 2],
+        raw[offset + 3],
+    ])
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+/// Check the ELF header and return the entry point and the `PT_LOAD`
+/// segments, sorted by address
+fn parse(data: &[u8]) -> Result<(usize, Vec<Segment>), ExecError> {
+    if data.len() < EHDR_SIZE || !data.starts_with(ELF_MAGIC) {
+        return Err(ExecError::NotExecutable);
+    }
+    if data[4] != ELFCLASS32 || data[5] != ELFDATA2LSB || data[6] as u32 != EV_CURRENT {
+        return Err(ExecError::NotExecutable);
+    }
+    let (e_type, machine, version) = (u16_at(data, 16), u16_at(data, 18), u32_at(data, 20));
+    if e_type != ET_EXEC || machine != MACHINE || version != EV_CURRENT {
+        return Err(ExecError::NotExecutable);
+    }
+    #[cfg(target_arch = "arm")]
+    if !matches!(
+        u32_at(data, 36) & EF_ARM_EABIMASK,
+        EF_ARM_EABI_VER4 | EF_ARM_EABI_VER5
+    ) {
+        return Err(ExecError::NotExecutable);
+    }
+
+    let entry = u32_at(data, 24) as usize;
+    let phoff = u32_at(data, 28) as usize;
+    let phentsize = u16_at(data, 42) as usize;
+    let phnum = u16_at(data, 44) as usize;
+    if phentsize != PHDR_SIZE {
+        return Err(ExecError::Malformed);
+    }
+    let table = phoff
+        .checked_add(phnum * PHDR_SIZE)
+        .and_then(|end| data.get(phoff..end))
+        .ok_or(ExecError::Malformed)?;
+
+    let mut segments = Vec::new();
+    for phdr in table.chunks_exact(PHDR_SIZE) {
+        match u32_at(phdr, 0) {
+            PT_LOAD => {}
+            PT_INTERP => return Err(ExecError::NotExecutable),
+            _ => continue,
+        }
+        let segment = Segment {
+            offset: u32_at(phdr, 4) as usize,
+            vaddr: u32_at(phdr, 8) as usize,
+            filesz: u32_at(phdr, 16) as usize,
+            memsz: u32_at(phdr, 20) as usize,
+            flags: u32_at(phdr, 24),
+        };
+        let in_file = segment
+            .offset
+            .checked_add(segment.filesz)
+            .is_some_and(|end| end <= data.len());
+        if segment.filesz > segment.memsz
+            || !in_file
+            || segment.vaddr.checked_add(segment.memsz).is_none()
+        {
+            return Err(ExecError::Malformed);
+        }
+        if segment.memsz > 0 {
+            segments.push(segment);
+        }
+    }
+
+    segments.sort_unstable_by_key(|segment| segment.vaddr);
+    if segments
+        .windows(2)
+        .any(|pair| pair[1].vaddr < pair[0].end())
+    {
+        return Err(ExecError::Malformed);
+    }
+    if !segments
+        .iter()
+        .any(|segment| (segment.vaddr..segment.end()).contains(&entry))
+    {
+        return Err(ExecError::Malformed);
+    }
+    Ok((entry, segments))
+}
+
+// ============================================================================
+// Loading
+// ============================================================================
+
+/// Build an address space for the executable in `data`, with a stack
+/// holding `argv` and `envp`.
+pub fn load(data: &[u8], argv: &[String], envp: &[String]) -> Result<Image, ExecError> {
+    let (entry, segments) = parse(data)?;
+    let space = AddressSpace::new()?;
+
+    // Areas first, so that a page two segments share can get the
+    // protection of both. Segments do not overlap, so only the last page
+    // of one can be the first of the next.
+    let mut mapped_end = 0;
+    let mut last_flags = MapFlags::empty();
+    for segment in &segments {
+        let flags = segment.map_flags();
+        let start = segment.vaddr & !(PAGE_SIZE - 1);
+        let end = segment
+            .end()
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(ExecError::Malformed)?;
+        if start < mapped_end {
+            space.unmap(start, mapped_end - start)?;
+            space.add_vma(start, mapped_end - start, last_flags | flags)?;
+        }
+        let from = start.max(mapped_end);
+        if from < end {
+            space.add_vma(from, end - from, flags)?;
+        }
+        mapped_end = end;
+        last_flags = flags;
+    }
+
+    // The rest of each segment reads as zero
+    for segment in &segments {
+        let bytes = &data[segment.offset..segment.offset + segment.filesz];
+        space.copy_to(segment.vaddr, bytes)?;
+    }
+
+    space.init_heap(mapped_end)?;
+    space.add_vma(
+        USER_STACK_TOP - USER_STACK_SIZE,
+        USER_STACK_SIZE,
+        Vma::flags_for_prot(PROT_READ | PROT_WRITE),
+    )?;
+    let sp = setup_stack(&space, argv, envp, entry)?;
+
+    Ok(Image {
+        address_space: space,
+        entry,
+        sp,
+    })
+}
+
+/// Lay out the initial stack below [`USER_STACK_TOP`]: `argc`, the `argv`
+/// and `envp` pointer arrays, each ending in a null, and the auxiliary
+/// vector, with the strings they point to above them. Returns the stack
+/// pointer.
+fn setup_stack(
+    space: &AddressSpace,
+    argv: &[String],
+    envp: &[String],
+    entry: usize,
+) -> Result<usize, ExecError> {
+    let auxv = [
+        AT_PAGESZ,
+        PAGE_SIZE as u32,
+        AT_ENTRY,
+        entry as u32,
+        AT_NULL,
+        0,
+    ];
+    let strings_len: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+    let words = 1 + argv.len() + 1 + envp.len() + 1 + auxv.len();
+    if strings_len + words * 4 > ARG_MAX {
+        return Err(ExecError::ArgumentsTooLong);
+    }
+
+    let strings_start = USER_STACK_TOP - strings_len;
+    // The AAPCS wants the stack 8-byte aligned at a public interface
+    let sp = (strings_start - words * 4) & !7;
+
+    let mut table: Vec<u8> = Vec::with_capacity(words * 4);
+    let mut strings: Vec<u8> = Vec::with_capacity(strings_len);
+    table.extend_from_slice(&(argv.len() as u32).to_le_bytes());
+    for list in [argv, envp] {
+        for s in list {
+            let addr = (strings_start + strings.len()) as u32;
+            table.extend_from_slice(&addr.to_le_bytes());
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+        }
+        table.extend_from_slice(&0u32.to_le_bytes());
+    }
+    for word in auxv {
+        table.extend_from_slice(&word.to_le_bytes());
+    }
+
+    space.copy_to(sp, &table)?;
+    space.copy_to(strings_start, &strings)?;
+    Ok(sp)
+}
+
+/// Read the whole file at `path`, unless its mount forbids executing it
+fn read_program(path: &str) -> Result<Vec<u8>, ExecError> {
+    if vfs().mount_flags(path)?.contains(MountFlags::NOEXEC) {
+        return Err(FsError::PermissionDenied.into());
+    }
+    let file = vfs().open(path, OpenFlags::RDONLY)?;
+    let size = file.stat().map_err(FsError::from)?.size;
+    let mut data = try_zeroed(size).ok_or(FsError::OutOfMemory)?;
+
+    let mut filled = 0;
+    while filled < size {
+        let n = file
+            .read(&mut data[filled..], filled)
+            .map_err(FsError::from)?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    data.truncate(filled);
+    Ok(data)
+}
+
+// ============================================================================
+// execve
+// ============================================================================
+
+/// Replace the running process's program with the executable at `path`,
+/// started with `argv` and `envp`. Descriptors marked close-on-exec are
+/// closed; the rest stay open.
+///
+/// Takes its arguments by value so that nothing is left allocated when
+/// the new program starts. Returns only on failure, which leaves the
+/// process as it was.
+pub fn execve(path: String, argv: Vec<String>, envp: Vec<String>) -> ExecError {
+    match replace_image(&path, &argv, &envp) {
+        Ok((entry, sp)) => {
+            drop((path, argv, envp));
+            // SAFETY: the new address space is active and owned by the
+            // running task, and nothing on this stack is needed again
+            unsafe { enter_user(entry, sp) }
+        }
+        Err(err) => err,
+    }
+}
+
+/// Load the program and install it in the running process, returning
+/// its entry point and stack pointer
+fn replace_image(
+    path: &str,
+    argv: &[String],
+    envp: &[String],
+) -> Result<(usize, usize), ExecError> {
+    let task = sched::current().ok_or(ExecError::NoProcess)?;
+    if process_table().lock().by_task(task).is_none() {
+        return Err(ExecError::NoProcess);
+    }
+
+    let image = load(&read_program(path)?, argv, envp)?;
+    let name = program_name(path);
+
+    sched::with_current(|task| {
+        task.name = name.into();
+        task.user_stack = None;
+        let old = task.address_space.replace(image.address_space);
+        if let Some(space) = &task.address_space {
+            // SAFETY: the address space lives in the task, which outlives
+            // its time as the running one
+            unsafe { space.activate() };
+        }
+        // Only now that it is no longer in use
+        drop(old);
+        if let Some(signals) = &task.signals {
+            signals.lock().exec();
+        }
+    })
+    .ok_or(ExecError::NoProcess)?;
+
+    let closed = process_table().lock().by_task_mut(task).map(|process| {
+        process.name = name.into();
+        process.fd_table.close_on_exec()
+    });
+    // The files are released without the process table
+    drop(closed);
+    Ok((image.entry, image.sp))
+}
+
+// ============================================================================
+// Spawning
+// ============================================================================
+
+/// Where spawned programs that have not run yet start: entry point and
+/// stack pointer, by task
+static STARTS: Mutex<Vec<(TaskId, usize, usize)>> = Mutex::new(Vec::new());
+
+/// Start the executable at `path` as a new process, started with `argv`
+/// and `envp`, and return its PID. The process has no parent and its
+/// standard descriptors are the console; the first one spawned gets
+/// [`INIT_PID`](super::exit::INIT_PID).
+///
+/// The program is loaded before anything else is created, so a missing
+/// or malformed file fails here rather than in the new process.
+pub fn spawn_from_path(path: &str, argv: &[String], envp: &[String]) -> Result<Pid, ExecError> {
+    let image = load(&read_program(path)?, argv, envp)?;
+    let mut task =
+        Task::new(program_name(path), start_program).map_err(|_| MapError::OutOfMemory)?;
+    task.address_space = Some(image.address_space);
+
+    let id = task.id;
+    // In place before the task can run
+    STARTS.lock().push((id, image.entry, image.sp));
+    fork::spawn(task, None, FileDescriptorTable::new())
+        .inspect_err(|_| STARTS.lock().retain(|&(task, _, _)| task != id))
+        .map_err(ExecError::from)
+}
+
+/// First code run by a spawned program's task, in its address space:
+/// drop to user mode at the program's entry point
+fn start_program() {
+    let Some(task) = sched::current() else {
+        return;
+    };
+    let start = {
+        let mut starts = STARTS.lock();
+        let index = starts.iter().position(|&(id, _, _)| id == task);
+        index.map(|index| starts.swap_remove(index))
+    };
+    if let Some((_, entry, sp)) = start {
+        // SAFETY: the task was created with the program's address space,
+        // which is active now that it runs, and its kernel stack holds
+        // nothing needed again
+        unsafe { enter_user(entry, sp) }
+    }
+}
+
+/// Name of the program at `path`, for the task and process tables
+fn program_name(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
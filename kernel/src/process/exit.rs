@@ -0,0 +1,110 @@
+//! Process exit and reaping
+//!
+//! An exiting process closes its files and gives up its task straight
+//! away, but stays in the process table as a zombie holding its wait
+//! status until its parent collects it with [`wait`] or [`waitpid`].
+//! Children left behind are handed to [`INIT_PID`], which reaps them in
+//! turn.
+
+use super::pcb::{Pid, ProcessState};
+use super::sched::{self, TaskId, WaitQueue};
+use super::table::process_table;
+
+/// The first process, which adopts orphans
+pub const INIT_PID: Pid = Pid(1);
+
+/// `waitpid` option: return at once if no child has exited
+pub const WNOHANG: u32 = 0x1;
+
+/// Parents waiting for a child to exit; each checks for its own
+static CHILD_EXIT: WaitQueue = WaitQueue::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+    /// The running task is not a process
+    NoProcess,
+    /// No child matches
+    NoChild,
+}
+
+/// End the running process with `status`. A task that is not a process
+/// just exits.
+///
+/// # Panics
+/// If the process is init.
+pub fn exit(status: i32) -> ! {
+    terminate((status & 0xFF) << 8)
+}
+
+/// End the running process as killed by `signal`
+pub(super) fn kill_current(signal: u32) -> ! {
+    terminate(signal as i32)
+}
+
+/// End the running process, leaving `wait_status` for its parent
+fn terminate(wait_status: i32) -> ! {
+    let files = sched::current().and_then(|task| {
+        let mut table = process_table().lock();
+        let process = table.by_task_mut(task)?;
+        let pid = process.pid;
+        assert_ne!(pid, INIT_PID, "init exited, wait status {:#x}", wait_status);
+
+        process.state = ProcessState::Zombie;
+        process.exit_code = Some(wait_status);
+        let files = process.fd_table.close_all();
+        table.reparent_children(pid, INIT_PID);
+        Some(files)
+    });
+    drop(files);
+
+    CHILD_EXIT.wake_all();
+    // The stacks and address space go with the task
+    sched::exit();
+}
+
+/// Wait for a child to exit and reap it, returning its PID and wait
+/// status.
+pub fn wait() -> Result<(Pid, i32), WaitError> {
+    waitpid(None, 0)?.ok_or(WaitError::NoChild)
+}
+
+/// Wait for the child `pid`, or any child if `None`, to exit and reap
+/// it, returning its PID and wait status. With [`WNOHANG`], returns
+/// `None` instead of waiting if no such child has exited yet.
+pub fn waitpid(pid: Option<Pid>, options: u32) -> Result<Option<(Pid, i32)>, WaitError> {
+    let task = sched::current().ok_or(WaitError::NoProcess)?;
+    if options & WNOHANG != 0 {
+        return reap(task, pid);
+    }
+
+    let mut result = Ok(None);
+    CHILD_EXIT.sleep_on(|| {
+        result = reap(task, pid);
+        result != Ok(None)
+    });
+    result
+}
+
+/// Remove an exited child of the process run by `task` from the table
+fn reap(task: TaskId, pid: Option<Pid>) -> Result<Option<(Pid, i32)>, WaitError> {
+    let mut table = process_table().lock();
+    let parent = table.by_task(task).ok_or(WaitError::NoProcess)?;
+
+    let mut matching = parent
+        .children
+        .iter()
+        .filter(|&&child| pid.is_none_or(|pid| pid == child))
+        .peekable();
+    if matching.peek().is_none() {
+        return Err(WaitError::NoChild);
+    }
+    let zombie = matching.copied().find(|&child| {
+        table
+            .get(child)
+            .is_some_and(|process| process.state == ProcessState::Zombie)
+    });
+
+    Ok(zombie
+        .and_then(|child| table.remove(child))
+        .map(|child| (child.pid, child.exit_code.unwrap_or(0))))
+}
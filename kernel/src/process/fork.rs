@@ -0,0 +1,69 @@
+//! Process creation
+
+use super::pcb::Pid;
+use super::sched::{self, Task};
+use super::signal::SignalState;
+use super::stack::StackError;
+use super::table::process_table;
+use crate::arch::TrapFrame;
+use crate::fs::fd::FileDescriptorTable;
+use crate::mm::address_space::MapError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForkError {
+    /// The running task is not a process
+    NoProcess,
+    /// Not enough memory for the child's stacks or page tables
+    OutOfMemory,
+    /// Every PID is in use
+    TooManyProcesses,
+}
+
+impl From<StackError> for ForkError {
+    fn from(_: StackError) -> Self {
+        ForkError::OutOfMemory
+    }
+}
+
+impl From<MapError> for ForkError {
+    fn from(_: MapError) -> Self {
+        ForkError::OutOfMemory
+    }
+}
+
+/// Enter `task` in the process table as a child of `parent` and make it
+/// ready to run. A task without signal state starts with the defaults.
+pub fn spawn(
+    mut task: Task,
+    parent: Option<Pid>,
+    fd_table: FileDescriptorTable,
+) -> Result<Pid, ForkError> {
+    let signals = task
+        .signals
+        .get_or_insert_with(|| SignalState::new().shared())
+        .clone();
+    let pid = process_table()
+        .lock()
+        .insert(&task.name, task.id, parent, fd_table, signals)
+        .ok_or(ForkError::TooManyProcesses)?;
+    sched::enqueue(task);
+    Ok(pid)
+}
+
+/// Duplicate the running process, which trapped into the kernel with
+/// `frame`. Returns the child's PID; the child itself resumes from the
+/// frame with a result of 0.
+///
+/// The child gets copies of the task, its file descriptors and signal
+/// actions, and shares the address space copy-on-write.
+pub fn fork(frame: &TrapFrame) -> Result<Pid, ForkError> {
+    let task = sched::current().ok_or(ForkError::NoProcess)?;
+    let (parent, fd_table) = {
+        let table = process_table().lock();
+        let parent = table.by_task(task).ok_or(ForkError::NoProcess)?;
+        (parent.pid, parent.fd_table.fork())
+    };
+
+    let child = sched::with_current(|task| task.fork(frame)).ok_or(ForkError::NoProcess)??;
+    spawn(child, Some(parent), fd_table)
+}
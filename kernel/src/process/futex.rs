@@ -0,0 +1,155 @@
+//! Futexes
+//!
+//! A futex is a word of user memory that user code takes locks or counts
+//! with, only entering the kernel to sleep until the word changes or to
+//! wake sleepers. Sleepers are kept in one of [`BUCKETS`] buckets, chosen
+//! by hashing the word's physical address, and sleep on that bucket's
+//! wait queue. A wake marks the sleepers on the word as woken and wakes
+//! the queue; sleepers on other words hashed to the same bucket go back
+//! to sleep.
+//!
+//! The word's page is given a private frame first, as a write would, so
+//! the physical address cannot change under a sleeper through
+//! copy-on-write. Futex words must therefore be in writable memory.
+//!
+//! A sleeper only returns once woken; there are no timeouts yet, and
+//! nothing interrupts the sleep.
+
+use super::sched::{self, TaskId, WaitQueue};
+use crate::arch::IrqSpinLock;
+use crate::mm::address_space::{AddressSpace, FaultError};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// `futex` operation: sleep if the word still holds the given value
+pub const FUTEX_WAIT: u32 = 0;
+/// `futex` operation: wake up to the given number of sleepers
+pub const FUTEX_WAKE: u32 = 1;
+/// `futex` operation flag: the word is private to the process. Ignored,
+/// as every futex is keyed by physical address.
+pub const FUTEX_PRIVATE_FLAG: u32 = 128;
+
+/// Buckets in the futex hash table
+pub const BUCKETS: usize = 64;
+
+static TABLE: [Bucket; BUCKETS] = [const { Bucket::new() }; BUCKETS];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The running task is not a process
+    NoProcess,
+    /// The word is not 4-byte aligned
+    Unaligned,
+    /// The word is not in writable user memory
+    BadAddress,
+    /// The word no longer holds the value to sleep on
+    ValueChanged,
+    /// No memory for the word's page
+    OutOfMemory,
+}
+
+struct Bucket {
+    /// Sleepers on the words hashed here, longest asleep first
+    waiters: IrqSpinLock<Vec<Waiter>>,
+    queue: WaitQueue,
+}
+
+impl Bucket {
+    const fn new() -> Self {
+        Self {
+            waiters: IrqSpinLock::new(Vec::new()),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Forget `task` if it has been woken, returning whether it was
+    fn take_woken(&self, task: TaskId) -> bool {
+        let mut waiters = self.waiters.lock();
+        let Some(index) = waiters
+            .iter()
+            .position(|waiter| waiter.task == task && waiter.woken)
+        else {
+            return false;
+        };
+        waiters.remove(index);
+        true
+    }
+}
+
+struct Waiter {
+    /// Physical address of the word
+    key: usize,
+    task: TaskId,
+    woken: bool,
+}
+
+/// Physical address of the word at `addr` in `space`, which identifies
+/// the futex
+fn key(space: &AddressSpace, addr: usize) -> Result<usize, FutexError> {
+    if !addr.is_multiple_of(4) {
+        return Err(FutexError::Unaligned);
+    }
+    space.private_address(addr).map_err(|err| match err {
+        FaultError::OutOfMemory => FutexError::OutOfMemory,
+        _ => FutexError::BadAddress,
+    })
+}
+
+fn bucket(key: usize) -> &'static Bucket {
+    &TABLE[(key / 4) % BUCKETS]
+}
+
+/// Sleep until woken by [`wake`] on the word at `addr` in `space`, the
+/// running process's address space, provided the word holds `expected`.
+/// Fails with [`FutexError::ValueChanged`] at once otherwise.
+pub fn wait(space: &AddressSpace, addr: usize, expected: u32) -> Result<(), FutexError> {
+    let key = key(space, addr)?;
+    let task = sched::current().ok_or(FutexError::NoProcess)?;
+    let bucket = bucket(key);
+
+    {
+        // A wake cannot slip in between the check and queueing
+        let mut waiters = bucket.waiters.lock();
+        // SAFETY: `key` is the physical address of an aligned word in a
+        // frame of `space`, and the kernel maps RAM at its physical
+        // address
+        let value = unsafe { &*(key as *const AtomicU32) }.load(Ordering::SeqCst);
+        if value != expected {
+            return Err(FutexError::ValueChanged);
+        }
+        waiters.push(Waiter {
+            key,
+            task,
+            woken: false,
+        });
+    }
+
+    bucket.queue.sleep_on(|| bucket.take_woken(task));
+    Ok(())
+}
+
+/// Wake up to `count` sleepers on the word at `addr` in `space`, the
+/// longest asleep first, returning how many were woken
+pub fn wake(space: &AddressSpace, addr: usize, count: usize) -> Result<usize, FutexError> {
+    let key = key(space, addr)?;
+    let bucket = bucket(key);
+
+    let woken = {
+        let mut waiters = bucket.waiters.lock();
+        let mut woken = 0;
+        for waiter in waiters
+            .iter_mut()
+            .filter(|waiter| waiter.key == key && !waiter.woken)
+            .take(count)
+        {
+            waiter.woken = true;
+            woken += 1;
+        }
+        woken
+    };
+
+    if woken > 0 {
+        bucket.queue.wake_all();
+    }
+    Ok(woken)
+}
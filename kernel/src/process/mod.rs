@@ -1,3 +1,9 @@
+pub mod elf;
+pub mod exit;
+pub mod fork;
+pub mod futex;
 pub mod pcb;
 pub mod sched;
+pub mod signal;
 pub mod stack;
+pub mod table;
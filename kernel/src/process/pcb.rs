@@ -1,20 +1,8 @@
-use super::stack::KernelStack;
+use super::sched::TaskId;
+use super::signal::SharedSignals;
 use crate::fs::fd::FileDescriptorTable;
-use crate::mm::page_table::L1Table;
-use crate::process::stack::UserStack;
 use alloc::string::String;
-
-cfg_if::cfg_if! {
-    if #[cfg(target_arch = "arm")] {
-        use crate::arch::arm::context::Context;
-    }
-    else if #[cfg(target_arch = "x86")] {
-        use crate::arch::x86::context::Context;
-    }
-    else {
-        compile_error!("Unsupported architecture for Process Control Block");
-    }
-}
+use alloc::vec::Vec;
 
 /// Process states
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -30,6 +18,9 @@ pub enum ProcessState {
 pub struct Pid(pub usize);
 
 /// Process Control Block
+///
+/// The stacks, CPU context and address space belong to the process's task,
+/// which the scheduler owns.
 pub struct Process {
     /// Process ID
     pub pid: Pid,
@@ -37,33 +28,26 @@ pub struct Process {
     /// Parent process ID
     pub parent_pid: Option<Pid>,
 
+    /// Child process IDs
+    pub children: Vec<Pid>,
+
     /// Current state
     pub state: ProcessState,
 
-    /// CPU context (registers to restore)
-    pub context: Context,
-
-    /// Page table
-    pub page_table: L1Table,
-
-    /// Kernel stack
-    pub kernel_stack: KernelStack,
-
-    /// User stack
-    pub user_stack: UserStack,
+    /// Task that runs the process
+    pub task: TaskId,
 
     /// Process name
     pub name: String,
 
-    /// Priority (for scheduling)
-    pub priority: u8,
-
-    /// Time quantum remaining
-    pub time_slice: u32,
-
     /// File descriptor table
     pub fd_table: FileDescriptorTable,
 
-    /// Exit code (if zombie)
+    /// Wait status (if zombie): the exit code in bits 8-15, or the
+    /// signal that killed it
     pub exit_code: Option<i32>,
+
+    /// Pending and blocked signals, and their actions, shared with the
+    /// task
+    pub signals: SharedSignals,
 }
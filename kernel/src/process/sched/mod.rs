@@ -1 +1,14 @@
 pub mod scheduler;
+pub mod task;
+pub mod timer;
+pub mod wait;
+
+pub use scheduler::{
+    AGING_TICKS, NICE_MAX, NICE_MIN, TICK_US, TIME_SLICE, TaskStats, WAKE_BOOST, block, cpu_stats,
+    current, enqueue, exit, init, init_secondary, is_locked, nice, preempt, preempt_disable,
+    preempt_enable, preempt_enable_no_resched, preemptible, set_nice, spawn, spawn_in, task_stats,
+    tick, try_for_each_task, wake, with_current, yield_now,
+};
+pub use task::{Task, TaskId};
+pub use timer::{sleep_ms, sleep_us, ticks};
+pub use wait::WaitQueue;
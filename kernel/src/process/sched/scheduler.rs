@@ -1,53 +1,664 @@
-use crate::arch::IrqSpinLock;
-use crate::process::pcb::Pid;
-use alloc::collections::VecDeque;
+//! Priority scheduler
+//!
+//! Ready tasks wait in a run queue, and the one with the best priority
+//! runs next, the longest queued first among equals. A task's priority is
+//! its nice level, from [`NICE_MIN`] (most favoured) to [`NICE_MAX`],
+//! improved by [`WAKE_BOOST`] levels when it wakes from a sleep, which
+//! wear off a level per tick it runs. A ready task also gains a level for
+//! every [`AGING_TICKS`] ticks it waits, so none starves.
+//!
+//! Each timer tick charges the running task one tick of its time slice;
+//! once the slice is used up, or a ready task has a better priority, the
+//! task is preempted on the way out of the interrupt and goes back in the
+//! queue. [`yield_now`] gives up the rest of a slice early.
+//!
+//! A task that [`block`]s is set aside until something calls [`wake`] on
+//! it, normally through a [`WaitQueue`](super::WaitQueue). When no task is
+//! ready, the idle task runs and waits for interrupts; it is kept out of
+//! the run queue and gives way as soon as another task is woken.
+//!
+//! The CPU time of every task is measured on the system timer at each
+//! switch, and the time spent busy and idle is totalled; see
+//! [`task_stats`] and [`cpu_stats`].
+//!
+//! Every CPU has a scheduler of its own, with its own run queue and idle
+//! task. A new task goes to the CPU with the fewest tasks ready, and
+//! stays there; as tasks never move between CPUs, a task switched out is
+//! never picked up elsewhere before its context is saved. Another CPU is
+//! told to reschedule with an inter-processor interrupt.
+//!
+//! Code that must not be switched away from, such as an interrupt
+//! handler, runs between [`preempt_disable`] and [`preempt_enable`]. A
+//! switch asked for meanwhile waits until the count drops back to zero,
+//! and switching tasks while it is raised is a bug.
+//!
+//! [`init`] adopts the boot thread as the first task and starts the idle
+//! task; [`init_secondary`] does the same on the other CPUs. x86 has no
+//! interrupt entry path yet, so tasks there only change on [`yield_now`].
 
-const HIGH_QUANTA: usize = 3;
-const MID_QUANTA: usize = 2;
-const LOW_QUANTA: usize = 1;
+use super::task::{Task, TaskId};
+use super::timer;
+use crate::arch::{Irq, IrqSpinLock, MAX_CPUS, TaskContext, context_switch, cpu_id};
+use crate::irq::handlers;
+use crate::mm::address_space::AddressSpace;
+use crate::process::pcb::ProcessState;
+use crate::process::stack::StackError;
+use crate::subsystems::{irq_controller, system_timer, uptime_us};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+use common::sync::irq::IrqControl;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use drivers::device_manager::DeviceManager;
+use drivers::platform::Platform;
+
+/// Timer tick period (100 Hz)
+pub const TICK_US: u32 = 10_000;
+
+/// Ticks a task runs before it is preempted
+pub const TIME_SLICE: u32 = 5;
+
+/// Most favoured nice level
+pub const NICE_MIN: i32 = -20;
+/// Least favoured nice level
+pub const NICE_MAX: i32 = 19;
+
+/// Priority levels lent to a task woken from a sleep
+pub const WAKE_BOOST: i32 = 5;
+
+/// Ticks a ready task waits for each level its priority improves
+pub const AGING_TICKS: u32 = 10;
+
+static SCHEDULERS: [Scheduler; MAX_CPUS] = [const { Scheduler::new() }; MAX_CPUS];
+
+/// Sections each CPU is in that must not switch tasks
+static PREEMPT_COUNT: [AtomicUsize; MAX_CPUS] = [const { AtomicUsize::new(0) }; MAX_CPUS];
 
 pub struct Scheduler {
     inner: IrqSpinLock<SchedulerInner>,
 }
 
+impl Scheduler {
+    const fn new() -> Self {
+        Self {
+            inner: IrqSpinLock::new(SchedulerInner {
+                current: None,
+                run_queue: VecDeque::new(),
+                blocked: BTreeMap::new(),
+                exited: None,
+                idle: None,
+                idle_id: None,
+                need_resched: false,
+                switched_at: 0,
+                busy_us: 0,
+                idle_us: 0,
+            }),
+        }
+    }
+}
+
+/// The running CPU's scheduler
+fn local() -> &'static Scheduler {
+    &SCHEDULERS[cpu_id()]
+}
+
+/// Ask `cpu`, which is not the running one, to call [`preempt`]
+fn kick(cpu: usize) {
+    #[cfg(all(target_arch = "arm", feature = "bcm2836"))]
+    crate::arch::arm::smp::send_reschedule(cpu);
+    #[cfg(not(all(target_arch = "arm", feature = "bcm2836")))]
+    let _ = cpu;
+}
+
 struct SchedulerInner {
-    // Realtime queue: ALWAYS runs first (strict priority)
-    realtime_queue: VecDeque<Pid>,
+    /// Running task; `None` until the CPU's scheduler is initialized
+    current: Option<Box<Task>>,
+
+    /// Ready tasks, next to run first
+    run_queue: VecDeque<Box<Task>>,
 
-    // Fair-share queues: Use 3:2:1 ratio
-    high_queue: VecDeque<Pid>,
-    mid_queue: VecDeque<Pid>,
-    low_queue: VecDeque<Pid>,
+    /// Tasks waiting to be woken
+    blocked: BTreeMap<TaskId, Box<Task>>,
 
-    schedule_cycle: usize,
-    time_slice: u32,
+    /// Last task to exit, kept until it is certainly off its stack. Every
+    /// switch frees it first, so there is never more than one.
+    exited: Option<Box<Task>>,
+
+    /// The idle task while another task runs
+    idle: Option<Box<Task>>,
+
+    idle_id: Option<TaskId>,
+
+    /// Set by the tick once the running task's slice is used up, and by
+    /// a wake that ends the idle task's turn
+    need_resched: bool,
+
+    /// System timer reading at the last switch, in microseconds
+    switched_at: u64,
+
+    /// CPU time spent in tasks other than the idle task
+    busy_us: u64,
+
+    /// CPU time spent in the idle task
+    idle_us: u64,
+}
+
+/// Effective priority of `task`; lower runs first
+fn priority(task: &Task) -> i32 {
+    task.nice - task.boost - (task.waited / AGING_TICKS) as i32
 }
 
 impl SchedulerInner {
-    pub fn schedule(&mut self) -> Option<Pid> {
-        // Realtime ALWAYS goes first (strict priority)
-        if let Some(pid) = self.realtime_queue.pop_front() {
-            return Some(pid);
+    /// Make the next ready task current, or the idle task if none is
+    /// and the running task cannot go on, returning the contexts to
+    /// switch from and to. Tasks are boxed, so both stay put after the
+    /// lock is released.
+    fn switch_next(&mut self, now: Option<u64>) -> Option<(*mut TaskContext, *const TaskContext)> {
+        self.need_resched = false;
+        // Nothing to switch from before `init`
+        let current = self.current.as_ref()?;
+        // The first of equals, so equal tasks take turns
+        let best = self
+            .run_queue
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, task)| priority(task))
+            .map(|(index, _)| index);
+        let mut next = match best {
+            Some(index) => self.run_queue.remove(index)?,
+            None if current.state == ProcessState::Running => return None,
+            None => self.idle.take()?,
+        };
+        let mut prev = self.current.take()?;
+        self.charge(&mut prev, now);
+
+        next.state = ProcessState::Running;
+        next.time_slice = TIME_SLICE;
+        next.waited = 0;
+        if let Some(space) = &next.address_space {
+            // SAFETY: the address space lives in the task, which outlives
+            // its time as the running one
+            unsafe { space.activate() };
         }
 
-        // Then use ratio for other queues
-        let step = self.schedule_cycle % (HIGH_QUANTA + MID_QUANTA + LOW_QUANTA);
+        let from = &mut prev.context as *mut TaskContext;
+        let to = &next.context as *const TaskContext;
+        match prev.state {
+            _ if Some(prev.id) == self.idle_id => {
+                prev.state = ProcessState::Ready;
+                self.idle = Some(prev);
+            }
+            ProcessState::Zombie => self.exited = Some(prev),
+            ProcessState::Blocked => {
+                self.blocked.insert(prev.id, prev);
+            }
+            _ => {
+                prev.state = ProcessState::Ready;
+                self.run_queue.push_back(prev);
+            }
+        }
+        #[cfg(feature = "lock-debug")]
+        crate::sync::lockdep::switch_to(next.id);
+        self.current = Some(next);
+        Some((from, to))
+    }
 
-        let queue: &mut VecDeque<Pid>;
-        if step < HIGH_QUANTA {
-            queue = &mut self.high_queue;
-        } else if step < MID_QUANTA {
-            queue = &mut self.mid_queue;
+    /// Charge `task`, which ran until `now`, for the time since the last
+    /// switch
+    fn charge(&mut self, task: &mut Task, now: Option<u64>) {
+        let Some(now) = now else {
+            return;
+        };
+        let ran = now.saturating_sub(core::mem::replace(&mut self.switched_at, now));
+        task.cpu_us += ran;
+        if Some(task.id) == self.idle_id {
+            self.idle_us += ran;
         } else {
-            queue = &mut self.low_queue;
+            self.busy_us += ran;
+        }
+    }
+
+    /// Whether the idle task is running
+    fn idling(&self) -> bool {
+        self.current
+            .as_ref()
+            .is_some_and(|task| Some(task.id) == self.idle_id)
+    }
+
+    /// Tasks ready or running, besides the idle task; `None` if the CPU
+    /// is not running tasks
+    fn load(&self) -> Option<usize> {
+        self.current.as_ref()?;
+        Some(self.run_queue.len() + usize::from(!self.idling()))
+    }
+
+    /// The task `id`, wherever it is
+    fn task_mut(&mut self, id: TaskId) -> Option<&mut Task> {
+        if let Some(task) = self.current.as_deref_mut()
+            && task.id == id
+        {
+            return Some(task);
+        }
+        if let Some(task) = self.blocked.get_mut(&id) {
+            return Some(task);
         }
+        if let Some(task) = self.idle.as_deref_mut()
+            && task.id == id
+        {
+            return Some(task);
+        }
+        self.run_queue
+            .iter_mut()
+            .map(|task| &mut **task)
+            .find(|task| task.id == id)
+    }
+}
+
+/// Switch to the next ready task, if any. The running task goes to the
+/// back of the run queue unless it has exited or blocked, in which case
+/// the idle task takes over if nothing else is ready.
+///
+/// # Panics
+/// If preemption is disabled, as in an interrupt handler.
+pub(super) fn schedule() {
+    assert!(preemptible(), "scheduling while atomic");
+    let irq = Irq::save_and_disable();
+    let now = uptime_us();
 
-        self.schedule_cycle += 1;
+    // Whichever task is running now, it is not this one
+    let exited = local().inner.lock().exited.take();
+    drop(exited);
 
-        queue.pop_front().or_else(|| self.fallback())
+    let mut inner = local().inner.lock();
+    if let Some((from, to)) = inner.switch_next(now) {
+        drop(inner);
+        // SAFETY: both contexts belong to tasks the scheduler owns, and
+        // interrupts stay off until the switch is complete
+        unsafe { context_switch(from, to) };
+    } else {
+        drop(inner);
     }
 
-    fn fallback(&self) -> Option<Pid> {
-        None
+    Irq::restore(irq);
+}
+
+/// Body of the idle task: wait for an interrupt, and let whatever it
+/// woke run. Without the tick nothing may interrupt, so it only yields.
+fn idle() {
+    loop {
+        if timer::ticking() {
+            Irq::wait_for_interrupt();
+        }
+        yield_now();
+    }
+}
+
+/// First code run by every spawned task
+pub(super) extern "C" fn task_start() -> ! {
+    let entry = local()
+        .inner
+        .lock()
+        .current
+        .as_ref()
+        .and_then(|task| task.entry);
+
+    // Tasks are switched to with interrupts off
+    Irq::enable();
+    if let Some(entry) = entry {
+        entry();
     }
+    exit();
+}
+
+/// Adopt the running boot thread as the first task, create the idle task
+/// and start the timer tick that drives preemption.
+///
+/// # Panics
+/// If there is no memory for the idle task's stack.
+pub fn init() {
+    adopt_boot_thread();
+
+    #[cfg(target_arch = "arm")]
+    match start_tick() {
+        Ok(()) => {
+            timer::start();
+            Irq::enable();
+        }
+        Err(e) => log::warn!("No scheduler tick ({}); tasks only switch on yield", e),
+    }
+}
+
+/// Start scheduling on a secondary CPU once it is up: its boot thread
+/// becomes a task and exits, leaving the CPU to its idle task until
+/// tasks are given to it. The timer tick on the boot CPU preempts its
+/// tasks.
+///
+/// # Panics
+/// If there is no memory for the idle task's stack.
+pub fn init_secondary() -> ! {
+    adopt_boot_thread();
+    Irq::enable();
+    exit();
+}
+
+/// Make the running thread the first task of this CPU, with an idle task
+/// to fall back on
+fn adopt_boot_thread() {
+    let idle = Task::new("idle", idle).expect("no memory for the idle task");
+    let now = uptime_us();
+    let mut inner = local().inner.lock();
+    assert!(inner.current.is_none(), "scheduler already initialized");
+    let boot = Box::new(Task::boot());
+    #[cfg(feature = "lock-debug")]
+    crate::sync::lockdep::switch_to(boot.id);
+    inner.current = Some(boot);
+    inner.idle_id = Some(idle.id);
+    inner.idle = Some(Box::new(idle));
+    inner.switched_at = now.unwrap_or(0);
+}
+
+/// Route the system timer's interrupt to the tick handler and start it
+fn start_tick() -> Result<(), &'static str> {
+    let irq = Platform::find_device("timer")
+        .and_then(|device| device.irq)
+        .ok_or("timer has no IRQ")?;
+    let channel = DeviceManager::sys_timer_channel().ok_or("no system timer channel")?;
+    let timer = system_timer().ok_or("no system timer")?;
+    let irqctl = irq_controller().ok_or("no IRQ controller")?;
+
+    handlers::register(irq, handlers::timer)
+        .map_err(|_| "invalid timer IRQ")?
+        .forget();
+    handlers::start_tick(timer, channel).map_err(|_| "failed to start system timer")?;
+    irqctl
+        .lock()
+        .enable(irq)
+        .map_err(|_| "failed to enable timer IRQ")
+}
+
+/// Start a kernel task that runs `entry` and exits when it returns
+pub fn spawn(name: &str, entry: fn()) -> Result<TaskId, StackError> {
+    Ok(enqueue(Task::new(name, entry)?))
+}
+
+/// Start a task that runs `entry` in `address_space`
+pub fn spawn_in(
+    name: &str,
+    entry: fn(),
+    address_space: AddressSpace,
+) -> Result<TaskId, StackError> {
+    Ok(enqueue(Task::with_address_space(
+        name,
+        entry,
+        address_space,
+    )?))
+}
+
+/// Make `task` ready to run, on the CPU with the fewest tasks. The
+/// running CPU takes it before the scheduler is initialized.
+pub fn enqueue(task: Task) -> TaskId {
+    let id = task.id;
+    let cpu = (0..MAX_CPUS)
+        .filter_map(|cpu| Some((SCHEDULERS[cpu].inner.lock().load()?, cpu)))
+        .min()
+        .map_or_else(cpu_id, |(_, cpu)| cpu);
+
+    let mut inner = SCHEDULERS[cpu].inner.lock();
+    inner.run_queue.push_back(Box::new(task));
+    if inner.idling() {
+        inner.need_resched = true;
+        drop(inner);
+        if cpu != cpu_id() {
+            kick(cpu);
+        }
+    }
+    id
+}
+
+/// Give up the rest of the time slice to the next ready task. Returns
+/// straight away if there is none.
+pub fn yield_now() {
+    schedule();
+}
+
+/// End the running task. Its stacks and address space are freed once
+/// another task runs.
+pub fn exit() -> ! {
+    if let Some(task) = local().inner.lock().current.as_mut() {
+        task.state = ProcessState::Zombie;
+    }
+    schedule();
+    panic!("no task left to run after exit");
+}
+
+/// Mark the running task blocked. It keeps running until the next
+/// [`schedule`], which sets it aside until [`wake`]; a wake in between
+/// cancels the block.
+pub fn block() {
+    if let Some(task) = local().inner.lock().current.as_mut() {
+        task.state = ProcessState::Blocked;
+    }
+}
+
+/// Make the blocked task `id` ready to run, on the CPU it blocked on.
+/// Does nothing if it is not blocked. Safe to call from interrupt
+/// handlers.
+pub fn wake(id: TaskId) {
+    for (cpu, scheduler) in SCHEDULERS.iter().enumerate() {
+        let mut guard = scheduler.inner.lock();
+        let inner = &mut *guard;
+        if let Some(mut task) = inner.blocked.remove(&id) {
+            task.state = ProcessState::Ready;
+            task.boost = WAKE_BOOST;
+            inner.run_queue.push_back(task);
+            if inner.idling() {
+                inner.need_resched = true;
+                drop(guard);
+                if cpu != cpu_id() {
+                    kick(cpu);
+                }
+            }
+            return;
+        }
+        if let Some(task) = inner.current.as_mut()
+            && task.id == id
+        {
+            if task.state == ProcessState::Blocked {
+                task.state = ProcessState::Running;
+            }
+            return;
+        }
+    }
+}
+
+/// Call `f` with the running task, once the scheduler is initialized.
+/// Interrupts are off meanwhile.
+pub fn with_current<R>(f: impl FnOnce(&mut Task) -> R) -> Option<R> {
+    local().inner.lock().current.as_deref_mut().map(f)
+}
+
+/// ID of the running task, once the scheduler is initialized
+pub fn current() -> Option<TaskId> {
+    local().inner.lock().current.as_ref().map(|task| task.id)
+}
+
+/// CPU use of one task
+#[derive(Debug, Clone)]
+pub struct TaskStats {
+    pub id: TaskId,
+    pub name: String,
+    pub state: ProcessState,
+    pub nice: i32,
+    /// CPU the task runs on
+    pub cpu: usize,
+    /// Time spent running, in microseconds
+    pub cpu_us: u64,
+}
+
+/// CPU use of every task, CPU by CPU: the running one first, then the
+/// ready, blocked and idle ones. Zero throughout without a counting
+/// system timer.
+pub fn task_stats() -> Vec<TaskStats> {
+    let now = uptime_us();
+    let mut tasks = Vec::new();
+    for (cpu, scheduler) in SCHEDULERS.iter().enumerate() {
+        let inner = scheduler.inner.lock();
+        let running = now.map_or(0, |now| now.saturating_sub(inner.switched_at));
+        let stats = |task: &Task, extra: u64| TaskStats {
+            id: task.id,
+            name: task.name.clone(),
+            state: task.state,
+            nice: task.nice,
+            cpu,
+            cpu_us: task.cpu_us + extra,
+        };
+
+        tasks.extend(inner.current.as_deref().map(|task| stats(task, running)));
+        tasks.extend(inner.run_queue.iter().map(|task| stats(task, 0)));
+        tasks.extend(inner.blocked.values().map(|task| stats(task, 0)));
+        tasks.extend(inner.idle.as_deref().map(|task| stats(task, 0)));
+    }
+    tasks
+}
+
+/// Call `f` with the ID, name and state of every task on `cpu`, in the
+/// order of [`task_stats`]. Allocates nothing, and does not wait for the
+/// CPU's scheduler: returns `false` if it is locked, or there is no such
+/// CPU.
+pub fn try_for_each_task(cpu: usize, mut f: impl FnMut(TaskId, &str, ProcessState)) -> bool {
+    let Some(inner) = SCHEDULERS
+        .get(cpu)
+        .and_then(|scheduler| scheduler.inner.try_lock())
+    else {
+        return false;
+    };
+    let tasks = inner
+        .current
+        .iter()
+        .chain(&inner.run_queue)
+        .chain(inner.blocked.values())
+        .chain(&inner.idle);
+    for task in tasks {
+        f(task.id, &task.name, task.state);
+    }
+    true
+}
+
+/// Whether the scheduler of `cpu` is locked
+pub fn is_locked(cpu: usize) -> bool {
+    SCHEDULERS
+        .get(cpu)
+        .is_some_and(|scheduler| scheduler.inner.is_locked())
+}
+
+/// Microseconds of CPU time spent busy and idle since [`init`], exited
+/// tasks included, summed over the CPUs
+pub fn cpu_stats() -> (u64, u64) {
+    let now = uptime_us();
+    let (mut busy, mut idle) = (0, 0);
+    for scheduler in &SCHEDULERS {
+        let inner = scheduler.inner.lock();
+        if inner.current.is_none() {
+            continue;
+        }
+        let running = now.map_or(0, |now| now.saturating_sub(inner.switched_at));
+        busy += inner.busy_us;
+        idle += inner.idle_us;
+        if inner.idling() {
+            idle += running;
+        } else {
+            busy += running;
+        }
+    }
+    (busy, idle)
+}
+
+/// Nice level of the task `id`, if it exists
+pub fn nice(id: TaskId) -> Option<i32> {
+    SCHEDULERS
+        .iter()
+        .find_map(|scheduler| scheduler.inner.lock().task_mut(id).map(|task| task.nice))
+}
+
+/// Set the nice level of the task `id`, limited to [`NICE_MIN`] to
+/// [`NICE_MAX`]. Returns false if there is no such task.
+pub fn set_nice(id: TaskId, nice: i32) -> bool {
+    SCHEDULERS.iter().any(|scheduler| {
+        let mut inner = scheduler.inner.lock();
+        let Some(task) = inner.task_mut(id) else {
+            return false;
+        };
+        task.nice = nice.clamp(NICE_MIN, NICE_MAX);
+        true
+    })
+}
+
+/// Wake the sleepers due, age the ready tasks and charge the running
+/// tasks one timer tick, on every CPU. Called from the timer interrupt;
+/// the switch itself waits for [`preempt`], which the other CPUs are
+/// interrupted to call.
+pub fn tick() {
+    timer::advance();
+
+    for (cpu, scheduler) in SCHEDULERS.iter().enumerate() {
+        let mut guard = scheduler.inner.lock();
+        let inner = &mut *guard;
+        for task in &mut inner.run_queue {
+            task.waited = task.waited.saturating_add(1);
+        }
+        let best = inner.run_queue.iter().map(|task| priority(task)).min();
+        let Some(task) = inner.current.as_mut() else {
+            continue;
+        };
+        task.time_slice = task.time_slice.saturating_sub(1);
+        task.boost = (task.boost - 1).max(0);
+        let idling = Some(task.id) == inner.idle_id;
+        if best.is_some_and(|best| idling || task.time_slice == 0 || best < priority(task)) {
+            inner.need_resched = true;
+            drop(guard);
+            if cpu != cpu_id() {
+                kick(cpu);
+            }
+        }
+    }
+}
+
+/// Switch tasks if the tick asked for it. Called by the architecture's
+/// interrupt entry on the way out, once no handler is still running, and
+/// by [`preempt_enable`]. Does nothing while preemption is disabled; the
+/// request stands until it is enabled again.
+pub fn preempt() {
+    if !preemptible() {
+        return;
+    }
+    let resched = core::mem::take(&mut local().inner.lock().need_resched);
+    if resched {
+        schedule();
+    }
+}
+
+/// Keep the running task on this CPU until the matching
+/// [`preempt_enable`]. Sections nest.
+pub fn preempt_disable() {
+    PREEMPT_COUNT[cpu_id()].fetch_add(1, Ordering::Relaxed);
+}
+
+/// End a [`preempt_disable`] section, switching tasks if the outermost
+/// one ends with a switch asked for
+pub fn preempt_enable() {
+    preempt_enable_no_resched();
+    preempt();
+}
+
+/// End a [`preempt_disable`] section without switching tasks, for the
+/// interrupt entry, which switches at a point of its own choosing
+pub fn preempt_enable_no_resched() {
+    let count = PREEMPT_COUNT[cpu_id()].fetch_sub(1, Ordering::Relaxed);
+    debug_assert!(count != 0, "preempt_enable without preempt_disable");
+}
+
+/// Whether the running task may be switched away from
+pub fn preemptible() -> bool {
+    PREEMPT_COUNT[cpu_id()].load(Ordering::Relaxed) == 0
 }
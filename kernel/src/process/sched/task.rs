@@ -0,0 +1,170 @@
+//! Schedulable tasks
+
+use crate::arch::{TaskContext, TrapFrame};
+use crate::mm::address_space::AddressSpace;
+use crate::process::fork::ForkError;
+use crate::process::pcb::ProcessState;
+use crate::process::signal::SharedSignals;
+use crate::process::stack::{KernelStack, StackError, UserStack};
+use alloc::string::String;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Task identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskId(pub usize);
+
+impl TaskId {
+    fn next() -> Self {
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A thread of execution known to the scheduler
+pub struct Task {
+    pub id: TaskId,
+
+    pub name: String,
+
+    /// `Running` while current, `Zombie` once it has exited
+    pub state: ProcessState,
+
+    /// Registers saved while the task is switched out
+    pub(crate) context: TaskContext,
+
+    /// Called when the task first runs; `None` for the boot task and
+    /// forked tasks
+    pub(super) entry: Option<fn()>,
+
+    /// Stack the task runs on; `None` for the boot task, which keeps the
+    /// stack it booted on
+    pub kernel_stack: Option<KernelStack>,
+
+    /// Stack for when the task enters user mode
+    pub user_stack: Option<UserStack>,
+
+    /// Activated whenever the task is switched to. Kernel tasks have none
+    /// and run in whichever address space was active.
+    pub address_space: Option<AddressSpace>,
+
+    /// Timer ticks left before the task is preempted
+    pub time_slice: u32,
+
+    /// Static priority, from `NICE_MIN` (most favoured) to `NICE_MAX`
+    pub nice: i32,
+
+    /// Priority levels lent for waking from a sleep, worn off as it runs
+    pub(super) boost: i32,
+
+    /// Ticks spent ready since the task last ran
+    pub(super) waited: u32,
+
+    /// Time spent running, in microseconds
+    pub cpu_us: u64,
+
+    /// Signal state of the process the task runs, also in its process
+    /// table entry; `None` for kernel tasks
+    pub signals: Option<SharedSignals>,
+}
+
+impl Task {
+    /// A kernel task that calls `entry` on a fresh kernel stack
+    pub fn new(name: &str, entry: fn()) -> Result<Self, StackError> {
+        let kernel_stack = KernelStack::new()?;
+        let context = TaskContext::new(super::scheduler::task_start, kernel_stack.initial_sp());
+
+        Ok(Self {
+            id: TaskId::next(),
+            name: name.into(),
+            state: ProcessState::Ready,
+            context,
+            entry: Some(entry),
+            kernel_stack: Some(kernel_stack),
+            user_stack: None,
+            address_space: None,
+            time_slice: 0,
+            nice: 0,
+            boost: 0,
+            waited: 0,
+            cpu_us: 0,
+            signals: None,
+        })
+    }
+
+    /// A task that runs in `address_space`, with a user stack for when
+    /// it enters user mode
+    pub fn with_address_space(
+        name: &str,
+        entry: fn(),
+        address_space: AddressSpace,
+    ) -> Result<Self, StackError> {
+        Ok(Self {
+            user_stack: Some(UserStack::new()?),
+            address_space: Some(address_space),
+            ..Self::new(name, entry)?
+        })
+    }
+
+    /// Copy of this task for a forked child, which resumes from `frame`
+    /// with a result of 0. The address space is shared copy-on-write, the
+    /// user stack is copied and the nice level and signal actions
+    /// inherited.
+    pub fn fork(&self, frame: &TrapFrame) -> Result<Self, ForkError> {
+        let kernel_stack = KernelStack::new()?;
+        let user_stack = self
+            .user_stack
+            .as_ref()
+            .map(UserStack::try_clone)
+            .transpose()?;
+        let address_space = self
+            .address_space
+            .as_ref()
+            .map(AddressSpace::fork)
+            .transpose()?;
+        // SAFETY: the stack was just allocated for the child
+        let mut context = unsafe { TaskContext::for_trap_return(frame, kernel_stack.top()) };
+        context.inherit_fpu(&self.context, self.id.0);
+
+        Ok(Self {
+            id: TaskId::next(),
+            name: self.name.clone(),
+            state: ProcessState::Ready,
+            context,
+            entry: None,
+            kernel_stack: Some(kernel_stack),
+            user_stack,
+            address_space,
+            time_slice: 0,
+            nice: self.nice,
+            boost: 0,
+            waited: 0,
+            cpu_us: 0,
+            signals: self
+                .signals
+                .as_ref()
+                .map(|signals| signals.lock().fork().shared()),
+        })
+    }
+
+    /// The thread that is already running, adopted as a task. Its context
+    /// is filled in the first time it is switched out.
+    pub(super) fn boot() -> Self {
+        Self {
+            id: TaskId::next(),
+            name: "boot".into(),
+            state: ProcessState::Running,
+            context: TaskContext::default(),
+            entry: None,
+            kernel_stack: None,
+            user_stack: None,
+            address_space: None,
+            time_slice: 0,
+            nice: 0,
+            boost: 0,
+            waited: 0,
+            cpu_us: 0,
+            signals: None,
+        }
+    }
+}
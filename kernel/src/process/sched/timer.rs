@@ -0,0 +1,121 @@
+//! Sleeping and per-task software timers
+//!
+//! A sleeping task blocks with a timer filed in a wheel of
+//! [`WHEEL_SLOTS`] slots, one per tick, by the tick it is due. Every
+//! timer tick advances the wheel a slot and wakes the tasks due there;
+//! timers more than a turn of the wheel away stay put until their turn
+//! comes round.
+//!
+//! Without the tick, which is only started on ARM, sleeps busy-wait on
+//! the system timer instead.
+
+use super::scheduler::{TICK_US, block, current, schedule, wake};
+use super::task::TaskId;
+use crate::arch::{Irq, IrqSpinLock};
+use crate::subsystems::uptime_us;
+use alloc::vec::Vec;
+use common::sync::irq::IrqControl;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Slots in the timer wheel
+pub const WHEEL_SLOTS: usize = 64;
+
+static WHEEL: IrqSpinLock<Wheel> = IrqSpinLock::new(Wheel {
+    now: 0,
+    slots: [const { Vec::new() }; WHEEL_SLOTS],
+});
+
+/// Set once the tick is running
+static TICKING: AtomicBool = AtomicBool::new(false);
+
+struct Wheel {
+    /// Ticks since the tick started
+    now: u64,
+    slots: [Vec<Timer>; WHEEL_SLOTS],
+}
+
+/// A sleeping task and the tick it wakes at
+#[derive(Debug, Clone, Copy)]
+struct Timer {
+    task: TaskId,
+    expires: u64,
+}
+
+/// Note that the tick is running, so sleeps can block
+pub(super) fn start() {
+    TICKING.store(true, Ordering::Release);
+}
+
+/// Advance the wheel by one tick and wake the tasks due. Called from the
+/// timer interrupt.
+pub(super) fn advance() {
+    let mut wheel = WHEEL.lock();
+    wheel.now += 1;
+    let now = wheel.now;
+    wheel.slots[now as usize % WHEEL_SLOTS].retain(|timer| {
+        if timer.expires > now {
+            return true;
+        }
+        wake(timer.task);
+        false
+    });
+}
+
+/// Ticks since the tick started
+pub fn ticks() -> u64 {
+    WHEEL.lock().now
+}
+
+/// Whether the tick is running, so that timers fire
+pub fn ticking() -> bool {
+    TICKING.load(Ordering::Acquire)
+}
+
+/// Wake `task` once the tick count reaches `expires`, unless it has
+/// already passed
+pub(super) fn add_timer(task: TaskId, expires: u64) {
+    let mut wheel = WHEEL.lock();
+    if expires > wheel.now {
+        wheel.slots[expires as usize % WHEEL_SLOTS].push(Timer { task, expires });
+    }
+}
+
+/// Block the running task for at least `us` microseconds
+pub fn sleep_us(us: u64) {
+    let Some(task) = current().filter(|_| ticking()) else {
+        spin_us(us);
+        return;
+    };
+
+    // The tick in progress is already partly over
+    let expires = ticks() + us.div_ceil(TICK_US as u64) + 1;
+    loop {
+        // The tick cannot fire between the check and blocking
+        let irq = Irq::save_and_disable();
+        let due = ticks() >= expires;
+        if !due {
+            add_timer(task, expires);
+            block();
+        }
+        Irq::restore(irq);
+        if due {
+            return;
+        }
+        schedule();
+    }
+}
+
+/// Block the running task for at least `ms` milliseconds
+pub fn sleep_ms(ms: u32) {
+    sleep_us(ms as u64 * 1000);
+}
+
+/// Busy-wait for `us` microseconds on the system timer, if there is one
+fn spin_us(us: u64) {
+    let Some(start) = uptime_us() else {
+        return;
+    };
+    while uptime_us().is_some_and(|now| now - start < us) {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,132 @@
+//! Wait queues
+//!
+//! A task sleeps on a [`WaitQueue`] until a condition holds, and whoever
+//! makes the condition true wakes the queue, from task or interrupt
+//! context alike. The sleeper queues itself before each check, so a wake
+//! that lands between the check and the sleep is not lost.
+//!
+//! Wait queues are also the kernel's [`WaitEvent`], which drivers sleep
+//! on until their interrupt reports progress.
+
+use super::scheduler::{self, block, current, wake};
+use super::task::TaskId;
+use super::timer;
+use crate::arch::{Irq, IrqSpinLock};
+use alloc::collections::VecDeque;
+use common::sync::event::WaitEvent;
+use common::sync::irq::IrqControl;
+
+pub struct WaitQueue {
+    waiters: IrqSpinLock<VecDeque<TaskId>>,
+}
+
+impl WaitQueue {
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub const fn new() -> Self {
+        Self {
+            waiters: IrqSpinLock::new(VecDeque::new()),
+        }
+    }
+
+    /// Block the running task until `condition` returns true, checking
+    /// it again after every wake. Spins instead before the scheduler is
+    /// initialized.
+    pub fn sleep_on(&self, mut condition: impl FnMut() -> bool) {
+        let Some(task) = current() else {
+            while !condition() {
+                core::hint::spin_loop();
+            }
+            return;
+        };
+
+        loop {
+            self.enqueue(task);
+            block();
+            if condition() {
+                self.cancel(task);
+                return;
+            }
+            scheduler::schedule();
+        }
+    }
+
+    /// Like [`Self::sleep_on`], but give up once `ticks` timer ticks have
+    /// passed. Returns whether the condition came true.
+    ///
+    /// Without the timer tick, the condition is checked once more after
+    /// yielding to other tasks.
+    pub fn sleep_on_timeout(&self, mut condition: impl FnMut() -> bool, ticks: u64) -> bool {
+        let Some(task) = current().filter(|_| timer::ticking()) else {
+            if condition() {
+                return true;
+            }
+            scheduler::yield_now();
+            return condition();
+        };
+
+        let expires = timer::ticks() + ticks;
+        loop {
+            // The tick cannot fire between filing the timer and blocking
+            let irq = Irq::save_and_disable();
+            self.enqueue(task);
+            block();
+            let done = if condition() {
+                Some(true)
+            } else if timer::ticks() >= expires {
+                Some(false)
+            } else {
+                timer::add_timer(task, expires);
+                None
+            };
+            if done.is_some() {
+                self.cancel(task);
+            }
+            Irq::restore(irq);
+
+            match done {
+                Some(result) => return result,
+                None => scheduler::schedule(),
+            }
+        }
+    }
+
+    fn enqueue(&self, task: TaskId) {
+        let mut waiters = self.waiters.lock();
+        if !waiters.contains(&task) {
+            waiters.push_back(task);
+        }
+    }
+
+    /// Undo the queueing and block of a sleeper that no longer needs to
+    /// sleep
+    fn cancel(&self, task: TaskId) {
+        wake(task);
+        self.waiters.lock().retain(|&waiter| waiter != task);
+    }
+
+    /// Wake the task that has been sleeping on the queue longest
+    pub fn wake_one(&self) {
+        let waiter = self.waiters.lock().pop_front();
+        if let Some(task) = waiter {
+            wake(task);
+        }
+    }
+
+    /// Wake every task sleeping on the queue
+    pub fn wake_all(&self) {
+        let waiters = core::mem::take(&mut *self.waiters.lock());
+        for task in waiters {
+            wake(task);
+        }
+    }
+}
+
+impl WaitEvent for WaitQueue {
+    fn wait_until(&self, condition: &mut dyn FnMut() -> bool) {
+        self.sleep_on(condition);
+    }
+
+    fn notify(&self) {
+        self.wake_all();
+    }
+}
@@ -0,0 +1,400 @@
+//! POSIX-style signals
+//!
+//! Each process has a mask of pending signals, a mask of blocked ones and
+//! an action per signal, shared with the task that runs it so the running
+//! process reaches its own without the process table. [`kill`] marks a signal pending; it is acted on
+//! when the process next returns to user mode. The default action either
+//! ends the process or ignores the signal. A caught signal runs its
+//! handler on the user stack, above a [`SignalFrame`] holding the
+//! interrupted context, and the handler's restorer calls `sigreturn` to
+//! resume from it.
+//!
+//! A process asleep in the kernel sees a signal once its sleep ends; nothing
+//! interrupts a blocking call yet. Stop signals are ignored until there
+//! is job control.
+
+use super::exit::{self, INIT_PID};
+use super::pcb::Pid;
+use super::sched;
+use super::table::process_table;
+use crate::arch::{IrqSpinLock, TrapFrame};
+use alloc::sync::Arc;
+
+/// Signals are numbered 1 to `NSIG - 1`
+pub const NSIG: usize = 32;
+
+pub const SIGHUP: u32 = 1;
+pub const SIGINT: u32 = 2;
+pub const SIGQUIT: u32 = 3;
+pub const SIGILL: u32 = 4;
+pub const SIGTRAP: u32 = 5;
+pub const SIGABRT: u32 = 6;
+pub const SIGBUS: u32 = 7;
+pub const SIGFPE: u32 = 8;
+pub const SIGKILL: u32 = 9;
+pub const SIGUSR1: u32 = 10;
+pub const SIGSEGV: u32 = 11;
+pub const SIGUSR2: u32 = 12;
+pub const SIGPIPE: u32 = 13;
+pub const SIGALRM: u32 = 14;
+pub const SIGTERM: u32 = 15;
+pub const SIGCHLD: u32 = 17;
+pub const SIGCONT: u32 = 18;
+pub const SIGSTOP: u32 = 19;
+pub const SIGTSTP: u32 = 20;
+pub const SIGTTIN: u32 = 21;
+pub const SIGTTOU: u32 = 22;
+pub const SIGURG: u32 = 23;
+pub const SIGWINCH: u32 = 28;
+
+/// Handler value for the default action
+pub const SIG_DFL: usize = 0;
+/// Handler value to ignore the signal
+pub const SIG_IGN: usize = 1;
+
+/// `sa_flags`: reset the action to the default once delivered
+pub const SA_RESETHAND: u32 = 0x8000_0000;
+/// `sa_flags`: do not block the signal while its handler runs
+pub const SA_NODEFER: u32 = 0x4000_0000;
+/// `sa_flags`: `sa_restorer` is set
+pub const SA_RESTORER: u32 = 0x0400_0000;
+
+/// `sigprocmask` how: block the given signals too
+pub const SIG_BLOCK: u32 = 0;
+/// `sigprocmask` how: unblock the given signals
+pub const SIG_UNBLOCK: u32 = 1;
+/// `sigprocmask` how: block exactly the given signals
+pub const SIG_SETMASK: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalError {
+    /// The running task is not a process
+    NoProcess,
+    /// The target process does not exist
+    NoSuchProcess,
+    /// Not a signal number, or the signal's action cannot be changed
+    InvalidSignal,
+    /// A bad argument, such as an unknown `sigprocmask` operation
+    InvalidArgument,
+}
+
+/// A set of signals, signal `n` at bit `n - 1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(transparent)]
+pub struct SigSet(pub u32);
+
+impl SigSet {
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn of(signal: u32) -> Self {
+        Self(1 << (signal - 1))
+    }
+
+    pub fn contains(self, signal: u32) -> bool {
+        self.0 & Self::of(signal).0 != 0
+    }
+
+    pub fn insert(&mut self, signal: u32) {
+        self.0 |= Self::of(signal).0;
+    }
+
+    pub fn remove(&mut self, signal: u32) {
+        self.0 &= !Self::of(signal).0;
+    }
+
+    /// The lowest-numbered signal in the set
+    pub fn first(self) -> Option<u32> {
+        (self.0 != 0).then(|| self.0.trailing_zeros() + 1)
+    }
+
+    /// The set without the signals that cannot be blocked
+    fn blockable(self) -> Self {
+        Self(self.0 & !(Self::of(SIGKILL).0 | Self::of(SIGSTOP).0))
+    }
+}
+
+/// What to do with a signal, laid out as the `sigaction` argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct SigAction {
+    /// [`SIG_DFL`], [`SIG_IGN`] or the handler's address
+    pub handler: usize,
+    /// Signals blocked while the handler runs, besides the signal itself
+    pub mask: SigSet,
+    pub flags: u32,
+    /// Where the handler returns to, to call `sigreturn`
+    pub restorer: usize,
+}
+
+impl SigAction {
+    pub const DEFAULT: Self = Self {
+        handler: SIG_DFL,
+        mask: SigSet::empty(),
+        flags: 0,
+        restorer: 0,
+    };
+}
+
+/// Signal state shared by a process's table entry and its task. The lock
+/// is IRQ-safe, as signals are taken on the way back to user mode.
+pub type SharedSignals = Arc<IrqSpinLock<SignalState>>;
+
+/// Signal state of a process
+#[derive(Debug, Clone)]
+pub struct SignalState {
+    pub pending: SigSet,
+    pub blocked: SigSet,
+    actions: [SigAction; NSIG],
+}
+
+impl SignalState {
+    pub const fn new() -> Self {
+        Self {
+            pending: SigSet::empty(),
+            blocked: SigSet::empty(),
+            actions: [SigAction::DEFAULT; NSIG],
+        }
+    }
+
+    /// The state behind a lock, to share between a process and its task
+    pub fn shared(self) -> SharedSignals {
+        Arc::new(IrqSpinLock::new(self))
+    }
+
+    pub fn action(&self, signal: u32) -> SigAction {
+        self.actions[signal as usize]
+    }
+
+    /// The state a forked child starts with: the same actions and mask,
+    /// nothing pending
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: SigSet::empty(),
+            ..self.clone()
+        }
+    }
+
+    /// Reset caught signals to their default action, as the handlers
+    /// are gone with the old program. Ignored signals stay ignored.
+    pub fn exec(&mut self) {
+        for action in &mut self.actions {
+            if action.handler != SIG_IGN {
+                *action = SigAction::DEFAULT;
+            }
+        }
+    }
+
+    /// Whether `signal` would be dropped on delivery
+    fn ignores(&self, signal: u32) -> bool {
+        match self.action(signal).handler {
+            SIG_IGN => true,
+            SIG_DFL => !terminates(signal),
+            _ => false,
+        }
+    }
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether the default action of `signal` ends the process
+fn terminates(signal: u32) -> bool {
+    !matches!(
+        signal,
+        SIGCHLD | SIGCONT | SIGURG | SIGWINCH | SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU
+    )
+}
+
+fn valid(signal: u32) -> bool {
+    (1..NSIG as u32).contains(&signal)
+}
+
+/// Send `signal` to the process `pid`. Signal 0 only checks that the
+/// process exists.
+///
+/// Init only receives signals it has a handler for.
+pub fn kill(pid: Pid, signal: u32) -> Result<(), SignalError> {
+    if signal != 0 && !valid(signal) {
+        return Err(SignalError::InvalidSignal);
+    }
+
+    let signals = process_table()
+        .lock()
+        .get(pid)
+        .map(|process| process.signals.clone())
+        .ok_or(SignalError::NoSuchProcess)?;
+    let mut signals = signals.lock();
+    if signal == 0 || signals.ignores(signal) {
+        return Ok(());
+    }
+    if pid == INIT_PID && signals.action(signal).handler == SIG_DFL {
+        return Ok(());
+    }
+    signals.pending.insert(signal);
+    Ok(())
+}
+
+/// Send `signal` to the running process for a fault it caused, such as
+/// `SIGSEGV` for a bad access. Retrying the faulting instruction would
+/// only fault again, so the signal cannot be ignored or blocked: it is
+/// unblocked, and an ignored signal gets its default action back.
+pub fn force(signal: u32) -> Result<(), SignalError> {
+    if !valid(signal) {
+        return Err(SignalError::InvalidSignal);
+    }
+
+    with_signals(|signals| {
+        if signals.action(signal).handler == SIG_IGN {
+            signals.actions[signal as usize] = SigAction::DEFAULT;
+        }
+        signals.blocked.remove(signal);
+        signals.pending.insert(signal);
+    })
+}
+
+/// Set the action for `signal` in the running process to `action`, if
+/// given, and return the previous one
+pub fn sigaction(signal: u32, action: Option<SigAction>) -> Result<SigAction, SignalError> {
+    if !valid(signal) {
+        return Err(SignalError::InvalidSignal);
+    }
+    if action.is_some() && matches!(signal, SIGKILL | SIGSTOP) {
+        return Err(SignalError::InvalidSignal);
+    }
+
+    with_signals(|signals| {
+        let old = signals.action(signal);
+        if let Some(action) = action {
+            signals.actions[signal as usize] = action;
+            // Ignoring a signal discards it if already pending
+            if signals.ignores(signal) {
+                signals.pending.remove(signal);
+            }
+        }
+        old
+    })
+}
+
+/// Change the running process's blocked signals by `set` as `how` says,
+/// if given, and return the previous mask
+pub fn sigprocmask(how: u32, set: Option<SigSet>) -> Result<SigSet, SignalError> {
+    if set.is_some() && !matches!(how, SIG_BLOCK | SIG_UNBLOCK | SIG_SETMASK) {
+        return Err(SignalError::InvalidArgument);
+    }
+
+    with_signals(|signals| {
+        let old = signals.blocked;
+        if let Some(set) = set {
+            let blocked = match how {
+                SIG_BLOCK => old.0 | set.0,
+                SIG_UNBLOCK => old.0 & !set.0,
+                _ => set.0,
+            };
+            signals.blocked = SigSet(blocked).blockable();
+        }
+        old
+    })
+}
+
+fn with_signals<R>(f: impl FnOnce(&mut SignalState) -> R) -> Result<R, SignalError> {
+    let signals = current_signals().ok_or(SignalError::NoProcess)?;
+    Ok(f(&mut signals.lock()))
+}
+
+/// The running process's signal state, from its task
+fn current_signals() -> Option<SharedSignals> {
+    sched::with_current(|task| task.signals.clone()).flatten()
+}
+
+/// Saved on the user stack while a handler runs. The handler starts with
+/// the stack pointer at the frame, which is how `sigreturn` finds it.
+#[repr(C)]
+pub struct SignalFrame {
+    /// The restorer, where the handler returns to
+    pub return_address: usize,
+    /// The signal number, the handler's argument
+    pub signal: usize,
+    /// The mask to restore
+    pub blocked: SigSet,
+    _reserved: u32,
+    /// The interrupted user context
+    pub context: TrapFrame,
+}
+
+/// A caught signal about to be handled
+#[derive(Debug, Clone, Copy)]
+pub struct Delivery {
+    pub signal: u32,
+    pub action: SigAction,
+    /// The mask to restore after the handler
+    pub blocked: SigSet,
+}
+
+/// Take the next signal to deliver to the running process, which is
+/// returning to user mode. Ignored signals are dropped and a signal that
+/// ends the process does so here. A caught signal is blocked, along with
+/// its action's mask, and returned for the caller to set up the handler.
+pub fn next_signal() -> Option<Delivery> {
+    let shared = current_signals()?;
+    let mut signals = shared.lock();
+
+    loop {
+        let signal = SigSet(signals.pending.0 & !signals.blocked.0).first()?;
+        signals.pending.remove(signal);
+        let action = signals.action(signal);
+
+        match action.handler {
+            SIG_IGN => continue,
+            SIG_DFL if !terminates(signal) => continue,
+            SIG_DFL => {
+                drop(signals);
+                exit::kill_current(signal);
+            }
+            _ => {}
+        }
+
+        let blocked = signals.blocked;
+        signals.blocked.0 |= action.mask.0;
+        if action.flags & SA_NODEFER == 0 {
+            signals.blocked.insert(signal);
+        }
+        signals.blocked = signals.blocked.blockable();
+        if action.flags & SA_RESETHAND != 0 {
+            signals.actions[signal as usize] = SigAction::DEFAULT;
+        }
+        return Some(Delivery {
+            signal,
+            action,
+            blocked,
+        });
+    }
+}
+
+/// Return from a handler: resume the context saved in the signal frame at
+/// `frame_addr` by loading it into `frame`, and restore the mask.
+pub fn sigreturn(frame: &mut TrapFrame, frame_addr: usize) -> Result<(), SignalError> {
+    let signal_frame = frame_addr as *const SignalFrame;
+    if signal_frame.is_null() || !signal_frame.is_aligned() {
+        return Err(SignalError::InvalidArgument);
+    }
+
+    // SAFETY: the handler returned with its stack pointer back at the
+    // frame, which `next_signal`'s caller wrote; a corrupted one can
+    // only resume the process somewhere else in user mode
+    let (context, blocked) = unsafe {
+        (
+            core::ptr::read(&raw const (*signal_frame).context),
+            (*signal_frame).blocked,
+        )
+    };
+    with_signals(|signals| signals.blocked = blocked.blockable())?;
+
+    *frame = context;
+    frame.force_user();
+    Ok(())
+}
@@ -85,6 +85,20 @@ impl UserStack {
     pub fn size(&self) -> usize {
         PAGE_SIZE << USER_STACK_ORDER
     }
+
+    /// Allocate a new user stack holding a copy of this one
+    pub fn try_clone(&self) -> Result<Self, StackError> {
+        let copy = Self::new()?;
+        // SAFETY: both blocks are owned, distinct and `size()` bytes long
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                self.bottom() as *const u8,
+                copy.bottom() as *mut u8,
+                self.size(),
+            );
+        }
+        Ok(copy)
+    }
 }
 
 /// Stack allocation error
@@ -0,0 +1,142 @@
+//! Process table
+//!
+//! Every process is entered under its PID, with links to its parent and
+//! children. PIDs count up from 1 and wrap at [`PID_MAX`], skipping ones
+//! still in use.
+
+use super::pcb::{Pid, Process, ProcessState};
+use super::sched::TaskId;
+use super::signal::SharedSignals;
+use crate::fs::fd::FileDescriptorTable;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// PIDs are below this
+pub const PID_MAX: usize = 32768;
+
+static PROCESS_TABLE: Mutex<ProcessTable> = Mutex::new(ProcessTable::new());
+
+pub fn process_table() -> &'static Mutex<ProcessTable> {
+    &PROCESS_TABLE
+}
+
+pub struct ProcessTable {
+    processes: BTreeMap<Pid, Process>,
+    /// Where the search for a free PID starts
+    next_pid: usize,
+}
+
+impl ProcessTable {
+    const fn new() -> Self {
+        Self {
+            processes: BTreeMap::new(),
+            next_pid: 1,
+        }
+    }
+
+    fn alloc_pid(&mut self) -> Option<Pid> {
+        for _ in 1..PID_MAX {
+            let pid = Pid(self.next_pid);
+            self.next_pid = if self.next_pid + 1 < PID_MAX {
+                self.next_pid + 1
+            } else {
+                1
+            };
+            if !self.processes.contains_key(&pid) {
+                return Some(pid);
+            }
+        }
+        None
+    }
+
+    /// Enter a new process run by `task` as a child of `parent`, with the
+    /// task's signal state. Returns `None` if every PID is taken.
+    pub fn insert(
+        &mut self,
+        name: &str,
+        task: TaskId,
+        parent: Option<Pid>,
+        fd_table: FileDescriptorTable,
+        signals: SharedSignals,
+    ) -> Option<Pid> {
+        let pid = self.alloc_pid()?;
+        if let Some(parent) = parent.and_then(|parent| self.processes.get_mut(&parent)) {
+            parent.children.push(pid);
+        }
+
+        self.processes.insert(
+            pid,
+            Process {
+                pid,
+                parent_pid: parent,
+                children: Vec::new(),
+                state: ProcessState::Ready,
+                task,
+                name: name.into(),
+                fd_table,
+                exit_code: None,
+                signals,
+            },
+        );
+        Some(pid)
+    }
+
+    /// Take `pid` out of the table and its parent's children. Its own
+    /// children are left pointing at it.
+    pub fn remove(&mut self, pid: Pid) -> Option<Process> {
+        let process = self.processes.remove(&pid)?;
+        if let Some(parent) = process
+            .parent_pid
+            .and_then(|parent| self.processes.get_mut(&parent))
+        {
+            parent.children.retain(|&child| child != pid);
+        }
+        Some(process)
+    }
+
+    /// Hand every child of `pid` to `parent`
+    pub fn reparent_children(&mut self, pid: Pid, parent: Pid) {
+        let Some(process) = self.processes.get_mut(&pid) else {
+            return;
+        };
+        let children = core::mem::take(&mut process.children);
+
+        for &child in &children {
+            if let Some(child) = self.processes.get_mut(&child) {
+                child.parent_pid = Some(parent);
+            }
+        }
+        if let Some(parent) = self.processes.get_mut(&parent) {
+            parent.children.extend(children);
+        }
+    }
+
+    pub fn get(&self, pid: Pid) -> Option<&Process> {
+        self.processes.get(&pid)
+    }
+
+    pub fn get_mut(&mut self, pid: Pid) -> Option<&mut Process> {
+        self.processes.get_mut(&pid)
+    }
+
+    /// The process run by `task`
+    pub fn by_task(&self, task: TaskId) -> Option<&Process> {
+        self.processes.values().find(|process| process.task == task)
+    }
+
+    pub fn by_task_mut(&mut self, task: TaskId) -> Option<&mut Process> {
+        self.processes
+            .values_mut()
+            .find(|process| process.task == task)
+    }
+
+    /// Every process, in PID order
+    pub fn iter(&self) -> impl Iterator<Item = &Process> {
+        self.processes.values()
+    }
+
+    pub fn count(&self) -> usize {
+        self.processes.len()
+    }
+}
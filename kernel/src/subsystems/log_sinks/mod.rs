@@ -1,25 +1,144 @@
+use crate::arch::IrqSpinLock;
+use crate::boot::cmdline::Console;
 use crate::logger::{self, LogSink};
+use crate::subsystems::IrqDevice;
 use alloc::sync::Arc;
 use alloc::vec;
+use alloc::vec::Vec;
+use common::sync::Once;
+use drivers::device_manager::DeviceClass;
+use drivers::hal::fb::FrameBuffer;
+use drivers::peripheral::fb_console::FbConsole;
 use spin::Mutex;
 
 /// Wraps the runtime serial console as a LogSink.
 /// Held as a &'static so it can be registered with the logger.
 pub struct SerialLogSink;
 
-// SAFETY: SerialLogSink has no fields; all state is behind a global Mutex.
+// SAFETY: SerialLogSink has no fields; all state is behind the port's lock.
 unsafe impl Sync for SerialLogSink {}
 unsafe impl Send for SerialLogSink {}
 
 impl LogSink for SerialLogSink {
     fn write_str(&self, s: &str) {
-        // serial_console() returns Option<Arc<Mutex<dyn DynSerialPort>>>
+        // Locked with IRQs off: handlers log too
         if let Some(serial) = crate::subsystems::serial_console() {
             let mut port = serial.lock();
             // DynSerialPort exposes write_str; convert str to bytes
             let _ = port.write(s.as_bytes());
         }
     }
+
+    fn write_bytes(&self, bytes: &[u8]) {
+        if let Some(serial) = crate::subsystems::serial_console() {
+            let _ = serial.lock().write(bytes);
+        }
+    }
 }
 
 pub static SERIAL_SINK: SerialLogSink = SerialLogSink;
+
+/// Writes log records as text on the first framebuffer, once attached.
+///
+/// The framebuffer is only tried, not waited for: a record logged while
+/// something else draws on it, such as a user program through `/dev/fb0`,
+/// is left off the screen, but still reaches the other sinks.
+pub struct FramebufferLogSink {
+    console: IrqSpinLock<Option<FramebufferConsole>>,
+}
+
+struct FramebufferConsole {
+    fb: IrqDevice<dyn FrameBuffer>,
+    text: FbConsole,
+}
+
+impl FramebufferLogSink {
+    const fn new() -> Self {
+        Self {
+            console: IrqSpinLock::new(None),
+        }
+    }
+
+    /// Clear the first framebuffer the device manager has and write to
+    /// it from now on. False if there is none.
+    pub fn attach(&self) -> bool {
+        let fb = {
+            let device_mgr = crate::subsystems::device_manager().lock();
+            device_mgr
+                .by_class(DeviceClass::FrameBuffer)
+                .next()
+                .and_then(|(name, _)| device_mgr.framebuffer(name))
+        };
+        let Some(fb) = fb else {
+            return false;
+        };
+        let fb = IrqDevice::new(fb);
+        let text = {
+            let mut fb = fb.lock();
+            let mut text = FbConsole::new(&*fb);
+            text.clear(&mut *fb);
+            text
+        };
+        *self.console.lock() = Some(FramebufferConsole { fb, text });
+        true
+    }
+
+    /// Whether log records go to a framebuffer
+    pub fn is_attached(&self) -> bool {
+        self.console.lock().is_some()
+    }
+}
+
+impl LogSink for FramebufferLogSink {
+    fn write_str(&self, s: &str) {
+        let mut console = self.console.lock();
+        let Some(console) = console.as_mut() else {
+            return;
+        };
+        if let Some(mut fb) = console.fb.try_lock() {
+            console.text.write_str(&mut *fb, s);
+        }
+    }
+}
+
+pub static FRAMEBUFFER_SINK: FramebufferLogSink = FramebufferLogSink::new();
+
+/// The sinks `console_sinks` picked
+static CONSOLES: Once<Vec<&'static dyn LogSink>> = Once::new();
+
+/// The sinks for the consoles the command line's `console=` options
+/// enable, attached. Falls back to the serial console if none can be, so
+/// that a `console=tty0` with no framebuffer does not silence the kernel.
+/// Decided once; later calls return the same sinks.
+pub fn console_sinks() -> Vec<&'static dyn LogSink> {
+    CONSOLES.call_once(pick_consoles).clone()
+}
+
+/// Write to every console kernel output goes to, or to the serial
+/// console until they are picked. The text goes straight out, not
+/// through the kernel message log.
+pub fn console_write(bytes: &[u8]) {
+    match CONSOLES.get() {
+        Some(consoles) => {
+            for console in consoles {
+                console.write_bytes(bytes);
+            }
+        }
+        None => SERIAL_SINK.write_bytes(bytes),
+    }
+}
+
+fn pick_consoles() -> Vec<&'static dyn LogSink> {
+    let cmdline = crate::boot::cmdline();
+    let mut sinks: Vec<&'static dyn LogSink> = Vec::new();
+    if cmdline.console_enabled(Console::Serial) {
+        sinks.push(&SERIAL_SINK);
+    }
+    if cmdline.console_enabled(Console::Framebuffer) && FRAMEBUFFER_SINK.attach() {
+        sinks.push(&FRAMEBUFFER_SINK);
+    }
+    if sinks.is_empty() {
+        sinks.push(&SERIAL_SINK);
+    }
+    sinks
+}
@@ -1,76 +1,140 @@
 pub mod boot_sinks;
 pub mod log_sinks;
 
+use crate::arch::{Irq, IrqSpinLock};
 use crate::subsystems::boot_sinks::BootSink;
 use alloc::format;
 use alloc::sync::Arc;
 use alloc::{boxed::Box, string::String};
-use core::cell::OnceCell;
+use common::sync::Once;
+use common::sync::irq_mutex::IrqMutexGuard;
+use drivers::device_manager::DeviceManager;
 use drivers::peripheral::x86::mb2fb::Mb2Fb;
 use drivers::{
-    device_manager::Device,
     hal::{
-        console::DynConsoleOutput, interrupt::DynInterruptController, serial::DynSerialPort,
-        timer::DynTimer,
+        console::DynConsoleOutput, interrupt::DynInterruptController, rtc::DynRtc,
+        serial::DynSerialPort, timer::DynTimer,
     },
     peripheral::x86::mb2fb::MB2_FB_TAG,
 };
 use spin::Mutex;
 
-struct DeviceManagerCell {
-    inner: OnceCell<Mutex<drivers::device_manager::DeviceManager>>,
-}
-unsafe impl Sync for DeviceManagerCell {}
-unsafe impl Send for DeviceManagerCell {}
-
-static DEVICE_MANAGER: DeviceManagerCell = DeviceManagerCell {
-    inner: OnceCell::new(),
-};
+/// Taken with IRQs off, as interrupt handlers look devices up in it
+static DEVICE_MANAGER: Once<IrqSpinLock<DeviceManager>> = Once::new();
 
 pub unsafe fn init_devices() {
     DEVICE_MANAGER
-        .inner
-        .set(Mutex::new(drivers::device_manager::DeviceManager::new()))
+        .set(IrqSpinLock::new(DeviceManager::new()))
         .ok()
         .expect("DeviceManager already initialized");
 
     unsafe {
-        drivers::platform::Platform::init_devices(&mut *DEVICE_MANAGER.inner.get().unwrap().lock())
+        drivers::platform::Platform::init_devices(&mut device_manager().lock())
             .expect("Failed to initialize platform devices");
     }
 }
 
-pub fn device_manager() -> &'static Mutex<drivers::device_manager::DeviceManager> {
-    DEVICE_MANAGER
-        .inner
-        .get()
-        .expect("DeviceManager not initialized")
+pub fn device_manager() -> &'static IrqSpinLock<DeviceManager> {
+    DEVICE_MANAGER.get().expect("DeviceManager not initialized")
 }
 
-pub fn serial_console() -> Option<Arc<Mutex<dyn DynSerialPort>>> {
-    device_manager().lock().serial_console()
+/// The device manager, if it has been initialized
+pub fn try_device_manager() -> Option<&'static IrqSpinLock<DeviceManager>> {
+    DEVICE_MANAGER.get()
 }
 
-pub fn system_timer() -> Option<Arc<Mutex<dyn DynTimer>>> {
-    device_manager().lock().system_timer()
+/// A device interrupt handlers use as well: a serial port, which they
+/// log to, the system timer or the interrupt controller.
+///
+/// The device manager hands out plain spinlocks, so one taken with IRQs
+/// on could be held by the very code a handler interrupted, and the
+/// handler would spin on it forever. This locks it with IRQs off, like
+/// an [`IrqSpinLock`]; every holder must go through it.
+pub struct IrqDevice<T: ?Sized>(Arc<Mutex<T>>);
+
+impl<T: ?Sized> IrqDevice<T> {
+    pub fn new(device: Arc<Mutex<T>>) -> Self {
+        Self(device)
+    }
+
+    /// Lock the device, with IRQs off until the guard is dropped
+    pub fn lock(&self) -> IrqMutexGuard<'_, T, Irq> {
+        IrqMutexGuard::lock(&self.0)
+    }
+
+    /// Lock the device if it is free, with IRQs off until the guard is
+    /// dropped
+    pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T, Irq>> {
+        IrqMutexGuard::try_lock(&self.0)
+    }
+
+    /// Unlock the device, whoever holds it.
+    ///
+    /// # Safety
+    /// The holder's guard must never be used or dropped again.
+    pub unsafe fn force_unlock(&self) {
+        common::sync::lockdep::release((&*self.0 as *const Mutex<T>).addr());
+        unsafe { self.0.force_unlock() }
+    }
+
+    /// Whether both are the same device
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
 }
 
-pub fn irq_controller() -> Option<Arc<Mutex<dyn DynInterruptController>>> {
-    device_manager().lock().irq_controller()
+impl<T: ?Sized> Clone for IrqDevice<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+pub fn serial_console() -> Option<IrqDevice<dyn DynSerialPort>> {
+    device_manager().lock().serial_console().map(IrqDevice::new)
+}
+
+/// The serial port named `name`. Any of them may be the console.
+pub fn serial(name: &str) -> Option<IrqDevice<dyn DynSerialPort>> {
+    device_manager().lock().serial(name).map(IrqDevice::new)
+}
+
+pub fn system_timer() -> Option<IrqDevice<dyn DynTimer>> {
+    device_manager().lock().system_timer().map(IrqDevice::new)
+}
+
+pub fn irq_controller() -> Option<IrqDevice<dyn DynInterruptController>> {
+    device_manager().lock().irq_controller().map(IrqDevice::new)
+}
+
+/// The RTC. Its lock leaves IRQs on, so this is for task context.
+pub fn wall_clock() -> Option<Arc<Mutex<dyn DynRtc>>> {
+    debug_assert!(
+        !crate::arch::in_interrupt(),
+        "RTC used in an interrupt handler"
+    );
+    device_manager().lock().wall_clock()
+}
+
+/// Microseconds on the system timer's free-running counter, if it has one
+pub fn uptime_us() -> Option<u64> {
+    system_timer()?
+        .lock()
+        .as_counting()
+        .map(|counter| counter.now_us())
 }
 
 pub fn print_devices() {
-    let dm = device_manager().lock();
-    log::info!("Registered Devices ({} total):\n", dm.count());
-    for name in dm.list() {
-        let dev_type = match dm.get(name.as_str()).unwrap() {
-            Device::Serial(_) => "Serial",
-            Device::Block(_) => "Block",
-            Device::FrameBuffer(_) => "FrameBuffer",
-            Device::Timer(_) => "Timer",
-            Device::InterruptController(_) => "InterruptController",
-        };
-        log::info!("  {} ({})\n", name, dev_type);
+    // Logging writes to the console, which is looked up in the device
+    // manager, so not under its lock
+    let devices: alloc::vec::Vec<(String, &'static str)> = {
+        let dm = device_manager().lock();
+        dm.devices()
+            .map(|(name, device)| (name.into(), device.class().name()))
+            .collect()
+    };
+    log::info!("Registered Devices ({} total):\n", devices.len());
+    for (name, class) in &devices {
+        log::info!("  {} ({})\n", name, class);
     }
 }
 
@@ -0,0 +1,81 @@
+//! Condition variable
+//!
+//! A [`CondVar`] lets a task holding a [`Mutex`] sleep until another task
+//! changes the data it guards: [`CondVar::wait`] unlocks the mutex and
+//! sleeps, and locks it again once notified. The condition has to be
+//! checked again after every wake, as another task may have got to the
+//! data first; [`CondVar::wait_while`] does so.
+//!
+//! A notification sent between the unlock and the sleep is not lost.
+//! Waiters may wake without one, which a loop around the condition takes
+//! in its stride. Notifying is safe from interrupt handlers, but the data
+//! lives behind a sleeping mutex they cannot take, so data filled in by
+//! an interrupt handler is waited for on a
+//! [`WaitQueue`](crate::process::sched::WaitQueue) instead.
+
+use super::mutex::{Mutex, MutexGuard};
+use crate::process::sched::WaitQueue;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+pub struct CondVar {
+    /// Bumped by every notification, so a waiter can tell it missed one
+    seq: AtomicU32,
+    waiters: WaitQueue,
+}
+
+impl CondVar {
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Unlock `guard`'s mutex and sleep until notified, then lock it
+    /// again.
+    ///
+    /// Before the scheduler is initialized this spins, so only another
+    /// CPU or an interrupt handler can notify it.
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex: &'a Mutex<T> = guard.mutex();
+        let seq = self.seq.load(Ordering::Acquire);
+        drop(guard);
+        self.waiters
+            .sleep_on(|| self.seq.load(Ordering::Acquire) != seq);
+        mutex.lock()
+    }
+
+    /// [`wait`](Self::wait) for as long as `condition` holds on the data,
+    /// returning with the mutex locked once it does not
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Wake the task that has waited longest
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.waiters.wake_one();
+    }
+
+    /// Wake every waiting task
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.waiters.wake_all();
+    }
+}
+
+impl Default for CondVar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
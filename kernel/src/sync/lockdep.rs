@@ -0,0 +1,336 @@
+//! Lock-order checking
+//!
+//! With the `lock-debug` feature, the spinlocks of `common::sync` and the
+//! sleeping [`Mutex`](super::Mutex) report every lock and unlock here.
+//! Each task, and each CPU until it runs tasks, has a stack of the locks
+//! it holds; an interrupt handler's locks go on the stack of the code it
+//! interrupted, which cannot run again until the handler is done.
+//!
+//! Locks are ordered by class, the place they were created. Taking a lock
+//! while holding others records that their classes come first, along
+//! with both call sites, and the kernel panics with the call sites when:
+//!
+//! - a lock is taken while holding one of a class seen taken after it,
+//!   the AB/BA inversion that deadlocks two CPUs, or a task and an
+//!   interrupt handler
+//! - a lock is taken again by the context that holds it, which spins or
+//!   sleeps forever
+//!
+//! Both are reported the first time the order is seen, whether or not it
+//! deadlocks that time. Only direct inversions are found, not longer
+//! cycles. Two locks of one class, such as the per-CPU schedulers', are
+//! not ordered against each other. A `try_lock` cannot deadlock, so it
+//! is recorded as held but orders nothing. Locks taken before the
+//! early initcalls go unchecked.
+//!
+//! The tables are fixed in size, so the checker never allocates or takes
+//! a lock it checks; filling one panics, naming the limit to raise.
+
+use crate::arch::{Irq, MAX_CPUS, cpu_id};
+use crate::process::sched::TaskId;
+use common::sync::irq::IrqControl;
+use common::sync::lockdep::{self, Checker, LockClass};
+use core::fmt;
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// Contexts holding locks at once
+const MAX_CONTEXTS: usize = 64;
+
+/// Locks one context holds at once
+const MAX_HELD: usize = 16;
+
+/// Distinct pairs of classes taken one inside the other
+const MAX_ORDERS: usize = 1024;
+
+crate::initcall!(early, LOCKDEP_INIT, init);
+
+/// Register the checker with the locks.
+fn init() {
+    let checker = Checker { acquire, release };
+    if lockdep::set_checker(checker).is_ok() {
+        log::info!("Checking lock order");
+    }
+}
+
+static STATE: Mutex<State> = Mutex::new(State::new());
+
+/// Cleared by the first report or panic, so that the panic handler's
+/// locking goes unchecked
+static CHECKING: AtomicBool = AtomicBool::new(true);
+
+/// Each CPU's running task's id plus one; zero until it runs tasks
+static RUNNING: [AtomicUsize; MAX_CPUS] = [const { AtomicUsize::new(0) }; MAX_CPUS];
+
+/// Note that the running CPU now runs `task`, so the locks it takes are
+/// that task's.
+pub fn switch_to(task: TaskId) {
+    RUNNING[cpu_id()].store(task.0 + 1, Ordering::Relaxed);
+}
+
+/// Stop checking, for good.
+pub fn stop() {
+    CHECKING.store(false, Ordering::Relaxed);
+}
+
+/// The context taking locks on the running CPU
+fn owner() -> Owner {
+    let cpu = cpu_id();
+    match RUNNING[cpu].load(Ordering::Relaxed) {
+        0 => Owner::Cpu(cpu),
+        id => Owner::Task(id - 1),
+    }
+}
+
+fn acquire(lock: usize, class: LockClass, site: &'static Location<'static>, try_lock: bool) {
+    if !CHECKING.load(Ordering::Relaxed) {
+        return;
+    }
+    let class = match class.site() {
+        Some(created) => Class::Site(created),
+        None => Class::Lock(lock),
+    };
+    let held = Held { lock, class, site };
+
+    // An interrupt handler taking a lock must not find the state locked
+    // by the code it interrupted
+    let irq = Irq::save_and_disable();
+    let result = STATE.lock().acquire(owner(), held, try_lock);
+    Irq::restore(irq);
+
+    if let Err(violation) = result {
+        stop();
+        panic!("lock-debug: {}", violation);
+    }
+}
+
+fn release(lock: usize) {
+    if !CHECKING.load(Ordering::Relaxed) {
+        return;
+    }
+    let irq = Irq::save_and_disable();
+    STATE.lock().release(owner(), lock);
+    Irq::restore(irq);
+}
+
+/// Who holds a lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Owner {
+    /// A CPU that does not run tasks yet
+    Cpu(usize),
+    /// A task, by id
+    Task(usize),
+}
+
+/// Locks ordered alike: those created at one place, or a lock whose
+/// creation site is not known on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Site(&'static Location<'static>),
+    Lock(usize),
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Class::Site(site) => write!(f, "lock created at {}", site),
+            Class::Lock(lock) => write!(f, "lock at {:#x}", lock),
+        }
+    }
+}
+
+/// A lock taken, and where
+#[derive(Debug, Clone, Copy)]
+struct Held {
+    lock: usize,
+    class: Class,
+    site: &'static Location<'static>,
+}
+
+/// Two locks seen taken one inside the other
+#[derive(Debug, Clone, Copy)]
+struct Order {
+    outer: Held,
+    inner: Held,
+}
+
+/// The locks one context holds, oldest first
+#[derive(Clone, Copy)]
+struct Stack {
+    /// `None` while the slot is free
+    owner: Option<Owner>,
+    held: [Option<Held>; MAX_HELD],
+    len: usize,
+}
+
+impl Stack {
+    const fn new() -> Self {
+        Self {
+            owner: None,
+            held: [None; MAX_HELD],
+            len: 0,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Held> + '_ {
+        self.held[..self.len].iter().flatten().copied()
+    }
+
+    fn holds(&self, lock: usize) -> bool {
+        self.iter().any(|held| held.lock == lock)
+    }
+
+    /// Forget the last hold of `lock`, freeing the slot once nothing is
+    /// held
+    fn remove(&mut self, lock: usize) {
+        let Some(index) = self.held[..self.len]
+            .iter()
+            .rposition(|held| held.is_some_and(|held| held.lock == lock))
+        else {
+            return;
+        };
+        self.held.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        self.held[self.len] = None;
+        if self.len == 0 {
+            self.owner = None;
+        }
+    }
+}
+
+struct State {
+    stacks: [Stack; MAX_CONTEXTS],
+    orders: [Option<Order>; MAX_ORDERS],
+    order_count: usize,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            stacks: [Stack::new(); MAX_CONTEXTS],
+            orders: [None; MAX_ORDERS],
+            order_count: 0,
+        }
+    }
+
+    fn acquire(&mut self, owner: Owner, new: Held, try_lock: bool) -> Result<(), Violation> {
+        let index = self.stack_of(owner)?;
+        if !try_lock {
+            let stack = self.stacks[index];
+            if let Some(held) = stack.iter().find(|held| held.lock == new.lock) {
+                return Err(Violation::Recursive { held, again: new });
+            }
+            for held in stack.iter().filter(|held| held.class != new.class) {
+                self.order(held, new)?;
+            }
+        }
+
+        let stack = &mut self.stacks[index];
+        if stack.len == MAX_HELD {
+            return Err(Violation::Full("locks held by one context", "MAX_HELD"));
+        }
+        stack.held[stack.len] = Some(new);
+        stack.len += 1;
+        stack.owner = Some(owner);
+        Ok(())
+    }
+
+    /// Forget `lock`, normally held by `owner`. A lock can be let go by
+    /// another context than the one that took it, as when a task switch
+    /// happens with it held, so failing that any holder will do. A lock
+    /// taken before the checker was registered is held by nobody.
+    fn release(&mut self, owner: Owner, lock: usize) {
+        let index = self
+            .stacks
+            .iter()
+            .position(|stack| stack.owner == Some(owner) && stack.holds(lock))
+            .or_else(|| self.stacks.iter().position(|stack| stack.holds(lock)));
+        if let Some(index) = index {
+            self.stacks[index].remove(lock);
+        }
+    }
+
+    /// The stack of `owner`, given a free slot if it holds nothing
+    fn stack_of(&mut self, owner: Owner) -> Result<usize, Violation> {
+        self.stacks
+            .iter()
+            .position(|stack| stack.owner == Some(owner))
+            .or_else(|| self.stacks.iter().position(|stack| stack.owner.is_none()))
+            .ok_or(Violation::Full("contexts holding locks", "MAX_CONTEXTS"))
+    }
+
+    /// Record that `inner` is taken while holding `outer`, unless their
+    /// classes were seen the other way round
+    fn order(&mut self, outer: Held, inner: Held) -> Result<(), Violation> {
+        let orders = &self.orders[..self.order_count];
+        if let Some(&seen) = orders
+            .iter()
+            .flatten()
+            .find(|seen| seen.outer.class == inner.class && seen.inner.class == outer.class)
+        {
+            return Err(Violation::Inversion {
+                held: outer,
+                taken: inner,
+                seen,
+            });
+        }
+        if orders
+            .iter()
+            .flatten()
+            .any(|seen| seen.outer.class == outer.class && seen.inner.class == inner.class)
+        {
+            return Ok(());
+        }
+
+        if self.order_count == MAX_ORDERS {
+            return Err(Violation::Full("lock orders", "MAX_ORDERS"));
+        }
+        self.orders[self.order_count] = Some(Order { outer, inner });
+        self.order_count += 1;
+        Ok(())
+    }
+}
+
+/// Why the checker panics
+enum Violation {
+    /// `again` is the lock `held` already is
+    Recursive { held: Held, again: Held },
+    /// `taken` while holding `held`, which `seen` took the other way
+    /// round
+    Inversion {
+        held: Held,
+        taken: Held,
+        seen: Order,
+    },
+    /// A table is full: what it holds, and the limit to raise
+    Full(&'static str, &'static str),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Recursive { held, again } => write!(
+                f,
+                "{} taken at {} is already held, taken at {}",
+                again.class, again.site, held.site
+            ),
+            Violation::Inversion { held, taken, seen } => write!(
+                f,
+                "lock order inversion: {} taken at {} while holding {} taken at {}; \
+                 the other order was seen taking {} at {} while holding {} taken at {}",
+                taken.class,
+                taken.site,
+                held.class,
+                held.site,
+                seen.inner.class,
+                seen.inner.site,
+                seen.outer.class,
+                seen.outer.site
+            ),
+            Violation::Full(what, limit) => {
+                write!(f, "too many {} to track; raise {}", what, limit)
+            }
+        }
+    }
+}
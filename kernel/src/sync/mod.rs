@@ -0,0 +1,19 @@
+//! Sleeping locks
+//!
+//! The spinlocks of `spin` and [`IrqSpinLock`](crate::arch::IrqSpinLock)
+//! suit short critical sections and interrupt handlers. The locks here
+//! put waiters to sleep on a [`WaitQueue`](crate::process::sched::WaitQueue)
+//! instead, for sections that take long, such as a filesystem waiting on
+//! its disk. They may only be taken in task context. A [`CondVar`] waits
+//! for the data behind a [`Mutex`] to change.
+//!
+//! With the `lock-debug` feature, [`lockdep`] checks the order every lock
+//! is taken in, spinlocks included.
+
+pub mod condvar;
+#[cfg(feature = "lock-debug")]
+pub mod lockdep;
+pub mod mutex;
+
+pub use condvar::CondVar;
+pub use mutex::{Mutex, MutexGuard};
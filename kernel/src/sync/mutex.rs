@@ -0,0 +1,151 @@
+//! Blocking mutex
+//!
+//! A [`Mutex`] whose waiters sleep on a wait queue rather than spin, so a
+//! holder that waits on a device for milliseconds leaves the CPU to
+//! others. Before the scheduler is initialized there is nothing to sleep
+//! on, and waiters spin.
+//!
+//! Unlocking wakes the longest sleeper, which then competes for the lock
+//! with any task that arrives meanwhile; the loser sleeps again. The
+//! lock is not recursive: taking it twice on one task deadlocks. Never
+//! take it in an interrupt handler, which would put the interrupted task
+//! to sleep; debug builds panic if one does, even when the lock is free.
+
+use crate::process::sched::WaitQueue;
+use common::sync::lockdep::{self, LockClass};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicBool,
+    waiters: WaitQueue,
+    class: LockClass,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+/// Access to the data of a locked [`Mutex`]; unlocks it when dropped
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T> Mutex<T> {
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+            class: LockClass::here(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Take the data out, which needs no locking as the mutex is owned
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Lock the mutex, sleeping until it is free
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        debug_assert!(
+            !crate::arch::in_interrupt(),
+            "sleeping Mutex taken in an interrupt handler"
+        );
+        lockdep::acquire(self.id(), self.class, false);
+        if !self.acquire() {
+            self.waiters.sleep_on(|| self.acquire());
+        }
+        MutexGuard { mutex: self }
+    }
+
+    /// Lock the mutex if it is free
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        if !self.acquire() {
+            return None;
+        }
+        lockdep::acquire(self.id(), self.class, true);
+        Some(MutexGuard { mutex: self })
+    }
+
+    /// Whether the mutex is held. Only a hint: it may change at once.
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// The data, which needs no locking as the mutex is borrowed mutably
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn acquire(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// What identifies the mutex to the lock checker
+    fn id(&self) -> usize {
+        (self as *const Self).addr()
+    }
+
+    fn release(&self) {
+        lockdep::release(self.id());
+        self.locked.store(false, Ordering::Release);
+        self.waiters.wake_one();
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    #[cfg_attr(feature = "lock-debug", track_caller)]
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("Mutex").field("data", &&*guard).finish(),
+            None => f.write_str("Mutex { <locked> }"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    /// The mutex the guard holds
+    pub(super) fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.release();
+    }
+}
@@ -1,3 +1,294 @@
+//! System call dispatch
+//!
+//! User code traps in with the syscall number in r7 and up to six
+//! arguments in r0-r5, as in the ARM EABI. [`dispatch`] looks the number
+//! up in [`TABLE`], runs the handler with the calling process's state and
+//! stores the result in r0: the value on success, or the negated error
+//! number, as [`Errno::as_result`] gives it.
+//!
+//! Handlers that work on a descriptor run on a copy of it, outside the
+//! process table lock, so that a read or write may sleep; the offset it
+//! leaves is stored back afterwards. Handlers that open or close files
+//! take the lock themselves, only around the table update.
+
+use super::errno::{ENOSYS, Errno};
+use super::handlers::{self, *};
 use crate::arch::TrapFrame;
+use crate::fs::fd::{Fd, FdError, FileDescriptor, FileDescriptorTable};
+use crate::mm::address_space::AddressSpace;
+use crate::process::sched;
+use crate::process::table::process_table;
+use alloc::sync::Arc;
+
+/// Arguments of a system call, in register order
+type Args = [usize; 6];
+
+type Handler = fn(&mut TrapFrame, Args) -> Result<usize, FdError>;
+
+/// Syscall numbers the table covers
+pub const NR_SYSCALLS: usize = 400;
+
+static TABLE: [Option<Handler>; NR_SYSCALLS] = {
+    let mut table: [Option<Handler>; NR_SYSCALLS] = [None; NR_SYSCALLS];
+    table[SYS_EXIT as usize] = Some(exit);
+    table[SYS_FORK as usize] = Some(fork);
+    table[SYS_READ as usize] = Some(read);
+    table[SYS_WRITE as usize] = Some(write);
+    table[SYS_OPEN as usize] = Some(open);
+    table[SYS_CLOSE as usize] = Some(close);
+    table[SYS_EXECVE as usize] = Some(execve);
+    table[SYS_LSEEK as usize] = Some(lseek);
+    table[SYS_GETPID as usize] = Some(getpid);
+    table[SYS_NICE as usize] = Some(nice);
+    table[SYS_SYNC as usize] = Some(sync);
+    table[SYS_KILL as usize] = Some(kill);
+    table[SYS_DUP as usize] = Some(dup);
+    table[SYS_PIPE as usize] = Some(pipe);
+    table[SYS_BRK as usize] = Some(brk);
+    table[SYS_IOCTL as usize] = Some(ioctl);
+    table[SYS_FCNTL as usize] = Some(fcntl);
+    table[SYS_DUP2 as usize] = Some(dup2);
+    table[SYS_GETPPID as usize] = Some(getppid);
+    table[SYS_GETTIMEOFDAY as usize] = Some(gettimeofday);
+    table[SYS_SIGACTION as usize] = Some(sigaction);
+    table[SYS_REBOOT as usize] = Some(reboot);
+    table[SYS_MUNMAP as usize] = Some(munmap);
+    table[SYS_GETPRIORITY as usize] = Some(getpriority);
+    table[SYS_SETPRIORITY as usize] = Some(setpriority);
+    table[SYS_STAT as usize] = Some(stat);
+    table[SYS_FSTAT as usize] = Some(fstat);
+    table[SYS_WAIT4 as usize] = Some(wait4);
+    table[SYS_FSYNC as usize] = Some(fsync);
+    table[SYS_SIGRETURN as usize] = Some(sigreturn);
+    table[SYS_SIGPROCMASK as usize] = Some(sigprocmask);
+    table[SYS_SCHED_YIELD as usize] = Some(sched_yield);
+    table[SYS_NANOSLEEP as usize] = Some(nanosleep);
+    table[SYS_POLL as usize] = Some(poll);
+    table[SYS_MMAP2 as usize] = Some(mmap2);
+    table[SYS_FUTEX as usize] = Some(futex);
+    table[SYS_CLOCK_GETTIME as usize] = Some(clock_gettime);
+    table
+};
+
+/// Run the system call `tf` trapped in with and store its result in `tf`
+pub fn dispatch(tf: &mut TrapFrame) {
+    let number = tf.syscall_number() as usize;
+    let args = tf.syscall_args();
+    let result = match TABLE.get(number).copied().flatten() {
+        Some(handler) => handler(tf, args).map_err(Errno::from),
+        None => Err(Errno(ENOSYS)),
+    };
+    tf.set_result(result.unwrap_or_else(Errno::as_result));
+}
+
+// ---------------------------------------------------------------------------
+// Process state
+// ---------------------------------------------------------------------------
+
+/// Run `f` on a copy of the calling process's descriptor `fd`, then store
+/// the offset it leaves back, unless the descriptor was closed meanwhile
+fn with_fd<R>(
+    fd: usize,
+    f: impl FnOnce(&mut FileDescriptor) -> Result<R, FdError>,
+) -> Result<R, FdError> {
+    let fd = Fd(fd);
+    let mut desc = with_fds(|fds| Ok(fds.get(fd)?.clone()))?;
+    let result = f(&mut desc);
+    let _ = with_fds(|fds| {
+        let entry = fds.get_mut(fd)?;
+        if Arc::ptr_eq(entry.file(), desc.file()) {
+            entry.set_offset(desc.offset());
+        }
+        Ok(())
+    });
+    result
+}
+
+/// Run `f` on a snapshot of the calling process's descriptor table, for
+/// calls that may wait without holding the process table lock
+fn with_fds_snapshot<R>(
+    f: impl FnOnce(&FileDescriptorTable) -> Result<R, FdError>,
+) -> Result<R, FdError> {
+    let fds = with_fds(|fds| Ok(fds.fork()))?;
+    f(&fds)
+}
+
+/// Run `f` on the calling process's address space
+fn with_space<R>(f: impl FnOnce(&AddressSpace) -> Result<R, FdError>) -> Result<R, FdError> {
+    let space = sched::with_current(|task| {
+        task.address_space
+            .as_ref()
+            .map(|space| space as *const AddressSpace)
+    })
+    .flatten()
+    .ok_or(FdError::NotSupported)?;
+    // SAFETY: the address space belongs to the running task, which is the
+    // caller, so it lives until the call returns; only `execve` replaces
+    // it, and `execve` does not come through here
+    f(unsafe { &*space })
+}
+
+// ---------------------------------------------------------------------------
+// Table entries
+// ---------------------------------------------------------------------------
+
+fn exit(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_exit(args[0] as i32)
+}
+
+fn fork(tf: &mut TrapFrame, _args: Args) -> Result<usize, FdError> {
+    handlers::sys_fork(tf)
+}
+
+fn read(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_fd(args[0], |desc| handlers::sys_read(desc, args[1], args[2]))
+}
+
+fn write(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_fd(args[0], |desc| handlers::sys_write(desc, args[1], args[2]))
+}
+
+fn open(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_open(args[0], args[1] as u32, args[2] as u32)
+}
+
+fn close(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_close(Fd(args[0]))
+}
+
+fn execve(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_execve(args[0], args[1], args[2])
+}
+
+fn lseek(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_fd(args[0], |desc| {
+        handlers::sys_lseek(desc, args[1] as isize, args[2] as u32)
+    })
+}
+
+fn getpid(_tf: &mut TrapFrame, _args: Args) -> Result<usize, FdError> {
+    handlers::sys_getpid()
+}
+
+fn nice(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_nice(args[0] as i32)
+}
+
+fn sync(_tf: &mut TrapFrame, _args: Args) -> Result<usize, FdError> {
+    handlers::sys_sync()
+}
+
+fn kill(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_kill(args[0] as i32, args[1] as u32)
+}
+
+fn dup(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_fds(|fds| handlers::sys_dup(fds, Fd(args[0])))
+}
+
+fn pipe(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_pipe(args[0])
+}
+
+fn brk(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_space(|space| handlers::sys_brk(space, args[0]))
+}
+
+fn ioctl(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_fds_snapshot(|fds| handlers::sys_ioctl(fds, Fd(args[0]), args[1] as u32, args[2]))
+}
+
+fn fcntl(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_fds(|fds| handlers::sys_fcntl(fds, Fd(args[0]), args[1] as u32, args[2]))
+}
+
+fn dup2(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_dup2(Fd(args[0]), Fd(args[1]))
+}
+
+fn getppid(_tf: &mut TrapFrame, _args: Args) -> Result<usize, FdError> {
+    handlers::sys_getppid()
+}
+
+fn gettimeofday(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_gettimeofday(args[0], args[1])
+}
+
+fn sigaction(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_sigaction(args[0] as u32, args[1], args[2])
+}
+
+fn reboot(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_reboot(args[0] as u32, args[1] as u32, args[2] as u32, args[3])
+}
+
+fn munmap(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_space(|space| handlers::sys_munmap(space, args[0], args[1]))
+}
+
+fn getpriority(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_getpriority(args[0] as u32, args[1] as u32)
+}
+
+fn setpriority(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_setpriority(args[0] as u32, args[1] as u32, args[2] as i32)
+}
+
+fn stat(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_stat(args[0], args[1])
+}
+
+fn fstat(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_fd(args[0], |desc| handlers::sys_fstat(desc, args[1]))
+}
+
+fn wait4(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_wait4(args[0] as i32, args[1], args[2] as u32, args[3])
+}
+
+fn fsync(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_fds_snapshot(|fds| handlers::sys_fsync(fds, Fd(args[0])))
+}
+
+fn sigreturn(tf: &mut TrapFrame, _args: Args) -> Result<usize, FdError> {
+    let sp = tf.user_sp();
+    handlers::sys_sigreturn(tf, sp)
+}
+
+fn sigprocmask(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_sigprocmask(args[0] as u32, args[1], args[2])
+}
+
+fn sched_yield(_tf: &mut TrapFrame, _args: Args) -> Result<usize, FdError> {
+    handlers::sys_sched_yield()
+}
+
+fn nanosleep(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_nanosleep(args[0], args[1])
+}
+
+fn poll(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_fds_snapshot(|fds| handlers::sys_poll(fds, args[0], args[1], args[2] as i32))
+}
+
+fn mmap2(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_space(|space| {
+        handlers::sys_mmap2(
+            space,
+            args[0],
+            args[1],
+            args[2] as u32,
+            args[3] as u32,
+            args[4] as i32,
+            args[5],
+        )
+    })
+}
+
+fn futex(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    with_space(|space| handlers::sys_futex(space, args[0], args[1] as u32, args[2] as u32))
+}
 
-pub fn dispatch(_tf: &TrapFrame) {}
+fn clock_gettime(_tf: &mut TrapFrame, args: Args) -> Result<usize, FdError> {
+    handlers::sys_clock_gettime(args[0] as u32, args[1])
+}
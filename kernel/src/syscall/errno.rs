@@ -0,0 +1,105 @@
+//! Error numbers returned to user space
+//!
+//! A failed system call returns the negated error number in the result
+//! register, as on Linux, so C libraries can tell the two apart by the
+//! range -4095 to -1. The numbers are Linux's.
+
+use crate::fs::FsError;
+use crate::fs::fd::FdError;
+
+/// Operation not permitted
+pub const EPERM: i32 = 1;
+/// No such file or directory
+pub const ENOENT: i32 = 2;
+/// No such process
+pub const ESRCH: i32 = 3;
+/// I/O error
+pub const EIO: i32 = 5;
+/// Bad file descriptor
+pub const EBADF: i32 = 9;
+/// No child processes
+pub const ECHILD: i32 = 10;
+/// Try again
+pub const EAGAIN: i32 = 11;
+/// Out of memory
+pub const ENOMEM: i32 = 12;
+/// Permission denied
+pub const EACCES: i32 = 13;
+/// Bad address
+pub const EFAULT: i32 = 14;
+/// Device or resource busy
+pub const EBUSY: i32 = 16;
+/// File exists
+pub const EEXIST: i32 = 17;
+/// Cross-device link
+pub const EXDEV: i32 = 18;
+/// Not a directory
+pub const ENOTDIR: i32 = 20;
+/// Is a directory
+pub const EISDIR: i32 = 21;
+/// Invalid argument
+pub const EINVAL: i32 = 22;
+/// Too many open files
+pub const EMFILE: i32 = 24;
+/// Illegal seek
+pub const ESPIPE: i32 = 29;
+/// Broken pipe
+pub const EPIPE: i32 = 32;
+/// Function not implemented
+pub const ENOSYS: i32 = 38;
+/// Too many symbolic links encountered
+pub const ELOOP: i32 = 40;
+/// Operation not supported
+pub const EOPNOTSUPP: i32 = 95;
+
+/// An error number, positive as defined above
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Errno(pub i32);
+
+impl Errno {
+    /// The value a failed system call returns: the negated number
+    pub fn as_result(self) -> usize {
+        (-self.0) as usize
+    }
+}
+
+impl From<FdError> for Errno {
+    fn from(err: FdError) -> Self {
+        Errno(match err {
+            FdError::BadFd => EBADF,
+            FdError::TooManyFiles => EMFILE,
+            FdError::IoError => EIO,
+            FdError::InvalidSeek => ESPIPE,
+            FdError::NotSupported => EOPNOTSUPP,
+            FdError::PermissionDenied => EACCES,
+            FdError::InvalidArgument => EINVAL,
+            FdError::BrokenPipe => EPIPE,
+            FdError::WouldBlock => EAGAIN,
+            FdError::OutOfMemory => ENOMEM,
+            FdError::NoChild => ECHILD,
+            FdError::NoSuchProcess => ESRCH,
+            FdError::BadAddress => EFAULT,
+            FdError::Fs(err) => return err.into(),
+            FdError::Other(_) => EIO,
+        })
+    }
+}
+
+impl From<FsError> for Errno {
+    fn from(err: FsError) -> Self {
+        Errno(match err {
+            FsError::NotFound => ENOENT,
+            FsError::AlreadyExists => EEXIST,
+            FsError::NotADirectory => ENOTDIR,
+            FsError::IsADirectory => EISDIR,
+            FsError::PermissionDenied => EACCES,
+            FsError::NotSupported => EOPNOTSUPP,
+            FsError::CrossDevice => EXDEV,
+            FsError::InvalidPath => EINVAL,
+            FsError::Busy => EBUSY,
+            FsError::LinkLoop => ELOOP,
+            FsError::OutOfMemory => ENOMEM,
+            FsError::IoError | FsError::Unknown => EIO,
+        })
+    }
+}
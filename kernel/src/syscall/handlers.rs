@@ -0,0 +1,790 @@
+//! System call implementations.
+//!
+//! Handlers take the calling process's state explicitly and return the
+//! value to place in the result register. Those that open or close files
+//! reach the descriptor table with [`with_fds`] instead, so the filesystem
+//! is never called with the process table locked.
+
+use super::uaccess::{
+    copy_bytes_from_user, copy_bytes_to_user, copy_from_user, copy_to_user, user_string,
+    user_strings,
+};
+use crate::arch::TrapFrame;
+use crate::block::flusher;
+use crate::fs::fd::{AccessMode, Fd, FdError, FdFlags, FileDescriptor, FileDescriptorTable};
+use crate::fs::file::{FileStat, FileType, OpenFlags, PollEvents, SeekWhence};
+use crate::fs::vfs::vfs;
+use crate::fs::{FileSystem, pipe, poll, try_zeroed};
+use crate::kcore::power;
+use crate::mm::address_space::AddressSpace;
+use crate::mm::page_allocator::PAGE_SIZE;
+use crate::mm::vma::{MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, MMAP_BASE, MMAP_END, Vma};
+use crate::process::exit::{self, WNOHANG};
+use crate::process::futex::{self, FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAKE};
+use crate::process::pcb::{Pid, ProcessState};
+use crate::process::sched;
+use crate::process::signal::{self, SigAction, SigSet};
+use crate::process::table::process_table;
+use crate::process::{elf, fork};
+use crate::subsystems::uptime_us;
+use crate::time;
+use alloc::vec::Vec;
+
+/// ARM EABI syscall number of `exit`
+pub const SYS_EXIT: u32 = 1;
+/// ARM EABI syscall number of `fork`
+pub const SYS_FORK: u32 = 2;
+/// ARM EABI syscall number of `read`
+pub const SYS_READ: u32 = 3;
+/// ARM EABI syscall number of `write`
+pub const SYS_WRITE: u32 = 4;
+/// ARM EABI syscall number of `open`
+pub const SYS_OPEN: u32 = 5;
+/// ARM EABI syscall number of `close`
+pub const SYS_CLOSE: u32 = 6;
+/// ARM EABI syscall number of `execve`
+pub const SYS_EXECVE: u32 = 11;
+/// ARM EABI syscall number of `lseek`
+pub const SYS_LSEEK: u32 = 19;
+/// ARM EABI syscall number of `getpid`
+pub const SYS_GETPID: u32 = 20;
+/// ARM EABI syscall number of `nice`
+pub const SYS_NICE: u32 = 34;
+/// ARM EABI syscall number of `sync`
+pub const SYS_SYNC: u32 = 36;
+/// ARM EABI syscall number of `kill`
+pub const SYS_KILL: u32 = 37;
+/// ARM EABI syscall number of `dup`
+pub const SYS_DUP: u32 = 41;
+/// ARM EABI syscall number of `pipe`
+pub const SYS_PIPE: u32 = 42;
+/// ARM EABI syscall number of `brk`
+pub const SYS_BRK: u32 = 45;
+/// ARM EABI syscall number of `ioctl`
+pub const SYS_IOCTL: u32 = 54;
+/// ARM EABI syscall number of `fcntl`
+pub const SYS_FCNTL: u32 = 55;
+/// ARM EABI syscall number of `dup2`
+pub const SYS_DUP2: u32 = 63;
+/// ARM EABI syscall number of `getppid`
+pub const SYS_GETPPID: u32 = 64;
+/// ARM EABI syscall number of `gettimeofday`
+pub const SYS_GETTIMEOFDAY: u32 = 78;
+/// ARM EABI syscall number of `sigaction`
+pub const SYS_SIGACTION: u32 = 67;
+/// ARM EABI syscall number of `reboot`
+pub const SYS_REBOOT: u32 = 88;
+/// ARM EABI syscall number of `munmap`
+pub const SYS_MUNMAP: u32 = 91;
+/// ARM EABI syscall number of `getpriority`
+pub const SYS_GETPRIORITY: u32 = 96;
+/// ARM EABI syscall number of `setpriority`
+pub const SYS_SETPRIORITY: u32 = 97;
+/// ARM EABI syscall number of `stat`
+pub const SYS_STAT: u32 = 106;
+/// ARM EABI syscall number of `fstat`
+pub const SYS_FSTAT: u32 = 108;
+/// ARM EABI syscall number of `wait4`
+pub const SYS_WAIT4: u32 = 114;
+/// ARM EABI syscall number of `fsync`
+pub const SYS_FSYNC: u32 = 118;
+/// ARM EABI syscall number of `sigreturn`
+pub const SYS_SIGRETURN: u32 = 119;
+/// ARM EABI syscall number of `sigprocmask`
+pub const SYS_SIGPROCMASK: u32 = 126;
+/// ARM EABI syscall number of `sched_yield`
+pub const SYS_SCHED_YIELD: u32 = 158;
+/// ARM EABI syscall number of `nanosleep`
+pub const SYS_NANOSLEEP: u32 = 162;
+/// ARM EABI syscall number of `poll`
+pub const SYS_POLL: u32 = 168;
+/// ARM EABI syscall number of `mmap2`
+pub const SYS_MMAP2: u32 = 192;
+/// ARM EABI syscall number of `futex`
+pub const SYS_FUTEX: u32 = 240;
+/// ARM EABI syscall number of `clock_gettime`
+pub const SYS_CLOCK_GETTIME: u32 = 263;
+
+/// `exit(status)`: end the calling process. Its parent collects the low
+/// byte of `status` with `wait4`.
+pub fn sys_exit(status: i32) -> ! {
+    exit::exit(status)
+}
+
+/// `wait4(pid, status, options, rusage)`: wait for the child `pid`, or
+/// any child if `pid` is -1, to exit and reap it. Returns its PID, or 0
+/// if `WNOHANG` is set and no such child has exited yet. The wait status
+/// is stored at `status` unless it is null.
+///
+/// Process groups and resource usage are not supported.
+pub fn sys_wait4(pid: i32, status: usize, options: u32, _rusage: usize) -> Result<usize, FdError> {
+    let pid = match pid {
+        -1 => None,
+        pid if pid > 0 => Some(Pid(pid as usize)),
+        _ => return Err(FdError::NotSupported),
+    };
+    if options & !WNOHANG != 0 {
+        return Err(FdError::InvalidArgument);
+    }
+
+    let Some((child, wait_status)) = exit::waitpid(pid, options)? else {
+        return Ok(0);
+    };
+    if status != 0 {
+        copy_to_user(status, wait_status)?;
+    }
+    Ok(child.0)
+}
+
+/// `fork()`: duplicate the calling process, which trapped in with
+/// `frame`. Returns the child's PID; the child sees 0.
+pub fn sys_fork(frame: &TrapFrame) -> Result<usize, FdError> {
+    Ok(fork::fork(frame)?.0)
+}
+
+/// `getpid()`: the calling process's PID.
+pub fn sys_getpid() -> Result<usize, FdError> {
+    let task = sched::current().ok_or(FdError::NotSupported)?;
+    process_table()
+        .lock()
+        .by_task(task)
+        .map(|process| process.pid.0)
+        .ok_or(FdError::NotSupported)
+}
+
+/// `getppid()`: the PID of the calling process's parent, or 0 if it has
+/// none.
+pub fn sys_getppid() -> Result<usize, FdError> {
+    let task = sched::current().ok_or(FdError::NotSupported)?;
+    process_table()
+        .lock()
+        .by_task(task)
+        .map(|process| process.parent_pid.map_or(0, |pid| pid.0))
+        .ok_or(FdError::NotSupported)
+}
+
+/// `sched_yield()`: let other runnable tasks run first.
+pub fn sys_sched_yield() -> Result<usize, FdError> {
+    sched::yield_now();
+    Ok(0)
+}
+
+/// `execve(path, argv, envp)`: replace the calling process's program with
+/// the executable at `path`. `argv` and `envp` are null-terminated arrays
+/// of string pointers; a null array counts as empty. Does not return on
+/// success.
+pub fn sys_execve(path: usize, argv: usize, envp: usize) -> Result<usize, FdError> {
+    let path = user_string(path)?;
+    let argv = user_strings(argv)?;
+    let envp = user_strings(envp)?;
+    Err(elf::execve(path, argv, envp).into())
+}
+
+/// `kill(pid, sig)`: send signal `sig` to the process `pid`, or just
+/// check that it exists if `sig` is 0.
+///
+/// Process groups are not supported.
+pub fn sys_kill(pid: i32, sig: u32) -> Result<usize, FdError> {
+    if pid <= 0 {
+        return Err(FdError::NotSupported);
+    }
+    signal::kill(Pid(pid as usize), sig)?;
+    Ok(0)
+}
+
+/// `sigaction(sig, act, oldact)`: set the action for `sig` to the one at
+/// `act` and store the previous one at `oldact`, each unless null.
+pub fn sys_sigaction(sig: u32, act: usize, oldact: usize) -> Result<usize, FdError> {
+    let action = match act {
+        0 => None,
+        act => Some(copy_from_user::<SigAction>(act)?),
+    };
+    let old = signal::sigaction(sig, action)?;
+    if oldact != 0 {
+        copy_to_user(oldact, old)?;
+    }
+    Ok(0)
+}
+
+/// `sigprocmask(how, set, oldset)`: change the blocked signals by the
+/// mask at `set` as `how` says, and store the previous mask at `oldset`,
+/// each unless null.
+pub fn sys_sigprocmask(how: u32, set: usize, oldset: usize) -> Result<usize, FdError> {
+    let mask = match set {
+        0 => None,
+        set => Some(copy_from_user::<SigSet>(set)?),
+    };
+    let old = signal::sigprocmask(how, mask)?;
+    if oldset != 0 {
+        copy_to_user(oldset, old)?;
+    }
+    Ok(0)
+}
+
+/// `sigreturn()`: called by a signal handler's restorer with the stack
+/// pointer `sp` back at the signal frame. Resumes the interrupted context
+/// by loading it into `frame`, and returns its result register so that
+/// storing the result leaves it intact.
+pub fn sys_sigreturn(frame: &mut TrapFrame, sp: usize) -> Result<usize, FdError> {
+    signal::sigreturn(frame, sp)?;
+    Ok(frame.result())
+}
+
+/// `which` for `getpriority` and `setpriority`: `who` is a PID
+pub const PRIO_PROCESS: u32 = 0;
+
+/// `nice(inc)`: add `inc` to the calling process's nice level, within
+/// the limits.
+pub fn sys_nice(inc: i32) -> Result<usize, FdError> {
+    let task = sched::current().ok_or(FdError::NotSupported)?;
+    let nice = sched::nice(task).ok_or(FdError::NoSuchProcess)?;
+    sched::set_nice(task, nice.saturating_add(inc));
+    Ok(0)
+}
+
+/// `getpriority(which, who)`: the nice level of the process `who`, or
+/// the caller if 0, returned as `20 - nice` so that it is never negative.
+///
+/// Process groups and users are not supported.
+pub fn sys_getpriority(which: u32, who: u32) -> Result<usize, FdError> {
+    let task = priority_target(which, who)?;
+    let nice = sched::nice(task).ok_or(FdError::NoSuchProcess)?;
+    Ok((20 - nice) as usize)
+}
+
+/// `setpriority(which, who, prio)`: set the nice level of the process
+/// `who`, or the caller if 0, to `prio`, within the limits.
+///
+/// Process groups and users are not supported.
+pub fn sys_setpriority(which: u32, who: u32, prio: i32) -> Result<usize, FdError> {
+    let task = priority_target(which, who)?;
+    if !sched::set_nice(task, prio) {
+        return Err(FdError::NoSuchProcess);
+    }
+    Ok(0)
+}
+
+/// The task of the process `getpriority` and `setpriority` refer to
+fn priority_target(which: u32, who: u32) -> Result<sched::TaskId, FdError> {
+    if which != PRIO_PROCESS {
+        return Err(FdError::NotSupported);
+    }
+    if who == 0 {
+        return sched::current().ok_or(FdError::NotSupported);
+    }
+    process_table()
+        .lock()
+        .get(Pid(who as usize))
+        .filter(|process| process.state != ProcessState::Zombie)
+        .map(|process| process.task)
+        .ok_or(FdError::NoSuchProcess)
+}
+
+/// `sync()`: write back every mounted filesystem, then what the disks
+/// have cached.
+pub fn sys_sync() -> Result<usize, FdError> {
+    vfs().sync().map_err(|_| FdError::IoError)?;
+    flusher::flush_all();
+    Ok(0)
+}
+
+/// `fsync(fd)`: write the file's data and metadata through to its device.
+pub fn sys_fsync(fds: &FileDescriptorTable, fd: Fd) -> Result<usize, FdError> {
+    fds.get(fd)?.file().flush()?;
+    Ok(0)
+}
+
+/// `reboot` magic: the first argument must be this
+pub const LINUX_REBOOT_MAGIC1: u32 = 0xFEE1_DEAD;
+/// `reboot` magics: the second argument must be one of these
+pub const LINUX_REBOOT_MAGIC2: [u32; 4] = [0x2812_1969, 0x0512_1996, 0x1604_1998, 0x2011_2000];
+/// `reboot` command: restart the machine
+pub const LINUX_REBOOT_CMD_RESTART: u32 = 0x0123_4567;
+/// `reboot` command: stop the machine, leaving the power on
+pub const LINUX_REBOOT_CMD_HALT: u32 = 0xCDEF_0123;
+/// `reboot` command: switch the machine off
+pub const LINUX_REBOOT_CMD_POWER_OFF: u32 = 0x4321_FEDC;
+/// `reboot` command: have Ctrl-Alt-Del reboot at once
+pub const LINUX_REBOOT_CMD_CAD_ON: u32 = 0x89AB_CDEF;
+/// `reboot` command: have Ctrl-Alt-Del signal init
+pub const LINUX_REBOOT_CMD_CAD_OFF: u32 = 0;
+
+/// `reboot(magic1, magic2, cmd, arg)`: sync, flush and then restart,
+/// halt or switch off the machine as `cmd` says; see [`power`]. Does not
+/// return on success.
+///
+/// There is no Ctrl-Alt-Del to configure, so those commands do nothing,
+/// and there are no users, so anyone may reboot.
+pub fn sys_reboot(magic1: u32, magic2: u32, cmd: u32, _arg: usize) -> Result<usize, FdError> {
+    if magic1 != LINUX_REBOOT_MAGIC1 || !LINUX_REBOOT_MAGIC2.contains(&magic2) {
+        return Err(FdError::InvalidArgument);
+    }
+    match cmd {
+        LINUX_REBOOT_CMD_RESTART => power::reboot(),
+        LINUX_REBOOT_CMD_HALT => power::halt(),
+        LINUX_REBOOT_CMD_POWER_OFF => power::power_off(),
+        LINUX_REBOOT_CMD_CAD_ON | LINUX_REBOOT_CMD_CAD_OFF => Ok(0),
+        _ => Err(FdError::InvalidArgument),
+    }
+}
+
+/// Most bytes one `read` or `write` transfers; larger requests are cut
+/// short, as a pipe or device would
+pub const IO_MAX: usize = 64 * 1024;
+
+/// `read(fd, buf, count)`: read up to `count` bytes from `desc` into the
+/// buffer at `buf`, returning how many were read.
+pub fn sys_read(desc: &mut FileDescriptor, buf: usize, count: usize) -> Result<usize, FdError> {
+    let mut data = try_zeroed(count.min(IO_MAX)).ok_or(FdError::OutOfMemory)?;
+    let n = desc.read(&mut data)?;
+    copy_bytes_to_user(buf, &data[..n])?;
+    Ok(n)
+}
+
+/// `write(fd, buf, count)`: write up to `count` bytes from the buffer at
+/// `buf` to `desc`, returning how many were written.
+pub fn sys_write(desc: &mut FileDescriptor, buf: usize, count: usize) -> Result<usize, FdError> {
+    let mut data = try_zeroed(count.min(IO_MAX)).ok_or(FdError::OutOfMemory)?;
+    copy_bytes_from_user(buf, &mut data)?;
+    desc.write(&data)
+}
+
+/// Run `f` on the calling process's descriptor table, with the process
+/// table locked. `f` must not call into a filesystem or touch user
+/// memory; descriptors it removes are handed back to be released after.
+pub(super) fn with_fds<R>(
+    f: impl FnOnce(&mut FileDescriptorTable) -> Result<R, FdError>,
+) -> Result<R, FdError> {
+    let task = sched::current().ok_or(FdError::NotSupported)?;
+    let mut table = process_table().lock();
+    let process = table.by_task_mut(task).ok_or(FdError::NotSupported)?;
+    f(&mut process.fd_table)
+}
+
+/// `open(path, flags, mode)`: open the file at `path` and return its new
+/// descriptor. `flags` takes the Linux `O_*` values.
+///
+/// There are no permission bits yet, so `mode` is ignored.
+pub fn sys_open(path: usize, flags: u32, _mode: u32) -> Result<usize, FdError> {
+    let path = user_string(path)?;
+    let flags = OpenFlags::from_bits_truncate(flags);
+    let file = vfs().open(&path, flags)?;
+    // Kept here, so a file that gets no descriptor is released unlocked
+    let fd = with_fds(|fds| fds.install(file.clone(), flags))?;
+    Ok(fd.0)
+}
+
+/// `close(fd)`: release the descriptor `fd`.
+pub fn sys_close(fd: Fd) -> Result<usize, FdError> {
+    let closed = with_fds(|fds| fds.close(fd))?;
+    drop(closed);
+    Ok(0)
+}
+
+/// `lseek(fd, offset, whence)`: move the offset of `desc` to `offset`
+/// from the start, the current offset or the end for `whence` 0, 1 or 2,
+/// and return it.
+pub fn sys_lseek(desc: &mut FileDescriptor, offset: isize, whence: u32) -> Result<usize, FdError> {
+    let whence = match whence {
+        0 => SeekWhence::Start,
+        1 => SeekWhence::Current,
+        2 => SeekWhence::End,
+        _ => return Err(FdError::InvalidArgument),
+    };
+    desc.seek(whence, offset)
+}
+
+/// `dup(fd)`: duplicate `fd` onto the lowest free descriptor.
+pub fn sys_dup(fds: &mut FileDescriptorTable, fd: Fd) -> Result<usize, FdError> {
+    Ok(fds.dup(fd)?.0)
+}
+
+/// `dup2(oldfd, newfd)`: duplicate `oldfd` onto `newfd`, closing what
+/// `newfd` held first.
+pub fn sys_dup2(oldfd: Fd, newfd: Fd) -> Result<usize, FdError> {
+    let (fd, closed) = with_fds(|fds| fds.dup2(oldfd, newfd))?;
+    drop(closed);
+    Ok(fd.0)
+}
+
+/// File status as `stat` and `fstat` return it: the ARM EABI
+/// `struct stat`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stat {
+    pub st_dev: u32,
+    pub st_ino: u32,
+    pub st_mode: u16,
+    pub st_nlink: u16,
+    pub st_uid: u16,
+    pub st_gid: u16,
+    pub st_rdev: u32,
+    pub st_size: u32,
+    pub st_blksize: u32,
+    pub st_blocks: u32,
+    pub st_atime: u32,
+    pub st_atime_nsec: u32,
+    pub st_mtime: u32,
+    pub st_mtime_nsec: u32,
+    pub st_ctime: u32,
+    pub st_ctime_nsec: u32,
+    _unused: [u32; 2],
+}
+
+const _: () = assert!(size_of::<Stat>() == 64);
+
+impl From<FileStat> for Stat {
+    /// Filesystems keep no owners, permissions or times yet, so every file
+    /// gets the usual default permissions and the epoch
+    fn from(stat: FileStat) -> Self {
+        let mode = match stat.file_type {
+            FileType::Regular => 0o100_644,
+            FileType::Directory => 0o040_755,
+            FileType::CharDevice => 0o020_644,
+            FileType::BlockDevice => 0o060_644,
+            FileType::Symlink => 0o120_777,
+            FileType::Pipe => 0o010_644,
+            FileType::Socket => 0o140_644,
+        };
+        Stat {
+            st_mode: mode,
+            st_nlink: 1,
+            st_size: stat.size as u32,
+            st_blksize: 512,
+            st_blocks: stat.size.div_ceil(512) as u32,
+            ..Default::default()
+        }
+    }
+}
+
+/// `stat(path, statbuf)`: store the status of the file at `path` at
+/// `statbuf`.
+pub fn sys_stat(path: usize, statbuf: usize) -> Result<usize, FdError> {
+    let path = user_string(path)?;
+    let stat = vfs().stat(&path)?;
+    copy_to_user(statbuf, Stat::from(stat))?;
+    Ok(0)
+}
+
+/// `fstat(fd, statbuf)`: store the status of the file behind `desc` at
+/// `statbuf`.
+pub fn sys_fstat(desc: &FileDescriptor, statbuf: usize) -> Result<usize, FdError> {
+    let stat = desc.file().stat()?;
+    copy_to_user(statbuf, Stat::from(stat))?;
+    Ok(0)
+}
+
+/// `pipe(fds)`: create an anonymous pipe and store its read and write
+/// descriptors, as two `i32`s, at `out`.
+pub fn sys_pipe(out: usize) -> Result<usize, FdError> {
+    let (read_end, write_end) = pipe::pipe();
+    let (read_fd, write_fd) = with_fds(|fds| {
+        let read_fd = fds.alloc(read_end.clone(), FdFlags::empty(), AccessMode::RDONLY)?;
+        match fds.alloc(write_end.clone(), FdFlags::empty(), AccessMode::WRONLY) {
+            Ok(write_fd) => Ok((read_fd, write_fd)),
+            Err(e) => {
+                let _ = fds.close(read_fd);
+                Err(e)
+            }
+        }
+    })?;
+
+    if let Err(e) = copy_to_user(out, [read_fd.0 as i32, write_fd.0 as i32]) {
+        let closed = with_fds(|fds| Ok((fds.close(read_fd), fds.close(write_fd))));
+        drop(closed);
+        return Err(e);
+    }
+    Ok(0)
+}
+
+/// `ioctl(fd, cmd, arg)`: forward a device control request to the file
+/// behind `fd`.
+pub fn sys_ioctl(
+    fds: &FileDescriptorTable,
+    fd: Fd,
+    cmd: u32,
+    arg: usize,
+) -> Result<usize, FdError> {
+    fds.get(fd)?.file().ioctl(cmd, arg)
+}
+
+/// `fcntl(fd, cmd, arg)`: descriptor flags, status flags and `F_DUPFD`;
+/// see [`FileDescriptorTable::fcntl`].
+pub fn sys_fcntl(
+    fds: &mut FileDescriptorTable,
+    fd: Fd,
+    cmd: u32,
+    arg: usize,
+) -> Result<usize, FdError> {
+    fds.fcntl(fd, cmd, arg)
+}
+
+/// A duration or point in time (`struct timespec`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Timespec {
+    pub tv_sec: i32,
+    pub tv_nsec: i32,
+}
+
+/// `nanosleep(req, rem)`: sleep for the duration at `req`.
+///
+/// Nothing interrupts a sleep yet, so the remaining time stored at `rem`,
+/// unless it is null, is always zero.
+pub fn sys_nanosleep(req: usize, rem: usize) -> Result<usize, FdError> {
+    let Timespec { tv_sec, tv_nsec } = copy_from_user(req)?;
+    if tv_sec < 0 || !(0..1_000_000_000).contains(&tv_nsec) {
+        return Err(FdError::InvalidArgument);
+    }
+
+    sched::sleep_us(tv_sec as u64 * 1_000_000 + (tv_nsec as u64).div_ceil(1000));
+    if rem != 0 {
+        copy_to_user(
+            rem,
+            Timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+        )?;
+    }
+    Ok(0)
+}
+
+/// A point in time to the microsecond (`struct timeval`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Timeval {
+    pub tv_sec: i32,
+    pub tv_usec: i32,
+}
+
+/// `clock_gettime` clock: wall-clock time
+pub const CLOCK_REALTIME: u32 = 0;
+/// `clock_gettime` clock: time since boot, never set back
+pub const CLOCK_MONOTONIC: u32 = 1;
+/// `clock_gettime` clock: as [`CLOCK_MONOTONIC`], which is never slewed
+pub const CLOCK_MONOTONIC_RAW: u32 = 4;
+/// `clock_gettime` clock: as [`CLOCK_MONOTONIC`], which keeps counting
+/// through suspend, as there is none
+pub const CLOCK_BOOTTIME: u32 = 7;
+
+/// `gettimeofday(tv, tz)`: store the wall-clock time at `tv`, unless it
+/// is null. The kernel keeps no time zone, so the one stored at `tz`,
+/// unless it is null, is always UTC.
+pub fn sys_gettimeofday(tv: usize, tz: usize) -> Result<usize, FdError> {
+    let now = time::realtime_us().ok_or(FdError::NotSupported)?;
+    if tv != 0 {
+        copy_to_user(
+            tv,
+            Timeval {
+                tv_sec: now.div_euclid(time::USEC_PER_SEC) as i32,
+                tv_usec: now.rem_euclid(time::USEC_PER_SEC) as i32,
+            },
+        )?;
+    }
+    if tz != 0 {
+        // struct timezone: minutes west of Greenwich and DST type
+        copy_to_user(tz, [0i32; 2])?;
+    }
+    Ok(0)
+}
+
+/// `clock_gettime(clk_id, tp)`: store the time of clock `clk_id` at `tp`.
+pub fn sys_clock_gettime(clk_id: u32, tp: usize) -> Result<usize, FdError> {
+    let us = match clk_id {
+        CLOCK_REALTIME => time::realtime_us(),
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_BOOTTIME => {
+            time::monotonic_us().map(|us| us as i64)
+        }
+        _ => return Err(FdError::InvalidArgument),
+    }
+    .ok_or(FdError::NotSupported)?;
+
+    copy_to_user(
+        tp,
+        Timespec {
+            tv_sec: us.div_euclid(time::USEC_PER_SEC) as i32,
+            tv_nsec: us.rem_euclid(time::USEC_PER_SEC) as i32 * 1000,
+        },
+    )?;
+    Ok(0)
+}
+
+/// One entry of the array passed to `poll` (`struct pollfd`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PollFd {
+    pub fd: i32,
+    /// Requested [`PollEvents`]
+    pub events: i16,
+    /// Returned [`PollEvents`]; `ERR`, `HUP` and `NVAL` are reported even
+    /// when not requested
+    pub revents: i16,
+}
+
+/// `poll(fds, nfds, timeout)`: wait until one of the `nfds` entries at
+/// `pollfds` is ready, or `timeout_ms` milliseconds pass (forever if
+/// negative). Returns the number of entries with events.
+///
+/// The caller sleeps between checks until a file's readiness may have
+/// changed; see [`poll`](crate::fs::poll). Without a system timer a
+/// finite timeout degrades to a single check.
+pub fn sys_poll(
+    fds: &FileDescriptorTable,
+    pollfds: usize,
+    nfds: usize,
+    timeout_ms: i32,
+) -> Result<usize, FdError> {
+    if nfds > fds.limit() {
+        return Err(FdError::InvalidArgument);
+    }
+    let entry_addr = |i: usize| pollfds + i * size_of::<PollFd>();
+    let mut entries = Vec::new();
+    for i in 0..nfds {
+        entries.push(copy_from_user::<PollFd>(entry_addr(i))?);
+    }
+
+    let deadline = match timeout_ms {
+        t if t < 0 => None,
+        t => Some(uptime_us().unwrap_or(0) + t as u64 * 1000),
+    };
+
+    let ready = loop {
+        // Read first, so a wake during the check is not missed
+        let seen = poll::generation();
+        let ready = poll_once(fds, &mut entries);
+        if ready > 0 {
+            break ready;
+        }
+        if let Some(deadline) = deadline {
+            match uptime_us() {
+                Some(now) if now < deadline => {}
+                _ => break 0,
+            }
+        }
+        poll::wait(seen);
+    };
+
+    for (i, entry) in entries.into_iter().enumerate() {
+        copy_to_user(entry_addr(i), entry)?;
+    }
+    Ok(ready)
+}
+
+/// Fill in `revents` for every entry, returning how many have any
+fn poll_once(fds: &FileDescriptorTable, entries: &mut [PollFd]) -> usize {
+    let always = PollEvents::ERR | PollEvents::HUP | PollEvents::NVAL;
+    let mut ready = 0;
+
+    for entry in entries.iter_mut() {
+        let revents = if entry.fd < 0 {
+            // Negative descriptors are skipped, as in POSIX
+            PollEvents::empty()
+        } else {
+            match fds.get(Fd(entry.fd as usize)) {
+                Ok(desc) => {
+                    let wanted = PollEvents::from_bits_truncate(entry.events as u16) | always;
+                    desc.file().poll() & wanted
+                }
+                Err(_) => PollEvents::NVAL,
+            }
+        };
+        entry.revents = revents.bits() as i16;
+        if !revents.is_empty() {
+            ready += 1;
+        }
+    }
+    ready
+}
+
+/// `futex(addr, op, val)`: with `FUTEX_WAIT`, sleep until woken if the
+/// word at `addr` still holds `val`; with `FUTEX_WAKE`, wake up to `val`
+/// sleepers on it and return how many were woken.
+///
+/// Timeouts and the other operations are not supported.
+pub fn sys_futex(space: &AddressSpace, addr: usize, op: u32, val: u32) -> Result<usize, FdError> {
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            futex::wait(space, addr, val)?;
+            Ok(0)
+        }
+        FUTEX_WAKE => Ok(futex::wake(space, addr, val as usize)?),
+        _ => Err(FdError::NotSupported),
+    }
+}
+
+/// `mmap2(addr, len, prot, flags, fd, pgoff)`: map `len` bytes of memory
+/// into the caller's address space and return the address.
+///
+/// Only private anonymous mappings are supported so far; their pages are
+/// allocated on first touch. `addr` is a hint unless `MAP_FIXED` is set,
+/// in which case whatever was mapped there is replaced.
+pub fn sys_mmap2(
+    space: &AddressSpace,
+    addr: usize,
+    len: usize,
+    prot: u32,
+    flags: u32,
+    _fd: i32,
+    _pgoff: usize,
+) -> Result<usize, FdError> {
+    if len == 0 || !addr.is_multiple_of(PAGE_SIZE) {
+        return Err(FdError::InvalidArgument);
+    }
+    if flags & MAP_ANONYMOUS == 0 || flags & MAP_PRIVATE == 0 {
+        return Err(FdError::NotSupported);
+    }
+
+    let len = len
+        .checked_next_multiple_of(PAGE_SIZE)
+        .ok_or(FdError::OutOfMemory)?;
+    let map_flags = Vma::flags_for_prot(prot);
+
+    if flags & MAP_FIXED != 0 {
+        space.unmap(addr, len)?;
+        space.add_vma(addr, len, map_flags)?;
+        return Ok(addr);
+    }
+    if addr != 0 && space.add_vma(addr, len, map_flags).is_ok() {
+        return Ok(addr);
+    }
+    Ok(space.add_vma_anywhere(len, MMAP_BASE, MMAP_END, map_flags)?)
+}
+
+/// `munmap(addr, len)`: remove the mappings in `len` bytes at `addr`.
+pub fn sys_munmap(space: &AddressSpace, addr: usize, len: usize) -> Result<usize, FdError> {
+    if len == 0 || !addr.is_multiple_of(PAGE_SIZE) {
+        return Err(FdError::InvalidArgument);
+    }
+    let len = len
+        .checked_next_multiple_of(PAGE_SIZE)
+        .ok_or(FdError::InvalidArgument)?;
+    space.unmap(addr, len)?;
+    Ok(0)
+}
+
+/// `brk(addr)`: move the program break to `addr` and return the new break.
+///
+/// As on Linux, failure is reported by returning the unchanged break, and
+/// `brk(0)` just queries it.
+pub fn sys_brk(space: &AddressSpace, addr: usize) -> Result<usize, FdError> {
+    let current = space.brk().ok_or(FdError::OutOfMemory)?;
+    if addr == 0 {
+        return Ok(current);
+    }
+    Ok(space.set_brk(addr).unwrap_or(current))
+}
+
+/// `sbrk(increment)`: move the program break by `increment` bytes and
+/// return the old break.
+///
+/// There is no syscall number for this; C libraries build it on `brk`, and
+/// it is provided for kernel-side callers.
+pub fn sys_sbrk(space: &AddressSpace, increment: isize) -> Result<usize, FdError> {
+    let current = space.brk().ok_or(FdError::OutOfMemory)?;
+    let brk = current
+        .checked_add_signed(increment)
+        .ok_or(FdError::OutOfMemory)?;
+    space.set_brk(brk)?;
+    Ok(current)
+}
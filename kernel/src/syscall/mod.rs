@@ -1,4 +1,6 @@
 pub mod dispatch;
+pub mod errno;
 pub mod handlers;
+pub mod uaccess;
 
 pub use dispatch::dispatch;
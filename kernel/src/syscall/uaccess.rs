@@ -0,0 +1,134 @@
+//! Copying system call arguments in and out of user memory
+//!
+//! Handlers never dereference user pointers themselves: they copy values,
+//! byte buffers and strings in and out through these helpers. A range is
+//! first checked against the calling process's areas, then copied by
+//! [`uaccess::copy_in`] or [`uaccess::copy_out`], which survive a fault
+//! on it. Either way a bad pointer fails the call with
+//! [`FdError::BadAddress`] (`EFAULT`) rather than a kernel data abort.
+
+use crate::arch::uaccess;
+use crate::fs::fd::FdError;
+use crate::mm::address_space;
+use crate::mm::page_allocator::PAGE_SIZE;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::MaybeUninit;
+
+/// Longest string taken from user memory, terminator included
+pub const USER_STRING_MAX: usize = 4096;
+
+/// Most entries in a user pointer array
+pub const USER_ARRAY_MAX: usize = 1024;
+
+/// Copy `len` bytes from `src` to `dst`, where `user` of the two is in
+/// user memory and is to be written if `write`
+///
+/// # Safety
+/// The kernel side of the copy must be valid for `len` bytes.
+unsafe fn copy(
+    dst: *mut u8,
+    src: *const u8,
+    len: usize,
+    user: usize,
+    write: bool,
+) -> Result<(), FdError> {
+    if len == 0 {
+        return Ok(());
+    }
+    address_space::check_user(user, len, write).map_err(|_| FdError::BadAddress)?;
+    // SAFETY: the caller vouches for the kernel side, and a fault on the
+    // user side only cuts the copy short
+    let left = unsafe {
+        if write {
+            uaccess::copy_out(dst, src, len)
+        } else {
+            uaccess::copy_in(dst, src, len)
+        }
+    };
+    match left {
+        0 => Ok(()),
+        _ => Err(FdError::BadAddress),
+    }
+}
+
+/// Copy the `T` at `addr` in from user memory. `T` must be valid for any
+/// bytes, as the plain C structures system calls take are.
+pub fn copy_from_user<T: Copy>(addr: usize) -> Result<T, FdError> {
+    let mut value = MaybeUninit::<T>::uninit();
+    // SAFETY: `value` holds a `T`, which the copy fills in
+    unsafe {
+        copy(
+            value.as_mut_ptr() as *mut u8,
+            addr as *const u8,
+            size_of::<T>(),
+            addr,
+            false,
+        )?;
+        Ok(value.assume_init())
+    }
+}
+
+/// Copy `value` out to the `T` at `addr` in user memory
+pub fn copy_to_user<T: Copy>(addr: usize, value: T) -> Result<(), FdError> {
+    // SAFETY: `value` is a `T` on the stack
+    unsafe {
+        copy(
+            addr as *mut u8,
+            &raw const value as *const u8,
+            size_of::<T>(),
+            addr,
+            true,
+        )
+    }
+}
+
+/// Fill `buf` from the bytes at `addr` in user memory
+pub fn copy_bytes_from_user(addr: usize, buf: &mut [u8]) -> Result<(), FdError> {
+    // SAFETY: `buf` is writable for its length
+    unsafe { copy(buf.as_mut_ptr(), addr as *const u8, buf.len(), addr, false) }
+}
+
+/// Copy `data` out to the bytes at `addr` in user memory
+pub fn copy_bytes_to_user(addr: usize, data: &[u8]) -> Result<(), FdError> {
+    // SAFETY: `data` is readable for its length
+    unsafe { copy(addr as *mut u8, data.as_ptr(), data.len(), addr, true) }
+}
+
+/// Copy the NUL-terminated string at `addr` in from user memory
+pub fn user_string(addr: usize) -> Result<String, FdError> {
+    let mut bytes = Vec::new();
+    let mut at = addr;
+    while bytes.len() < USER_STRING_MAX {
+        // The string may end just before an inaccessible page, so copy
+        // no further than the end of the page it has reached
+        let start = bytes.len();
+        let chunk = (PAGE_SIZE - at % PAGE_SIZE).min(USER_STRING_MAX - start);
+        bytes.resize(start + chunk, 0);
+        copy_bytes_from_user(at, &mut bytes[start..])?;
+
+        if let Some(len) = bytes[start..].iter().position(|&byte| byte == 0) {
+            bytes.truncate(start + len);
+            return String::from_utf8(bytes).map_err(|_| FdError::InvalidArgument);
+        }
+        at = at.checked_add(chunk).ok_or(FdError::BadAddress)?;
+    }
+    Err(FdError::InvalidArgument)
+}
+
+/// Copy the strings of the null-terminated pointer array at `addr` in
+/// from user memory. A null array is empty.
+pub fn user_strings(addr: usize) -> Result<Vec<String>, FdError> {
+    let mut strings = Vec::new();
+    if addr == 0 {
+        return Ok(strings);
+    }
+    for i in 0..USER_ARRAY_MAX {
+        let ptr = copy_from_user::<usize>(addr.wrapping_add(i * size_of::<usize>()))?;
+        if ptr == 0 {
+            return Ok(strings);
+        }
+        strings.push(user_string(ptr)?);
+    }
+    Err(FdError::InvalidArgument)
+}
@@ -0,0 +1,102 @@
+//! Kernel time keeping
+//!
+//! Monotonic time is the system timer's free-running counter, which
+//! starts near zero at boot and never goes back. Wall-clock time is
+//! monotonic time plus the boot epoch: the Unix time at which the counter
+//! read zero. [`init`] seeds the epoch from the RTC, if there is one;
+//! without it the wall clock starts at 1970-01-01 on every boot.
+//! [`set_realtime`] moves the epoch and writes the new time through to
+//! the RTC, so the two stay in step.
+//!
+//! The epoch sits behind a [`SeqLock`], so reading the wall clock never
+//! waits on a lock and never sees the 64-bit epoch half updated.
+
+use crate::arch::Irq;
+use crate::subsystems::{uptime_us, wall_clock};
+use common::sync::SeqLock;
+use common::sync::irq::IrqControl;
+use drivers::hal::rtc::{DateTime, RtcError};
+
+/// What the wall clock is reckoned from
+#[derive(Debug, Clone, Copy)]
+struct Clock {
+    /// Unix time in microseconds at which the monotonic clock read zero
+    boot_epoch_us: i64,
+    /// Whether the boot epoch came from an RTC or was set
+    synced: bool,
+}
+
+static CLOCK: SeqLock<Clock> = SeqLock::new(Clock {
+    boot_epoch_us: 0,
+    synced: false,
+});
+
+/// Microseconds in a second
+pub const USEC_PER_SEC: i64 = 1_000_000;
+
+crate::initcall!(core, TIME_INIT, init);
+
+/// Seed the wall clock from the RTC.
+pub fn init() {
+    match sync_from_rtc() {
+        Ok(()) => log::info!("Wall clock set from RTC: {:?}", now()),
+        Err(e) => log::warn!("Wall clock not set, no usable RTC: {:?}", e),
+    }
+}
+
+/// Microseconds since boot, if there is a counting timer
+pub fn monotonic_us() -> Option<u64> {
+    uptime_us()
+}
+
+/// Microseconds since the Unix epoch, if there is a counting timer
+pub fn realtime_us() -> Option<i64> {
+    Some(CLOCK.read().boot_epoch_us + monotonic_us()? as i64)
+}
+
+/// Whether the wall clock has been set, from the RTC or otherwise
+pub fn is_synced() -> bool {
+    CLOCK.read().synced
+}
+
+/// Current wall-clock date and time, to the second
+pub fn now() -> Option<DateTime> {
+    Some(DateTime::from_unix(realtime_us()?.div_euclid(USEC_PER_SEC)))
+}
+
+/// Move the boot epoch so the wall clock reads the RTC's time now.
+///
+/// The RTC only counts whole seconds, so this is accurate to a second.
+pub fn sync_from_rtc() -> Result<(), RtcError> {
+    let rtc = wall_clock().ok_or(RtcError::Unsupported)?;
+    let time = rtc.lock().read_time()?;
+    let uptime = monotonic_us().ok_or(RtcError::Unsupported)?;
+    set_boot_epoch(time.to_unix() * USEC_PER_SEC - uptime as i64);
+    Ok(())
+}
+
+/// Set the wall clock to `unix_us` microseconds since the Unix epoch, and
+/// the RTC with it if there is one. The wall clock is set even if
+/// writing the RTC fails.
+pub fn set_realtime(unix_us: i64) -> Result<(), RtcError> {
+    let uptime = monotonic_us().ok_or(RtcError::Unsupported)?;
+    set_boot_epoch(unix_us - uptime as i64);
+
+    let Some(rtc) = wall_clock() else {
+        return Ok(());
+    };
+    let time = DateTime::from_unix(unix_us.div_euclid(USEC_PER_SEC));
+    rtc.lock().set_time(&time)
+}
+
+/// Move the boot epoch to `unix_us`, marking the wall clock set.
+/// Interrupts stay off meanwhile, so no reader preempts the write and
+/// spins behind it.
+fn set_boot_epoch(unix_us: i64) {
+    let irq = Irq::save_and_disable();
+    *CLOCK.write() = Clock {
+        boot_epoch_us: unix_us,
+        synced: true,
+    };
+    Irq::restore(irq);
+}